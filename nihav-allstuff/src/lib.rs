@@ -29,6 +29,7 @@ use nihav_llaudio::*;
 use nihav_mpeg::*;
 use nihav_ms::*;
 use nihav_qt::qt_register_all_decoders;
+use nihav_qt::qt_register_all_encoders;
 use nihav_rad::*;
 use nihav_realmedia::*;
 use nihav_vivo::*;
@@ -80,6 +81,7 @@ pub fn nihav_register_all_encoders(re: &mut RegisteredEncoders) {
     duck_register_all_encoders(re);
     llaudio_register_all_encoders(re);
     ms_register_all_encoders(re);
+    qt_register_all_encoders(re);
 }
 
 /// Registers all known demuxers.
@@ -19,6 +19,10 @@ pub mod data;
 #[allow(clippy::many_single_char_names)]
 pub mod imgwrite;
 
+#[allow(clippy::identity_op)]
+#[allow(clippy::unreadable_literal)]
+pub mod md5;
+
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub mod test;
@@ -1,3 +1,4 @@
+//! MD5 hash calculation.
 use std::fmt;
 
 const MD5_SHIFTS: [u8; 64] = [
@@ -25,8 +26,10 @@ fn box1(b: u32, c: u32, d: u32) -> u32 { (d & b) | (!d & c) }
 fn box2(b: u32, c: u32, d: u32) -> u32 { b ^ c ^ d }
 fn box3(b: u32, c: u32, d: u32) -> u32 { c ^ (b | !d) }
 
+/// MD5 hasher context.
 #[derive(Clone)]
 pub struct MD5 {
+    /// Current hash state.
     pub hash:   [u32; 4],
     inwords:    [u32; 16],
     buf:        [u8; 64],
@@ -48,6 +51,10 @@ impl fmt::Display for MD5 {
     }
 }
 
+impl Default for MD5 {
+    fn default() -> Self { Self::new() }
+}
+
 macro_rules! round {
     ($a: ident, $b: ident, $c: ident, $d: ident, $box: ident, $k: expr, $inval: expr) => {
         let f = $box($b, $c, $d).wrapping_add($a).wrapping_add(MD5_K[$k]).wrapping_add($inval);
@@ -60,6 +67,7 @@ macro_rules! round {
 
 #[allow(dead_code)]
 impl MD5 {
+    /// Constructs a new hasher instance.
     pub fn new() -> Self {
         Self {
             hash:       INITIAL_MD5_HASH,
@@ -94,6 +102,7 @@ impl MD5 {
 
         self.pos = 0;
     }
+    /// Feeds more data to the hasher.
     pub fn update_hash(&mut self, src: &[u8]) {
         for byte in src.iter() {
             self.buf[self.pos] = *byte;
@@ -104,6 +113,7 @@ impl MD5 {
         }
         self.count += src.len();
     }
+    /// Finalises hash calculation.
     pub fn finish(&mut self) {
         self.buf[self.pos] = 0x80;
         self.pos += 1;
@@ -123,12 +133,14 @@ impl MD5 {
         }
         self.calc_one_block();
     }
+    /// Returns calculated hash as four 32-bit words (as MD5 hash is usually printed).
     pub fn get_hash(&self, dst: &mut [u32; 4]) {
         for (dst, src) in dst.iter_mut().zip(self.hash.iter()) {
             *dst = src.swap_bytes();
         }
     }
-    pub fn get_hash_bytes(&self, dst: &mut [u8; 4]) {
+    /// Returns calculated hash as a sequence of bytes.
+    pub fn get_hash_bytes(&self, dst: &mut [u8; 16]) {
         for (dst, src) in dst.chunks_exact_mut(4).zip(self.hash.iter()) {
             dst[0] = (*src >>  0) as u8;
             dst[1] = (*src >>  8) as u8;
@@ -136,6 +148,7 @@ impl MD5 {
             dst[3] = (*src >> 24) as u8;
         }
     }
+    /// Calculates hash for the provided data in one go.
     pub fn calculate_hash(src: &[u8], hash: &mut [u32; 4]) {
         let mut md5 = Self::new();
         md5.update_hash(src);
@@ -168,5 +181,14 @@ mod test {
         md5.finish();
         md5.get_hash(&mut hash);
         assert_eq!(hash, [ 0x7707D6AE, 0x4E027C70, 0xEEA2A935, 0xC2296F21 ]);
+
+        let mut bytes = [0u8; 16];
+        MD5::calculate_hash(b"abc", &mut hash);
+        let mut md5 = MD5::new();
+        md5.update_hash(b"abc");
+        md5.finish();
+        md5.get_hash_bytes(&mut bytes);
+        assert_eq!(&bytes[..4], &[0x90, 0x01, 0x50, 0x98]);
+        assert_eq!(&bytes[12..], &[0x28, 0xE1, 0x7F, 0x72]);
     }
 }
@@ -209,6 +209,44 @@ pub fn test_remuxing_md5(dec_config: &DecoderTestParams, muxer: &str, mux_reg: &
     assert_eq!(hash, md5_hash);
 }
 
+fn hash_stream_info(md5: &mut MD5, info: &NACodecInfoRef) {
+    md5.update_hash(info.get_name().as_bytes());
+    match info.get_properties() {
+        NACodecTypeInfo::Video(ref vinfo) => {
+            let mut hdr = [0u8; 10];
+            hdr[0] = (vinfo.width  >> 24) as u8;
+            hdr[1] = (vinfo.width  >> 16) as u8;
+            hdr[2] = (vinfo.width  >>  8) as u8;
+            hdr[3] =  vinfo.width         as u8;
+            hdr[4] = (vinfo.height >> 24) as u8;
+            hdr[5] = (vinfo.height >> 16) as u8;
+            hdr[6] = (vinfo.height >>  8) as u8;
+            hdr[7] =  vinfo.height        as u8;
+            hdr[8] = vinfo.flipped as u8;
+            hdr[9] = vinfo.bits;
+            md5.update_hash(&hdr);
+        },
+        NACodecTypeInfo::Audio(ref ainfo) => {
+            let mut hdr = [0u8; 10];
+            hdr[0] = (ainfo.sample_rate >> 24) as u8;
+            hdr[1] = (ainfo.sample_rate >> 16) as u8;
+            hdr[2] = (ainfo.sample_rate >>  8) as u8;
+            hdr[3] =  ainfo.sample_rate        as u8;
+            hdr[4] = ainfo.channels;
+            hdr[5] = ainfo.format.bits;
+            hdr[6] = (ainfo.block_len >> 24) as u8;
+            hdr[7] = (ainfo.block_len >> 16) as u8;
+            hdr[8] = (ainfo.block_len >>  8) as u8;
+            hdr[9] =  ainfo.block_len        as u8;
+            md5.update_hash(&hdr);
+        },
+        _ => {},
+    };
+    if let Some(ref buf) = info.get_extradata() {
+        md5.update_hash(buf.as_slice());
+    }
+}
+
 /// Tests an encoder by decoding a stream from input file, feeding it to the encoder and muxing the result into output file.
 pub fn test_encoding_to_file(dec_config: &DecoderTestParams, enc_config: &EncoderTestParams, mut enc_params: EncodeParameters, enc_options: &[NAOption]) {
     let dmx_f = dec_config.dmx_reg.find_demuxer(dec_config.demuxer).unwrap();
@@ -368,42 +406,7 @@ pub fn test_encoding_md5(dec_config: &DecoderTestParams, enc_config: &EncoderTes
     let out_str = encoder.init(0, enc_params).unwrap();
 
     let mut md5 = MD5::new();
-    let info = out_str.get_info();
-    md5.update_hash(info.get_name().as_bytes());
-    match info.get_properties() {
-        NACodecTypeInfo::Video(ref vinfo) => {
-            let mut hdr = [0u8; 10];
-            hdr[0] = (vinfo.width  >> 24) as u8;
-            hdr[1] = (vinfo.width  >> 16) as u8;
-            hdr[2] = (vinfo.width  >>  8) as u8;
-            hdr[3] =  vinfo.width         as u8;
-            hdr[4] = (vinfo.height >> 24) as u8;
-            hdr[5] = (vinfo.height >> 16) as u8;
-            hdr[6] = (vinfo.height >>  8) as u8;
-            hdr[7] =  vinfo.height        as u8;
-            hdr[8] = vinfo.flipped as u8;
-            hdr[9] = vinfo.bits;
-            md5.update_hash(&hdr);
-        },
-        NACodecTypeInfo::Audio(ref ainfo) => {
-            let mut hdr = [0u8; 10];
-            hdr[0] = (ainfo.sample_rate >> 24) as u8;
-            hdr[1] = (ainfo.sample_rate >> 16) as u8;
-            hdr[2] = (ainfo.sample_rate >>  8) as u8;
-            hdr[3] =  ainfo.sample_rate        as u8;
-            hdr[4] = ainfo.channels;
-            hdr[5] = ainfo.format.bits;
-            hdr[6] = (ainfo.block_len >> 24) as u8;
-            hdr[7] = (ainfo.block_len >> 16) as u8;
-            hdr[8] = (ainfo.block_len >>  8) as u8;
-            hdr[9] =  ainfo.block_len        as u8;
-            md5.update_hash(&hdr);
-        },
-        _ => {},
-    };
-    if let Some(ref buf) = info.get_extradata() {
-        md5.update_hash(buf.as_slice());
-    }
+    hash_stream_info(&mut md5, &out_str.get_info());
 
     let (mut ifmt, dst_vinfo) = if let NACodecTypeInfo::Video(vinfo) = enc_params.format {
             (ScaleInfo { fmt: vinfo.format, width: vinfo.width, height: vinfo.height },
@@ -460,3 +463,143 @@ pub fn test_encoding_md5(dec_config: &DecoderTestParams, enc_config: &EncoderTes
     println!("encode hash {}", md5);
     assert_eq!(&hash, ref_hash);
 }
+
+/// Generates a synthetic test picture with a rectangle moving over a gradient background.
+///
+/// Only planar formats with 8-bit components and paletted formats are supported.
+pub fn gen_test_frame(vinfo: NAVideoInfo, frameno: usize) -> NABufferType {
+    let fmt = vinfo.get_format();
+    let buf = alloc_video_buffer(vinfo, 4).unwrap();
+    let mut vbuf = buf.get_vbuf().unwrap();
+    let ncomp = if fmt.is_paletted() { 1 } else { fmt.get_num_comp() };
+    for comp in 0..ncomp {
+        let (w, h) = vbuf.get_dimensions(comp);
+        let off = vbuf.get_offset(comp);
+        let stride = vbuf.get_stride(comp);
+        let data = vbuf.get_data_mut().unwrap();
+        for (y, line) in data[off..].chunks_mut(stride).take(h).enumerate() {
+            for (x, pix) in line[..w].iter_mut().enumerate() {
+                let in_rect = (x + frameno * 4 * w / vinfo.width) % w < w / 4 && y >= h / 4 && y < h * 3 / 4;
+                *pix = match (fmt.is_paletted(), comp) {
+                        (true, _) if in_rect => 200,
+                        (true, _) => ((x / 8 + y / 8) * 9 + x % 3 + frameno / 4) as u8,
+                        (false, 0) if in_rect => 0xD0 - (x & 0xF) as u8,
+                        (false, 0) => (x + y + frameno + ((x ^ y) & 3) * 2 + 0x18) as u8,
+                        (false, _) => (x * 4 + comp * 40 + y / 4) as u8,
+                    };
+            }
+        }
+    }
+    if fmt.is_paletted() {
+        let paloff = vbuf.get_offset(1);
+        let data = vbuf.get_data_mut().unwrap();
+        for (i, clr) in data[paloff..].chunks_exact_mut(3).take(256).enumerate() {
+            clr[0] = i as u8;
+            clr[1] = (255 - i) as u8;
+            clr[2] = (i / 2) as u8;
+        }
+    }
+    buf
+}
+
+fn frame_mse(src: &NABufferType, dst: &NABufferType, mse: &mut Vec<u64>) {
+    let sbuf = src.get_vbuf().unwrap();
+    let dbuf = dst.get_vbuf().unwrap();
+    let sdata = sbuf.get_data();
+    let ddata = dbuf.get_data();
+    if sbuf.get_info().get_format().is_paletted() {
+        let (w, h) = sbuf.get_dimensions(0);
+        let (spal, dpal) = (&sdata[sbuf.get_offset(1)..], &ddata[dbuf.get_offset(1)..]);
+        let mut sum = 0;
+        for (sline, dline) in sdata[sbuf.get_offset(0)..].chunks(sbuf.get_stride(0)).zip(ddata[dbuf.get_offset(0)..].chunks(dbuf.get_stride(0))).take(h) {
+            for (&sidx, &didx) in sline[..w].iter().zip(dline[..w].iter()) {
+                let sclr = &spal[usize::from(sidx) * 3..][..3];
+                let dclr = &dpal[usize::from(didx) * 3..][..3];
+                for (&a, &b) in sclr.iter().zip(dclr.iter()) {
+                    sum += (i64::from(a) - i64::from(b)).pow(2) as u64;
+                }
+            }
+        }
+        mse.resize(1, 0);
+        mse[0] = mse[0].max(sum / ((w * h) as u64));
+    } else {
+        let ncomp = sbuf.get_info().get_format().get_num_comp().min(dbuf.get_info().get_format().get_num_comp());
+        mse.resize(ncomp, 0);
+        for (comp, cmse) in mse.iter_mut().enumerate() {
+            let (w, h) = sbuf.get_dimensions(comp);
+            let mut sum = 0;
+            for (sline, dline) in sdata[sbuf.get_offset(comp)..].chunks(sbuf.get_stride(comp)).zip(ddata[dbuf.get_offset(comp)..].chunks(dbuf.get_stride(comp))).take(h) {
+                for (&a, &b) in sline[..w].iter().zip(dline[..w].iter()) {
+                    sum += (i64::from(a) - i64::from(b)).pow(2) as u64;
+                }
+            }
+            *cmse = (*cmse).max(sum / ((w * h) as u64));
+        }
+    }
+}
+
+/// Results of encoding synthetic frames.
+pub struct SynthTestResult {
+    /// Total size of the encoded data.
+    pub size:       usize,
+    /// Number of encoded keyframes.
+    pub keyframes:  usize,
+    /// The largest mean squared error between source and decoded frames for each component (or RGB difference for paletted formats).
+    pub max_mse:    Vec<u64>,
+}
+
+/// Tests an encoder by feeding it frames produced by [`gen_test_frame`].
+///
+/// The encoded frames are decoded back in order to measure the distortion and the hash for the encoded stream is compared to the reference one.
+///
+/// [`gen_test_frame`]: ./fn.gen_test_frame.html
+pub fn test_encoding_synth_md5(enc_reg: &RegisteredEncoders, dec_reg: &RegisteredDecoders, enc_name: &str, enc_params: EncodeParameters, enc_options: &[NAOption], nframes: usize, ref_hash: &[u32; 4]) -> SynthTestResult {
+    let vinfo = enc_params.format.get_video_info().unwrap();
+
+    let encfunc = enc_reg.find_encoder(enc_name).unwrap();
+    let mut encoder = (encfunc)();
+    encoder.set_options(enc_options);
+    let out_str = encoder.init(0, enc_params).unwrap();
+
+    let decfunc = dec_reg.find_decoder(out_str.get_info().get_name()).unwrap();
+    let mut dec = (decfunc)();
+    let mut dsupp = Box::new(NADecoderSupport::new());
+    dec.init(&mut dsupp, out_str.get_info()).unwrap();
+
+    let mut md5 = MD5::new();
+    hash_stream_info(&mut md5, &out_str.get_info());
+
+    let mut result = SynthTestResult { size: 0, keyframes: 0, max_mse: Vec::new() };
+    let mut frames = Vec::with_capacity(nframes);
+    let mut process_packet = |pkt: NAPacket, frames: &[NABufferType]| {
+        md5.update_hash(pkt.get_buffer().as_slice());
+        result.size += pkt.get_buffer().len();
+        if pkt.is_keyframe() {
+            result.keyframes += 1;
+        }
+        let frm = dec.decode(&mut dsupp, &pkt).unwrap();
+        let src = &frames[pkt.get_pts().unwrap() as usize];
+        frame_mse(src, &frm.get_buffer(), &mut result.max_mse);
+    };
+    for frameno in 0..nframes {
+        let buf = gen_test_frame(vinfo, frameno);
+        frames.push(buf.clone());
+        let ts = NATimeInfo::new(Some(frameno as u64), None, None, enc_params.tb_num, enc_params.tb_den);
+        let frm = NAFrame::new(ts, FrameType::Other, false, out_str.get_info(), buf);
+        encoder.encode(&frm).unwrap();
+        while let Ok(Some(pkt)) = encoder.get_packet() {
+            process_packet(pkt, &frames);
+        }
+    }
+    encoder.flush().unwrap();
+    while let Ok(Some(pkt)) = encoder.get_packet() {
+        process_packet(pkt, &frames);
+    }
+
+    let mut hash = [0; 4];
+    md5.finish();
+    md5.get_hash(&mut hash);
+    println!("encode hash {}", md5);
+    assert_eq!(&hash, ref_hash);
+    result
+}
@@ -6,9 +6,7 @@ pub mod dec_video;
 pub mod enc_video;
 pub mod wavwriter;
 
-#[allow(clippy::identity_op)]
-#[allow(clippy::unreadable_literal)]
-mod md5; // for internal checksums only
+use crate::md5;
 
 /// Decoder testing modes.
 ///
@@ -122,6 +122,104 @@ impl VQElementSum<YUVCode> for YUVCodeSum {
     }
 }
 
+#[derive(Default,Clone,Copy,PartialEq,Debug)]
+struct RGBCode {
+    rgb:    [[u8; 3]; 4],
+}
+impl VQElement for RGBCode {
+    fn dist(&self, rval: Self) -> u32 {
+        let mut sum = 0;
+        for (clr0, clr1) in self.rgb.iter().zip(rval.rgb.iter()) {
+            for (&c0, &c1) in clr0.iter().zip(clr1.iter()) {
+                let d = i32::from(c0) - i32::from(c1);
+                sum += d * d;
+            }
+        }
+        sum as u32
+    }
+    fn min_cw() -> Self { RGBCode { rgb: [[0; 3]; 4] } }
+    fn max_cw() -> Self { RGBCode { rgb: [[255; 3]; 4] } }
+    fn min(&self, rval: Self) -> Self {
+        let mut code = RGBCode::default();
+        for (dst, (clr0, clr1)) in code.rgb.iter_mut().zip(self.rgb.iter().zip(rval.rgb.iter())) {
+            for i in 0..3 {
+                dst[i] = clr0[i].min(clr1[i]);
+            }
+        }
+        code
+    }
+    fn max(&self, rval: Self) -> Self {
+        let mut code = RGBCode::default();
+        for (dst, (clr0, clr1)) in code.rgb.iter_mut().zip(self.rgb.iter().zip(rval.rgb.iter())) {
+            for i in 0..3 {
+                dst[i] = clr0[i].max(clr1[i]);
+            }
+        }
+        code
+    }
+    fn num_components() -> usize { 12 }
+    fn sort_by_component(arr: &mut [Self], component: usize) {
+        let (pix, comp) = (component / 3, component % 3);
+        let mut counts = [0; 256];
+        for entry in arr.iter() {
+            counts[entry.rgb[pix][comp] as usize] += 1;
+        }
+        let mut offs = [0; 256];
+        for i in 0..255 {
+            offs[i + 1] = offs[i] + counts[i];
+        }
+        let mut dst = vec![RGBCode::default(); arr.len()];
+        for entry in arr.iter() {
+            let idx = entry.rgb[pix][comp] as usize;
+            dst[offs[idx]] = *entry;
+            offs[idx] += 1;
+        }
+        arr.copy_from_slice(dst.as_slice());
+    }
+    fn max_dist_component(min: &Self, max: &Self) -> usize {
+        let mut comp = 0;
+        let mut diff = 0;
+        for pix in 0..4 {
+            for i in 0..3 {
+                let d = u32::from(max.rgb[pix][i]) - u32::from(min.rgb[pix][i]);
+                if d > diff {
+                    diff = d;
+                    comp = pix * 3 + i;
+                }
+            }
+        }
+        comp
+    }
+}
+
+#[derive(Default)]
+struct RGBCodeSum {
+    sum:    [[u64; 3]; 4],
+    count:  u64,
+}
+
+impl VQElementSum<RGBCode> for RGBCodeSum {
+    fn zero() -> Self { Self::default() }
+    fn add(&mut self, rval: RGBCode, count: u64) {
+        for (dst, clr) in self.sum.iter_mut().zip(rval.rgb.iter()) {
+            for (sum, &c) in dst.iter_mut().zip(clr.iter()) {
+                *sum += u64::from(c) * count;
+            }
+        }
+        self.count += count;
+    }
+    fn get_centroid(&self) -> RGBCode {
+        let mut code = RGBCode::default();
+        let count = self.count.max(1);
+        for (dst, sum) in code.rgb.iter_mut().zip(self.sum.iter()) {
+            for (c, &s) in dst.iter_mut().zip(sum.iter()) {
+                *c = ((s + count / 2) / count) as u8;
+            }
+        }
+        code
+    }
+}
+
 struct RNG {
     seed: u32,
 }
@@ -228,6 +326,28 @@ impl std::string::ToString for QuantMode {
     }
 }
 
+#[derive(Clone,Copy,PartialEq)]
+enum CodingMode {
+    Auto,
+    Yuv,
+    Gray,
+    Palette,
+}
+
+impl std::fmt::Display for CodingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CodingMode::Auto    => write!(f, "auto"),
+            CodingMode::Yuv     => write!(f, "yuv"),
+            CodingMode::Gray    => write!(f, "gray"),
+            CodingMode::Palette => write!(f, "palette"),
+        }
+    }
+}
+
+const MIN_CB_LEN: usize = 16;
+const MAX_SKIP_THR: u32 = 1 << 16;
+
 struct CinepakEncoder {
     stream:     Option<NAStreamRef>,
     lastfrm:    Option<NAVideoBufferRef<u8>>,
@@ -249,6 +369,21 @@ struct CinepakEncoder {
     v1_idx:     Vec<u8>,
     v4_idx:     Vec<u8>,
     grayscale:  bool,
+    cmode:      CodingMode,
+    palette:    bool,
+    pal:        [[u8; 3]; 256],
+    pal_changed: bool,
+    v1_pal_entries: Vec<RGBCode>,
+    v4_pal_entries: Vec<RGBCode>,
+    v1_rgb_cb:  [RGBCode; 256],
+    v4_rgb_cb:  [RGBCode; 256],
+    bitrate:    u32,
+    tb_num:     u32,
+    tb_den:     u32,
+    cb_len:     usize,
+    cb_reuse:   bool,
+    skip_thr:   u32,
+    rc_debt:    i64,
     rng:        RNG,
     masks:      MaskWriter,
     skip_dist:  Vec<u32>,
@@ -287,6 +422,21 @@ impl CinepakEncoder {
             v1_len:     0,
             v4_len:     0,
             grayscale:  false,
+            cmode:      CodingMode::Auto,
+            palette:    false,
+            pal:        [[0; 3]; 256],
+            pal_changed: false,
+            v1_pal_entries: Vec::new(),
+            v4_pal_entries: Vec::new(),
+            v1_rgb_cb:  [RGBCode::default(); 256],
+            v4_rgb_cb:  [RGBCode::default(); 256],
+            bitrate:    0,
+            tb_num:     0,
+            tb_den:     0,
+            cb_len:     256,
+            cb_reuse:   false,
+            skip_thr:   0,
+            rc_debt:    0,
             rng:        RNG::new(),
             v1_idx:     Vec::new(),
             v4_idx:     Vec::new(),
@@ -345,8 +495,114 @@ impl CinepakEncoder {
             voff += vstride * 2;
         }
     }
-    fn find_nearest(codebook: &[YUVCode], code: YUVCode) -> (u8, u32) {
-        let mut min_dist = std::u32::MAX;
+    fn read_strip_pal(&mut self, in_frm: &NAVideoBuffer<u8>, start: usize, end: usize) {
+        let stride  = in_frm.get_stride(0);
+        let mut off = in_frm.get_offset(0) + start * stride;
+        let (width, _) = in_frm.get_dimensions(0);
+        let data = in_frm.get_data();
+        self.v1_pal_entries.clear();
+        self.v4_pal_entries.clear();
+        for _ in (start..end).step_by(4) {
+            for x in (0..width).step_by(4) {
+                let mut blk = [[0; 3]; 16];
+                for (j, line) in blk.chunks_exact_mut(4).enumerate() {
+                    for (i, clr) in line.iter_mut().enumerate() {
+                        *clr = self.pal[data[off + x + i + j * stride] as usize];
+                    }
+                }
+                let mut v1 = RGBCode::default();
+                for (i, dst) in v1.rgb.iter_mut().enumerate() {
+                    let idx = (i & 1) * 2 + (i & 2) * 4;
+                    for comp in 0..3 {
+                        dst[comp] = avg4(blk[idx][comp], blk[idx + 1][comp], blk[idx + 4][comp], blk[idx + 5][comp]);
+                    }
+                }
+                self.v1_pal_entries.push(v1);
+                for i in 0..4 {
+                    let idx = (i & 1) * 2 + (i & 2) * 4;
+                    self.v4_pal_entries.push(RGBCode {
+                            rgb: [blk[idx], blk[idx + 1], blk[idx + 4], blk[idx + 5]]
+                        });
+                }
+            }
+            off += stride * 4;
+        }
+    }
+    fn num_blocks(&self) -> usize {
+        if !self.palette {
+            self.v1_entries.len()
+        } else {
+            self.v1_pal_entries.len()
+        }
+    }
+    fn find_v1(&self, blk: usize) -> (u8, u32) {
+        if !self.palette {
+            Self::find_nearest(&self.v1_cur_cb[self.cur_strip][..self.v1_len], self.v1_entries[blk])
+        } else {
+            Self::find_nearest(&self.v1_rgb_cb[..self.v1_len], self.v1_pal_entries[blk])
+        }
+    }
+    fn find_v4(&self, blk: usize) -> ([u8; 4], u32) {
+        let mut idx = [0; 4];
+        let mut dist = 0;
+        for (i, dst) in idx.iter_mut().enumerate() {
+            let (v4_idx, v4_dist) = if !self.palette {
+                    Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], self.v4_entries[blk * 4 + i])
+                } else {
+                    Self::find_nearest(&self.v4_rgb_cb[..self.v4_len], self.v4_pal_entries[blk * 4 + i])
+                };
+            *dst = v4_idx;
+            dist += v4_dist;
+        }
+        (idx, dist)
+    }
+    fn strip_dist(&self) -> u64 {
+        let mut sum = 0;
+        for blk in 0..self.num_blocks() {
+            let (_, v1_dist) = self.find_v1(blk);
+            let dist = if v1_dist > 0 { self.find_v4(blk).1.min(v1_dist) } else { 0 };
+            sum += u64::from(dist);
+        }
+        sum
+    }
+    fn find_nearest_clr(&self, clr: [u8; 3]) -> u8 {
+        let mut min_dist = u32::MAX;
+        let mut idx = 0;
+        for (i, pclr) in self.pal.iter().enumerate() {
+            let mut dist = 0;
+            for (&c0, &c1) in clr.iter().zip(pclr.iter()) {
+                let d = i32::from(c0) - i32::from(c1);
+                dist += (d * d) as u32;
+            }
+            if dist < min_dist {
+                min_dist = dist;
+                idx = i;
+                if dist == 0 {
+                    break;
+                }
+            }
+        }
+        idx as u8
+    }
+    fn expand_cb(&self, cb: &[YUVCode], rgb_cb: &mut [RGBCode]) {
+        for (dst, src) in rgb_cb.iter_mut().zip(cb.iter()) {
+            for (clr, &idx) in dst.rgb.iter_mut().zip(src.y.iter()) {
+                *clr = self.pal[idx as usize];
+            }
+        }
+    }
+    fn prepare_search(&mut self) {
+        if self.palette {
+            let mut v1_rgb_cb = [RGBCode::default(); 256];
+            let mut v4_rgb_cb = [RGBCode::default(); 256];
+            self.expand_cb(&self.v1_cur_cb[self.cur_strip][..self.v1_len], &mut v1_rgb_cb);
+            self.expand_cb(&self.v4_cur_cb[self.cur_strip][..self.v4_len], &mut v4_rgb_cb);
+            self.v1_rgb_cb = v1_rgb_cb;
+            self.v4_rgb_cb = v4_rgb_cb;
+        }
+    }
+    fn find_nearest<T: VQElement>(codebook: &[T], code: T) -> (u8, u32) {
+        let mut min_dist = u32::MAX;
         let mut idx = 0;
         for (i, cw) in codebook.iter().enumerate() {
             let dist = cw.dist(code);
@@ -360,18 +616,30 @@ impl CinepakEncoder {
         }
         (idx as u8, min_dist)
     }
-    fn can_update_cb(new_cb: &[YUVCode; 256], old_cb: &[YUVCode; 256], cb_size: usize) -> bool {
+    fn can_update_cb(new_cb: &[YUVCode], old_cb: &[YUVCode], cb_size: usize) -> bool {
         let mut skip_count = 0;
         for (new, old) in new_cb.iter().zip(old_cb.iter()) {
             if new == old {
                 skip_count += 1;
             }
         }
-        let full_size = cb_size * 256;
-        let upd_size = cb_size * (256 - skip_count) + 64;
+        let full_size = cb_size * new_cb.len();
+        let upd_size = cb_size * (new_cb.len() - skip_count) + 64;
         upd_size < full_size
     }
-    fn write_cb(bw: &mut ByteWriter, mut id: u8, new_cb: &[YUVCode; 256], old_cb: &[YUVCode; 256], grayscale: bool, update: bool) -> EncoderResult<()> {
+    fn update_size(new_cb: &[YUVCode], old_cb: &[YUVCode], cb_size: usize) -> usize {
+        let end = Self::update_end(new_cb, old_cb);
+        let changed = new_cb[..end].iter().zip(old_cb.iter()).filter(|(new, old)| new != old).count();
+        if changed > 0 {
+            changed * cb_size + ((end - 1) / 32 + 1) * 4 + 4
+        } else {
+            0
+        }
+    }
+    fn update_end(new_cb: &[YUVCode], old_cb: &[YUVCode]) -> usize {
+        new_cb.len() - new_cb.iter().rev().zip(old_cb.iter().rev()).take_while(|(ncw, ocw)| ncw == ocw).count()
+    }
+    fn write_cb(bw: &mut ByteWriter, mut id: u8, new_cb: &[YUVCode], old_cb: &[YUVCode], grayscale: bool, update: bool) -> EncoderResult<()> {
         if grayscale {
             id |= 4;
         }
@@ -390,25 +658,18 @@ impl CinepakEncoder {
                 }
             }
         } else {
-            let mut end = 256;
-            for (i, (ncw, ocw)) in new_cb.iter().rev().zip(old_cb.iter().rev()).enumerate() {
-                if ncw == ocw {
-                    end = i;
-                } else {
-                    break;
-                }
-            }
+            let end = Self::update_end(new_cb, old_cb);
             for i in (0..end).step_by(32) {
                 let mut mask = 0;
                 for j in 0..32 {
                     mask <<= 1;
-                    if new_cb[i + j] != old_cb[i + j] {
+                    if i + j < end && new_cb[i + j] != old_cb[i + j] {
                         mask |= 1;
                     }
                 }
                 bw.write_u32be(mask)?;
                 for j in 0..32 {
-                    if new_cb[i + j] == old_cb[i + j] { continue; }
+                    if i + j >= end || new_cb[i + j] == old_cb[i + j] { continue; }
                     bw.write_buf(&new_cb[i + j].y)?;
                     if !grayscale {
                         bw.write_byte(new_cb[i + j].u ^ 0x80)?;
@@ -531,6 +792,10 @@ impl CinepakEncoder {
     }
     fn calc_skip_dist(&mut self, in_frm: &NAVideoBuffer<u8>, start: usize, end: usize) {
         self.skip_dist.clear();
+        if self.palette {
+            self.calc_skip_dist_pal(in_frm, start, end);
+            return;
+        }
         if let Some(ref ref_frm) = self.lastfrm {
             let rystride  = ref_frm.get_stride(0);
             let mut ryoff = ref_frm.get_offset(0) + start * rystride;
@@ -601,38 +866,208 @@ impl CinepakEncoder {
             unreachable!();
         }
     }
-    fn quant_vectors(&mut self) {
-        match self.qmode {
-            QuantMode::ELBG => {
-                let mut elbg_v1: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v1_cb[self.cur_strip]);
-                let mut elbg_v4: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v4_cb[self.cur_strip]);
+    fn calc_skip_dist_pal(&mut self, in_frm: &NAVideoBuffer<u8>, start: usize, end: usize) {
+        if let Some(ref ref_frm) = self.lastfrm {
+            let rstride  = ref_frm.get_stride(0);
+            let mut roff = ref_frm.get_offset(0) + start * rstride;
+            let (width, _) = ref_frm.get_dimensions(0);
+            let rdata = ref_frm.get_data();
 
-                for entry in self.v1_cb[self.cur_strip].iter_mut().skip(self.v1_len) {
-                    self.rng.fill_entry(entry);
-                }
-                for entry in self.v4_cb[self.cur_strip].iter_mut().skip(self.v4_len) {
-                    self.rng.fill_entry(entry);
+            let istride  = in_frm.get_stride(0);
+            let mut ioff = in_frm.get_offset(0) + start * istride;
+            let idata = in_frm.get_data();
+
+            for _ in (start..end).step_by(4) {
+                for x in (0..width).step_by(4) {
+                    let mut dist = 0;
+                    for (rline, iline) in rdata[roff + x..].chunks(rstride).zip(idata[ioff + x..].chunks(istride)).take(4) {
+                        for (&ridx, &iidx) in rline[..4].iter().zip(iline[..4].iter()) {
+                            let rclr = self.pal[ridx as usize];
+                            let iclr = self.pal[iidx as usize];
+                            for (&c0, &c1) in rclr.iter().zip(iclr.iter()) {
+                                let d = i32::from(c0) - i32::from(c1);
+                                dist += d * d;
+                            }
+                        }
+                    }
+                    self.skip_dist.push(dist as u32);
                 }
+                roff += rstride * 4;
+                ioff += istride * 4;
+            }
+        } else {
+            unreachable!();
+        }
+    }
+    fn quant_vectors(&mut self) {
+        let cb_len = self.cb_len;
+        let cur = self.cur_strip;
+        if !self.palette {
+            match self.qmode {
+                QuantMode::ELBG => {
+                    let mut elbg_v1: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v1_cb[cur][..cb_len]);
+                    let mut elbg_v4: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v4_cb[cur][..cb_len]);
 
-                self.v1_len = elbg_v1.quantise(&self.v1_entries, &mut self.v1_cur_cb[self.cur_strip]);
-                self.v4_len = elbg_v4.quantise(&self.v4_entries, &mut self.v4_cur_cb[self.cur_strip]);
+                    for entry in self.v1_cb[cur][..cb_len].iter_mut().skip(self.v1_len) {
+                        self.rng.fill_entry(entry);
+                    }
+                    for entry in self.v4_cb[cur][..cb_len].iter_mut().skip(self.v4_len) {
+                        self.rng.fill_entry(entry);
+                    }
+
+                    self.v1_len = elbg_v1.quantise(&self.v1_entries, &mut self.v1_cur_cb[cur][..cb_len]);
+                    self.v4_len = elbg_v4.quantise(&self.v4_entries, &mut self.v4_cur_cb[cur][..cb_len]);
+                },
+                QuantMode::Hybrid => {
+                    quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v1_entries, &mut self.v1_cur_cb[cur][..cb_len]);
+                    quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v4_entries, &mut self.v4_cur_cb[cur][..cb_len]);
+                    let mut elbg_v1: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v1_cur_cb[cur][..cb_len]);
+                    let mut elbg_v4: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v4_cur_cb[cur][..cb_len]);
+                    self.v1_len = elbg_v1.quantise(&self.v1_entries, &mut self.v1_cur_cb[cur][..cb_len]);
+                    self.v4_len = elbg_v4.quantise(&self.v4_entries, &mut self.v4_cur_cb[cur][..cb_len]);
+                },
+                QuantMode::MedianCut => {
+                    self.v1_len = quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v1_entries, &mut self.v1_cur_cb[cur][..cb_len]);
+                    self.v4_len = quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v4_entries, &mut self.v4_cur_cb[cur][..cb_len]);
+                },
+            };
+        } else {
+            self.quant_vectors_pal();
+        }
+
+        for e in self.v1_cur_cb[cur][..cb_len].iter_mut().skip(self.v1_len) { *e = YUVCode::default(); }
+        for e in self.v4_cur_cb[cur][..cb_len].iter_mut().skip(self.v4_len) { *e = YUVCode::default(); }
+        // entries past the current codebook size are not transmitted
+        let (old_v1, old_v4) = (self.v1_cb[cur], self.v4_cb[cur]);
+        self.v1_cur_cb[cur][cb_len..].copy_from_slice(&old_v1[cb_len..]);
+        self.v4_cur_cb[cur][cb_len..].copy_from_slice(&old_v4[cb_len..]);
+        if self.grayscale {
+            for cw in self.v1_cur_cb[cur].iter_mut() {
+                cw.u = 128;
+                cw.v = 128;
+            }
+            for cw in self.v4_cur_cb[cur].iter_mut() {
+                cw.u = 128;
+                cw.v = 128;
+            }
+        }
+        self.prepare_search();
+    }
+    fn quant_vectors_pal(&mut self) {
+        let cb_len = self.cb_len;
+        let cur = self.cur_strip;
+        let mut v1_cb = [RGBCode::default(); 256];
+        let mut v4_cb = [RGBCode::default(); 256];
+        match self.qmode {
+            QuantMode::ELBG => {
+                self.expand_cb(&self.v1_cb[cur][..cb_len], &mut v1_cb);
+                self.expand_cb(&self.v4_cb[cur][..cb_len], &mut v4_cb);
+                let mut elbg_v1: ELBG<RGBCode, RGBCodeSum> = ELBG::new(&v1_cb[..cb_len]);
+                let mut elbg_v4: ELBG<RGBCode, RGBCodeSum> = ELBG::new(&v4_cb[..cb_len]);
+                self.v1_len = elbg_v1.quantise(&self.v1_pal_entries, &mut v1_cb[..cb_len]);
+                self.v4_len = elbg_v4.quantise(&self.v4_pal_entries, &mut v4_cb[..cb_len]);
             },
             QuantMode::Hybrid => {
-                quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v1_entries, &mut self.v1_cur_cb[self.cur_strip]);
-                quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v4_entries, &mut self.v4_cur_cb[self.cur_strip]);
-                let mut elbg_v1: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v1_cur_cb[self.cur_strip]);
-                let mut elbg_v4: ELBG<YUVCode, YUVCodeSum> = ELBG::new(&self.v4_cur_cb[self.cur_strip]);
-                self.v1_len = elbg_v1.quantise(&self.v1_entries, &mut self.v1_cur_cb[self.cur_strip]);
-                self.v4_len = elbg_v4.quantise(&self.v4_entries, &mut self.v4_cur_cb[self.cur_strip]);
+                quantise_median_cut::<RGBCode, RGBCodeSum>(&self.v1_pal_entries, &mut v1_cb[..cb_len]);
+                quantise_median_cut::<RGBCode, RGBCodeSum>(&self.v4_pal_entries, &mut v4_cb[..cb_len]);
+                let mut elbg_v1: ELBG<RGBCode, RGBCodeSum> = ELBG::new(&v1_cb[..cb_len]);
+                let mut elbg_v4: ELBG<RGBCode, RGBCodeSum> = ELBG::new(&v4_cb[..cb_len]);
+                self.v1_len = elbg_v1.quantise(&self.v1_pal_entries, &mut v1_cb[..cb_len]);
+                self.v4_len = elbg_v4.quantise(&self.v4_pal_entries, &mut v4_cb[..cb_len]);
             },
             QuantMode::MedianCut => {
-                self.v1_len = quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v1_entries, &mut self.v1_cur_cb[self.cur_strip]);
-                self.v4_len = quantise_median_cut::<YUVCode, YUVCodeSum>(&self.v4_entries, &mut self.v4_cur_cb[self.cur_strip]);
+                self.v1_len = quantise_median_cut::<RGBCode, RGBCodeSum>(&self.v1_pal_entries, &mut v1_cb[..cb_len]);
+                self.v4_len = quantise_median_cut::<RGBCode, RGBCodeSum>(&self.v4_pal_entries, &mut v4_cb[..cb_len]);
             },
         };
 
-        for e in self.v1_cur_cb[self.cur_strip].iter_mut().skip(self.v1_len) { *e = YUVCode::default(); }
-        for e in self.v4_cur_cb[self.cur_strip].iter_mut().skip(self.v4_len) { *e = YUVCode::default(); }
+        let mut v1_cur_cb = self.v1_cur_cb[cur];
+        for (dst, src) in v1_cur_cb.iter_mut().zip(v1_cb.iter()).take(self.v1_len) {
+            *dst = self.map_to_palette(src);
+        }
+        self.v1_cur_cb[cur] = v1_cur_cb;
+        let mut v4_cur_cb = self.v4_cur_cb[cur];
+        for (dst, src) in v4_cur_cb.iter_mut().zip(v4_cb.iter()).take(self.v4_len) {
+            *dst = self.map_to_palette(src);
+        }
+        self.v4_cur_cb[cur] = v4_cur_cb;
+    }
+    fn map_to_palette(&self, code: &RGBCode) -> YUVCode {
+        let mut ycode = YUVCode { y: [0; 4], u: 128, v: 128 };
+        for (dst, &clr) in ycode.y.iter_mut().zip(code.rgb.iter()) {
+            *dst = self.find_nearest_clr(clr);
+        }
+        ycode
+    }
+    /// Checks whether the strip can be coded with the codebooks inherited from
+    /// the previous strip or frame without sending any updates for them.
+    fn reuse_codebooks(&mut self) -> bool {
+        // rate control relies on it, otherwise it is used only on request
+        if !self.cb_reuse && self.bitrate == 0 {
+            return false;
+        }
+        let cur = self.cur_strip;
+        let cb_len = self.cb_len;
+        let cb_size = if self.grayscale { 4 } else { 6 };
+        let upd_size = Self::update_size(&self.v1_cur_cb[cur][..cb_len], &self.v1_cb[cur][..cb_len], cb_size)
+                     + Self::update_size(&self.v4_cur_cb[cur][..cb_len], &self.v4_cb[cur][..cb_len], cb_size);
+        if upd_size == 0 {
+            return true;
+        }
+        let new_dist = self.strip_dist();
+        let (new_v1, new_v4) = (self.v1_cur_cb[cur], self.v4_cur_cb[cur]);
+        let (new_v1_len, new_v4_len) = (self.v1_len, self.v4_len);
+
+        self.v1_cur_cb[cur] = self.v1_cb[cur];
+        self.v4_cur_cb[cur] = self.v4_cb[cur];
+        self.v1_len = cb_len;
+        self.v4_len = cb_len;
+        self.prepare_search();
+        let old_dist = self.strip_dist();
+        // distortion increase is traded for the codebook update size with the same weight as skipped blocks
+        if old_dist <= new_dist + u64::from(self.skip_thr / 8) * (upd_size as u64) {
+            true
+        } else {
+            self.v1_cur_cb[cur] = new_v1;
+            self.v4_cur_cb[cur] = new_v4;
+            self.v1_len = new_v1_len;
+            self.v4_len = new_v4_len;
+            self.prepare_search();
+            false
+        }
+    }
+    fn frame_bits(&self) -> i64 {
+        if self.tb_num > 0 && self.tb_den > 0 {
+            i64::from(self.bitrate) * i64::from(self.tb_num) / i64::from(self.tb_den)
+        } else {
+            i64::from(self.bitrate) / 25
+        }
+    }
+    fn update_rate_control(&mut self, size: usize) {
+        if self.bitrate == 0 {
+            return;
+        }
+        let frame_bits = self.frame_bits();
+        self.rc_debt += (size * 8) as i64 - frame_bits;
+        let limit = frame_bits * i64::from(self.key_int.max(1));
+        self.rc_debt = self.rc_debt.clamp(-limit, limit);
+        if self.rc_debt > frame_bits / 4 {
+            self.skip_thr = (self.skip_thr + self.skip_thr / 4 + 64).min(MAX_SKIP_THR);
+        } else if self.rc_debt < -frame_bits / 4 {
+            self.skip_thr = self.skip_thr.saturating_sub(self.skip_thr / 4 + 64);
+        }
+    }
+    // codebook size is changed only on keyframes so all strips stay in sync with the decoder
+    fn update_cb_len(&mut self) {
+        if self.bitrate == 0 {
+            return;
+        }
+        let frame_bits = self.frame_bits();
+        if self.rc_debt > frame_bits {
+            self.cb_len = (self.cb_len * 3 / 4).max(MIN_CB_LEN);
+        } else if self.rc_debt < -frame_bits {
+            self.cb_len = (self.cb_len + 32).min(256);
+        }
     }
     fn encode_intra(&mut self, bw: &mut ByteWriter, in_frm: &NAVideoBuffer<u8>) -> EncoderResult<bool> {
         let (width, height) = in_frm.get_dimensions(0);
@@ -658,46 +1093,36 @@ impl CinepakEncoder {
         for entry in self.v4_cb[self.cur_strip].iter_mut() {
             self.rng.fill_entry(entry);
         }
+        let cb_len = self.cb_len;
         while start_line < height {
-            self.read_strip(in_frm, start_line, end_line);
+            if !self.palette {
+                self.read_strip(in_frm, start_line, end_line);
+            } else {
+                self.read_strip_pal(in_frm, start_line, end_line);
+            }
 
             if self.cur_strip > 0 {
                 self.v1_cb[self.cur_strip] = self.v1_cb[self.cur_strip - 1];
                 self.v4_cb[self.cur_strip] = self.v4_cb[self.cur_strip - 1];
             }
             self.quant_vectors();
-            if self.grayscale {
-                for cw in self.v1_cur_cb[self.cur_strip].iter_mut() {
-                    cw.u = 128;
-                    cw.v = 128;
-                }
-                for cw in self.v4_cur_cb[self.cur_strip].iter_mut() {
-                    cw.u = 128;
-                    cw.v = 128;
-                }
-            }
+            let reuse_cb = self.cur_strip > 0 && self.reuse_codebooks();
 
             self.v1_idx.clear();
             self.v4_idx.clear();
             self.masks.reset();
 
-            for (v1_entry, v4_entries) in self.v1_entries.iter().zip(self.v4_entries.chunks(4)) {
-                let (v1_idx, v1_dist) = Self::find_nearest(&self.v1_cur_cb[self.cur_strip][..self.v1_len], *v1_entry);
+            for blk in 0..self.num_blocks() {
+                let (v1_idx, v1_dist) = self.find_v1(blk);
                 if v1_dist == 0 {
                     self.masks.put_v1();
                     self.v1_idx.push(v1_idx);
                     continue;
                 }
-                let (v40_idx, v40_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[0]);
-                let (v41_idx, v41_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[1]);
-                let (v42_idx, v42_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[2]);
-                let (v43_idx, v43_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[3]);
-                if v40_dist + v41_dist + v42_dist + v43_dist > v1_dist {
+                let (v4_idx, v4_dist) = self.find_v4(blk);
+                if v4_dist > v1_dist {
                     self.masks.put_v4();
-                    self.v4_idx.push(v40_idx);
-                    self.v4_idx.push(v41_idx);
-                    self.v4_idx.push(v42_idx);
-                    self.v4_idx.push(v43_idx);
+                    self.v4_idx.extend_from_slice(&v4_idx);
                 } else {
                     self.masks.put_v1();
                     self.v1_idx.push(v1_idx);
@@ -706,14 +1131,14 @@ impl CinepakEncoder {
             self.masks.end();
 
             let mut is_intra_strip = start_line == 0;
-            let (upd_v1, upd_v4) = if !is_intra_strip {
+            let (upd_v1, upd_v4) = if !is_intra_strip && !reuse_cb {
                     let cb_size = if self.grayscale { 4 } else { 6 };
-                    (Self::can_update_cb(&self.v1_cur_cb[self.cur_strip], &self.v1_cb[self.cur_strip], cb_size),
-                     Self::can_update_cb(&self.v4_cur_cb[self.cur_strip], &self.v4_cb[self.cur_strip], cb_size))
+                    (Self::can_update_cb(&self.v1_cur_cb[self.cur_strip][..cb_len], &self.v1_cb[self.cur_strip][..cb_len], cb_size),
+                     Self::can_update_cb(&self.v4_cur_cb[self.cur_strip][..cb_len], &self.v4_cb[self.cur_strip][..cb_len], cb_size))
                 } else {
                     (false, false)
                 };
-            if !is_intra_strip && !upd_v1 && !upd_v4 {
+            if !is_intra_strip && !reuse_cb && !upd_v1 && !upd_v4 {
                 is_intra_strip = true;
             }
             bw.write_byte(if is_intra_strip { 0x10 } else { 0x11 })?;
@@ -724,8 +1149,10 @@ impl CinepakEncoder {
             bw.write_u16be((end_line - start_line) as u16)?;
             bw.write_u16be(width as u16)?;
 
-            Self::write_cb(bw, 0x20, &self.v4_cur_cb[self.cur_strip], &self.v4_cb[self.cur_strip], self.grayscale, upd_v4)?;
-            Self::write_cb(bw, 0x22, &self.v1_cur_cb[self.cur_strip], &self.v1_cb[self.cur_strip], self.grayscale, upd_v1)?;
+            if !reuse_cb {
+                Self::write_cb(bw, 0x20, &self.v4_cur_cb[self.cur_strip][..cb_len], &self.v4_cb[self.cur_strip][..cb_len], self.grayscale, upd_v4)?;
+                Self::write_cb(bw, 0x22, &self.v1_cur_cb[self.cur_strip][..cb_len], &self.v1_cb[self.cur_strip][..cb_len], self.grayscale, upd_v1)?;
+            }
 
             self.render_stripe(true, start_line, end_line);
 
@@ -794,34 +1221,29 @@ impl CinepakEncoder {
         bw.write_u16be(self.nstrips as u16)?;
 
         self.cur_strip = 0;
+        let cb_len = self.cb_len;
         while start_line < height {
-            self.read_strip(in_frm, start_line, end_line);
+            if !self.palette {
+                self.read_strip(in_frm, start_line, end_line);
+            } else {
+                self.read_strip_pal(in_frm, start_line, end_line);
+            }
             self.calc_skip_dist(in_frm, start_line, end_line);
 
             self.quant_vectors();
-            if self.grayscale {
-                for cw in self.v1_cur_cb[self.cur_strip].iter_mut() {
-                    cw.u = 128;
-                    cw.v = 128;
-                }
-                for cw in self.v4_cur_cb[self.cur_strip].iter_mut() {
-                    cw.u = 128;
-                    cw.v = 128;
-                }
-            }
+            let reuse_cb = self.reuse_codebooks();
 
             self.v1_idx.clear();
             self.v4_idx.clear();
             self.masks.reset();
 
-            let mut skip_iter = self.skip_dist.iter();
-            for (v1_entry, v4_entries) in self.v1_entries.iter().zip(self.v4_entries.chunks(4)) {
-                let skip_dist = *skip_iter.next().unwrap();
-                if skip_dist == 0 {
+            for blk in 0..self.num_blocks() {
+                let skip_dist = self.skip_dist[blk];
+                if skip_dist <= self.skip_thr {
                     self.masks.put_inter(true);
                     continue;
                 }
-                let (v1_idx, v1_dist) = Self::find_nearest(&self.v1_cur_cb[self.cur_strip][..self.v1_len], *v1_entry);
+                let (v1_idx, v1_dist) = self.find_v1(blk);
                 if skip_dist < v1_dist {
                     self.masks.put_inter(true);
                     continue;
@@ -833,16 +1255,10 @@ impl CinepakEncoder {
                     self.v1_idx.push(v1_idx);
                     continue;
                 }
-                let (v40_idx, v40_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[0]);
-                let (v41_idx, v41_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[1]);
-                let (v42_idx, v42_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[2]);
-                let (v43_idx, v43_dist) = Self::find_nearest(&self.v4_cur_cb[self.cur_strip][..self.v4_len], v4_entries[3]);
-                if v40_dist + v41_dist + v42_dist + v43_dist > v1_dist {
+                let (v4_idx, v4_dist) = self.find_v4(blk);
+                if v4_dist > v1_dist {
                     self.masks.put_v4();
-                    self.v4_idx.push(v40_idx);
-                    self.v4_idx.push(v41_idx);
-                    self.v4_idx.push(v42_idx);
-                    self.v4_idx.push(v43_idx);
+                    self.v4_idx.extend_from_slice(&v4_idx);
                 } else {
                     self.masks.put_v1();
                     self.v1_idx.push(v1_idx);
@@ -852,8 +1268,8 @@ impl CinepakEncoder {
 
             let (upd_v1, upd_v4) = {
                     let cb_size = if self.grayscale { 4 } else { 6 };
-                    (Self::can_update_cb(&self.v1_cur_cb[self.cur_strip], &self.v1_cb[self.cur_strip], cb_size),
-                     Self::can_update_cb(&self.v4_cur_cb[self.cur_strip], &self.v4_cb[self.cur_strip], cb_size))
+                    (Self::can_update_cb(&self.v1_cur_cb[self.cur_strip][..cb_len], &self.v1_cb[self.cur_strip][..cb_len], cb_size),
+                     Self::can_update_cb(&self.v4_cur_cb[self.cur_strip][..cb_len], &self.v4_cb[self.cur_strip][..cb_len], cb_size))
                 };
             bw.write_byte(0x11)?;
             bw.write_u24be(0)?; // strip size
@@ -863,8 +1279,13 @@ impl CinepakEncoder {
             bw.write_u16be((end_line - start_line) as u16)?;
             bw.write_u16be(width as u16)?;
 
-            Self::write_cb(bw, 0x20, &self.v4_cur_cb[self.cur_strip], &self.v4_cb[self.cur_strip], self.grayscale, upd_v4)?;
-            Self::write_cb(bw, 0x22, &self.v1_cur_cb[self.cur_strip], &self.v1_cb[self.cur_strip], self.grayscale, upd_v1)?;
+            if !reuse_cb {
+                Self::write_cb(bw, 0x20, &self.v4_cur_cb[self.cur_strip][..cb_len], &self.v4_cb[self.cur_strip][..cb_len], self.grayscale, upd_v4)?;
+                Self::write_cb(bw, 0x22, &self.v1_cur_cb[self.cur_strip][..cb_len], &self.v1_cb[self.cur_strip][..cb_len], self.grayscale, upd_v1)?;
+            } else if self.cur_strip == 0 {
+                // decoders detect the frame coding mode from the codebook chunks in the first strip
+                Self::write_cb(bw, 0x22, &self.v1_cur_cb[0][..cb_len], &self.v1_cb[0][..cb_len], self.grayscale, true)?;
+            }
 
             self.render_stripe(false, start_line, end_line);
 
@@ -883,7 +1304,8 @@ impl CinepakEncoder {
             let mut skip = true;
             for mask in self.masks.masks.iter() {
                 bw.write_u32be(*mask)?;
-                if *mask == 0 { continue; }
+                // an empty mask may still carry the coding mode of a block started in the previous one
+                if *mask == 0 && skip { continue; }
                 let mut bit = 1 << 31;
                 while bit > 0 {
                     if skip {
@@ -917,7 +1339,7 @@ impl CinepakEncoder {
             self.cur_strip += 1;
         }
         patch_size(bw, frame_data_pos)?;
-        Ok(true)
+        Ok(false)
     }
 }
 
@@ -931,7 +1353,13 @@ impl NAEncoder for CinepakEncoder {
             },
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                let pix_fmt = if vinfo.format == GRAY_FORMAT { GRAY_FORMAT } else { YUV420_FORMAT };
+                let pix_fmt = match self.cmode {
+                        CodingMode::Auto if vinfo.format == GRAY_FORMAT => GRAY_FORMAT,
+                        CodingMode::Auto if vinfo.format.is_paletted() => PAL8_FORMAT,
+                        CodingMode::Auto | CodingMode::Yuv => YUV420_FORMAT,
+                        CodingMode::Gray => GRAY_FORMAT,
+                        CodingMode::Palette => PAL8_FORMAT,
+                    };
                 let outinfo = NAVideoInfo::new((vinfo.width + 3) & !3, (vinfo.height + 3) & !3, false, pix_fmt);
                 let mut ofmt = *encinfo;
                 ofmt.format = NACodecTypeInfo::Video(outinfo);
@@ -944,9 +1372,18 @@ impl NAEncoder for CinepakEncoder {
             NACodecTypeInfo::None => Err(EncoderError::FormatError),
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                if vinfo.format != YUV420_FORMAT && vinfo.format != GRAY_FORMAT {
+                if vinfo.format != YUV420_FORMAT && vinfo.format != GRAY_FORMAT && vinfo.format != PAL8_FORMAT {
                     return Err(EncoderError::FormatError);
                 }
+                let palette = vinfo.format == PAL8_FORMAT;
+                match self.cmode {
+                    CodingMode::Yuv if vinfo.format != YUV420_FORMAT => return Err(EncoderError::FormatError),
+                    CodingMode::Palette if !palette => return Err(EncoderError::FormatError),
+                    CodingMode::Gray if palette => return Err(EncoderError::FormatError),
+                    _ => {},
+                };
+                self.palette = palette;
+                self.grayscale = vinfo.format != YUV420_FORMAT || self.cmode == CodingMode::Gray;
                 if ((vinfo.width | vinfo.height) & 3) != 0 {
                     return Err(EncoderError::FormatError);
                 }
@@ -954,7 +1391,14 @@ impl NAEncoder for CinepakEncoder {
                     return Err(EncoderError::FormatError);
                 }
 
-                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, false, vinfo.format);
+                let out_fmt = if self.palette {
+                        PAL8_FORMAT
+                    } else if self.grayscale {
+                        GRAY_FORMAT
+                    } else {
+                        YUV420_FORMAT
+                    };
+                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, false, out_fmt);
                 let info = NACodecInfo::new("cinepak", NACodecTypeInfo::Video(out_info), None);
                 let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
                 stream.set_num(stream_id as usize);
@@ -962,10 +1406,20 @@ impl NAEncoder for CinepakEncoder {
 
                 self.stream = Some(stream.clone());
                 self.quality = encinfo.quality;
-                self.grayscale = vinfo.format != YUV420_FORMAT;
+                self.bitrate = encinfo.bitrate;
+                self.tb_num  = encinfo.tb_num;
+                self.tb_den  = encinfo.tb_den;
+                self.cb_len  = 256;
+                self.skip_thr = 0;
+                self.rc_debt = 0;
+                self.pal_changed = true;
                 let num_blocks = vinfo.width / 2 * vinfo.height / 2;
                 self.v1_entries = Vec::with_capacity(num_blocks);
                 self.v4_entries = Vec::with_capacity(num_blocks * 4);
+                if self.palette {
+                    self.v1_pal_entries = Vec::with_capacity(num_blocks);
+                    self.v4_pal_entries = Vec::with_capacity(num_blocks * 4);
+                }
                 self.v1_idx = Vec::with_capacity(num_blocks);
                 self.v4_idx = Vec::with_capacity(num_blocks * 4);
                 self.skip_dist = Vec::with_capacity(vinfo.width / 4 * vinfo.height / 4);
@@ -993,15 +1447,43 @@ impl NAEncoder for CinepakEncoder {
                 }
             }
 
+            if self.palette {
+                let paloff = vbuf.get_offset(1);
+                let data = vbuf.get_data();
+                let mut npal = [[0; 3]; 256];
+                for (dst, src) in npal.iter_mut().zip(data[paloff..].chunks_exact(3)) {
+                    dst.copy_from_slice(src);
+                }
+                if npal != self.pal {
+                    self.pal = npal;
+                    self.pal_changed = true;
+                }
+                // codebooks refer to the palette so it can change only on a keyframe
+                if self.pal_changed {
+                    self.frmcount = 0;
+                }
+            }
+
             let mut dbuf = Vec::with_capacity(4);
             let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
             let mut bw   = ByteWriter::new(&mut gw);
             let is_intra = if self.frmcount == 0 {
+                    self.update_cb_len();
                     self.encode_intra(&mut bw, vbuf)?
                 } else {
                     self.encode_inter(&mut bw, vbuf)?
                 };
-            self.pkt = Some(NAPacket::new(self.stream.clone().unwrap(), frm.ts, is_intra, dbuf));
+            self.update_rate_control(dbuf.len());
+            let mut pkt = NAPacket::new(self.stream.clone().unwrap(), frm.ts, is_intra, dbuf);
+            if self.palette {
+                let mut pal = [0; 1024];
+                for (dst, src) in pal.chunks_exact_mut(4).zip(self.pal.iter()) {
+                    dst[..3].copy_from_slice(src);
+                }
+                pkt.add_side_data(NASideData::Palette(self.pal_changed, Arc::new(pal)));
+                self.pal_changed = false;
+            }
+            self.pkt = Some(pkt);
             self.frmcount += 1;
             if self.frmcount == self.key_int {
                 self.frmcount = 0;
@@ -1032,6 +1514,12 @@ const ENCODER_OPTS: &[NAOptionDefinition] = &[
     NAOptionDefinition {
         name: "quant_mode", description: "Quantisation mode",
         opt_type: NAOptionDefinitionType::String(Some(&["elbg", "hybrid", "mediancut"])) },
+    NAOptionDefinition {
+        name: "mode", description: "Coding mode",
+        opt_type: NAOptionDefinitionType::String(Some(&["auto", "yuv", "gray", "palette"])) },
+    NAOptionDefinition {
+        name: "cb_reuse", description: "Reuse codebooks from the previous strip when updating them does not pay off (always on with rate control)",
+        opt_type: NAOptionDefinitionType::Bool },
 ];
 
 impl NAOptionHandler for CinepakEncoder {
@@ -1061,6 +1549,22 @@ impl NAOptionHandler for CinepakEncoder {
                                 };
                             }
                         },
+                        "mode" => {
+                            if let NAValue::String(ref str) = option.value {
+                                match str.as_str() {
+                                    "auto"      => self.cmode = CodingMode::Auto,
+                                    "yuv"       => self.cmode = CodingMode::Yuv,
+                                    "gray"      => self.cmode = CodingMode::Gray,
+                                    "palette"   => self.cmode = CodingMode::Palette,
+                                    _ => {},
+                                };
+                            }
+                        },
+                        "cb_reuse" => {
+                            if let NAValue::Bool(val) = option.value {
+                                self.cb_reuse = val;
+                            }
+                        },
                         _ => {},
                     };
                 }
@@ -1072,6 +1576,8 @@ impl NAOptionHandler for CinepakEncoder {
             KEYFRAME_OPTION => Some(NAValue::Int(i64::from(self.key_int))),
             "nstrips" => Some(NAValue::Int(self.nstrips as i64)),
             "quant_mode" => Some(NAValue::String(self.qmode.to_string())),
+            "mode" => Some(NAValue::String(self.cmode.to_string())),
+            "cb_reuse" => Some(NAValue::Bool(self.cb_reuse)),
             _ => None,
         }
     }
@@ -1132,4 +1638,58 @@ mod test {
         test_encoding_md5(&dec_config, &enc_config, enc_params, &[],
                           &[0xd73cb3c7, 0x30d59f90, 0x1d6e0e28, 0x5b72cc0c]);
     }
+
+    fn encode_synth(fmt: NAPixelFormaton, mode: &str, bitrate: u32, cb_reuse: bool, nframes: usize, ref_hash: &[u32; 4]) -> SynthTestResult {
+        let mut enc_reg = RegisteredEncoders::new();
+        generic_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        generic_register_all_decoders(&mut dec_reg);
+
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, fmt)),
+                quality: 0,
+                bitrate,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let enc_options = &[NAOption{ name: "mode", value: NAValue::String(mode.to_string()) },
+                            NAOption{ name: KEYFRAME_OPTION, value: NAValue::Int(10) },
+                            NAOption{ name: "cb_reuse", value: NAValue::Bool(cb_reuse) }];
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "cinepak", enc_params, enc_options, nframes, ref_hash);
+        assert_eq!(result.keyframes, (0..nframes).step_by(10).count());
+        result
+    }
+
+    #[test]
+    fn test_cinepak_encoder_modes() {
+        let yuv = encode_synth(YUV420_FORMAT, "auto", 0, false, 12, &[0x434f0a74, 0x01800f3e, 0x2a6877b5, 0xcf91f546]);
+        assert!(yuv.max_mse.iter().all(|&mse| mse < 16));
+        // only luma is coded in grayscale mode
+        let gray = encode_synth(YUV420_FORMAT, "gray", 0, false, 12, &[0xa6bf922b, 0x06e1619b, 0x195d715c, 0x16a58db6]);
+        assert!(gray.max_mse[0] < 16);
+        assert!(gray.size < yuv.size);
+        let gray = encode_synth(super::GRAY_FORMAT, "auto", 0, false, 12, &[0xa6bf922b, 0x06e1619b, 0x195d715c, 0x16a58db6]);
+        assert!(gray.max_mse[0] < 16);
+        let pal = encode_synth(PAL8_FORMAT, "palette", 0, false, 12, &[0xd91fe26d, 0x3fcdc8bb, 0x0141ed1f, 0x1c0a1e1b]);
+        assert!(pal.max_mse[0] < 64);
+    }
+    #[test]
+    fn test_cinepak_encoder_rate_control() {
+        const NFRAMES: usize = 30;
+        let full = encode_synth(YUV420_FORMAT, "yuv", 0, false, NFRAMES, &[0x6dc675f0, 0xeb38f220, 0xd2744fe8, 0xfe1cf10e]);
+        let bitrate = (full.size * 8 * 25 / NFRAMES / 3) as u32;
+        let result = encode_synth(YUV420_FORMAT, "yuv", bitrate, false, NFRAMES, &[0x3059727e, 0x3518b1c8, 0x61e88c18, 0xefe4c554]);
+        let target = (bitrate as usize) * NFRAMES / 25 / 8;
+        assert!(result.size < full.size / 2);
+        assert!(result.size < target * 3 / 2);
+        assert!(result.max_mse[0] < 64);
+    }
+    #[test]
+    fn test_cinepak_encoder_cb_reuse() {
+        let full = encode_synth(PAL8_FORMAT, "palette", 0, false, 12, &[0xd91fe26d, 0x3fcdc8bb, 0x0141ed1f, 0x1c0a1e1b]);
+        let reuse = encode_synth(PAL8_FORMAT, "palette", 0, true, 12, &[0xece9dcb3, 0xe3f0d116, 0x8f1414a0, 0x4e6793e0]);
+        assert!(reuse.size < full.size);
+        assert!(reuse.max_mse.iter().zip(full.max_mse.iter()).all(|(&a, &b)| a <= b));
+    }
 }
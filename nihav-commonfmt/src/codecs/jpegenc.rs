@@ -0,0 +1,688 @@
+use nihav_core::codecs::*;
+use nihav_core::io::byteio::*;
+use nihav_core::io::bitwriter::*;
+use nihav_codec_support::codecs::ZIGZAG;
+use nihav_codec_support::dsp::dct::*;
+use super::jpegdata::*;
+
+const DEFAULT_QUALITY: u8 = 75;
+
+// quantisation tables from ITU T.81 Annex K (in natural order)
+const LUMA_QUANT: [u8; 64] = [
+    16,  11,  10,  16,  24,  40,  51,  61,
+    12,  12,  14,  19,  26,  58,  60,  55,
+    14,  13,  16,  24,  40,  57,  69,  56,
+    14,  17,  22,  29,  51,  87,  80,  62,
+    18,  22,  37,  56,  68, 109, 103,  77,
+    24,  35,  55,  64,  81, 104, 113,  92,
+    49,  64,  78,  87, 103, 121, 120, 101,
+    72,  92,  95,  98, 112, 100, 103,  99
+];
+const CHROMA_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99
+];
+
+fn scale_quant(src: &[u8; 64], quality: u8) -> [u16; 64] {
+    let quality = u32::from(quality.clamp(1, 100));
+    let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+    let mut dst = [0; 64];
+    for (dst, &src) in dst.iter_mut().zip(src.iter()) {
+        *dst = ((u32::from(src) * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    dst
+}
+
+fn get_category(val: i16) -> u8 {
+    (16 - val.unsigned_abs().leading_zeros()) as u8
+}
+
+fn get_coded_bits(val: i16, cat: u8) -> u32 {
+    if val >= 0 {
+        val as u32
+    } else {
+        ((i32::from(val) - 1) as u32) & ((1 << cat) - 1)
+    }
+}
+
+#[derive(Clone)]
+struct HuffTable {
+    lens:   [u8; 16],
+    syms:   Vec<u8>,
+    codes:  [(u16, u8); 256],
+}
+
+impl HuffTable {
+    fn new(lens: &[u8; 16], syms: &[u8]) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0;
+        let mut siter = syms.iter();
+        for (i, &len) in lens.iter().enumerate() {
+            for _ in 0..len {
+                let sym = *siter.next().unwrap();
+                codes[usize::from(sym)] = (code, (i + 1) as u8);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Self { lens: *lens, syms: syms.to_vec(), codes }
+    }
+    // Huffman table generation as described in ITU T.81 Annex K.2
+    fn from_stats(stats: &[u32; 256]) -> Self {
+        const MAX_CODE_LEN: usize = 32;
+
+        let mut freq = [0u32; 257];
+        freq[..256].copy_from_slice(stats);
+        // reserved symbol so no code consists of all ones
+        freq[256] = 1;
+        let mut codesize = [0usize; 257];
+        let mut others = [usize::MAX; 257];
+        loop {
+            let mut c1 = usize::MAX;
+            let mut v = u32::MAX;
+            for (i, &f) in freq.iter().enumerate() {
+                if f != 0 && f <= v {
+                    v = f;
+                    c1 = i;
+                }
+            }
+            let mut c2 = usize::MAX;
+            let mut v = u32::MAX;
+            for (i, &f) in freq.iter().enumerate() {
+                if f != 0 && f <= v && i != c1 {
+                    v = f;
+                    c2 = i;
+                }
+            }
+            if c2 == usize::MAX {
+                break;
+            }
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+            codesize[c1] += 1;
+            while others[c1] != usize::MAX {
+                c1 = others[c1];
+                codesize[c1] += 1;
+            }
+            others[c1] = c2;
+            codesize[c2] += 1;
+            while others[c2] != usize::MAX {
+                c2 = others[c2];
+                codesize[c2] += 1;
+            }
+        }
+
+        let mut bits = [0u8; MAX_CODE_LEN + 1];
+        for &size in codesize.iter() {
+            if size > 0 {
+                bits[size.min(MAX_CODE_LEN)] += 1;
+            }
+        }
+        // limit code lengths to 16 bits
+        for i in (17..=MAX_CODE_LEN).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+        // and remove the reserved code
+        let mut i = 16;
+        while bits[i] == 0 {
+            i -= 1;
+        }
+        bits[i] -= 1;
+
+        let mut syms = Vec::with_capacity(256);
+        for len in 1..=MAX_CODE_LEN {
+            for (sym, &size) in codesize[..256].iter().enumerate() {
+                if size == len {
+                    syms.push(sym as u8);
+                }
+            }
+        }
+        let mut lens = [0; 16];
+        lens.copy_from_slice(&bits[1..=16]);
+        Self::new(&lens, &syms)
+    }
+    fn write(&self, bw: &mut BitWriter, sym: u8) {
+        let (code, len) = self.codes[usize::from(sym)];
+        bw.write(u32::from(code), len);
+    }
+}
+
+/// Calls the provided function for every symbol that should be coded for the block.
+fn scan_block<F: FnMut(bool, u8, i16)>(blk: &[i16; 64], dc_diff: i16, mut process: F) {
+    process(true, get_category(dc_diff), dc_diff);
+    let mut run = 0;
+    for &level in blk[1..].iter() {
+        if level == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            process(false, 0xF0, 0);
+            run -= 16;
+        }
+        let cat = get_category(level);
+        process(false, (run << 4) | cat, level);
+        run = 0;
+    }
+    if run > 0 {
+        process(false, 0x00, 0);
+    }
+}
+
+#[derive(Clone,Copy,Default)]
+struct ComponentParams {
+    hsamp:  usize,
+    vsamp:  usize,
+    table:  usize,
+}
+
+fn get_subsampling(fmt: NAPixelFormaton) -> Option<(usize, u8, u8)> {
+    if !matches!(fmt.get_model(), ColorModel::YUV(_)) || fmt.is_paletted() {
+        return None;
+    }
+    let ncomp = fmt.get_num_comp();
+    if ncomp != 1 && ncomp != 3 {
+        return None;
+    }
+    for chr in fmt.comp_info[..ncomp].iter() {
+        if let Some(chr) = chr {
+            if chr.packed || chr.depth != 8 || chr.shift != 0 || chr.next_elem != 1 {
+                return None;
+            }
+        } else {
+            return None;
+        }
+    }
+    let luma = fmt.get_chromaton(0).unwrap();
+    if luma.h_ss != 0 || luma.v_ss != 0 {
+        return None;
+    }
+    if ncomp == 1 {
+        return Some((1, 0, 0));
+    }
+    let cb = fmt.get_chromaton(1).unwrap();
+    let cr = fmt.get_chromaton(2).unwrap();
+    if (cb.h_ss, cb.v_ss) != (cr.h_ss, cr.v_ss) {
+        return None;
+    }
+    match (cb.h_ss, cb.v_ss) {
+        (1, 1) | (1, 0) | (0, 0) => Some((3, cb.h_ss, cb.v_ss)),
+        _ => None,
+    }
+}
+
+struct JPEGEncoder {
+    stream:     Option<NAStreamRef>,
+    pkt:        Option<NAPacket>,
+    quality:    u8,
+    optimal:    bool,
+    width:      usize,
+    height:     usize,
+    ncomp:      usize,
+    comps:      [ComponentParams; 3],
+    qmat:       [[u16; 64]; 2],
+    dct:        DCT,
+    blocks:     Vec<[i16; 64]>,
+}
+
+impl JPEGEncoder {
+    fn new() -> Self {
+        Self {
+            stream:     None,
+            pkt:        None,
+            quality:    DEFAULT_QUALITY,
+            optimal:    true,
+            width:      0,
+            height:     0,
+            ncomp:      0,
+            comps:      [ComponentParams::default(); 3],
+            qmat:       [[0; 64]; 2],
+            dct:        DCT::new(DCTMode::DCT_II, 8),
+            blocks:     Vec::new(),
+        }
+    }
+    fn fdct(&mut self, blk: &mut [f32; 64]) {
+        let mut tmp = [0.0f32; 64];
+        for (src, dst) in blk.chunks_exact(8).zip(tmp.chunks_exact_mut(8)) {
+            self.dct.do_dct(src, dst);
+        }
+        for i in 0..8 {
+            let mut src = [0.0f32; 8];
+            let mut dst = [0.0f32; 8];
+            for (j, el) in src.iter_mut().enumerate() {
+                *el = tmp[i + j * 8];
+            }
+            self.dct.do_dct(&src, &mut dst);
+            for (j, &el) in dst.iter().enumerate() {
+                blk[i + j * 8] = el;
+            }
+        }
+    }
+    fn get_mcu_size(&self) -> (usize, usize) {
+        (self.comps[0].hsamp * 8, self.comps[0].vsamp * 8)
+    }
+    // transforms and quantises all blocks of the picture storing them in the coding order
+    fn transform_picture(&mut self, vbuf: &NAVideoBuffer<u8>) {
+        let (mcu_w, mcu_h) = self.get_mcu_size();
+        self.blocks.clear();
+        let data = vbuf.get_data();
+        let mut fblk = [0.0f32; 64];
+        for mcu_y in (0..self.height).step_by(mcu_h) {
+            for mcu_x in (0..self.width).step_by(mcu_w) {
+                for comp in 0..self.ncomp {
+                    let cparams = self.comps[comp];
+                    let (w, h) = vbuf.get_dimensions(comp);
+                    let off = vbuf.get_offset(comp);
+                    let stride = vbuf.get_stride(comp);
+                    for by in 0..cparams.vsamp {
+                        for bx in 0..cparams.hsamp {
+                            let xpos = (mcu_x / mcu_w * cparams.hsamp + bx) * 8;
+                            let ypos = (mcu_y / mcu_h * cparams.vsamp + by) * 8;
+                            for (y, row) in fblk.chunks_exact_mut(8).enumerate() {
+                                let line = &data[off + (ypos + y).min(h - 1) * stride..];
+                                for (x, el) in row.iter_mut().enumerate() {
+                                    *el = f32::from(line[(xpos + x).min(w - 1)]) - 128.0;
+                                }
+                            }
+                            self.fdct(&mut fblk);
+                            let qmat = &self.qmat[cparams.table];
+                            let mut blk = [0i16; 64];
+                            for (dst, &idx) in blk.iter_mut().zip(ZIGZAG.iter()) {
+                                *dst = (fblk[idx] / f32::from(qmat[idx])).round() as i16;
+                            }
+                            self.blocks.push(blk);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn gather_stats(&self) -> ([[u32; 256]; 2], [[u32; 256]; 2]) {
+        let mut dc_stats = [[0; 256]; 2];
+        let mut ac_stats = [[0; 256]; 2];
+        let mut last_dc = [0; 3];
+        let mut biter = self.blocks.iter();
+        while biter.len() > 0 {
+            for (cparams, last_dc) in self.comps[..self.ncomp].iter().zip(last_dc.iter_mut()) {
+                for _ in 0..cparams.hsamp * cparams.vsamp {
+                    let blk = biter.next().unwrap();
+                    let tbl = cparams.table;
+                    scan_block(blk, blk[0] - *last_dc, |is_dc, sym, _| {
+                            if is_dc {
+                                dc_stats[tbl][usize::from(sym)] += 1;
+                            } else {
+                                ac_stats[tbl][usize::from(sym)] += 1;
+                            }
+                        });
+                    *last_dc = blk[0];
+                }
+            }
+        }
+        (dc_stats, ac_stats)
+    }
+    fn write_scan_data(&self, dc_tabs: &[HuffTable], ac_tabs: &[HuffTable]) -> Vec<u8> {
+        let mut bw = BitWriter::new(Vec::with_capacity(self.blocks.len() * 32), BitWriterMode::BE);
+        let mut last_dc = [0; 3];
+        let mut biter = self.blocks.iter();
+        while biter.len() > 0 {
+            for (cparams, last_dc) in self.comps[..self.ncomp].iter().zip(last_dc.iter_mut()) {
+                for _ in 0..cparams.hsamp * cparams.vsamp {
+                    let blk = biter.next().unwrap();
+                    let dc_tab = &dc_tabs[cparams.table];
+                    let ac_tab = &ac_tabs[cparams.table];
+                    scan_block(blk, blk[0] - *last_dc, |is_dc, sym, val| {
+                            if is_dc {
+                                dc_tab.write(&mut bw, sym);
+                            } else {
+                                ac_tab.write(&mut bw, sym);
+                            }
+                            let cat = sym & 0xF;
+                            if cat > 0 {
+                                bw.write(get_coded_bits(val, cat), cat);
+                            }
+                        });
+                    *last_dc = blk[0];
+                }
+            }
+        }
+        // pad the last byte with ones
+        let pad = (8 - (bw.tell() & 7)) & 7;
+        if pad > 0 {
+            bw.write((1 << pad) - 1, pad as u8);
+        }
+        let data = bw.end();
+
+        let mut dst = Vec::with_capacity(data.len() + data.len() / 64);
+        for &b in data.iter() {
+            dst.push(b);
+            if b == 0xFF {
+                dst.push(0x00);
+            }
+        }
+        dst
+    }
+    fn encode_picture(&mut self, bw: &mut ByteWriter, vbuf: &NAVideoBuffer<u8>) -> EncoderResult<()> {
+        self.transform_picture(vbuf);
+
+        let ntables = if self.ncomp > 1 { 2 } else { 1 };
+        let mut dc_tabs = Vec::with_capacity(ntables);
+        let mut ac_tabs = Vec::with_capacity(ntables);
+        if self.optimal {
+            let (dc_stats, ac_stats) = self.gather_stats();
+            for (dc_stat, ac_stat) in dc_stats.iter().zip(ac_stats.iter()).take(ntables) {
+                dc_tabs.push(HuffTable::from_stats(dc_stat));
+                ac_tabs.push(HuffTable::from_stats(ac_stat));
+            }
+        } else {
+            for i in 0..ntables {
+                dc_tabs.push(HuffTable::new(&DC_LENS[i], &DC_SYMS));
+                ac_tabs.push(HuffTable::new(&AC_LENS[i], AC_SYMS[i]));
+            }
+        }
+
+        bw.write_u16be(0xFFD8)?; // start of image
+
+        bw.write_u16be(0xFFE0)?; // JFIF header
+        bw.write_u16be(16)?;
+        bw.write_buf(b"JFIF\0")?;
+        bw.write_u16be(0x0101)?; // version
+        bw.write_byte(0)?; // no density units
+        bw.write_u16be(1)?;
+        bw.write_u16be(1)?;
+        bw.write_byte(0)?; // no thumbnail
+        bw.write_byte(0)?;
+
+        bw.write_u16be(0xFFDB)?; // quantisation tables
+        bw.write_u16be((2 + ntables * 65) as u16)?;
+        for (i, qmat) in self.qmat[..ntables].iter().enumerate() {
+            bw.write_byte(i as u8)?;
+            for &idx in ZIGZAG.iter() {
+                bw.write_byte(qmat[idx] as u8)?;
+            }
+        }
+
+        bw.write_u16be(0xFFC0)?; // baseline frame header
+        bw.write_u16be((8 + self.ncomp * 3) as u16)?;
+        bw.write_byte(8)?;
+        bw.write_u16be(self.height as u16)?;
+        bw.write_u16be(self.width as u16)?;
+        bw.write_byte(self.ncomp as u8)?;
+        for (i, cparams) in self.comps[..self.ncomp].iter().enumerate() {
+            bw.write_byte((i + 1) as u8)?;
+            bw.write_byte(((cparams.hsamp << 4) | cparams.vsamp) as u8)?;
+            bw.write_byte(cparams.table as u8)?;
+        }
+
+        bw.write_u16be(0xFFC4)?; // Huffman tables
+        let tab_size: usize = dc_tabs.iter().chain(ac_tabs.iter()).fold(0, |acc, tab| acc + 17 + tab.syms.len());
+        bw.write_u16be((2 + tab_size) as u16)?;
+        for (class, tabs) in [&dc_tabs, &ac_tabs].iter().enumerate() {
+            for (id, tab) in tabs.iter().enumerate() {
+                bw.write_byte(((class << 4) | id) as u8)?;
+                bw.write_buf(&tab.lens)?;
+                bw.write_buf(&tab.syms)?;
+            }
+        }
+
+        bw.write_u16be(0xFFDA)?; // start of scan
+        bw.write_u16be((6 + self.ncomp * 2) as u16)?;
+        bw.write_byte(self.ncomp as u8)?;
+        for (i, cparams) in self.comps[..self.ncomp].iter().enumerate() {
+            bw.write_byte((i + 1) as u8)?;
+            bw.write_byte(((cparams.table << 4) | cparams.table) as u8)?;
+        }
+        bw.write_byte(0)?;  // spectral selection start
+        bw.write_byte(63)?; // spectral selection end
+        bw.write_byte(0)?;  // successive approximation
+
+        let data = self.write_scan_data(&dc_tabs, &ac_tabs);
+        bw.write_buf(&data)?;
+
+        bw.write_u16be(0xFFD9)?; // end of image
+        Ok(())
+    }
+}
+
+impl NAEncoder for JPEGEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                let pix_fmt = if get_subsampling(vinfo.format).is_some() { vinfo.format } else { YUV420_FORMAT };
+                let outinfo = NAVideoInfo::new(vinfo.width.clamp(1, 65535), vinfo.height.clamp(1, 65535), false, pix_fmt);
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Video(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                let (ncomp, h_ss, v_ss) = if let Some(params) = get_subsampling(vinfo.format) {
+                        params
+                    } else {
+                        return Err(EncoderError::FormatError);
+                    };
+                if vinfo.width == 0 || vinfo.height == 0 || vinfo.width > 65535 || vinfo.height > 65535 {
+                    return Err(EncoderError::FormatError);
+                }
+
+                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, false, vinfo.format);
+                let info = NACodecInfo::new("jpeg", NACodecTypeInfo::Video(out_info), None);
+                let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+
+                self.width  = vinfo.width;
+                self.height = vinfo.height;
+                self.ncomp  = ncomp;
+                self.comps[0] = ComponentParams { hsamp: 1 << h_ss, vsamp: 1 << v_ss, table: 0 };
+                for cparams in self.comps[1..].iter_mut() {
+                    *cparams = ComponentParams { hsamp: 1, vsamp: 1, table: 1 };
+                }
+                if encinfo.quality != 0 {
+                    self.quality = encinfo.quality.min(100);
+                }
+                self.qmat = [scale_quant(&LUMA_QUANT, self.quality), scale_quant(&CHROMA_QUANT, self.quality)];
+
+                self.stream = Some(stream.clone());
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref vbuf) = buf.get_vbuf() {
+            let mut dbuf = Vec::new();
+            let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
+            let mut bw   = ByteWriter::new(&mut gw);
+            self.encode_picture(&mut bw, vbuf)?;
+            self.pkt = Some(NAPacket::new(self.stream.clone().unwrap(), frm.ts, true, dbuf));
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        let mut npkt = None;
+        std::mem::swap(&mut self.pkt, &mut npkt);
+        Ok(npkt)
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        Ok(())
+    }
+}
+
+const ENCODER_OPTS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: "huffman", description: "Huffman tables to use",
+        opt_type: NAOptionDefinitionType::String(Some(&["optimal", "default"])) },
+];
+
+impl NAOptionHandler for JPEGEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { ENCODER_OPTS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in ENCODER_OPTS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        "huffman" => {
+                            if let NAValue::String(ref str) = option.value {
+                                match str.as_str() {
+                                    "optimal" => self.optimal = true,
+                                    "default" => self.optimal = false,
+                                    _ => {},
+                                };
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            "huffman" => Some(NAValue::String(if self.optimal { "optimal" } else { "default" }.to_string())),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(JPEGEncoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_core::demuxers::*;
+    use nihav_core::muxers::*;
+    use crate::*;
+    use nihav_codec_support::test::enc_video::*;
+
+    fn make_format(ncomp: u8, h_ss: u8, v_ss: u8) -> NAPixelFormaton {
+        let mut fmt = YUV420_FORMAT;
+        fmt.components = ncomp;
+        if ncomp == 1 {
+            fmt.comp_info[1] = None;
+            fmt.comp_info[2] = None;
+        } else {
+            for chr in fmt.comp_info[1..3].iter_mut() {
+                let mut chromaton = chr.unwrap();
+                chromaton.h_ss = h_ss;
+                chromaton.v_ss = v_ss;
+                *chr = Some(chromaton);
+            }
+        }
+        fmt
+    }
+
+    fn encode_synth(fmt: NAPixelFormaton, huffman: &str, ref_hash: &[u32; 4]) -> usize {
+        let mut enc_reg = RegisteredEncoders::new();
+        generic_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        generic_register_all_decoders(&mut dec_reg);
+
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(84, 52, false, fmt)),
+                quality: 90,
+                bitrate: 0,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let enc = (enc_reg.find_encoder("jpeg").unwrap())();
+        let enc_params = enc.negotiate_format(&enc_params).unwrap();
+        assert!(enc_params.format.get_video_info().unwrap().format == fmt);
+
+        let enc_options = &[NAOption { name: "huffman", value: NAValue::String(huffman.to_string()) }];
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "jpeg", enc_params, enc_options, 1, ref_hash);
+        assert_eq!(result.keyframes, 1);
+        assert_eq!(result.max_mse.len(), fmt.get_num_comp());
+        for &mse in result.max_mse.iter() {
+            assert!(mse < 16);
+        }
+        result.size
+    }
+
+    #[test]
+    fn test_jpeg_encoder_formats() {
+        encode_synth(YUV420_FORMAT, "optimal", &[0x9db1b1b4, 0x58841526, 0xd3de40ee, 0x8ce56b48]);
+        encode_synth(make_format(3, 1, 0), "optimal", &[0xf1039d4e, 0xf5c7264f, 0x43ed7e43, 0xb5c9eb7e]);
+        encode_synth(make_format(3, 0, 0), "optimal", &[0x2e973b7e, 0x07e43e2a, 0xe1456678, 0xa973c8ce]);
+        encode_synth(make_format(1, 0, 0), "optimal", &[0xa6e168ee, 0xfae8ddae, 0x3063cde3, 0x68d3ad65]);
+    }
+    #[test]
+    fn test_jpeg_encoder_huffman() {
+        let opt_size = encode_synth(YUV420_FORMAT, "optimal", &[0x9db1b1b4, 0x58841526, 0xd3de40ee, 0x8ce56b48]);
+        let def_size = encode_synth(YUV420_FORMAT, "default", &[0xe4af5b2f, 0xd9bd5b47, 0x1764cfd7, 0x959e3f4b]);
+        assert!(opt_size < def_size);
+    }
+    #[test]
+    fn test_jpeg_encoder() {
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        generic_register_all_decoders(&mut dec_reg);
+        let mut mux_reg = RegisteredMuxers::new();
+        generic_register_all_muxers(&mut mux_reg);
+        let mut enc_reg = RegisteredEncoders::new();
+        generic_register_all_encoders(&mut enc_reg);
+
+        let dec_config = DecoderTestParams {
+                demuxer:        "avi",
+                in_name:        "assets/Misc/TalkingHead_352x288.avi",
+                stream_type:    StreamType::Video,
+                limit:          Some(2),
+                dmx_reg, dec_reg,
+            };
+        let enc_config = EncoderTestParams {
+                muxer:          "avi",
+                enc_name:       "jpeg",
+                out_name:       "mjpeg.avi",
+                mux_reg, enc_reg,
+            };
+        let dst_vinfo = NAVideoInfo {
+                width:   0,
+                height:  0,
+                format:  YUV420_FORMAT,
+                flipped: false,
+                bits:    12,
+            };
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(dst_vinfo),
+                quality: 80,
+                bitrate: 0,
+                tb_num:  0,
+                tb_den:  0,
+                flags:   0,
+            };
+        test_encoding_to_file(&dec_config, &enc_config, enc_params, &[]);
+    }
+}
@@ -10,6 +10,8 @@ mod cinepak;
 mod clearvideo;
 #[cfg(feature="decoder_jpeg")]
 mod jpeg;
+#[cfg(any(feature="decoder_jpeg", feature="encoder_jpeg"))]
+mod jpegdata;
 #[cfg(feature="decoder_rawvideo")]
 mod rawvideo;
 #[cfg(feature="decoder_rawvideo_ms")]
@@ -21,7 +23,7 @@ mod zmbv;
 #[allow(clippy::identity_op)]
 #[allow(clippy::useless_let_if_seq)]
 mod atrac3;
-#[cfg(any(feature="decoder_pcm",feature="encoder_pcm"))]
+#[cfg(any(feature="decoder_pcm",feature="encoder_pcm",feature="encoder_alaw",feature="encoder_ulaw"))]
 mod pcm;
 #[cfg(feature="decoder_sipro")]
 #[allow(clippy::collapsible_if)]
@@ -72,6 +74,8 @@ pub fn generic_register_all_decoders(rd: &mut RegisteredDecoders) {
 
 #[cfg(feature="encoder_cinepak")]
 mod cinepakenc;
+#[cfg(feature="encoder_jpeg")]
+mod jpegenc;
 #[cfg(feature="encoder_zmbv")]
 mod zmbvenc;
 
@@ -79,11 +83,17 @@ mod zmbvenc;
 const ENCODERS: &[EncoderInfo] = &[
 #[cfg(feature="encoder_cinepak")]
     EncoderInfo { name: "cinepak", get_encoder: cinepakenc::get_encoder },
+#[cfg(feature="encoder_jpeg")]
+    EncoderInfo { name: "jpeg", get_encoder: jpegenc::get_encoder },
 #[cfg(feature="encoder_zmbv")]
     EncoderInfo { name: "zmbv", get_encoder: zmbvenc::get_encoder },
 
 #[cfg(feature="encoder_pcm")]
     EncoderInfo { name: "pcm", get_encoder: pcm::get_encoder },
+#[cfg(feature="encoder_alaw")]
+    EncoderInfo { name: "alaw", get_encoder: pcm::get_a_law_encoder },
+#[cfg(feature="encoder_ulaw")]
+    EncoderInfo { name: "ulaw", get_encoder: pcm::get_mu_law_encoder },
 ];
 
 /// Registers all available encoders provided by this crate.
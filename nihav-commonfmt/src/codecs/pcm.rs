@@ -1,10 +1,11 @@
 use nihav_core::formats::*;
 use nihav_core::codecs::*;
-#[cfg(feature="encoder_pcm")]
+#[cfg(any(feature="decoder_pcm", feature="encoder_pcm"))]
 use nihav_core::io::byteio::*;
 
 #[derive(Clone,Copy,Debug,PartialEq)]
-#[cfg(feature="decoder_pcm")]
+#[cfg(any(feature="decoder_pcm", feature="encoder_alaw", feature="encoder_ulaw"))]
+#[cfg_attr(not(feature="decoder_pcm"), allow(dead_code))]
 enum PCMMode {
     Infinity,
     ALaw,
@@ -21,9 +22,9 @@ struct PCMDecoder {
 fn cvt_alaw(val: u8) -> i16 {
     let val = val ^ 0x55;
     let sign = (val & 0x80) != 0;
-    let exp  = ((val >> 4) & 7) + 4;
-    let mant = (val & 0xF) + if exp != 0 { 16 } else { 0 };
-    let aval = i16::from(mant) << exp;
+    let exp  = (val >> 4) & 7;
+    let mant = i16::from(val & 0xF) << 4;
+    let aval = if exp == 0 { mant + 8 } else { (mant + 0x108) << (exp - 1) };
     if sign { aval } else { -aval }
 }
 
@@ -31,9 +32,9 @@ fn cvt_alaw(val: u8) -> i16 {
 fn cvt_mulaw(val: u8) -> i16 {
     let val = !val;
     let sign = (val & 0x80) != 0;
-    let exp  = ((val >> 4) & 7) + 3;
-    let mant = (val & 0xF) | 0x10;
-    let aval = (i16::from(mant) << exp) - 128 - 4;
+    let exp  = (val >> 4) & 7;
+    let mant = i16::from(val & 0xF) << 3;
+    let aval = ((mant + 0x84) << exp) - 0x84;
     if !sign { aval } else { -aval }
 }
 
@@ -93,6 +94,16 @@ impl NADecoder for PCMDecoder {
     fn init(&mut self, _supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
         if let NACodecTypeInfo::Audio(ainfo) = info.get_properties() {
             self.chmap = get_default_chmap(ainfo.get_channels());
+            // WAVE_FORMAT_EXTENSIBLE header provides channel mask
+            if let Some(ref edata) = info.get_extradata() {
+                if edata.len() >= 22 {
+                    let mask = read_u32le(&edata[2..]).unwrap_or(0);
+                    let chmap = NAChannelMap::from_ms_mapping(mask);
+                    if chmap.num_channels() == usize::from(ainfo.get_channels()) {
+                        self.chmap = chmap;
+                    }
+                }
+            }
             if self.chmap.num_channels() == 0 { return Err(DecoderError::InvalidData); }
             Ok(())
         } else {
@@ -276,3 +287,218 @@ impl NAOptionHandler for PCMEncoder {
 pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
     Box::new(PCMEncoder::new())
 }
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+fn find_segment(val: i32, ends: &[i32; 8]) -> usize {
+    ends.iter().position(|&end| val <= end).unwrap_or(8)
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+fn to_alaw(sample: i16) -> u8 {
+    const SEG_END: [i32; 8] = [ 0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF ];
+
+    let val = i32::from(sample) >> 3;
+    let (val, mask) = if val >= 0 { (val, 0xD5) } else { (-val - 1, 0x55) };
+    let seg = find_segment(val, &SEG_END);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    let mant = if seg < 2 { (val >> 1) & 0xF } else { (val >> seg) & 0xF };
+    (((seg as i32) << 4) | mant) as u8 ^ mask
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+fn to_mulaw(sample: i16) -> u8 {
+    const SEG_END: [i32; 8] = [ 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF ];
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 8159;
+
+    let val = i32::from(sample) >> 2;
+    let (val, mask) = if val >= 0 { (val, 0xFF) } else { (-val, 0x7F) };
+    let val = val.min(CLIP) + (BIAS >> 2);
+    let seg = find_segment(val, &SEG_END);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    (((seg as i32) << 4) | ((val >> (seg + 1)) & 0xF)) as u8 ^ mask
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+struct XLawEncoder {
+    stream: Option<NAStreamRef>,
+    pkt:    Option<NAPacket>,
+    mode:   PCMMode,
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+impl XLawEncoder {
+    fn new(mode: PCMMode) -> Self {
+        XLawEncoder {
+            stream:     None,
+            pkt:        None,
+            mode,
+        }
+    }
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+impl NAEncoder for XLawEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Audio(NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 0)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                let mut outinfo = ainfo;
+                outinfo.channels = outinfo.channels.max(1);
+                if outinfo.format != SND_S16P_FORMAT && outinfo.format != SND_S16_FORMAT {
+                    outinfo.format = SND_S16_FORMAT;
+                }
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Audio(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                if ainfo.format != SND_S16P_FORMAT && ainfo.format != SND_S16_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.channels == 0 {
+                    return Err(EncoderError::FormatError);
+                }
+                let name = if self.mode == PCMMode::ALaw { "alaw" } else { "ulaw" };
+                let out_ainfo = NAAudioInfo::new(ainfo.sample_rate, ainfo.channels, NASoniton::new(8, 0), usize::from(ainfo.channels));
+                let info = NACodecInfo::new(name, NACodecTypeInfo::Audio(out_ainfo), None);
+                let mut stream = NAStream::new(StreamType::Audio, stream_id, info, 1, ainfo.sample_rate, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+                self.stream = Some(stream.clone());
+                Ok(stream)
+            }
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref abuf) = buf.get_abuf_i16() {
+            let len  = abuf.get_length();
+            let data = abuf.get_data();
+            let channels = abuf.get_chmap().num_channels();
+            let stride = abuf.get_stride();
+            let step = abuf.get_step();
+            let cvt = if self.mode == PCMMode::ALaw { to_alaw } else { to_mulaw };
+
+            let mut dbuf = Vec::with_capacity(len * channels);
+            for off in 0..len {
+                for j in 0..channels {
+                    dbuf.push(cvt(data[off * step + j * stride]));
+                }
+            }
+            let mut pkt = NAPacket::new(self.stream.clone().unwrap(), frm.ts, true, dbuf);
+            pkt.ts.set_duration(Some(len as u64));
+            self.pkt = Some(pkt);
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        let mut npkt = None;
+        std::mem::swap(&mut self.pkt, &mut npkt);
+        Ok(npkt)
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(feature="encoder_alaw", feature="encoder_ulaw"))]
+impl NAOptionHandler for XLawEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+    fn set_options(&mut self, _options: &[NAOption]) { }
+    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+}
+
+#[cfg(feature="encoder_alaw")]
+pub fn get_a_law_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(XLawEncoder::new(PCMMode::ALaw))
+}
+
+#[cfg(feature="encoder_ulaw")]
+pub fn get_mu_law_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(XLawEncoder::new(PCMMode::MuLaw))
+}
+
+#[cfg(all(test, feature="decoder_pcm", feature="encoder_alaw", feature="encoder_ulaw"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xlaw_decoding() {
+        // reference values from ITU-T G.711
+        for &(code, val) in [(0xD5, 8), (0x55, -8), (0x80, 5504), (0x00, -5504), (0xAA, 32256), (0x2A, -32256)].iter() {
+            assert_eq!(cvt_alaw(code), val);
+        }
+        for &(code, val) in [(0xFF, 0), (0x7F, 0), (0xFE, 8), (0x7E, -8), (0x80, 32124), (0x00, -32124)].iter() {
+            assert_eq!(cvt_mulaw(code), val);
+        }
+    }
+
+    #[test]
+    fn test_xlaw_codes() {
+        for code in 0..=255u8 {
+            assert_eq!(to_alaw(cvt_alaw(code)), code);
+        }
+        for code in (0..=255u8).filter(|&code| code != 0x7F) {
+            assert_eq!(to_mulaw(cvt_mulaw(code)), code);
+        }
+    }
+
+    #[test]
+    fn test_xlaw_encoder() {
+        let mut enc_reg = RegisteredEncoders::new();
+        crate::generic_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::generic_register_all_decoders(&mut dec_reg);
+
+        for &name in ["alaw", "ulaw"].iter() {
+            let mut encoder = (enc_reg.find_encoder(name).unwrap())();
+            let ainfo = NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 0);
+            let enc_params = EncodeParameters {
+                    format:  NACodecTypeInfo::Audio(ainfo),
+                    quality: 0,
+                    bitrate: 0,
+                    tb_num:  0,
+                    tb_den:  0,
+                    flags:   0,
+                };
+            let stream = encoder.init(0, enc_params).unwrap();
+            let mut decoder = (dec_reg.find_decoder(name).unwrap())();
+            let mut dsupp = NADecoderSupport::new();
+            decoder.init(&mut dsupp, stream.get_info()).unwrap();
+
+            let src: Vec<i16> = (0..800).map(|i| ((i as f32 * 0.07).sin() * 20000.0) as i16).collect();
+            let abuf = alloc_audio_buffer(ainfo, src.len(), NAChannelMap::from_ms_mapping(0x4)).unwrap();
+            abuf.get_abuf_i16().unwrap().get_data_mut().unwrap()[..src.len()].copy_from_slice(&src);
+            let frm = NAFrame::new(NATimeInfo::new(Some(0), None, None, 1, 8000), FrameType::I, true, stream.get_info(), abuf);
+            encoder.encode(&frm).unwrap();
+            let pkt = encoder.get_packet().unwrap().unwrap();
+            assert_eq!(pkt.get_buffer().len(), src.len());
+            let dfrm = decoder.decode(&mut dsupp, &pkt).unwrap();
+            let dbuf = dfrm.get_buffer().get_abuf_i16().unwrap();
+            for (&s, &d) in src.iter().zip(dbuf.get_data().iter()) {
+                // the quantisation step is 1/16 of the segment size
+                let diff = (i32::from(s) - i32::from(d)).abs();
+                assert!(diff <= i32::from(s).abs() / 16 + 16);
+            }
+        }
+    }
+}
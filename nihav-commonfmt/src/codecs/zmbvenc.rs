@@ -129,8 +129,8 @@ impl ZMBVEncoder {
             key_int:    25,
             tile_w:     16,
             tile_h:     16,
-            cmode:      DeflateMode::default(),
-            compr:      Deflate::new(DeflateMode::default()),
+            cmode:      DeflateMode::Max,
+            compr:      Deflate::new(DeflateMode::Max),
             bpp:        0,
             width:      0,
             height:     0,
@@ -547,7 +547,71 @@ mod test {
     use nihav_core::muxers::*;
     use crate::*;
     use nihav_codec_support::test::enc_video::*;
-    use super::{RGB555_FORMAT, RGB24_0_FORMAT};
+    use super::{RGB555_FORMAT, RGB565_FORMAT, RGB24_0_FORMAT};
+
+    // encodes frames with moving content and checks that they are decoded back losslessly, returns the total coded size
+    fn encode_lossless(compr_level: &str) -> usize {
+        const WIDTH: usize = 64;
+        const HEIGHT: usize = 48;
+
+        let mut enc_reg = RegisteredEncoders::new();
+        generic_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        generic_register_all_decoders(&mut dec_reg);
+
+        let mut encoder = (enc_reg.find_encoder("zmbv").unwrap())();
+        encoder.set_options(&[NAOption { name: "compr_level", value: NAValue::String(compr_level.to_string()) }]);
+        let vinfo = NAVideoInfo::new(WIDTH, HEIGHT, false, RGB565_FORMAT);
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(vinfo),
+                quality: 0,
+                bitrate: 0,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let stream = encoder.init(0, enc_params).unwrap();
+        let mut decoder = (dec_reg.find_decoder("zmbv").unwrap())();
+        let mut dsupp = NADecoderSupport::new();
+        decoder.init(&mut dsupp, stream.get_info()).unwrap();
+
+        let mut total_size = 0;
+        for i in 0..4 {
+            let buf = alloc_video_buffer(vinfo, 0).unwrap();
+            if let NABufferType::Video16(ref vbuf) = buf {
+                let mut vbuf = vbuf.clone();
+                let stride = vbuf.get_stride(0);
+                for (y, line) in vbuf.get_data_mut().unwrap().chunks_mut(stride).take(HEIGHT).enumerate() {
+                    for (x, pix) in line[..WIDTH].iter_mut().enumerate() {
+                        *pix = (((x + i * 3) / 8 + y / 4) * 0x841 + (x ^ y) % 5) as u16;
+                    }
+                }
+            }
+            let frm = NAFrame::new(NATimeInfo::new(Some(i as u64), None, None, 1, 25), FrameType::Other, i == 0, stream.get_info(), buf.clone());
+            encoder.encode(&frm).unwrap();
+            let pkt = encoder.get_packet().unwrap().unwrap();
+            total_size += pkt.get_buffer().len();
+            let dfrm = decoder.decode(&mut dsupp, &pkt).unwrap();
+            let src = buf.get_vbuf16().unwrap();
+            let dst = dfrm.get_buffer().get_vbuf16().unwrap();
+            let (sstride, dstride) = (src.get_stride(0), dst.get_stride(0));
+            for (sline, dline) in src.get_data().chunks(sstride).zip(dst.get_data().chunks(dstride)).take(HEIGHT) {
+                assert_eq!(sline[..WIDTH], dline[..WIDTH]);
+            }
+        }
+        total_size
+    }
+    #[test]
+    fn test_zmbv_encoder_max() {
+        if let Some(NAValue::String(level)) = super::get_encoder().query_option_value("compr_level") {
+            assert_eq!(level, "max");
+        } else {
+            panic!("compression level is not reported");
+        }
+        let max_size = encode_lossless("max");
+        let better_size = encode_lossless("better");
+        assert!(max_size <= better_size);
+    }
 
     #[test]
     fn test_zmbv_encoder_8bit() {
@@ -590,6 +654,7 @@ mod test {
             };
         let enc_options = &[
                 NAOption { name: "range", value: NAValue::Int(16) },
+                NAOption { name: "compr_level", value: NAValue::String("better".to_string()) },
             ];
         //test_encoding_to_file(&dec_config, &enc_config, enc_params, enc_options);
         test_encoding_md5(&dec_config, &enc_config, enc_params, enc_options,
@@ -637,6 +702,7 @@ mod test {
             };
         let enc_options = &[
                 NAOption { name: "range", value: NAValue::Int(16) },
+                NAOption { name: "compr_level", value: NAValue::String("better".to_string()) },
             ];
         //test_encoding_to_file(&dec_config, &enc_config, enc_params, enc_options);
         test_encoding_md5(&dec_config, &enc_config, enc_params, enc_options,
@@ -684,6 +750,7 @@ mod test {
             };
         let enc_options = &[
                 NAOption { name: "range", value: NAValue::Int(16) },
+                NAOption { name: "compr_level", value: NAValue::String("better".to_string()) },
             ];
         //test_encoding_to_file(&dec_config, &enc_config, enc_params, enc_options);
         test_encoding_md5(&dec_config, &enc_config, enc_params, enc_options,
@@ -731,6 +798,7 @@ mod test {
             };
         let enc_options = &[
                 NAOption { name: "range", value: NAValue::Int(16) },
+                NAOption { name: "compr_level", value: NAValue::String("better".to_string()) },
             ];
         //test_encoding_to_file(&dec_config, &enc_config, enc_params, enc_options);
         test_encoding_md5(&dec_config, &enc_config, enc_params, enc_options,
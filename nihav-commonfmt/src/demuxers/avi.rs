@@ -76,6 +76,13 @@ struct AVIDemuxer<'a> {
     odml:           bool,
     odml_idx:       Vec<u64>,
     odml_riff:      Vec<RIFFSegment>,
+    metadata:       ContainerMetadata,
+    frame_time:     u32,
+    tot_frames:     u32,
+    auto_index:     bool,
+    key_check:      Vec<KeyframeCheck>,
+    resilient:      bool,
+    skipped:        Vec<SkippedRange>,
 }
 
 #[derive(Debug,Clone,Copy,PartialEq)]
@@ -84,6 +91,30 @@ enum RIFFTag {
     List(u32,u32),
 }
 
+type KeyframeCheck = fn(&[u8]) -> bool;
+
+fn is_vpx_intra_frame(buf: &[u8]) -> bool {
+    !buf.is_empty() && (buf[0] & 0x80) == 0
+}
+
+fn is_any_frame(_buf: &[u8]) -> bool { true }
+
+/// Returns a function telling whether the frame is a keyframe if that can be done without an index.
+fn get_keyframe_check(stream: &NAStream) -> Option<KeyframeCheck> {
+    if stream.get_media_type() != StreamType::Video {
+        return Some(is_any_frame);
+    }
+    let cname = stream.get_info().get_name();
+    match cname {
+        // frame header starts with a flag signalling inter frame
+        "vp3" | "vp5" | "vp6" => Some(is_vpx_intra_frame),
+        _ => match register::get_codec_description(cname) {
+                Some(desc) if desc.is_intraonly() => Some(is_any_frame),
+                _ => None,
+            },
+    }
+}
+
 struct RIFFParser {
     tag:   RIFFTag,
     parse: fn(&mut AVIDemuxer, strmgr: &mut StreamManager, size: usize) -> DemuxerResult<usize>,
@@ -115,6 +146,16 @@ impl<'a> DemuxCore<'a> for AVIDemuxer<'a> {
                 }
             }
             let is_keyframe = self.key_offs.binary_search(&self.src.tell()).is_ok();
+            if self.resilient && !self.check_chunk_header()? {
+                self.resync_chunk()?;
+                if self.movi_size == 0 {
+                    if !self.odml {
+                        return Err(EOF);
+                    }
+                    self.try_next_odml_chunk()?;
+                }
+                continue;
+            }
             self.src.read_buf(&mut tag)?;
             let size = self.src.read_u32le()? as usize;
             if mktag!(tag) == mktag!(b"JUNK") {
@@ -180,7 +221,20 @@ impl<'a> DemuxCore<'a> for AVIDemuxer<'a> {
             if stream.get_media_type() == StreamType::Audio && tb_num == 1 && stream.get_info().get_name() == "pcm" {
                 ts.pts = None;
             }
-            let mut pkt = self.src.read_packet(stream, ts, is_keyframe, size)?;
+            let left = self.src.left();
+            let read_size = if self.resilient && left >= 0 && (size as i64) > left {
+                    // truncated file, return what is left of the packet
+                    if left == 0 {
+                        return Err(EOF);
+                    }
+                    left as usize
+                } else {
+                    size
+                };
+            let mut pkt = self.src.read_packet(stream, ts, is_keyframe, read_size)?;
+            if self.auto_index {
+                pkt.keyframe = (self.key_check[stream_no as usize])(&pkt.get_buffer());
+            }
             for pe in self.pal.iter_mut() {
                 if pe.stream_no == (stream_no as usize) {
                     pkt.add_side_data(NASideData::Palette(pe.changed, pe.pal.clone()));
@@ -189,7 +243,13 @@ impl<'a> DemuxCore<'a> for AVIDemuxer<'a> {
                 }
             }
             self.cur_frame[stream_no as usize] += 1;
-            self.movi_size -= size + 8;
+            self.movi_size -= read_size + 8;
+            if read_size < size {
+                // the rest of the list lies beyond the end of input
+                let pos = self.src.tell();
+                self.skipped.push(SkippedRange { start: pos, end: pos + (self.movi_size as u64) });
+                self.movi_size = 0;
+            }
 
             return Ok(pkt);
         }
@@ -242,13 +302,64 @@ impl<'a> DemuxCore<'a> for AVIDemuxer<'a> {
 
         Ok(())
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        u64::from(self.tot_frames) * u64::from(self.frame_time) / 1000
+    }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata = self.metadata.clone();
+    }
+    fn get_packet_pos(&mut self) -> Option<u64> {
+        if self.auto_index {
+            Some(self.src.tell())
+        } else {
+            None
+        }
+    }
+    fn get_skipped_ranges(&self) -> &[SkippedRange] { &self.skipped }
 }
 
+const DEMUXER_OPTIONS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: RESILIENT_OPTION, description: RESILIENT_OPTION_DESC,
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
 impl<'a> NAOptionHandler for AVIDemuxer<'a> {
-    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
-    fn set_options(&mut self, _options: &[NAOption]) { }
-    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { DEMUXER_OPTIONS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in DEMUXER_OPTIONS.iter() {
+                if opt_def.check(option).is_ok() {
+                    if let (RESILIENT_OPTION, NAValue::Bool(val)) = (option.name, &option.value) {
+                        self.resilient = *val;
+                    }
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            RESILIENT_OPTION => Some(NAValue::Bool(self.resilient)),
+            _ => None,
+        }
+    }
+}
+
+fn is_valid_chunk(hdr: &[u8], num_streams: u8, left: usize) -> bool {
+    let size = read_u32le(&hdr[4..]).unwrap_or(0) as usize;
+    if size.saturating_add(8) > left {
+        return false;
+    }
+    let tag = &hdr[..4];
+    if tag == b"JUNK" || tag == b"LIST" || tag == b"idx1" {
+        return true;
+    }
+    let (num, ctype) = if &tag[..2] == b"ix" { (&tag[2..], &tag[..2]) } else { (&tag[..2], &tag[2..]) };
+    if !num[0].is_ascii_digit() || !num[1].is_ascii_digit() {
+        return false;
+    }
+    let stream_no = (num[0] - b'0') * 10 + (num[1] - b'0');
+    stream_no < num_streams && matches!(ctype, b"ix" | b"dc" | b"db" | b"wb" | b"pc" | b"tx")
 }
 
 impl<'a> AVIDemuxer<'a> {
@@ -270,6 +381,13 @@ impl<'a> AVIDemuxer<'a> {
             odml: false,
             odml_idx: Vec::new(),
             odml_riff: Vec::with_capacity(1),
+            metadata: ContainerMetadata::new(),
+            frame_time: 0,
+            tot_frames: 0,
+            auto_index: false,
+            key_check:  Vec::new(),
+            resilient:  false,
+            skipped:    Vec::new(),
         }
     }
 
@@ -388,13 +506,14 @@ impl<'a> AVIDemuxer<'a> {
                         start = 0;
                         last_strm_no = stream_no;
                     }
-                    let ret = parse_odml_ix(&mut self.src, strmgr, seek_idx, stream_no, size, start);
+                    let ret = parse_odml_ix(&mut self.src, strmgr, seek_idx, stream_no, size, start, &mut self.key_offs);
                     if let Ok(new_start) = ret {
                         start = new_start;
                     } else {
                         break;
                     }
                 }
+                self.key_offs.sort_unstable();
             }
         }
         if self.movi_pos != 0 {
@@ -402,6 +521,17 @@ impl<'a> AVIDemuxer<'a> {
         } else {
             return Err(InvalidData);
         }
+        if seek_idx.seek_info.iter().any(|info| info.filled) {
+            seek_idx.mode = SeekIndexMode::Present;
+        } else if !seek_idx.skip_index {
+            // without an index keyframes can be told only from the frame data and only for some codecs
+            let checks: Option<Vec<KeyframeCheck>> = strmgr.iter().map(|stream| get_keyframe_check(&stream)).collect();
+            if let Some(checks) = checks {
+                seek_idx.mode = SeekIndexMode::Automatic;
+                self.auto_index = true;
+                self.key_check = checks;
+            }
+        }
         if !self.sstate.valid_state() || self.sstate.strm_no != self.num_streams {
             return Err(InvalidData);
         }
@@ -421,6 +551,7 @@ impl<'a> AVIDemuxer<'a> {
                 let start_clr           = self.src.read_byte()? as usize;
                 let len                 = self.src.read_byte()? as usize;
                 let _flags              = self.src.read_u16le()?;
+                let len = if len == 0 { 256 } else { len };
                 validate!(start_clr + len <= 256);
                 validate!(len * 4 + 4 == size);
                 let mut newpal = *pe.pal;
@@ -439,6 +570,34 @@ impl<'a> AVIDemuxer<'a> {
         self.src.read_skip(size)?;
         Ok(())
     }
+    fn check_chunk_header(&mut self) -> DemuxerResult<bool> {
+        let mut hdr = [0; 8];
+        if self.movi_size < hdr.len() || self.src.peek_buf(&mut hdr)? < hdr.len() {
+            return Ok(false);
+        }
+        Ok(is_valid_chunk(&hdr, self.num_streams, self.movi_size))
+    }
+    fn resync_chunk(&mut self) -> DemuxerResult<()> {
+        let start = self.src.tell();
+        let end = start + (self.movi_size as u64);
+        let num_streams = self.num_streams;
+        let ret = resync(self.src, 8, self.movi_size as u64, |pos, hdr| {
+                (pos & 1) == 0 && pos < end && is_valid_chunk(hdr, num_streams, (end - pos) as usize)
+            });
+        match ret {
+            Ok(range) => {
+                self.movi_size -= (range.end - range.start) as usize;
+                self.skipped.push(range);
+            },
+            Err(EOF) => {
+                self.skipped.push(SkippedRange { start, end });
+                self.src.seek(SeekFrom::Start(end))?;
+                self.movi_size = 0;
+            },
+            Err(err) => return Err(err),
+        };
+        Ok(())
+    }
     fn try_next_odml_chunk(&mut self) -> DemuxerResult<()> {
         let last_seg = self.odml_riff.last().unwrap();
         if self.src.tell() < last_seg.get_end() {
@@ -493,6 +652,9 @@ const CHUNKS: &[RIFFParser] = &[
     RIFFParser { tag: RIFFTag::Chunk(mktag!(b"indx")), parse: parse_indx },
     RIFFParser { tag: RIFFTag::Chunk(mktag!(b"JUNK")), parse: parse_junk },
     RIFFParser { tag: RIFFTag::List(mktag!(b"LIST"), mktag!(b"odml")), parse: parse_odml },
+    RIFFParser { tag: RIFFTag::List(mktag!(b"LIST"), mktag!(b"INFO")), parse: parse_info },
+    RIFFParser { tag: RIFFTag::Chunk(mktag!(b"strn")), parse: parse_strn },
+    RIFFParser { tag: RIFFTag::Chunk(mktag!(b"dmlh")), parse: parse_dmlh },
 ];
 
 fn is_list_tag(tag: u32) -> bool {
@@ -521,6 +683,33 @@ fn parse_odml(dmx: &mut AVIDemuxer, _strmgr: &mut StreamManager, _size: usize) -
     Ok(0)
 }
 
+fn parse_dmlh(dmx: &mut AVIDemuxer, _strmgr: &mut StreamManager, size: usize) -> DemuxerResult<usize> {
+    validate!(size >= 4);
+    let frames = dmx.src.read_u32le()?;
+    // main header contains the number of frames in the first RIFF chunk only
+    dmx.tot_frames = dmx.tot_frames.max(frames);
+    dmx.src.read_skip(size - 4)?;
+    Ok(size)
+}
+
+fn parse_info(dmx: &mut AVIDemuxer, _strmgr: &mut StreamManager, size: usize) -> DemuxerResult<usize> {
+    let mut buf = vec![0; size];
+    dmx.src.read_buf(&mut buf)?;
+    parse_riff_info(&buf, dmx.metadata.get_global_mut());
+    Ok(size)
+}
+
+fn parse_strn(dmx: &mut AVIDemuxer, _strmgr: &mut StreamManager, size: usize) -> DemuxerResult<usize> {
+    let mut buf = vec![0; size];
+    dmx.src.read_buf(&mut buf)?;
+    // stream name follows stream format so the stream number has been advanced already
+    if dmx.sstate.strm_no > 0 {
+        let name = String::from_utf8_lossy(&buf);
+        dmx.metadata.get_stream_mut(u32::from(dmx.sstate.strm_no - 1)).add_text(METADATA_TITLE, name.trim_end_matches('\0'));
+    }
+    Ok(size)
+}
+
 #[allow(unused_variables)]
 fn parse_strh(dmx: &mut AVIDemuxer, strmgr: &mut StreamManager, size: usize) -> DemuxerResult<usize> {
     if size < 0x38 { return Err(InvalidData); }
@@ -714,6 +903,8 @@ fn parse_avih(dmx: &mut AVIDemuxer, strmgr: &mut StreamManager, size: usize) ->
     dmx.src.read_skip(4)?; //padding
     dmx.src.read_u32le()?; //flags
     let frames = dmx.src.read_u32le()?; //frames
+    dmx.frame_time = timebase;
+    dmx.tot_frames = dmx.tot_frames.max(frames);
     dmx.src.read_skip(4)?; //initial frames
     let streams = dmx.src.read_u32le()?; //streams
     if streams > 100 { return Err(InvalidData); }
@@ -804,7 +995,7 @@ fn parse_idx1(src: &mut ByteReader, strmgr: &mut StreamManager, seek_idx: &mut S
     Ok(size)
 }
 
-fn parse_odml_ix(src: &mut ByteReader, strmgr: &mut StreamManager, seek_idx: &mut SeekIndex, stream_no: usize, size: usize, start: u64) -> DemuxerResult<u64> {
+fn parse_odml_ix(src: &mut ByteReader, strmgr: &mut StreamManager, seek_idx: &mut SeekIndex, stream_no: usize, size: usize, start: u64, key_offs: &mut Vec<u64>) -> DemuxerResult<u64> {
     validate!(size >= 24);
     let entry_size = src.read_u16le()? as usize;
     if entry_size != 2 {
@@ -825,11 +1016,17 @@ fn parse_odml_ix(src: &mut ByteReader, strmgr: &mut StreamManager, seek_idx: &mu
             for i in 0..entries {
                 let offset = src.read_u32le()?;
                 validate!(offset >= 8);
-                let _size  = src.read_u32le()?;
+                let size   = src.read_u32le()?;
+                // the top bit marks non-keyframes
+                if (size & 0x80000000) != 0 {
+                    continue;
+                }
 
                 let pts = start + (i as u64);
                 let time = NATimeInfo::ts_to_time(pts, 1000, tb_num, tb_den);
-                seek_idx.add_entry(stream_no as u32, SeekEntry { time, pts, pos: base_offset + u64::from(offset - 8) });
+                let pos = base_offset + u64::from(offset - 8);
+                seek_idx.add_entry(stream_no as u32, SeekEntry { time, pts, pos });
+                key_offs.push(pos);
             }
 
             Ok(start + (entries as u64))
@@ -875,4 +1072,232 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_avi_metadata() {
+        fn chunk(tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+            let mut ret = tag.to_vec();
+            ret.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            ret.extend_from_slice(payload);
+            if (payload.len() & 1) != 0 {
+                ret.push(0);
+            }
+            ret
+        }
+        fn list(ltype: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+            let mut data = ltype.to_vec();
+            data.extend_from_slice(payload);
+            chunk(b"LIST", &data)
+        }
+        fn u32_list(vals: &[u32]) -> Vec<u8> {
+            vals.iter().flat_map(|v| v.to_le_bytes()).collect()
+        }
+
+        let avih = u32_list(&[125000, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        let mut strh = b"auds\0\0\0\0".to_vec();
+        strh.extend_from_slice(&u32_list(&[0, 0, 0, 1, 8000, 0, 2, 0, 0, 2, 0, 0]));
+        // 8kHz 16-bit mono PCM
+        let strf = [1, 0, 1, 0, 0x40, 0x1F, 0, 0, 0x80, 0x3E, 0, 0, 2, 0, 16, 0];
+        let mut strl = chunk(b"strh", &strh);
+        strl.extend_from_slice(&chunk(b"strf", &strf));
+        strl.extend_from_slice(&chunk(b"strn", b"Commentary\0"));
+        let mut hdrl = chunk(b"avih", &avih);
+        hdrl.extend_from_slice(&list(b"strl", &strl));
+        let mut info = chunk(b"INAM", b"Test title\0");
+        info.extend_from_slice(&chunk(b"IART", b"Test artist\0"));
+        info.extend_from_slice(&chunk(b"ISFT", b"Test encoder\0"));
+
+        let mut riff = list(b"hdrl", &hdrl);
+        riff.extend_from_slice(&list(b"INFO", &info));
+        riff.extend_from_slice(&list(b"movi", &chunk(b"00wb", &[0; 4])));
+        let file = chunk(b"RIFF", &[b"AVI ".to_vec(), riff].concat());
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = AVIDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        let mut meta = ContainerMetadata::new();
+        dmx.fill_metadata(&mut meta);
+        let gmeta = meta.get_global();
+        assert_eq!(gmeta.get_text(METADATA_TITLE), Some("Test title"));
+        assert_eq!(gmeta.get_text(METADATA_ARTIST), Some("Test artist"));
+        assert_eq!(gmeta.get_text(METADATA_ENCODER), Some("Test encoder"));
+        assert_eq!(meta.get_stream(0).and_then(|smeta| smeta.get_text(METADATA_TITLE)), Some("Commentary"));
+    }
+
+    #[test]
+    fn test_avi_chunk_check() {
+        assert!(is_valid_chunk(b"00dc\x10\0\0\0", 2, 100));
+        assert!(is_valid_chunk(b"01wb\x10\0\0\0", 2, 24));
+        assert!(is_valid_chunk(b"ix00\x10\0\0\0", 2, 100));
+        assert!(is_valid_chunk(b"JUNK\0\0\0\0", 2, 8));
+        assert!(!is_valid_chunk(b"02dc\x10\0\0\0", 2, 100));
+        assert!(!is_valid_chunk(b"00dc\x10\0\0\0", 2, 23));
+        assert!(!is_valid_chunk(b"00xx\x10\0\0\0", 2, 100));
+        assert!(!is_valid_chunk(b"\0\0\0\0\0\0\0\0", 2, 100));
+    }
+
+    #[cfg(feature="muxer_avi")]
+    #[test]
+    fn test_avi_demux_resilient() {
+        use nihav_core::muxers::*;
+        use crate::generic_register_all_muxers;
+
+        const NUM_FRAMES: usize = 6;
+        const BAD_FRAME: usize = 2;
+        let vinfo = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+        let vci = NACodecInfo::new("cinepak", NACodecTypeInfo::Video(vinfo), None);
+        let ainfo = NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 2);
+        let aci = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Video, 0, vci, 1, 10, 0)).unwrap();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 1, aci, 1, 8000, 0)).unwrap();
+        let vstream = strmgr.get_stream(0).unwrap();
+        let astream = strmgr.get_stream(1).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("avi").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            for i in 0..NUM_FRAMES {
+                let ts = NATimeInfo::new(Some(i as u64), None, None, 1, 10);
+                mux.mux_frame(NAPacket::new(vstream.clone(), ts, true, vec![i as u8; 200])).unwrap();
+                let ts = NATimeInfo::new(Some((i * 400) as u64), None, None, 1, 8000);
+                mux.mux_frame(NAPacket::new(astream.clone(), ts, true, vec![i as u8; 800])).unwrap();
+            }
+            mux.end().unwrap();
+        }
+
+        // damage the header of one video chunk
+        let (bad_pos, _) = dst.windows(4).enumerate().filter(|(_, tag)| tag == b"00dc").nth(BAD_FRAME).unwrap();
+        dst[bad_pos..][..8].copy_from_slice(&[0xFF; 8]);
+
+        let read_all = |data: &[u8], skip_index: bool| -> (Vec<(usize, usize, u8)>, Vec<SkippedRange>) {
+                let mut mr = MemoryReader::new_read(data);
+                let mut br = ByteReader::new(&mut mr);
+                let mut dmx = AVIDemuxer::new(&mut br);
+                dmx.set_options(&[NAOption{ name: RESILIENT_OPTION, value: NAValue::Bool(true) }]);
+                let mut sm = StreamManager::new();
+                let mut si = SeekIndex::new();
+                si.skip_index = skip_index;
+                dmx.open(&mut sm, &mut si).unwrap();
+                let mut pkts = Vec::new();
+                loop {
+                    match dmx.get_frame(&mut sm) {
+                        Ok(pkt) => pkts.push((pkt.get_stream().get_num(), pkt.get_buffer().len(), pkt.get_buffer()[0])),
+                        Err(DemuxerError::EOF) => break,
+                        Err(err) => panic!("demuxing error {:?}", err),
+                    }
+                }
+                (pkts, dmx.get_skipped_ranges().to_vec())
+            };
+
+        let (pkts, skipped) = read_all(&dst, false);
+        let mut ref_pkts = Vec::new();
+        for i in 0..NUM_FRAMES {
+            if i != BAD_FRAME {
+                ref_pkts.push((0, 200, i as u8));
+            }
+            ref_pkts.push((1, 800, i as u8));
+        }
+        assert_eq!(pkts, ref_pkts);
+        assert_eq!(skipped, [SkippedRange { start: bad_pos as u64, end: (bad_pos + 8 + 200) as u64 }]);
+
+        // cut the input in the middle of the last audio chunk (the index is lost as well)
+        let movi_end = dst.windows(4).position(|tag| tag == b"idx1").unwrap();
+        let last_pos = dst[..movi_end].windows(4).rposition(|tag| tag == b"01wb").unwrap();
+        let trunc_len = last_pos + 8 + 300;
+        let (pkts, skipped) = read_all(&dst[..trunc_len], true);
+        ref_pkts.pop();
+        ref_pkts.push((1, 300, (NUM_FRAMES - 1) as u8));
+        assert_eq!(pkts, ref_pkts);
+        assert_eq!(skipped[1], SkippedRange { start: trunc_len as u64, end: movi_end as u64 });
+    }
+
+    #[cfg(feature="muxer_avi")]
+    fn create_unindexed_avi(vcodec: &'static str, num_frames: usize) -> Vec<u8> {
+        use nihav_core::muxers::*;
+        use crate::generic_register_all_muxers;
+
+        let vinfo = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+        let vci = NACodecInfo::new(vcodec, NACodecTypeInfo::Video(vinfo), None);
+        let ainfo = NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 2);
+        let aci = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Video, 0, vci, 1, 10, 0)).unwrap();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 1, aci, 1, 8000, 0)).unwrap();
+        let vstream = strmgr.get_stream(0).unwrap();
+        let astream = strmgr.get_stream(1).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("avi").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            for i in 0..num_frames {
+                // every other frame is marked as an inter frame in VP6 terms
+                let mut vdata = vec![i as u8; 200];
+                vdata[0] = if (i & 1) == 0 { 0x00 } else { 0x80 };
+                let ts = NATimeInfo::new(Some(i as u64), None, None, 1, 10);
+                mux.mux_frame(NAPacket::new(vstream.clone(), ts, (i & 1) == 0, vdata)).unwrap();
+                let ts = NATimeInfo::new(Some((i * 400) as u64), None, None, 1, 8000);
+                mux.mux_frame(NAPacket::new(astream.clone(), ts, true, vec![i as u8; 800])).unwrap();
+            }
+            mux.end().unwrap();
+        }
+
+        // hide the index so keyframe information is lost
+        let idx_pos = dst.windows(4).position(|tag| tag == b"idx1").unwrap();
+        dst[idx_pos..][..4].copy_from_slice(b"JUNK");
+        dst
+    }
+
+    #[cfg(feature="muxer_avi")]
+    #[test]
+    fn test_avi_auto_index_keyframes() {
+        const NUM_FRAMES: usize = 4;
+        let dst = create_unindexed_avi("vp6", NUM_FRAMES);
+
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = AVIDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        assert!(si.mode == SeekIndexMode::Automatic);
+        let mut keyframes = Vec::new();
+        loop {
+            match dmx.get_frame(&mut sm) {
+                Ok(pkt) => keyframes.push((pkt.get_stream().get_num(), pkt.is_keyframe())),
+                Err(DemuxerError::EOF) => break,
+                Err(err) => panic!("demuxing error {:?}", err),
+            }
+        }
+        let mut ref_keyframes = Vec::new();
+        for i in 0..NUM_FRAMES {
+            ref_keyframes.push((0, (i & 1) == 0));
+            ref_keyframes.push((1, true));
+        }
+        assert_eq!(keyframes, ref_keyframes);
+
+        // keyframes cannot be detected for Cinepak so no index should be built
+        let dst = create_unindexed_avi("cinepak", NUM_FRAMES);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = AVIDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        assert!(si.mode == SeekIndexMode::None);
+        assert_eq!(dmx.get_packet_pos(), None);
+    }
 }
@@ -71,7 +71,7 @@ struct TrackChunkHandler {
 }
 
 const IGNORED_CHUNKS: &[u32] = &[
-    mktag!(b"free"), mktag!(b"skip"), mktag!(b"udta"), mktag!(b"wide")
+    mktag!(b"free"), mktag!(b"skip"), mktag!(b"wide")
 ];
 
 const ROOT_CHUNK_HANDLERS: &[RootChunkHandler] = &[
@@ -80,6 +80,8 @@ const ROOT_CHUNK_HANDLERS: &[RootChunkHandler] = &[
     RootChunkHandler { ctype: mktag!(b"moov"), parse: read_moov },
     RootChunkHandler { ctype: mktag!(b"moof"), parse: read_moof },
     RootChunkHandler { ctype: mktag!(b"sidx"), parse: read_sidx },
+    RootChunkHandler { ctype: mktag!(b"udta"), parse: read_udta },
+    RootChunkHandler { ctype: mktag!(b"meta"), parse: read_meta },
 ];
 
 fn print_cname(ctype: u32, size: u64, off: u64, depth: u8) {
@@ -204,6 +206,7 @@ const MOOV_CHUNK_HANDLERS: &[RootChunkHandler] = &[
     RootChunkHandler { ctype: mktag!(b"ctab"), parse: read_ctab },
     RootChunkHandler { ctype: mktag!(b"trak"), parse: read_trak },
     RootChunkHandler { ctype: mktag!(b"meta"), parse: read_meta },
+    RootChunkHandler { ctype: mktag!(b"udta"), parse: read_udta },
     RootChunkHandler { ctype: mktag!(b"mvex"), parse: read_mvex },
     RootChunkHandler { ctype: mktag!(b"iods"), parse: skip_chunk_mov },
 ];
@@ -283,11 +286,142 @@ fn read_ctab(dmx: &mut MOVDemuxer, _strmgr: &mut StreamManager, size: u64) -> De
     Ok(size)
 }
 
+const MAX_META_SIZE: u64 = 1 << 24;
+
 fn read_meta(dmx: &mut MOVDemuxer, _strmgr: &mut StreamManager, size: u64) -> DemuxerResult<u64> {
-    dmx.src.skip64(size)?;
+    if size > MAX_META_SIZE {
+        dmx.src.skip64(size)?;
+        return Ok(size);
+    }
+    let mut buf = vec![0; size as usize];
+    dmx.src.read_buf(&mut buf)?;
+    parse_meta(&buf, dmx.metadata.get_global_mut());
+    Ok(size)
+}
+
+fn read_udta(dmx: &mut MOVDemuxer, _strmgr: &mut StreamManager, size: u64) -> DemuxerResult<u64> {
+    if size > MAX_META_SIZE {
+        dmx.src.skip64(size)?;
+        return Ok(size);
+    }
+    let mut buf = vec![0; size as usize];
+    dmx.src.read_buf(&mut buf)?;
+    parse_udta(&buf, dmx.metadata.get_global_mut());
     Ok(size)
 }
 
+fn read_u32be_at(src: &[u8], pos: usize) -> Option<u32> {
+    let buf = src.get(pos..pos + 4)?;
+    Some(read_u32be(buf).unwrap_or(0))
+}
+
+/// Iterates over the atoms stored in the buffer calling the provided function for each of them.
+fn for_each_atom<F: FnMut(u32, &[u8])>(src: &[u8], mut f: F) {
+    let mut pos = 0;
+    while pos + 8 <= src.len() {
+        let size = read_u32be_at(src, pos).unwrap_or(0) as usize;
+        let ctype = read_u32be_at(src, pos + 4).unwrap_or(0);
+        if size < 8 || pos + size > src.len() {
+            break;
+        }
+        f(ctype, &src[pos + 8..pos + size]);
+        pos += size;
+    }
+}
+
+fn udta_tag_to_key(ctype: u32) -> Option<&'static str> {
+    match ctype {
+        0xA96E616D /* (c)nam */ => Some(METADATA_TITLE),
+        0xA9415254 /* (c)ART */ |
+        0xA9617574 /* (c)aut */ => Some(METADATA_ARTIST),
+        0xA9616C62 /* (c)alb */ => Some(METADATA_ALBUM),
+        0xA9636D74 /* (c)cmt */ |
+        0xA9696E66 /* (c)inf */ |
+        0xA9646573 /* (c)des */ => Some(METADATA_COMMENT),
+        0xA9637079 /* (c)cpy */ |
+        0x63707274 /* cprt */ => Some(METADATA_COPYRIGHT),
+        0xA9646179 /* (c)day */ => Some(METADATA_DATE),
+        0xA967656E /* (c)gen */ => Some(METADATA_GENRE),
+        0xA9746F6F /* (c)too */ |
+        0xA9656E63 /* (c)enc */ |
+        0xA9737772 /* (c)swr */ => Some(METADATA_ENCODER),
+        _ => None,
+    }
+}
+
+fn parse_udta(src: &[u8], meta: &mut Metadata) {
+    for_each_atom(src, |ctype, data| {
+        if ctype == mktag!(b"meta") {
+            parse_meta(data, meta);
+        } else if let Some(key) = udta_tag_to_key(ctype) {
+            if (ctype >> 24) == 0xA9 {
+                // QuickTime text entries: 16-bit length, 16-bit language code, text
+                let mut pos = 0;
+                while pos + 4 <= data.len() {
+                    let len = read_u16be(&data[pos..]).unwrap_or(0) as usize;
+                    pos += 4;
+                    if pos + len > data.len() {
+                        break;
+                    }
+                    meta.add_text(key, String::from_utf8_lossy(&data[pos..][..len]).trim_end_matches('\0'));
+                    pos += len;
+                }
+            } else if data.len() > 6 {
+                // ISO-style entry: version/flags, language, NUL-terminated text
+                meta.add_text(key, String::from_utf8_lossy(&data[6..]).trim_end_matches('\0'));
+            }
+        }
+    });
+}
+
+fn parse_meta(src: &[u8], meta: &mut Metadata) {
+    // QuickTime meta atom has no version and flags while ISO one does
+    let src = if src.len() >= 12 && &src[4..8] != b"hdlr" { &src[4..] } else { src };
+    for_each_atom(src, |ctype, data| {
+        if ctype == mktag!(b"ilst") {
+            for_each_atom(data, |itype, idata| parse_ilst_item(itype, idata, meta));
+        }
+    });
+}
+
+fn parse_ilst_item(itype: u32, src: &[u8], meta: &mut Metadata) {
+    for_each_atom(src, |ctype, data| {
+        if ctype != mktag!(b"data") || data.len() < 8 {
+            return;
+        }
+        let dtype = read_u32be_at(data, 0).unwrap_or(0) & 0xFFFFFF;
+        let payload = &data[8..];
+        if let Some(key) = udta_tag_to_key(itype) {
+            if dtype == 1 {
+                meta.add_text(key, &String::from_utf8_lossy(payload));
+            }
+            return;
+        }
+        match itype {
+            0x74726B6E /* trkn */ if payload.len() >= 4 => {
+                let track = read_u16be(&payload[2..]).unwrap_or(0);
+                if track != 0 {
+                    meta.add_text(METADATA_TRACK, &track.to_string());
+                }
+            },
+            0x636F7672 /* covr */ => {
+                let mime = match dtype {
+                        13 => "image/jpeg",
+                        14 => "image/png",
+                        27 => "image/bmp",
+                        _ => "",
+                    };
+                meta.add(METADATA_COVER_ART, MetadataValue::Picture(MetadataPicture {
+                        mime:   mime.to_string(),
+                        desc:   String::new(),
+                        data:   payload.to_vec(),
+                    }));
+            },
+            _ => {},
+        }
+    });
+}
+
 fn read_mvex(_dmx: &mut MOVDemuxer, _strmgr: &mut StreamManager, _size: u64) -> DemuxerResult<u64> {
     Ok(0)
 }
@@ -355,14 +489,61 @@ fn read_traf(dmx: &mut MOVDemuxer, _strmgr: &mut StreamManager, size: u64) -> De
 const TRAK_CHUNK_HANDLERS: &[TrackChunkHandler] = &[
     TrackChunkHandler { ctype: mktag!(b"clip"), parse: skip_chunk },
     TrackChunkHandler { ctype: mktag!(b"matt"), parse: skip_chunk },
-    TrackChunkHandler { ctype: mktag!(b"edts"), parse: skip_chunk },
-    TrackChunkHandler { ctype: mktag!(b"tref"), parse: skip_chunk },
+    TrackChunkHandler { ctype: mktag!(b"edts"), parse: read_edts },
+    TrackChunkHandler { ctype: mktag!(b"tref"), parse: read_tref },
     TrackChunkHandler { ctype: mktag!(b"load"), parse: skip_chunk },
     TrackChunkHandler { ctype: mktag!(b"imap"), parse: skip_chunk },
     TrackChunkHandler { ctype: mktag!(b"tkhd"), parse: read_tkhd },
     TrackChunkHandler { ctype: mktag!(b"mdia"), parse: read_mdia },
 ];
 
+fn read_edts(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult<u64> {
+    track.read_edts(br, size)?;
+    Ok(size)
+}
+
+const EDTS_CHUNK_HANDLERS: &[TrackChunkHandler] = &[
+    TrackChunkHandler { ctype: mktag!(b"elst"), parse: read_elst },
+];
+
+fn read_elst(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult<u64> {
+    validate!(size >= 8);
+    let version             = br.read_byte()?;
+    validate!(version <= 1);
+    let _flags              = br.read_u24be()?;
+    let entries             = br.read_u32be()? as u64;
+    let entry_size = if version == 0 { 12 } else { 20 };
+    validate!(entries <= (size - 8) / entry_size);
+    track.edits.clear();
+    for _ in 0..entries {
+        let (duration, media_time) = if version == 0 {
+                (u64::from(br.read_u32be()?), i64::from(br.read_u32be()? as i32))
+            } else {
+                (br.read_u64be()?, br.read_u64be()? as i64)
+            };
+        let rate            = br.read_u32be()?;
+        track.edits.push(EditEntry { duration, media_time, rate });
+    }
+    Ok(8 + entries * entry_size)
+}
+
+fn read_tref(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult<u64> {
+    track.read_tref(br, size)?;
+    Ok(size)
+}
+
+const TREF_CHUNK_HANDLERS: &[TrackChunkHandler] = &[
+    TrackChunkHandler { ctype: mktag!(b"chap"), parse: read_chap },
+];
+
+fn read_chap(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult<u64> {
+    for _ in 0..size / 4 {
+        let id              = br.read_u32be()?;
+        track.chapter_tracks.push(id);
+    }
+    Ok(size & !3)
+}
+
 fn read_tkhd(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult<u64> {
     const KNOWN_TKHD_SIZE: u64 = 84;
     validate!(size >= KNOWN_TKHD_SIZE);
@@ -414,6 +595,7 @@ fn read_mdhd(track: &mut Track, br: &mut ByteReader, size: u64) -> DemuxerResult
     let _mtime              = br.read_u32be()?;
     track.tb_den            = br.read_u32be()?;
     validate!(track.tb_den != 0);
+    track.media_tscale      = track.tb_den;
     track.duration          = br.read_u32be()?;
     let _language           = br.read_u16be()?;
     let _quality            = br.read_u16be()?;
@@ -1047,6 +1229,7 @@ struct MOVDemuxer<'a> {
     tb_den:         u32,
     duration:       u32,
     pal:            Option<Arc<[u8; 1024]>>,
+    metadata:       ContainerMetadata,
 
     moof_off:       u64,
 
@@ -1090,12 +1273,25 @@ struct Track {
     last_offset:    u64,
     pal:            Option<Arc<[u8; 1024]>>,
     timesearch:     TimeSearcher,
+    movie_tscale:   u32,
+    media_tscale:   u32,
+    edits:          Vec<EditEntry>,
+    edit_delay:     u64,
+    edit_start:     u64,
+    chapter_tracks: Vec<u32>,
 
     moof_off:       u64,
 
     print_chunks:   bool,
 }
 
+struct EditEntry {
+    duration:       u64,
+    media_time:     i64,
+    #[allow(dead_code)]
+    rate:           u32,
+}
+
 #[derive(Default)]
 struct TimeSearcher {
     idx:        usize,
@@ -1233,6 +1429,12 @@ impl Track {
             last_offset:    0,
             pal:            None,
             timesearch:     TimeSearcher::new(),
+            movie_tscale:   tb_den,
+            media_tscale:   tb_den,
+            edits:          Vec::new(),
+            edit_delay:     0,
+            edit_start:     0,
+            chapter_tracks: Vec::new(),
 
             moof_off:       0,
 
@@ -1244,6 +1446,8 @@ impl Track {
     read_chunk_list!(track; "minf", read_minf, MINF_CHUNK_HANDLERS);
     read_chunk_list!(track; "stbl", read_stbl, STBL_CHUNK_HANDLERS);
     read_chunk_list!(track; "traf", read_traf, TRAF_CHUNK_HANDLERS);
+    read_chunk_list!(track; "edts", read_edts, EDTS_CHUNK_HANDLERS);
+    read_chunk_list!(track; "tref", read_tref, TREF_CHUNK_HANDLERS);
     fn rescale(&mut self, tb_num: u32) {
         self.tb_div = tb_num;
         if let Some(ref mut stream) = self.stream {
@@ -1257,6 +1461,78 @@ impl Track {
             self.duration /= self.tb_div;
         }
     }
+    /// Converts time in the provided timescale to the track timestamp units.
+    fn scale_time(&self, time: u64, tscale: u32) -> u64 {
+        if tscale == 0 || self.tb_num == 0 {
+            return 0;
+        }
+        (u128::from(time) * u128::from(self.tb_den) / (u128::from(tscale) * u128::from(self.tb_num))) as u64
+    }
+    /// Converts edit list into the initial delay and media start time.
+    ///
+    /// Only the first non-empty edit is taken into account since the samples are output sequentially anyway.
+    fn setup_edits(&mut self) {
+        if self.edits.is_empty() {
+            return;
+        }
+        let mut delay = 0;
+        let mut start = None;
+        let mut total_duration = 0;
+        for edit in self.edits.iter() {
+            if edit.media_time < 0 {
+                if start.is_none() {
+                    delay += edit.duration;
+                }
+            } else if start.is_none() {
+                start = Some(edit.media_time as u64);
+            }
+            total_duration += edit.duration;
+        }
+        self.edit_delay = self.scale_time(delay, self.movie_tscale);
+        self.edit_start = self.scale_time(start.unwrap_or(0), self.media_tscale);
+        if total_duration > 0 {
+            let duration = self.scale_time(total_duration, self.movie_tscale);
+            if let Some(ref mut stream) = self.stream {
+                stream.duration = duration;
+            }
+        }
+    }
+    /// Maps media time to the presentation time, media times before the edit start are mapped to the edit beginning.
+    fn map_edit_time(&self, time: u64) -> u64 {
+        time.saturating_sub(self.edit_start) + self.edit_delay
+    }
+    /// Maps media timestamp to the presentation timestamp.
+    ///
+    /// Samples before the edit start (e.g. audio priming) get the timestamp of the edit beginning and their duration is reduced to the part that should be presented (so zero means that the decoded output should be discarded).
+    fn apply_edits(&self, mut ts: NATimeInfo) -> NATimeInfo {
+        if self.edit_delay == 0 && self.edit_start == 0 {
+            return ts;
+        }
+        if let Some(pts) = ts.pts {
+            if pts < self.edit_start {
+                let dur = ts.duration.unwrap_or(if self.time_to_sample.is_empty() { 1 } else { u64::from(self.timesearch.cur_mul) });
+                ts.duration = Some((pts + dur).saturating_sub(self.edit_start));
+            }
+            ts.pts = Some(self.map_edit_time(pts));
+        }
+        if let Some(dts) = ts.dts {
+            ts.dts = Some(self.map_edit_time(dts));
+        }
+        ts
+    }
+    /// Maps presentation timestamp back to the media timestamp.
+    fn unmap_edits(&self, pts: u64) -> u64 {
+        pts.saturating_sub(self.edit_delay) + self.edit_start
+    }
+    fn reset_position(&mut self) {
+        self.cur_chunk = 0;
+        self.cur_sample = 0;
+        self.cur_ts = None;
+        self.samples_left = 0;
+        self.last_offset = 0;
+        self.raw_apos = 0;
+        self.timesearch.reset();
+    }
     fn fill_seek_index(&self, seek_index: &mut SeekIndex) {
         if !self.keyframes.is_empty() {
             seek_index.mode = SeekIndexMode::Present;
@@ -1264,6 +1540,7 @@ impl Track {
         let mut tsearch = TimeSearcher::new();
         for kf_time in self.keyframes.iter() {
             let pts = tsearch.map_time(*kf_time - 1, &self.time_to_sample);
+            let pts = self.map_edit_time(pts);
             let time = NATimeInfo::ts_to_time(pts, 1000, self.tb_num, self.tb_den);
             seek_index.add_entry(self.track_no as u32, SeekEntry { time, pts: u64::from(*kf_time - 1), pos: 0 });
         }
@@ -1404,7 +1681,7 @@ impl Track {
         self.cur_ts = None;
         if self.stream_type == StreamType::Audio {
             if let NATimePoint::Milliseconds(ms) = tpoint {
-                let exp_pts = NATimeInfo::time_to_ts(ms, 1000, self.tb_num, self.tb_den);
+                let exp_pts = self.unmap_edits(NATimeInfo::time_to_ts(ms, 1000, self.tb_num, self.tb_den));
                 if self.raw_audio {
                     if self.frame_samples != 0 {
                         self.raw_apos = exp_pts / (self.frame_samples as u64);
@@ -1540,6 +1817,7 @@ impl Track {
 }
 
 fn process_packet(src: &mut ByteReader, strmgr: &StreamManager, track: &mut Track, pts: NATimeInfo, offset: u64, size: usize, first: bool) -> DemuxerResult<NAPacket> {
+    let pts = track.apply_edits(pts);
     if let Some(cpts) = pts.get_pts() {
         let ts = NATimeInfo::ts_to_time(cpts, 1000, pts.tb_num, pts.tb_den);
         track.cur_ts = Some(ts);
@@ -1564,6 +1842,7 @@ impl<'a> DemuxCore<'a> for MOVDemuxer<'a> {
         validate!(self.mdat_pos > 0);
         validate!(!self.tracks.is_empty());
         for track in self.tracks.iter_mut() {
+            track.setup_edits();
             let mut str = None;
             std::mem::swap(&mut track.stream, &mut str);
             if let Some(stream) = str {
@@ -1574,6 +1853,7 @@ impl<'a> DemuxCore<'a> for MOVDemuxer<'a> {
         for track in self.tracks.iter() {
             track.fill_seek_index(seek_index);
         }
+        self.read_chapters()?;
         self.src.seek(SeekFrom::Start(self.mdat_pos))?;
         self.cur_track = 0;
         Ok(())
@@ -1661,6 +1941,9 @@ impl<'a> DemuxCore<'a> for MOVDemuxer<'a> {
             0
         }
     }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata = self.metadata.clone();
+    }
 }
 
 const PRINT_CHUNKS: &str = "print_chunks";
@@ -1709,6 +1992,7 @@ impl<'a> MOVDemuxer<'a> {
             tb_den:         0,
             duration:       0,
             pal:            None,
+            metadata:       ContainerMetadata::new(),
 
             moof_off:       0,
 
@@ -1744,6 +2028,56 @@ impl<'a> MOVDemuxer<'a> {
     }
     read_chunk_list!(root; "moov", read_moov, MOOV_CHUNK_HANDLERS);
     read_chunk_list!(root; "moof", read_moof, MOOF_CHUNK_HANDLERS);
+    fn read_chapters(&mut self) -> DemuxerResult<()> {
+        let mut chap_ids = Vec::new();
+        for track in self.tracks.iter() {
+            for &id in track.chapter_tracks.iter() {
+                if !chap_ids.contains(&id) {
+                    chap_ids.push(id);
+                }
+            }
+        }
+        let duration = self.get_duration();
+        for id in chap_ids {
+            let track = if let Some(trk) = self.tracks.iter_mut().find(|trk| trk.track_id == id) {
+                    trk
+                } else {
+                    continue;
+                };
+            let mut chapters: Vec<Chapter> = Vec::new();
+            while let Some((ts, offset, size)) = track.get_next_chunk() {
+                let ts = track.apply_edits(ts);
+                let start = NATimeInfo::ts_to_time(ts.pts.unwrap_or(0), 1000, ts.tb_num, ts.tb_den);
+                let mut buf = vec![0; size];
+                self.src.seek(SeekFrom::Start(offset))?;
+                self.src.read_buf(&mut buf)?;
+                if let Some(last) = chapters.last_mut() {
+                    last.end = start;
+                }
+                chapters.push(Chapter { start, end: duration.max(start), title: parse_text_sample(&buf) });
+            }
+            track.reset_position();
+            for chapter in chapters.into_iter() {
+                self.metadata.add_chapter(chapter);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts text from QuickTime text sample (16-bit length followed by UTF-8 or UTF-16 text).
+fn parse_text_sample(src: &[u8]) -> String {
+    if src.len() < 2 {
+        return String::new();
+    }
+    let len = (read_u16be(src).unwrap_or(0) as usize).min(src.len() - 2);
+    let text = &src[2..][..len];
+    if text.len() >= 2 && text[0] == 0xFE && text[1] == 0xFF {
+        let wchars: Vec<u16> = text[2..].chunks_exact(2).map(|c| u16::from(c[0]) * 256 + u16::from(c[1])).collect();
+        String::from_utf16_lossy(&wchars)
+    } else {
+        String::from_utf8_lossy(text).to_string()
+    }
 }
 
 pub struct MOVDemuxerCreator { }
@@ -2043,6 +2377,147 @@ mod test {
     use super::*;
     use std::fs::File;
 
+    fn atom(tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(payload.len() + 8);
+        ret.extend_from_slice(&((payload.len() + 8) as u32).to_be_bytes());
+        ret.extend_from_slice(tag);
+        ret.extend_from_slice(payload);
+        ret
+    }
+
+    fn u32_list(vals: &[u32]) -> Vec<u8> {
+        vals.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn data_track(track_id: u32, tscale: u32, duration: u32, sample_dur: u32, sizes: &[u32], offset: u32, extra: &[u8]) -> Vec<u8> {
+        let mut tkhd = vec![0; 84];
+        tkhd[12..16].copy_from_slice(&track_id.to_be_bytes());
+        let mut mdhd = u32_list(&[0, 0, 0, tscale, duration]);
+        mdhd.extend_from_slice(&[0; 4]);
+        let mut hdlr = u32_list(&[0, mktag!(b"mhlr"), mktag!(b"text"), 0, 0, 0]);
+        hdlr.push(0);
+        let mut stsd = u32_list(&[0, 1, 16, mktag!(b"text"), 0, 1]);
+        stsd.extend_from_slice(&[0; 8]);
+        let stts = u32_list(&[0, 1, sizes.len() as u32, sample_dur]);
+        let stsc = u32_list(&[0, 1, 1, sizes.len() as u32, 1]);
+        let mut stsz = u32_list(&[0, 0, sizes.len() as u32]);
+        stsz.extend_from_slice(&u32_list(sizes));
+        let stco = u32_list(&[0, 1, offset]);
+
+        let mut stbl = atom(b"stsd", &stsd);
+        stbl.extend_from_slice(&atom(b"stts", &stts));
+        stbl.extend_from_slice(&atom(b"stsc", &stsc));
+        stbl.extend_from_slice(&atom(b"stsz", &stsz));
+        stbl.extend_from_slice(&atom(b"stco", &stco));
+        let mut minf = atom(b"gmhd", &[]);
+        minf.extend_from_slice(&atom(b"stbl", &stbl));
+        let mut mdia = atom(b"mdhd", &mdhd);
+        mdia.extend_from_slice(&atom(b"hdlr", &hdlr));
+        mdia.extend_from_slice(&atom(b"minf", &minf));
+        let mut trak = atom(b"tkhd", &tkhd);
+        trak.extend_from_slice(extra);
+        trak.extend_from_slice(&atom(b"mdia", &mdia));
+        atom(b"trak", &trak)
+    }
+
+    #[test]
+    fn test_mov_edits_chapters() {
+        let chap1 = b"\x00\x05Intro";
+        let chap2 = b"\x00\x04Main";
+        let mut mdat = vec![1, 2, 3, 4];
+        mdat.extend_from_slice(chap1);
+        mdat.extend_from_slice(chap2);
+        let mut file = atom(b"mdat", &mdat);
+
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&3000u32.to_be_bytes());
+        // 100ms empty edit followed by media starting at 0.3s
+        let elst = u32_list(&[0, 2, 100, 0xFFFFFFFF, 0x10000, 400, 3, 0x10000]);
+        let mut extra = atom(b"edts", &atom(b"elst", &elst));
+        extra.extend_from_slice(&atom(b"tref", &atom(b"chap", &u32_list(&[2]))));
+        let mut moov = atom(b"mvhd", &mvhd);
+        moov.extend_from_slice(&data_track(1, 10, 4, 1, &[1, 1, 1, 1], 8, &extra));
+        moov.extend_from_slice(&data_track(2, 1000, 3000, 1500, &[chap1.len() as u32, chap2.len() as u32], 12, &[]));
+        file.extend_from_slice(&atom(b"moov", &moov));
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = MOVDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        let mut meta = ContainerMetadata::new();
+        dmx.fill_metadata(&mut meta);
+        let chapters = meta.get_chapters();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0], Chapter { start: 0, end: 1500, title: "Intro".to_string() });
+        assert_eq!(chapters[1], Chapter { start: 1500, end: 3000, title: "Main".to_string() });
+
+        let mut pts = Vec::new();
+        while let Ok(pkt) = dmx.get_frame(&mut sm) {
+            if pkt.get_stream().get_id() == 0 {
+                pts.push((pkt.ts.pts, pkt.ts.duration));
+            }
+        }
+        // the first three samples precede the media start and should be discarded, the rest is delayed by the empty edit
+        assert_eq!(pts, vec![(Some(1), Some(0)), (Some(1), Some(0)), (Some(1), Some(0)), (Some(1), None)]);
+    }
+
+    #[test]
+    fn test_mov_metadata() {
+        fn qt_text(text: &str) -> Vec<u8> {
+            let mut ret = (text.len() as u16).to_be_bytes().to_vec();
+            ret.extend_from_slice(&[0x55, 0xC4]); // language code
+            ret.extend_from_slice(text.as_bytes());
+            ret
+        }
+        fn ilst_item(tag: &[u8; 4], dtype: u32, payload: &[u8]) -> Vec<u8> {
+            let mut data = u32_list(&[dtype, 0]);
+            data.extend_from_slice(payload);
+            atom(tag, &atom(b"data", &data))
+        }
+
+        let mut ilst = ilst_item(b"\xA9alb", 1, b"Test album");
+        ilst.extend_from_slice(&ilst_item(b"trkn", 0, &[0, 0, 0, 7, 0, 12, 0, 0]));
+        ilst.extend_from_slice(&ilst_item(b"covr", 14, b"\x89PNG"));
+        let mut meta = vec![0; 4];
+        meta.extend_from_slice(&atom(b"hdlr", &u32_list(&[0, 0, mktag!(b"mdir"), 0, 0, 0])));
+        meta.extend_from_slice(&atom(b"ilst", &ilst));
+        let mut udta = atom(b"\xA9nam", &qt_text("Test title"));
+        udta.extend_from_slice(&atom(b"\xA9ART", &qt_text("Test artist")));
+        udta.extend_from_slice(&atom(b"meta", &meta));
+
+        let mut file = atom(b"mdat", &[1]);
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        let mut moov = atom(b"mvhd", &mvhd);
+        moov.extend_from_slice(&data_track(1, 10, 10, 10, &[1], 8, &[]));
+        moov.extend_from_slice(&atom(b"udta", &udta));
+        file.extend_from_slice(&atom(b"moov", &moov));
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = MOVDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        let mut meta = ContainerMetadata::new();
+        dmx.fill_metadata(&mut meta);
+        let gmeta = meta.get_global();
+        assert_eq!(gmeta.get_text(METADATA_TITLE), Some("Test title"));
+        assert_eq!(gmeta.get_text(METADATA_ARTIST), Some("Test artist"));
+        assert_eq!(gmeta.get_text(METADATA_ALBUM), Some("Test album"));
+        assert_eq!(gmeta.get_text(METADATA_TRACK), Some("7"));
+        if let Some(MetadataValue::Picture(ref pic)) = gmeta.get(METADATA_COVER_ART) {
+            assert_eq!(pic.mime, "image/png");
+            assert_eq!(pic.data, b"\x89PNG");
+        } else {
+            panic!("no cover art");
+        }
+    }
+
     #[test]
     fn test_mov_demux() {
         let mut file = File::open("assets/Indeo/cubes.mov").unwrap();
@@ -2,6 +2,8 @@ use nihav_core::demuxers::*;
 use nihav_registry::register;
 use nihav_core::demuxers::DemuxerError::*;
 
+const DS64_SIZE: usize = 28;
+
 macro_rules! mktag {
     ($a:expr, $b:expr, $c:expr, $d:expr) => {
         (u32::from($a) << 24) | (u32::from($b) << 16) | (u32::from($c) << 8) | u32::from($d)
@@ -20,24 +22,43 @@ struct WAVDemuxer<'a> {
     is_pcm:         bool,
     avg_bytes:      u32,
     duration:       u64,
+    metadata:       Metadata,
 }
 
 impl<'a> DemuxCore<'a> for WAVDemuxer<'a> {
     fn open(&mut self, strmgr: &mut StreamManager, seek_index: &mut SeekIndex) -> DemuxerResult<()> {
         let riff                        = self.src.read_u32be()?;
         let riff_size                   = self.src.read_u32le()? as usize;
-        let riff_end = self.src.tell() + if riff_size > 0 { riff_size as u64 } else { u64::from(std::u32::MAX) };
+        let mut riff_end = self.src.tell() + if riff_size > 0 { riff_size as u64 } else { u64::from(u32::MAX) };
         let wave                        = self.src.read_u32be()?;
-        validate!(riff == mktag!(b"RIFF"));
+        let is_rf64 = riff == mktag!(b"RF64") || riff == mktag!(b"BW64");
+        validate!(riff == mktag!(b"RIFF") || is_rf64);
         validate!(wave == mktag!(b"WAVE"));
 
         seek_index.mode = SeekIndexMode::Automatic;
 
         let mut fmt_parsed = false;
         let mut duration = 0;
-        while self.src.tell() < riff_end {
+        let mut data_size64 = 0;
+        if is_rf64 {
             let ctype                   = self.src.read_tag()?;
             let csize                   = self.src.read_u32le()? as usize;
+            validate!(&ctype == b"ds64" && csize >= DS64_SIZE);
+            let riff_size64             = self.src.read_u64le()?;
+            data_size64                 = self.src.read_u64le()?;
+            let nsamples                = self.src.read_u64le()?;
+                                          self.src.read_skip(csize - 24)?; // chunk size table is not used
+            if riff_size == (u32::MAX as usize) {
+                riff_end = 8 + riff_size64;
+            }
+            duration = nsamples as usize;
+        }
+        while self.src.tell() < riff_end {
+            let ctype                   = self.src.read_tag()?;
+            let mut csize               = self.src.read_u32le()? as usize;
+            if is_rf64 && csize == (u32::MAX as usize) && &ctype == b"data" {
+                csize = data_size64 as usize;
+            }
             match &ctype {
                 b"fmt " => {
                     validate!(!fmt_parsed);
@@ -46,23 +67,42 @@ impl<'a> DemuxCore<'a> for WAVDemuxer<'a> {
                 },
                 b"fact" => {
                     validate!(csize == 4);
-                    duration            = self.src.read_u32le()? as usize;
+                    let nsamples        = self.src.read_u32le()? as usize;
+                    if !is_rf64 || nsamples != (u32::MAX as usize) {
+                        duration = nsamples;
+                    }
                 },
                 b"data" => {
                     validate!(fmt_parsed);
                     self.data_pos = self.src.tell();
-                    self.data_end = self.data_pos + (csize as u64);
+                    // streamed files may have the data size not filled in
+                    let unknown_size = csize == (u32::MAX as usize) || (csize == 0 && !self.src.is_seekable());
+                    self.data_end = if !unknown_size { self.data_pos + (csize as u64) } else { u64::MAX };
 
                     if duration != 0 {
                         self.duration = (duration as u64) * 1000 / u64::from(self.srate);
+                    } else if unknown_size {
+                        self.duration = 0;
                     } else if self.avg_bytes > 0 {
                         self.duration = (self.data_end - self.data_pos) * 1000 / u64::from(self.avg_bytes);
                     } else {
                         self.duration = 0;
                     }
 
+                    // metadata is often stored after the audio data
+                    if !unknown_size && self.src.is_seekable() {
+                        let data_end = self.data_end + (self.data_end & 1);
+                        if data_end < riff_end && self.src.seek(SeekFrom::Start(data_end)).is_ok() {
+                            let _ = self.parse_trailing_chunks(riff_end);
+                            self.src.seek(SeekFrom::Start(self.data_pos))?;
+                        }
+                    }
+
                     return Ok(());
                 },
+                b"LIST" => {
+                    self.parse_list(csize)?;
+                },
                 _ => {
                                           self.src.read_skip(csize)?;
                 },
@@ -72,7 +112,8 @@ impl<'a> DemuxCore<'a> for WAVDemuxer<'a> {
     }
 
     fn get_frame(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<NAPacket> {
-        if self.src.tell() >= self.data_end {
+        // data size may be unknown for streamed input so its end is reached only at the end of input
+        if self.src.tell() >= self.data_end || self.src.is_eof() {
             return Err(DemuxerError::EOF);
         }
         let str = strmgr.get_stream(0);
@@ -117,6 +158,10 @@ impl<'a> DemuxCore<'a> for WAVDemuxer<'a> {
     }
 
     fn get_duration(&self) -> u64 { self.duration }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
+    fn is_seekable(&mut self) -> bool { self.src.is_seekable() }
 }
 
 impl<'a> NAOptionHandler for WAVDemuxer<'a> {
@@ -136,7 +181,35 @@ impl<'a> WAVDemuxer<'a> {
             is_pcm:     false,
             avg_bytes:  0,
             duration:   0,
+            metadata:   Metadata::new(),
+        }
+    }
+    fn parse_trailing_chunks(&mut self, riff_end: u64) -> DemuxerResult<()> {
+        while self.src.tell() + 8 <= riff_end {
+            let ctype                   = self.src.read_tag()?;
+            let csize                   = self.src.read_u32le()? as usize;
+            if &ctype == b"LIST" {
+                self.parse_list(csize)?;
+            } else {
+                                          self.src.read_skip(csize + (csize & 1))?;
+            }
         }
+        Ok(())
+    }
+    fn parse_list(&mut self, csize: usize) -> DemuxerResult<()> {
+        validate!(csize >= 4);
+        let ltype                       = self.src.read_tag()?;
+        if &ltype == b"INFO" {
+            let mut buf = vec![0; csize - 4];
+            self.src.read_buf(&mut buf)?;
+            parse_riff_info(&buf, &mut self.metadata);
+        } else {
+                                          self.src.read_skip(csize - 4)?;
+        }
+        if (csize & 1) != 0 {
+                                          self.src.read_skip(1)?;
+        }
+        Ok(())
     }
     fn parse_fmt(&mut self, strmgr: &mut StreamManager, csize: usize) -> DemuxerResult<()> {
         validate!(csize >= 14);
@@ -161,7 +234,12 @@ impl<'a> WAVDemuxer<'a> {
             } else {
                 None
             };
-
+        // WAVE_FORMAT_EXTENSIBLE stores the actual format tag in the first bytes of sub-format GUID
+        let format_tag = match (format_tag, &edata) {
+                (0xFFFE, Some(ref buf)) if buf.len() >= 22 => read_u16le(&buf[6..])?,
+                (0xFFFE, _) => return Err(InvalidData),
+                _ => format_tag,
+            };
         let cname = register::find_codec_from_wav_twocc(format_tag).unwrap_or("unknown");
         let soniton = if cname == "pcm" {
                 if format_tag != 0x0003 {
@@ -227,4 +305,37 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_wav_demux_stream() {
+        let audio: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        // streamed files may have sizes not filled in
+        for &size in [audio.len() as u32, 0].iter() {
+            let mut data = Vec::new();
+            data.extend_from_slice(b"RIFF");
+            data.extend_from_slice(&(if size > 0 { size + 36 } else { 0 }).to_le_bytes());
+            data.extend_from_slice(b"WAVEfmt \x10\0\0\0");
+            // 8kHz 16-bit mono PCM
+            data.extend_from_slice(&[1, 0, 1, 0, 0x40, 0x1F, 0, 0, 0x80, 0x3E, 0, 0, 2, 0, 16, 0]);
+            data.extend_from_slice(b"data");
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&audio);
+
+            let mut sr = StreamReader::new_read(&data[..]);
+            let mut br = ByteReader::new(&mut sr);
+            let dmx_cr = WAVDemuxerCreator {};
+            let mut dmx = create_demuxer(&dmx_cr, &mut br).unwrap();
+            assert!(!dmx.is_seekable());
+            let mut demuxed = Vec::new();
+            loop {
+                match dmx.get_frame() {
+                    Ok(pkt) => demuxed.extend_from_slice(&pkt.get_buffer()),
+                    Err(DemuxerError::EOF) => break,
+                    Err(err) => panic!("demuxing error {:?}", err),
+                }
+            }
+            assert_eq!(demuxed, audio);
+            assert_eq!(dmx.seek(NATimePoint::Milliseconds(0)).unwrap_err(), DemuxerError::NotPossible);
+        }
+    }
 }
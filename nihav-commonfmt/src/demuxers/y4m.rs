@@ -10,6 +10,7 @@ struct Y4MDemuxer<'a> {
     fps_num:    u32,
     fps_den:    u32,
     frameno:    u64,
+    nframes:    u64,
 }
 
 impl<'a> DemuxCore<'a> for Y4MDemuxer<'a> {
@@ -17,10 +18,16 @@ impl<'a> DemuxCore<'a> for Y4MDemuxer<'a> {
         let format = self.parse_header()?;
         seek_index.mode = SeekIndexMode::Automatic;
 
+        // frames have fixed size so their number can be calculated from the file size
+        let file_size = self.src.size();
+        if file_size > 0 && (file_size as u64) > self.hdr_size {
+            self.nframes = (file_size as u64 - self.hdr_size) / ((self.frame_size + 6) as u64);
+        }
+
         let vhdr = NAVideoInfo::new(self.width, self.height, false, format);
         let vci = NACodecTypeInfo::Video(vhdr);
         let vinfo = NACodecInfo::new("rawvideo", vci, None);
-        if strmgr.add_stream(NAStream::new(StreamType::Video, 0, vinfo, self.fps_num, self.fps_den, 0)).is_none() {
+        if strmgr.add_stream(NAStream::new(StreamType::Video, 0, vinfo, self.fps_num, self.fps_den, self.nframes)).is_none() {
             return Err(DemuxerError::MemoryError);
         }
 
@@ -63,7 +70,14 @@ impl<'a> DemuxCore<'a> for Y4MDemuxer<'a> {
 
         Ok(())
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        if self.fps_den != 0 {
+            NATimeInfo::ts_to_time(self.nframes, 1000, self.fps_num, self.fps_den)
+        } else {
+            0
+        }
+    }
+    fn is_seekable(&mut self) -> bool { self.src.is_seekable() }
 }
 
 impl<'a> NAOptionHandler for Y4MDemuxer<'a> {
@@ -83,6 +97,7 @@ impl<'a> Y4MDemuxer<'a> {
             fps_den:    0,
             hdr_size:   0,
             frameno:    0,
+            nframes:    0,
         }
     }
     fn parse_header(&mut self) -> DemuxerResult<NAPixelFormaton> {
@@ -158,6 +173,7 @@ impl<'a> Y4MDemuxer<'a> {
             }
         }
         validate!(self.frame_size > 0);
+        self.hdr_size = self.src.tell();
 
         Ok(format)
     }
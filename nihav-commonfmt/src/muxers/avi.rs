@@ -10,12 +10,44 @@ struct IdxEntry {
     len:        u32,
 }
 
+// RIFF segments are limited to 1GB for compatibility with AVI 1.0 readers
+const RIFF_SIZE_LIMIT: u64 = 1 << 30;
+const MAX_SUPER_INDEX_ENTRIES: usize = 256;
+const SUPER_INDEX_SIZE: usize = 24 + MAX_SUPER_INDEX_ENTRIES * 16;
+const ODML_LIST_SIZE: usize = 4 + 8 + 248;
+
 #[derive(Clone,Copy)]
+struct SuperIndexEntry {
+    pos:        u64,
+    size:       u32,
+    duration:   u32,
+}
+
+#[derive(Clone)]
 struct AVIStream {
     strh_pos:   u64,
     nframes:    u32,
     is_video:   bool,
+    stype:      StreamType,
     max_size:   u32,
+    indx_pos:   u64,
+    // standard index entries for the current RIFF segment as (position, size) pairs
+    ix:         Vec<(u64, u32)>,
+    super_idx:  Vec<SuperIndexEntry>,
+    // sample rate and frame length for VBR audio
+    vbr_info:   Option<(u32, u32)>,
+    strf_pos:   u64,
+    tot_size:   u64,
+    pal:        Option<[u8; 1024]>,
+}
+
+fn get_vbr_frame_length(cname: &str, srate: u32) -> Option<u32> {
+    match cname {
+        "mp3" if srate < 32000 => Some(576),
+        "mp3" => Some(1152),
+        "aac" => Some(1024),
+        _ => None,
+    }
 }
 
 struct AVIMuxer<'a> {
@@ -26,6 +58,15 @@ struct AVIMuxer<'a> {
     data_pos:       u64,
     stream_info:    Vec<AVIStream>,
     pal_pos:        Vec<u32>,
+    metadata:       ContainerMetadata,
+    riff_pos:       u64,
+    riff_no:        usize,
+    riff_limit:     u64,
+    odml_pos:       u64,
+    avih_frames:    u32,
+    interleave:     u64,
+    skip_index:     bool,
+    queue:          Vec<(u64, NAPacket)>,
 }
 
 impl<'a> AVIMuxer<'a> {
@@ -38,8 +79,216 @@ impl<'a> AVIMuxer<'a> {
             data_pos:       0,
             stream_info:    Vec::with_capacity(2),
             pal_pos:        Vec::with_capacity(2),
+            metadata:       ContainerMetadata::new(),
+            riff_pos:       0,
+            riff_no:        0,
+            riff_limit:     RIFF_SIZE_LIMIT,
+            odml_pos:       0,
+            avih_frames:    0,
+            interleave:     0,
+            skip_index:     false,
+            queue:          Vec::new(),
+        }
+    }
+    fn index_size(&self) -> u64 {
+        let mut size = 0;
+        for stri in self.stream_info.iter() {
+            if !stri.ix.is_empty() {
+                size += 32 + 8 * (stri.ix.len() as u64 + 1);
+            }
+        }
+        if self.riff_no == 0 {
+            size += 8 + 16 * (self.index.len() as u64 + 1);
         }
+        size
     }
+    fn write_std_indices(&mut self) -> MuxerResult<()> {
+        for (str_no, stri) in self.stream_info.iter_mut().enumerate() {
+            if stri.ix.is_empty() {
+                continue;
+            }
+            if stri.super_idx.len() >= MAX_SUPER_INDEX_ENTRIES {
+                return Err(MuxerError::NotPossible);
+            }
+            let pos = self.bw.tell();
+            let size = 24 + stri.ix.len() * 8;
+            self.bw.write_buf(b"ix")?;
+            self.bw.write_byte(b'0' + ((str_no / 10) as u8))?;
+            self.bw.write_byte(b'0' + ((str_no % 10) as u8))?;
+            self.bw.write_u32le(size as u32)?;
+            self.bw.write_u16le(2)?; // longs per entry
+            self.bw.write_byte(0)?; // index sub-type
+            self.bw.write_byte(1)?; // index type - index of chunks
+            self.bw.write_u32le(stri.ix.len() as u32)?;
+            write_chunk_hdr(self.bw, stri.stype, str_no as u32)?;
+            self.bw.write_u64le(self.data_pos)?;
+            self.bw.write_u32le(0)?; // reserved
+            for &(chunk_pos, chunk_size) in stri.ix.iter() {
+                self.bw.write_u32le((chunk_pos + 8 - self.data_pos) as u32)?;
+                self.bw.write_u32le(chunk_size)?;
+            }
+            stri.super_idx.push(SuperIndexEntry { pos, size: (size + 8) as u32, duration: stri.ix.len() as u32 });
+            stri.ix.clear();
+        }
+        Ok(())
+    }
+    fn write_idx1(&mut self) -> MuxerResult<()> {
+        if !self.index.is_empty() && !self.skip_index {
+            self.bw.write_buf(b"idx1")?;
+            self.bw.write_u32le((self.index.len() * 16) as u32)?;
+            for item in self.index.iter() {
+                write_chunk_hdr(self.bw, item.stype, item.stream)?;
+                self.bw.write_u32le(if item.key { 0x10 } else { 0 })?;
+                self.bw.write_u32le(item.pos)?;
+                self.bw.write_u32le(item.len)?;
+            }
+        }
+        Ok(())
+    }
+    fn write_packet(&mut self, pkt: NAPacket) -> MuxerResult<()> {
+        let str = pkt.get_stream();
+        let str_num = str.get_num();
+
+        let chunk_len = pkt.get_buffer().len() as u32;
+
+        let pal_info = pkt.side_data.iter().find_map(|sdata|
+                if let NASideData::Palette(changed, ref pal) = sdata {
+                    Some((*changed, pal.clone()))
+                } else {
+                    None
+                });
+        let pal_range = match pal_info {
+                Some((true, ref pal)) if self.pal_pos[str_num] == 0 => self.changed_pal_range(str_num, pal),
+                _ => None,
+            };
+        let pal_size = pal_range.map_or(0, |(start, end)| 8 + 4 + ((end - start) as u64) * 4);
+        let chunk_size = 8 + u64::from(chunk_len) + u64::from(chunk_len & 1) + pal_size;
+        let seg_empty = self.bw.tell() == self.data_pos + 4;
+        if !seg_empty && self.bw.tell() - self.riff_pos + chunk_size + self.index_size() > self.riff_limit {
+            self.start_new_riff()?;
+        }
+
+        if let Some((changed, pal)) = pal_info {
+            if self.pal_pos[str_num] != 0 {
+                let cur_pos = self.bw.tell();
+                self.bw.seek(SeekFrom::Start(u64::from(self.pal_pos[str_num])))?;
+                for clr in pal.chunks_exact(4) {
+                    self.bw.write_byte(clr[2])?;
+                    self.bw.write_byte(clr[1])?;
+                    self.bw.write_byte(clr[0])?;
+                    self.bw.write_byte(0)?;
+                }
+                self.bw.seek(SeekFrom::Start(cur_pos))?;
+                self.pal_pos[str_num] = 0;
+                self.stream_info[str_num].pal = Some(*pal);
+            } else if changed {
+                if let Some((start_clr, end_clr)) = pal_range {
+                    let chunk_len = ((end_clr - start_clr) as u32) * 4 + 4;
+                    self.bw.write_byte(b'0' + ((str_num / 10) as u8))?;
+                    self.bw.write_byte(b'0' + ((str_num % 10) as u8))?;
+                    self.bw.write_buf(b"pc")?;
+                    self.bw.write_u32le(chunk_len)?;
+                    self.bw.write_byte(start_clr as u8)?;
+                    self.bw.write_byte((end_clr - start_clr) as u8)?; // zero means all 256 entries
+                    self.bw.write_u16le(0)?; //flags
+                    for clr in pal[start_clr * 4..end_clr * 4].chunks_exact(4) {
+                        self.bw.write_buf(&clr[..3])?;
+                        self.bw.write_byte(0)?;
+                    }
+                }
+                self.stream_info[str_num].pal = Some(*pal);
+            }
+        }
+
+        self.stream_info[str_num].nframes += 1;
+        self.stream_info[str_num].tot_size += u64::from(chunk_len);
+        self.stream_info[str_num].max_size = self.stream_info[str_num].max_size.max(chunk_len);
+        if !self.skip_index {
+            if self.riff_no == 0 {
+                self.index.push(IdxEntry {
+                        stream: str_num as u32,
+                        stype:  str.get_media_type(),
+                        key:    pkt.keyframe,
+                        pos:    self.bw.tell() as u32,
+                        len:    chunk_len });
+            }
+            let ix_size = if pkt.keyframe { chunk_len } else { chunk_len | 0x80000000 };
+            self.stream_info[str_num].ix.push((self.bw.tell(), ix_size));
+        }
+        write_chunk_hdr(&mut self.bw, str.get_media_type(), str_num as u32)?;
+        self.bw.write_u32le(chunk_len)?;
+        self.bw.write_buf(pkt.get_buffer().as_slice())?;
+        if (self.bw.tell() & 1) != 0 {
+            self.bw.write_byte(0)?;
+        }
+        Ok(())
+    }
+    fn changed_pal_range(&self, str_num: usize, pal: &[u8; 1024]) -> Option<(usize, usize)> {
+        let (start, end) = if let Some(ref old_pal) = self.stream_info[str_num].pal {
+                let differs = |idx: &usize| old_pal[idx * 4..][..3] != pal[idx * 4..][..3];
+                let start = (0..256).find(differs).unwrap_or(256);
+                let end = (0..256).rev().find(differs).map_or(start, |idx| idx + 1);
+                (start, end)
+            } else {
+                (0, 256)
+            };
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+    fn start_new_riff(&mut self) -> MuxerResult<()> {
+        self.write_std_indices()?;
+        patch_size(self.bw, self.data_pos)?;
+        if self.riff_no == 0 {
+            self.write_idx1()?;
+            self.avih_frames = self.stream_info.iter().fold(0, |acc, stri| acc.max(stri.nframes));
+        }
+        patch_size(self.bw, self.riff_pos + 8)?;
+
+        self.riff_no += 1;
+        self.riff_pos = self.bw.tell();
+        self.data_pos = self.riff_pos + 20;
+        self.bw.write_buf(b"RIFF\0\0\0\0AVIXLIST\0\0\0\0movi")?;
+        Ok(())
+    }
+    fn write_odml_headers(&mut self, tot_frames: u32) -> MuxerResult<()> {
+        for (str_no, stri) in self.stream_info.iter().enumerate() {
+            self.bw.seek(SeekFrom::Start(stri.indx_pos))?;
+            self.bw.write_buf(b"indx")?;
+            self.bw.write_u32le(SUPER_INDEX_SIZE as u32)?;
+            self.bw.write_u16le(4)?; // longs per entry
+            self.bw.write_byte(0)?; // index sub-type
+            self.bw.write_byte(0)?; // index type - index of indices
+            self.bw.write_u32le(stri.super_idx.len() as u32)?;
+            write_chunk_hdr(self.bw, stri.stype, str_no as u32)?;
+            self.bw.write_u32le(0)?; // reserved
+            self.bw.write_u32le(0)?; // reserved
+            self.bw.write_u32le(0)?; // reserved
+            for entry in stri.super_idx.iter() {
+                self.bw.write_u64le(entry.pos)?;
+                self.bw.write_u32le(entry.size)?;
+                self.bw.write_u32le(entry.duration)?;
+            }
+        }
+        self.bw.seek(SeekFrom::Start(self.odml_pos))?;
+        self.bw.write_buf(b"LIST")?;
+        self.bw.write_u32le(ODML_LIST_SIZE as u32)?;
+        self.bw.write_buf(b"odmldmlh")?;
+        self.bw.write_u32le(248)?;
+        self.bw.write_u32le(tot_frames)?;
+        Ok(())
+    }
+}
+
+fn write_junk(bw: &mut ByteWriter, size: usize) -> MuxerResult<()> {
+    bw.write_buf(b"JUNK")?;
+    bw.write_u32le(size as u32)?;
+    for _ in 0..size {
+        bw.write_byte(0)?;
+    }
+    Ok(())
 }
 
 fn patch_size(bw: &mut ByteWriter, pos: u64) -> MuxerResult<()> {
@@ -50,6 +299,18 @@ fn patch_size(bw: &mut ByteWriter, pos: u64) -> MuxerResult<()> {
     Ok(())
 }
 
+fn write_chunk(bw: &mut ByteWriter, tag: &[u8; 4], text: &[u8]) -> MuxerResult<()> {
+    let size = text.len() + 1;
+    bw.write_buf(tag)?;
+    bw.write_u32le(size as u32)?;
+    bw.write_buf(text)?;
+    bw.write_byte(0)?;
+    if (size & 1) != 0 {
+        bw.write_byte(0)?;
+    }
+    Ok(())
+}
+
 fn write_chunk_hdr(bw: &mut ByteWriter, stype: StreamType, str_no: u32) -> MuxerResult<()> {
     bw.write_byte(b'0' + ((str_no / 10) as u8))?;
     bw.write_byte(b'0' + ((str_no % 10) as u8))?;
@@ -142,21 +403,39 @@ impl<'a> MuxCore<'a> for AVIMuxer<'a> {
             self.stream_info.push(AVIStream {
                     strh_pos:   self.bw.tell(),
                     is_video:   str.get_media_type() == StreamType::Video,
+                    stype:      str.get_media_type(),
                     nframes:    0,
                     max_size:   0,
+                    indx_pos:   0,
+                    ix:         Vec::new(),
+                    super_idx:  Vec::new(),
+                    vbr_info:   None,
+                    strf_pos:   0,
+                    tot_size:   0,
+                    pal:        None,
                 });
+            let (tb_num, tb_den) = if let NACodecTypeInfo::Audio(ainfo) = str.get_info().get_properties() {
+                    if let Some(frame_len) = get_vbr_frame_length(str.get_info().get_name(), ainfo.sample_rate) {
+                        self.stream_info[strno].vbr_info = Some((ainfo.sample_rate, frame_len));
+                        (frame_len, ainfo.sample_rate)
+                    } else {
+                        (str.tb_num, str.tb_den)
+                    }
+                } else {
+                    (str.tb_num, str.tb_den)
+                };
 
             self.bw.write_u32le(0)?; // flags
             self.bw.write_u16le(0)?; // priority
             self.bw.write_u16le(0)?; // language
             self.bw.write_u32le(0)?; // initial frames
-            self.bw.write_u32le(str.tb_num)?;
-            self.bw.write_u32le(str.tb_den)?;
+            self.bw.write_u32le(tb_num)?;
+            self.bw.write_u32le(tb_den)?;
             self.bw.write_u32le(0)?; // start
             self.bw.write_u32le(0)?; // length
             self.bw.write_u32le(0)?; // suggested buffer size
             self.bw.write_u32le(0)?; // quality
-            self.bw.write_u32le(0)?; // sample_size
+            self.bw.write_u32le(0)?; // sample_size, zero for VBR audio as well
             self.bw.write_u16le(0)?; // x
             self.bw.write_u16le(0)?; // y
             self.bw.write_u16le(0)?; // w
@@ -172,8 +451,13 @@ impl<'a> MuxCore<'a> for AVIMuxer<'a> {
                     self.bw.write_u32le(0)?;
                     self.bw.write_u32le(vinfo.width as u32)?;
                     self.bw.write_u32le(vinfo.height as u32)?;
-                    self.bw.write_u16le(vinfo.format.components as u16)?;
-                    self.bw.write_u16le(vinfo.format.get_total_depth() as u16)?;
+                    if vinfo.format.palette {
+                        self.bw.write_u16le(1)?; // planes
+                        self.bw.write_u16le(8)?; // bitcount
+                    } else {
+                        self.bw.write_u16le(vinfo.format.components as u16)?;
+                        self.bw.write_u16le(vinfo.format.get_total_depth() as u16)?;
+                    }
                     let fcc = find_avi_fourcc(str.get_info().get_name());
                     if fcc.is_none() {
                         return Err(MuxerError::UnsupportedFormat);
@@ -210,8 +494,14 @@ impl<'a> MuxCore<'a> for AVIMuxer<'a> {
                     self.bw.write_u16le(twocc.unwrap_or(0))?;
                     self.bw.write_u16le(ainfo.channels as u16)?;
                     self.bw.write_u32le(ainfo.sample_rate)?;
+                    self.stream_info[strno].strf_pos = self.bw.tell();
                     self.bw.write_u32le(0)?; // avg bytes per second
-                    self.bw.write_u16le(ainfo.block_len as u16)?;
+                    // VBR audio has block alignment set to the frame length
+                    if let Some((_, frame_len)) = self.stream_info[strno].vbr_info {
+                        self.bw.write_u16le(frame_len as u16)?;
+                    } else {
+                        self.bw.write_u16le(ainfo.block_len as u16)?;
+                    }
                     self.bw.write_u16le(ainfo.format.bits as u16)?;
                     if let Some(ref edata) = str.get_info().get_extradata() {
                         self.bw.write_buf(edata.as_slice())?;
@@ -225,10 +515,26 @@ impl<'a> MuxCore<'a> for AVIMuxer<'a> {
                 _ => unreachable!(),
             };
             patch_size(&mut self.bw, strf_pos)?;
+            // space for OpenDML super index, it is filled only if the file grows too large for a single RIFF
+            self.stream_info[strno].indx_pos = self.bw.tell();
+            write_junk(self.bw, SUPER_INDEX_SIZE)?;
+            if let Some(name) = self.metadata.get_stream(str.get_id()).and_then(|meta| meta.get_text(METADATA_TITLE)) {
+                write_chunk(self.bw, b"strn", name.as_bytes())?;
+            }
             patch_size(&mut self.bw, strl_pos)?;
         }
+        self.odml_pos = self.bw.tell();
+        write_junk(self.bw, ODML_LIST_SIZE)?;
         patch_size(&mut self.bw, hdrl_pos)?;
 
+        let info = create_riff_info(self.metadata.get_global());
+        if !info.is_empty() {
+            self.bw.write_buf(b"LIST")?;
+            self.bw.write_u32le((info.len() + 4) as u32)?;
+            self.bw.write_buf(b"INFO")?;
+            self.bw.write_buf(&info)?;
+        }
+
         self.data_pos = self.bw.tell() + 8;
         self.bw.write_buf(b"LIST\0\0\0\0movi")?;
 
@@ -243,94 +549,115 @@ impl<'a> MuxCore<'a> for AVIMuxer<'a> {
         if str_num > 99 || str_num >= self.stream_info.len() {
             return Err(MuxerError::UnsupportedFormat);
         }
-
-        let chunk_len = pkt.get_buffer().len() as u32;
-
-        if self.pal_pos[str_num] != 0 {
-            for sdata in pkt.side_data.iter() {
-                if let NASideData::Palette(_, ref pal) = sdata {
-                    let cur_pos = self.bw.tell();
-                    self.bw.seek(SeekFrom::Start(u64::from(self.pal_pos[str_num])))?;
-                    self.bw.write_buf(pal.as_ref())?;
-                    self.bw.seek(SeekFrom::Start(cur_pos))?;
-                    self.pal_pos[str_num] = 0;
-                    break;
-                }
-            }
-        } else {
-            for sdata in pkt.side_data.iter() {
-                if let NASideData::Palette(true, ref pal) = sdata {
-                    //todo search for changed region
-                    let start_clr = 0usize;
-                    let end_clr = 256usize;
-                    if start_clr < end_clr {
-                        let chunk_len = ((end_clr - start_clr) as u32) * 4 + 4;
-                        self.bw.write_byte(b'0' + ((str_num / 10) as u8))?;
-                        self.bw.write_byte(b'0' + ((str_num % 10) as u8))?;
-                        self.bw.write_buf(b"pc")?;
-                        self.bw.write_u32le(chunk_len)?;
-                        self.bw.write_byte(start_clr as u8)?;
-                        self.bw.write_byte((end_clr - start_clr) as u8)?;
-                        self.bw.write_u16le(0)?; //flags
-                        self.bw.write_buf(&pal[start_clr * 4..end_clr * 4])?;
-                    }
-                }
-            }
+        if self.interleave == 0 {
+            return self.write_packet(pkt);
         }
 
-        self.stream_info[str_num].nframes += 1;
-        self.stream_info[str_num].max_size = self.stream_info[str_num].max_size.max(chunk_len);
-        self.index.push(IdxEntry {
-                stream: str_num as u32,
-                stype:  str.get_media_type(),
-                key:    pkt.keyframe,
-                pos:    self.bw.tell() as u32,
-                len:    chunk_len });
-        write_chunk_hdr(&mut self.bw, str.get_media_type(), str_num as u32)?;
-        self.bw.write_u32le(chunk_len)?;
-        self.bw.write_buf(pkt.get_buffer().as_slice())?;
-        if (self.bw.tell() & 1) != 0 {
-            self.bw.write_byte(0)?;
+        let time = if let Some(ts) = pkt.get_pts().or_else(|| pkt.get_dts()) {
+                let (tb_num, tb_den) = str.get_timebase();
+                NATimeInfo::ts_to_time(ts, 1000, tb_num, tb_den)
+            } else if let Some(&(last_time, _)) = self.queue.last() {
+                last_time
+            } else {
+                0
+            };
+        let pos = self.queue.iter().position(|(qtime, _)| *qtime > time).unwrap_or(self.queue.len());
+        self.queue.insert(pos, (time, pkt));
+        while let (Some(&(first, _)), Some(&(last, _))) = (self.queue.first(), self.queue.last()) {
+            if last - first < self.interleave {
+                break;
+            }
+            let (_, pkt) = self.queue.remove(0);
+            self.write_packet(pkt)?;
         }
         Ok(())
     }
     fn flush(&mut self) -> MuxerResult<()> {
+        let queue = std::mem::take(&mut self.queue);
+        for (_, pkt) in queue.into_iter() {
+            self.write_packet(pkt)?;
+        }
         Ok(())
     }
+    fn set_metadata(&mut self, metadata: &ContainerMetadata) {
+        self.metadata = metadata.clone();
+    }
     fn end(&mut self) -> MuxerResult<()> {
-        patch_size(&mut self.bw, self.data_pos)?;
-        if !self.index.is_empty() {
-            self.bw.write_buf(b"idx1")?;
-            self.bw.write_u32le((self.index.len() * 16) as u32)?;
-            for item in self.index.iter() {
-                write_chunk_hdr(&mut self.bw, item.stype, item.stream)?;
-                self.bw.write_u32le(if item.key { 0x10 } else { 0 })?;
-                self.bw.write_u32le(item.pos)?;
-                self.bw.write_u32le(item.len)?;
-            }
+        self.flush()?;
+        if self.riff_no == 0 {
+            patch_size(&mut self.bw, self.data_pos)?;
+            self.write_idx1()?;
+        } else {
+            self.write_std_indices()?;
+            patch_size(&mut self.bw, self.data_pos)?;
         }
-        patch_size(&mut self.bw, 8)?;
+        patch_size(&mut self.bw, self.riff_pos + 8)?;
         let mut max_frames = 0;
         let mut max_size = 0;
         for stri in self.stream_info.iter() {
             max_frames = max_frames.max(stri.nframes);
             max_size = max_size.max(stri.max_size);
             self.bw.seek(SeekFrom::Start(stri.strh_pos + 0x18))?;
-            self.bw.write_u32le(if stri.is_video { stri.nframes } else { 0 })?;
+            self.bw.write_u32le(if stri.is_video || stri.vbr_info.is_some() { stri.nframes } else { 0 })?;
             self.bw.write_u32le(stri.max_size)?;
+            if let Some((srate, frame_len)) = stri.vbr_info {
+                let duration = u64::from(stri.nframes) * u64::from(frame_len);
+                if let Some(avg_bytes) = (stri.tot_size * u64::from(srate)).checked_div(duration) {
+                    self.bw.seek(SeekFrom::Start(stri.strf_pos))?;
+                    self.bw.write_u32le(avg_bytes as u32)?;
+                }
+            }
         }
         self.bw.seek(SeekFrom::Start(0x30))?;
-        self.bw.write_u32le(max_frames)?;
+        self.bw.write_u32le(if self.riff_no == 0 { max_frames } else { self.avih_frames })?;
         self.bw.seek(SeekFrom::Current(8))?;
         self.bw.write_u32le(max_size)?;
+        if self.riff_no > 0 {
+            self.write_odml_headers(max_frames)?;
+        }
         Ok(())
     }
 }
 
+const MUXER_OPTIONS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: "interleave", description: "Maximum duration of buffered packets for interleaving (in milliseconds)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(10000)) },
+    NAOptionDefinition {
+        name: "skip_index", description: "Do not write index",
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
 impl<'a> NAOptionHandler for AVIMuxer<'a> {
-    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
-    fn set_options(&mut self, _options: &[NAOption]) { }
-    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { MUXER_OPTIONS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in MUXER_OPTIONS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        "interleave" => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.interleave = intval as u64;
+                            }
+                        },
+                        "skip_index" => {
+                            if let NAValue::Bool(bval) = option.value {
+                                self.skip_index = bval;
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            "interleave" => Some(NAValue::Int(self.interleave as i64)),
+            "skip_index" => Some(NAValue::Bool(self.skip_index)),
+            _ => None,
+        }
+    }
 }
 
 pub struct AVIMuxerCreator {}
@@ -374,4 +701,223 @@ mod test {
         test_remuxing_md5(&dec_config, "avi", &mux_reg,
                           [0xa0fb0e47, 0x412e24dd, 0x6b89711c, 0x276fb799]);
     }
+    #[test]
+    fn test_avi_muxer_synth() {
+        use nihav_codec_support::md5::MD5;
+
+        const NUM_FRAMES: usize = 10;
+        let vinfo = NAVideoInfo::new(64, 48, false, YUV420_FORMAT);
+        let vci = NACodecInfo::new("cinepak", NACodecTypeInfo::Video(vinfo), None);
+        let ainfo = NAAudioInfo::new(22050, 1, SND_S16_FORMAT, 2);
+        let aci = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Video, 0, vci, 1, 15, 0)).unwrap();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 1, aci, 1, 22050, 0)).unwrap();
+        let vstream = strmgr.get_stream(0).unwrap();
+        let astream = strmgr.get_stream(1).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("avi").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            for i in 0..NUM_FRAMES {
+                let ts = NATimeInfo::new(Some(i as u64), None, None, 1, 15);
+                let vdata = (0..100 + i * 17).map(|j| (i * 3 + j) as u8).collect();
+                mux.mux_frame(NAPacket::new(vstream.clone(), ts, (i % 5) == 0, vdata)).unwrap();
+                let ts = NATimeInfo::new(Some((i * 1470) as u64), None, None, 1, 22050);
+                mux.mux_frame(NAPacket::new(astream.clone(), ts, true, vec![i as u8; 1470 * 2])).unwrap();
+            }
+            mux.end().unwrap();
+        }
+
+        let mut hash = [0; 4];
+        MD5::calculate_hash(dst.as_slice(), &mut hash);
+        assert_eq!(hash, [0x6697a893, 0x77ed08b9, 0x6c41ec73, 0x08469ac3]);
+
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("avi").unwrap(), &mut br).unwrap();
+        for i in 0..NUM_FRAMES * 2 {
+            let pkt = dmx.get_frame().unwrap();
+            assert_eq!(pkt.get_stream().get_num(), i & 1);
+            let size = if (i & 1) == 0 { 100 + i / 2 * 17 } else { 1470 * 2 };
+            assert_eq!(pkt.get_buffer().len(), size);
+        }
+        assert!(dmx.get_frame().is_err());
+    }
+    #[test]
+    fn test_avi_muxer_odml() {
+        use super::AVIMuxer;
+
+        const NUM_FRAMES: usize = 40;
+        let vinfo = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+        let info = NACodecInfo::new("cinepak", NACodecTypeInfo::Video(vinfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Video, 0, info, 1, 25, 0)).unwrap();
+        let stream = strmgr.get_stream(0).unwrap();
+
+        for &riff_limit in [16384, super::RIFF_SIZE_LIMIT].iter() {
+            let mut dst = Vec::new();
+            {
+                let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+                let mut bw = ByteWriter::new(&mut gw);
+                let mut mux = AVIMuxer::new(&mut bw);
+                mux.riff_limit = riff_limit;
+                mux.create(&strmgr).unwrap();
+                for i in 0..NUM_FRAMES {
+                    let ts = NATimeInfo::new(Some(i as u64), None, None, 1, 25);
+                    mux.mux_frame(&strmgr, NAPacket::new(stream.clone(), ts, (i % 10) == 0, vec![i as u8; 1001])).unwrap();
+                }
+                mux.end().unwrap();
+                assert_eq!(mux.riff_no > 0, riff_limit < super::RIFF_SIZE_LIMIT);
+            }
+
+            let mut mr = MemoryReader::new_read(&dst);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx_reg = RegisteredDemuxers::new();
+            generic_register_all_demuxers(&mut dmx_reg);
+            let mut dmx = create_demuxer(dmx_reg.find_demuxer("avi").unwrap(), &mut br).unwrap();
+            assert!(dmx.get_seek_index().mode == SeekIndexMode::Present);
+            assert_eq!(dmx.get_seek_index().seek_info[0].entries.len(), NUM_FRAMES / 10);
+            for i in 0..NUM_FRAMES {
+                let pkt = dmx.get_frame().unwrap();
+                assert_eq!(pkt.get_pts(), Some(i as u64));
+                assert_eq!(pkt.get_buffer().len(), 1001);
+                assert_eq!(pkt.get_buffer()[0], i as u8);
+                assert_eq!(pkt.is_keyframe(), (i % 10) == 0);
+            }
+            assert!(dmx.get_frame().is_err());
+            assert_eq!(dmx.get_duration(), (NUM_FRAMES as u64) * 40);
+        }
+    }
+    #[test]
+    fn test_avi_muxer_vbr_audio() {
+        const NUM_FRAMES: usize = 8;
+        for &(cname, srate, channels, frame_len) in [("mp3", 22050, 1, 576), ("aac", 48000, 2, 1024)].iter() {
+            let ainfo = NAAudioInfo::new(srate, channels, SND_S16_FORMAT, 0);
+            let aci = NACodecInfo::new(cname, NACodecTypeInfo::Audio(ainfo), None);
+            let mut strmgr = StreamManager::new();
+            strmgr.add_stream(NAStream::new(StreamType::Audio, 0, aci, 1, srate, 0)).unwrap();
+            let astream = strmgr.get_stream(0).unwrap();
+
+            let mut dst = Vec::new();
+            {
+                let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+                let mut bw = ByteWriter::new(&mut gw);
+                let mut mux_reg = RegisteredMuxers::new();
+                generic_register_all_muxers(&mut mux_reg);
+                let mux_creator = mux_reg.find_muxer("avi").unwrap();
+                let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+                for i in 0..NUM_FRAMES {
+                    let ts = NATimeInfo::new(Some((i * frame_len) as u64), None, None, 1, srate);
+                    mux.mux_frame(NAPacket::new(astream.clone(), ts, true, vec![i as u8; 200 + i * 30])).unwrap();
+                }
+                mux.end().unwrap();
+            }
+
+            let mut mr = MemoryReader::new_read(&dst);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx_reg = RegisteredDemuxers::new();
+            generic_register_all_demuxers(&mut dmx_reg);
+            let mut dmx = create_demuxer(dmx_reg.find_demuxer("avi").unwrap(), &mut br).unwrap();
+            let stream = dmx.get_stream(0).unwrap();
+            assert_eq!(stream.get_info().get_name(), cname);
+            // strh scale/rate should describe one audio frame
+            let (tb_num, tb_den) = stream.get_timebase();
+            assert_eq!(u64::from(tb_num) * u64::from(srate), u64::from(tb_den) * (frame_len as u64));
+            assert_eq!(stream.get_duration(), NUM_FRAMES as u64);
+            let ainfo = stream.get_info().get_properties().get_audio_info().unwrap();
+            assert_eq!(ainfo.sample_rate, srate);
+            assert_eq!(ainfo.channels, channels);
+            assert_eq!(ainfo.block_len, frame_len);
+            for i in 0..NUM_FRAMES {
+                let pkt = dmx.get_frame().unwrap();
+                assert_eq!(pkt.get_pts(), Some(i as u64));
+                assert_eq!(pkt.get_buffer().len(), 200 + i * 30);
+            }
+            assert!(dmx.get_frame().is_err());
+        }
+    }
+    #[test]
+    fn test_avi_muxer_palette_interleave() {
+        use std::sync::Arc;
+
+        let vinfo = NAVideoInfo::new(4, 4, false, PAL8_FORMAT);
+        let vci = NACodecInfo::new("msvideo1", NACodecTypeInfo::Video(vinfo), None);
+        let ainfo = NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 2);
+        let aci = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Video, 0, vci, 1, 10, 0)).unwrap();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 1, aci, 1, 10, 0)).unwrap();
+        let vstream = strmgr.get_stream(0).unwrap();
+        let astream = strmgr.get_stream(1).unwrap();
+
+        let mut pal = [0u8; 1024];
+        for (i, clr) in pal.chunks_exact_mut(4).enumerate() {
+            clr[0] = i as u8;
+            clr[1] = !(i as u8);
+        }
+        let pal1 = pal;
+        for clr in pal[10 * 4..20 * 4].chunks_exact_mut(4) {
+            clr[2] = 0x42;
+        }
+        let pal2 = pal;
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("avi").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            mux.set_options(&[NAOption { name: "interleave", value: NAValue::Int(500) }]);
+            // all video first, then all audio
+            for (i, &(changed, ref vpal)) in [(true, pal1), (true, pal2), (false, pal2)].iter().enumerate() {
+                let ts = NATimeInfo::new(Some(i as u64), None, None, 1, 10);
+                let mut pkt = NAPacket::new(vstream.clone(), ts, true, vec![0; 16]);
+                pkt.add_side_data(NASideData::Palette(changed, Arc::new(*vpal)));
+                mux.mux_frame(pkt).unwrap();
+            }
+            for i in 0..3 {
+                let ts = NATimeInfo::new(Some(i), None, None, 1, 10);
+                mux.mux_frame(NAPacket::new(astream.clone(), ts, true, vec![0; 1600])).unwrap();
+            }
+            mux.end().unwrap();
+        }
+
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("avi").unwrap(), &mut br).unwrap();
+        for i in 0..6 {
+            let pkt = dmx.get_frame().unwrap();
+            assert_eq!(pkt.get_stream().get_num(), i & 1);
+            if pkt.get_stream().get_num() != 0 {
+                continue;
+            }
+            let mut found = false;
+            for sdata in pkt.side_data.iter() {
+                if let NASideData::Palette(changed, ref dpal) = sdata {
+                    let ref_pal = if i == 0 { &pal1 } else { &pal2 };
+                    for (dclr, sclr) in dpal.chunks_exact(4).zip(ref_pal.chunks_exact(4)) {
+                        assert_eq!(dclr[..3], sclr[..3]);
+                    }
+                    if i == 4 {
+                        assert!(!changed);
+                    }
+                    found = true;
+                }
+            }
+            assert!(found);
+        }
+        assert!(dmx.get_frame().is_err());
+    }
 }
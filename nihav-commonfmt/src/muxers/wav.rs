@@ -1,9 +1,21 @@
 use nihav_core::muxers::*;
 use nihav_registry::register::*;
 
+const DS64_SIZE: usize = 28;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const KSDATAFORMAT_SUBTYPE_TAIL: [u8; 14] = [0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71, 0, 0];
+
 struct WAVMuxer<'a> {
     bw:         &'a mut ByteWriter<'a>,
     data_pos:   u64,
+    fmt_pos:    u64,
+    fact_pos:   u64,
+    is_pcm:     bool,
+    frame_size: u64,
+    srate:      u32,
+    nsamples:   u64,
+    force_rf64: bool,
+    metadata:   Metadata,
 }
 
 impl<'a> WAVMuxer<'a> {
@@ -11,16 +23,44 @@ impl<'a> WAVMuxer<'a> {
         Self {
             bw,
             data_pos:   0,
+            fmt_pos:    0,
+            fact_pos:   0,
+            is_pcm:     false,
+            frame_size: 0,
+            srate:      0,
+            nsamples:   0,
+            force_rf64: false,
+            metadata:   Metadata::new(),
         }
     }
 }
 
-fn patch_size(bw: &mut ByteWriter, pos: u64) -> MuxerResult<()> {
-    let size = bw.tell() - pos;
-    bw.seek(SeekFrom::Current(-((size + 4) as i64)))?;
-    bw.write_u32le(size as u32)?;
-    bw.seek(SeekFrom::End(0))?;
-    Ok(())
+fn default_channel_map(channels: u8) -> Option<NAChannelMap> {
+    let map = match channels {
+            1 => "C",
+            2 => "L,R",
+            3 => "L,R,C",
+            4 => "L,R,Ls,Rs",
+            5 => "L,R,C,Ls,Rs",
+            6 => "L,R,C,LFE,Ls,Rs",
+            7 => "L,R,C,LFE,Ls,Rs,Cs",
+            8 => "L,R,C,LFE,Ls,Rs,Lss,Rss",
+            _ => return None,
+        };
+    map.parse::<NAChannelMap>().ok()
+}
+
+// PCM streams from WAVE_FORMAT_EXTENSIBLE input carry the format extension (with the channel mask) in extradata
+fn stream_channel_map(edata: Option<Arc<Vec<u8>>>, channels: u8) -> Option<NAChannelMap> {
+    if let Some(buf) = edata {
+        if buf.len() >= 22 {
+            let chmap = NAChannelMap::from_ms_mapping(read_u32le(&buf[2..]).unwrap_or(0));
+            if chmap.num_channels() == usize::from(channels) {
+                return Some(chmap);
+            }
+        }
+    }
+    None
 }
 
 impl<'a> MuxCore<'a> for WAVMuxer<'a> {
@@ -45,28 +85,78 @@ impl<'a> MuxCore<'a> for WAVMuxer<'a> {
         if twocc.is_none() {
             return Err(MuxerError::UnsupportedFormat);
         }
-        let twocc = if stream.get_info().get_name() == "pcm" {
+        self.is_pcm = stream.get_info().get_name() == "pcm";
+        self.srate = ainfo.sample_rate;
+        let twocc = if self.is_pcm {
                 if !ainfo.format.float { 0x0001 } else { 0x0003 }
             } else {
                 twocc.unwrap_or(0)
             };
-        let avg_bytes_per_sec = if stream.get_info().get_name() == "pcm" {
+        let avg_bytes_per_sec = if self.is_pcm {
                 (u32::from(ainfo.channels) * ainfo.sample_rate * u32::from(ainfo.format.bits)) >> 3
             } else {
                 0
             };
 
-        self.bw.write_buf(b"RIFF\0\0\0\0WAVEfmt ")?;
-        self.bw.write_u32le(if edata_len == 0 { 16 } else { 18 + edata_len } as u32)?;
-        self.bw.write_u16le(twocc)?;
+        let stream_chmap = if self.is_pcm { stream_channel_map(stream.get_info().get_extradata(), ainfo.channels) } else { None };
+        let def_chmask = default_channel_map(ainfo.channels).and_then(|chmap| chmap.to_ms_mapping());
+        let chmask = if let Some(ref chmap) = stream_chmap {
+                chmap.to_ms_mapping().unwrap_or(0)
+            } else {
+                def_chmask.unwrap_or(0)
+            };
+        // WAVE_FORMAT_EXTENSIBLE is required for more than two channels, for high-resolution PCM and for non-default channel layouts
+        let extensible = self.is_pcm && (ainfo.channels > 2 || ainfo.format.bits > 16 || (stream_chmap.is_some() && Some(chmask) != def_chmask));
+
+        // space for ds64 chunk in case the output grows too large
+        self.bw.write_buf(b"RIFF\0\0\0\0WAVEJUNK")?;
+        self.bw.write_u32le(DS64_SIZE as u32)?;
+        self.bw.write_buf(&[0; DS64_SIZE])?;
+        self.bw.write_buf(b"fmt ")?;
+        if !extensible {
+            self.bw.write_u32le(if edata_len == 0 { 16 } else { 18 + edata_len } as u32)?;
+            self.bw.write_u16le(twocc)?;
+        } else {
+            self.bw.write_u32le(40)?;
+            self.bw.write_u16le(WAVE_FORMAT_EXTENSIBLE)?;
+        }
         self.bw.write_u16le(u16::from(ainfo.channels))?;
         self.bw.write_u32le(ainfo.sample_rate)?;
+        self.fmt_pos = self.bw.tell();
         self.bw.write_u32le(avg_bytes_per_sec)?;
-        self.bw.write_u16le(ainfo.block_len as u16)?;
-        self.bw.write_u16le(u16::from(ainfo.format.bits))?;
-        if let Some(ref buf) = stream.get_info().get_extradata() {
-            self.bw.write_u16le(edata_len as u16)?;
-            self.bw.write_buf(buf.as_slice())?;
+        if !extensible {
+            self.bw.write_u16le(ainfo.block_len as u16)?;
+            self.bw.write_u16le(u16::from(ainfo.format.bits))?;
+            if let Some(ref buf) = stream.get_info().get_extradata() {
+                self.bw.write_u16le(edata_len as u16)?;
+                self.bw.write_buf(buf.as_slice())?;
+            }
+        } else {
+            let container_bits = (u16::from(ainfo.format.bits) + 7) & !7;
+            self.bw.write_u16le(u16::from(ainfo.channels) * container_bits / 8)?;
+            self.bw.write_u16le(container_bits)?;
+            self.bw.write_u16le(22)?; // extension size
+            self.bw.write_u16le(u16::from(ainfo.format.bits))?; // valid bits per sample
+            self.bw.write_u32le(chmask)?;
+            self.bw.write_u16le(twocc)?;
+            self.bw.write_buf(&KSDATAFORMAT_SUBTYPE_TAIL)?;
+        }
+        if self.is_pcm {
+            self.frame_size = u64::from(ainfo.channels) * u64::from((ainfo.format.bits + 7) >> 3);
+        }
+        // fact chunk is mandatory for everything except integer PCM
+        if !self.is_pcm || ainfo.format.float {
+            self.bw.write_buf(b"fact")?;
+            self.bw.write_u32le(4)?;
+            self.fact_pos = self.bw.tell();
+            self.bw.write_u32le(0)?;
+        }
+        let info = create_riff_info(&self.metadata);
+        if !info.is_empty() {
+            self.bw.write_buf(b"LIST")?;
+            self.bw.write_u32le((info.len() + 4) as u32)?;
+            self.bw.write_buf(b"INFO")?;
+            self.bw.write_buf(&info)?;
         }
         self.bw.write_buf(b"data\0\0\0\0")?;
         self.data_pos = self.bw.tell();
@@ -83,25 +173,84 @@ impl<'a> MuxCore<'a> for WAVMuxer<'a> {
             return Err(MuxerError::UnsupportedFormat);
         }
 
+        if let Some(pts) = pkt.get_pts() {
+            let (tb_num, tb_den) = stream.get_timebase();
+            let end = pts + pkt.get_duration().unwrap_or(0);
+            self.nsamples = self.nsamples.max(NATimeInfo::ts_to_time(end, u64::from(self.srate), tb_num, tb_den));
+        }
+
         self.bw.write_buf(pkt.get_buffer().as_slice())?;
         Ok(())
     }
     fn flush(&mut self) -> MuxerResult<()> {
         Ok(())
     }
+    fn set_metadata(&mut self, metadata: &ContainerMetadata) {
+        self.metadata = metadata.get_global().clone();
+    }
     fn end(&mut self) -> MuxerResult<()> {
-        patch_size(&mut self.bw, self.data_pos)?;
-        patch_size(&mut self.bw, 8)?;
-        // todo patch avg_bytes_per_second if calculated
-        // todo write fact value if calculated
+        let data_size = self.bw.tell() - self.data_pos;
+        if (data_size & 1) != 0 {
+            self.bw.write_byte(0)?;
+        }
+        let riff_size = self.bw.tell() - 8;
+        if riff_size <= u64::from(u32::MAX) && !self.force_rf64 {
+            self.bw.seek(SeekFrom::Start(4))?;
+            self.bw.write_u32le(riff_size as u32)?;
+            self.bw.seek(SeekFrom::Start(self.data_pos - 4))?;
+            self.bw.write_u32le(data_size as u32)?;
+        } else {
+            self.bw.seek(SeekFrom::Start(0))?;
+            self.bw.write_buf(b"RF64")?;
+            self.bw.write_u32le(u32::MAX)?;
+            self.bw.write_buf(b"WAVEds64")?;
+            self.bw.write_u32le(DS64_SIZE as u32)?;
+            self.bw.write_u64le(riff_size)?;
+            self.bw.write_u64le(data_size)?;
+            self.bw.write_u64le(self.nsamples)?;
+            self.bw.write_u32le(0)?; // table length
+            self.bw.seek(SeekFrom::Start(self.data_pos - 4))?;
+            self.bw.write_u32le(u32::MAX)?;
+        }
+        if !self.is_pcm && self.nsamples > 0 {
+            self.bw.seek(SeekFrom::Start(self.fmt_pos))?;
+            self.bw.write_u32le((data_size * u64::from(self.srate) / self.nsamples) as u32)?;
+        }
+        if self.fact_pos != 0 {
+            let nsamples = if self.is_pcm { data_size / self.frame_size.max(1) } else { self.nsamples };
+            self.bw.seek(SeekFrom::Start(self.fact_pos))?;
+            self.bw.write_u32le(nsamples.min(u64::from(u32::MAX)) as u32)?;
+        }
+        self.bw.seek(SeekFrom::End(0))?;
         Ok(())
     }
 }
 
+const MUXER_OPTIONS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: "force_rf64", description: "Write RF64 header even for small files",
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
 impl<'a> NAOptionHandler for WAVMuxer<'a> {
-    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
-    fn set_options(&mut self, _options: &[NAOption]) { }
-    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { MUXER_OPTIONS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in MUXER_OPTIONS.iter() {
+                if opt_def.check(option).is_ok() {
+                    if let ("force_rf64", NAValue::Bool(bval)) = (option.name, &option.value) {
+                        self.force_rf64 = *bval;
+                    }
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            "force_rf64" => Some(NAValue::Bool(self.force_rf64)),
+            _ => None,
+        }
+    }
 }
 
 pub struct WAVMuxerCreator {}
@@ -145,4 +294,142 @@ mod test {
         test_remuxing_md5(&dec_config, "wav", &mux_reg,
                           [0x1040ebe8, 0xe7a43e84, 0x49fbe234, 0xe870b6b3]);
     }
+    #[test]
+    fn test_wav_metadata() {
+        let mut meta = ContainerMetadata::new();
+        meta.get_global_mut().add_text(METADATA_TITLE, "Test title");
+        meta.get_global_mut().add_text(METADATA_ARTIST, "Test artist");
+
+        let ainfo = NAAudioInfo::new(8000, 1, SND_S16_FORMAT, 2);
+        let info = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 0, info, 1, 8000, 0)).unwrap();
+        let stream = strmgr.get_stream(0).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("wav").unwrap();
+            let mut mux = create_muxer_with_metadata(mux_creator, strmgr, &meta, &mut bw).unwrap();
+            let ts = NATimeInfo::new(Some(0), None, None, 1, 8000);
+            mux.mux_frame(NAPacket::new(stream, ts, true, vec![0; 400])).unwrap();
+            mux.end().unwrap();
+        }
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let dmx = create_demuxer(dmx_reg.find_demuxer("wav").unwrap(), &mut br).unwrap();
+        let gmeta = dmx.get_metadata().get_global();
+        assert_eq!(gmeta.get_text(METADATA_TITLE), Some("Test title"));
+        assert_eq!(gmeta.get_text(METADATA_ARTIST), Some("Test artist"));
+    }
+    #[test]
+    fn test_wav_rf64_extensible() {
+        let ainfo = NAAudioInfo::new(48000, 6, NASoniton::new(24, SONITON_FLAG_SIGNED), 18);
+        let info = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 0, info, 1, 48000, 0)).unwrap();
+        let stream = strmgr.get_stream(0).unwrap();
+
+        let data: Vec<u8> = (0..18 * 100).map(|i| i as u8).collect();
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("wav").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            mux.set_options(&[NAOption { name: "force_rf64", value: NAValue::Bool(true) }]);
+            let ts = NATimeInfo::new(Some(0), None, Some(100), 1, 48000);
+            mux.mux_frame(NAPacket::new(stream, ts, true, data.clone())).unwrap();
+            mux.end().unwrap();
+        }
+        assert_eq!(&dst[..4], b"RF64");
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("wav").unwrap(), &mut br).unwrap();
+        let stream = dmx.get_stream(0).unwrap();
+        assert_eq!(stream.get_info().get_name(), "pcm");
+        let ainfo = stream.get_info().get_properties().get_audio_info().unwrap();
+        assert_eq!(ainfo.get_channels(), 6);
+        assert_eq!(ainfo.get_format().bits, 24);
+        assert_eq!(dmx.get_duration(), 2);
+        let edata = stream.get_info().get_extradata().unwrap();
+        assert_eq!(NAChannelMap::from_ms_mapping(read_u32le(&edata[2..]).unwrap()).to_string(), "L,R,C,LFE,Ls,Rs");
+        let mut demuxed = Vec::new();
+        while let Ok(pkt) = dmx.get_frame() {
+            demuxed.extend_from_slice(pkt.get_buffer().as_slice());
+        }
+        assert_eq!(demuxed, data);
+    }
+    #[test]
+    fn test_wav_extensible_chmap() {
+        // WAVE_FORMAT_EXTENSIBLE extension with non-default L,R,C,LFE layout
+        let mut edata = vec![16, 0, 0x0F, 0, 0, 0, 1, 0];
+        edata.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+        let ainfo = NAAudioInfo::new(44100, 4, SND_S16_FORMAT, 8);
+        let info = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), Some(edata));
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 0, info, 1, 44100, 0)).unwrap();
+        let stream = strmgr.get_stream(0).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("wav").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            let ts = NATimeInfo::new(Some(0), None, None, 1, 44100);
+            mux.mux_frame(NAPacket::new(stream, ts, true, vec![0; 800])).unwrap();
+            mux.end().unwrap();
+        }
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let dmx = create_demuxer(dmx_reg.find_demuxer("wav").unwrap(), &mut br).unwrap();
+        let edata = dmx.get_stream(0).unwrap().get_info().get_extradata().unwrap();
+        assert_eq!(NAChannelMap::from_ms_mapping(read_u32le(&edata[2..]).unwrap()).to_string(), "L,R,C,LFE");
+    }
+    #[test]
+    fn test_wav_float_fact() {
+        let ainfo = NAAudioInfo::new(44100, 2, SND_F32P_FORMAT, 8);
+        let info = NACodecInfo::new("pcm", NACodecTypeInfo::Audio(ainfo), None);
+        let mut strmgr = StreamManager::new();
+        strmgr.add_stream(NAStream::new(StreamType::Audio, 0, info, 1, 44100, 0)).unwrap();
+        let stream = strmgr.get_stream(0).unwrap();
+
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mux_creator = mux_reg.find_muxer("wav").unwrap();
+            let mut mux = create_muxer(mux_creator, strmgr, &mut bw).unwrap();
+            let ts = NATimeInfo::new(Some(0), None, None, 1, 44100);
+            mux.mux_frame(NAPacket::new(stream, ts, true, vec![0; 800])).unwrap();
+            mux.end().unwrap();
+        }
+
+        let fmt_pos = dst.windows(4).position(|tag| tag == b"fmt ").unwrap();
+        // 32-bit samples require WAVE_FORMAT_EXTENSIBLE with IEEE float subformat
+        assert_eq!(read_u16le(&dst[fmt_pos + 8..]).unwrap(), 0xFFFE);
+        assert_eq!(read_u16le(&dst[fmt_pos + 8 + 24..]).unwrap(), 3);
+        let fact_pos = dst.windows(4).position(|tag| tag == b"fact").unwrap();
+        assert_eq!(read_u32le(&dst[fact_pos + 4..]).unwrap(), 4);
+        assert_eq!(read_u32le(&dst[fact_pos + 8..]).unwrap(), 100);
+    }
 }
@@ -461,8 +461,19 @@ impl Inflate {
                             self.state = InflateState::StaticBlockCopy(len - i);
                             return Err(DecompressError::ShortData);
                         }
+                        if self.output_idx >= dst.len() {
+                            self.br = csrc.br;
+                            self.state = InflateState::StaticBlockCopy(len - i);
+                            return Err(DecompressError::OutputFull);
+                        }
                         let val = csrc.read(8).unwrap() as u8;
                         self.put_literal(val);
+                        dst[self.output_idx] = val;
+                        self.output_idx += 1;
+                    }
+                    if self.final_block {
+                        self.state = InflateState::End;
+                        return Ok(self.output_idx);
                     }
                     self.state = InflateState::BlockStart;
                 }
@@ -848,8 +859,7 @@ impl GzipCRC32 {
     }
 }
 
-///! Decodes input data in gzip file format (RFC 1952) returning a vector containing decoded data.
-pub fn gzip_decode(br: &mut ByteReader, skip_crc: bool) -> DecompressResult<Vec<u8>> {
+fn read_gzip_header(br: &mut ByteReader) -> DecompressResult<()> {
     const FLAG_HCRC:    u8 = 0x02;
     const FLAG_EXTRA:   u8 = 0x04;
     const FLAG_NAME:    u8 = 0x08;
@@ -894,6 +904,27 @@ pub fn gzip_decode(br: &mut ByteReader, skip_crc: bool) -> DecompressResult<Vec<
     if (flg & 0xE0) != 0 {
         return Err(DecompressError::Unsupported);
     }
+    Ok(())
+}
+
+fn update_tail(tail: &mut [u8; 8], src: &[u8]) {
+    let inlen = src.len();
+    if inlen >= 8 {
+        tail.copy_from_slice(&src[inlen - 8..][..8]);
+    } else {
+        let shift_len = 8 - inlen;
+        for i in 0..shift_len {
+            tail[i] = tail[i + inlen];
+        }
+        for i in shift_len..8 {
+            tail[i] = src[i - shift_len];
+        }
+    }
+}
+
+/// Decodes input data in gzip file format (RFC 1952) returning a vector containing decoded data.
+pub fn gzip_decode(br: &mut ByteReader, skip_crc: bool) -> DecompressResult<Vec<u8>> {
+    read_gzip_header(br)?;
 
     let mut output: Vec<u8> = Vec::new();
     let mut tail = [0u8; 8];
@@ -934,17 +965,7 @@ pub fn gzip_decode(br: &mut ByteReader, skip_crc: bool) -> DecompressResult<Vec<
             }
         }
         // Save last 8 bytes for CRC and size.
-        if inlen >= 8 {
-            tail.copy_from_slice(&inblk[inlen - 8..][..8]);
-        } else {
-            let shift_len = 8 - inlen;
-            for i in 0..shift_len {
-                tail[i] = tail[i + inlen];
-            }
-            for i in shift_len..8 {
-                tail[i] = inblk[i - shift_len];
-            }
-        }
+        update_tail(&mut tail, &inblk[..inlen]);
     }
     if !skip_crc {
         if !inflate.is_finished() { println!("???"); }
@@ -961,6 +982,257 @@ pub fn gzip_decode(br: &mut ByteReader, skip_crc: bool) -> DecompressResult<Vec<
     Ok(output)
 }
 
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum StreamState {
+    NeedInput,
+    ContinueBlock,
+    Finished,
+}
+
+const STREAM_BUF_SIZE: usize = 65536;
+
+/// Bytestream reader that decompresses deflated data on the fly.
+///
+/// It reads raw deflate or zlib streams (zlib header is detected automatically) and gzip files from the provided [`ByteReader`] and acts as a normal [`ByteIO`] implementation so it can be used to create another `ByteReader` for parsing decompressed data.
+/// Since there is no way to seek in compressed data, only seeking forward is supported.
+///
+/// # Examples
+///
+/// ```
+/// use nihav_core::compr::deflate::InflateReader;
+/// use nihav_core::io::byteio::{ByteReader, ByteIOResult};
+///
+/// # fn parse(br: &mut ByteReader) -> ByteIOResult<()> {
+/// let mut ir = InflateReader::new(br);
+/// let mut dbr = ByteReader::new(&mut ir);
+/// let tag = dbr.read_tag()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ByteReader`]: ../../io/byteio/struct.ByteReader.html
+/// [`ByteIO`]: ../../io/byteio/trait.ByteIO.html
+pub struct InflateReader<'a, 'b> {
+    src:        &'a mut ByteReader<'b>,
+    inflate:    Box<Inflate>,
+    inbuf:      Vec<u8>,
+    inlen:      usize,
+    outbuf:     Vec<u8>,
+    out_idx:    usize,
+    queue:      Vec<u8>,
+    qpos:       usize,
+    pos:        u64,
+    state:      StreamState,
+    gzip:       Option<(GzipCRC32, [u8; 8])>,
+    skip_crc:   bool,
+}
+
+impl<'a, 'b> InflateReader<'a, 'b> {
+    /// Creates a new reader for raw deflate or zlib stream.
+    pub fn new(src: &'a mut ByteReader<'b>) -> Self {
+        Self {
+            src,
+            inflate:    Box::new(Inflate::new()),
+            inbuf:      vec![0; STREAM_BUF_SIZE],
+            inlen:      0,
+            outbuf:     vec![0; STREAM_BUF_SIZE],
+            out_idx:    0,
+            queue:      Vec::with_capacity(STREAM_BUF_SIZE),
+            qpos:       0,
+            pos:        0,
+            state:      StreamState::NeedInput,
+            gzip:       None,
+            skip_crc:   false,
+        }
+    }
+    /// Creates a new reader for gzip file (RFC 1952).
+    ///
+    /// Gzip header is parsed immediately and the data checksum is verified when the end of stream is reached unless `skip_crc` is set.
+    pub fn new_gzip(src: &'a mut ByteReader<'b>, skip_crc: bool) -> DecompressResult<Self> {
+        read_gzip_header(src)?;
+        let mut ir = Self::new(src);
+        ir.gzip = Some((GzipCRC32::new(), [0; 8]));
+        ir.skip_crc = skip_crc;
+        Ok(ir)
+    }
+    /// Reports whether the end of compressed stream has been reached.
+    pub fn is_finished(&self) -> bool { self.state == StreamState::Finished && self.qpos == self.queue.len() }
+    fn queue_data(&mut self, end: usize) {
+        if self.qpos > 0 && self.qpos == self.queue.len() {
+            self.queue.clear();
+            self.qpos = 0;
+        } else if self.qpos >= STREAM_BUF_SIZE {
+            self.queue.drain(..self.qpos);
+            self.qpos = 0;
+        }
+        let data = &self.outbuf[self.out_idx..end];
+        if let Some((ref mut crc, _)) = self.gzip {
+            crc.update_crc(data);
+        }
+        self.queue.extend_from_slice(data);
+    }
+    fn check_gzip_trailer(&mut self) -> ByteIOResult<()> {
+        if let Some((ref crc, ref mut tail)) = self.gzip {
+            let mut buf = [0u8; 1024];
+            loop {
+                match self.src.read_buf_some(&mut buf) {
+                    Ok(len) => update_tail(tail, &buf[..len]),
+                    Err(ByteIOError::EOF) => break,
+                    Err(err) => return Err(err),
+                };
+            }
+            if !self.skip_crc {
+                let exp_crc  = read_u32le(&tail[0..4])?;
+                let exp_size = read_u32le(&tail[4..8])?;
+                let size = (self.pos as usize) + self.queue.len() - self.qpos;
+                if exp_crc != crc.crc || exp_size != (size as u32) {
+                    return Err(ByteIOError::ReadError);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn decode_more(&mut self) -> ByteIOResult<bool> {
+        let ret = match self.state {
+                StreamState::Finished => return Ok(false),
+                StreamState::NeedInput => {
+                    self.inlen = match self.src.read_buf_some(&mut self.inbuf) {
+                            Ok(len) => len,
+                            Err(ByteIOError::EOF) => {
+                                self.state = StreamState::Finished;
+                                return Ok(false);
+                            },
+                            Err(err) => return Err(err),
+                        };
+                    if let Some((_, ref mut tail)) = self.gzip {
+                        update_tail(tail, &self.inbuf[..self.inlen]);
+                    }
+                    self.inflate.decompress_data(&self.inbuf[..self.inlen], &mut self.outbuf, false)
+                },
+                StreamState::ContinueBlock => {
+                    self.out_idx = 0;
+                    self.inflate.decompress_data(&self.inbuf[..self.inlen], &mut self.outbuf, true)
+                },
+            };
+        match ret {
+            Ok(len) => {
+                self.queue_data(len);
+                self.state = StreamState::Finished;
+                self.check_gzip_trailer()?;
+            },
+            Err(DecompressError::ShortData) => {
+                let end = self.inflate.get_current_output_size();
+                self.queue_data(end);
+                self.out_idx = end;
+                self.state = StreamState::NeedInput;
+            },
+            Err(DecompressError::OutputFull) => {
+                self.queue_data(self.outbuf.len());
+                self.state = StreamState::ContinueBlock;
+            },
+            Err(_) => {
+                self.state = StreamState::Finished;
+                return Err(ByteIOError::ReadError);
+            },
+        };
+        Ok(true)
+    }
+    fn fill(&mut self, size: usize) -> ByteIOResult<usize> {
+        while self.queue.len() - self.qpos < size {
+            if !self.decode_more()? {
+                break;
+            }
+        }
+        Ok((self.queue.len() - self.qpos).min(size))
+    }
+}
+
+impl<'a, 'b> ByteIO for InflateReader<'a, 'b> {
+    fn read_byte(&mut self) -> ByteIOResult<u8> {
+        let mut buf = [0u8; 1];
+        self.read_buf(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn peek_byte(&mut self) -> ByteIOResult<u8> {
+        let mut buf = [0u8; 1];
+        self.peek_buf(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn peek_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        let copy_size = self.fill(buf.len())?;
+        if copy_size == 0 { return Err(ByteIOError::EOF); }
+        buf[..copy_size].copy_from_slice(&self.queue[self.qpos..][..copy_size]);
+        Ok(copy_size)
+    }
+
+    fn read_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        let read_size = self.peek_buf(buf)?;
+        if read_size < buf.len() { return Err(ByteIOError::EOF); }
+        self.qpos += read_size;
+        self.pos  += read_size as u64;
+        Ok(read_size)
+    }
+
+    fn read_buf_some(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        if self.qpos == self.queue.len() {
+            self.fill(1)?;
+        }
+        let read_size = (self.queue.len() - self.qpos).min(buf.len());
+        if read_size == 0 { return Err(ByteIOError::EOF); }
+        buf[..read_size].copy_from_slice(&self.queue[self.qpos..][..read_size]);
+        self.qpos += read_size;
+        self.pos  += read_size as u64;
+        Ok(read_size)
+    }
+
+    #[allow(unused_variables)]
+    fn write_buf(&mut self, buf: &[u8]) -> ByteIOResult<()> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    fn tell(&mut self) -> u64 {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> ByteIOResult<u64> {
+        let target = match pos {
+                SeekFrom::Start(x)   => x as i64,
+                SeekFrom::Current(x) => (self.pos as i64) + x,
+                SeekFrom::End(_)     => return Err(ByteIOError::NotImplemented),
+            };
+        if target < (self.pos as i64) {
+            return Err(ByteIOError::NotImplemented);
+        }
+        let mut skip = (target as u64) - self.pos;
+        while skip > 0 {
+            let avail = self.fill(skip.min(STREAM_BUF_SIZE as u64) as usize)?;
+            if avail == 0 {
+                return Err(ByteIOError::EOF);
+            }
+            self.qpos += avail;
+            self.pos  += avail as u64;
+            skip      -= avail as u64;
+        }
+        Ok(self.pos)
+    }
+
+    fn is_eof(&self) -> bool {
+        self.is_finished()
+    }
+
+    fn is_seekable(&mut self) -> bool {
+        false
+    }
+
+    fn size(&mut self) -> i64 {
+        -1
+    }
+
+    fn flush(&mut self) -> ByteIOResult<()> { Ok(()) }
+}
+
 #[derive(Clone,Copy,Default)]
 struct Token {
     sym:        u16,
@@ -981,27 +1253,52 @@ impl Token {
         }
     }
     fn from_match(dist: u16, len: u16) -> Self {
-        let sym = match len {
-              3..= 10 => 257 +  len -   3,
-             11..= 18 => 265 + (len -  11) /  2,
-             19..= 34 => 269 + (len -  19) /  4,
-             35..= 66 => 273 + (len -  35) /  8,
-             67..=130 => 277 + (len -  67) / 16,
-            131..=257 => 281 + (len - 131) / 32,
-                    _ => 285,
-        };
-        let distsym = if dist <= 4 {
-                (dist - 1) as u8
-            } else {
-                let bits = 16 - (dist - 1).leading_zeros();
-                (bits as u8) * 2 - 2 + if ((dist - 1) & (1 << (bits - 2))) != 0 { 1 } else { 0 }
-            };
+        let sym = len_sym(len);
+        let distsym = dist_sym(dist);
         Self {
             sym, distsym, len, dist
         }
     }
 }
 
+fn len_sym(len: u16) -> u16 {
+    match len {
+          3..= 10 => 257 +  len -   3,
+         11..= 18 => 265 + (len -  11) /  2,
+         19..= 34 => 269 + (len -  19) /  4,
+         35..= 66 => 273 + (len -  35) /  8,
+         67..=130 => 277 + (len -  67) / 16,
+        131..=257 => 281 + (len - 131) / 32,
+                _ => 285,
+    }
+}
+
+fn dist_sym(dist: u16) -> u8 {
+    if dist <= 4 {
+        (dist - 1) as u8
+    } else {
+        let bits = 16 - (dist - 1).leading_zeros();
+        (bits as u8) * 2 - 2 + if ((dist - 1) & (1 << (bits - 2))) != 0 { 1 } else { 0 }
+    }
+}
+
+fn len_extra_bits(len: u16) -> u32 {
+    let llen = len - 3;
+    if (8..255).contains(&llen) {
+        16 - llen.leading_zeros() - 3
+    } else {
+        0
+    }
+}
+
+fn dist_extra_bits(dist: u16) -> u32 {
+    if dist >= 4 {
+        16 - (dist - 1).leading_zeros() - 2
+    } else {
+        0
+    }
+}
+
 fn add_codes(lens: &[u8], stats: &mut [u32], toks: &mut Vec<(u8, u8)>) {
     let mut last = 42;
     let mut lcount = 0;
@@ -1076,6 +1373,54 @@ fn add_codes(lens: &[u8], stats: &mut [u32], toks: &mut Vec<(u8, u8)>) {
     }
 }
 
+struct LenCodes {
+    toks:   Vec<(u8, u8)>,
+    cw:     [u16; 19],
+    cl:     [u8; 19],
+    nc:     usize,
+}
+
+impl LenCodes {
+    fn new(codes: &CodeHuff, dists: &DistHuff) -> Self {
+        let mut stats = [0u32; 19];
+        let mut toks = Vec::with_capacity(NUM_LITERALS + NUM_DISTS);
+        let mut cw = [0u16; 19];
+        let mut cl = [0u8; 19];
+        let mut nc = 0;
+
+        add_codes(&codes.lens[..codes.num_codes], &mut stats, &mut toks);
+        add_codes(&dists.lens[..dists.num_codes], &mut stats, &mut toks);
+
+        gen_tree(&mut cw, &mut cl, &mut nc, &mut stats, 7);
+
+        nc = cw.len();
+        for &idx in LEN_RECODE.iter().rev() {
+            if cl[idx] == 0 {
+                nc -= 1;
+            } else {
+                break;
+            }
+        }
+        if nc < 4 {
+            nc = 4;
+        }
+        Self { toks, cw, cl, nc }
+    }
+    fn bits(&self) -> u32 {
+        let mut bits = 4 + 3 * (self.nc as u32);
+        for &(sym, _) in self.toks.iter() {
+            bits += u32::from(self.cl[sym as usize]);
+            bits += match sym {
+                    16 => 2,
+                    17 => 3,
+                    18 => 7,
+                    _  => 0,
+                };
+        }
+        bits
+    }
+}
+
 ///! Deflate stream writer.
 pub struct DeflateWriter {
     dst:    Vec<u8>,
@@ -1109,6 +1454,11 @@ impl DeflateWriter {
         self.bbuf |= u32::from(val) << self.bits;
         self.bits += len;
     }
+    fn take_output(&mut self) -> Vec<u8> {
+        self.flush();
+        let cap = self.dst.capacity();
+        std::mem::replace(&mut self.dst, Vec::with_capacity(cap))
+    }
     ///! Finishes writing the stream and returns the output vector.
     pub fn end(mut self) -> Vec<u8> {
         self.flush();
@@ -1119,34 +1469,13 @@ impl DeflateWriter {
     }
 
     fn write_codes(&mut self, codes: &CodeHuff, dists: &DistHuff) {
-        let mut stats = [0u32; 19];
-        let mut toks = Vec::with_capacity(NUM_LITERALS + NUM_DISTS);
-        let mut cw = [0u16; 19];
-        let mut cl = [0u8; 19];
-        let mut nc = 0;
-
-        add_codes(&codes.lens[..codes.num_codes], &mut stats, &mut toks);
-        add_codes(&dists.lens[..dists.num_codes], &mut stats, &mut toks);
-
-        gen_tree(&mut cw, &mut cl, &mut nc, &mut stats, 7);
-
-        nc = cw.len();
-        for &idx in LEN_RECODE.iter().rev() {
-            if cl[idx] == 0 {
-                nc -= 1;
-            } else {
-                break;
-            }
+        let lcodes = LenCodes::new(codes, dists);
+        self.write((lcodes.nc - 4) as u16, 4);
+        for &idx in LEN_RECODE.iter().take(lcodes.nc) {
+            self.write(u16::from(lcodes.cl[idx]), 3);
         }
-        if nc < 4 {
-            nc = 4;
-        }
-        self.write((nc - 4) as u16, 4);
-        for &idx in LEN_RECODE.iter().take(nc) {
-            self.write(u16::from(cl[idx]), 3);
-        }
-        for &(sym, add) in toks.iter() {
-            self.write(cw[sym as usize], cl[sym as usize]);
+        for &(sym, add) in lcodes.toks.iter() {
+            self.write(lcodes.cw[sym as usize], lcodes.cl[sym as usize]);
             match sym {
                 16 => self.write(u16::from(add), 2),
                 17 => self.write(u16::from(add), 3),
@@ -1459,7 +1788,7 @@ struct MatchFinder<'a> {
     pos:        usize,
     hstart:     [usize; HASH_SIZE],
     hend:       [usize; HASH_SIZE],
-    hnext:      [usize; WINDOW_SIZE * 3],
+    hnext:      Vec<usize>,
 }
 
 impl<'a> MatchFinder<'a> {
@@ -1469,7 +1798,7 @@ impl<'a> MatchFinder<'a> {
             pos:        0,
             hstart:     [0; HASH_SIZE],
             hend:       [0; HASH_SIZE],
-            hnext:      [0; WINDOW_SIZE * 3],
+            hnext:      vec![NONEXT; WINDOW_SIZE * 3],
         };
         obj.build_hash();
         obj
@@ -1717,6 +2046,304 @@ impl LZParse for OptimalParser {
     }
 }
 
+const OPT_HASH_BITS:    usize = 16;
+const OPT_MAX_DIST:     usize = 32768;
+const OPT_MAX_CHAIN:    usize = 4096;
+const OPT_ITERATIONS:   usize = 8;
+const OPT_UNUSED_PRICE: u32   = 15;
+
+struct ChainMatchFinder {
+    head:   Vec<u32>,
+    prev:   Vec<u32>,
+}
+
+impl ChainMatchFinder {
+    fn new(size: usize) -> Self {
+        Self {
+            head:   vec![0; 1 << OPT_HASH_BITS],
+            prev:   vec![0; size],
+        }
+    }
+    fn hash(src: &[u8]) -> usize {
+        let val = (u32::from(src[0]) << 16) | (u32::from(src[1]) << 8) | u32::from(src[2]);
+        (val.wrapping_mul(0x9E37_79B1) >> (32 - OPT_HASH_BITS)) as usize
+    }
+    // Finds matches starting at the given position with strictly increasing length and distance.
+    // Positions should be fed in order since the current one is added to the chains afterwards.
+    fn find_matches(&mut self, src: &[u8], pos: usize, matches: &mut Vec<(u16, u16)>) {
+        if pos + 3 > src.len() {
+            return;
+        }
+        let key = Self::hash(&src[pos..]);
+        let max_len = (src.len() - pos).min(MAX_MATCH_LEN);
+        let mut last_len = 2;
+        let mut idx = self.head[key];
+        let mut depth = 0;
+        while idx != 0 && depth < OPT_MAX_CHAIN {
+            let cand = (idx - 1) as usize;
+            if pos - cand > OPT_MAX_DIST {
+                break;
+            }
+            let len = check_match(&src[pos..][..max_len], &src[cand..]) as usize;
+            if len > last_len {
+                matches.push((len as u16, (pos - cand) as u16));
+                last_len = len;
+                if len == max_len {
+                    break;
+                }
+            }
+            idx = self.prev[cand];
+            depth += 1;
+        }
+        self.prev[pos] = self.head[key];
+        self.head[key] = (pos + 1) as u32;
+    }
+}
+
+struct PriceModel {
+    lit:    [u32; NUM_LITERALS],
+    len:    [u32; MAX_MATCH_LEN + 1],
+    dist:   [u32; NUM_DISTS],
+}
+
+impl PriceModel {
+    fn new_fixed() -> Self {
+        let mut codes = CodeHuff::new(true);
+        codes.make_codes(&[]);
+        let mut dists = DistHuff::new(true);
+        dists.make_codes(&[]);
+        Self::from_codes(&codes, &dists)
+    }
+    fn from_codes(codes: &CodeHuff, dists: &DistHuff) -> Self {
+        let mut obj = Self {
+            lit:    [0; NUM_LITERALS],
+            len:    [0; MAX_MATCH_LEN + 1],
+            dist:   [0; NUM_DISTS],
+        };
+        for (price, &len) in obj.lit.iter_mut().zip(codes.lens.iter()) {
+            *price = if len != 0 { u32::from(len) } else { OPT_UNUSED_PRICE };
+        }
+        for (price, &len) in obj.dist.iter_mut().zip(dists.lens.iter()) {
+            *price = if len != 0 { u32::from(len) } else { OPT_UNUSED_PRICE };
+        }
+        for len in 3..=MAX_MATCH_LEN {
+            obj.len[len] = obj.lit[len_sym(len as u16) as usize] + len_extra_bits(len as u16);
+        }
+        obj
+    }
+    fn dist_price(&self, dist: u16) -> u32 {
+        self.dist[dist_sym(dist) as usize] + dist_extra_bits(dist)
+    }
+}
+
+fn tokens_bits(src: &[Token], codes: &CodeHuff, dists: &DistHuff) -> u32 {
+    let mut bits = 0;
+    for &tok in src.iter() {
+        bits += u32::from(codes.lens[tok.sym as usize]);
+        if tok.sym > 256 {
+            bits += len_extra_bits(tok.len);
+            bits += u32::from(dists.lens[tok.distsym as usize]);
+            bits += dist_extra_bits(tok.dist);
+        }
+    }
+    bits
+}
+
+fn dynamic_block_bits(src: &[Token]) -> u32 {
+    let mut codes = CodeHuff::new(false);
+    codes.make_codes(src);
+    let mut dists = DistHuff::new(false);
+    dists.make_codes(src);
+    3 + 5 + 5 + LenCodes::new(&codes, &dists).bits() + tokens_bits(src, &codes, &dists)
+}
+
+// Parser that refines the optimal parsing by repeating it with the symbol prices taken from the previous pass statistics.
+struct IterativeParser {
+    trellis:    Vec<TNode>,
+    matches:    Vec<(u16, u16)>,
+    mstart:     Vec<usize>,
+    tokens:     Vec<Token>,
+}
+
+impl IterativeParser {
+    fn new() -> Self {
+        Self {
+            trellis:    Vec::new(),
+            matches:    Vec::new(),
+            mstart:     Vec::new(),
+            tokens:     Vec::new(),
+        }
+    }
+    fn find_matches(&mut self, src: &[u8]) {
+        let mut finder = ChainMatchFinder::new(src.len());
+        self.matches.clear();
+        self.mstart.clear();
+        for pos in 0..src.len() {
+            self.mstart.push(self.matches.len());
+            finder.find_matches(src, pos, &mut self.matches);
+        }
+        self.mstart.push(self.matches.len());
+    }
+    fn parse_with_prices(&mut self, src: &[u8], prices: &PriceModel) {
+        self.trellis.clear();
+        self.trellis.resize(src.len() + 1, TNode::default());
+        self.trellis[0].price = 0;
+        for (i, &sym) in src.iter().enumerate() {
+            let cur_price = self.trellis[i].price;
+            let lprice = cur_price + prices.lit[sym as usize];
+            if self.trellis[i + 1].price > lprice {
+                self.trellis[i + 1] = TNode { price: lprice, dist: 0, link: i };
+            }
+            let mut len = 3;
+            for &(mlen, dist) in self.matches[self.mstart[i]..self.mstart[i + 1]].iter() {
+                let dprice = cur_price + prices.dist_price(dist);
+                while len <= usize::from(mlen) {
+                    let mprice = dprice + prices.len[len];
+                    if self.trellis[i + len].price > mprice {
+                        self.trellis[i + len] = TNode { price: mprice, dist, link: i };
+                    }
+                    len += 1;
+                }
+            }
+        }
+
+        self.tokens.clear();
+        let mut idx = src.len();
+        while idx > 0 {
+            let node = self.trellis[idx];
+            if node.dist == 0 {
+                self.tokens.push(Token::from_literal(src[node.link]));
+            } else {
+                self.tokens.push(Token::from_match(node.dist, (idx - node.link) as u16));
+            }
+            idx = node.link;
+        }
+        self.tokens.reverse();
+        self.tokens.push(TOKEN_EOB);
+    }
+}
+
+impl LZParse for IterativeParser {
+    fn parse(&mut self, src: &[u8], dst: &mut Vec<Token>) {
+        if src.is_empty() {
+            dst.push(TOKEN_EOB);
+            return;
+        }
+        self.find_matches(src);
+
+        let start = dst.len();
+        let mut best_bits = u32::MAX;
+        let mut prices = PriceModel::new_fixed();
+        for _ in 0..OPT_ITERATIONS {
+            self.parse_with_prices(src, &prices);
+
+            let mut codes = CodeHuff::new(false);
+            codes.make_codes(&self.tokens);
+            let mut dists = DistHuff::new(false);
+            dists.make_codes(&self.tokens);
+            let bits = LenCodes::new(&codes, &dists).bits() + tokens_bits(&self.tokens, &codes, &dists);
+            if bits >= best_bits {
+                break;
+            }
+            best_bits = bits;
+            dst.truncate(start);
+            dst.extend_from_slice(&self.tokens);
+            prices = PriceModel::from_codes(&codes, &dists);
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum BlockType {
+    Stored,
+    Fixed,
+    Dynamic,
+}
+
+const SPLIT_MIN_TOKENS: usize = 512;
+const SPLIT_STEPS:      usize = 16;
+
+// Block splitter that selects block boundaries and types giving the smallest compressed size.
+struct BlockSplitter {
+    offs:   Vec<usize>,
+    tmp:    Vec<Token>,
+    blocks: Vec<(usize, usize, BlockType)>,
+}
+
+impl BlockSplitter {
+    fn new() -> Self {
+        Self {
+            offs:   Vec::new(),
+            tmp:    Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+    fn block_cost(&mut self, tokens: &[Token], start: usize, end: usize) -> (BlockType, u32) {
+        self.tmp.clear();
+        self.tmp.extend_from_slice(&tokens[start..end]);
+        self.tmp.push(TOKEN_EOB);
+
+        let dyn_bits = dynamic_block_bits(&self.tmp);
+        let mut codes = CodeHuff::new(true);
+        codes.make_codes(&[]);
+        let mut dists = DistHuff::new(true);
+        dists.make_codes(&[]);
+        let fixed_bits = 3 + tokens_bits(&self.tmp, &codes, &dists);
+        // approximate alignment overhead
+        let stored_bits = 3 + 5 + 32 + ((self.offs[end] - self.offs[start]) as u32) * 8;
+
+        let mut best = (BlockType::Dynamic, dyn_bits);
+        if fixed_bits <= best.1 {
+            best = (BlockType::Fixed, fixed_bits);
+        }
+        if stored_bits < best.1 {
+            best = (BlockType::Stored, stored_bits);
+        }
+        best
+    }
+    fn split_range(&mut self, tokens: &[Token], start: usize, end: usize) {
+        let (btype, whole_bits) = self.block_cost(tokens, start, end);
+        if end - start >= SPLIT_MIN_TOKENS * 2 {
+            let mut best_bits = whole_bits;
+            let mut best_pos = 0;
+            for step in 1..SPLIT_STEPS {
+                let mid = start + (end - start) * step / SPLIT_STEPS;
+                if mid - start < SPLIT_MIN_TOKENS || end - mid < SPLIT_MIN_TOKENS {
+                    continue;
+                }
+                let (_, lbits) = self.block_cost(tokens, start, mid);
+                let (_, rbits) = self.block_cost(tokens, mid, end);
+                if lbits + rbits < best_bits {
+                    best_bits = lbits + rbits;
+                    best_pos = mid;
+                }
+            }
+            if best_pos != 0 {
+                self.split_range(tokens, start, best_pos);
+                self.split_range(tokens, best_pos, end);
+                return;
+            }
+        }
+        self.blocks.push((start, end, btype));
+    }
+    // Splits tokens (without end of block marker) into blocks.
+    fn split(&mut self, tokens: &[Token]) {
+        self.offs.clear();
+        let mut pos = 0;
+        for tok in tokens.iter() {
+            self.offs.push(pos);
+            pos += if tok.sym > 256 { usize::from(tok.len) } else { 1 };
+        }
+        self.offs.push(pos);
+        self.blocks.clear();
+        if !tokens.is_empty() {
+            self.split_range(tokens, 0, tokens.len());
+        } else {
+            self.blocks.push((0, 0, BlockType::Fixed));
+        }
+    }
+}
+
 ///! Deflate compression mode.
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub enum DeflateMode {
@@ -1728,6 +2355,10 @@ pub enum DeflateMode {
     Better,
     ///! Slow but the best compression.
     Best,
+    ///! Very slow but even better compression.
+    ///!
+    ///! It uses iterative optimal parsing and splits data into blocks when it results in smaller output.
+    Max,
 }
 
 impl Default for DeflateMode {
@@ -1743,9 +2374,11 @@ pub const DEFLATE_MODE_FAST: &str = "fast";
 pub const DEFLATE_MODE_BETTER: &str = "better";
 ///! Deflate option for best compression.
 pub const DEFLATE_MODE_BEST: &str = "best";
+///! Deflate option for maximum compression.
+pub const DEFLATE_MODE_MAX: &str = "max";
 
 ///! All possible option values for deflate compression.
-pub const DEFLATE_OPTION_VALUES: NAOptionDefinitionType = NAOptionDefinitionType::String(Some(&[DEFLATE_MODE_NONE, DEFLATE_MODE_FAST, DEFLATE_MODE_BETTER, DEFLATE_MODE_BEST]));
+pub const DEFLATE_OPTION_VALUES: NAOptionDefinitionType = NAOptionDefinitionType::String(Some(&[DEFLATE_MODE_NONE, DEFLATE_MODE_FAST, DEFLATE_MODE_BETTER, DEFLATE_MODE_BEST, DEFLATE_MODE_MAX]));
 
 impl std::str::FromStr for DeflateMode {
     type Err = ();
@@ -1756,6 +2389,7 @@ impl std::str::FromStr for DeflateMode {
             DEFLATE_MODE_FAST   => Ok(DeflateMode::Fast),
             DEFLATE_MODE_BETTER => Ok(DeflateMode::Better),
             DEFLATE_MODE_BEST   => Ok(DeflateMode::Best),
+            DEFLATE_MODE_MAX    => Ok(DeflateMode::Max),
             _ => Err(()),
         }
     }
@@ -1768,6 +2402,7 @@ impl ToString for DeflateMode {
             DeflateMode::Fast       => DEFLATE_MODE_FAST.to_string(),
             DeflateMode::Better     => DEFLATE_MODE_BETTER.to_string(),
             DeflateMode::Best       => DEFLATE_MODE_BEST.to_string(),
+            DeflateMode::Max        => DEFLATE_MODE_MAX.to_string(),
         }
     }
 }
@@ -1777,6 +2412,7 @@ enum Mode {
     Copy,
     Fixed,
     Dynamic,
+    Optimal,
 }
 
 const MAX_BLOCK_SIZE: usize = 65535;
@@ -1791,6 +2427,7 @@ pub struct Deflate {
     sum2:       u32,
     zlib_mode:  bool,
     parser:     Box<dyn LZParse + Send>,
+    splitter:   BlockSplitter,
 }
 
 impl Deflate {
@@ -1801,6 +2438,7 @@ impl Deflate {
             DeflateMode::Fast    => (Mode::Fixed,   Box::new(GreedyParser{}) as Box<dyn LZParse + Send>),
             DeflateMode::Better  => (Mode::Dynamic, Box::new(LazyParser{}) as Box<dyn LZParse + Send>),
             DeflateMode::Best    => (Mode::Dynamic, Box::new(OptimalParser::new()) as Box<dyn LZParse + Send>),
+            DeflateMode::Max     => (Mode::Optimal, Box::new(IterativeParser::new()) as Box<dyn LZParse + Send>),
         };
         Self {
             mode, parser,
@@ -1810,6 +2448,7 @@ impl Deflate {
             sum1:       1,
             sum2:       0,
             zlib_mode:  false,
+            splitter:   BlockSplitter::new(),
         }
     }
     ///! Writes zlib stream header.
@@ -1820,7 +2459,7 @@ impl Deflate {
             Mode::Copy      => 0x01,
             Mode::Fixed     => 0x5E,
             Mode::Dynamic   => 0x9C,
-            // 0xDA for the strongest one
+            Mode::Optimal   => 0xDA,
             };
         wr.write(level, 8);
         self.zlib_mode = true;
@@ -1893,45 +2532,232 @@ impl Deflate {
         }
         match self.mode {
             Mode::Copy => {
-                wr.write(final_block as u16, 1);
-                wr.write(0, 2);
-                wr.align();
-                wr.write(self.ssize as u16, 16);
-                wr.write(!self.ssize as u16, 16);
-                for &b in self.srcbuf[..self.ssize].iter() {
-                    wr.write(u16::from(b), 8);
-                }
+                write_stored_block(wr, &self.srcbuf[..self.ssize], final_block);
             },
             Mode::Fixed => {
-                wr.write(final_block as u16, 1);
-                wr.write(1, 2);
                 self.tokens.clear();
                 self.parser.parse(&self.srcbuf[..self.ssize], &mut self.tokens);
-                let mut codes = CodeHuff::new(true);
-                codes.make_codes(&self.tokens);
-                let mut dists = DistHuff::new(true);
-                dists.make_codes(&self.tokens);
-                wr.write_tokens(&self.tokens, &codes, &dists);
+                write_fixed_block(wr, &self.tokens, final_block);
             },
             Mode::Dynamic => {
-                wr.write(final_block as u16, 1);
-                wr.write(2, 2);
                 self.tokens.clear();
                 self.parser.parse(&self.srcbuf[..self.ssize], &mut self.tokens);
-                let mut codes = CodeHuff::new(false);
-                codes.make_codes(&self.tokens);
-                let mut dists = DistHuff::new(false);
-                dists.make_codes(&self.tokens);
-                wr.write((codes.num_codes - 257) as u16, 5);
-                wr.write((dists.num_codes - 1) as u16, 5);
-                wr.write_codes(&codes, &dists);
-                wr.write_tokens(&self.tokens, &codes, &dists);
+                write_dynamic_block(wr, &self.tokens, final_block);
+            },
+            Mode::Optimal => {
+                self.tokens.clear();
+                self.parser.parse(&self.srcbuf[..self.ssize], &mut self.tokens);
+                self.tokens.pop(); // end of block marker
+                self.splitter.split(&self.tokens);
+                let nblocks = self.splitter.blocks.len();
+                let mut blk_tokens = Vec::with_capacity(self.tokens.len() + 1);
+                for (i, &(start, end, btype)) in self.splitter.blocks.iter().enumerate() {
+                    let last = final_block && (i + 1 == nblocks);
+                    blk_tokens.clear();
+                    blk_tokens.extend_from_slice(&self.tokens[start..end]);
+                    blk_tokens.push(TOKEN_EOB);
+                    match btype {
+                        BlockType::Stored => {
+                            let src = &self.srcbuf[self.splitter.offs[start]..self.splitter.offs[end]];
+                            write_stored_block(wr, src, last);
+                        },
+                        BlockType::Fixed => write_fixed_block(wr, &blk_tokens, last),
+                        BlockType::Dynamic => write_dynamic_block(wr, &blk_tokens, last),
+                    }
+                }
             },
         }
         self.ssize = 0;
     }
 }
 
+fn write_stored_block(wr: &mut DeflateWriter, src: &[u8], final_block: bool) {
+    wr.write(final_block as u16, 1);
+    wr.write(0, 2);
+    wr.align();
+    wr.write(src.len() as u16, 16);
+    wr.write(!src.len() as u16, 16);
+    for &b in src.iter() {
+        wr.write(u16::from(b), 8);
+    }
+}
+
+fn write_fixed_block(wr: &mut DeflateWriter, tokens: &[Token], final_block: bool) {
+    wr.write(final_block as u16, 1);
+    wr.write(1, 2);
+    let mut codes = CodeHuff::new(true);
+    codes.make_codes(tokens);
+    let mut dists = DistHuff::new(true);
+    dists.make_codes(tokens);
+    wr.write_tokens(tokens, &codes, &dists);
+}
+
+fn write_dynamic_block(wr: &mut DeflateWriter, tokens: &[Token], final_block: bool) {
+    wr.write(final_block as u16, 1);
+    wr.write(2, 2);
+    let mut codes = CodeHuff::new(false);
+    codes.make_codes(tokens);
+    let mut dists = DistHuff::new(false);
+    dists.make_codes(tokens);
+    wr.write((codes.num_codes - 257) as u16, 5);
+    wr.write((dists.num_codes - 1) as u16, 5);
+    wr.write_codes(&codes, &dists);
+    wr.write_tokens(tokens, &codes, &dists);
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum StreamFormat {
+    Raw,
+    Zlib,
+    Gzip,
+}
+
+/// Bytestream writer that compresses data on the fly.
+///
+/// It produces raw deflate, zlib or gzip stream into the provided [`ByteWriter`] and acts as a normal [`ByteIO`] implementation so it can be used to create another `ByteWriter`.
+/// Compressed data is written to the output as soon as it is available and the stream is terminated by calling [`finish`].
+///
+/// [`ByteWriter`]: ../../io/byteio/struct.ByteWriter.html
+/// [`ByteIO`]: ../../io/byteio/trait.ByteIO.html
+/// [`finish`]: #method.finish
+pub struct DeflateWriterIO<'a, 'b> {
+    dst:        &'a mut ByteWriter<'b>,
+    compr:      Box<Deflate>,
+    wr:         DeflateWriter,
+    format:     StreamFormat,
+    crc:        GzipCRC32,
+    pos:        u64,
+    finished:   bool,
+}
+
+impl<'a, 'b> DeflateWriterIO<'a, 'b> {
+    fn new_internal(dst: &'a mut ByteWriter<'b>, mode: DeflateMode, format: StreamFormat) -> ByteIOResult<Self> {
+        let mut compr = Box::new(Deflate::new(mode));
+        let mut wr = DeflateWriter::new(Vec::with_capacity(STREAM_BUF_SIZE));
+        match format {
+            StreamFormat::Raw => {},
+            StreamFormat::Zlib => compr.write_zlib_header(&mut wr),
+            StreamFormat::Gzip => {
+                let xfl = match mode {
+                        DeflateMode::Best | DeflateMode::Max => 2,
+                        DeflateMode::Fast => 4,
+                        _ => 0,
+                    };
+                // no flags and no modification time, unknown OS
+                dst.write_buf(&[0x1F, 0x8B, 8, 0, 0, 0, 0, 0, xfl, 0xFF])?;
+            },
+        };
+        Ok(Self {
+            dst, compr, wr, format,
+            crc:        GzipCRC32::new(),
+            pos:        0,
+            finished:   false,
+        })
+    }
+    /// Creates a new writer producing raw deflate stream.
+    pub fn new(dst: &'a mut ByteWriter<'b>, mode: DeflateMode) -> Self {
+        Self::new_internal(dst, mode, StreamFormat::Raw).unwrap()
+    }
+    /// Creates a new writer producing zlib stream.
+    pub fn new_zlib(dst: &'a mut ByteWriter<'b>, mode: DeflateMode) -> Self {
+        Self::new_internal(dst, mode, StreamFormat::Zlib).unwrap()
+    }
+    /// Creates a new writer producing gzip file (RFC 1952).
+    pub fn new_gzip(dst: &'a mut ByteWriter<'b>, mode: DeflateMode) -> ByteIOResult<Self> {
+        Self::new_internal(dst, mode, StreamFormat::Gzip)
+    }
+    fn write_output(&mut self) -> ByteIOResult<()> {
+        let data = self.wr.take_output();
+        if !data.is_empty() {
+            self.dst.write_buf(&data)?;
+        }
+        Ok(())
+    }
+    /// Finishes compression and writes the rest of the stream.
+    pub fn finish(&mut self) -> ByteIOResult<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.compr.compress_end(&mut self.wr);
+        let wr = std::mem::replace(&mut self.wr, DeflateWriter::new(Vec::new()));
+        let data = wr.end();
+        self.dst.write_buf(&data)?;
+        if self.format == StreamFormat::Gzip {
+            self.dst.write_u32le(self.crc.crc)?;
+            self.dst.write_u32le(self.pos as u32)?;
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ByteIO for DeflateWriterIO<'a, 'b> {
+    fn read_byte(&mut self) -> ByteIOResult<u8> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    fn peek_byte(&mut self) -> ByteIOResult<u8> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    #[allow(unused_variables)]
+    fn read_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    #[allow(unused_variables)]
+    fn read_buf_some(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    #[allow(unused_variables)]
+    fn peek_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    fn write_buf(&mut self, buf: &[u8]) -> ByteIOResult<()> {
+        if self.finished {
+            return Err(ByteIOError::WriteError);
+        }
+        if self.format == StreamFormat::Gzip {
+            self.crc.update_crc(buf);
+        }
+        self.compr.compress(buf, &mut self.wr);
+        self.pos += buf.len() as u64;
+        self.write_output()
+    }
+
+    fn tell(&mut self) -> u64 {
+        self.pos
+    }
+
+    #[allow(unused_variables)]
+    fn seek(&mut self, pos: SeekFrom) -> ByteIOResult<u64> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    fn is_eof(&self) -> bool {
+        false
+    }
+
+    fn is_seekable(&mut self) -> bool {
+        false
+    }
+
+    fn size(&mut self) -> i64 {
+        self.pos as i64
+    }
+
+    fn flush(&mut self) -> ByteIOResult<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.compr.compress_flush(&mut self.wr);
+        self.write_output()?;
+        self.dst.flush()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -2089,4 +2915,112 @@ A partridge in a pear tree.";
     fn test_deflate_best() {
         deflate_test(DeflateMode::Best);
     }
+    #[test]
+    fn test_deflate_max() {
+        deflate_test(DeflateMode::Max);
+    }
+    fn compress_buf(src: &[u8], mode: DeflateMode) -> Vec<u8> {
+        let mut writer = DeflateWriter::new(Vec::new());
+        let mut compr = Deflate::new(mode);
+        compr.write_zlib_header(&mut writer);
+        compr.compress(src, &mut writer);
+        compr.compress_end(&mut writer);
+        writer.end()
+    }
+    #[test]
+    fn test_deflate_max_blocks() {
+        // noise followed by a picture-like data with repeating patterns
+        let mut src = Vec::with_capacity(100000);
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..20000 {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            src.push((seed >> 24) as u8);
+        }
+        for y in 0..300 {
+            for x in 0..256 {
+                src.push(if ((x / 16) + (y / 8)) & 1 == 0 { (x & 0xF) as u8 } else { 0x80 + (y & 3) as u8 });
+            }
+        }
+
+        let better = compress_buf(&src, DeflateMode::Better);
+        let max = compress_buf(&src, DeflateMode::Max);
+        assert!(max.len() <= better.len());
+
+        let mut uncompr = vec![0u8; src.len()];
+        let len = Inflate::uncompress(&max, &mut uncompr).unwrap();
+        assert_eq!(len, src.len());
+        assert_eq!(src, uncompr);
+    }
+    #[test]
+    fn test_inflate_stored() {
+        let output = Vec::with_capacity(20);
+        let mut writer = DeflateWriter::new(output);
+        let mut compr = Deflate::new(DeflateMode::NoCompr);
+        compr.compress(b"Hello, world!", &mut writer);
+        compr.compress_end(&mut writer);
+        let output = writer.end();
+        let mut uncompr = [0u8; 13];
+        let len = Inflate::uncompress(&output, &mut uncompr).unwrap();
+        assert_eq!(len, 13);
+        assert_eq!(&uncompr, b"Hello, world!");
+    }
+    fn stream_test(mode: DeflateMode, gzip: bool) {
+        let mut src = Vec::with_capacity(200000);
+        let mut seed = 42u32;
+        while src.len() < 200000 {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let word = &b"partridge pear tree turtle doves French hens colly birds gold rings"[(seed >> 27) as usize..][..5];
+            src.extend_from_slice(word);
+            src.push((seed >> 8) as u8);
+        }
+
+        let mut compressed = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut compressed);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut dw = if gzip {
+                    DeflateWriterIO::new_gzip(&mut bw, mode).unwrap()
+                } else {
+                    DeflateWriterIO::new_zlib(&mut bw, mode)
+                };
+            for chunk in src.chunks(10000) {
+                dw.write_buf(chunk).unwrap();
+            }
+            dw.finish().unwrap();
+        }
+
+        let mut mr = MemoryReader::new_read(&compressed);
+        let mut br = ByteReader::new(&mut mr);
+        if gzip {
+            let dst = gzip_decode(&mut br, false).unwrap();
+            assert_eq!(dst, src);
+            br.seek(SeekFrom::Start(0)).unwrap();
+        }
+        let mut ir = if gzip {
+                InflateReader::new_gzip(&mut br, false).unwrap()
+            } else {
+                InflateReader::new(&mut br)
+            };
+        let mut ibr = ByteReader::new(&mut ir);
+        assert_eq!(ibr.read_u32le().unwrap(), read_u32le(&src).unwrap());
+        ibr.read_skip(1000).unwrap();
+        let mut dst = vec![0; src.len() - 1004];
+        let mut pos = 0;
+        while pos < dst.len() {
+            let len = ibr.read_buf_some(&mut dst[pos..]).unwrap();
+            pos += len;
+        }
+        assert_eq!(&dst, &src[1004..]);
+        assert!(ibr.read_byte().is_err());
+    }
+    #[test]
+    fn test_stream_zlib() {
+        stream_test(DeflateMode::NoCompr, false);
+        stream_test(DeflateMode::Fast, false);
+        stream_test(DeflateMode::Better, false);
+    }
+    #[test]
+    fn test_stream_gzip() {
+        stream_test(DeflateMode::Better, true);
+    }
 }
@@ -0,0 +1,482 @@
+//! LZ4 format support.
+//!
+//! This module provides functionality for decompressing raw LZ4 blocks via [`decompress_block`] and LZ4 frames (as produced by `lz4` utility) via [`LZ4Decoder`].
+//!
+//! [`decompress_block`]: ./fn.decompress_block.html
+//! [`LZ4Decoder`]: ./struct.LZ4Decoder.html
+//!
+//! # Examples
+//!
+//! Decompressing LZ4 frame in chunks:
+//! ```
+//! use nihav_core::compr::DecompressError;
+//! use nihav_core::compr::lz4::LZ4Decoder;
+//!
+//! # fn decompress(input_data: &[u8]) -> Result<Vec<u8>, DecompressError> {
+//! let mut decoder = LZ4Decoder::new();
+//! let mut dst_buf: Vec<u8> = Vec::new();
+//! let mut output_chunk = [0u8; 1024];
+//! for src in input_data.chunks(512) {
+//!     let mut repeat = false;
+//!     loop {
+//!         match decoder.decompress_data(src, &mut output_chunk, repeat) {
+//!             Ok(len) => {
+//!                 dst_buf.extend_from_slice(&output_chunk[..len]);
+//!                 return Ok(dst_buf);
+//!             },
+//!             Err(DecompressError::ShortData) => break,
+//!             Err(DecompressError::OutputFull) => {
+//!                 repeat = true;
+//!                 dst_buf.extend_from_slice(&output_chunk);
+//!             },
+//!             Err(err) => return Err(err),
+//!         }
+//!     }
+//! }
+//! Err(DecompressError::ShortData)
+//! # }
+//! ```
+
+use super::*;
+use super::stream::*;
+
+const MAX_DIST: usize = 65535;
+const FRAME_MAGIC: u32 = 0x184D2204;
+const LEGACY_MAGIC: u32 = 0x184C2102;
+
+fn read_length(src: &[u8], pos: &mut usize, mut len: usize) -> DecompressResult<usize> {
+    if len == 15 {
+        loop {
+            let b = *src.get(*pos).ok_or(DecompressError::InvalidData)?;
+            *pos += 1;
+            len += usize::from(b);
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+// Decodes a block into history, `OutputFull` is reported if the decoded data exceeds `max_len` bytes.
+fn decode_block(src: &[u8], hist: &mut History, max_len: usize) -> DecompressResult<()> {
+    let mut pos = 0;
+    let mut out_len = 0;
+    loop {
+        let token = *src.get(pos).ok_or(DecompressError::InvalidData)?;
+        pos += 1;
+        let lit_len = read_length(src, &mut pos, usize::from(token >> 4))?;
+        if pos + lit_len > src.len() {
+            return Err(DecompressError::InvalidData);
+        }
+        out_len += lit_len;
+        if out_len > max_len {
+            return Err(DecompressError::OutputFull);
+        }
+        hist.push_slice(&src[pos..][..lit_len]);
+        pos += lit_len;
+        if pos == src.len() {
+            return Ok(());
+        }
+        if pos + 2 > src.len() {
+            return Err(DecompressError::InvalidData);
+        }
+        let offset = usize::from(src[pos]) | (usize::from(src[pos + 1]) << 8);
+        pos += 2;
+        let match_len = read_length(src, &mut pos, usize::from(token & 0xF))? + 4;
+        out_len += match_len;
+        if out_len > max_len {
+            return Err(DecompressError::OutputFull);
+        }
+        hist.copy_match(offset, match_len)?;
+    }
+}
+
+/// Decompresses raw LZ4 block into the provided buffer.
+///
+/// The function returns the length of decompressed data.
+/// If the block decodes to more data than the buffer can hold, `OutputFull` error is returned.
+pub fn decompress_block(src: &[u8], dst: &mut [u8]) -> DecompressResult<usize> {
+    let mut hist = History::new(MAX_DIST);
+    decode_block(src, &mut hist, dst.len())?;
+    Ok(hist.deliver(dst))
+}
+
+const PRIME1: u32 = 2654435761;
+const PRIME2: u32 = 2246822519;
+const PRIME3: u32 = 3266489917;
+const PRIME4: u32 = 668265263;
+const PRIME5: u32 = 374761393;
+
+fn read_u32le(src: &[u8]) -> u32 {
+    u32::from(src[0]) | (u32::from(src[1]) << 8) | (u32::from(src[2]) << 16) | (u32::from(src[3]) << 24)
+}
+
+/// xxHash32 calculator used for LZ4 frame checksums.
+#[derive(Clone)]
+struct XXHash32 {
+    acc:    [u32; 4],
+    buf:    [u8; 16],
+    blen:   usize,
+    total:  u64,
+    seed:   u32,
+}
+
+impl XXHash32 {
+    fn new(seed: u32) -> Self {
+        Self {
+            acc:    [seed.wrapping_add(PRIME1).wrapping_add(PRIME2), seed.wrapping_add(PRIME2), seed, seed.wrapping_sub(PRIME1)],
+            buf:    [0; 16],
+            blen:   0,
+            total:  0,
+            seed,
+        }
+    }
+    fn round(acc: u32, lane: u32) -> u32 {
+        acc.wrapping_add(lane.wrapping_mul(PRIME2)).rotate_left(13).wrapping_mul(PRIME1)
+    }
+    fn process_stripe(&mut self, stripe: &[u8]) {
+        for (acc, lane) in self.acc.iter_mut().zip(stripe.chunks_exact(4)) {
+            *acc = Self::round(*acc, read_u32le(lane));
+        }
+    }
+    fn update(&mut self, src: &[u8]) {
+        self.total += src.len() as u64;
+        let mut src = src;
+        if self.blen > 0 {
+            let copy_len = (16 - self.blen).min(src.len());
+            self.buf[self.blen..][..copy_len].copy_from_slice(&src[..copy_len]);
+            self.blen += copy_len;
+            src = &src[copy_len..];
+            if self.blen < 16 {
+                return;
+            }
+            let stripe = self.buf;
+            self.process_stripe(&stripe);
+            self.blen = 0;
+        }
+        let mut stripes = src.chunks_exact(16);
+        for stripe in &mut stripes {
+            self.process_stripe(stripe);
+        }
+        let tail = stripes.remainder();
+        self.buf[..tail.len()].copy_from_slice(tail);
+        self.blen = tail.len();
+    }
+    fn finish(&self) -> u32 {
+        let mut hash = if self.total >= 16 {
+                self.acc[0].rotate_left(1).wrapping_add(self.acc[1].rotate_left(7))
+                    .wrapping_add(self.acc[2].rotate_left(12)).wrapping_add(self.acc[3].rotate_left(18))
+            } else {
+                self.seed.wrapping_add(PRIME5)
+            };
+        hash = hash.wrapping_add(self.total as u32);
+        let mut tail = self.buf[..self.blen].chunks_exact(4);
+        for lane in &mut tail {
+            hash = hash.wrapping_add(read_u32le(lane).wrapping_mul(PRIME3)).rotate_left(17).wrapping_mul(PRIME4);
+        }
+        for &b in tail.remainder() {
+            hash = hash.wrapping_add(u32::from(b).wrapping_mul(PRIME5)).rotate_left(11).wrapping_mul(PRIME1);
+        }
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^= hash >> 16;
+        hash
+    }
+    fn hash(src: &[u8]) -> u32 {
+        let mut hasher = Self::new(0);
+        hasher.update(src);
+        hasher.finish()
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum FrameState {
+    Magic,
+    Skip(u32),
+    Header,
+    BlockSize,
+    Block(usize, bool),
+    ContentChecksum,
+    End,
+}
+
+struct FrameDecoder {
+    state:          FrameState,
+    block_csum:     bool,
+    content_csum:   bool,
+    max_block_size: usize,
+    content_size:   Option<u64>,
+    out_size:       u64,
+    hasher:         XXHash32,
+}
+
+impl FrameDecoder {
+    fn new() -> Self {
+        Self {
+            state:          FrameState::Magic,
+            block_csum:     false,
+            content_csum:   false,
+            max_block_size: 0,
+            content_size:   None,
+            out_size:       0,
+            hasher:         XXHash32::new(0),
+        }
+    }
+    fn decode_unit(&mut self, input: &mut InputBuffer, hist: &mut History) -> DecompressResult<()> {
+        match self.state {
+            FrameState::Magic => {
+                let magic = input.read_u32le()?;
+                match magic {
+                    FRAME_MAGIC => self.state = FrameState::Header,
+                    0x184D2A50..=0x184D2A5F => {
+                        let size = input.read_u32le()?;
+                        self.state = FrameState::Skip(size);
+                    },
+                    LEGACY_MAGIC => return Err(DecompressError::Unsupported),
+                    _ => return Err(DecompressError::InvalidHeader),
+                }
+            },
+            FrameState::Skip(size) => {
+                let len = (size as usize).min(input.left());
+                if len == 0 && size > 0 {
+                    return Err(DecompressError::ShortData);
+                }
+                input.read_buf(len)?;
+                let left = size - (len as u32);
+                self.state = if left > 0 { FrameState::Skip(left) } else { FrameState::Magic };
+            },
+            FrameState::Header => {
+                let hdr = input.peek_buf(2)?;
+                let (flags, bd) = (hdr[0], hdr[1]);
+                if (flags >> 6) != 1 || (flags & 2) != 0 || (bd & 0x8F) != 0 {
+                    return Err(DecompressError::InvalidHeader);
+                }
+                let has_size = (flags & 8) != 0;
+                let has_dict = (flags & 1) != 0;
+                let hdr_len = 2 + if has_size { 8 } else { 0 } + if has_dict { 4 } else { 0 };
+                let hdr = input.peek_buf(hdr_len + 1)?;
+                if ((XXHash32::hash(&hdr[..hdr_len]) >> 8) & 0xFF) as u8 != hdr[hdr_len] {
+                    return Err(DecompressError::CRCError);
+                }
+                if has_dict {
+                    return Err(DecompressError::Unsupported);
+                }
+                self.content_size = if has_size {
+                        Some(u64::from(read_u32le(&hdr[2..])) | (u64::from(read_u32le(&hdr[6..])) << 32))
+                    } else {
+                        None
+                    };
+                self.block_csum   = (flags & 0x10) != 0;
+                self.content_csum = (flags & 4) != 0;
+                self.max_block_size = match bd >> 4 {
+                        4 => 1 << 16,
+                        5 => 1 << 18,
+                        6 => 1 << 20,
+                        7 => 1 << 22,
+                        _ => return Err(DecompressError::InvalidHeader),
+                    };
+                self.hasher = XXHash32::new(0);
+                self.out_size = 0;
+                input.read_buf(hdr_len + 1)?;
+                self.state = FrameState::BlockSize;
+            },
+            FrameState::BlockSize => {
+                let size = input.read_u32le()?;
+                if size == 0 {
+                    if let Some(csize) = self.content_size {
+                        if csize != self.out_size {
+                            return Err(DecompressError::InvalidData);
+                        }
+                    }
+                    self.state = if self.content_csum { FrameState::ContentChecksum } else { FrameState::End };
+                } else {
+                    let len = (size & 0x7FFF_FFFF) as usize;
+                    if len > self.max_block_size {
+                        return Err(DecompressError::InvalidData);
+                    }
+                    self.state = FrameState::Block(len, (size >> 31) == 0);
+                }
+            },
+            FrameState::Block(len, compressed) => {
+                let full_len = len + if self.block_csum { 4 } else { 0 };
+                let data = input.peek_buf(full_len)?;
+                if self.block_csum && XXHash32::hash(&data[..len]) != read_u32le(&data[len..]) {
+                    return Err(DecompressError::CRCError);
+                }
+                let start = hist.len();
+                if compressed {
+                    match decode_block(&data[..len], hist, self.max_block_size) {
+                        Ok(()) => {},
+                        Err(DecompressError::OutputFull) => return Err(DecompressError::InvalidData),
+                        Err(err) => return Err(err),
+                    }
+                } else {
+                    hist.push_slice(&data[..len]);
+                }
+                let out_len = hist.len() - start;
+                if out_len > self.max_block_size {
+                    return Err(DecompressError::InvalidData);
+                }
+                if self.content_csum {
+                    self.hasher.update(hist.get_last(out_len));
+                }
+                self.out_size += out_len as u64;
+                input.read_buf(full_len)?;
+                self.state = FrameState::BlockSize;
+            },
+            FrameState::ContentChecksum => {
+                let csum = input.read_u32le()?;
+                if csum != self.hasher.finish() {
+                    return Err(DecompressError::CRCError);
+                }
+                self.state = FrameState::End;
+            },
+            FrameState::End => {},
+        }
+        Ok(())
+    }
+    fn decode(&mut self, input: &mut InputBuffer, hist: &mut History, want: usize) -> DecompressResult<bool> {
+        while hist.pending() < want && self.state != FrameState::End {
+            let pos = input.tell();
+            if let Err(err) = self.decode_unit(input, hist) {
+                input.restore(pos);
+                return Err(err);
+            }
+        }
+        Ok(self.state == FrameState::End)
+    }
+}
+
+/// LZ4 frame decompressor.
+pub struct LZ4Decoder {
+    stream: StreamState,
+    frame:  FrameDecoder,
+}
+
+impl LZ4Decoder {
+    /// Creates a new instance of `LZ4Decoder`.
+    pub fn new() -> Self {
+        Self {
+            stream: StreamState::new(MAX_DIST),
+            frame:  FrameDecoder::new(),
+        }
+    }
+    /// Reports whether the frame was decoded completely.
+    pub fn is_finished(&self) -> bool { self.stream.is_finished() }
+    /// Reports the current amount of bytes output into the destination buffer after the last run.
+    pub fn get_current_output_size(&self) -> usize { self.stream.get_current_output_size() }
+    /// Reports the content size stored in the frame header (if present).
+    pub fn get_content_size(&self) -> Option<u64> { self.frame.content_size }
+    /// Tries to decompress input data and write it to the output buffer.
+    ///
+    /// The semantics are the same as in [`Inflate::decompress_data`]:
+    /// `ShortData` error means that all input was consumed and more is required (output is accumulated in the same buffer),
+    /// `OutputFull` means that the output buffer is full and the function should be called again with `continue_block` set to `true`
+    /// (the input provided in that call is ignored).
+    ///
+    /// [`Inflate::decompress_data`]: ../deflate/struct.Inflate.html#method.decompress_data
+    pub fn decompress_data(&mut self, src: &[u8], dst: &mut [u8], continue_block: bool) -> DecompressResult<usize> {
+        let frame = &mut self.frame;
+        self.stream.decompress(src, dst, continue_block, |input, hist, want| frame.decode(input, hist, want))
+    }
+    /// Resets decoder state.
+    pub fn reset(&mut self) {
+        self.stream.reset();
+        self.frame = FrameDecoder::new();
+    }
+    /// Decompresses input LZ4 frame into the provided buffer.
+    pub fn uncompress(src: &[u8], dst: &mut [u8]) -> DecompressResult<usize> {
+        let mut decoder = Self::new();
+        decoder.decompress_data(src, dst, false)
+    }
+}
+
+impl Default for LZ4Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XMAS_LEN: usize = 603;
+
+    #[test]
+    fn test_lz4_block() {
+        let src = [0x35, b'a', b'b', b'c', 0x03, 0x00, 0x10, b'!'];
+        let mut dst = [0u8; 16];
+        let len = decompress_block(&src, &mut dst).unwrap();
+        assert_eq!(&dst[..len], b"abcabcabcabc!");
+        let mut dst = [0u8; 4];
+        assert!(matches!(decompress_block(&src, &mut dst), Err(DecompressError::OutputFull)));
+        // a single match expanding to almost 64 MB
+        let mut bomb = vec![0x1F, 0x00, 0x01, 0x00];
+        bomb.extend_from_slice(&[0xFF; 0x40000]);
+        bomb.push(0x00);
+        let mut dst = [0u8; 1024];
+        assert!(matches!(decompress_block(&bomb, &mut dst), Err(DecompressError::OutputFull)));
+    }
+    #[test]
+    fn test_xxhash() {
+        assert_eq!(XXHash32::hash(b""), 0x02CC5D05);
+        assert_eq!(XXHash32::hash(b"abc"), 0x32D153FF);
+        let mut hasher = XXHash32::new(0);
+        hasher.update(b"Nobody inspects");
+        hasher.update(b" the spammish repetition");
+        assert_eq!(hasher.finish(), XXHash32::hash(b"Nobody inspects the spammish repetition"));
+    }
+    #[test]
+    fn test_lz4_frame() {
+        const SRC: &[u8] = &[
+        0x04, 0x22, 0x4D, 0x18, 0x74, 0x40, 0xBD, 0xD9, 0x00, 0x00, 0x00, 0xFF, 0x4C, 0x54, 0x68, 0x65,
+        0x20, 0x66, 0x69, 0x72, 0x73, 0x74, 0x20, 0x64, 0x61, 0x79, 0x20, 0x6F, 0x66, 0x20, 0x43, 0x68,
+        0x72, 0x69, 0x73, 0x74, 0x6D, 0x61, 0x73, 0x2C, 0x0A, 0x4D, 0x79, 0x20, 0x74, 0x72, 0x75, 0x65,
+        0x20, 0x6C, 0x6F, 0x76, 0x65, 0x20, 0x73, 0x65, 0x6E, 0x74, 0x20, 0x74, 0x6F, 0x20, 0x6D, 0x65,
+        0x0A, 0x41, 0x20, 0x70, 0x61, 0x72, 0x74, 0x72, 0x69, 0x64, 0x67, 0x65, 0x20, 0x69, 0x6E, 0x20,
+        0x61, 0x20, 0x70, 0x65, 0x61, 0x72, 0x20, 0x74, 0x72, 0x65, 0x65, 0x2E, 0x0A, 0x0A, 0x54, 0x68,
+        0x65, 0x20, 0x73, 0x65, 0x63, 0x6F, 0x6E, 0x64, 0x52, 0x00, 0x18, 0xFF, 0x06, 0x54, 0x77, 0x6F,
+        0x20, 0x74, 0x75, 0x72, 0x74, 0x6C, 0x65, 0x20, 0x64, 0x6F, 0x76, 0x65, 0x73, 0x2C, 0x20, 0x61,
+        0x6E, 0x64, 0x68, 0x00, 0x0F, 0x4F, 0x74, 0x68, 0x69, 0x72, 0x67, 0x00, 0x1A, 0xFF, 0x02, 0x68,
+        0x72, 0x65, 0x65, 0x20, 0x46, 0x72, 0x65, 0x6E, 0x63, 0x68, 0x20, 0x68, 0x65, 0x6E, 0x73, 0x2C,
+        0x7A, 0x00, 0x25, 0x6F, 0x66, 0x6F, 0x75, 0x72, 0x74, 0x68, 0x34, 0x01, 0x18, 0xF0, 0x00, 0x46,
+        0x6F, 0x75, 0x72, 0x20, 0x63, 0x6F, 0x6C, 0x6C, 0x79, 0x20, 0x62, 0x69, 0x72, 0x64, 0x7A, 0x00,
+        0x0F, 0x8D, 0x00, 0x37, 0x2F, 0x69, 0x66, 0x8C, 0x00, 0x1B, 0xFF, 0x00, 0x69, 0x76, 0x65, 0x20,
+        0x67, 0x6F, 0x6C, 0x64, 0x20, 0x72, 0x69, 0x6E, 0x67, 0x73, 0x2C, 0x9D, 0x00, 0x3F, 0x50, 0x74,
+        0x72, 0x65, 0x65, 0x2E, 0xB9, 0xB1, 0xA0, 0x23, 0x00, 0x00, 0x00, 0x00, 0xD3, 0x5F, 0x70, 0x50,
+        ];
+        let mut dst = [0u8; XMAS_LEN];
+        let len = LZ4Decoder::uncompress(SRC, &mut dst).unwrap();
+        assert_eq!(len, XMAS_LEN);
+        assert_eq!(&dst[..27], b"The first day of Christmas,");
+
+        let mut decoder = LZ4Decoder::new();
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 100];
+        let mut finished = false;
+        for src in SRC.chunks(7) {
+            let mut repeat = false;
+            loop {
+                match decoder.decompress_data(src, &mut chunk, repeat) {
+                    Ok(len) => {
+                        out.extend_from_slice(&chunk[..len]);
+                        finished = true;
+                        break;
+                    },
+                    Err(DecompressError::ShortData) => break,
+                    Err(DecompressError::OutputFull) => {
+                        repeat = true;
+                        out.extend_from_slice(&chunk);
+                    },
+                    Err(err) => panic!("decoding error {:?}", err),
+                }
+            }
+        }
+        assert!(finished);
+        assert_eq!(out.as_slice(), &dst[..]);
+    }
+}
@@ -0,0 +1,516 @@
+//! LZMA format support.
+//!
+//! This module provides functionality for decompressing LZMA streams either in `.lzma` file format (also known as LZMA-alone) or raw streams with externally provided parameters via [`LZMADecoder`].
+//!
+//! [`LZMADecoder`]: ./struct.LZMADecoder.html
+//!
+//! # Examples
+//!
+//! Decompressing `.lzma` file contents into sufficiently large output buffer:
+//! ```
+//! # use nihav_core::compr::DecompressError;
+//! use nihav_core::compr::lzma::LZMADecoder;
+//!
+//! # fn decompress(input: &[u8]) -> Result<(), DecompressError> {
+//! # let mut output_buffer = [0u8; 16];
+//! let output_length = LZMADecoder::uncompress(input, &mut output_buffer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::*;
+use super::stream::*;
+
+const NUM_STATES:           usize = 12;
+const MAX_POS_BITS:         usize = 4;
+const NUM_LEN_TO_POS:       usize = 4;
+const END_POS_MODEL_INDEX:  usize = 14;
+const NUM_FULL_DISTANCES:   usize = 1 << (END_POS_MODEL_INDEX >> 1);
+const NUM_ALIGN_BITS:       usize = 4;
+const MATCH_MIN_LEN:        usize = 2;
+const MIN_DICT_SIZE:        u32 = 1 << 12;
+const HEADER_SIZE:          usize = 13;
+/// Maximum number of input bytes a single literal or match may take.
+const MAX_SYMBOL_INPUT:     usize = 64;
+
+const PROB_INIT:            u16 = 1 << 10;
+
+const IS_MATCH:             usize = 0;
+const IS_REP:               usize = IS_MATCH + (NUM_STATES << MAX_POS_BITS);
+const IS_REP_G0:            usize = IS_REP + NUM_STATES;
+const IS_REP_G1:            usize = IS_REP_G0 + NUM_STATES;
+const IS_REP_G2:            usize = IS_REP_G1 + NUM_STATES;
+const IS_REP0_LONG:         usize = IS_REP_G2 + NUM_STATES;
+const POS_SLOT:             usize = IS_REP0_LONG + (NUM_STATES << MAX_POS_BITS);
+const SPEC_POS:             usize = POS_SLOT + (NUM_LEN_TO_POS << 6);
+const ALIGN:                usize = SPEC_POS + NUM_FULL_DISTANCES - END_POS_MODEL_INDEX;
+const LEN_CODER:            usize = ALIGN + (1 << NUM_ALIGN_BITS);
+const REP_LEN_CODER:        usize = LEN_CODER + LEN_CODER_SIZE;
+const LITERAL:              usize = REP_LEN_CODER + LEN_CODER_SIZE;
+
+const LEN_CHOICE:           usize = 0;
+const LEN_CHOICE2:          usize = 1;
+const LEN_LOW:              usize = 2;
+const LEN_MID:              usize = LEN_LOW + (1 << (MAX_POS_BITS + 3));
+const LEN_HIGH:             usize = LEN_MID + (1 << (MAX_POS_BITS + 3));
+const LEN_CODER_SIZE:       usize = LEN_HIGH + (1 << 8);
+
+#[derive(Clone,Copy,Default)]
+struct RangeDecoder {
+    range:  u32,
+    code:   u32,
+}
+
+/// Decoder parameters and state that may be restored if the input ended in the middle of a symbol.
+#[derive(Clone,Copy,Default)]
+struct DecState {
+    rc:         RangeDecoder,
+    state:      usize,
+    reps:       [u32; 4],
+    total_pos:  u64,
+    unp_left:   Option<u64>,
+}
+
+struct LZMAState {
+    lc:         u8,
+    lp:         u8,
+    pb:         u8,
+    dict_size:  u32,
+    probs:      Vec<u16>,
+    undo:       Vec<(u32, u16)>,
+    logging:    bool,
+    st:         DecState,
+    started:    bool,
+    ended:      bool,
+}
+
+impl LZMAState {
+    fn new(props: u8, dict_size: u32, unp_size: Option<u64>) -> DecompressResult<Self> {
+        if props >= 9 * 5 * 5 {
+            return Err(DecompressError::InvalidHeader);
+        }
+        let lc = props % 9;
+        let lp = (props / 9) % 5;
+        let pb = props / 45;
+        let dict_size = dict_size.max(MIN_DICT_SIZE);
+        let nprobs = LITERAL + (0x300 << (lc + lp));
+        Ok(Self {
+            lc, lp, pb, dict_size,
+            probs:      vec![PROB_INIT; nprobs],
+            undo:       Vec::new(),
+            logging:    false,
+            st:         DecState { unp_left: unp_size, ..Default::default() },
+            started:    false,
+            ended:      false,
+        })
+    }
+    fn reset(&mut self, unp_size: Option<u64>) {
+        for el in self.probs.iter_mut() {
+            *el = PROB_INIT;
+        }
+        self.st = DecState { unp_left: unp_size, ..Default::default() };
+        self.started = false;
+        self.ended = false;
+    }
+    fn normalize(&mut self, input: &mut InputBuffer) -> DecompressResult<()> {
+        if self.st.rc.range < (1 << 24) {
+            self.st.rc.range <<= 8;
+            self.st.rc.code = (self.st.rc.code << 8) | u32::from(input.read_byte()?);
+        }
+        Ok(())
+    }
+    fn decode_bit(&mut self, input: &mut InputBuffer, idx: usize) -> DecompressResult<u32> {
+        let prob = self.probs[idx];
+        if self.logging {
+            self.undo.push((idx as u32, prob));
+        }
+        let bound = (self.st.rc.range >> 11) * u32::from(prob);
+        let bit = if self.st.rc.code < bound {
+                self.probs[idx] = prob + ((2048 - prob) >> 5);
+                self.st.rc.range = bound;
+                0
+            } else {
+                self.probs[idx] = prob - (prob >> 5);
+                self.st.rc.code  -= bound;
+                self.st.rc.range -= bound;
+                1
+            };
+        self.normalize(input)?;
+        Ok(bit)
+    }
+    fn decode_direct_bits(&mut self, input: &mut InputBuffer, nbits: usize) -> DecompressResult<u32> {
+        let mut res = 0;
+        for _ in 0..nbits {
+            self.st.rc.range >>= 1;
+            self.st.rc.code = self.st.rc.code.wrapping_sub(self.st.rc.range);
+            let mask = 0u32.wrapping_sub(self.st.rc.code >> 31);
+            self.st.rc.code = self.st.rc.code.wrapping_add(self.st.rc.range & mask);
+            if self.st.rc.code == self.st.rc.range {
+                return Err(DecompressError::InvalidData);
+            }
+            self.normalize(input)?;
+            res = (res << 1) + mask.wrapping_add(1);
+        }
+        Ok(res)
+    }
+    fn decode_tree(&mut self, input: &mut InputBuffer, base: usize, nbits: usize) -> DecompressResult<u32> {
+        let mut m = 1;
+        for _ in 0..nbits {
+            m = (m << 1) + self.decode_bit(input, base + (m as usize))?;
+        }
+        Ok(m - (1 << nbits))
+    }
+    fn decode_reverse_tree(&mut self, input: &mut InputBuffer, base: usize, nbits: usize) -> DecompressResult<u32> {
+        let mut m = 1;
+        let mut sym = 0;
+        for i in 0..nbits {
+            let bit = self.decode_bit(input, base + (m as usize))?;
+            m = (m << 1) + bit;
+            sym |= bit << i;
+        }
+        Ok(sym)
+    }
+    fn decode_len(&mut self, input: &mut InputBuffer, base: usize, pos_state: usize) -> DecompressResult<usize> {
+        if self.decode_bit(input, base + LEN_CHOICE)? == 0 {
+            return Ok(self.decode_tree(input, base + LEN_LOW + (pos_state << 3), 3)? as usize);
+        }
+        if self.decode_bit(input, base + LEN_CHOICE2)? == 0 {
+            return Ok(8 + self.decode_tree(input, base + LEN_MID + (pos_state << 3), 3)? as usize);
+        }
+        Ok(16 + self.decode_tree(input, base + LEN_HIGH, 8)? as usize)
+    }
+    fn decode_distance(&mut self, input: &mut InputBuffer, len: usize) -> DecompressResult<u32> {
+        let len_state = len.min(NUM_LEN_TO_POS - 1);
+        let pos_slot = self.decode_tree(input, POS_SLOT + (len_state << 6), 6)? as usize;
+        if pos_slot < 4 {
+            return Ok(pos_slot as u32);
+        }
+        let nbits = (pos_slot >> 1) - 1;
+        let mut dist = ((2 | (pos_slot & 1)) << nbits) as u32;
+        if pos_slot < END_POS_MODEL_INDEX {
+            dist += self.decode_reverse_tree(input, SPEC_POS + (dist as usize) - pos_slot, nbits)?;
+        } else {
+            dist += self.decode_direct_bits(input, nbits - NUM_ALIGN_BITS)? << NUM_ALIGN_BITS;
+            dist += self.decode_reverse_tree(input, ALIGN, NUM_ALIGN_BITS)?;
+        }
+        Ok(dist)
+    }
+    fn decode_literal(&mut self, input: &mut InputBuffer, hist: &mut History) -> DecompressResult<()> {
+        let prev_byte = if !hist.is_empty() { usize::from(hist.get_byte(1)) } else { 0 };
+        let lit_state = (((self.st.total_pos as usize) & ((1 << self.lp) - 1)) << self.lc) + (prev_byte >> (8 - self.lc));
+        let base = LITERAL + 0x300 * lit_state;
+        let mut sym = 1;
+        if self.st.state >= 7 {
+            let mut match_byte = usize::from(hist.get_byte(self.st.reps[0] as usize + 1));
+            while sym < 0x100 {
+                let match_bit = (match_byte >> 7) & 1;
+                match_byte <<= 1;
+                let bit = self.decode_bit(input, base + ((1 + match_bit) << 8) + sym)? as usize;
+                sym = (sym << 1) | bit;
+                if match_bit != bit {
+                    break;
+                }
+            }
+        }
+        while sym < 0x100 {
+            sym = (sym << 1) | (self.decode_bit(input, base + sym)? as usize);
+        }
+        hist.push((sym - 0x100) as u8);
+        Ok(())
+    }
+    fn init_rc(&mut self, input: &mut InputBuffer) -> DecompressResult<()> {
+        let buf = input.read_buf(5)?;
+        if buf[0] != 0 {
+            return Err(DecompressError::InvalidData);
+        }
+        self.st.rc.range = 0xFFFF_FFFF;
+        self.st.rc.code  = (u32::from(buf[1]) << 24) | (u32::from(buf[2]) << 16) | (u32::from(buf[3]) << 8) | u32::from(buf[4]);
+        if self.st.rc.code == self.st.rc.range {
+            return Err(DecompressError::InvalidData);
+        }
+        self.started = true;
+        Ok(())
+    }
+    // Decodes single literal or match, returns true when the end of stream is reached.
+    fn decode_symbol(&mut self, input: &mut InputBuffer, hist: &mut History) -> DecompressResult<bool> {
+        if self.st.unp_left == Some(0) && self.st.rc.code == 0 {
+            return Ok(true);
+        }
+        let pos_state = (self.st.total_pos as usize) & ((1 << self.pb) - 1);
+        let state = self.st.state;
+        if self.decode_bit(input, IS_MATCH + (state << MAX_POS_BITS) + pos_state)? == 0 {
+            if self.st.unp_left == Some(0) {
+                return Err(DecompressError::InvalidData);
+            }
+            self.decode_literal(input, hist)?;
+            self.st.state = match state {
+                    0..=3 => 0,
+                    4..=9 => state - 3,
+                    _     => state - 6,
+                };
+            self.advance(1);
+            return Ok(false);
+        }
+        let len;
+        if self.decode_bit(input, IS_REP + state)? != 0 {
+            if self.st.unp_left == Some(0) || hist.is_empty() {
+                return Err(DecompressError::InvalidData);
+            }
+            if self.decode_bit(input, IS_REP_G0 + state)? == 0 {
+                if self.decode_bit(input, IS_REP0_LONG + (state << MAX_POS_BITS) + pos_state)? == 0 {
+                    self.st.state = if state < 7 { 9 } else { 11 };
+                    let b = hist.get_byte(self.st.reps[0] as usize + 1);
+                    hist.push(b);
+                    self.advance(1);
+                    return Ok(false);
+                }
+            } else {
+                let dist;
+                if self.decode_bit(input, IS_REP_G1 + state)? == 0 {
+                    dist = self.st.reps[1];
+                } else {
+                    if self.decode_bit(input, IS_REP_G2 + state)? == 0 {
+                        dist = self.st.reps[2];
+                    } else {
+                        dist = self.st.reps[3];
+                        self.st.reps[3] = self.st.reps[2];
+                    }
+                    self.st.reps[2] = self.st.reps[1];
+                }
+                self.st.reps[1] = self.st.reps[0];
+                self.st.reps[0] = dist;
+            }
+            len = self.decode_len(input, REP_LEN_CODER, pos_state)?;
+            self.st.state = if state < 7 { 8 } else { 11 };
+        } else {
+            self.st.reps[3] = self.st.reps[2];
+            self.st.reps[2] = self.st.reps[1];
+            self.st.reps[1] = self.st.reps[0];
+            len = self.decode_len(input, LEN_CODER, pos_state)?;
+            self.st.state = if state < 7 { 7 } else { 10 };
+            self.st.reps[0] = self.decode_distance(input, len)?;
+            if self.st.reps[0] == 0xFFFF_FFFF {
+                return if self.st.rc.code == 0 { Ok(true) } else { Err(DecompressError::InvalidData) };
+            }
+            if self.st.unp_left == Some(0) || self.st.reps[0] >= self.dict_size {
+                return Err(DecompressError::InvalidData);
+            }
+        }
+        let len = len + MATCH_MIN_LEN;
+        if let Some(left) = self.st.unp_left {
+            if left < (len as u64) {
+                return Err(DecompressError::InvalidData);
+            }
+        }
+        hist.copy_match(self.st.reps[0] as usize + 1, len)?;
+        self.advance(len);
+        Ok(false)
+    }
+    fn advance(&mut self, len: usize) {
+        self.st.total_pos += len as u64;
+        if let Some(ref mut left) = self.st.unp_left {
+            *left -= len as u64;
+        }
+    }
+    fn decode(&mut self, input: &mut InputBuffer, hist: &mut History, want: usize) -> DecompressResult<bool> {
+        if self.ended {
+            return Ok(true);
+        }
+        if !self.started {
+            self.init_rc(input)?;
+        }
+        while hist.pending() < want {
+            // if there is not enough input for any symbol the changes should be undone in case the input ends prematurely
+            self.logging = input.left() < MAX_SYMBOL_INPUT;
+            self.undo.clear();
+            let saved = self.st;
+            let pos = input.tell();
+            let hlen = hist.len();
+            match self.decode_symbol(input, hist) {
+                Ok(true) => {
+                    self.ended = true;
+                    return Ok(true);
+                },
+                Ok(false) => {},
+                Err(DecompressError::ShortData) => {
+                    for &(idx, prob) in self.undo.iter().rev() {
+                        self.probs[idx as usize] = prob;
+                    }
+                    self.st = saved;
+                    input.restore(pos);
+                    hist.truncate(hlen);
+                    return Err(DecompressError::ShortData);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// LZMA stream decompressor.
+pub struct LZMADecoder {
+    stream:     StreamState,
+    lzma:       Option<LZMAState>,
+    unp_size:   Option<u64>,
+    raw:        bool,
+}
+
+impl LZMADecoder {
+    /// Creates a new instance of `LZMADecoder` for decoding data in `.lzma` file format.
+    ///
+    /// Stream parameters are read from the header in the beginning of the input.
+    pub fn new() -> Self {
+        Self {
+            stream:     StreamState::new(MIN_DICT_SIZE as usize),
+            lzma:       None,
+            unp_size:   None,
+            raw:        false,
+        }
+    }
+    /// Creates a new instance of `LZMADecoder` for decoding raw LZMA stream with the provided parameters.
+    ///
+    /// `props` is the standard LZMA properties byte (`(pb * 5 + lp) * 9 + lc`). Unpacked size may be omitted if the stream has an end marker.
+    pub fn new_raw(props: u8, dict_size: u32, unp_size: Option<u64>) -> DecompressResult<Self> {
+        let lzma = LZMAState::new(props, dict_size, unp_size)?;
+        Ok(Self {
+            stream:     StreamState::new(lzma.dict_size as usize),
+            lzma:       Some(lzma),
+            unp_size,
+            raw:        true,
+        })
+    }
+    /// Reports whether the stream was decoded completely.
+    pub fn is_finished(&self) -> bool { self.stream.is_finished() }
+    /// Reports the current amount of bytes output into the destination buffer after the last run.
+    pub fn get_current_output_size(&self) -> usize { self.stream.get_current_output_size() }
+    /// Reports the unpacked data size (if known).
+    pub fn get_unpacked_size(&self) -> Option<u64> { self.unp_size }
+    /// Tries to decompress input data and write it to the output buffer.
+    ///
+    /// The semantics are the same as in [`Inflate::decompress_data`]:
+    /// `ShortData` error means that all input was consumed and more is required (output is accumulated in the same buffer),
+    /// `OutputFull` means that the output buffer is full and the function should be called again with `continue_block` set to `true`
+    /// (the input provided in that call is ignored).
+    ///
+    /// [`Inflate::decompress_data`]: ../deflate/struct.Inflate.html#method.decompress_data
+    pub fn decompress_data(&mut self, src: &[u8], dst: &mut [u8], continue_block: bool) -> DecompressResult<usize> {
+        let lzma = &mut self.lzma;
+        let unp_size = &mut self.unp_size;
+        self.stream.decompress(src, dst, continue_block, |input, hist, want| {
+                if lzma.is_none() {
+                    let hdr = input.peek_buf(HEADER_SIZE)?;
+                    let dict_size = u32::from(hdr[1]) | (u32::from(hdr[2]) << 8) | (u32::from(hdr[3]) << 16) | (u32::from(hdr[4]) << 24);
+                    let mut size = 0;
+                    for &b in hdr[5..HEADER_SIZE].iter().rev() {
+                        size = (size << 8) | u64::from(b);
+                    }
+                    *unp_size = if size != u64::MAX { Some(size) } else { None };
+                    let state = LZMAState::new(hdr[0], dict_size, *unp_size)?;
+                    hist.set_max_dist(state.dict_size as usize);
+                    input.read_buf(HEADER_SIZE)?;
+                    *lzma = Some(state);
+                }
+                if let Some(ref mut state) = lzma {
+                    state.decode(input, hist, want)
+                } else {
+                    unreachable!()
+                }
+            })
+    }
+    /// Resets decoder state.
+    pub fn reset(&mut self) {
+        self.stream.reset();
+        if self.raw {
+            if let Some(ref mut lzma) = self.lzma {
+                lzma.reset(self.unp_size);
+            }
+        } else {
+            self.lzma = None;
+            self.unp_size = None;
+        }
+    }
+    /// Decompresses input `.lzma` stream into the provided buffer.
+    pub fn uncompress(src: &[u8], dst: &mut [u8]) -> DecompressResult<usize> {
+        let mut decoder = Self::new();
+        decoder.decompress_data(src, dst, false)
+    }
+}
+
+impl Default for LZMADecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XMAS_LEN: usize = 603;
+    const SRC: &[u8] = &[
+        0x5D, 0x00, 0x00, 0x01, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x2A, 0x1A,
+        0x08, 0xA2, 0x02, 0xCA, 0x86, 0x10, 0x36, 0x16, 0x3F, 0x07, 0x6D, 0xF5, 0xD8, 0x94, 0x22, 0xA3,
+        0x89, 0xF9, 0xAC, 0x23, 0x04, 0xBC, 0xE7, 0x1E, 0x31, 0x92, 0x01, 0x9D, 0x6F, 0x7D, 0xF5, 0xCE,
+        0x39, 0x7D, 0xC5, 0x88, 0x6E, 0xCF, 0xC8, 0xEF, 0xEF, 0xB8, 0xFF, 0x6B, 0x05, 0xB5, 0x6F, 0x8B,
+        0xDD, 0x77, 0x2D, 0xE8, 0x40, 0x74, 0x6B, 0x10, 0x66, 0xD8, 0xB2, 0xB4, 0x07, 0xCC, 0xCD, 0xE7,
+        0xC4, 0x66, 0xFB, 0x63, 0x76, 0x46, 0x03, 0xDF, 0xD2, 0xD2, 0xB3, 0xD5, 0x05, 0xE1, 0xEF, 0x7D,
+        0x08, 0xAC, 0x81, 0x2B, 0x03, 0xBA, 0xBF, 0x3F, 0x13, 0x57, 0x08, 0xA5, 0x4C, 0x1D, 0x22, 0x61,
+        0xC4, 0x04, 0xF0, 0xEF, 0x06, 0xD5, 0x31, 0x78, 0x68, 0x9A, 0xFB, 0x43, 0xD9, 0x60, 0xFA, 0xE7,
+        0x77, 0x4E, 0x64, 0x5F, 0x63, 0xDD, 0x2F, 0x7B, 0x4B, 0xB3, 0xF0, 0x91, 0x5E, 0xAC, 0x5E, 0x6B,
+        0xE9, 0xC2, 0x82, 0x98, 0xA3, 0xA6, 0xB8, 0x93, 0xD0, 0x0E, 0x90, 0x00, 0x26, 0x4E, 0x9A, 0x4A,
+        0xB8, 0x7A, 0x34, 0x3D, 0x98, 0x68, 0x90, 0xA6, 0xE7, 0x98, 0x19, 0xDA, 0xD5, 0x65, 0x8C, 0xFE,
+        0x38, 0x7B, 0x8C, 0x95, 0x34, 0xAA, 0xE5, 0xD9, 0x9A, 0xC5, 0x1F, 0xC2, 0xC5, 0xE6, 0x5A, 0xFF,
+        0xDB, 0xC5, 0xE1, 0x40,
+    ];
+
+    #[test]
+    fn test_lzma() {
+        let mut dst = [0u8; XMAS_LEN];
+        let len = LZMADecoder::uncompress(SRC, &mut dst).unwrap();
+        assert_eq!(len, XMAS_LEN);
+        assert_eq!(&dst[..27], b"The first day of Christmas,");
+        assert_eq!(&dst[XMAS_LEN - 27..], b"A partridge in a pear tree.");
+
+        let mut dst2 = [0u8; XMAS_LEN];
+        let mut decoder = LZMADecoder::new_raw(SRC[0], 65536, Some(XMAS_LEN as u64)).unwrap();
+        let len = decoder.decompress_data(&SRC[HEADER_SIZE..], &mut dst2, false).unwrap();
+        assert_eq!(len, XMAS_LEN);
+        assert_eq!(&dst[..], &dst2[..]);
+    }
+    #[test]
+    fn test_lzma_stream() {
+        let mut dst = [0u8; XMAS_LEN];
+        LZMADecoder::uncompress(SRC, &mut dst).unwrap();
+
+        for &(in_size, out_size) in [(1, 100), (5, 13), (64, 7)].iter() {
+            let mut decoder = LZMADecoder::new();
+            let mut out = Vec::new();
+            let mut chunk = vec![0u8; out_size];
+            let mut finished = false;
+            for src in SRC.chunks(in_size) {
+                let mut repeat = false;
+                loop {
+                    match decoder.decompress_data(src, &mut chunk, repeat) {
+                        Ok(len) => {
+                            out.extend_from_slice(&chunk[..len]);
+                            finished = true;
+                            break;
+                        },
+                        Err(DecompressError::ShortData) => break,
+                        Err(DecompressError::OutputFull) => {
+                            repeat = true;
+                            out.extend_from_slice(&chunk);
+                        },
+                        Err(err) => panic!("decoding error {:?}", err),
+                    }
+                }
+                if finished {
+                    break;
+                }
+            }
+            assert!(finished);
+            assert_eq!(out.as_slice(), &dst[..]);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! LZO format support.
+//!
+//! This module provides functionality for decompressing raw LZO1X streams (which also covers LZO1X-999 output) via [`LZODecoder`].
+//!
+//! [`LZODecoder`]: ./struct.LZODecoder.html
+//!
+//! # Examples
+//!
+//! Decompressing full input buffer into sufficiently large output buffer:
+//! ```
+//! # use nihav_core::compr::DecompressError;
+//! use nihav_core::compr::lzo::LZODecoder;
+//!
+//! # fn decompress(input: &[u8]) -> Result<(), DecompressError> {
+//! # let mut output_buffer = [0u8; 16];
+//! let output_length = LZODecoder::uncompress(input, &mut output_buffer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::*;
+use super::stream::*;
+
+const MAX_DIST: usize = 0xBFFF;
+
+fn read_zero_ext(input: &mut InputBuffer) -> DecompressResult<usize> {
+    let mut len = 0;
+    loop {
+        let b = input.read_byte()?;
+        if b != 0 {
+            return Ok(len + usize::from(b));
+        }
+        len += 255;
+    }
+}
+
+#[derive(Clone,Copy)]
+struct OpState {
+    state:      usize,
+    started:    bool,
+    ended:      bool,
+}
+
+impl OpState {
+    fn new() -> Self {
+        Self { state: 0, started: false, ended: false }
+    }
+    fn decode_op(&mut self, input: &mut InputBuffer, hist: &mut History) -> DecompressResult<()> {
+        if !self.started {
+            self.started = true;
+            let b = usize::from(input.peek_buf(1)?[0]);
+            if b > 17 {
+                input.read_byte()?;
+                let nlits = b - 17;
+                hist.push_slice(input.read_buf(nlits)?);
+                self.state = nlits.min(4);
+                return Ok(());
+            }
+        }
+        let t = usize::from(input.read_byte()?);
+        let (dist, len, next) = if t < 16 {
+                match self.state {
+                    0 => {
+                        let nlits = if t == 0 { 15 + read_zero_ext(input)? } else { t } + 3;
+                        hist.push_slice(input.read_buf(nlits)?);
+                        self.state = 4;
+                        return Ok(());
+                    },
+                    4 => {
+                        let b = usize::from(input.read_byte()?);
+                        (1 + 0x800 + (t >> 2) + (b << 2), 3, t & 3)
+                    },
+                    _ => {
+                        let b = usize::from(input.read_byte()?);
+                        (1 + (t >> 2) + (b << 2), 2, t & 3)
+                    },
+                }
+            } else if t >= 64 {
+                let b = usize::from(input.read_byte()?);
+                (1 + ((t >> 2) & 7) + (b << 3), (t >> 5) + 1, t & 3)
+            } else if t >= 32 {
+                let len = if (t & 31) == 0 { 31 + read_zero_ext(input)? } else { t & 31 };
+                let word = usize::from(input.read_u16le()?);
+                (1 + (word >> 2), len + 2, word & 3)
+            } else {
+                let len = if (t & 7) == 0 { 7 + read_zero_ext(input)? } else { t & 7 };
+                let word = usize::from(input.read_u16le()?);
+                let dist = ((t & 8) << 11) + (word >> 2);
+                if dist == 0 {
+                    self.ended = true;
+                    return Ok(());
+                }
+                (dist + 0x4000, len + 2, word & 3)
+            };
+        hist.copy_match(dist, len)?;
+        hist.push_slice(input.read_buf(next)?);
+        self.state = next;
+        Ok(())
+    }
+    fn decode(&mut self, input: &mut InputBuffer, hist: &mut History, want: usize) -> DecompressResult<bool> {
+        while hist.pending() < want && !self.ended {
+            let pos = input.tell();
+            let hlen = hist.len();
+            let saved = *self;
+            if let Err(err) = self.decode_op(input, hist) {
+                input.restore(pos);
+                hist.truncate(hlen);
+                *self = saved;
+                return Err(err);
+            }
+        }
+        Ok(self.ended)
+    }
+}
+
+/// LZO1X stream decompressor.
+pub struct LZODecoder {
+    stream: StreamState,
+    op:     OpState,
+}
+
+impl LZODecoder {
+    /// Creates a new instance of `LZODecoder`.
+    pub fn new() -> Self {
+        Self {
+            stream: StreamState::new(MAX_DIST),
+            op:     OpState::new(),
+        }
+    }
+    /// Reports whether the stream end marker was decoded and all data was output.
+    pub fn is_finished(&self) -> bool { self.stream.is_finished() }
+    /// Reports the current amount of bytes output into the destination buffer after the last run.
+    pub fn get_current_output_size(&self) -> usize { self.stream.get_current_output_size() }
+    /// Tries to decompress input data and write it to the output buffer.
+    ///
+    /// The semantics are the same as in [`Inflate::decompress_data`]:
+    /// `ShortData` error means that all input was consumed and more is required (output is accumulated in the same buffer),
+    /// `OutputFull` means that the output buffer is full and the function should be called again with `continue_block` set to `true`
+    /// (the input provided in that call is ignored).
+    ///
+    /// [`Inflate::decompress_data`]: ../deflate/struct.Inflate.html#method.decompress_data
+    pub fn decompress_data(&mut self, src: &[u8], dst: &mut [u8], continue_block: bool) -> DecompressResult<usize> {
+        let op = &mut self.op;
+        self.stream.decompress(src, dst, continue_block, |input, hist, want| op.decode(input, hist, want))
+    }
+    /// Resets decoder state.
+    pub fn reset(&mut self) {
+        self.stream.reset();
+        self.op = OpState::new();
+    }
+    /// Decompresses input LZO1X stream into the provided buffer.
+    pub fn uncompress(src: &[u8], dst: &mut [u8]) -> DecompressResult<usize> {
+        let mut decoder = Self::new();
+        decoder.decompress_data(src, dst, false)
+    }
+}
+
+impl Default for LZODecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SRC: &[u8] = &[
+        0x14, b'a', b'b', b'c',         // three literals
+        0xE8, 0x00,                     // M2 match, distance 3, length 8
+        0x27, 0x08, 0x00,               // M3 match, distance 3, length 9
+        0x02, b'h', b'e', b'l', b'l', b'o', // literal run
+        0x0D, 0x00,                     // M1 match after literal run, distance 2052, length 3
+        0x11, 0x00, 0x00                // end of stream
+    ];
+
+    #[test]
+    fn test_lzo_invalid_dist() {
+        let mut dst = [0u8; 64];
+        assert!(LZODecoder::uncompress(SRC, &mut dst).is_err());
+    }
+    #[test]
+    fn test_lzo() {
+        const DATA: &[u8] = &[
+            0x14, b'a', b'b', b'c',
+            0xE8, 0x00,
+            0x27, 0x08, 0x00,
+            0x02, b'h', b'e', b'l', b'l', b'o',
+            0x11, 0x00, 0x00
+        ];
+        const REF: &[u8] = b"abcabcabcabcabcabcabhello";
+        let mut dst = [0u8; 64];
+        let len = LZODecoder::uncompress(DATA, &mut dst).unwrap();
+        assert_eq!(&dst[..len], REF);
+
+        let mut decoder = LZODecoder::new();
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 4];
+        let mut finished = false;
+        for src in DATA.chunks(1) {
+            let mut repeat = false;
+            loop {
+                match decoder.decompress_data(src, &mut chunk, repeat) {
+                    Ok(len) => {
+                        out.extend_from_slice(&chunk[..len]);
+                        finished = true;
+                        break;
+                    },
+                    Err(DecompressError::ShortData) => break,
+                    Err(DecompressError::OutputFull) => {
+                        repeat = true;
+                        out.extend_from_slice(&chunk);
+                    },
+                    Err(err) => panic!("decoding error {:?}", err),
+                }
+            }
+        }
+        assert!(finished);
+        assert_eq!(out.as_slice(), REF);
+    }
+}
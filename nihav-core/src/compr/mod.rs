@@ -1,6 +1,14 @@
 //! Various compression formats support.
 #[cfg(feature="deflate")]
 pub mod deflate;
+#[cfg(feature="lz4")]
+pub mod lz4;
+#[cfg(feature="lzma")]
+pub mod lzma;
+#[cfg(feature="lzo")]
+pub mod lzo;
+#[cfg(any(feature="lz4", feature="lzma", feature="lzo"))]
+mod stream;
 
 use crate::io::byteio::ByteIOError;
 use crate::io::bitreader::BitReaderError;
@@ -0,0 +1,181 @@
+//! Common code for streaming LZ-family decompressors.
+use super::*;
+
+/// Input data accumulated from several calls.
+pub(crate) struct InputBuffer {
+    buf:    Vec<u8>,
+    pos:    usize,
+}
+
+impl InputBuffer {
+    pub fn new() -> Self {
+        Self { buf: Vec::new(), pos: 0 }
+    }
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.pos = 0;
+    }
+    pub fn append(&mut self, src: &[u8]) {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(src);
+    }
+    pub fn left(&self) -> usize { self.buf.len() - self.pos }
+    pub fn tell(&self) -> usize { self.pos }
+    pub fn restore(&mut self, pos: usize) { self.pos = pos; }
+    pub fn read_byte(&mut self) -> DecompressResult<u8> {
+        if self.pos < self.buf.len() {
+            self.pos += 1;
+            Ok(self.buf[self.pos - 1])
+        } else {
+            Err(DecompressError::ShortData)
+        }
+    }
+    pub fn read_u16le(&mut self) -> DecompressResult<u16> {
+        let buf = self.read_buf(2)?;
+        Ok(u16::from(buf[0]) | (u16::from(buf[1]) << 8))
+    }
+    pub fn read_u32le(&mut self) -> DecompressResult<u32> {
+        let buf = self.read_buf(4)?;
+        Ok(u32::from(buf[0]) | (u32::from(buf[1]) << 8) | (u32::from(buf[2]) << 16) | (u32::from(buf[3]) << 24))
+    }
+    pub fn peek_buf(&self, len: usize) -> DecompressResult<&[u8]> {
+        if self.left() >= len {
+            Ok(&self.buf[self.pos..][..len])
+        } else {
+            Err(DecompressError::ShortData)
+        }
+    }
+    pub fn read_buf(&mut self, len: usize) -> DecompressResult<&[u8]> {
+        if self.left() >= len {
+            self.pos += len;
+            Ok(&self.buf[self.pos - len..][..len])
+        } else {
+            Err(DecompressError::ShortData)
+        }
+    }
+}
+
+/// Decoded data that has not been sent to the caller yet together with the previous data used for back-references.
+pub(crate) struct History {
+    buf:        Vec<u8>,
+    out_pos:    usize,
+    max_dist:   usize,
+}
+
+impl History {
+    pub fn new(max_dist: usize) -> Self {
+        Self { buf: Vec::new(), out_pos: 0, max_dist }
+    }
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.out_pos = 0;
+    }
+    pub fn set_max_dist(&mut self, max_dist: usize) { self.max_dist = max_dist; }
+    /// Returns the number of bytes available for back-references.
+    pub fn len(&self) -> usize { self.buf.len() }
+    pub fn is_empty(&self) -> bool { self.buf.is_empty() }
+    pub fn truncate(&mut self, len: usize) {
+        debug_assert!(len >= self.out_pos);
+        self.buf.truncate(len);
+    }
+    /// Returns the number of decoded bytes not yet sent to the caller.
+    pub fn pending(&self) -> usize { self.buf.len() - self.out_pos }
+    pub fn push(&mut self, b: u8) { self.buf.push(b); }
+    pub fn push_slice(&mut self, src: &[u8]) { self.buf.extend_from_slice(src); }
+    /// Returns byte at the provided distance back from the current position.
+    pub fn get_byte(&self, dist: usize) -> u8 { self.buf[self.buf.len() - dist] }
+    pub fn copy_match(&mut self, dist: usize, len: usize) -> DecompressResult<()> {
+        if dist == 0 || dist > self.buf.len() || dist > self.max_dist {
+            return Err(DecompressError::InvalidData);
+        }
+        let start = self.buf.len() - dist;
+        if dist >= len {
+            self.buf.extend_from_within(start..start + len);
+        } else {
+            self.buf.reserve(len);
+            for i in 0..len {
+                let b = self.buf[start + i];
+                self.buf.push(b);
+            }
+        }
+        Ok(())
+    }
+    /// Returns the last decoded bytes.
+    pub fn get_last(&self, len: usize) -> &[u8] { &self.buf[self.buf.len() - len..] }
+    /// Copies pending data to the output and discards the data that cannot be referenced any longer.
+    pub fn deliver(&mut self, dst: &mut [u8]) -> usize {
+        let len = self.pending().min(dst.len());
+        dst[..len].copy_from_slice(&self.buf[self.out_pos..][..len]);
+        self.out_pos += len;
+        if self.out_pos > self.max_dist * 2 {
+            let skip = self.out_pos - self.max_dist;
+            self.buf.drain(..skip);
+            self.out_pos -= skip;
+        }
+        len
+    }
+}
+
+/// Common state for streaming decompression.
+pub(crate) struct StreamState {
+    pub input:      InputBuffer,
+    pub hist:       History,
+    output_idx:     usize,
+    finished:       bool,
+}
+
+impl StreamState {
+    pub fn new(max_dist: usize) -> Self {
+        Self {
+            input:      InputBuffer::new(),
+            hist:       History::new(max_dist),
+            output_idx: 0,
+            finished:   false,
+        }
+    }
+    pub fn reset(&mut self) {
+        self.input.reset();
+        self.hist.reset();
+        self.output_idx = 0;
+        self.finished = false;
+    }
+    pub fn is_finished(&self) -> bool { self.finished && self.hist.pending() == 0 }
+    pub fn get_current_output_size(&self) -> usize { self.output_idx }
+    /// Runs decompression loop.
+    ///
+    /// Provided function should decode data until at least the requested amount of data is pending in the history or the stream ends.
+    /// It should return `true` when the end of stream is reached and `ShortData` error if more input is required (after restoring the state to the point it can be resumed from).
+    pub fn decompress<F>(&mut self, src: &[u8], dst: &mut [u8], continue_block: bool, mut decode: F) -> DecompressResult<usize>
+            where F: FnMut(&mut InputBuffer, &mut History, usize) -> DecompressResult<bool> {
+        if continue_block {
+            self.output_idx = 0;
+        } else {
+            self.input.append(src);
+        }
+        loop {
+            self.output_idx += self.hist.deliver(&mut dst[self.output_idx..]);
+            if self.hist.pending() > 0 {
+                return Err(DecompressError::OutputFull);
+            }
+            if self.finished {
+                return Ok(self.output_idx);
+            }
+            let want = (dst.len() - self.output_idx).max(1);
+            match decode(&mut self.input, &mut self.hist, want) {
+                Ok(true) => self.finished = true,
+                Ok(false) => {},
+                Err(DecompressError::ShortData) => {
+                    self.output_idx += self.hist.deliver(&mut dst[self.output_idx..]);
+                    if self.hist.pending() > 0 {
+                        return Err(DecompressError::OutputFull);
+                    }
+                    return Err(DecompressError::ShortData);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
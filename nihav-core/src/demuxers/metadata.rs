@@ -0,0 +1,416 @@
+//! Container and stream metadata.
+//!
+//! Metadata is stored as a list of key-value pairs. Containers use different names for the same information so demuxers should map known names to the common keys defined here (e.g. [`METADATA_TITLE`]) and keep unknown names as they are.
+//! The same key may be present several times (e.g. when a track has several artists).
+//!
+//! [`METADATA_TITLE`]: ./constant.METADATA_TITLE.html
+use std::fmt;
+
+/// Common key for title.
+pub const METADATA_TITLE: &str = "title";
+/// Common key for artist or author.
+pub const METADATA_ARTIST: &str = "artist";
+/// Common key for album name.
+pub const METADATA_ALBUM: &str = "album";
+/// Common key for comment.
+pub const METADATA_COMMENT: &str = "comment";
+/// Common key for copyright information.
+pub const METADATA_COPYRIGHT: &str = "copyright";
+/// Common key for creation date or year.
+pub const METADATA_DATE: &str = "date";
+/// Common key for genre.
+pub const METADATA_GENRE: &str = "genre";
+/// Common key for track number.
+pub const METADATA_TRACK: &str = "track";
+/// Common key for the name of the software used to create the file.
+pub const METADATA_ENCODER: &str = "encoder";
+/// Common key for language.
+pub const METADATA_LANGUAGE: &str = "language";
+/// Common key for cover art picture.
+pub const METADATA_COVER_ART: &str = "cover_art";
+
+/// Picture stored in metadata (e.g. cover art).
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct MetadataPicture {
+    /// Picture MIME type (e.g. `image/jpeg`), empty if unknown.
+    pub mime:   String,
+    /// Picture description.
+    pub desc:   String,
+    /// Picture data.
+    pub data:   Vec<u8>,
+}
+
+/// Metadata value.
+#[derive(Clone,Debug,PartialEq)]
+pub enum MetadataValue {
+    /// Text string.
+    Text(String),
+    /// Picture.
+    Picture(MetadataPicture),
+    /// Binary data of unspecified kind.
+    Binary(Vec<u8>),
+}
+
+impl MetadataValue {
+    /// Returns text if the value is a text string.
+    pub fn as_text(&self) -> Option<&str> {
+        if let MetadataValue::Text(ref s) = *self {
+            Some(s.as_str())
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetadataValue::Text(ref s) => write!(f, "{}", s),
+            MetadataValue::Picture(ref pic) => write!(f, "<picture {} {} bytes>", pic.mime, pic.data.len()),
+            MetadataValue::Binary(ref data) => write!(f, "<{} bytes>", data.len()),
+        }
+    }
+}
+
+/// List of metadata entries.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Metadata {
+    entries:    Vec<(String, MetadataValue)>,
+}
+
+impl Metadata {
+    /// Constructs a new empty `Metadata` instance.
+    pub fn new() -> Self { Self::default() }
+    /// Reports whether there are no entries.
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize { self.entries.len() }
+    /// Adds a new entry (previous entries with the same key are retained).
+    pub fn add(&mut self, key: &str, value: MetadataValue) {
+        self.entries.push((key.to_string(), value));
+    }
+    /// Adds a new text entry.
+    ///
+    /// Empty strings are ignored.
+    pub fn add_text(&mut self, key: &str, text: &str) {
+        if !text.is_empty() {
+            self.add(key, MetadataValue::Text(text.to_string()));
+        }
+    }
+    /// Replaces all entries with the given key with the new value.
+    pub fn set(&mut self, key: &str, value: MetadataValue) {
+        self.remove(key);
+        self.add(key, value);
+    }
+    /// Removes all entries with the given key.
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+    /// Returns the first value for the given key.
+    ///
+    /// Keys are compared case-insensitively.
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.entries.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+    }
+    /// Returns the first text value for the given key.
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        self.entries.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).find_map(|(_, v)| v.as_text())
+    }
+    /// Returns an iterator over all values for the given key.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a MetadataValue> + 'a {
+        self.entries.iter().filter(move |(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+    }
+    /// Returns an iterator over all entries.
+    pub fn iter(&self) -> std::slice::Iter<'_, (String, MetadataValue)> {
+        self.entries.iter()
+    }
+    /// Adds Vorbis comment-style entry (`NAME=value`) mapping known names to the common keys.
+    ///
+    /// Returns `false` if the entry does not conform to the format.
+    pub fn add_vorbis_comment(&mut self, comment: &str) -> bool {
+        if let Some(pos) = comment.find('=') {
+            let (name, value) = comment.split_at(pos);
+            if name.is_empty() {
+                return false;
+            }
+            let key = match name.to_ascii_uppercase().as_str() {
+                    "TITLE"         => METADATA_TITLE,
+                    "ARTIST"        => METADATA_ARTIST,
+                    "ALBUM"         => METADATA_ALBUM,
+                    "COMMENT" | "DESCRIPTION" => METADATA_COMMENT,
+                    "COPYRIGHT"     => METADATA_COPYRIGHT,
+                    "DATE" | "YEAR" => METADATA_DATE,
+                    "GENRE"         => METADATA_GENRE,
+                    "TRACKNUMBER" | "TRACK" => METADATA_TRACK,
+                    "ENCODER"       => METADATA_ENCODER,
+                    "LANGUAGE"      => METADATA_LANGUAGE,
+                    _ => name,
+                };
+            self.add_text(key, &value[1..]);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Chapter information.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Chapter {
+    /// Chapter start time in milliseconds.
+    pub start:  u64,
+    /// Chapter end time in milliseconds.
+    pub end:    u64,
+    /// Chapter title.
+    pub title:  String,
+}
+
+/// Metadata for the whole container and its individual streams.
+#[derive(Clone,Debug,Default)]
+pub struct ContainerMetadata {
+    global:     Metadata,
+    streams:    Vec<(u32, Metadata)>,
+    chapters:   Vec<Chapter>,
+}
+
+impl ContainerMetadata {
+    /// Constructs a new empty `ContainerMetadata` instance.
+    pub fn new() -> Self { Self::default() }
+    /// Reports whether no metadata is present.
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty() && self.streams.iter().all(|(_, meta)| meta.is_empty()) && self.chapters.is_empty()
+    }
+    /// Returns container-level metadata.
+    pub fn get_global(&self) -> &Metadata { &self.global }
+    /// Returns mutable reference to container-level metadata.
+    pub fn get_global_mut(&mut self) -> &mut Metadata { &mut self.global }
+    /// Returns metadata for the stream with the provided ID.
+    pub fn get_stream(&self, id: u32) -> Option<&Metadata> {
+        self.streams.iter().find(|(sid, _)| *sid == id).map(|(_, meta)| meta)
+    }
+    /// Returns mutable reference to the metadata for the stream with the provided ID (creating it if needed).
+    pub fn get_stream_mut(&mut self, id: u32) -> &mut Metadata {
+        if let Some(pos) = self.streams.iter().position(|(sid, _)| *sid == id) {
+            &mut self.streams[pos].1
+        } else {
+            self.streams.push((id, Metadata::new()));
+            &mut self.streams.last_mut().unwrap().1
+        }
+    }
+    /// Returns an iterator over stream IDs and their metadata.
+    pub fn iter_streams(&self) -> std::slice::Iter<'_, (u32, Metadata)> {
+        self.streams.iter()
+    }
+    /// Adds a new chapter.
+    pub fn add_chapter(&mut self, chapter: Chapter) {
+        self.chapters.push(chapter);
+    }
+    /// Returns the list of chapters.
+    pub fn get_chapters(&self) -> &[Chapter] { &self.chapters }
+}
+
+/// Parses Vorbis comment block (as used in FLAC and Ogg formats) into metadata.
+///
+/// Vendor string is stored as the encoder name.
+pub fn parse_vorbis_comment(src: &[u8], meta: &mut Metadata) -> Option<()> {
+    fn read_u32le(src: &[u8], pos: &mut usize) -> Option<usize> {
+        let buf = src.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some((buf[0] as usize) | ((buf[1] as usize) << 8) | ((buf[2] as usize) << 16) | ((buf[3] as usize) << 24))
+    }
+    let mut pos = 0;
+    let vlen = read_u32le(src, &mut pos)?;
+    let vendor = src.get(pos..pos + vlen)?;
+    pos += vlen;
+    meta.add_text(METADATA_ENCODER, &String::from_utf8_lossy(vendor));
+    let count = read_u32le(src, &mut pos)?;
+    for _ in 0..count {
+        let len = read_u32le(src, &mut pos)?;
+        let comment = src.get(pos..pos + len)?;
+        pos += len;
+        meta.add_vorbis_comment(&String::from_utf8_lossy(comment));
+    }
+    Some(())
+}
+
+/// Creates Vorbis comment block from the metadata.
+///
+/// Text entries are stored using common names (e.g. `TITLE` for [`METADATA_TITLE`]), other entries are skipped.
+///
+/// [`METADATA_TITLE`]: ./constant.METADATA_TITLE.html
+pub fn create_vorbis_comment(meta: &Metadata, vendor: &str) -> Vec<u8> {
+    fn write_str(dst: &mut Vec<u8>, s: &str) {
+        dst.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        dst.extend_from_slice(s.as_bytes());
+    }
+    let mut comments = Vec::new();
+    let mut vendor = vendor;
+    for (key, value) in meta.iter() {
+        if let MetadataValue::Text(ref text) = *value {
+            let name = match key.as_str() {
+                    METADATA_TITLE      => "TITLE",
+                    METADATA_ARTIST     => "ARTIST",
+                    METADATA_ALBUM      => "ALBUM",
+                    METADATA_COMMENT    => "COMMENT",
+                    METADATA_COPYRIGHT  => "COPYRIGHT",
+                    METADATA_DATE       => "DATE",
+                    METADATA_GENRE      => "GENRE",
+                    METADATA_TRACK      => "TRACKNUMBER",
+                    METADATA_LANGUAGE   => "LANGUAGE",
+                    METADATA_ENCODER    => {
+                        vendor = text.as_str();
+                        continue;
+                    },
+                    _ => key.as_str(),
+                };
+            comments.push(format!("{}={}", name.to_ascii_uppercase(), text));
+        }
+    }
+    let mut dst = Vec::new();
+    write_str(&mut dst, vendor);
+    dst.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments.iter() {
+        write_str(&mut dst, comment);
+    }
+    dst
+}
+
+/// Parses FLAC `PICTURE` metadata block (also used in Vorbis comments as `METADATA_BLOCK_PICTURE`).
+///
+/// Returns picture type (e.g. 3 for front cover) and picture data.
+pub fn parse_flac_picture(src: &[u8]) -> Option<(u32, MetadataPicture)> {
+    fn read_u32be(src: &[u8], pos: &mut usize) -> Option<usize> {
+        let buf = src.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some(((buf[0] as usize) << 24) | ((buf[1] as usize) << 16) | ((buf[2] as usize) << 8) | (buf[3] as usize))
+    }
+    let mut pos = 0;
+    let ptype = read_u32be(src, &mut pos)? as u32;
+    let mime_len = read_u32be(src, &mut pos)?;
+    let mime = String::from_utf8_lossy(src.get(pos..pos + mime_len)?).to_string();
+    pos += mime_len;
+    let desc_len = read_u32be(src, &mut pos)?;
+    let desc = String::from_utf8_lossy(src.get(pos..pos + desc_len)?).to_string();
+    pos += desc_len;
+    pos += 16; // width, height, depth and number of colours
+    let data_len = read_u32be(src, &mut pos)?;
+    let data = src.get(pos..pos + data_len)?.to_vec();
+    Some((ptype, MetadataPicture { mime, desc, data }))
+}
+
+/// Creates FLAC `PICTURE` metadata block contents for the provided picture type.
+///
+/// Picture dimensions are not filled in.
+pub fn create_flac_picture(ptype: u32, pic: &MetadataPicture) -> Vec<u8> {
+    let mut dst = Vec::with_capacity(pic.data.len() + pic.mime.len() + pic.desc.len() + 32);
+    dst.extend_from_slice(&ptype.to_be_bytes());
+    dst.extend_from_slice(&(pic.mime.len() as u32).to_be_bytes());
+    dst.extend_from_slice(pic.mime.as_bytes());
+    dst.extend_from_slice(&(pic.desc.len() as u32).to_be_bytes());
+    dst.extend_from_slice(pic.desc.as_bytes());
+    dst.extend_from_slice(&[0; 16]);
+    dst.extend_from_slice(&(pic.data.len() as u32).to_be_bytes());
+    dst.extend_from_slice(&pic.data);
+    dst
+}
+
+const RIFF_INFO_TAGS: &[(&[u8; 4], &str)] = &[
+    (b"INAM", METADATA_TITLE),
+    (b"IART", METADATA_ARTIST),
+    (b"IPRD", METADATA_ALBUM),
+    (b"ICMT", METADATA_COMMENT),
+    (b"ICOP", METADATA_COPYRIGHT),
+    (b"ICRD", METADATA_DATE),
+    (b"IGNR", METADATA_GENRE),
+    (b"ITRK", METADATA_TRACK),
+    (b"IPRT", METADATA_TRACK),
+    (b"ISFT", METADATA_ENCODER),
+    (b"ILNG", METADATA_LANGUAGE),
+];
+
+/// Parses RIFF `INFO` list contents (without the list header) into metadata.
+///
+/// Unknown tags are stored under their four-character names.
+pub fn parse_riff_info(src: &[u8], meta: &mut Metadata) {
+    let mut pos = 0;
+    while pos + 8 <= src.len() {
+        let tag = &src[pos..][..4];
+        let size = (src[pos + 4] as usize) | ((src[pos + 5] as usize) << 8) | ((src[pos + 6] as usize) << 16) | ((src[pos + 7] as usize) << 24);
+        pos += 8;
+        if size > src.len() - pos {
+            break;
+        }
+        let text = String::from_utf8_lossy(&src[pos..][..size]);
+        let text = text.trim_end_matches('\0');
+        if let Some(&(_, key)) = RIFF_INFO_TAGS.iter().find(|(name, _)| &name[..] == tag) {
+            meta.add_text(key, text);
+        } else if tag.iter().all(|c| c.is_ascii_alphanumeric()) {
+            meta.add_text(&String::from_utf8_lossy(tag), text);
+        }
+        pos += size + (size & 1);
+    }
+}
+
+/// Creates RIFF `INFO` list contents (without the list header) from the metadata.
+///
+/// Only text entries with known keys or four-character uppercase names are stored. An empty vector is returned if there is nothing to store.
+pub fn create_riff_info(meta: &Metadata) -> Vec<u8> {
+    let mut dst = Vec::new();
+    for (key, value) in meta.iter() {
+        if let MetadataValue::Text(ref text) = *value {
+            let tag = if let Some(&(name, _)) = RIFF_INFO_TAGS.iter().find(|(_, k)| *k == key.as_str()) {
+                    *name
+                } else if key.len() == 4 && key.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+                    let mut name = [0; 4];
+                    name.copy_from_slice(key.as_bytes());
+                    name
+                } else {
+                    continue;
+                };
+            let size = text.len() + 1;
+            dst.extend_from_slice(&tag);
+            dst.extend_from_slice(&(size as u32).to_le_bytes());
+            dst.extend_from_slice(text.as_bytes());
+            dst.push(0);
+            if (size & 1) != 0 {
+                dst.push(0);
+            }
+        }
+    }
+    dst
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metadata() {
+        let mut meta = Metadata::new();
+        meta.add_text(METADATA_TITLE, "Some title");
+        meta.add_text(METADATA_ARTIST, "First");
+        meta.add_text(METADATA_ARTIST, "Second");
+        meta.add_text(METADATA_COMMENT, "");
+        assert_eq!(meta.len(), 3);
+        assert_eq!(meta.get_text("TITLE"), Some("Some title"));
+        assert_eq!(meta.get_all(METADATA_ARTIST).count(), 2);
+        meta.set(METADATA_ARTIST, MetadataValue::Text("Third".to_string()));
+        assert_eq!(meta.get_text(METADATA_ARTIST), Some("Third"));
+
+        let vc = create_vorbis_comment(&meta, "test");
+        let mut meta2 = Metadata::new();
+        parse_vorbis_comment(&vc, &mut meta2).unwrap();
+        assert_eq!(meta2.get_text(METADATA_ENCODER), Some("test"));
+        assert_eq!(meta2.get_text(METADATA_TITLE), Some("Some title"));
+        assert_eq!(meta2.get_text(METADATA_ARTIST), Some("Third"));
+
+        let info = create_riff_info(&meta);
+        let mut meta3 = Metadata::new();
+        parse_riff_info(&info, &mut meta3);
+        assert_eq!(meta3, meta);
+
+        let pic = MetadataPicture { mime: "image/png".to_string(), desc: "cover".to_string(), data: vec![1, 2, 3] };
+        let pblock = create_flac_picture(3, &pic);
+        assert_eq!(parse_flac_picture(&pblock), Some((3, pic)));
+    }
+}
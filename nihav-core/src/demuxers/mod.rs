@@ -3,6 +3,11 @@ pub use crate::frame::*;
 pub use crate::io::byteio::*;
 pub use crate::options::*;
 
+mod metadata;
+pub use self::metadata::*;
+mod resync;
+pub use self::resync::*;
+
 /// A list specifying general demuxing errors.
 #[derive(Debug,Clone,Copy,PartialEq)]
 #[allow(dead_code)]
@@ -40,6 +45,28 @@ pub trait DemuxCore<'a>: NAOptionHandler {
     fn seek(&mut self, time: NATimePoint, seek_idx: &SeekIndex) -> DemuxerResult<()>;
     /// Returns container duration in milliseconds (zero if not available).
     fn get_duration(&self) -> u64;
+    /// Fills container and stream metadata.
+    ///
+    /// It is called after successfully opening the input.
+    fn fill_metadata(&mut self, _metadata: &mut ContainerMetadata) {}
+    /// Reports the position of the next packet to be demuxed.
+    ///
+    /// It is used to build seek index automatically when the demuxer sets `SeekIndexMode::Automatic`.
+    /// In that case `seek()` should accept index entries with the positions reported here.
+    /// `None` means that the current position cannot be used for that purpose (or the feature is not supported at all).
+    fn get_packet_pos(&mut self) -> Option<u64> { None }
+    /// Returns input ranges skipped because of damaged data.
+    ///
+    /// Normally demuxers report an error on damaged data, ranges are recorded only when error-resilient mode is enabled via [`RESILIENT_OPTION`].
+    ///
+    /// [`RESILIENT_OPTION`]: ../options/constant.RESILIENT_OPTION.html
+    fn get_skipped_ranges(&self) -> &[SkippedRange] { &[] }
+    /// Reports whether the input allows seeking.
+    ///
+    /// Demuxers that can work on non-seekable input (see [`ByteReader::is_seekable`]) should report its state here so seeking requests are rejected early.
+    ///
+    /// [`ByteReader::is_seekable`]: ../io/byteio/struct.ByteReader.html#method.is_seekable
+    fn is_seekable(&mut self) -> bool { true }
 }
 
 /// An auxiliary trait to make bytestream reader read packet data.
@@ -231,33 +258,19 @@ impl StreamSeekInfo {
         self.entries.push(entry);
     }
     /// Searches for an appropriate seek position before requested time.
+    ///
+    /// Entries are expected to be sorted by time.
     pub fn find_pos(&self, time: NATimePoint) -> Option<SeekEntry> {
         if time == NATimePoint::None {
             return None;
         }
-        if !self.entries.is_empty() {
-// todo something faster like binary search
-            let mut cand = None;
-            for entry in self.entries.iter() {
-                match time {
-                    NATimePoint::Milliseconds(ms) => {
-                        if entry.time <= ms {
-                            cand = Some(*entry);
-                        } else {
-                            break;
-                        }
-                    },
-                    NATimePoint::PTS(pts) => {
-                        if entry.pts <= pts {
-                            cand = Some(*entry);
-                        } else {
-                            break;
-                        }
-                    },
-                    NATimePoint::None => unreachable!(),
-                };
-            }
-            cand
+        let idx = match time {
+                NATimePoint::Milliseconds(ms) => self.entries.partition_point(|entry| entry.time <= ms),
+                NATimePoint::PTS(pts)         => self.entries.partition_point(|entry| entry.pts <= pts),
+                NATimePoint::None => unreachable!(),
+            };
+        if idx > 0 {
+            Some(self.entries[idx - 1])
         } else {
             None
         }
@@ -325,6 +338,68 @@ impl SeekIndex {
         self.seek_info[idx.unwrap()].add_entry(entry);
         self.seek_info[idx.unwrap()].filled = true;
     }
+    /// Returns the last entry for the stream with the provided ID.
+    pub fn get_last_entry(&self, id: u32) -> Option<SeekEntry> {
+        let idx = self.stream_id_to_index(id)?;
+        self.seek_info[idx].entries.last().copied()
+    }
+    /// Writes seek index contents in order to reload it later with [`load`].
+    ///
+    /// [`load`]: #method.load
+    pub fn save(&self, bw: &mut ByteWriter, complete: bool) -> ByteIOResult<()> {
+        bw.write_buf(SEEK_INDEX_TAG)?;
+        bw.write_byte(SEEK_INDEX_VERSION)?;
+        bw.write_byte(complete as u8)?;
+        bw.write_u16le(0)?;
+        bw.write_u32le(self.seek_info.len() as u32)?;
+        for str in self.seek_info.iter() {
+            bw.write_u32le(str.id)?;
+            bw.write_u32le(str.entries.len() as u32)?;
+            for entry in str.entries.iter() {
+                bw.write_u64le(entry.time)?;
+                bw.write_u64le(entry.pts)?;
+                bw.write_u64le(entry.pos)?;
+            }
+        }
+        Ok(())
+    }
+    /// Replaces seek index contents with the data written by [`save`].
+    ///
+    /// Returns the flag telling whether the saved index covered the whole input.
+    ///
+    /// [`save`]: #method.save
+    pub fn load(&mut self, br: &mut ByteReader) -> DemuxerResult<bool> {
+        let tag                         = br.read_tag()?;
+        let version                     = br.read_byte()?;
+        if &tag != SEEK_INDEX_TAG || version != SEEK_INDEX_VERSION {
+            return Err(DemuxerError::InvalidData);
+        }
+        let complete                    = br.read_byte()? != 0;
+                                          br.read_skip(2)?;
+        let nstreams                    = br.read_u32le()? as usize;
+        let mut seek_info = Vec::with_capacity(nstreams.min(256));
+        for _ in 0..nstreams {
+            let id                      = br.read_u32le()?;
+            let nentries                = br.read_u32le()? as usize;
+            let mut str = StreamSeekInfo::new(id);
+            str.entries.reserve(nentries.min(1 << 16));
+            for _ in 0..nentries {
+                let time                = br.read_u64le()?;
+                let pts                 = br.read_u64le()?;
+                let pos                 = br.read_u64le()?;
+                if let Some(last) = str.entries.last() {
+                    if last.time > time {
+                        return Err(DemuxerError::InvalidData);
+                    }
+                }
+                str.entries.push(SeekEntry { time, pts, pos });
+            }
+            str.filled = !str.entries.is_empty();
+            seek_info.push(str);
+        }
+        self.seek_info = seek_info;
+        Ok(complete)
+    }
     /// Searches for a seek position before requested time.
     pub fn find_pos(&self, time: NATimePoint) -> Option<SeekIndexResult> {
         let mut cand = None;
@@ -345,20 +420,35 @@ impl SeekIndex {
     }
 }
 
+const SEEK_INDEX_TAG: &[u8; 4] = b"NASI";
+const SEEK_INDEX_VERSION: u8 = 1;
+/// Minimum distance between automatically added seek points for streams where every packet is a keyframe.
+const AUTO_INDEX_MIN_GAP: u64 = 1000;
+
 /// Demuxer structure with auxiliary data.
 pub struct Demuxer<'a> {
     dmx:        Box<dyn DemuxCore<'a> + 'a>,
     streams:    StreamManager,
     seek_idx:   SeekIndex,
+    metadata:   ContainerMetadata,
+    auto_idx:   bool,
+    idx_video:  bool,
+    idx_done:   bool,
 }
 
 impl<'a> Demuxer<'a> {
     /// Constructs a new `Demuxer` instance.
-    fn new(dmx: Box<dyn DemuxCore<'a> + 'a>, str: StreamManager, seek_idx: SeekIndex) -> Self {
+    fn new(mut dmx: Box<dyn DemuxCore<'a> + 'a>, str: StreamManager, seek_idx: SeekIndex, metadata: ContainerMetadata) -> Self {
+        let auto_idx = seek_idx.mode == SeekIndexMode::Automatic && dmx.get_packet_pos().is_some();
+        let idx_video = str.iter().any(|stream| stream.get_media_type() == StreamType::Video);
         Demuxer {
             dmx,
             streams:    str,
             seek_idx,
+            metadata,
+            auto_idx,
+            idx_video,
+            idx_done:   false,
         }
     }
     /// Returns a stream reference by its number.
@@ -397,7 +487,7 @@ impl<'a> Demuxer<'a> {
     /// Demuxes a new packet from the container.
     pub fn get_frame(&mut self) -> DemuxerResult<NAPacket> {
         loop {
-            let res = self.dmx.get_frame(&mut self.streams);
+            let res = self.get_indexed_frame();
             if self.streams.no_ign || res.is_err() { return res; }
             let res = res.unwrap();
             let idx = res.get_stream().get_num();
@@ -407,16 +497,143 @@ impl<'a> Demuxer<'a> {
         }
     }
     /// Seeks to the requested time if possible.
+    ///
+    /// If the seek index is built automatically, the input is scanned up to the requested time first.
     pub fn seek(&mut self, time: NATimePoint) -> DemuxerResult<()> {
-        if self.seek_idx.skip_index {
+        if self.seek_idx.skip_index || !self.dmx.is_seekable() {
             return Err(DemuxerError::NotPossible);
         }
+        if self.auto_idx && !self.idx_done {
+            self.scan_for_index(Some(time))?;
+        }
         self.dmx.seek(time, &self.seek_idx)
     }
     /// Returns internal seek index.
     pub fn get_seek_index(&self) -> &SeekIndex {
         &self.seek_idx
     }
+    /// Reports whether the input allows seeking.
+    pub fn is_seekable(&mut self) -> bool {
+        self.dmx.is_seekable()
+    }
+    /// Reports whether seek index is built automatically while demuxing.
+    pub fn is_seek_index_automatic(&self) -> bool {
+        self.auto_idx
+    }
+    /// Scans the whole input to build seek index.
+    ///
+    /// Afterwards demuxing continues from the first seek point.
+    pub fn build_seek_index(&mut self) -> DemuxerResult<()> {
+        if !self.auto_idx || !self.dmx.is_seekable() {
+            return Err(DemuxerError::NotPossible);
+        }
+        if !self.idx_done {
+            self.scan_for_index(None)?;
+        }
+        self.dmx.seek(NATimePoint::Milliseconds(0), &self.seek_idx)
+    }
+    /// Writes automatically built seek index so it can be reused with [`load_seek_index`] later.
+    ///
+    /// [`load_seek_index`]: #method.load_seek_index
+    pub fn save_seek_index(&self, bw: &mut ByteWriter) -> DemuxerResult<()> {
+        if !self.auto_idx {
+            return Err(DemuxerError::NotPossible);
+        }
+        self.seek_idx.save(bw, self.idx_done)?;
+        Ok(())
+    }
+    /// Loads previously saved seek index.
+    pub fn load_seek_index(&mut self, br: &mut ByteReader) -> DemuxerResult<()> {
+        if !self.auto_idx {
+            return Err(DemuxerError::NotPossible);
+        }
+        self.idx_done = self.seek_idx.load(br)?;
+        Ok(())
+    }
+    fn get_indexed_frame(&mut self) -> DemuxerResult<NAPacket> {
+        if !self.auto_idx {
+            return self.dmx.get_frame(&mut self.streams);
+        }
+        let pos = self.dmx.get_packet_pos();
+        let res = self.dmx.get_frame(&mut self.streams);
+        match res {
+            Ok(ref pkt) => {
+                if let Some(pos) = pos {
+                    self.add_index_entry(pkt, pos);
+                }
+            },
+            Err(DemuxerError::EOF) => self.idx_done = true,
+            _ => {},
+        };
+        res
+    }
+    fn add_index_entry(&mut self, pkt: &NAPacket, pos: u64) {
+        if !pkt.is_keyframe() {
+            return;
+        }
+        let stream = pkt.get_stream();
+        let is_video = stream.get_media_type() == StreamType::Video;
+        if self.idx_video && !is_video {
+            return;
+        }
+        let pts = if let Some(pts) = pkt.get_pts().or_else(|| pkt.get_dts()) { pts } else { return; };
+        let (tb_num, tb_den) = stream.get_timebase();
+        let time = NATimeInfo::ts_to_time(pts, 1000, tb_num, tb_den);
+        let id = stream.get_id();
+        if let Some(last) = self.seek_idx.get_last_entry(id) {
+            if last.pts >= pts || (!is_video && time < last.time + AUTO_INDEX_MIN_GAP) {
+                return;
+            }
+        }
+        self.seek_idx.add_entry(id, SeekEntry { time, pts, pos });
+    }
+    fn scan_for_index(&mut self, time: Option<NATimePoint>) -> DemuxerResult<()> {
+        let mut last_time = None;
+        for str in self.seek_idx.seek_info.iter() {
+            if let Some(entry) = str.entries.last() {
+                last_time = Some(last_time.unwrap_or(0).max(entry.time));
+            }
+        }
+        if let Some(ms) = last_time {
+            if let Some(NATimePoint::Milliseconds(target)) = time {
+                if target <= ms {
+                    return Ok(());
+                }
+            }
+            self.dmx.seek(NATimePoint::Milliseconds(ms), &self.seek_idx)?;
+        }
+        loop {
+            match self.get_indexed_frame() {
+                Ok(pkt) => {
+                    if let Some(pts) = pkt.get_pts() {
+                        let (tb_num, tb_den) = pkt.get_stream().get_timebase();
+                        let passed = match time {
+                                Some(NATimePoint::Milliseconds(target)) => NATimeInfo::ts_to_time(pts, 1000, tb_num, tb_den) > target,
+                                Some(NATimePoint::PTS(target)) => pts > target,
+                                _ => false,
+                            };
+                        if passed {
+                            return Ok(());
+                        }
+                    }
+                },
+                Err(DemuxerError::EOF) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+        }
+    }
+    /// Returns container and stream metadata.
+    pub fn get_metadata(&self) -> &ContainerMetadata {
+        &self.metadata
+    }
+    /// Returns metadata for the stream with the provided ID.
+    pub fn get_stream_metadata(&self, id: u32) -> Option<&Metadata> {
+        self.metadata.get_stream(id)
+    }
+    /// Returns the list of chapters.
+    pub fn get_chapters(&self) -> &[Chapter] {
+        self.metadata.get_chapters()
+    }
     /// Returns media duration reported by container or its streams.
     ///
     /// Duration is in milliseconds and set to zero when it is not available.
@@ -427,15 +644,14 @@ impl<'a> Demuxer<'a> {
         }
         let mut duration = 0;
         for stream in self.streams.iter() {
-            if stream.duration > 0 {
-                let dur = NATimeInfo::ts_to_time(stream.duration, 1000, stream.tb_num, stream.tb_den);
-                if duration < dur {
-                    duration = dur;
-                }
-            }
+            duration = duration.max(stream.get_duration_ms());
         }
         duration
     }
+    /// Returns input ranges skipped by the demuxer in error-resilient mode.
+    pub fn get_skipped_ranges(&self) -> &[SkippedRange] {
+        self.dmx.get_skipped_ranges()
+    }
 }
 
 impl<'a> NAOptionHandler for Demuxer<'a> {
@@ -468,7 +684,9 @@ pub fn create_demuxer<'a>(dmxcr: &dyn DemuxerCreator, br: &'a mut ByteReader<'a>
     let mut str = StreamManager::new();
     let mut seek_idx = SeekIndex::new();
     dmx.open(&mut str, &mut seek_idx)?;
-    Ok(Demuxer::new(dmx, str, seek_idx))
+    let mut metadata = ContainerMetadata::new();
+    dmx.fill_metadata(&mut metadata);
+    Ok(Demuxer::new(dmx, str, seek_idx, metadata))
 }
 
 /// List of registered demuxers.
@@ -511,6 +729,12 @@ pub trait RawDemuxCore<'a>: NAOptionHandler {
     fn seek(&mut self, time: NATimePoint, seek_idx: &SeekIndex) -> DemuxerResult<()>;
     /// Returns container duration in milliseconds (zero if not available).
     fn get_duration(&self) -> u64;
+    /// Fills container and stream metadata.
+    ///
+    /// It is called after successfully opening the input.
+    fn fill_metadata(&mut self, _metadata: &mut ContainerMetadata) {}
+    /// Reports whether the input allows seeking.
+    fn is_seekable(&mut self) -> bool { true }
 }
 
 /// Demuxer structure with auxiliary data.
@@ -518,15 +742,17 @@ pub struct RawDemuxer<'a> {
     dmx:        Box<dyn RawDemuxCore<'a> + 'a>,
     streams:    StreamManager,
     seek_idx:   SeekIndex,
+    metadata:   ContainerMetadata,
 }
 
 impl<'a> RawDemuxer<'a> {
     /// Constructs a new `Demuxer` instance.
-    fn new(dmx: Box<dyn RawDemuxCore<'a> + 'a>, strmgr: StreamManager, seek_idx: SeekIndex) -> Self {
+    fn new(dmx: Box<dyn RawDemuxCore<'a> + 'a>, strmgr: StreamManager, seek_idx: SeekIndex, metadata: ContainerMetadata) -> Self {
         Self {
             dmx,
             streams:    strmgr,
             seek_idx,
+            metadata,
         }
     }
     /// Returns a stream reference by its number.
@@ -576,15 +802,31 @@ impl<'a> RawDemuxer<'a> {
     }
     /// Seeks to the requested time if possible.
     pub fn seek(&mut self, time: NATimePoint) -> DemuxerResult<()> {
-        if self.seek_idx.skip_index {
+        if self.seek_idx.skip_index || !self.dmx.is_seekable() {
             return Err(DemuxerError::NotPossible);
         }
         self.dmx.seek(time, &self.seek_idx)
     }
+    /// Reports whether the input allows seeking.
+    pub fn is_seekable(&mut self) -> bool {
+        self.dmx.is_seekable()
+    }
     /// Returns internal seek index.
     pub fn get_seek_index(&self) -> &SeekIndex {
         &self.seek_idx
     }
+    /// Returns container and stream metadata.
+    pub fn get_metadata(&self) -> &ContainerMetadata {
+        &self.metadata
+    }
+    /// Returns metadata for the stream with the provided ID.
+    pub fn get_stream_metadata(&self, id: u32) -> Option<&Metadata> {
+        self.metadata.get_stream(id)
+    }
+    /// Returns the list of chapters.
+    pub fn get_chapters(&self) -> &[Chapter] {
+        self.metadata.get_chapters()
+    }
     /// Returns media duration reported by container or its streams.
     ///
     /// Duration is in milliseconds and set to zero when it is not available.
@@ -595,12 +837,7 @@ impl<'a> RawDemuxer<'a> {
         }
         let mut duration = 0;
         for stream in self.streams.iter() {
-            if stream.duration > 0 {
-                let dur = NATimeInfo::ts_to_time(stream.duration, 1000, stream.tb_num, stream.tb_den);
-                if duration < dur {
-                    duration = dur;
-                }
-            }
+            duration = duration.max(stream.get_duration_ms());
         }
         duration
     }
@@ -634,7 +871,9 @@ pub fn create_raw_demuxer<'a>(dmxcr: &dyn RawDemuxerCreator, br: &'a mut ByteRea
     let mut str = StreamManager::new();
     let mut seek_idx = SeekIndex::new();
     dmx.open(&mut str, &mut seek_idx)?;
-    Ok(RawDemuxer::new(dmx, str, seek_idx))
+    let mut metadata = ContainerMetadata::new();
+    dmx.fill_metadata(&mut metadata);
+    Ok(RawDemuxer::new(dmx, str, seek_idx, metadata))
 }
 
 /// List of registered demuxers.
@@ -666,3 +905,113 @@ impl RegisteredRawDemuxers {
         self.dmxs.iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NUM_FRAMES: u8 = 50;
+
+    // packets are two bytes long: frame number and keyframe flag
+    struct AutoIndexDemuxer<'a> {
+        src:    &'a mut ByteReader<'a>,
+    }
+
+    impl<'a> DemuxCore<'a> for AutoIndexDemuxer<'a> {
+        fn open(&mut self, strmgr: &mut StreamManager, seek_idx: &mut SeekIndex) -> DemuxerResult<()> {
+            let vhdr = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+            let vinfo = NACodecInfo::new("test", NACodecTypeInfo::Video(vhdr), None);
+            strmgr.add_stream(NAStream::new(StreamType::Video, 0, vinfo, 1, 10, u64::from(NUM_FRAMES))).unwrap();
+            seek_idx.mode = SeekIndexMode::Automatic;
+            Ok(())
+        }
+        fn get_frame(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<NAPacket> {
+            if self.src.is_eof() {
+                return Err(DemuxerError::EOF);
+            }
+            let pts     = u64::from(self.src.read_byte()?);
+            let is_key  = self.src.read_byte()? != 0;
+            let stream = strmgr.get_stream(0).unwrap();
+            let ts = NATimeInfo::new(Some(pts), None, None, 1, 10);
+            Ok(NAPacket::new(stream, ts, is_key, Vec::new()))
+        }
+        fn seek(&mut self, time: NATimePoint, seek_idx: &SeekIndex) -> DemuxerResult<()> {
+            let ret = seek_idx.find_pos(time).ok_or(DemuxerError::SeekError)?;
+            self.src.seek(SeekFrom::Start(ret.pos))?;
+            Ok(())
+        }
+        fn get_duration(&self) -> u64 { 0 }
+        fn get_packet_pos(&mut self) -> Option<u64> { Some(self.src.tell()) }
+    }
+
+    impl<'a> NAOptionHandler for AutoIndexDemuxer<'a> {
+        fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+        fn set_options(&mut self, _options: &[NAOption]) { }
+        fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    }
+
+    struct AutoIndexDemuxerCreator {}
+
+    impl DemuxerCreator for AutoIndexDemuxerCreator {
+        fn new_demuxer<'a>(&self, br: &'a mut ByteReader<'a>) -> Box<dyn DemuxCore<'a> + 'a> {
+            Box::new(AutoIndexDemuxer { src: br })
+        }
+        fn get_name(&self) -> &'static str { "test" }
+    }
+
+    fn next_pts(dmx: &mut Demuxer) -> u64 {
+        dmx.get_frame().unwrap().get_pts().unwrap()
+    }
+
+    #[test]
+    fn test_auto_seek_index() {
+        let mut data = Vec::new();
+        for i in 0..NUM_FRAMES {
+            let is_key = [0, 10, 20, 30, 40].contains(&i);
+            data.push(i);
+            data.push(is_key as u8);
+        }
+        let dmx_cr = AutoIndexDemuxerCreator {};
+
+        let mut saved_index = Vec::new();
+        {
+            let mut mr = MemoryReader::new_read(&data);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = create_demuxer(&dmx_cr, &mut br).unwrap();
+            assert!(dmx.is_seek_index_automatic());
+
+            for _ in 0..5 {
+                dmx.get_frame().unwrap();
+            }
+            assert_eq!(dmx.get_seek_index().seek_info[0].entries.len(), 1);
+
+            // seeking forward scans the input up to the requested time
+            dmx.seek(NATimePoint::Milliseconds(3500)).unwrap();
+            assert_eq!(next_pts(&mut dmx), 30);
+            assert_eq!(dmx.get_seek_index().seek_info[0].entries.len(), 4);
+            dmx.seek(NATimePoint::Milliseconds(1500)).unwrap();
+            assert_eq!(next_pts(&mut dmx), 10);
+
+            dmx.build_seek_index().unwrap();
+            assert_eq!(next_pts(&mut dmx), 0);
+            let entries = &dmx.get_seek_index().seek_info[0].entries;
+            assert_eq!(entries.len(), 5);
+            assert_eq!(entries[4].pos, 80);
+            assert_eq!(entries[4].time, 4000);
+
+            let mut mw = GrowableMemoryWriter::new_write(&mut saved_index);
+            let mut bw = ByteWriter::new(&mut mw);
+            dmx.save_seek_index(&mut bw).unwrap();
+        }
+
+        let mut mr = MemoryReader::new_read(&data);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(&dmx_cr, &mut br).unwrap();
+        let mut mr = MemoryReader::new_read(&saved_index);
+        let mut br = ByteReader::new(&mut mr);
+        dmx.load_seek_index(&mut br).unwrap();
+        assert_eq!(dmx.get_seek_index().seek_info[0].entries.len(), 5);
+        dmx.seek(NATimePoint::Milliseconds(4900)).unwrap();
+        assert_eq!(next_pts(&mut dmx), 40);
+    }
+}
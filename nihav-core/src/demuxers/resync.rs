@@ -0,0 +1,89 @@
+use super::*;
+
+/// Input range skipped by demuxer because of damaged data.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct SkippedRange {
+    /// Position of the first skipped byte.
+    pub start:  u64,
+    /// Position after the last skipped byte.
+    pub end:    u64,
+}
+
+const RESYNC_BUF_SIZE: usize = 4096;
+
+/// Scans the input for the next position accepted by the provided check function.
+///
+/// Scanning starts right after the current position and continues for at most `max_dist` bytes.
+/// The check function receives a candidate position and `sig_len` bytes of data starting there.
+/// On success the input is positioned at the found location and the skipped range is returned, otherwise `DemuxerError::EOF` is reported.
+pub fn resync<F>(src: &mut ByteReader, sig_len: usize, max_dist: u64, mut check: F) -> DemuxerResult<SkippedRange>
+        where F: FnMut(u64, &[u8]) -> bool {
+    let start = src.tell();
+    let end = start.saturating_add(max_dist);
+    let mut buf = vec![0u8; RESYNC_BUF_SIZE + sig_len];
+    let mut pos = start + 1;
+    let mut filled = 0;
+    src.seek(SeekFrom::Start(pos))?;
+    loop {
+        let read = match src.read_buf_some(&mut buf[filled..]) {
+                Ok(size) => size,
+                Err(ByteIOError::EOF) => 0,
+                Err(err) => return Err(err.into()),
+            };
+        filled += read;
+        if filled < sig_len {
+            break;
+        }
+        let nchecks = filled - sig_len + 1;
+        for (i, window) in buf[..filled].windows(sig_len).enumerate() {
+            let cand_pos = pos + (i as u64);
+            if cand_pos > end {
+                return Err(DemuxerError::EOF);
+            }
+            if check(cand_pos, window) {
+                src.seek(SeekFrom::Start(cand_pos))?;
+                return Ok(SkippedRange { start, end: cand_pos });
+            }
+        }
+        buf.copy_within(nchecks..filled, 0);
+        filled -= nchecks;
+        pos += nchecks as u64;
+        if read == 0 {
+            break;
+        }
+    }
+    Err(DemuxerError::EOF)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resync() {
+        let mut data = vec![0u8; 10000];
+        data[7000..7004].copy_from_slice(b"SYNC");
+        data[9000..9004].copy_from_slice(b"SYNC");
+        let mut mr = MemoryReader::new_read(&data);
+        let mut br = ByteReader::new(&mut mr);
+        br.read_skip(100).unwrap();
+
+        let range = resync(&mut br, 4, 1 << 20, |_, buf| buf == b"SYNC").unwrap();
+        assert_eq!(range, SkippedRange { start: 100, end: 7000 });
+        assert_eq!(br.tell(), 7000);
+
+        // the current position is never accepted
+        let range = resync(&mut br, 4, 1 << 20, |_, buf| buf == b"SYNC").unwrap();
+        assert_eq!(range.end, 9000);
+
+        let ret = resync(&mut br, 4, 1 << 20, |_, buf| buf == b"SYNC");
+        assert_eq!(ret, Err(DemuxerError::EOF));
+
+        br.seek(SeekFrom::Start(0)).unwrap();
+        let ret = resync(&mut br, 4, 5000, |_, buf| buf == b"SYNC");
+        assert_eq!(ret, Err(DemuxerError::EOF));
+        br.seek(SeekFrom::Start(0)).unwrap();
+        let range = resync(&mut br, 4, 1 << 20, |pos, _| pos == 1234).unwrap();
+        assert_eq!(range.end, 1234);
+    }
+}
@@ -314,6 +314,24 @@ impl NAChannelMap {
         }
         cm
     }
+    /// Converts the channel map into the channel mapping flags for WAVE format.
+    ///
+    /// `None` is returned if some channel cannot be represented there or channels are not in the order required by WAVE format.
+    pub fn to_ms_mapping(&self) -> Option<u32> {
+        let mut chmap = 0;
+        let mut last_bit = None;
+        for &ch in self.ids.iter() {
+            let bit = MS_CHANNEL_MAP.iter().position(|&x| x == ch)?;
+            if let Some(lbit) = last_bit {
+                if bit <= lbit {
+                    return None;
+                }
+            }
+            last_bit = Some(bit);
+            chmap |= 1 << bit;
+        }
+        Some(chmap)
+    }
 }
 
 impl fmt::Display for NAChannelMap {
@@ -1123,4 +1141,12 @@ mod test {
         assert_eq!(YUV420_FORMAT.to_short_string().unwrap(), "yuv422p");
         assert_eq!(YUVA410_FORMAT.to_short_string().unwrap(), "yuva410p");
     }
+    #[test]
+    fn test_chmap() {
+        let chmap = NAChannelMap::from_str("L,R,C,LFE,Ls,Rs").unwrap();
+        assert_eq!(chmap.to_ms_mapping(), Some(0x3F));
+        assert_eq!(NAChannelMap::from_ms_mapping(0x3F).to_string(), chmap.to_string());
+        assert_eq!(NAChannelMap::from_str("R,L").unwrap().to_ms_mapping(), None);
+        assert_eq!(NAChannelMap::from_str("L,R,Lh").unwrap().to_ms_mapping(), None);
+    }
 }
@@ -1356,8 +1356,18 @@ impl NAStream {
         self.tb_num = n;
         self.tb_den = d;
     }
-    /// Returns stream duration.
-    pub fn get_duration(&self) -> usize { self.num }
+    /// Returns stream duration in timebase units (zero if unknown).
+    pub fn get_duration(&self) -> u64 { self.duration }
+    /// Sets new stream duration.
+    pub fn set_duration(&mut self, duration: u64) { self.duration = duration; }
+    /// Returns stream duration in milliseconds (zero if unknown).
+    pub fn get_duration_ms(&self) -> u64 {
+        if self.duration != 0 && self.tb_den != 0 {
+            NATimeInfo::ts_to_time(self.duration, 1000, self.tb_num, self.tb_den)
+        } else {
+            0
+        }
+    }
     /// Converts current instance into a reference-counted one.
     pub fn into_ref(self) -> NAStreamRef { Arc::new(self) }
 }
@@ -1378,6 +1388,11 @@ pub enum NASideData {
     Palette(bool, Arc<[u8; 1024]>),
     /// Generic user data.
     UserData(Arc<Vec<u8>>),
+    /// MD5 hash of the whole decoded stream.
+    ///
+    /// Lossless encoders attach it to the last packet of the stream so muxers can store it in the stream header (e.g. FLAC `STREAMINFO`).
+    /// The hash is calculated over the samples in little-endian interleaved form.
+    StreamMD5([u8; 16]),
 }
 
 /// Packet with compressed data.
@@ -100,6 +100,19 @@ pub struct BoundedFileReader<T: Read+Seek> {
     eof:      bool,
 }
 
+/// Bytestream reader from non-seekable sources like pipes or sockets.
+///
+/// The reader keeps the recently read data in the internal buffer so it is possible to seek back within the look-back window.
+/// Seeking forward is performed by reading and discarding data.
+pub struct StreamReader<T: Read> {
+    src:      Box<T>,
+    buf:      Vec<u8>,
+    buf_pos:  u64,
+    pos:      u64,
+    lookback: usize,
+    eof:      bool,
+}
+
 macro_rules! read_int {
     ($s: ident, $inttype: ty, $size: expr, $which: ident) => ({
         unsafe {
@@ -455,6 +468,13 @@ impl<'a> ByteReader<'a> {
         self.io.is_eof()
     }
 
+    /// Reports whether the underlying stream supports arbitrary seeking.
+    ///
+    /// Non-seekable streams may still allow seeking back for a limited distance and seeking forward.
+    pub fn is_seekable(&mut self) -> bool {
+        self.io.is_seekable()
+    }
+
     /// Returns stream size or -1 if it is not known.
     pub fn size(&mut self) -> i64 {
         self.io.size()
@@ -835,6 +855,137 @@ impl<T: Read+Seek> ByteIO for BoundedFileReader<T> {
     fn flush(&mut self) -> ByteIOResult<()> { Ok(()) }
 }
 
+const STREAM_READ_CHUNK: usize = 65536;
+
+impl<T: Read> StreamReader<T> {
+    /// Default size of the look-back window.
+    pub const DEFAULT_LOOKBACK: usize = 1 << 20;
+
+    /// Constructs a new instance of `StreamReader` with the default look-back window size.
+    pub fn new_read(src: T) -> Self {
+        Self::new_read_with_lookback(src, Self::DEFAULT_LOOKBACK)
+    }
+    /// Constructs a new instance of `StreamReader` that allows seeking back for up to `lookback` bytes.
+    pub fn new_read_with_lookback(src: T, lookback: usize) -> Self {
+        StreamReader { src: Box::new(src), buf: Vec::new(), buf_pos: 0, pos: 0, lookback, eof: false }
+    }
+    /// Destroys the reader and releases the reader resource for a further use.
+    pub fn finish(self) -> Box<T> { self.src }
+    fn buf_end(&self) -> u64 { self.buf_pos + (self.buf.len() as u64) }
+    fn avail(&self) -> usize { (self.buf_end() - self.pos) as usize }
+    fn fill(&mut self, end: u64) -> ByteIOResult<()> {
+        let keep_from = self.pos.saturating_sub(self.lookback as u64).max(self.buf_pos);
+        let discard = (keep_from - self.buf_pos) as usize;
+        if discard >= self.lookback.max(STREAM_READ_CHUNK) {
+            self.buf.drain(..discard);
+            self.buf_pos = keep_from;
+        }
+        while !self.eof && self.buf_end() < end {
+            let old_len = self.buf.len();
+            let to_read = ((end - self.buf_end()) as usize).max(STREAM_READ_CHUNK);
+            self.buf.resize(old_len + to_read, 0);
+            let ret = self.src.read(&mut self.buf[old_len..]);
+            let read = match ret {
+                    Ok(size) => size,
+                    Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => 0,
+                    Err(_) => {
+                        self.buf.truncate(old_len);
+                        return Err(ByteIOError::ReadError);
+                    },
+                };
+            self.buf.truncate(old_len + read);
+            if read == 0 && ret.is_ok() {
+                self.eof = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Read> ByteIO for StreamReader<T> {
+    fn read_byte(&mut self) -> ByteIOResult<u8> {
+        let b = self.peek_byte()?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn peek_byte(&mut self) -> ByteIOResult<u8> {
+        self.fill(self.pos + 1)?;
+        if self.avail() == 0 { return Err(ByteIOError::EOF); }
+        Ok(self.buf[(self.pos - self.buf_pos) as usize])
+    }
+
+    fn read_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        let read_size = self.peek_buf(buf)?;
+        if read_size < buf.len() {
+            self.pos += read_size as u64;
+            return Err(ByteIOError::EOF);
+        }
+        self.pos += read_size as u64;
+        Ok(read_size)
+    }
+
+    fn read_buf_some(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        let read_size = self.peek_buf(buf)?;
+        self.pos += read_size as u64;
+        Ok(read_size)
+    }
+
+    fn peek_buf(&mut self, buf: &mut [u8]) -> ByteIOResult<usize> {
+        self.fill(self.pos + (buf.len() as u64))?;
+        let copy_size = self.avail().min(buf.len());
+        if copy_size == 0 { return Err(ByteIOError::EOF); }
+        let start = (self.pos - self.buf_pos) as usize;
+        buf[..copy_size].copy_from_slice(&self.buf[start..][..copy_size]);
+        Ok(copy_size)
+    }
+
+    #[allow(unused_variables)]
+    fn write_buf(&mut self, buf: &[u8]) -> ByteIOResult<()> {
+        Err(ByteIOError::NotImplemented)
+    }
+
+    fn tell(&mut self) -> u64 {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> ByteIOResult<u64> {
+        let new_pos = match pos {
+                SeekFrom::Start(x) => x as i64,
+                SeekFrom::Current(x) => (self.pos as i64) + x,
+                SeekFrom::End(_) => return Err(ByteIOError::SeekError),
+            };
+        if new_pos < (self.buf_pos as i64) {
+            return Err(ByteIOError::SeekError);
+        }
+        let new_pos = new_pos as u64;
+        while self.buf_end() < new_pos {
+            if self.eof {
+                return Err(ByteIOError::EOF);
+            }
+            self.pos = self.buf_end();
+            let end = new_pos.min(self.pos + (STREAM_READ_CHUNK as u64));
+            self.fill(end)?;
+        }
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn is_eof(&self) -> bool {
+        self.eof && self.pos >= self.buf_end()
+    }
+
+    fn is_seekable(&mut self) -> bool {
+        false
+    }
+
+    fn size(&mut self) -> i64 {
+        -1
+    }
+
+    fn flush(&mut self) -> ByteIOResult<()> { Ok(()) }
+}
+
 
 /// High-level bytestream writer.
 ///
@@ -1246,6 +1397,29 @@ mod test {
         assert_eq!(br2.read_u32le().unwrap(), 0xCE6200AA);
     }
     #[test]
+    fn test_stream_read() {
+        let data: Vec<u8> = (0..=255).cycle().take(200000).collect();
+        let mut sr = StreamReader::new_read_with_lookback(&data[..], 1000);
+        let mut br = ByteReader::new(&mut sr);
+        assert!(!br.is_seekable());
+        assert_eq!(br.peek_u32be().unwrap(), 0x00010203);
+        assert_eq!(br.read_u32be().unwrap(), 0x00010203);
+        br.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(br.read_byte().unwrap(), 0);
+        br.read_skip(99999).unwrap();
+        assert_eq!(br.tell(), 100000);
+        assert_eq!(br.read_byte().unwrap(), (100000 & 0xFF) as u8);
+        br.seek(SeekFrom::Current(-900)).unwrap();
+        assert_eq!(br.read_byte().unwrap(), (99101 & 0xFF) as u8);
+        assert!(br.seek(SeekFrom::Start(0)).is_err());
+        assert!(br.seek(SeekFrom::End(0)).is_err());
+        br.seek(SeekFrom::Start(199990)).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(br.read_buf_some(&mut buf).unwrap(), 10);
+        assert!(br.is_eof());
+        assert!(br.read_byte().is_err());
+    }
+    #[test]
     fn test_write() {
         let mut buf: [u8; 64] = [0; 64];
         {
@@ -31,5 +31,7 @@ pub mod reorder;
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::trivially_copy_pass_by_ref)]
 pub mod scale;
+#[cfg(all(feature="decoders", feature="demuxers"))]
+pub mod seek;
 #[allow(clippy::unreadable_literal)]
 pub mod soundcvt;
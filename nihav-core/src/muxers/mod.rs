@@ -1,7 +1,9 @@
 //! Muxer definitions.
 pub use crate::frame::*;
 pub use crate::io::byteio::*;
-pub use crate::demuxers::{StreamManager, StreamIter};
+pub use crate::demuxers::{StreamManager, StreamIter, ContainerMetadata, Metadata, MetadataValue, MetadataPicture, Chapter};
+pub use crate::demuxers::{METADATA_TITLE, METADATA_ARTIST, METADATA_ALBUM, METADATA_COMMENT, METADATA_COPYRIGHT, METADATA_DATE, METADATA_GENRE, METADATA_TRACK, METADATA_ENCODER, METADATA_LANGUAGE, METADATA_COVER_ART};
+pub use crate::demuxers::{create_vorbis_comment, create_riff_info, create_flac_picture};
 pub use crate::options::*;
 
 /// A list specifying general muxing errors.
@@ -64,6 +66,12 @@ pub trait MuxCore<'a>: NAOptionHandler {
     fn flush(&mut self) -> MuxerResult<()>;
     /// Finishes muxing and writes necessary header and trailer information if needed.
     fn end(&mut self) -> MuxerResult<()>;
+    /// Sets container and stream metadata to be written.
+    ///
+    /// It is called before [`create`] and muxers not supporting metadata may ignore it.
+    ///
+    /// [`create`]: #tymethod.create
+    fn set_metadata(&mut self, _metadata: &ContainerMetadata) {}
 }
 
 /// Muxer structure with auxiliary data.
@@ -140,6 +148,14 @@ pub fn create_muxer<'a>(mxcr: &dyn MuxerCreator, str: StreamManager, bw: &'a mut
     Ok(Muxer::new(mux, str))
 }
 
+/// Creates muxer for a provided bytestream writer that will also store the provided metadata.
+pub fn create_muxer_with_metadata<'a>(mxcr: &dyn MuxerCreator, str: StreamManager, metadata: &ContainerMetadata, bw: &'a mut ByteWriter<'a>) -> MuxerResult<Muxer<'a>> {
+    let mut mux = mxcr.new_muxer(bw);
+    mux.set_metadata(metadata);
+    mux.create(&str)?;
+    Ok(Muxer::new(mux, str))
+}
+
 /// List of registered muxers.
 #[derive(Default)]
 pub struct RegisteredMuxers {
@@ -8,7 +8,7 @@
 use std::sync::Arc;
 use std::fmt;
 
-pub use crate::compr::deflate::{DEFLATE_MODE_DESCRIPTION, DEFLATE_OPTION_VALUES, DEFLATE_MODE_NONE, DEFLATE_MODE_FAST, DEFLATE_MODE_BETTER, DEFLATE_MODE_BEST};
+pub use crate::compr::deflate::{DEFLATE_MODE_DESCRIPTION, DEFLATE_OPTION_VALUES, DEFLATE_MODE_NONE, DEFLATE_MODE_FAST, DEFLATE_MODE_BETTER, DEFLATE_MODE_BEST, DEFLATE_MODE_MAX};
 
 /// Common name for keyframe interval option.
 pub const KEYFRAME_OPTION: &str = "key_int";
@@ -26,6 +26,11 @@ pub const FRAME_SKIP_OPTION_VAL_KEYFRAME: &str = "keyframes";
 /// Frame skipping option value for decoding only intra frames.
 pub const FRAME_SKIP_OPTION_VAL_INTRA: &str = "intra";
 
+/// Common name for error-resilient demuxing mode.
+pub const RESILIENT_OPTION: &str = "resilient";
+/// Common description for error-resilient demuxing mode.
+pub const RESILIENT_OPTION_DESC: &str = "Skip damaged data and resynchronise instead of reporting an error";
+
 /// A list specifying option parsing and validating errors.
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub enum OptionError {
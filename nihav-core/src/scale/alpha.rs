@@ -0,0 +1,95 @@
+use super::*;
+use super::kernel::Kernel;
+
+#[derive(Default)]
+struct Premultiply {
+    keep_alpha: bool,
+}
+
+impl Premultiply {
+    fn new() -> Self { Self::default() }
+}
+
+macro_rules! premultiply_loop {
+    ($sbuf:expr, $dbuf:expr, $keep_alpha:expr, $ttype:ty, $itype:ty) => {
+            let fmt = $sbuf.get_info().get_format();
+            let acomp = fmt.get_num_comp() - 1;
+            let adepth = fmt.comp_info[acomp].unwrap().depth;
+            let amax = ((1 << adepth) - 1) as $itype;
+            let is_yuv = fmt.get_model().is_yuv();
+            let astride = $sbuf.get_stride(acomp);
+            let aoff = $sbuf.get_offset(acomp);
+            let src = $sbuf.get_data();
+            let alpha = &src[aoff..];
+            for comp in 0..acomp {
+                let chr = fmt.comp_info[comp].unwrap();
+                let (hss, vss) = chr.get_subsampling();
+                let bias = if is_yuv && comp > 0 { (1 << (chr.depth - 1)) as $itype } else { 0 };
+                let istride = $sbuf.get_stride(comp);
+                let dstride = $dbuf.get_stride(comp);
+                let ioff = $sbuf.get_offset(comp);
+                let doff = $dbuf.get_offset(comp);
+                let (w, h) = $sbuf.get_dimensions(comp);
+                let dst = $dbuf.get_data_mut().unwrap();
+                for (y, (sline, dline)) in src[ioff..].chunks(istride).zip(dst[doff..].chunks_mut(dstride)).take(h).enumerate() {
+                    let aline = &alpha[(y << vss) * astride..];
+                    for (x, (&pix, dpix)) in sline.iter().zip(dline.iter_mut()).take(w).enumerate() {
+                        let a = aline[x << hss] as $itype;
+                        let val = ((pix as $itype) - bias) * a / amax + bias;
+                        *dpix = val as $ttype;
+                    }
+                }
+            }
+            if $keep_alpha {
+                let dstride = $dbuf.get_stride(acomp);
+                let doff = $dbuf.get_offset(acomp);
+                let (w, h) = $sbuf.get_dimensions(acomp);
+                let dst = $dbuf.get_data_mut().unwrap();
+                for (sline, dline) in alpha.chunks(astride).zip(dst[doff..].chunks_mut(dstride)).take(h) {
+                    dline[..w].copy_from_slice(&sline[..w]);
+                }
+            }
+    };
+}
+
+impl Kernel for Premultiply {
+    fn init(&mut self, in_fmt: &ScaleInfo, dest_fmt: &ScaleInfo, options: &[(String, String)]) -> ScaleResult<NABufferType> {
+        let mut debug = false;
+        for (name, value) in options.iter() {
+            match (name.as_str(), value.as_str()) {
+                ("debug", "")     => { debug = true; },
+                ("debug", "true") => { debug = true; },
+                _ => {},
+            }
+        }
+        if !in_fmt.fmt.alpha || !in_fmt.fmt.is_unpacked() || in_fmt.fmt.get_max_depth() > 16 {
+            return Err(ScaleError::InvalidArgument);
+        }
+        self.keep_alpha = dest_fmt.fmt.alpha;
+        let mut df = in_fmt.fmt;
+        if !self.keep_alpha {
+            df.components -= 1;
+            df.comp_info[df.components as usize] = None;
+            df.alpha = false;
+        }
+        if debug {
+            println!(" [intermediate format {}]", df);
+        }
+        let res = alloc_video_buffer(NAVideoInfo::new(in_fmt.width, in_fmt.height, false, df), 3);
+        if res.is_err() { return Err(ScaleError::AllocError); }
+        Ok(res.unwrap())
+    }
+    fn process(&mut self, pic_in: &NABufferType, pic_out: &mut NABufferType) {
+        if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
+            premultiply_loop!(sbuf, dbuf, self.keep_alpha, u8, i32);
+        } else if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf16(), pic_out.get_vbuf16()) {
+            premultiply_loop!(sbuf, dbuf, self.keep_alpha, u16, i32);
+        } else {
+            unreachable!();
+        }
+    }
+}
+
+pub fn create_premultiply() -> Box<dyn Kernel> {
+    Box::new(Premultiply::new())
+}
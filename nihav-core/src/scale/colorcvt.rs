@@ -187,6 +187,33 @@ fn matrix_mul(mat: &[[f32; 3]; 3], a: f32, b: f32, c: f32) -> (f32, f32, f32) {
      a * mat[2][0] + b * mat[2][1] + c * mat[2][2] )
 }
 
+fn process_alpha(sbuf: &NAVideoBuffer<u8>, dbuf: &mut NAVideoBuffer<u8>) {
+    let sfmt = sbuf.get_info().get_format();
+    let dfmt = dbuf.get_info().get_format();
+    match (get_alpha_comp(&sfmt), get_alpha_comp(&dfmt)) {
+        (Some(sacomp), Some(dacomp)) => {
+            let sstride = sbuf.get_stride(sacomp);
+            let dstride = dbuf.get_stride(dacomp);
+            let soff = sbuf.get_offset(sacomp);
+            let doff = dbuf.get_offset(dacomp);
+            let (w, h) = dbuf.get_dimensions(dacomp);
+            let src = sbuf.get_data();
+            let dst = dbuf.get_data_mut().unwrap();
+            for (sline, dline) in src[soff..].chunks(sstride).zip(dst[doff..].chunks_mut(dstride)).take(h) {
+                dline[..w].copy_from_slice(&sline[..w]);
+            }
+        },
+        (None, Some(dacomp)) => {
+            let dstride = dbuf.get_stride(dacomp);
+            let doff = dbuf.get_offset(dacomp);
+            let (w, h) = dbuf.get_dimensions(dacomp);
+            let dst = dbuf.get_data_mut().unwrap();
+            fill_alpha(&mut dst[doff..], w, h, dstride, 0xFF);
+        },
+        _ => {},
+    };
+}
+
 #[derive(Default)]
 struct RgbToYuv {
     matrix: [[f32; 3]; 3],
@@ -242,6 +269,7 @@ impl Kernel for RgbToYuv {
     }
     fn process(&mut self, pic_in: &NABufferType, pic_out: &mut NABufferType) {
         if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
+            process_alpha(sbuf, dbuf);
             if dbuf.get_info().get_format().get_num_comp() < 3 {
                 return self.process_grayscale(sbuf, dbuf);
             }
@@ -411,6 +439,7 @@ impl Kernel for YuvToRgb {
     }
     fn process(&mut self, pic_in: &NABufferType, pic_out: &mut NABufferType) {
         if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
+            process_alpha(sbuf, dbuf);
             let istrides = [sbuf.get_stride(0), sbuf.get_stride(1), sbuf.get_stride(2)];
             let dstrides = [dbuf.get_stride(0), dbuf.get_stride(1), dbuf.get_stride(2)];
             let (w, h) = sbuf.get_dimensions(0);
@@ -0,0 +1,79 @@
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+/// Dithering modes used when reducing colour precision.
+pub enum DitheringMode {
+    /// No dithering, the nearest value is used.
+    #[default]
+    None,
+    /// Floyd-Steinberg error diffusion.
+    ///
+    /// This gives the best looking output but it is slower and may produce worse results with interframe compression.
+    FloydSteinberg,
+    /// Ordered dithering using 8x8 Bayer matrix.
+    ///
+    /// It is fast and produces stable patterns which compress better in video.
+    Ordered,
+}
+
+impl DitheringMode {
+    pub(crate) fn from_option(name: &str) -> Self {
+        match name {
+            "fs" | "floyd-steinberg"    => DitheringMode::FloydSteinberg,
+            "ordered" | "bayer"         => DitheringMode::Ordered,
+            _                           => DitheringMode::None,
+        }
+    }
+}
+
+const BAYER8: [[u8; 8]; 8] = [
+    [  0, 32,  8, 40,  2, 34, 10, 42 ],
+    [ 48, 16, 56, 24, 50, 18, 58, 26 ],
+    [ 12, 44,  4, 36, 14, 46,  6, 38 ],
+    [ 60, 28, 52, 20, 62, 30, 54, 22 ],
+    [  3, 35, 11, 43,  1, 33,  9, 41 ],
+    [ 51, 19, 59, 27, 49, 17, 57, 25 ],
+    [ 15, 47,  7, 39, 13, 45,  5, 37 ],
+    [ 63, 31, 55, 23, 61, 29, 53, 21 ],
+];
+
+/// Returns ordered dithering offset for the given position scaled to `[0; step)` range.
+pub fn ordered_offset(x: usize, y: usize, step: i32) -> i32 {
+    (i32::from(BAYER8[y & 7][x & 7]) * 2 + 1) * step / 128
+}
+
+/// Returns ordered dithering offset for the given position scaled to `[-step/2; step/2)` range.
+pub fn ordered_offset_centred(x: usize, y: usize, step: i32) -> i32 {
+    ordered_offset(x, y, step) - step / 2
+}
+
+/// Floyd-Steinberg error accumulator.
+pub struct ErrorDiffusion {
+    ncomps: usize,
+    cur:    Vec<i32>,
+    next:   Vec<i32>,
+}
+
+impl ErrorDiffusion {
+    pub fn new(width: usize, ncomps: usize) -> Self {
+        let size = (width + 2) * ncomps;
+        Self { ncomps, cur: vec![0; size], next: vec![0; size] }
+    }
+    /// Prepares accumulator for processing the next line.
+    pub fn next_line(&mut self) {
+        std::mem::swap(&mut self.cur, &mut self.next);
+        for el in self.next.iter_mut() {
+            *el = 0;
+        }
+    }
+    /// Returns accumulated error for the given position.
+    pub fn get(&self, x: usize, comp: usize) -> i32 {
+        (self.cur[(x + 1) * self.ncomps + comp] + 8) >> 4
+    }
+    /// Distributes quantisation error from the given position to its neighbours.
+    pub fn put(&mut self, x: usize, comp: usize, err: i32) {
+        let pos = (x + 1) * self.ncomps + comp;
+        self.cur[pos + self.ncomps]  += err * 7;
+        self.next[pos - self.ncomps] += err * 3;
+        self.next[pos]               += err * 5;
+        self.next[pos + self.ncomps] += err;
+    }
+}
@@ -19,14 +19,17 @@ use crate::frame::*;
 
 mod kernel;
 
+mod alpha;
 mod colorcvt;
+mod dither;
 mod repack;
 #[allow(clippy::module_inception)]
 mod scale;
 
 mod palette;
 
-pub use crate::scale::palette::{palettise_frame, QuantisationMode, PaletteSearchMode};
+pub use crate::scale::palette::{palettise_frame, palettise_frame_dithered, QuantisationMode, PaletteSearchMode};
+pub use crate::scale::dither::DitheringMode;
 
 /// Image format information used by the converter.
 #[derive(Clone,Copy,PartialEq)]
@@ -93,6 +96,7 @@ const KERNELS: &[KernelDesc] = &[
     KernelDesc { name: "scale",         create: scale::create_scale },
     KernelDesc { name: "rgb_to_yuv",    create: colorcvt::create_rgb2yuv },
     KernelDesc { name: "yuv_to_rgb",    create: colorcvt::create_yuv2rgb },
+    KernelDesc { name: "premultiply",   create: alpha::create_premultiply },
 ];
 
 struct Stage {
@@ -108,6 +112,37 @@ pub fn get_scale_fmt_from_pic(pic: &NABufferType) -> ScaleInfo {
     ScaleInfo { fmt: info.get_format(), width: info.get_width(), height: info.get_height() }
 }
 
+/// Returns the index of alpha component for the format (if present).
+fn get_alpha_comp(fmt: &NAPixelFormaton) -> Option<usize> {
+    if fmt.alpha && fmt.components > 0 {
+        Some(fmt.components as usize - 1)
+    } else {
+        None
+    }
+}
+
+/// Maps input component index to the output one, alpha component is mapped to the output alpha component.
+fn map_component(ifmt: &NAPixelFormaton, ofmt: &NAPixelFormaton, comp: usize) -> Option<usize> {
+    let ialpha = get_alpha_comp(ifmt);
+    let oalpha = get_alpha_comp(ofmt);
+    if Some(comp) == ialpha {
+        return oalpha;
+    }
+    if comp >= ofmt.get_num_comp() || Some(comp) == oalpha {
+        None
+    } else {
+        Some(comp)
+    }
+}
+
+fn fill_alpha<T: Copy>(dst: &mut [T], w: usize, h: usize, stride: usize, val: T) {
+    for line in dst.chunks_mut(stride).take(h) {
+        for el in line[..w].iter_mut() {
+            *el = val;
+        }
+    }
+}
+
 impl Stage {
     fn new(name: &str, in_fmt: &ScaleInfo, dest_fmt: &ScaleInfo, options: &[(String, String)]) -> ScaleResult<Self> {
         let mut worker = KernelDesc::find(name)?;
@@ -156,74 +191,60 @@ fn check_format(in_fmt: NAVideoInfo, ref_fmt: &ScaleInfo, just_convert: bool) ->
     Ok(())
 }
 
-fn copy(pic_in: &NABufferType, pic_out: &mut NABufferType)
-{
-    if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
-        let mut same = true;
-        let num_components = sbuf.get_info().get_format().get_num_comp();
+macro_rules! copy_loop {
+    ($sbuf:expr, $dbuf:expr, $maxval:expr) => {
+        let sfmt = $sbuf.get_info().get_format();
+        let dfmt = $dbuf.get_info().get_format();
+        let mut same = sfmt.get_num_comp() == dfmt.get_num_comp();
+        let num_components = sfmt.get_num_comp();
         for i in 0..num_components {
-            if sbuf.get_stride(i) != dbuf.get_stride(i) {
+            if $sbuf.get_stride(i) != $dbuf.get_stride(i) {
                 same = false;
                 break;
             }
-            if sbuf.get_offset(i) != dbuf.get_offset(i) {
+            if $sbuf.get_offset(i) != $dbuf.get_offset(i) {
                 same = false;
                 break;
             }
         }
         if same {
-            let sdata = sbuf.get_data();
-            let ddata = dbuf.get_data_mut().unwrap();
+            let sdata = $sbuf.get_data();
+            let ddata = $dbuf.get_data_mut().unwrap();
             ddata.copy_from_slice(&sdata[0..]);
         } else {
-            let sdata = sbuf.get_data();
+            let sdata = $sbuf.get_data();
             for comp in 0..num_components {
-                let (_, h) = sbuf.get_dimensions(comp);
-                let src = &sdata[sbuf.get_offset(comp)..];
-                let sstride = sbuf.get_stride(comp);
-                let doff = dbuf.get_offset(comp);
-                let dstride = dbuf.get_stride(comp);
-                let ddata = dbuf.get_data_mut().unwrap();
+                let dcomp = if let Some(dcomp) = map_component(&sfmt, &dfmt, comp) { dcomp } else { continue; };
+                let (_, h) = $sbuf.get_dimensions(comp);
+                let src = &sdata[$sbuf.get_offset(comp)..];
+                let sstride = $sbuf.get_stride(comp);
+                let doff = $dbuf.get_offset(dcomp);
+                let dstride = $dbuf.get_stride(dcomp);
+                let ddata = $dbuf.get_data_mut().unwrap();
                 let dst = &mut ddata[doff..];
                 let copy_size = sstride.min(dstride);
                 for (dline, sline) in dst.chunks_exact_mut(dstride).take(h).zip(src.chunks_exact(sstride)) {
                     (&mut dline[..copy_size]).copy_from_slice(&sline[..copy_size]);
                 }
             }
-        }
-    } else if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf16(), pic_out.get_vbuf16()) {
-        let mut same = true;
-        let num_components = sbuf.get_info().get_format().get_num_comp();
-        for i in 0..num_components {
-            if sbuf.get_stride(i) != dbuf.get_stride(i) {
-                same = false;
-                break;
-            }
-            if sbuf.get_offset(i) != dbuf.get_offset(i) {
-                same = false;
-                break;
-            }
-        }
-        if same {
-            let sdata = sbuf.get_data();
-            let ddata = dbuf.get_data_mut().unwrap();
-            ddata.copy_from_slice(&sdata[0..]);
-        } else {
-            let sdata = sbuf.get_data();
-            for comp in 0..num_components {
-                let (_, h) = sbuf.get_dimensions(comp);
-                let src = &sdata[sbuf.get_offset(comp)..];
-                let sstride = sbuf.get_stride(comp);
-                let doff = dbuf.get_offset(comp);
-                let dstride = dbuf.get_stride(comp);
-                let ddata = dbuf.get_data_mut().unwrap();
-                let dst = &mut ddata[doff..];
-                let copy_size = sstride.min(dstride);
-                for (dline, sline) in dst.chunks_exact_mut(dstride).take(h).zip(src.chunks_exact(sstride)) {
-                    (&mut dline[..copy_size]).copy_from_slice(&sline[..copy_size]);
-                }
+            if let (None, Some(acomp)) = (get_alpha_comp(&sfmt), get_alpha_comp(&dfmt)) {
+                let (w, h) = $dbuf.get_dimensions(acomp);
+                let doff = $dbuf.get_offset(acomp);
+                let dstride = $dbuf.get_stride(acomp);
+                let ddata = $dbuf.get_data_mut().unwrap();
+                let depth = dfmt.comp_info[acomp].unwrap().depth;
+                fill_alpha(&mut ddata[doff..], w, h, dstride, $maxval(depth));
             }
         }
+    };
+}
+
+fn copy(pic_in: &NABufferType, pic_out: &mut NABufferType)
+{
+    if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
+        copy_loop!(sbuf, dbuf, |depth: u8| ((1u16 << depth) - 1) as u8);
+    } else if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf16(), pic_out.get_vbuf16()) {
+        copy_loop!(sbuf, dbuf, |depth: u8| ((1u32 << depth) - 1) as u16);
     } else {
         unimplemented!();
     }
@@ -262,10 +283,12 @@ fn fmt_needs_scale(ifmt: &NAPixelFormaton, ofmt: &NAPixelFormaton) -> bool {
 }
 fn build_pipeline(ifmt: &ScaleInfo, ofmt: &ScaleInfo, just_convert: bool, options: &[(String, String)]) -> ScaleResult<Option<Stage>> {
     let mut debug = false;
+    let mut premultiply = false;
     for (name, value) in options.iter() {
-        if name == "debug" && (value == "" || value == "true") {
-            debug = true;
-            break;
+        match (name.as_str(), value.as_str()) {
+            ("debug", "") | ("debug", "true") => { debug = true; },
+            ("alpha.premultiply", "") | ("alpha.premultiply", "true") => { premultiply = true; },
+            _ => {},
         }
     }
 
@@ -286,7 +309,8 @@ fn build_pipeline(ifmt: &ScaleInfo, ofmt: &ScaleInfo, just_convert: bool, option
     let scale_before_cvt = is_better_fmt(&ifmt, &ofmt) && needs_convert
                            && (ofmt.fmt.get_max_subsampling() == 0);
     let needs_palettise = ofmt.fmt.palette;
-//todo stages for model and gamma conversion
+    let needs_premultiply = premultiply && ifmt.fmt.alpha;
+//todo stages for gamma conversion
 
     let mut stages: Option<Stage> = None;
     let mut cur_fmt = *ifmt;
@@ -295,14 +319,24 @@ fn build_pipeline(ifmt: &ScaleInfo, ofmt: &ScaleInfo, just_convert: bool, option
         if debug {
             println!("[adding unpack]");
         }
+        // premultiplication needs alpha even if the output format does not have it
+        let unpack_fmt = if needs_premultiply { ifmt } else { ofmt };
         let new_stage = if !cur_fmt.fmt.is_paletted() {
-                Stage::new("unpack", &cur_fmt, &ofmt, options)?
+                Stage::new("unpack", &cur_fmt, unpack_fmt, options)?
             } else {
-                Stage::new("depal", &cur_fmt, &ofmt, options)?
+                Stage::new("depal", &cur_fmt, unpack_fmt, options)?
             };
         cur_fmt = new_stage.fmt_out;
         add_stage!(stages, new_stage);
     }
+    if needs_premultiply {
+        if debug {
+            println!("[adding premultiply]");
+        }
+        let new_stage = Stage::new("premultiply", &cur_fmt, ofmt, options)?;
+        cur_fmt = new_stage.fmt_out;
+        add_stage!(stages, new_stage);
+    }
     if needs_scale && scale_before_cvt {
         if debug {
             println!("[adding scale]");
@@ -319,11 +353,10 @@ fn build_pipeline(ifmt: &ScaleInfo, ofmt: &ScaleInfo, just_convert: bool, option
         if debug {
             println!("[{}]", cvtname);
         }
-        let new_stage = Stage::new(&cvtname, &cur_fmt, &ofmt, options)?;
+        let new_stage = Stage::new(&cvtname, &cur_fmt, ofmt, options)?;
 //todo if fails try converting via RGB or YUV
         cur_fmt = new_stage.fmt_out;
         add_stage!(stages, new_stage);
-//todo alpha plane copy/add
     }
     if needs_scale && !scale_before_cvt {
         if debug {
@@ -494,6 +527,7 @@ impl NAScale {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::str::FromStr;
 
     fn fill_pic(pic: &mut NABufferType, val: u8) {
         if let Some(ref mut buf) = pic.get_vbuf() {
@@ -597,4 +631,110 @@ mod test {
         assert_eq!(odata[paloff + 1], 129);
         assert_eq!(odata[paloff + 2], 170);
     }
+    #[test]
+    fn test_dither() {
+        let mut in_pic = alloc_video_buffer(NAVideoInfo::new(8, 8, false, RGB24_FORMAT), 3).unwrap();
+        fill_pic(&mut in_pic, 4);
+        let ifmt = get_scale_fmt_from_pic(&in_pic);
+        for &mode in ["none", "fs", "ordered"].iter() {
+            let mut out_pic = alloc_video_buffer(NAVideoInfo::new(8, 8, false, RGB565_FORMAT), 3).unwrap();
+            let ofmt = get_scale_fmt_from_pic(&out_pic);
+            let options = [("pack.dither".to_string(), mode.to_string())];
+            let mut scaler = NAScale::new_with_options(ifmt, ofmt, &options).unwrap();
+            scaler.convert(&in_pic, &mut out_pic).unwrap();
+            let obuf = out_pic.get_vbuf16().unwrap();
+            let blue_sum: u16 = obuf.get_data().iter().take(8 * 8).map(|&pix| pix & 0x1F).sum();
+            // 4/8 per pixel should result in about every second pixel being rounded up
+            match mode {
+                "none"      => assert_eq!(blue_sum, 0),
+                "ordered"   => assert_eq!(blue_sum, 32),
+                _           => assert!(blue_sum > 24 && blue_sum <= 32),
+            };
+        }
+
+        let in_pic = alloc_video_buffer(NAVideoInfo::new(16, 16, false, RGB24_FORMAT), 0).unwrap();
+        if let Some(ref mut buf) = in_pic.get_vbuf() {
+            let stride = buf.get_stride(0);
+            let data = buf.get_data_mut().unwrap();
+            for line in data.chunks_mut(stride) {
+                for (x, pix) in line.chunks_exact_mut(3).enumerate() {
+                    let clr = if x < 8 { 0 } else { 255 };
+                    pix.copy_from_slice(&[clr, clr, clr]);
+                }
+            }
+        }
+        let mut out_pic = alloc_video_buffer(NAVideoInfo::new(16, 16, false, PAL8_FORMAT), 0).unwrap();
+        palettise_frame_dithered(&in_pic, &mut out_pic, QuantisationMode::MedianCut, PaletteSearchMode::Full, DitheringMode::FloydSteinberg).unwrap();
+        let obuf = out_pic.get_vbuf().unwrap();
+        let paloff = obuf.get_offset(1);
+        let data = obuf.get_data();
+        assert_eq!(data[paloff + usize::from(data[0]) * 3], 0);
+        assert_eq!(data[paloff + usize::from(data[15]) * 3], 255);
+    }
+    #[test]
+    fn test_alpha() {
+        let rgba_fmt = NAPixelFormaton::from_str("rgba").unwrap();
+
+        let in_pic = alloc_video_buffer(NAVideoInfo::new(4, 4, false, rgba_fmt), 3).unwrap();
+        if let Some(ref mut buf) = in_pic.get_vbuf() {
+            let data = buf.get_data_mut().unwrap();
+            for pix in data.chunks_exact_mut(4) {
+                pix.copy_from_slice(&[200, 100, 50, 64]);
+            }
+        }
+        let mut out_pic = alloc_video_buffer(NAVideoInfo::new(4, 4, false, YUVA410_FORMAT), 3).unwrap();
+        let ifmt = get_scale_fmt_from_pic(&in_pic);
+        let ofmt = get_scale_fmt_from_pic(&out_pic);
+        let mut scaler = NAScale::new(ifmt, ofmt).unwrap();
+        scaler.convert(&in_pic, &mut out_pic).unwrap();
+        let obuf = out_pic.get_vbuf().unwrap();
+        let aoff = obuf.get_offset(3);
+        assert_eq!(obuf.get_data()[aoff], 64);
+
+        let mut back_pic = alloc_video_buffer(NAVideoInfo::new(4, 4, false, rgba_fmt), 3).unwrap();
+        let mut scaler = NAScale::new(ofmt, ifmt).unwrap();
+        scaler.convert(&out_pic, &mut back_pic).unwrap();
+        let obuf = back_pic.get_vbuf().unwrap();
+        assert_eq!(obuf.get_data()[3], 64);
+
+        let mut yuv_pic = alloc_video_buffer(NAVideoInfo::new(4, 4, false, YUV410_FORMAT), 3).unwrap();
+        let yfmt = get_scale_fmt_from_pic(&yuv_pic);
+        let mut scaler = NAScale::new(ofmt, yfmt).unwrap();
+        scaler.convert(&out_pic, &mut yuv_pic).unwrap();
+        let mut scaler = NAScale::new(yfmt, ofmt).unwrap();
+        scaler.convert(&yuv_pic, &mut out_pic).unwrap();
+        let obuf = out_pic.get_vbuf().unwrap();
+        assert_eq!(obuf.get_data()[aoff], 0xFF);
+        let mut scaler = NAScale::new(yfmt, ifmt).unwrap();
+        scaler.convert(&yuv_pic, &mut back_pic).unwrap();
+        let obuf = back_pic.get_vbuf().unwrap();
+        assert_eq!(obuf.get_data()[3], 0xFF);
+    }
+    #[test]
+    fn test_alpha_premultiply() {
+        let rgba_fmt = NAPixelFormaton::from_str("rgba").unwrap();
+
+        let in_pic = alloc_video_buffer(NAVideoInfo::new(2, 2, false, rgba_fmt), 3).unwrap();
+        if let Some(ref mut buf) = in_pic.get_vbuf() {
+            let data = buf.get_data_mut().unwrap();
+            for pix in data.chunks_exact_mut(4) {
+                pix.copy_from_slice(&[200, 100, 50, 51]);
+            }
+        }
+        let mut out_pic = alloc_video_buffer(NAVideoInfo::new(2, 2, false, RGB24_FORMAT), 3).unwrap();
+        let ifmt = get_scale_fmt_from_pic(&in_pic);
+        let ofmt = get_scale_fmt_from_pic(&out_pic);
+        let options = [("alpha.premultiply".to_string(), "true".to_string())];
+        let mut scaler = NAScale::new_with_options(ifmt, ofmt, &options).unwrap();
+        scaler.convert(&in_pic, &mut out_pic).unwrap();
+        let obuf = out_pic.get_vbuf().unwrap();
+        assert_eq!(&obuf.get_data()[..3], &[40, 20, 10]);
+
+        let mut out_pic = alloc_video_buffer(NAVideoInfo::new(4, 4, false, rgba_fmt), 3).unwrap();
+        let ofmt = get_scale_fmt_from_pic(&out_pic);
+        let mut scaler = NAScale::new_with_options(ifmt, ofmt, &options).unwrap();
+        scaler.convert(&in_pic, &mut out_pic).unwrap();
+        let obuf = out_pic.get_vbuf().unwrap();
+        assert_eq!(&obuf.get_data()[..4], &[40, 20, 10, 51]);
+    }
 }
@@ -86,8 +86,28 @@ use crate::scale::palette::elbg::ELBG;
 use crate::scale::palette::mediancut::quantise_median_cut;
 use crate::scale::palette::neuquant::NeuQuantQuantiser;
 use crate::scale::palette::palettise::*;
+use crate::scale::dither::*;
 
-fn palettise_frame_internal(pic_in: &NABufferType, pic_out: &mut NABufferType, qmode: QuantisationMode, palmode: PaletteSearchMode, pixels: &mut Vec<Pixel>) -> ScaleResult<()> {
+/// Amplitude of the noise added by ordered dithering.
+const ORDERED_DITHER_STEP: i32 = 32;
+
+enum PaletteSearcher<'a> {
+    Full(&'a [[u8; 3]; 256]),
+    Local(Box<LocalSearch>),
+    KDTree(KDTree),
+}
+
+impl<'a> PaletteSearcher<'a> {
+    fn search(&self, pix: [u8; 3]) -> usize {
+        match *self {
+            PaletteSearcher::Full(pal)          => find_nearest(&pix, pal),
+            PaletteSearcher::Local(ref ls)      => ls.search(pix),
+            PaletteSearcher::KDTree(ref kdtree) => kdtree.search(pix),
+        }
+    }
+}
+
+fn palettise_frame_internal(pic_in: &NABufferType, pic_out: &mut NABufferType, qmode: QuantisationMode, palmode: PaletteSearchMode, dmode: DitheringMode, pixels: &mut Vec<Pixel>) -> ScaleResult<()> {
     if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
         let ioff = sbuf.get_offset(0);
         let (w, h) = sbuf.get_dimensions(0);
@@ -153,29 +173,50 @@ fn palettise_frame_internal(pic_in: &NABufferType, pic_out: &mut NABufferType, q
         }
 
         let dst = &mut dst[doff..];
-        match palmode {
-            PaletteSearchMode::Full => {
+        let searcher = match palmode {
+                PaletteSearchMode::Full     => PaletteSearcher::Full(&pal),
+                PaletteSearchMode::Local    => PaletteSearcher::Local(Box::new(LocalSearch::new(&pal))),
+                PaletteSearchMode::KDTree   => PaletteSearcher::KDTree(KDTree::new(&pal)),
+            };
+        match dmode {
+            DitheringMode::None => {
                 for (dline, sline) in dst.chunks_mut(dstride).take(h).zip(pixels.chunks(w)) {
                     for (didx, pix) in dline.iter_mut().take(w).zip(sline.iter()) {
-                        let rgb = pix.to_rgb();
-                        *didx = find_nearest(&rgb, &pal) as u8;
+                        *didx = searcher.search(pix.to_rgb()) as u8;
                     }
                 }
             },
-            PaletteSearchMode::Local => {
-                let ls = LocalSearch::new(&pal);
-                for (dline, sline) in dst.chunks_mut(dstride).take(h).zip(pixels.chunks(w)) {
-                    for (didx, pix) in dline.iter_mut().take(w).zip(sline.iter()) {
-                        *didx = ls.search(pix.to_rgb()) as u8;
+            DitheringMode::Ordered => {
+                for (y, (dline, sline)) in dst.chunks_mut(dstride).take(h).zip(pixels.chunks(w)).enumerate() {
+                    for (x, (didx, pix)) in dline.iter_mut().take(w).zip(sline.iter()).enumerate() {
+                        let off = ordered_offset_centred(x, y, ORDERED_DITHER_STEP);
+                        let mut rgb = pix.to_rgb();
+                        for el in rgb.iter_mut() {
+                            *el = (i32::from(*el) + off).clamp(0, 255) as u8;
+                        }
+                        *didx = searcher.search(rgb) as u8;
                     }
                 }
             },
-            PaletteSearchMode::KDTree => {
-                let kdtree = KDTree::new(&pal);
+            DitheringMode::FloydSteinberg => {
+                let mut errs = ErrorDiffusion::new(w, 3);
                 for (dline, sline) in dst.chunks_mut(dstride).take(h).zip(pixels.chunks(w)) {
-                    for (didx, pix) in dline.iter_mut().take(w).zip(sline.iter()) {
-                        *didx = kdtree.search(pix.to_rgb()) as u8;
+                    for (x, (didx, pix)) in dline.iter_mut().take(w).zip(sline.iter()).enumerate() {
+                        let mut want = [0i32; 3];
+                        let mut rgb = [0u8; 3];
+                        for (comp, (wval, &sval)) in want.iter_mut().zip(pix.to_rgb().iter()).enumerate() {
+                            *wval = (i32::from(sval) + errs.get(x, comp)).clamp(0, 255);
+                        }
+                        for (dval, &wval) in rgb.iter_mut().zip(want.iter()) {
+                            *dval = wval as u8;
+                        }
+                        let idx = searcher.search(rgb);
+                        for (comp, (&wval, &pval)) in want.iter().zip(pal[idx].iter()).enumerate() {
+                            errs.put(x, comp, wval - i32::from(pval));
+                        }
+                        *didx = idx as u8;
                     }
+                    errs.next_line();
                 }
             },
         };
@@ -195,6 +236,16 @@ fn palettise_frame_internal(pic_in: &NABufferType, pic_out: &mut NABufferType, q
 /// [`QuantisationMode`]: ./enum.QuantisationMode.html
 /// [`PaletteSearchMode`]: ./enum.PaletteSearchMode.html
 pub fn palettise_frame(pic_in: &NABufferType, pic_out: &mut NABufferType, qmode: QuantisationMode, palmode: PaletteSearchMode) -> ScaleResult<()> {
+    palettise_frame_dithered(pic_in, pic_out, qmode, palmode, DitheringMode::None)
+}
+
+/// Converts packed RGB frame into palettised one applying dithering.
+///
+/// This works the same way as [`palettise_frame`] but additionally allows to select [`DitheringMode`].
+///
+/// [`palettise_frame`]: ./fn.palettise_frame.html
+/// [`DitheringMode`]: ./enum.DitheringMode.html
+pub fn palettise_frame_dithered(pic_in: &NABufferType, pic_out: &mut NABufferType, qmode: QuantisationMode, palmode: PaletteSearchMode, dmode: DitheringMode) -> ScaleResult<()> {
     let size;
     if let Some(ref vbuf) = pic_in.get_vbuf() {
 //todo check format for being packed RGB in and pal out
@@ -204,15 +255,15 @@ pub fn palettise_frame(pic_in: &NABufferType, pic_out: &mut NABufferType, qmode:
         return Err(ScaleError::InvalidArgument);
     }
     let mut pixels = Vec::with_capacity(size);
-    palettise_frame_internal(pic_in, pic_out, qmode, palmode, &mut pixels)
+    palettise_frame_internal(pic_in, pic_out, qmode, palmode, dmode, &mut pixels)
 }
 
-
 #[derive(Default)]
 struct PalettiseKernel {
     pixels:     Vec<Pixel>,
     qmode:      QuantisationMode,
     palmode:    PaletteSearchMode,
+    dmode:      DitheringMode,
 }
 
 impl PalettiseKernel {
@@ -239,6 +290,9 @@ impl Kernel for PalettiseKernel {
                             _           => PaletteSearchMode::default(),
                         };
                 },
+                "pal.dither" => {
+                    self.dmode = DitheringMode::from_option(value.as_str());
+                },
                 _ => {},
             };
         }
@@ -249,7 +303,7 @@ impl Kernel for PalettiseKernel {
         Ok(res.unwrap())
     }
     fn process(&mut self, pic_in: &NABufferType, pic_out: &mut NABufferType) {
-        palettise_frame_internal(pic_in, pic_out, self.qmode, self.palmode, &mut self.pixels).unwrap();
+        palettise_frame_internal(pic_in, pic_out, self.qmode, self.palmode, self.dmode, &mut self.pixels).unwrap();
     }
 }
 
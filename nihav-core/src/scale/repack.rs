@@ -1,6 +1,7 @@
 use crate::formats::*;
 use super::*;
 use super::kernel::Kernel;
+use super::dither::*;
 
 fn convert_depth(val: u32, indepth: u8, outdepth: u8) -> u32 {
     if indepth >= outdepth {
@@ -24,25 +25,66 @@ struct PackKernel {
     shifts: [u8;  MAX_CHROMATONS],
     depths: [u8;  MAX_CHROMATONS],
     ncomps: usize,
+    icomp:  [usize; MAX_CHROMATONS],
+    ocomp:  [usize; MAX_CHROMATONS],
     osize:  [u8;  MAX_CHROMATONS],
     ooff:   [usize; MAX_CHROMATONS],
+    alpha:  Option<usize>,
+    dmode:  DitheringMode,
 }
 
 impl PackKernel {
     fn new() -> Self { Self::default() }
+    fn needs_dither(&self) -> bool {
+        self.dmode != DitheringMode::None && (0..self.ncomps).any(|i| self.depths[i] > self.osize[i])
+    }
+    fn dither_elem(&self, val: u32, comp: usize, x: usize, y: usize, errs: &mut ErrorDiffusion) -> u32 {
+        let indepth = self.depths[comp];
+        let outdepth = self.osize[comp];
+        if indepth <= outdepth {
+            return convert_depth(val, indepth, outdepth);
+        }
+        let maxval = (1i32 << indepth) - 1;
+        match self.dmode {
+            DitheringMode::FloydSteinberg => {
+                let want = (val as i32 + errs.get(x, comp)).clamp(0, maxval) as u32;
+                let res = convert_depth(want, indepth, outdepth);
+                errs.put(x, comp, (want as i32) - (convert_depth(res, outdepth, indepth) as i32));
+                res
+            },
+            DitheringMode::Ordered => {
+                let step = 1 << (indepth - outdepth);
+                let want = (val as i32 + ordered_offset(x, y, step)).min(maxval) as u32;
+                convert_depth(want, indepth, outdepth)
+            },
+            DitheringMode::None => convert_depth(val, indepth, outdepth),
+        }
+    }
 }
 
 impl Kernel for PackKernel {
-    fn init(&mut self, in_fmt: &ScaleInfo, dest_fmt: &ScaleInfo, _options: &[(String, String)]) -> ScaleResult<NABufferType> {
-        self.ncomps = in_fmt.fmt.components.min(dest_fmt.fmt.components) as usize;
-        for i in 0..self.ncomps {
-            let ichr = in_fmt.fmt.comp_info[i].unwrap();
-            let ochr = dest_fmt.fmt.comp_info[i].unwrap();
-            self.shifts[i] = ochr.shift;
-            self.osize[i] = ochr.depth;
-            self.depths[i] = ichr.depth;
-            self.ooff[i] = ochr.comp_offs as usize;
+    fn init(&mut self, in_fmt: &ScaleInfo, dest_fmt: &ScaleInfo, options: &[(String, String)]) -> ScaleResult<NABufferType> {
+        for (name, value) in options.iter() {
+            if name == "pack.dither" {
+                self.dmode = DitheringMode::from_option(value.as_str());
+            }
+        }
+        self.ncomps = 0;
+        for comp in 0..in_fmt.fmt.get_num_comp() {
+            if let Some(ocomp) = map_component(&in_fmt.fmt, &dest_fmt.fmt, comp) {
+                let i = self.ncomps;
+                let ichr = in_fmt.fmt.comp_info[comp].unwrap();
+                let ochr = dest_fmt.fmt.comp_info[ocomp].unwrap();
+                self.icomp[i] = comp;
+                self.ocomp[i] = ocomp;
+                self.shifts[i] = ochr.shift;
+                self.osize[i] = ochr.depth;
+                self.depths[i] = ichr.depth;
+                self.ooff[i] = ochr.comp_offs as usize;
+                self.ncomps += 1;
+            }
         }
+        self.alpha = if !in_fmt.fmt.alpha { get_alpha_comp(&dest_fmt.fmt) } else { None };
         let res = alloc_video_buffer(NAVideoInfo::new(in_fmt.width, in_fmt.height, false, dest_fmt.fmt), 3);
         if res.is_err() { return Err(ScaleError::AllocError); }
         Ok(res.unwrap())
@@ -52,42 +94,79 @@ impl Kernel for PackKernel {
             if let Some(ref mut dbuf) = pic_out.get_vbuf() {
                 let dstride = dbuf.get_stride(0);
                 for comp in 0..self.ncomps {
-                    let ioff = buf.get_offset(comp);
-                    let istride = buf.get_stride(comp);
-                    let step = dbuf.get_info().get_format().get_chromaton(comp).unwrap().get_step() as usize;
-                    let (w, h) = dbuf.get_dimensions(comp);
+                    let ioff = buf.get_offset(self.icomp[comp]);
+                    let istride = buf.get_stride(self.icomp[comp]);
+                    let step = dbuf.get_info().get_format().get_chromaton(self.ocomp[comp]).unwrap().get_step() as usize;
+                    let (w, h) = dbuf.get_dimensions(self.ocomp[comp]);
                     let sdata = buf.get_data();
                     let sdata = &sdata[ioff..];
                     let ddata = dbuf.get_data_mut().unwrap();
-                    for (src, dst) in sdata.chunks(istride).zip(ddata.chunks_mut(dstride)).take(h) {
+                    if !self.needs_dither() {
+                        for (src, dst) in sdata.chunks(istride).zip(ddata.chunks_mut(dstride)).take(h) {
+                            for x in 0..w {
+                                dst[x * step + self.ooff[comp]] = convert_depth(u32::from(src[x]), self.depths[comp], self.osize[comp]) as u8;
+                            }
+                        }
+                    } else {
+                        let mut errs = ErrorDiffusion::new(w, self.ncomps);
+                        for (y, (src, dst)) in sdata.chunks(istride).zip(ddata.chunks_mut(dstride)).take(h).enumerate() {
+                            for x in 0..w {
+                                dst[x * step + self.ooff[comp]] = self.dither_elem(u32::from(src[x]), comp, x, y, &mut errs) as u8;
+                            }
+                            errs.next_line();
+                        }
+                    }
+                }
+                if let Some(acomp) = self.alpha {
+                    let chr = dbuf.get_info().get_format().get_chromaton(acomp).unwrap();
+                    let step = chr.get_step() as usize;
+                    let aoff = chr.get_offset() as usize;
+                    let aval = ((1u16 << chr.get_depth()) - 1) as u8;
+                    let (w, h) = dbuf.get_dimensions(acomp);
+                    let ddata = dbuf.get_data_mut().unwrap();
+                    for dst in ddata.chunks_mut(dstride).take(h) {
                         for x in 0..w {
-                            dst[x * step + self.ooff[comp]] = convert_depth(u32::from(src[x]), self.depths[comp], self.osize[comp]) as u8;
+                            dst[x * step + aoff] = aval;
                         }
                     }
                 }
             } else if let Some(ref mut dbuf) = pic_out.get_vbuf16() {
                 let (w, h) = dbuf.get_dimensions(0);
                 let dstride = dbuf.get_stride(0);
+                let aval = if let Some(acomp) = self.alpha {
+                        let chr = dbuf.get_info().get_format().get_chromaton(acomp).unwrap();
+                        ((1 << chr.get_depth()) - 1) << chr.get_shift()
+                    } else { 0 };
                 let ddata = dbuf.get_data_mut().unwrap();
                 let src = buf.get_data();
                 let mut ioff: [usize; MAX_CHROMATONS] = [0; MAX_CHROMATONS];
                 let mut istride: [usize; MAX_CHROMATONS] = [0; MAX_CHROMATONS];
                 for comp in 0..self.ncomps {
-                    ioff[comp] = buf.get_offset(comp);
-                    istride[comp] = buf.get_stride(comp);
+                    ioff[comp] = buf.get_offset(self.icomp[comp]);
+                    istride[comp] = buf.get_stride(self.icomp[comp]);
                 }
-                for dst in ddata.chunks_mut(dstride).take(h) {
+                let dither = self.needs_dither();
+                let mut errs = ErrorDiffusion::new(if dither { w } else { 0 }, self.ncomps);
+                for (y, dst) in ddata.chunks_mut(dstride).take(h).enumerate() {
                     for x in 0..w {
-                        let mut elem: u32 = 0;
+                        let mut elem: u32 = aval;
                         for comp in 0..self.ncomps {
                             let c = u32::from(src[ioff[comp] + x]);
-                            elem |= convert_depth(c, self.depths[comp], self.osize[comp]) << self.shifts[comp];
+                            let val = if !dither {
+                                    convert_depth(c, self.depths[comp], self.osize[comp])
+                                } else {
+                                    self.dither_elem(c, comp, x, y, &mut errs)
+                                };
+                            elem |= val << self.shifts[comp];
                         }
                         dst[x] = elem as u16;
                     }
                     for comp in 0..self.ncomps {
                         ioff[comp] += istride[comp];
                     }
+                    if dither {
+                        errs.next_line();
+                    }
                 }
             } else {
 unimplemented!();
@@ -130,11 +209,13 @@ impl Kernel for UnpackKernel {
             }
         }
 
-        self.ncomps = in_fmt.fmt.components.min(dest_fmt.fmt.components) as usize;
+        // all colour components are kept for the following conversion, alpha is kept only if it is needed
+        let drop_alpha = in_fmt.fmt.alpha && !dest_fmt.fmt.alpha;
+        self.ncomps = if drop_alpha { in_fmt.fmt.get_num_comp() - 1 } else { in_fmt.fmt.get_num_comp() };
         let mut chr: Vec<Option<NAPixelChromaton>> = Vec::with_capacity(MAX_CHROMATONS);
         for i in 0..self.ncomps {
             let ichr = in_fmt.fmt.comp_info[i].unwrap();
-            let ochr = dest_fmt.fmt.comp_info[i].unwrap();
+            let ochr = dest_fmt.fmt.comp_info[i].unwrap_or(ichr);
             self.shifts[i] = ichr.shift;
             self.masks[i] = (1 << ichr.depth) - 1;
             if ochr.depth > ichr.depth {
@@ -153,7 +234,11 @@ impl Kernel for UnpackKernel {
         }
         let mut df = in_fmt.fmt;
         df.comp_info[..self.ncomps].clone_from_slice(&chr[..self.ncomps]);
+        for el in df.comp_info[self.ncomps..].iter_mut() {
+            *el = None;
+        }
         df.components = self.ncomps as u8;
+        df.alpha = in_fmt.fmt.alpha && !drop_alpha;
         df.palette = false;
         if debug {
             println!(" [intermediate format {}]", df);
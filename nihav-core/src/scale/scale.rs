@@ -51,16 +51,18 @@ fn fill_plane<T: Copy>(dst: &mut [T], w: usize, h: usize, stride: usize, val: T)
 }
 
 macro_rules! scale_loop {
-    ($sbuf:expr, $dbuf:expr) => {
+    ($sbuf:expr, $dbuf:expr, $maxval:expr) => {
             let fmt = $sbuf.get_info().get_format();
+            let dfmt = $dbuf.get_info().get_format();
             let ncomp = fmt.get_num_comp();
             for comp in 0..ncomp {
+                let dcomp = if let Some(dcomp) = map_component(&fmt, &dfmt, comp) { dcomp } else { continue; };
                 let istride = $sbuf.get_stride(comp);
-                let dstride = $dbuf.get_stride(comp);
+                let dstride = $dbuf.get_stride(dcomp);
                 let (sw, sh) = $sbuf.get_dimensions(comp);
-                let (dw, dh) = $dbuf.get_dimensions(comp);
+                let (dw, dh) = $dbuf.get_dimensions(dcomp);
                 let ioff = $sbuf.get_offset(comp);
-                let mut doff = $dbuf.get_offset(comp);
+                let mut doff = $dbuf.get_offset(dcomp);
                 let src = $sbuf.get_data();
                 let dst = $dbuf.get_data_mut().unwrap();
                 for y in 0..dh {
@@ -70,16 +72,15 @@ macro_rules! scale_loop {
                     doff += dstride;
                 }
             }
-            let dfmt = $dbuf.get_info().get_format();
             let ndcomp = dfmt.get_num_comp();
-            if ndcomp > ncomp {
+            if ndcomp > ncomp || (!fmt.alpha && dfmt.alpha) {
                 if !fmt.alpha && dfmt.alpha {
                     let acomp = ndcomp - 1;
                     let dstride = $dbuf.get_stride(acomp);
                     let (dw, dh) = $dbuf.get_dimensions(acomp);
                     let doff = $dbuf.get_offset(acomp);
                     let dst = $dbuf.get_data_mut().unwrap();
-                    fill_plane(&mut dst[doff..], dw, dh, dstride, 0);
+                    fill_plane(&mut dst[doff..], dw, dh, dstride, $maxval(dfmt.comp_info[acomp].unwrap().depth));
                 }
                 if fmt.model.is_yuv() && ((!fmt.alpha && ncomp == 1) || (fmt.alpha && ncomp == 2)) && ndcomp >= 3 {
                     let uval = 1 << (dfmt.comp_info[1].unwrap().depth - 1);
@@ -107,11 +108,11 @@ impl Kernel for NNResampler {
     }
     fn process(&mut self, pic_in: &NABufferType, pic_out: &mut NABufferType) {
         if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf(), pic_out.get_vbuf()) {
-            scale_loop!(sbuf, dbuf);
+            scale_loop!(sbuf, dbuf, |depth: u8| ((1u16 << depth) - 1) as u8);
         } else if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf16(), pic_out.get_vbuf16()) {
-            scale_loop!(sbuf, dbuf);
+            scale_loop!(sbuf, dbuf, |depth: u8| ((1u32 << depth) - 1) as u16);
         } else if let (Some(ref sbuf), Some(ref mut dbuf)) = (pic_in.get_vbuf32(), pic_out.get_vbuf32()) {
-            scale_loop!(sbuf, dbuf);
+            scale_loop!(sbuf, dbuf, |depth: u8| ((1u64 << depth) - 1) as u32);
         } else {
             unreachable!();
         }
@@ -0,0 +1,204 @@
+//! Frame-accurate seeking.
+//!
+//! Demuxers can seek only to the positions stored in the seek index (usually keyframes) so the first decoded frame after seeking is likely to be before the requested time.
+//! This module provides a helper that seeks to the preceding keyframe and then decodes and discards frames until the requested time is reached.
+use crate::codecs::*;
+use crate::demuxers::*;
+use crate::reorder::FrameReorderer;
+
+/// A list specifying seeking helper errors.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SeekError {
+    /// Demuxer reported an error.
+    DemuxerError(DemuxerError),
+    /// Decoder reported an error.
+    DecoderError(DecoderError),
+    /// Requested stream does not exist.
+    InvalidStream,
+    /// There are no frames at or after the requested time.
+    EOF,
+}
+
+impl From<DemuxerError> for SeekError {
+    fn from(err: DemuxerError) -> Self { SeekError::DemuxerError(err) }
+}
+
+impl From<DecoderError> for SeekError {
+    fn from(err: DecoderError) -> Self { SeekError::DecoderError(err) }
+}
+
+/// A specialised `Result` type for seeking operations.
+pub type SeekResult<T> = Result<T, SeekError>;
+
+fn frame_reached(frm: &NAFrameRef, target: u64) -> bool {
+    match frm.get_pts().or_else(|| frm.get_dts()) {
+        Some(ts) => ts >= target,
+        None => true,
+    }
+}
+
+/// Seeks to the requested time and returns the first frame of the stream at or after it.
+///
+/// The demuxer is moved to the preceding seek point, then the decoder and the reorderer are flushed and packets of the stream with the provided number are decoded until a frame with the presentation timestamp not less than the requested one is output.
+/// Packets belonging to other streams are discarded so their decoders should be flushed by the caller as well.
+///
+/// Decoding errors caused by missing references (which is normal when seeking lands on a non-intra frame) are ignored, other errors are reported.
+pub fn seek_to_frame(dmx: &mut Demuxer, stream_no: usize, dec: &mut dyn NADecoder, supp: &mut NADecoderSupport, reord: &mut dyn FrameReorderer, time: NATimePoint) -> SeekResult<NAFrameRef> {
+    let stream = dmx.get_stream(stream_no).ok_or(SeekError::InvalidStream)?;
+    let (tb_num, tb_den) = stream.get_timebase();
+    let target = match time {
+            NATimePoint::Milliseconds(ms) => NATimeInfo::time_to_ts(ms, 1000, tb_num, tb_den),
+            NATimePoint::PTS(pts) => pts,
+            NATimePoint::None => return Err(SeekError::DemuxerError(DemuxerError::SeekError)),
+        };
+
+    dmx.seek(time)?;
+    dec.flush();
+    reord.flush();
+
+    loop {
+        let pkt = match dmx.get_frame() {
+                Ok(pkt) => pkt,
+                Err(DemuxerError::EOF) => break,
+                Err(err) => return Err(err.into()),
+            };
+        if pkt.get_stream().get_num() != stream_no {
+            continue;
+        }
+        let frm = match dec.decode(supp, &pkt) {
+                Ok(frm) => frm,
+                Err(DecoderError::MissingReference) | Err(DecoderError::NoFrame) => continue,
+                Err(err) => return Err(err.into()),
+            };
+        reord.add_frame(frm);
+        while let Some(frm) = reord.get_frame() {
+            if frame_reached(&frm, target) {
+                return Ok(frm);
+            }
+        }
+    }
+    while let Some(frm) = reord.get_last_frames() {
+        if frame_reached(&frm, target) {
+            return Ok(frm);
+        }
+    }
+    Err(SeekError::EOF)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reorder::NoReorderer;
+
+    const NUM_FRAMES: u64 = 30;
+    const KEYFRAMES: [u64; 3] = [0, 10, 20];
+
+    // every packet is a single byte containing frame number, keyframes are marked in the seek index
+    struct TestDemuxer<'a> {
+        src:    &'a mut ByteReader<'a>,
+    }
+
+    impl<'a> DemuxCore<'a> for TestDemuxer<'a> {
+        fn open(&mut self, strmgr: &mut StreamManager, seek_idx: &mut SeekIndex) -> DemuxerResult<()> {
+            let vhdr = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+            let vinfo = NACodecInfo::new("test", NACodecTypeInfo::Video(vhdr), None);
+            strmgr.add_stream(NAStream::new(StreamType::Video, 0, vinfo, 1, 25, NUM_FRAMES)).unwrap();
+            seek_idx.mode = SeekIndexMode::Present;
+            for &pts in KEYFRAMES.iter() {
+                seek_idx.add_entry(0, SeekEntry { time: pts * 40, pts, pos: pts });
+            }
+            Ok(())
+        }
+        fn get_frame(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<NAPacket> {
+            let pts = self.src.tell();
+            if pts >= NUM_FRAMES {
+                return Err(DemuxerError::EOF);
+            }
+            let stream = strmgr.get_stream(0).unwrap();
+            let ts = NATimeInfo::new(Some(pts), None, None, 1, 25);
+            self.src.read_packet(stream, ts, KEYFRAMES.contains(&pts), 1)
+        }
+        fn seek(&mut self, time: NATimePoint, seek_idx: &SeekIndex) -> DemuxerResult<()> {
+            let ret = seek_idx.find_pos(time).ok_or(DemuxerError::SeekError)?;
+            self.src.seek(SeekFrom::Start(ret.pos))?;
+            Ok(())
+        }
+        fn get_duration(&self) -> u64 { NUM_FRAMES * 40 }
+    }
+
+    impl<'a> NAOptionHandler for TestDemuxer<'a> {
+        fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+        fn set_options(&mut self, _options: &[NAOption]) { }
+        fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    }
+
+    struct TestDemuxerCreator {}
+
+    impl DemuxerCreator for TestDemuxerCreator {
+        fn new_demuxer<'a>(&self, br: &'a mut ByteReader<'a>) -> Box<dyn DemuxCore<'a> + 'a> {
+            Box::new(TestDemuxer { src: br })
+        }
+        fn get_name(&self) -> &'static str { "test" }
+    }
+
+    // outputs frames only after a keyframe was seen
+    struct TestDecoder {
+        has_ref:    bool,
+        ndecoded:   usize,
+    }
+
+    impl NADecoder for TestDecoder {
+        fn init(&mut self, _supp: &mut NADecoderSupport, _info: NACodecInfoRef) -> DecoderResult<()> { Ok(()) }
+        fn decode(&mut self, _supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef> {
+            if pkt.is_keyframe() {
+                self.has_ref = true;
+            }
+            if !self.has_ref {
+                return Err(DecoderError::MissingReference);
+            }
+            self.ndecoded += 1;
+            let ftype = if pkt.is_keyframe() { FrameType::I } else { FrameType::P };
+            let mut frm = NAFrame::new_from_pkt(pkt, pkt.get_stream().get_info(), NABufferType::None);
+            frm.set_frame_type(ftype);
+            Ok(frm.into_ref())
+        }
+        fn flush(&mut self) {
+            self.has_ref = false;
+        }
+    }
+
+    impl NAOptionHandler for TestDecoder {
+        fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+        fn set_options(&mut self, _options: &[NAOption]) { }
+        fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    }
+
+    #[test]
+    fn test_seek_to_frame() {
+        let data: Vec<u8> = (0..NUM_FRAMES as u8).collect();
+        let mut mr = MemoryReader::new_read(&data);
+        let mut br = ByteReader::new(&mut mr);
+        let dmx_cr = TestDemuxerCreator {};
+        let mut dmx = create_demuxer(&dmx_cr, &mut br).unwrap();
+        let mut dec = TestDecoder { has_ref: false, ndecoded: 0 };
+        let mut supp = NADecoderSupport::new();
+        let mut reord = NoReorderer::new();
+
+        // start decoding from the middle of GOP to make sure the state is reset
+        let _ = dmx.get_frame().unwrap();
+
+        let frm = seek_to_frame(&mut dmx, 0, &mut dec, &mut supp, &mut reord, NATimePoint::PTS(17)).unwrap();
+        assert_eq!(frm.get_pts(), Some(17));
+        assert_eq!(dec.ndecoded, 8);
+
+        let frm = seek_to_frame(&mut dmx, 0, &mut dec, &mut supp, &mut reord, NATimePoint::Milliseconds(520)).unwrap();
+        assert_eq!(frm.get_pts(), Some(13));
+
+        let frm = seek_to_frame(&mut dmx, 0, &mut dec, &mut supp, &mut reord, NATimePoint::PTS(20)).unwrap();
+        assert_eq!(frm.get_pts(), Some(20));
+        assert!(frm.is_keyframe());
+
+        let ret = seek_to_frame(&mut dmx, 0, &mut dec, &mut supp, &mut reord, NATimePoint::PTS(NUM_FRAMES + 5));
+        assert_eq!(ret.err(), Some(SeekError::EOF));
+    }
+}
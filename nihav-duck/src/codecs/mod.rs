@@ -13,7 +13,7 @@ mod truemotionrt;
 mod truemotion2;
 #[cfg(feature="decoder_truemotion2x")]
 mod truemotion2x;
-#[cfg(any(feature="decoder_vp3", feature="decoder_vp4", feature="decoder_vp5", feature="decoder_vp6", feature="decoder_vp7"))]
+#[cfg(any(feature="decoder_vp3", feature="decoder_vp4", feature="decoder_vp5", feature="decoder_vp6", feature="decoder_vp7", feature="encoder_vp8"))]
 #[macro_use]
 #[allow(clippy::erasing_op)]
 #[allow(clippy::needless_range_loop)]
@@ -43,7 +43,7 @@ mod vp6;
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::useless_let_if_seq)]
 mod vp7;
-#[cfg(any(feature="decoder_vp7", feature="decoder_vp8"))]
+#[cfg(any(feature="decoder_vp7", feature="decoder_vp8", feature="encoder_vp8"))]
 mod vp78data;
 #[cfg(feature="decoder_vp7")]
 #[allow(clippy::erasing_op)]
@@ -51,11 +51,11 @@ mod vp78data;
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::useless_let_if_seq)]
 mod vp7dsp;
-#[cfg(any(feature="decoder_vp7", feature="decoder_vp8"))]
+#[cfg(any(feature="decoder_vp7", feature="decoder_vp8", feature="encoder_vp8"))]
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::useless_let_if_seq)]
 mod vp78;
-#[cfg(any(feature="decoder_vp7", feature="decoder_vp8"))]
+#[cfg(any(feature="decoder_vp7", feature="decoder_vp8", feature="encoder_vp8"))]
 #[allow(clippy::erasing_op)]
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::too_many_arguments)]
@@ -65,7 +65,9 @@ mod vp78dsp;
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::useless_let_if_seq)]
 mod vp8;
-#[cfg(feature="decoder_vp8")]
+#[cfg(any(feature="decoder_vp8", feature="encoder_vp8"))]
+mod vp8data;
+#[cfg(any(feature="decoder_vp8", feature="encoder_vp8"))]
 #[allow(clippy::erasing_op)]
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::too_many_arguments)]
@@ -127,12 +129,20 @@ pub fn duck_register_all_decoders(rd: &mut RegisteredDecoders) {
 #[cfg(feature="encoder_vp6")]
 #[allow(clippy::needless_range_loop)]
 mod vp6enc;
+#[cfg(feature="encoder_vp8")]
+#[allow(clippy::needless_range_loop)]
+#[allow(clippy::too_many_arguments)]
+mod vp8enc;
 
 const DUCK_ENCODERS: &[EncoderInfo] = &[
 #[cfg(feature="encoder_vp6")]
     EncoderInfo { name: "vp6", get_encoder: vp6enc::get_encoder },
 #[cfg(feature="encoder_vp6")]
     EncoderInfo { name: "vp6f", get_encoder: vp6enc::get_encoder_flv },
+#[cfg(feature="encoder_vp6")]
+    EncoderInfo { name: "vp6a", get_encoder: vp6enc::get_encoder_alpha },
+#[cfg(feature="encoder_vp8")]
+    EncoderInfo { name: "vp8", get_encoder: vp8enc::get_encoder },
 ];
 
 /// Registers all available encoders provided by this crate.
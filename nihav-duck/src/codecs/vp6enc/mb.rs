@@ -215,7 +215,7 @@ impl FrameEncoder {
         let (w, _) = vbuf.get_dimensions(0);
 
         self.src_mbs.clear();
-        for (ys, (us, vs)) in y.chunks(ystride * 16).zip(u.chunks(ustride * 8).zip(v.chunks(vstride * 8))) {
+        for (ys, (us, vs)) in y.chunks(ystride * 16).zip(u.chunks(ustride * 8).zip(v.chunks(vstride * 8))).take(self.mb_h) {
             for x in (0..w).step_by(16) {
                 let mut mb = ResidueMB::new();
                 for (i, blk) in mb.coeffs[..4].iter_mut().enumerate() {
@@ -528,6 +528,9 @@ impl FrameEncoder {
         }
         (intra_count > non_intra * 3, intra_count > non_intra)
     }
+    pub fn count_golden_mbs(&self) -> usize {
+        self.mb_types.iter().filter(|mb_type| mb_type.get_ref_id() == VP_REF_GOLDEN).count()
+    }
     fn find_mv_pred(&self, mb_x: usize, mb_y: usize, ref_id: u8) -> (usize, MV, MV, MV) {
         const CAND_POS: [(i8, i8); 12] = [
             (-1,  0), ( 0, -1),
@@ -1,5 +1,7 @@
 use nihav_core::codecs::*;
 use nihav_core::io::byteio::*;
+use std::fs::File;
+use std::io::BufReader;
 use super::vp6data::*;
 use super::vpcommon::*;
 
@@ -19,6 +21,9 @@ mod rdo;
 
 const VERSION_VP61: u8 = VERSION_VP60 + 1;
 
+// minimum distance between golden frame refreshes triggered by low golden frame usage
+const MIN_GOLDEN_DIST: u8 = 8;
+
 enum VP6Writer<'a, 'b> {
     BoolCoder(BoolEncoder<'a, 'b>),
     Huffman(HuffEncoder<'a, 'b>),
@@ -173,6 +178,7 @@ struct VP6Encoder {
     ratectl:    RateControl,
 
     flipped:    bool,
+    has_alpha:  bool,
     huffman:    bool,
 
     version:    u8,
@@ -185,14 +191,22 @@ struct VP6Encoder {
     last_frame: NABufferType,
     gold_frame: NABufferType,
     last_gold:  bool,
+    golden_int: u8,
+    auto_gold:  bool,
+    since_gold: u8,
     me_mode:    MVSearchMode,
     me_range:   i16,
 
     force_q:    Option<usize>,
+    pass:       u8,
+    passlog:    String,
+
+    alpha_enc:  Option<Box<VP6Encoder>>,
+    alpha_buf:  NABufferType,
 }
 
 impl VP6Encoder {
-    fn new(flipped: bool) -> Self {
+    fn new(flipped: bool, has_alpha: bool) -> Self {
         let vt = alloc_video_buffer(NAVideoInfo::new(24, 24, false, VP_YUVA420_FORMAT), 4).unwrap();
         let mc_buf = vt.get_vbuf().unwrap();
         Self {
@@ -209,6 +223,7 @@ impl VP6Encoder {
             mc_buf,
 
             flipped,
+            has_alpha,
             huffman:    false,
 
             version:    VERSION_VP60,
@@ -221,12 +236,66 @@ impl VP6Encoder {
             last_frame: NABufferType::None,
             gold_frame: NABufferType::None,
             last_gold:  false,
+            golden_int: 0,
+            auto_gold:  false,
+            since_gold: 0,
             me_mode:    MVSearchMode::default(),
             me_range:   16,
 
             force_q:    None,
+            pass:       0,
+            passlog:    String::new(),
+
+            alpha_enc:  None,
+            alpha_buf:  NABufferType::None,
         }
     }
+    fn encode_picture(&mut self, vbuf: &NAVideoBuffer<u8>, is_intra: bool, plan_golden: bool, quant: usize) -> EncoderResult<(Vec<u8>, bool)> {
+        let mut dbuf = Vec::with_capacity(4);
+        let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
+        let mut bw   = ByteWriter::new(&mut gw);
+
+        self.fenc.read_mbs(vbuf);
+        self.fenc.set_quant(quant);
+        self.fenc.me_mode = self.me_mode;
+        self.fenc.me_range = self.me_range;
+        let golden_frame = if is_intra {
+                self.encode_intra(&mut bw, quant)?
+            } else {
+                self.fenc.estimate_mvs(self.last_frame.get_vbuf().unwrap(), self.mc_buf.clone(), false);
+                if !self.last_gold {
+                    self.fenc.estimate_mvs(self.gold_frame.get_vbuf().unwrap(), self.mc_buf.clone(), true);
+                }
+                self.encode_inter(&mut bw, quant, plan_golden)?
+            };
+        self.fenc.reconstruct_frame(&mut self.dc_pred, self.last_frame.get_vbuf().unwrap());
+        self.last_gold = golden_frame;
+        self.since_gold = if golden_frame { 0 } else { self.since_gold.saturating_add(1) };
+        if golden_frame {
+            let mut dfrm = self.gold_frame.get_vbuf().unwrap();
+            let src = self.last_frame.get_vbuf().unwrap();
+
+            let dst = dfrm.get_data_mut().unwrap();
+            dst.copy_from_slice(src.get_data());
+        }
+        Ok((dbuf, golden_frame))
+    }
+    fn init_planes(&mut self, out_info: NAVideoInfo) -> EncoderResult<()> {
+        self.last_frame = alloc_video_buffer(out_info, 4)?;
+        self.gold_frame = alloc_video_buffer(out_info, 4)?;
+
+        self.mb_w = (out_info.width  + 15) >> 4;
+        self.mb_h = (out_info.height + 15) >> 4;
+        self.fenc.resize(self.mb_w, self.mb_h);
+
+        self.dc_pred.resize(self.mb_w);
+        self.top_ctx = [vec![false; self.mb_w * 2], vec![false; self.mb_w], vec![false; self.mb_w], vec![false; self.mb_w * 2]];
+
+        self.version = VERSION_VP60;
+        self.profile = VP6_SIMPLE_PROFILE;
+        self.since_gold = 0;
+        Ok(())
+    }
     fn decide_encoding(&mut self) -> bool {
         false
     }
@@ -465,7 +534,7 @@ impl VP6Encoder {
         self.encode_coeffs(writer)?;
         Ok(true)
     }
-    fn encode_inter(&mut self, bw: &mut ByteWriter, quant: usize) -> EncoderResult<bool> {
+    fn encode_inter(&mut self, bw: &mut ByteWriter, quant: usize, force_golden: bool) -> EncoderResult<bool> {
         self.stats.reset();
 
         let multistream = self.huffman || self.version != VERSION_VP60;
@@ -479,7 +548,14 @@ impl VP6Encoder {
         let lambda = if self.force_q.is_some() { 1.0 } else { self.ratectl.lambda };
         self.fenc.select_inter_blocks(self.last_frame.get_vbuf().unwrap(), self.mc_buf.clone(), !self.last_gold, lambda);
         // todo implement forced intra
-        let (_force_intra, golden_frame) = self.fenc.decide_frame_type();
+        let (_force_intra, mut golden_frame) = self.fenc.decide_frame_type();
+        if force_golden {
+            golden_frame = true;
+        }
+        // refresh golden frame if it is not useful any longer
+        if self.auto_gold && !self.last_gold && self.since_gold >= MIN_GOLDEN_DIST && self.fenc.count_golden_mbs() * 32 < self.mb_w * self.mb_h {
+            golden_frame = true;
+        }
         self.fenc.apply_dc_prediction(&mut self.dc_pred);
         self.fenc.predict_mvs();
         self.estimate_blocks(false);
@@ -635,12 +711,14 @@ impl NAEncoder for VP6Encoder {
         match encinfo.format {
             NACodecTypeInfo::None => {
                 let mut ofmt = EncodeParameters::default();
-                ofmt.format = NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, self.flipped, YUV420_FORMAT));
+                let fmt = if !self.has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+                ofmt.format = NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, self.flipped, fmt));
                 Ok(ofmt)
             },
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                let outinfo = NAVideoInfo::new((vinfo.width + 3) & !3, (vinfo.height + 3) & !3, self.flipped, YUV420_FORMAT);
+                let fmt = if !self.has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+                let outinfo = NAVideoInfo::new((vinfo.width + 3) & !3, (vinfo.height + 3) & !3, self.flipped, fmt);
                 let mut ofmt = *encinfo;
                 ofmt.format = NACodecTypeInfo::Video(outinfo);
                 Ok(ofmt)
@@ -652,9 +730,13 @@ impl NAEncoder for VP6Encoder {
             NACodecTypeInfo::None => Err(EncoderError::FormatError),
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                if vinfo.format != YUV420_FORMAT {
+                if vinfo.format != (if !self.has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT }) {
                     return Err(EncoderError::FormatError);
                 }
+                // statistics file has to be set explicitly for two-pass encoding
+                if self.pass != 0 && self.passlog.is_empty() {
+                    return Err(EncoderError::InvalidParameters);
+                }
                 if ((vinfo.width | vinfo.height) & 15) != 0 {
                     return Err(EncoderError::FormatError);
                 }
@@ -663,26 +745,50 @@ impl NAEncoder for VP6Encoder {
                 }
 
                 let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, self.flipped, vinfo.format);
-                let info = NACodecInfo::new(if self.flipped { "vp6" } else { "vp6f" }, NACodecTypeInfo::Video(out_info), None);
+                let cname = match (self.flipped, self.has_alpha) {
+                        (_, true)      => "vp6a",
+                        (true, false)  => "vp6",
+                        (false, false) => "vp6f",
+                    };
+                let info = NACodecInfo::new(cname, NACodecTypeInfo::Video(out_info), None);
                 let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
                 stream.set_num(stream_id as usize);
                 let stream = stream.into_ref();
 
-                self.last_frame = alloc_video_buffer(out_info, 4)?;
-                self.gold_frame = alloc_video_buffer(out_info, 4)?;
+                let pic_info = NAVideoInfo::new(vinfo.width, vinfo.height, self.flipped, YUV420_FORMAT);
+                self.init_planes(pic_info)?;
 
                 self.stream = Some(stream.clone());
 
-                self.mb_w = (vinfo.width  + 15) >> 4;
-                self.mb_h = (vinfo.height + 15) >> 4;
-                self.fenc.resize(self.mb_w, self.mb_h);
                 self.ratectl.init(self.mb_w, self.mb_h, encinfo.bitrate, encinfo.tb_num, encinfo.tb_den);
+                if self.pass == 2 {
+                    let file = File::open(&self.passlog).map_err(|_| EncoderError::InvalidParameters)?;
+                    if self.ratectl.read_stats(BufReader::new(file)).is_none() {
+                        return Err(EncoderError::InvalidParameters);
+                    }
+                    self.ratectl.init_second_pass(self.huffman);
+                }
 
-                self.dc_pred.resize(self.mb_w);
-                self.top_ctx = [vec![false; self.mb_w * 2], vec![false; self.mb_w], vec![false; self.mb_w], vec![false; self.mb_w * 2]];
-
-                self.version = VERSION_VP60;
-                self.profile = VP6_SIMPLE_PROFILE;
+                if self.has_alpha {
+                    let mut aenc = VP6Encoder::new(self.flipped, false);
+                    aenc.key_int    = self.key_int;
+                    aenc.huffman    = self.huffman;
+                    aenc.golden_int = self.golden_int;
+                    aenc.auto_gold  = self.auto_gold;
+                    aenc.me_mode    = self.me_mode;
+                    aenc.me_range   = self.me_range;
+                    aenc.init_planes(pic_info)?;
+                    aenc.alpha_buf = alloc_video_buffer(pic_info, 4)?;
+                    // chroma planes of the alpha stream are not used so they are kept flat
+                    if let Some(ref mut abuf) = aenc.alpha_buf.get_vbuf() {
+                        let coff = abuf.get_offset(1);
+                        let data = abuf.get_data_mut().unwrap();
+                        for el in data[coff..].iter_mut() {
+                            *el = 0x80;
+                        }
+                    }
+                    self.alpha_enc = Some(Box::new(aenc));
+                }
 
                 Ok(stream)
             },
@@ -691,44 +797,54 @@ impl NAEncoder for VP6Encoder {
     fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
         let buf = frm.get_buffer();
         if let Some(ref vbuf) = buf.get_vbuf() {
-            let mut dbuf = Vec::with_capacity(4);
-            let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
-            let mut bw   = ByteWriter::new(&mut gw);
-
             // todo integrate with rate control
             let is_intra = (self.frmcount == 0) || self.decide_encoding();
+            let plan_golden = !is_intra && self.golden_int > 0 && self.since_gold + 1 >= self.golden_int;
             let quant = if let Some(q) = self.force_q {
                     q
                 } else {
-                    self.ratectl.guess_quant(is_intra, self.huffman)
+                    self.ratectl.guess_quant(is_intra, plan_golden, self.huffman)
                 };
 
-            self.fenc.read_mbs(vbuf);
-            self.fenc.set_quant(quant);
-            self.fenc.me_mode = self.me_mode;
-            self.fenc.me_range = self.me_range;
-            let golden_frame = if is_intra {
-                    self.encode_intra(&mut bw, quant)?
-                } else {
-                    self.fenc.estimate_mvs(self.last_frame.get_vbuf().unwrap(), self.mc_buf.clone(), false);
-                    if !self.last_gold {
-                        self.fenc.estimate_mvs(self.gold_frame.get_vbuf().unwrap(), self.mc_buf.clone(), true);
-                    }
-                    self.encode_inter(&mut bw, quant)?
-                };
-            self.fenc.reconstruct_frame(&mut self.dc_pred, self.last_frame.get_vbuf().unwrap());
-            self.last_gold = golden_frame;
-            if golden_frame {
-                let mut dfrm = self.gold_frame.get_vbuf().unwrap();
-                let src = self.last_frame.get_vbuf().unwrap();
-
-                let dst = dfrm.get_data_mut().unwrap();
-                dst.copy_from_slice(src.get_data());
+            let (mut dbuf, golden_frame) = self.encode_picture(vbuf, is_intra, plan_golden, quant)?;
+            if let Some(ref mut aenc) = self.alpha_enc {
+                // alpha plane is coded as luma of the second stream
+                let mut abuf = aenc.alpha_buf.get_vbuf().unwrap();
+                let (w, h) = vbuf.get_dimensions(3);
+                let sstride = vbuf.get_stride(3);
+                let dstride = abuf.get_stride(0);
+                let doff = abuf.get_offset(0);
+                let src = &vbuf.get_data()[vbuf.get_offset(3)..];
+                let dst = &mut abuf.get_data_mut().unwrap()[doff..];
+                for (dline, sline) in dst.chunks_mut(dstride).zip(src.chunks(sstride)).take(h) {
+                    dline[..w].copy_from_slice(&sline[..w]);
+                }
+                let (adata, _) = aenc.encode_picture(&abuf, is_intra, plan_golden, quant)?;
+
+                let aoffset = dbuf.len();
+                if aoffset >= (1 << 24) {
+                    return Err(EncoderError::Bug);
+                }
+                let mut pkt = Vec::with_capacity(3 + aoffset + adata.len());
+                pkt.push((aoffset >> 16) as u8);
+                pkt.push((aoffset >>  8) as u8);
+                pkt.push( aoffset        as u8);
+                pkt.extend_from_slice(&dbuf);
+                pkt.extend_from_slice(&adata);
+                dbuf = pkt;
             }
 
             if self.force_q.is_none() {
                 self.ratectl.update(dbuf.len() * 8);
             }
+            if self.pass == 1 {
+                let kind = match (is_intra, golden_frame) {
+                        (true, _)      => FrameKind::Intra,
+                        (false, true)  => FrameKind::Golden,
+                        (false, false) => FrameKind::Inter,
+                    };
+                self.ratectl.add_frame_stats(FrameStats { kind, quant, bits: dbuf.len() * 8 });
+            }
 
             self.pkt = Some(NAPacket::new(self.stream.clone().unwrap(), frm.ts, is_intra, dbuf));
             if self.key_int > 0 {
@@ -749,6 +865,10 @@ impl NAEncoder for VP6Encoder {
     }
     fn flush(&mut self) -> EncoderResult<()> {
         self.frmcount = 0;
+        if self.pass == 1 {
+            let mut file = File::create(&self.passlog).map_err(|_| EncoderError::InvalidParameters)?;
+            self.ratectl.write_stats(&mut file).map_err(|_| EncoderError::Bug)?;
+        }
         Ok(())
     }
 }
@@ -758,6 +878,10 @@ const QUANT_OPTION: &str = "quant";
 const VERSION_OPTION: &str = "version";
 const MV_SEARCH_OPTION: &str = "mv_mode";
 const MV_RANGE_OPTION: &str = "mv_range";
+const GOLDEN_INT_OPTION: &str = "golden_int";
+const AUTO_GOLDEN_OPTION: &str = "auto_golden";
+const PASS_OPTION: &str = "pass";
+const PASSLOG_OPTION: &str = "passlog";
 
 const ENCODER_OPTS: &[NAOptionDefinition] = &[
     NAOptionDefinition {
@@ -778,6 +902,18 @@ const ENCODER_OPTS: &[NAOptionDefinition] = &[
     NAOptionDefinition {
         name: MV_RANGE_OPTION, description: "motion search range (in pixels)",
         opt_type: NAOptionDefinitionType::Int(Some(0), Some(30)) },
+    NAOptionDefinition {
+        name: GOLDEN_INT_OPTION, description: "golden frame refresh interval (0 - disabled)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(128)) },
+    NAOptionDefinition {
+        name: AUTO_GOLDEN_OPTION, description: "refresh golden frame when it is rarely referenced",
+        opt_type: NAOptionDefinitionType::Bool },
+    NAOptionDefinition {
+        name: PASS_OPTION, description: "two-pass encoding stage (0 - single pass, 1 - first pass, 2 - second pass)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(2)) },
+    NAOptionDefinition {
+        name: PASSLOG_OPTION, description: "file name for two-pass encoding statistics (required for two-pass mode)",
+        opt_type: NAOptionDefinitionType::String(None) },
 ];
 
 impl NAOptionHandler for VP6Encoder {
@@ -824,6 +960,26 @@ impl NAOptionHandler for VP6Encoder {
                                 self.me_range = intval as i16;
                             }
                         },
+                        GOLDEN_INT_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.golden_int = intval as u8;
+                            }
+                        },
+                        AUTO_GOLDEN_OPTION => {
+                            if let NAValue::Bool(bval) = option.value {
+                                self.auto_gold = bval;
+                            }
+                        },
+                        PASS_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.pass = intval as u8;
+                            }
+                        },
+                        PASSLOG_OPTION => {
+                            if let NAValue::String(ref string) = option.value {
+                                self.passlog = string.clone();
+                            }
+                        },
                         _ => {},
                     };
                 }
@@ -850,17 +1006,25 @@ impl NAOptionHandler for VP6Encoder {
                 },
             MV_SEARCH_OPTION => Some(NAValue::String(self.me_mode.to_string())),
             MV_RANGE_OPTION => Some(NAValue::Int(i64::from(self.me_range))),
+            GOLDEN_INT_OPTION => Some(NAValue::Int(i64::from(self.golden_int))),
+            AUTO_GOLDEN_OPTION => Some(NAValue::Bool(self.auto_gold)),
+            PASS_OPTION => Some(NAValue::Int(i64::from(self.pass))),
+            PASSLOG_OPTION => Some(NAValue::String(self.passlog.clone())),
             _ => None,
         }
     }
 }
 
 pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
-    Box::new(VP6Encoder::new(true))
+    Box::new(VP6Encoder::new(true, false))
 }
 
 pub fn get_encoder_flv() -> Box<dyn NAEncoder + Send> {
-    Box::new(VP6Encoder::new(false))
+    Box::new(VP6Encoder::new(false, false))
+}
+
+pub fn get_encoder_alpha() -> Box<dyn NAEncoder + Send> {
+    Box::new(VP6Encoder::new(false, true))
 }
 
 #[cfg(test)]
@@ -935,4 +1099,101 @@ mod test {
             ];
         encode_test("vp6-huff.avi", enc_options, &[0x6e9bb23d, 0xde296d92, 0x4c225bae, 0x3651e31f]);
     }
+    fn encode_synth(enc_options: &[NAOption], bitrate: u32, nframes: usize, ref_hash: &[u32; 4]) -> SynthTestResult {
+        let mut enc_reg = RegisteredEncoders::new();
+        duck_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        duck_register_all_decoders(&mut dec_reg);
+
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, true, YUV420_FORMAT)),
+                quality: 0,
+                bitrate,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "vp6", enc_params, enc_options, nframes, ref_hash);
+        // luma PSNR should stay above ~30dB
+        assert!(result.max_mse[0] < 64);
+        result
+    }
+    #[test]
+    fn test_vp6_encoder_golden() {
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(42) },
+                NAOption { name: super::GOLDEN_INT_OPTION, value: NAValue::Int(4) },
+            ];
+        let result = encode_synth(enc_options, 0, 12, &[0x16884e41, 0x478c2789, 0xb9dba8e2, 0x2d57651e]);
+        assert_eq!(result.keyframes, 2);
+    }
+    #[test]
+    fn test_vp6_encoder_auto_golden() {
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(42) },
+            ];
+        encode_synth(enc_options, 0, 20, &[0xe9f75903, 0x7967fac9, 0x7158696a, 0x8ba54ea8]);
+        // the same input with the heuristic refresh enabled should produce a different stream
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(42) },
+                NAOption { name: super::AUTO_GOLDEN_OPTION, value: NAValue::Bool(true) },
+            ];
+        encode_synth(enc_options, 0, 20, &[0x265168a3, 0x6f56a389, 0x3ab2314b, 0x92e570be]);
+    }
+    #[test]
+    fn test_vp6_encoder_two_pass() {
+        // removes the statistics file even if the test fails
+        struct TempFile(std::path::PathBuf);
+        impl Drop for TempFile {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+
+        let mut enc_reg = RegisteredEncoders::new();
+        duck_register_all_encoders(&mut enc_reg);
+        let mut enc = (enc_reg.find_encoder("vp6").unwrap())();
+        enc.set_options(&[NAOption { name: super::PASS_OPTION, value: NAValue::Int(1) }]);
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, true, YUV420_FORMAT)),
+                quality: 0,
+                bitrate: 25000,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        assert!(matches!(enc.init(0, enc_params), Err(EncoderError::InvalidParameters)));
+
+        let passlog = TempFile(std::env::temp_dir().join(format!("nihav-vp6pass-{}.log", std::process::id())));
+        let mut enc_options = vec![
+                NAOption { name: super::PASS_OPTION, value: NAValue::Int(1) },
+                NAOption { name: super::PASSLOG_OPTION, value: NAValue::String(passlog.0.to_str().unwrap().to_string()) },
+            ];
+        encode_synth(&enc_options, 25000, 10, &[0xbc976398, 0xf1e70ea5, 0xaceeaf35, 0xb88a785e]);
+        enc_options[0].value = NAValue::Int(2);
+        encode_synth(&enc_options, 25000, 10, &[0x2c5c08b0, 0x665038ca, 0x3346e973, 0x4a5e2f4b]);
+    }
+    #[test]
+    fn test_vp6_encoder_alpha() {
+        let mut enc_reg = RegisteredEncoders::new();
+        duck_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        duck_register_all_decoders(&mut dec_reg);
+
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(50) },
+            ];
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, super::VP_YUVA420_FORMAT)),
+                quality: 0,
+                bitrate: 0,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "vp6a", enc_params, enc_options, 4, &[0xe03786fb, 0x71f99158, 0x45e6af1b, 0x19b6e60a]);
+        assert!(result.max_mse[0] < 64);
+        // synthetic alpha ramp wraps around inside a block so it is allowed more ringing
+        assert!(result.max_mse[3] < 256);
+    }
 }
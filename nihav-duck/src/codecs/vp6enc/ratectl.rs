@@ -1,5 +1,44 @@
+use std::io::{BufRead, Write};
 use super::rdo::*;
 
+const DEFAULT_QUANT: usize = 42;
+// complexity compression factor for two-pass bit distribution
+const QCOMP: f32 = 0.6;
+// number of frames to spread the accumulated second pass error over
+const ERR_SPREAD: isize = 16;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FrameKind {
+    Intra,
+    Golden,
+    Inter,
+}
+
+impl FrameKind {
+    fn to_char(self) -> char {
+        match self {
+            FrameKind::Intra  => 'I',
+            FrameKind::Golden => 'G',
+            FrameKind::Inter  => 'P',
+        }
+    }
+    fn from_char(c: &str) -> Option<Self> {
+        match c {
+            "I" => Some(FrameKind::Intra),
+            "G" => Some(FrameKind::Golden),
+            "P" => Some(FrameKind::Inter),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct FrameStats {
+    pub kind:   FrameKind,
+    pub quant:  usize,
+    pub bits:   usize,
+}
+
 pub struct RateControl {
     pub lambda:     f32,
     tgt_br:         u32,
@@ -10,6 +49,11 @@ pub struct RateControl {
     mb_w:           usize,
     mb_h:           usize,
     projected:      usize,
+    huffman:        bool,
+    fp_stats:       Vec<FrameStats>,
+    targets:        Vec<usize>,
+    frame_no:       usize,
+    sp_error:       isize,
 }
 
 // todo intra/inter decision, better allocation for intra frames
@@ -25,6 +69,11 @@ impl RateControl {
             mb_w:       0,
             mb_h:       0,
             projected:  0,
+            huffman:    false,
+            fp_stats:   Vec::new(),
+            targets:    Vec::new(),
+            frame_no:   0,
+            sp_error:   0,
         }
     }
     pub fn init(&mut self, mb_w: usize, mb_h: usize, bitrate: u32, ts_num: u32, ts_den: u32) {
@@ -32,6 +81,9 @@ impl RateControl {
         self.mb_h   = mb_h;
         self.lambda = 1.0;
         self.cur_time = 0;
+        self.frame_no = 0;
+        self.sp_error = 0;
+        self.targets.clear();
         if bitrate == 0 || ts_num == 0 || ts_den == 0 {
             self.tgt_br = 0;
             self.budget = 0;
@@ -42,8 +94,16 @@ impl RateControl {
             self.ts_den     = ts_den;
         }
     }
-    pub fn guess_quant(&mut self, intra: bool, huffman: bool) -> usize {
-        let fsize = self.get_target_frame_size(intra);
+    pub fn guess_quant(&mut self, intra: bool, golden: bool, huffman: bool) -> usize {
+        self.huffman = huffman;
+        if let Some(q) = self.guess_quant_second_pass(intra) {
+            return q;
+        }
+        let mut fsize = self.get_target_frame_size(intra);
+        if golden && !intra {
+            // golden frames are used as a reference for longer so they deserve better quality
+            fsize += fsize / 2;
+        }
         self.projected = fsize;
         if fsize > 0 {
             for q in 0..64 {
@@ -57,9 +117,29 @@ impl RateControl {
             }
             63
         } else {
-            42
+            DEFAULT_QUANT
         }
     }
+    fn guess_quant_second_pass(&mut self, intra: bool) -> Option<usize> {
+        if self.tgt_br == 0 || self.frame_no >= self.targets.len() {
+            return None;
+        }
+        let stats = self.fp_stats[self.frame_no];
+        let base_est = estimate_frame_size(intra, self.huffman, stats.quant, self.mb_w, self.mb_h).max(1);
+        let planned = self.targets[self.frame_no] as isize;
+        let tgt = (planned + self.sp_error / ERR_SPREAD).max(planned / 4) as usize;
+        self.projected = tgt;
+
+        let mut quant = 0;
+        for q in 0..64 {
+            let est_fsize = estimate_frame_size(intra, self.huffman, q, self.mb_w, self.mb_h);
+            if stats.bits * est_fsize / base_est > tgt {
+                break;
+            }
+            quant = q;
+        }
+        Some(quant)
+    }
     pub fn update(&mut self, dsize: usize) {
         const LAMBDA_STEP: f32 = 1.0 / 32.0;
 
@@ -71,6 +151,10 @@ impl RateControl {
         } else if self.projected < dsize - dsize / 10 {
             self.lambda += LAMBDA_STEP;
         }
+        if self.frame_no < self.targets.len() {
+            self.sp_error += (self.targets[self.frame_no] as isize) - (dsize as isize);
+            self.frame_no += 1;
+        }
         self.budget -= dsize as isize;
         self.cur_time += self.ts_num;
         while self.cur_time >= self.ts_den {
@@ -78,6 +162,55 @@ impl RateControl {
             self.budget += self.tgt_br as isize;
         }
     }
+    pub fn add_frame_stats(&mut self, stats: FrameStats) {
+        self.fp_stats.push(stats);
+    }
+    pub fn write_stats<W: Write>(&self, dst: &mut W) -> std::io::Result<()> {
+        for stats in self.fp_stats.iter() {
+            writeln!(dst, "{} {} {}", stats.kind.to_char(), stats.quant, stats.bits)?;
+        }
+        Ok(())
+    }
+    pub fn read_stats<R: BufRead>(&mut self, src: R) -> Option<()> {
+        self.fp_stats.clear();
+        for line in src.lines() {
+            let line = line.ok()?;
+            let mut parts = line.split_whitespace();
+            let kind  = FrameKind::from_char(parts.next()?)?;
+            let quant = parts.next()?.parse::<usize>().ok()?;
+            let bits  = parts.next()?.parse::<usize>().ok()?;
+            if quant > 63 {
+                return None;
+            }
+            self.fp_stats.push(FrameStats { kind, quant, bits });
+        }
+        if !self.fp_stats.is_empty() { Some(()) } else { None }
+    }
+    pub fn init_second_pass(&mut self, huffman: bool) {
+        self.huffman = huffman;
+        self.targets.clear();
+        self.frame_no = 0;
+        self.sp_error = 0;
+        if self.tgt_br == 0 || self.fp_stats.is_empty() {
+            return;
+        }
+        let nframes = self.fp_stats.len() as u64;
+        let total_bits = u64::from(self.tgt_br) * nframes * u64::from(self.ts_num) / u64::from(self.ts_den);
+
+        let mut weights = Vec::with_capacity(self.fp_stats.len());
+        for stats in self.fp_stats.iter() {
+            // normalise complexity to the same quantiser
+            let intra = stats.kind == FrameKind::Intra;
+            let base_est = estimate_frame_size(intra, self.huffman, stats.quant, self.mb_w, self.mb_h).max(1);
+            let ref_est = estimate_frame_size(intra, self.huffman, DEFAULT_QUANT, self.mb_w, self.mb_h);
+            let complexity = (stats.bits * ref_est / base_est).max(1) as f32;
+            weights.push(complexity.powf(QCOMP));
+        }
+        let sum: f32 = weights.iter().sum();
+        for weight in weights.iter() {
+            self.targets.push(((total_bits as f32) * weight / sum) as usize);
+        }
+    }
     fn get_target_frame_size(&self, intra: bool) -> usize {
         if self.tgt_br == 0 {
             0
@@ -95,3 +228,34 @@ impl RateControl {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_two_pass_stats() {
+        let mut rc = RateControl::new();
+        rc.init(20, 15, 100000, 1, 25);
+        for i in 0..50 {
+            let kind = if i == 0 { FrameKind::Intra } else if (i % 10) == 0 { FrameKind::Golden } else { FrameKind::Inter };
+            rc.add_frame_stats(FrameStats { kind, quant: DEFAULT_QUANT, bits: if i == 0 { 40000 } else { 2000 + i * 10 } });
+        }
+        let mut log = Vec::new();
+        rc.write_stats(&mut log).unwrap();
+
+        let mut rc2 = RateControl::new();
+        rc2.init(20, 15, 100000, 1, 25);
+        rc2.read_stats(log.as_slice()).unwrap();
+        assert_eq!(rc2.fp_stats, rc.fp_stats);
+        rc2.init_second_pass(false);
+        let total: usize = rc2.targets.iter().sum();
+        assert!(total <= 200000 && total > 199000);
+        assert!(rc2.targets[0] > rc2.targets[1]);
+
+        let q_intra = rc2.guess_quant(true, false, false);
+        rc2.update(rc2.targets[0]);
+        let q_inter = rc2.guess_quant(false, false, false);
+        assert!(q_intra <= 63 && q_inter <= 63);
+    }
+}
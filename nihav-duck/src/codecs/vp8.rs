@@ -5,6 +5,7 @@ use super::vpcommon::*;
 use super::vp78::*;
 use super::vp78data::*;
 use super::vp78dsp::*;
+use super::vp8data::*;
 use super::vp8dsp::*;
 
 #[derive(Clone,Copy,PartialEq,Debug)]
@@ -73,22 +74,7 @@ struct SBParams<'a> {
     qmat:       &'a [i16; 16],
 }
 
-pub const COEF_NE_TREE: &[VPTreeDef<DCTToken>] = &[
-    VPTreeDef::Value(DCTToken::Zero),   VPTreeDef::Index(2),
-    VPTreeDef::Value(DCTToken::One),    VPTreeDef::Index(4),
-    VPTreeDef::Index(6),                VPTreeDef::Index(10),
-    VPTreeDef::Value(DCTToken::Two),    VPTreeDef::Index(8),
-    VPTreeDef::Value(DCTToken::Three),  VPTreeDef::Value(DCTToken::Four),
-    VPTreeDef::Index(12),               VPTreeDef::Index(14),
-    VPTreeDef::Value(DCTToken::Cat1),   VPTreeDef::Value(DCTToken::Cat2),
-    VPTreeDef::Index(16),               VPTreeDef::Index(18),
-    VPTreeDef::Value(DCTToken::Cat3),   VPTreeDef::Value(DCTToken::Cat4),
-    VPTreeDef::Value(DCTToken::Cat5),   VPTreeDef::Value(DCTToken::Cat6)
-];
-
 fn decode_subblock(bc: &mut BoolCoder, coeffs: &mut [i16; 16], ctype: usize, pctx: u8, sbparams: &SBParams) -> u8 {
-    const COEF_BANDS: [usize; 16] = [ 0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7 ];
-
     let mut has_nz = 0;
     let start = if ctype != 0 { 0 } else { 1 };
     *coeffs = [0; 16];
@@ -177,15 +163,10 @@ struct DecoderState {
 
 impl DecoderState {
     fn reset(&mut self) {
-        const VP8_DEFAULT_MV_PROBS: [[u8; 19]; 2] = [
-          [ 162, 128, 225, 146, 172, 147, 214,  39, 156, 128, 129, 132,  75, 145, 178, 206, 239, 254, 254 ],
-          [ 164, 128, 204, 170, 119, 235, 140, 230, 228, 128, 130, 130,  74, 148, 180, 203, 236, 254, 254 ]
-        ];
-
         self.kf_ymode_prob.copy_from_slice(Y_MODE_TREE_PROBS);
         self.kf_uvmode_prob.copy_from_slice(UV_MODE_TREE_PROBS);
         self.coef_probs.copy_from_slice(&DEFAULT_DCT_PROBS);
-        self.mv_probs.copy_from_slice(&VP8_DEFAULT_MV_PROBS);
+        self.mv_probs.copy_from_slice(&DEFAULT_MV_PROBS);
         self.segment_probs = [255; 3];
         self.seg = [Segment::default(); 4];
     }
@@ -403,10 +384,6 @@ impl VP8Decoder {
         Ok(())
     }
     fn read_mv_prob_upd(&mut self, bc: &mut BoolCoder) -> DecoderResult<()> {
-        const MV_UPDATE_PROBS: [[u8; 19]; 2] = [
-          [ 237, 246, 253, 253, 254, 254, 254, 254, 254, 254, 254, 254, 254, 254, 250, 250, 252, 254, 254 ],
-          [ 231, 243, 245, 253, 254, 254, 254, 254, 254, 254, 254, 254, 254, 254, 251, 251, 254, 254, 254 ]
-        ];
         for comp in 0..2 {
             for i in 0..19 {
                 if bc.read_prob(MV_UPDATE_PROBS[comp][i]) {
@@ -578,15 +555,6 @@ impl VP8Decoder {
         }
     }
     fn find_mv_pred(&self, mb_x: usize, mb_y: usize, frm_sign: bool) -> ([u8; 4], MV, MV, MV) {
-        const VP8_MV_PROBS: [[u8; 4]; 6] = [
-            [   7,   1,   1, 143 ],
-            [  14,  18,  14, 107 ],
-            [ 135,  64,  57,  68 ],
-            [  60,  56, 128,  65 ],
-            [ 159, 134, 128,  34 ],
-            [ 234, 188, 128,  28 ]
-        ];
-
         const OFFS: [(u8, u8, u8); 3] = [(0, 1, 2), (1, 0, 2), (1, 1, 1)];
         let mut mvs = [ZERO_MV; 3];
         let mut mvc = [0; 3];
@@ -691,10 +659,10 @@ impl VP8Decoder {
         ct[3] = split_w;
         let best_mv = self.clip_mv(best_mv, mb_x, mb_y);
 
-        let mvprobs = [VP8_MV_PROBS[ct[0] as usize][0],
-                       VP8_MV_PROBS[ct[1] as usize][1],
-                       VP8_MV_PROBS[ct[2] as usize][2],
-                       VP8_MV_PROBS[ct[3] as usize][3]];
+        let mvprobs = [MV_REF_PROBS[ct[0] as usize][0],
+                       MV_REF_PROBS[ct[1] as usize][1],
+                       MV_REF_PROBS[ct[2] as usize][2],
+                       MV_REF_PROBS[ct[3] as usize][3]];
 
         (mvprobs, self.clip_mv(nearest_mv, mb_x, mb_y), self.clip_mv(near_mv, mb_x, mb_y), best_mv)
     }
@@ -864,7 +832,7 @@ impl VP8Decoder {
                     ipred_ctx_y.has_left = has_left || x > 0;
                     let bmode = self.ymodes[iidx + x];
                     let cur_yoff = yoff + x * 4;
-                    let has_tr = ipred_ctx_y.has_top && ((x < 3) || ((y == 0) && (mb_y < self.mb_w - 1)));
+                    let has_tr = ipred_ctx_y.has_top && ((x < 3) || ((y == 0) && (mb_x < self.mb_w - 1)));
                     let has_dl = ipred_ctx_y.has_left && (x == 0) && (y < 3);
                     ipred_ctx_y.fill(ydst, cur_yoff, ystride,
                                      if has_tr { 8 } else { 4 },
@@ -1068,78 +1036,6 @@ impl VP8Decoder {
             self.add_residue(dframe, mb_x, mb_y, true);
         }
     }
-    fn loop_filter_mb(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, loop_str: u8, filter_inner: bool) {
-        const HIGH_EDGE_VAR_THR: [[u8; 64]; 2] = [
-          [
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-            1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
-            2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 3,
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3
-          ], [
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-            1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2,
-            2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2
-          ]];
-
-        let inner_thr = if self.dstate.loop_sharpness == 0 {
-                i16::from(loop_str)
-            } else {
-                let bound1 = i16::from(9 - self.dstate.loop_sharpness);
-                let shift = (self.dstate.loop_sharpness + 3) >> 2;
-                (i16::from(loop_str) >> shift).min(bound1).max(1)
-            };
-        let blk_thr  = i16::from(loop_str) * 2 + inner_thr;
-        let edge_thr = blk_thr + 4;
-        let hev_thr     = i16::from(HIGH_EDGE_VAR_THR[if self.dstate.is_intra { 1 } else { 0 }][loop_str as usize]);
-
-        let ystride = dframe.stride[0];
-        let ustride = dframe.stride[1];
-        let vstride = dframe.stride[2];
-        let ypos = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
-        let upos = dframe.offset[1] + mb_x *  8 + mb_y *  8 * ustride;
-        let vpos = dframe.offset[2] + mb_x *  8 + mb_y *  8 * vstride;
-
-        let (loop_edge, loop_inner) = if self.dstate.lf_simple {
-                (simple_loop_filter as LoopFilterFunc, simple_loop_filter as LoopFilterFunc)
-            } else {
-                (normal_loop_filter_edge as LoopFilterFunc, normal_loop_filter_inner as LoopFilterFunc)
-            };
-
-        if mb_x > 0 {
-            loop_edge(dframe.data, ypos, 1, ystride, 16, edge_thr, inner_thr, hev_thr);
-            if !self.dstate.lf_simple {
-                loop_edge(dframe.data, upos, 1, ustride,  8, edge_thr, inner_thr, hev_thr);
-                loop_edge(dframe.data, vpos, 1, vstride,  8, edge_thr, inner_thr, hev_thr);
-            }
-        }
-        if filter_inner {
-            for x in 1..4 {
-                loop_inner(dframe.data, ypos + x * 4, 1, ystride, 16, blk_thr, inner_thr, hev_thr);
-            }
-            if !self.dstate.lf_simple {
-                loop_inner(dframe.data, upos + 4, 1, ustride, 8, blk_thr, inner_thr, hev_thr);
-                loop_inner(dframe.data, vpos + 4, 1, vstride, 8, blk_thr, inner_thr, hev_thr);
-            }
-        }
-
-        if mb_y > 0 {
-            loop_edge(dframe.data, ypos, ystride, 1, 16, edge_thr, inner_thr, hev_thr);
-            if !self.dstate.lf_simple {
-                loop_edge(dframe.data, upos, ustride, 1,  8, edge_thr, inner_thr, hev_thr);
-                loop_edge(dframe.data, vpos, vstride, 1,  8, edge_thr, inner_thr, hev_thr);
-            }
-        }
-        if filter_inner {
-            for y in 1..4 {
-                loop_inner(dframe.data, ypos + y * 4 * ystride, ystride, 1, 16, blk_thr, inner_thr, hev_thr);
-            }
-            if !self.dstate.lf_simple {
-                loop_inner(dframe.data, upos + 4 * ustride, ustride, 1, 8, blk_thr, inner_thr, hev_thr);
-                loop_inner(dframe.data, vpos + 4 * vstride, vstride, 1, 8, blk_thr, inner_thr, hev_thr);
-            }
-        }
-    }
 }
 
 impl NADecoder for VP8Decoder {
@@ -1478,7 +1374,7 @@ impl NADecoder for VP8Decoder {
                 for mb_x in 0..self.mb_w {
                     let loop_str = self.mb_info[mb_idx].loop_str;
                     if loop_str > 0 {
-                        self.loop_filter_mb(&mut dframe, mb_x, mb_y, loop_str, self.mb_info[mb_idx].inner_filt);
+                        loop_filter_mb(&mut dframe, mb_x, mb_y, loop_str, self.mb_info[mb_idx].inner_filt, self.dstate.lf_simple, self.dstate.loop_sharpness, self.dstate.is_intra);
                     }
                     mb_idx += 1;
                 }
@@ -1643,41 +1539,3 @@ mod test {
                       [0x95a68ffb, 0x228d1d8c, 0x6ee54f16, 0xa10fb9eb]);
     }
 }
-
-const DC_QUANTS: [i16; 128] = [
-      4,   5,   6,   7,   8,   9,  10,  10,
-     11,  12,  13,  14,  15,  16,  17,  17,
-     18,  19,  20,  20,  21,  21,  22,  22,
-     23,  23,  24,  25,  25,  26,  27,  28,
-     29,  30,  31,  32,  33,  34,  35,  36,
-     37,  37,  38,  39,  40,  41,  42,  43,
-     44,  45,  46,  46,  47,  48,  49,  50,
-     51,  52,  53,  54,  55,  56,  57,  58,
-     59,  60,  61,  62,  63,  64,  65,  66,
-     67,  68,  69,  70,  71,  72,  73,  74,
-     75,  76,  76,  77,  78,  79,  80,  81,
-     82,  83,  84,  85,  86,  87,  88,  89,
-     91,  93,  95,  96,  98, 100, 101, 102,
-    104, 106, 108, 110, 112, 114, 116, 118,
-    122, 124, 126, 128, 130, 132, 134, 136,
-    138, 140, 143, 145, 148, 151, 154, 157
-];
-
-const AC_QUANTS: [i16; 128] = [
-      4,   5,   6,   7,   8,   9,  10,  11,
-     12,  13,  14,  15,  16,  17,  18,  19,
-     20,  21,  22,  23,  24,  25,  26,  27,
-     28,  29,  30,  31,  32,  33,  34,  35,
-     36,  37,  38,  39,  40,  41,  42,  43,
-     44,  45,  46,  47,  48,  49,  50,  51,
-     52,  53,  54,  55,  56,  57,  58,  60,
-     62,  64,  66,  68,  70,  72,  74,  76,
-     78,  80,  82,  84,  86,  88,  90,  92,
-     94,  96,  98, 100, 102, 104, 106, 108,
-    110, 112, 114, 116, 119, 122, 125, 128,
-    131, 134, 137, 140, 143, 146, 149, 152,
-    155, 158, 161, 164, 167, 170, 173, 177,
-    181, 185, 189, 193, 197, 201, 205, 209,
-    213, 217, 221, 225, 229, 234, 239, 245,
-    249, 254, 259, 264, 269, 274, 279, 284
-];
@@ -1,4 +1,4 @@
-use nihav_core::frame::NAVideoBufferRef;
+use nihav_core::frame::{NAVideoBufferRef, NASimpleVideoFrame};
 use nihav_codec_support::codecs::blockdsp::edge_emu;
 
 fn clip_u8(val: i16) -> u8 {
@@ -94,6 +94,79 @@ pub fn normal_loop_filter_edge(buf: &mut [u8], off: usize, step: usize, stride:
     normal_loop_filter(buf, off, step, stride, len, thr, thr_inner, thr_hev, true);
 }
 
+pub fn loop_filter_mb(dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, loop_str: u8, filter_inner: bool, lf_simple: bool, sharpness: u8, is_intra: bool) {
+    const HIGH_EDGE_VAR_THR: [[u8; 64]; 2] = [
+      [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+        2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 3,
+        3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3
+      ], [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2,
+        2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2
+      ]];
+
+    let inner_thr = if sharpness == 0 {
+            i16::from(loop_str)
+        } else {
+            let bound1 = i16::from(9 - sharpness);
+            let shift = (sharpness + 3) >> 2;
+            (i16::from(loop_str) >> shift).min(bound1).max(1)
+        };
+    let blk_thr  = i16::from(loop_str) * 2 + inner_thr;
+    let edge_thr = blk_thr + 4;
+    let hev_thr     = i16::from(HIGH_EDGE_VAR_THR[if is_intra { 1 } else { 0 }][loop_str as usize]);
+
+    let ystride = dframe.stride[0];
+    let ustride = dframe.stride[1];
+    let vstride = dframe.stride[2];
+    let ypos = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+    let upos = dframe.offset[1] + mb_x *  8 + mb_y *  8 * ustride;
+    let vpos = dframe.offset[2] + mb_x *  8 + mb_y *  8 * vstride;
+
+    let (loop_edge, loop_inner) = if lf_simple {
+            (simple_loop_filter as LoopFilterFunc, simple_loop_filter as LoopFilterFunc)
+        } else {
+            (normal_loop_filter_edge as LoopFilterFunc, normal_loop_filter_inner as LoopFilterFunc)
+        };
+
+    if mb_x > 0 {
+        loop_edge(dframe.data, ypos, 1, ystride, 16, edge_thr, inner_thr, hev_thr);
+        if !lf_simple {
+            loop_edge(dframe.data, upos, 1, ustride,  8, edge_thr, inner_thr, hev_thr);
+            loop_edge(dframe.data, vpos, 1, vstride,  8, edge_thr, inner_thr, hev_thr);
+        }
+    }
+    if filter_inner {
+        for x in 1..4 {
+            loop_inner(dframe.data, ypos + x * 4, 1, ystride, 16, blk_thr, inner_thr, hev_thr);
+        }
+        if !lf_simple {
+            loop_inner(dframe.data, upos + 4, 1, ustride, 8, blk_thr, inner_thr, hev_thr);
+            loop_inner(dframe.data, vpos + 4, 1, vstride, 8, blk_thr, inner_thr, hev_thr);
+        }
+    }
+
+    if mb_y > 0 {
+        loop_edge(dframe.data, ypos, ystride, 1, 16, edge_thr, inner_thr, hev_thr);
+        if !lf_simple {
+            loop_edge(dframe.data, upos, ustride, 1,  8, edge_thr, inner_thr, hev_thr);
+            loop_edge(dframe.data, vpos, vstride, 1,  8, edge_thr, inner_thr, hev_thr);
+        }
+    }
+    if filter_inner {
+        for y in 1..4 {
+            loop_inner(dframe.data, ypos + y * 4 * ystride, ystride, 1, 16, blk_thr, inner_thr, hev_thr);
+        }
+        if !lf_simple {
+            loop_inner(dframe.data, upos + 4 * ustride, ustride, 1, 8, blk_thr, inner_thr, hev_thr);
+            loop_inner(dframe.data, vpos + 4 * vstride, vstride, 1, 8, blk_thr, inner_thr, hev_thr);
+        }
+    }
+}
+
 pub fn iwht4x4(coeffs: &mut [i16; 16]) {
     for i in 0..4 {
         let s0 = coeffs[i];
@@ -0,0 +1,451 @@
+use nihav_core::io::byteio::*;
+use nihav_core::codecs::{EncoderResult, EncoderError};
+use super::super::vpcommon::*;
+use super::super::vp78::*;
+use super::super::vp78data::*;
+use super::super::vp8data::*;
+
+// bits to code zero probability multiplied by eight
+pub const PROB_BITS: [u8; 256] = [
+     0, 64, 56, 51, 48, 45, 43, 42,
+    40, 39, 37, 36, 35, 34, 34, 33,
+    32, 31, 31, 30, 29, 29, 28, 28,
+    27, 27, 26, 26, 26, 25, 25, 24,
+    24, 24, 23, 23, 23, 22, 22, 22,
+    21, 21, 21, 21, 20, 20, 20, 20,
+    19, 19, 19, 19, 18, 18, 18, 18,
+    18, 17, 17, 17, 17, 17, 16, 16,
+    16, 16, 16, 15, 15, 15, 15, 15,
+    15, 14, 14, 14, 14, 14, 14, 14,
+    13, 13, 13, 13, 13, 13, 13, 12,
+    12, 12, 12, 12, 12, 12, 12, 11,
+    11, 11, 11, 11, 11, 11, 11, 11,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    10,  9,  9,  9,  9,  9,  9,  9,
+     9,  9,  9,  8,  8,  8,  8,  8,
+     8,  8,  8,  8,  8,  8,  7,  7,
+     7,  7,  7,  7,  7,  7,  7,  7,
+     7,  7,  6,  6,  6,  6,  6,  6,
+     6,  6,  6,  6,  6,  6,  6,  5,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     5,  5,  5,  5,  5,  5,  4,  4,
+     4,  4,  4,  4,  4,  4,  4,  4,
+     4,  4,  4,  4,  4,  4,  3,  3,
+     3,  3,  3,  3,  3,  3,  3,  3,
+     3,  3,  3,  3,  3,  3,  3,  2,
+     2,  2,  2,  2,  2,  2,  2,  2,
+     2,  2,  2,  2,  2,  2,  2,  2,
+     2,  1,  1,  1,  1,  1,  1,  1,
+     1,  1,  1,  1,  1,  1,  1,  1,
+     1,  1,  1,  1,  1,  1,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0
+];
+
+pub fn bit_cost(bit: bool, prob: u8) -> u32 {
+    if !bit {
+        u32::from(PROB_BITS[prob as usize])
+    } else {
+        u32::from(PROB_BITS[256 - (prob as usize)])
+    }
+}
+
+#[derive(Clone,Copy,Default)]
+pub struct ProbCounter {
+    zeroes: u32,
+    total:  u32,
+}
+
+impl ProbCounter {
+    pub fn add(&mut self, bit: bool) {
+        if !bit {
+            self.zeroes += 1;
+        }
+        self.total += 1;
+    }
+    pub fn is_empty(self) -> bool { self.total == 0 }
+    pub fn to_prob(self) -> u8 {
+        if let Some(prob) = (self.zeroes * 256 + self.total / 2).checked_div(self.total) {
+            prob.clamp(1, 255) as u8
+        } else {
+            128
+        }
+    }
+    // estimated number of bits (multiplied by eight) to code the collected data with the provided probability
+    pub fn est_bits(self, prob: u8) -> u32 {
+        u32::from(PROB_BITS[prob as usize]) * self.zeroes + u32::from(PROB_BITS[256 - (prob as usize)]) * (self.total - self.zeroes)
+    }
+    // decides whether it is beneficial to transmit a new probability
+    pub fn get_update(self, old_prob: u8, upd_prob: u8, raw_bits: u32) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let new_prob = self.to_prob();
+        if new_prob == old_prob {
+            return None;
+        }
+        let old_cost = self.est_bits(old_prob) + bit_cost(false, upd_prob);
+        let new_cost = self.est_bits(new_prob) + bit_cost(true, upd_prob) + raw_bits * 8;
+        if new_cost < old_cost {
+            Some(new_prob)
+        } else {
+            None
+        }
+    }
+}
+
+pub type CoeffProbs = [[[[u8; 11]; 3]; 8]; 4];
+pub type CoeffStats = [[[[ProbCounter; 11]; 3]; 8]; 4];
+
+const MAX_TREE_DEPTH: usize = 16;
+
+fn find_tree_path<T: Copy+PartialEq>(tree: &[VPTreeDef<T>], idx: usize, val: T, path: &mut [(bool, u8); MAX_TREE_DEPTH], depth: usize) -> Option<usize> {
+    for bit in 0..2 {
+        path[depth] = (bit == 1, (idx >> 1) as u8);
+        match tree[idx + bit] {
+            VPTreeDef::Value(v) => {
+                if v == val {
+                    return Some(depth + 1);
+                }
+            },
+            VPTreeDef::Index(ix) => {
+                if let Some(len) = find_tree_path(tree, ix as usize, val, path, depth + 1) {
+                    return Some(len);
+                }
+            },
+        };
+    }
+    None
+}
+
+// returns a sequence of bits and probability indices needed to code the value
+fn tree_path<T: Copy+PartialEq>(tree: &[VPTreeDef<T>], val: T) -> ([(bool, u8); MAX_TREE_DEPTH], usize) {
+    let mut path = [(false, 0); MAX_TREE_DEPTH];
+    let len = find_tree_path(tree, 0, val, &mut path, 0).unwrap_or(0);
+    (path, len)
+}
+
+pub fn tree_cost<T: Copy+PartialEq>(tree: &[VPTreeDef<T>], probs: &[u8], val: T) -> u32 {
+    let (path, len) = tree_path(tree, val);
+    let mut cost = 0;
+    for &(bit, idx) in path[..len].iter() {
+        cost += bit_cost(bit, probs[idx as usize]);
+    }
+    cost
+}
+
+pub struct BoolEncoder<'a, 'b> {
+    bw:     &'a mut ByteWriter<'b>,
+    val:    u32,
+    range:  u32,
+    bits:   u8,
+    saved:  u8,
+    run:    usize,
+}
+
+impl<'a, 'b> BoolEncoder<'a, 'b> {
+    pub fn new(bw: &'a mut ByteWriter<'b>) -> Self {
+        Self {
+            bw,
+            val:    0,
+            range:  255,
+            bits:   0,
+            saved:  0,
+            run:    0,
+        }
+    }
+    pub fn put_bool(&mut self, bit: bool, prob: u8) -> EncoderResult<()> {
+        let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+        if bit {
+            self.range -= split;
+            self.val   += split;
+        } else {
+            self.range = split;
+        }
+
+        if self.range < 128 {
+            self.renorm()?;
+        }
+        Ok(())
+    }
+    fn flush_run(&mut self, overflow: bool) -> EncoderResult<()> {
+        if self.run > 0 {
+            self.bw.write_byte(self.saved + (overflow as u8))?;
+            if !overflow {
+                for _ in 1..self.run {
+                    self.bw.write_byte(0xFF)?;
+                }
+            } else {
+                for _ in 1..self.run {
+                    self.bw.write_byte(0)?;
+                }
+            }
+            self.run = 0;
+        }
+        Ok(())
+    }
+    fn renorm(&mut self) -> EncoderResult<()> {
+        let bits = (self.range.leading_zeros() & 7) as u8;
+        self.range <<= bits;
+        if self.bits + bits < 23 {
+            self.bits += bits;
+            self.val <<= bits;
+        } else {
+            for _ in 0..bits {
+                if (self.bits == 23) && ((self.val >> 31) != 0) {
+                    self.flush_run(true)?;
+                }
+                self.val <<= 1;
+                self.bits += 1;
+                if self.bits == 24 {
+                    let tbyte = (self.val >> 24) as u8;
+                    let nbyte = (self.val >> 16) as u8;
+                    if tbyte < 0xFF {
+                        self.flush_run(false)?;
+                        if nbyte < 0xFE {
+                            self.bw.write_byte(tbyte)?;
+                        } else {
+                            self.saved = tbyte;
+                            self.run = 1;
+                        }
+                    } else {
+                        self.run += 1;
+                    }
+                    self.val &= 0xFFFFFF;
+                    self.bits -= 8;
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn flush(mut self) -> EncoderResult<()> {
+        self.flush_run(false)?;
+        self.val <<= 24 - self.bits;
+        self.bw.write_u32be(self.val)?;
+        Ok(())
+    }
+
+    pub fn put_bits(&mut self, val: u32, len: u8) -> EncoderResult<()> {
+        let mut mask = 1 << (len - 1);
+        while mask != 0 {
+            self.put_bool((val & mask) != 0, 128)?;
+            mask >>= 1;
+        }
+        Ok(())
+    }
+    pub fn put_flag(&mut self, flag: bool) -> EncoderResult<()> {
+        self.put_bool(flag, 128)
+    }
+    pub fn put_byte(&mut self, val: u8) -> EncoderResult<()> {
+        self.put_bits(u32::from(val), 8)
+    }
+    pub fn write_tree<T: Copy+PartialEq>(&mut self, tree: &[VPTreeDef<T>], probs: &[u8], val: T) -> EncoderResult<()> {
+        let (path, len) = tree_path(tree, val);
+        if len == 0 {
+            return Err(EncoderError::Bug);
+        }
+        for &(bit, idx) in path[..len].iter() {
+            self.put_bool(bit, probs[idx as usize])?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone,Copy)]
+enum CoefBit {
+    // coefficient token tree bit (band, context, probability index)
+    Token(usize, usize, usize),
+    Fixed(u8),
+}
+
+fn level_to_token(level: i16) -> DCTToken {
+    match level.abs() {
+        0           => DCTToken::Zero,
+        1           => DCTToken::One,
+        2           => DCTToken::Two,
+        3           => DCTToken::Three,
+        4           => DCTToken::Four,
+        5  ..=  6   => DCTToken::Cat1,
+        7  ..= 10   => DCTToken::Cat2,
+        11 ..= 18   => DCTToken::Cat3,
+        19 ..= 34   => DCTToken::Cat4,
+        35 ..= 66   => DCTToken::Cat5,
+        _           => DCTToken::Cat6,
+    }
+}
+
+pub const MAX_LEVEL: i16 = 67 + 2047;
+
+// calls the provided function for every bit needed to code the block and reports whether the block has non-zero coefficients
+fn walk_block<F: FnMut(bool, CoefBit)>(levels: &[i16; 16], ctype: usize, pctx: u8, mut put: F) -> u8 {
+    let start = if ctype != 0 { 0 } else { 1 };
+    let mut end = start;
+    for (idx, &level) in levels.iter().enumerate().skip(start) {
+        if level != 0 {
+            end = idx + 1;
+        }
+    }
+
+    let mut has_nz = 0;
+    let mut cval = pctx as usize;
+    for idx in start..16 {
+        let band = COEF_BANDS[idx];
+        if idx >= end {
+            put(false, CoefBit::Token(band, cval, 0));
+            break;
+        }
+        let level = levels[idx];
+        let tok = level_to_token(level);
+        if cval != 0 || idx == start {
+            let (path, len) = tree_path(COEF_TREE, tok);
+            for &(bit, pidx) in path[..len].iter() {
+                put(bit, CoefBit::Token(band, cval, pidx as usize));
+            }
+        } else {
+            let (path, len) = tree_path(COEF_NE_TREE, tok);
+            for &(bit, pidx) in path[..len].iter() {
+                put(bit, CoefBit::Token(band, cval, (pidx as usize) + 1));
+            }
+        }
+        let cat = match tok {
+                DCTToken::Cat1 => Some(0),
+                DCTToken::Cat2 => Some(1),
+                DCTToken::Cat3 => Some(2),
+                DCTToken::Cat4 => Some(3),
+                DCTToken::Cat5 => Some(4),
+                DCTToken::Cat6 => Some(5),
+                _ => None,
+            };
+        if let Some(cat) = cat {
+            let add_probs = &VP56_COEF_ADD_PROBS[cat];
+            let nbits = add_probs.iter().position(|&p| p == 128).unwrap_or(add_probs.len());
+            let add = level.abs() - VP56_COEF_BASE[cat];
+            for (i, &prob) in add_probs[..nbits].iter().enumerate() {
+                put(((add >> (nbits - 1 - i)) & 1) != 0, CoefBit::Fixed(prob));
+            }
+        }
+        if level != 0 {
+            put(level < 0, CoefBit::Fixed(128));
+        }
+        cval = level.abs().min(2) as usize;
+        has_nz |= cval;
+    }
+    if has_nz > 0 { 1 } else { 0 }
+}
+
+pub fn encode_block(bc: &mut BoolEncoder, levels: &[i16; 16], ctype: usize, pctx: u8, probs: &CoeffProbs) -> EncoderResult<u8> {
+    let mut ret = Ok(());
+    let has_nz = walk_block(levels, ctype, pctx, |bit, cbit| {
+            if ret.is_ok() {
+                let prob = match cbit {
+                        CoefBit::Token(band, ctx, idx) => probs[ctype][band][ctx][idx],
+                        CoefBit::Fixed(prob) => prob,
+                    };
+                ret = bc.put_bool(bit, prob);
+            }
+        });
+    ret?;
+    Ok(has_nz)
+}
+
+pub fn estimate_block(levels: &[i16; 16], ctype: usize, pctx: u8, probs: &CoeffProbs) -> (u32, u8) {
+    let mut cost = 0;
+    let has_nz = walk_block(levels, ctype, pctx, |bit, cbit| {
+            let prob = match cbit {
+                    CoefBit::Token(band, ctx, idx) => probs[ctype][band][ctx][idx],
+                    CoefBit::Fixed(prob) => prob,
+                };
+            cost += bit_cost(bit, prob);
+        });
+    (cost, has_nz)
+}
+
+pub fn collect_block_stats(levels: &[i16; 16], ctype: usize, pctx: u8, stats: &mut CoeffStats) -> u8 {
+    walk_block(levels, ctype, pctx, |bit, cbit| {
+            if let CoefBit::Token(band, ctx, idx) = cbit {
+                stats[ctype][band][ctx][idx].add(bit);
+            }
+        })
+}
+
+pub fn encode_coef_prob_updates(bc: &mut BoolEncoder, probs: &mut CoeffProbs, stats: &CoeffStats) -> EncoderResult<()> {
+    for i in 0..4 {
+        for j in 0..8 {
+            for k in 0..3 {
+                for l in 0..11 {
+                    let upd_prob = DCT_UPDATE_PROBS[i][j][k][l];
+                    if let Some(new_prob) = stats[i][j][k][l].get_update(probs[i][j][k][l], upd_prob, 8) {
+                        bc.put_bool(true, upd_prob)?;
+                        bc.put_byte(new_prob)?;
+                        probs[i][j][k][l] = new_prob;
+                    } else {
+                        bc.put_bool(false, upd_prob)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const LONG_VECTOR_ORDER: [usize; 9] = [ 0, 1, 2, 9, 8, 7, 6, 5, 4 ];
+pub const MAX_MV_COMP: i16 = 1023;
+
+fn walk_mv_component<F: FnMut(bool, usize)>(val: i16, mut put: F) {
+    let aval = val.abs();
+    if aval < 8 {
+        put(false, 0);
+        let (path, len) = tree_path(SMALL_MV_TREE, aval);
+        for &(bit, idx) in path[..len].iter() {
+            put(bit, 2 + (idx as usize));
+        }
+    } else {
+        put(true, 0);
+        for &ord in LONG_VECTOR_ORDER.iter() {
+            put(((aval >> ord) & 1) != 0, 9 + ord);
+        }
+        if (aval & 0x3F0) != 0 {
+            put(((aval >> 3) & 1) != 0, 9 + 3);
+        }
+    }
+    if aval != 0 {
+        put(val < 0, 1);
+    }
+}
+
+pub fn encode_mv_component(bc: &mut BoolEncoder, val: i16, probs: &[u8; 19]) -> EncoderResult<()> {
+    let mut ret = Ok(());
+    walk_mv_component(val, |bit, idx| {
+            if ret.is_ok() {
+                ret = bc.put_bool(bit, probs[idx]);
+            }
+        });
+    ret
+}
+
+pub fn estimate_mv_component(val: i16, probs: &[u8; 19]) -> u32 {
+    let mut cost = 0;
+    walk_mv_component(val, |bit, idx| cost += bit_cost(bit, probs[idx]));
+    cost
+}
+
+pub fn collect_mv_stats(val: i16, stats: &mut [ProbCounter; 19]) {
+    walk_mv_component(val, |bit, idx| stats[idx].add(bit));
+}
+
+pub fn encode_mv_prob_updates(bc: &mut BoolEncoder, probs: &mut [[u8; 19]; 2], stats: &[[ProbCounter; 19]; 2]) -> EncoderResult<()> {
+    for comp in 0..2 {
+        for i in 0..19 {
+            let upd_prob = MV_UPDATE_PROBS[comp][i];
+            // MV probabilities are transmitted with seven bits precision
+            let update = stats[comp][i].get_update(probs[comp][i], upd_prob, 7).map(|p| (p >> 1).max(1));
+            if let Some(new_prob) = update {
+                bc.put_bool(true, upd_prob)?;
+                bc.put_bits(u32::from(new_prob), 7)?;
+                probs[comp][i] = new_prob << 1;
+            } else {
+                bc.put_bool(false, upd_prob)?;
+            }
+        }
+    }
+    Ok(())
+}
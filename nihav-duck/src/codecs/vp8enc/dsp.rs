@@ -0,0 +1,108 @@
+pub fn fdct4x4(blk: &mut [i16; 16]) {
+    let mut tmp = [0i32; 16];
+    for (dst, src) in tmp.chunks_mut(4).zip(blk.chunks(4)) {
+        let a1 = (i32::from(src[0]) + i32::from(src[3])) * 8;
+        let b1 = (i32::from(src[1]) + i32::from(src[2])) * 8;
+        let c1 = (i32::from(src[1]) - i32::from(src[2])) * 8;
+        let d1 = (i32::from(src[0]) - i32::from(src[3])) * 8;
+        dst[0] = a1 + b1;
+        dst[2] = a1 - b1;
+        dst[1] = (c1 * 2217 + d1 * 5352 + 14500) >> 12;
+        dst[3] = (d1 * 2217 - c1 * 5352 +  7500) >> 12;
+    }
+    for i in 0..4 {
+        let a1 = tmp[i]     + tmp[i + 12];
+        let b1 = tmp[i + 4] + tmp[i + 8];
+        let c1 = tmp[i + 4] - tmp[i + 8];
+        let d1 = tmp[i]     - tmp[i + 12];
+        blk[i]      = ((a1 + b1 + 7) >> 4) as i16;
+        blk[i + 8]  = ((a1 - b1 + 7) >> 4) as i16;
+        blk[i + 4]  = (((c1 * 2217 + d1 * 5352 + 12000) >> 16) + if d1 != 0 { 1 } else { 0 }) as i16;
+        blk[i + 12] = ((d1 * 2217 - c1 * 5352 + 51000) >> 16) as i16;
+    }
+}
+
+pub fn fwht4x4(blk: &mut [i16; 16]) {
+    let mut tmp = [0i32; 16];
+    for (dst, src) in tmp.chunks_mut(4).zip(blk.chunks(4)) {
+        let a1 = (i32::from(src[0]) + i32::from(src[2])) * 4;
+        let d1 = (i32::from(src[1]) + i32::from(src[3])) * 4;
+        let c1 = (i32::from(src[1]) - i32::from(src[3])) * 4;
+        let b1 = (i32::from(src[0]) - i32::from(src[2])) * 4;
+        dst[0] = a1 + d1 + if a1 != 0 { 1 } else { 0 };
+        dst[1] = b1 + c1;
+        dst[2] = b1 - c1;
+        dst[3] = a1 - d1;
+    }
+    for i in 0..4 {
+        let a1 = tmp[i]     + tmp[i + 8];
+        let d1 = tmp[i + 4] + tmp[i + 12];
+        let c1 = tmp[i + 4] - tmp[i + 12];
+        let b1 = tmp[i]     - tmp[i + 8];
+        let out = [a1 + d1, b1 + c1, b1 - c1, a1 - d1];
+        for (j, &val) in out.iter().enumerate() {
+            let val = if val < 0 { val + 1 } else { val };
+            blk[i + j * 4] = ((val + 3) >> 3) as i16;
+        }
+    }
+}
+
+pub fn get_residue(dst: &mut [i16; 16], src: &[u8], soff: usize, sstride: usize, pred: &[u8], poff: usize, pstride: usize) {
+    for (drow, (srow, prow)) in dst.chunks_mut(4).zip(src[soff..].chunks(sstride).zip(pred[poff..].chunks(pstride))) {
+        for (d, (&s, &p)) in drow.iter_mut().zip(srow.iter().zip(prow.iter())) {
+            *d = i16::from(s) - i16::from(p);
+        }
+    }
+}
+
+pub fn sad(src1: &[u8], off1: usize, stride1: usize, src2: &[u8], off2: usize, stride2: usize, w: usize, h: usize) -> u32 {
+    let mut dist = 0;
+    for (row1, row2) in src1[off1..].chunks(stride1).zip(src2[off2..].chunks(stride2)).take(h) {
+        for (&a, &b) in row1[..w].iter().zip(row2[..w].iter()) {
+            dist += u32::from((i16::from(a) - i16::from(b)).unsigned_abs());
+        }
+    }
+    dist
+}
+
+pub fn sse(src1: &[u8], off1: usize, stride1: usize, src2: &[u8], off2: usize, stride2: usize, w: usize, h: usize) -> u32 {
+    let mut dist = 0;
+    for (row1, row2) in src1[off1..].chunks(stride1).zip(src2[off2..].chunks(stride2)).take(h) {
+        for (&a, &b) in row1[..w].iter().zip(row2[..w].iter()) {
+            let diff = i32::from(a) - i32::from(b);
+            dist += (diff * diff) as u32;
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::super::vp8dsp::*;
+
+    #[test]
+    fn test_transforms() {
+        let mut blk = [0i16; 16];
+        for (i, el) in blk.iter_mut().enumerate() {
+            *el = ((i * 37) % 61) as i16 - 30;
+        }
+        let orig = blk;
+        fdct4x4(&mut blk);
+        idct4x4(&mut blk);
+        for (&a, &b) in blk.iter().zip(orig.iter()) {
+            assert!((a - b).abs() <= 1);
+        }
+
+        let mut dcs = [0i16; 16];
+        for (i, el) in dcs.iter_mut().enumerate() {
+            *el = (i as i16) * 50 - 400;
+        }
+        let orig = dcs;
+        fwht4x4(&mut dcs);
+        iwht4x4(&mut dcs);
+        for (&a, &b) in dcs.iter().zip(orig.iter()) {
+            assert!((a - b).abs() <= 1);
+        }
+    }
+}
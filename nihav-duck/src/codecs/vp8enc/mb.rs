@@ -0,0 +1,984 @@
+use nihav_core::frame::*;
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::super::vpcommon::*;
+use super::super::vp78::*;
+use super::super::vp78data::*;
+use super::super::vp78dsp::*;
+use super::super::vp8data::*;
+use super::super::vp8dsp::*;
+use super::coder::*;
+use super::dsp::*;
+
+#[derive(Clone,Copy,PartialEq,Debug,Default)]
+pub enum VP8Ref {
+    #[default]
+    Intra,
+    Last,
+    Golden,
+    AltRef,
+}
+
+#[derive(Clone,Copy)]
+pub struct MBInfo {
+    pub mb_type:    VPMBType,
+    pub ymode:      PredMode,
+    pub uvmode:     PredMode,
+    pub rframe:     VP8Ref,
+    pub mv:         MV,
+    pub has_y2:     bool,
+    pub skip:       bool,
+}
+
+impl Default for MBInfo {
+    fn default() -> Self {
+        Self {
+            mb_type:    VPMBType::Intra,
+            ymode:      PredMode::DCPred,
+            uvmode:     PredMode::DCPred,
+            rframe:     VP8Ref::Intra,
+            mv:         ZERO_MV,
+            has_y2:     true,
+            skip:       false,
+        }
+    }
+}
+
+pub type MBLevels = [[i16; 16]; 25];
+
+#[derive(Clone,Copy)]
+pub struct ModeProbs {
+    pub prob_intra: u8,
+    pub prob_last:  u8,
+    pub prob_gold:  u8,
+    pub prob_skip:  u8,
+}
+
+impl Default for ModeProbs {
+    fn default() -> Self {
+        Self {
+            prob_intra: 64,
+            prob_last:  192,
+            prob_gold:  128,
+            prob_skip:  128,
+        }
+    }
+}
+
+fn has_nonzero(levels: &[i16; 16]) -> bool {
+    levels.iter().any(|&el| el != 0)
+}
+
+/// Walks macroblock blocks in coding order maintaining non-zero coefficient contexts the same way decoder does.
+pub fn process_mb_tokens<F: FnMut(&[i16; 16], usize, u8) -> u8>(pcache: &mut PredCache, levels: &MBLevels, has_y2: bool, skip: bool, mb_x: usize, mut f: F) {
+    if skip {
+        let y2_left = pcache.y2_pred_left;
+        let y2_top  = pcache.y2_pred.data[pcache.y2_pred.xpos + mb_x - pcache.y2_pred.stride];
+        pcache.reset_left();
+        if !has_y2 {
+            pcache.y2_pred_left = y2_left;
+            pcache.y2_pred.data[pcache.y2_pred.xpos + mb_x] = y2_top;
+        }
+        return;
+    }
+    let ytype = if has_y2 {
+            let pred = &mut pcache.y2_pred;
+            let pidx = pred.xpos + mb_x;
+            let pctx = pcache.y2_pred_left + pred.data[pidx - pred.stride];
+            let has_nz = f(&levels[24], 1, pctx);
+            pred.data[pidx] = has_nz;
+            pcache.y2_pred_left = has_nz;
+            0
+        } else {
+            let pred = &mut pcache.y2_pred;
+            let pidx = pred.xpos + mb_x;
+            pred.data[pidx] = pred.data[pidx - pred.stride];
+            3
+        };
+    for (i, blk) in levels[..16].iter().enumerate() {
+        let bx = i & 3;
+        let by = i >> 2;
+        let pred = &mut pcache.y_pred;
+        let pidx = pred.xpos + mb_x * 4 + bx + by * pred.stride;
+        let pctx = pcache.y_pred_left[by] + pred.data[pidx - pred.stride];
+        let has_nz = f(blk, ytype, pctx);
+        pred.data[pidx] = has_nz;
+        pcache.y_pred_left[by] = has_nz;
+    }
+    for (i, blk) in levels[16..20].iter().enumerate() {
+        let bx = i & 1;
+        let by = i >> 1;
+        let pred = &mut pcache.u_pred;
+        let pidx = pred.xpos + mb_x * 2 + bx + by * pred.stride;
+        let pctx = pcache.u_pred_left[by] + pred.data[pidx - pred.stride];
+        let has_nz = f(blk, 2, pctx);
+        pred.data[pidx] = has_nz;
+        pcache.u_pred_left[by] = has_nz;
+    }
+    for (i, blk) in levels[20..24].iter().enumerate() {
+        let bx = i & 1;
+        let by = i >> 1;
+        let pred = &mut pcache.v_pred;
+        let pidx = pred.xpos + mb_x * 2 + bx + by * pred.stride;
+        let pctx = pcache.v_pred_left[by] + pred.data[pidx - pred.stride];
+        let has_nz = f(blk, 2, pctx);
+        pred.data[pidx] = has_nz;
+        pcache.v_pred_left[by] = has_nz;
+    }
+}
+
+fn quant_block(coeffs: &[i16; 16], levels: &mut [i16; 16], qmat: &[i16; 16], start: usize) {
+    *levels = [0; 16];
+    for (idx, level) in levels.iter_mut().enumerate().skip(start) {
+        let coef = i32::from(coeffs[DEFAULT_SCAN_ORDER[idx]]);
+        let q = i32::from(qmat[idx]);
+        // DC is rounded to the nearest value while AC coefficients have a slight deadzone
+        let bias = if idx == 0 { q / 2 } else { q * 3 / 8 };
+        let max_level = i32::from(MAX_LEVEL).min(i32::from(i16::MAX) / q);
+        let aval = ((coef.abs() + bias) / q).min(max_level);
+        *level = if coef < 0 { -aval } else { aval } as i16;
+    }
+}
+
+fn dequant_block(levels: &[i16; 16], qmat: &[i16; 16], start: usize) -> [i16; 16] {
+    let mut coeffs = [0; 16];
+    for (idx, (&level, &q)) in levels.iter().zip(qmat.iter()).enumerate().skip(start) {
+        coeffs[DEFAULT_SCAN_ORDER[idx]] = level.wrapping_mul(q);
+    }
+    coeffs
+}
+
+fn inv_transform(coeffs: &mut [i16; 16], has_ac: bool) {
+    if has_ac {
+        idct4x4(coeffs);
+    } else if coeffs[0] != 0 {
+        idct4x4_dc(coeffs);
+    }
+}
+
+fn put_mb(dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, recon: &MBRecon) {
+    let ystride = dframe.stride[0];
+    let yoff = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+    for (dline, sline) in dframe.data[yoff..].chunks_mut(ystride).zip(recon.y.chunks(16)) {
+        dline[..16].copy_from_slice(sline);
+    }
+    for (plane, src) in [&recon.u, &recon.v].iter().enumerate() {
+        let stride = dframe.stride[plane + 1];
+        let off = dframe.offset[plane + 1] + mb_x * 8 + mb_y * 8 * stride;
+        for (dline, sline) in dframe.data[off..].chunks_mut(stride).zip(src.chunks(8)) {
+            dline[..8].copy_from_slice(sline);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MBRecon {
+    y:  [u8; 256],
+    u:  [u8; 64],
+    v:  [u8; 64],
+}
+
+impl MBRecon {
+    fn new() -> Self {
+        Self { y: [0; 256], u: [0; 64], v: [0; 64] }
+    }
+    fn save_luma(&mut self, dframe: &NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize) {
+        let ystride = dframe.stride[0];
+        let yoff = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+        for (dline, sline) in self.y.chunks_mut(16).zip(dframe.data[yoff..].chunks(ystride)) {
+            dline.copy_from_slice(&sline[..16]);
+        }
+    }
+    fn save_chroma(&mut self, dframe: &NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize) {
+        for (plane, dst) in [&mut self.u, &mut self.v].iter_mut().enumerate() {
+            let stride = dframe.stride[plane + 1];
+            let off = dframe.offset[plane + 1] + mb_x * 8 + mb_y * 8 * stride;
+            for (dline, sline) in dst.chunks_mut(8).zip(dframe.data[off..].chunks(stride)) {
+                dline.copy_from_slice(&sline[..8]);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MBDecision {
+    cost:   u64,
+    info:   MBInfo,
+    levels: MBLevels,
+    bmodes: [PredMode; 16],
+    recon:  MBRecon,
+}
+
+impl MBDecision {
+    fn new() -> Self {
+        Self {
+            cost:   u64::MAX,
+            info:   MBInfo::default(),
+            levels: [[0; 16]; 25],
+            bmodes: [PredMode::DCPred; 16],
+            recon:  MBRecon::new(),
+        }
+    }
+}
+
+// chroma intra prediction result shared between luma intra candidates
+struct ChromaDecision {
+    mode:   PredMode,
+    levels: [[i16; 16]; 8],
+    dist:   u32,
+    bits:   u32,
+    recon:  MBRecon,
+}
+
+const INTRA16_MODES: [PredMode; 4] = [ PredMode::DCPred, PredMode::VPred, PredMode::HPred, PredMode::TMPred ];
+const BPRED_MODES: [PredMode; 10] = [
+    PredMode::DCPred, PredMode::TMPred, PredMode::VPred, PredMode::HPred, PredMode::LDPred,
+    PredMode::RDPred, PredMode::VRPred, PredMode::VLPred, PredMode::HDPred, PredMode::HUPred
+];
+
+const MV_SEARCH_STEPS: [(i16, i16); 4] = [ (0, -4), (-4, 0), (4, 0), (0, 4) ];
+const MV_REFINE_STEPS: [(i16, i16); 8] = [ (-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1) ];
+
+pub struct FrameEncoder {
+    pub mb_w:       usize,
+    pub mb_h:       usize,
+    src:            [Vec<u8>; 3],
+    src_stride:     [usize; 3],
+
+    pub mb_info:    Vec<MBInfo>,
+    pub levels:     Vec<MBLevels>,
+    pub ymodes:     Vec<PredMode>,
+    pub ymode_stride: usize,
+    pub pcache:     PredCache,
+
+    pub coef_probs: CoeffProbs,
+    pub mv_probs:   [[u8; 19]; 2],
+    pub mprobs:     ModeProbs,
+
+    qmat:           [[i16; 16]; 3],
+    lambda:         u64,
+    lambda_sad:     u64,
+    pub me_range:   i16,
+
+    mc_buf:         Vec<u8>,
+    pred_buf:       [u8; 256],
+    ipred_ctx:      IPredContext,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self {
+            mb_w:       0,
+            mb_h:       0,
+            src:        [Vec::new(), Vec::new(), Vec::new()],
+            src_stride: [0; 3],
+
+            mb_info:    Vec::new(),
+            levels:     Vec::new(),
+            ymodes:     Vec::new(),
+            ymode_stride: 0,
+            pcache:     PredCache::new(),
+
+            coef_probs: DEFAULT_DCT_PROBS,
+            mv_probs:   DEFAULT_MV_PROBS,
+            mprobs:     ModeProbs::default(),
+
+            qmat:       [[0; 16]; 3],
+            lambda:     1,
+            lambda_sad: 1,
+            me_range:   16,
+
+            mc_buf:     vec![0; 32 * 32],
+            pred_buf:   [0; 256],
+            ipred_ctx:  IPredContext::default(),
+        }
+    }
+    pub fn resize(&mut self, mb_w: usize, mb_h: usize) {
+        self.mb_w = mb_w;
+        self.mb_h = mb_h;
+        self.src_stride = [mb_w * 16, mb_w * 8, mb_w * 8];
+        self.src[0].resize(mb_w * 16 * mb_h * 16, 0);
+        self.src[1].resize(mb_w * 8 * mb_h * 8, 0);
+        self.src[2].resize(mb_w * 8 * mb_h * 8, 0);
+
+        self.mb_info.clear();
+        self.mb_info.resize(mb_w * mb_h, MBInfo::default());
+        self.levels.clear();
+        self.levels.resize(mb_w * mb_h, [[0; 16]; 25]);
+        self.ymode_stride = mb_w * 4;
+        self.ymodes.clear();
+        self.ymodes.resize(self.ymode_stride * mb_h * 4, PredMode::DCPred);
+        self.pcache.resize(mb_w);
+    }
+    pub fn read_frame(&mut self, vbuf: &NAVideoBuffer<u8>) {
+        for plane in 0..3 {
+            let (w, h) = vbuf.get_dimensions(plane);
+            let sstride = vbuf.get_stride(plane);
+            let src = &vbuf.get_data()[vbuf.get_offset(plane)..];
+            let dstride = self.src_stride[plane];
+            let dst = &mut self.src[plane];
+            for (dline, sline) in dst.chunks_mut(dstride).zip(src.chunks(sstride)).take(h) {
+                dline[..w].copy_from_slice(&sline[..w]);
+                let last = dline[w - 1];
+                for el in dline[w..].iter_mut() {
+                    *el = last;
+                }
+            }
+            let last_start = (h - 1) * dstride;
+            for y in h..(dst.len() / dstride) {
+                dst.copy_within(last_start..last_start + dstride, y * dstride);
+            }
+        }
+    }
+    pub fn frame_sse(&self, vbuf: &NAVideoBuffer<u8>) -> u64 {
+        let mut dist = 0;
+        for plane in 0..3 {
+            let size = if plane == 0 { 16 } else { 8 };
+            let dstride = vbuf.get_stride(plane);
+            let sstride = self.src_stride[plane];
+            // lines are processed one by one to avoid overflow
+            for y in 0..self.mb_h * size {
+                dist += u64::from(sse(vbuf.get_data(), vbuf.get_offset(plane) + y * dstride, dstride,
+                                      &self.src[plane], y * sstride, sstride, self.mb_w * size, 1));
+            }
+        }
+        dist
+    }
+    pub fn set_quant(&mut self, q: usize) {
+        let y_dc = DC_QUANTS[q];
+        let ac   = AC_QUANTS[q];
+        self.qmat[0] = [ac; 16];
+        self.qmat[0][0] = y_dc;
+        self.qmat[1] = [ac; 16];
+        self.qmat[1][0] = y_dc.min(132);
+        self.qmat[2] = [(i32::from(ac) * 155 / 100).max(8) as i16; 16];
+        self.qmat[2][0] = y_dc * 2;
+
+        let ac = u64::from(ac as u16);
+        self.lambda     = (ac * ac / 5).max(1);
+        self.lambda_sad = (ac / 2).max(1);
+    }
+    fn rd_cost(&self, dist: u32, bits: u32) -> u64 {
+        u64::from(dist) * 8 + self.lambda * u64::from(bits)
+    }
+
+    pub fn clip_mv(&self, mv: MV, mb_x: usize, mb_y: usize) -> MV {
+        let pos_x = (mb_x as i32) * 16 * 4;
+        let pos_y = (mb_y as i32) * 16 * 4;
+        let mv_x = (pos_x + i32::from(mv.x)).max(-16 * 4).min((self.mb_w as i32) * 16 * 4);
+        let mv_y = (pos_y + i32::from(mv.y)).max(-16 * 4).min((self.mb_h as i32) * 16 * 4);
+        MV {x: (mv_x - pos_x) as i16, y: (mv_y - pos_y) as i16 }
+    }
+    /// Finds motion vector predictors exactly like decoder does (sign bias is never set by the encoder).
+    pub fn find_mv_pred(&self, mb_x: usize, mb_y: usize) -> ([u8; 4], MV, MV, MV) {
+        const OFFS: [(u8, u8, u8); 3] = [(0, 1, 2), (1, 0, 2), (1, 1, 1)];
+        let mut mvs = [ZERO_MV; 3];
+        let mut mvc = [0; 3];
+        let mut num_mv = 0;
+
+        let mut nearest_mv = ZERO_MV;
+        let mut near_mv = ZERO_MV;
+
+        for &(x, y, weight) in OFFS.iter() {
+            if (x == 1 && mb_x == 0) || (y == 1 && mb_y == 0) {
+                continue;
+            }
+            let mb_idx = mb_x - usize::from(x) + (mb_y - usize::from(y)) * self.mb_w;
+            if self.mb_info[mb_idx].mb_type.is_intra() {
+                continue;
+            }
+            let mv = self.mb_info[mb_idx].mv;
+            if let Some(i) = mvs[..num_mv].iter().position(|&el| el == mv) {
+                mvc[i] += weight;
+            } else {
+                mvs[num_mv] = mv;
+                mvc[num_mv] = weight;
+                num_mv += 1;
+            }
+        }
+
+        match num_mv {
+            2 if mvc[0] < mvc[1] => {
+                mvs.swap(0, 1);
+                mvc.swap(0, 1);
+            },
+            3 => {
+                if mvc[1] < mvc[2] {
+                    mvs.swap(1, 2);
+                    mvc.swap(1, 2);
+                }
+                if mvc[0] < mvc[1] {
+                    mvs.swap(0, 1);
+                    mvc.swap(0, 1);
+                }
+                if mvc[1] < mvc[2] {
+                    mvs.swap(1, 2);
+                    mvc.swap(1, 2);
+                }
+            },
+            _ => {},
+        };
+
+        let mut best_mv = mvs[0];
+
+        let mut ct = [0; 4];
+        for (&mv, &count) in mvs[..num_mv].iter().zip(mvc.iter()) {
+            if mv != ZERO_MV {
+                if nearest_mv == ZERO_MV {
+                    nearest_mv = mv;
+                    if mvc[0] == count {
+                        best_mv = mv;
+                    }
+                    ct[1] = count;
+                } else {
+                    near_mv = mv;
+                    ct[2] = count;
+                    break;
+                }
+            }
+        }
+        for (&mv, &count) in mvs[..num_mv].iter().zip(mvc.iter()) {
+            if mv == ZERO_MV {
+                ct[0] = count;
+                break;
+            }
+        }
+        let best_mv = self.clip_mv(best_mv, mb_x, mb_y);
+
+        let mvprobs = [MV_REF_PROBS[ct[0] as usize][0],
+                       MV_REF_PROBS[ct[1] as usize][1],
+                       MV_REF_PROBS[ct[2] as usize][2],
+                       MV_REF_PROBS[ct[3] as usize][3]];
+
+        (mvprobs, self.clip_mv(nearest_mv, mb_x, mb_y), self.clip_mv(near_mv, mb_x, mb_y), best_mv)
+    }
+
+    fn estimate_luma_tokens(&self, levels: &MBLevels, has_y2: bool, mb_x: usize) -> u32 {
+        let pc = &self.pcache;
+        let mut bits = 0;
+        let ytype = if has_y2 {
+                let pctx = pc.y2_pred_left + pc.y2_pred.data[pc.y2_pred.xpos + mb_x - pc.y2_pred.stride];
+                bits += estimate_block(&levels[24], 1, pctx, &self.coef_probs).0;
+                0
+            } else {
+                3
+            };
+        let mut left = pc.y_pred_left;
+        let mut top = [0; 4];
+        top.copy_from_slice(&pc.y_pred.data[pc.y_pred.xpos + mb_x * 4 - pc.y_pred.stride..][..4]);
+        for (i, blk) in levels[..16].iter().enumerate() {
+            let (bx, by) = (i & 3, i >> 2);
+            let (nbits, has_nz) = estimate_block(blk, ytype, left[by] + top[bx], &self.coef_probs);
+            bits += nbits;
+            left[by] = has_nz;
+            top[bx]  = has_nz;
+        }
+        bits
+    }
+    fn estimate_chroma_tokens(&self, levels: &[[i16; 16]], mb_x: usize) -> u32 {
+        let pc = &self.pcache;
+        let mut bits = 0;
+        for (plane, blocks) in levels.chunks(4).enumerate() {
+            let (pred, mut left) = if plane == 0 { (&pc.u_pred, pc.u_pred_left) } else { (&pc.v_pred, pc.v_pred_left) };
+            let mut top = [0; 2];
+            top.copy_from_slice(&pred.data[pred.xpos + mb_x * 2 - pred.stride..][..2]);
+            for (i, blk) in blocks.iter().enumerate() {
+                let (bx, by) = (i & 1, i >> 1);
+                let (nbits, has_nz) = estimate_block(blk, 2, left[by] + top[bx], &self.coef_probs);
+                bits += nbits;
+                left[by] = has_nz;
+                top[bx]  = has_nz;
+            }
+        }
+        bits
+    }
+
+    // codes luma predicted in the frame as 16x16 block with Y2, reconstructs it and returns the distortion
+    fn code_luma16(&self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, levels: &mut MBLevels) -> u32 {
+        let ystride = dframe.stride[0];
+        let yoff = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+        let sstride = self.src_stride[0];
+        let soff = mb_x * 16 + mb_y * 16 * sstride;
+        let mut coeffs = [[0i16; 16]; 16];
+        let mut dcs = [0i16; 16];
+        for (i, (blk, dc)) in coeffs.iter_mut().zip(dcs.iter_mut()).enumerate() {
+            get_residue(blk, &self.src[0], soff + (i & 3) * 4 + (i >> 2) * 4 * sstride, sstride,
+                        dframe.data, yoff + (i & 3) * 4 + (i >> 2) * 4 * ystride, ystride);
+            fdct4x4(blk);
+            *dc = blk[0];
+        }
+        fwht4x4(&mut dcs);
+        quant_block(&dcs, &mut levels[24], &self.qmat[2], 0);
+        for (blk, lvl) in coeffs.iter().zip(levels.iter_mut()) {
+            quant_block(blk, lvl, &self.qmat[0], 1);
+        }
+
+        let mut y2 = dequant_block(&levels[24], &self.qmat[2], 0);
+        if has_nonzero(&levels[24]) {
+            iwht4x4(&mut y2);
+        } else if y2[0] != 0 {
+            iwht4x4_dc(&mut y2);
+        }
+        for (i, (lvl, &dc)) in levels[..16].iter().zip(y2.iter()).enumerate() {
+            let mut blk = dequant_block(lvl, &self.qmat[0], 1);
+            blk[0] = dc;
+            inv_transform(&mut blk, has_nonzero(lvl));
+            add_coeffs4x4(dframe.data, yoff + (i & 3) * 4 + (i >> 2) * 4 * ystride, ystride, &blk);
+        }
+        sse(dframe.data, yoff, ystride, &self.src[0], soff, sstride, 16, 16)
+    }
+    // codes chroma predicted in the frame, reconstructs it and returns the distortion
+    fn code_chroma(&self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, levels: &mut [[i16; 16]]) -> u32 {
+        let mut dist = 0;
+        for (plane, lvls) in levels.chunks_mut(4).enumerate() {
+            let dstride = dframe.stride[plane + 1];
+            let doff = dframe.offset[plane + 1] + mb_x * 8 + mb_y * 8 * dstride;
+            let sstride = self.src_stride[plane + 1];
+            let soff = mb_x * 8 + mb_y * 8 * sstride;
+            for (i, lvl) in lvls.iter_mut().enumerate() {
+                let bdoff = doff + (i & 1) * 4 + (i >> 1) * 4 * dstride;
+                let bsoff = soff + (i & 1) * 4 + (i >> 1) * 4 * sstride;
+                let mut blk = [0; 16];
+                get_residue(&mut blk, &self.src[plane + 1], bsoff, sstride, dframe.data, bdoff, dstride);
+                fdct4x4(&mut blk);
+                quant_block(&blk, lvl, &self.qmat[1], 0);
+                let mut blk = dequant_block(lvl, &self.qmat[1], 0);
+                inv_transform(&mut blk, has_nonzero(lvl));
+                add_coeffs4x4(dframe.data, bdoff, dstride, &blk);
+            }
+            dist += sse(dframe.data, doff, dstride, &self.src[plane + 1], soff, sstride, 8, 8);
+        }
+        dist
+    }
+
+    fn predict_intra16(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, mode: PredMode) {
+        let has_top = mb_y > 0;
+        let has_left = mb_x > 0;
+        let ystride = dframe.stride[0];
+        let ydst = &mut dframe.data[dframe.offset[0]..];
+        let yoff = mb_x * 16 + mb_y * 16 * ystride;
+        let ctx = &mut self.ipred_ctx;
+        ctx.has_top  = has_top;
+        ctx.has_left = has_left;
+        ctx.fill(ydst, yoff, ystride, 16, 16);
+        if !has_top && mode == PredMode::VPred {
+            IPred16x16::ipred_const(ydst, yoff, ystride, 0x7F)
+        } else if !has_left && mode == PredMode::HPred {
+            IPred16x16::ipred_const(ydst, yoff, ystride, 0x81)
+        } else {
+            match mode {
+                PredMode::DCPred => IPred16x16::ipred_dc(ydst, yoff, ystride, ctx),
+                PredMode::HPred  => IPred16x16::ipred_h (ydst, yoff, ystride, ctx),
+                PredMode::VPred  => IPred16x16::ipred_v (ydst, yoff, ystride, ctx),
+                PredMode::TMPred => IPred16x16::ipred_tm(ydst, yoff, ystride, ctx),
+                _ => unreachable!(),
+            };
+        }
+    }
+    fn predict_chroma(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, mode: PredMode) {
+        let has_top = mb_y > 0;
+        let has_left = mb_x > 0;
+        for plane in 1..3 {
+            let stride = dframe.stride[plane];
+            let off = dframe.offset[plane] + mb_x * 8 + mb_y * 8 * stride;
+            let dst = &mut dframe.data[0..];
+            let ctx = &mut self.ipred_ctx;
+            ctx.has_top  = has_top;
+            ctx.has_left = has_left;
+            ctx.fill(dst, off, stride, 8, 8);
+            if !has_top && mode == PredMode::VPred {
+                IPred8x8::ipred_const(dst, off, stride, 0x7F);
+            } else if !has_left && mode == PredMode::HPred {
+                IPred8x8::ipred_const(dst, off, stride, 0x81);
+            } else {
+                match mode {
+                    PredMode::DCPred => IPred8x8::ipred_dc(dst, off, stride, ctx),
+                    PredMode::HPred  => IPred8x8::ipred_h (dst, off, stride, ctx),
+                    PredMode::VPred  => IPred8x8::ipred_v (dst, off, stride, ctx),
+                    PredMode::TMPred => IPred8x8::ipred_tm(dst, off, stride, ctx),
+                    _ => unreachable!(),
+                };
+            }
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn predict_subblock(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, x: usize, y: usize, bmode: PredMode, tr_save: &mut [u8; 16], tr_edge: u8) {
+        let has_top = mb_y > 0;
+        let has_left = mb_x > 0;
+        let ystride = dframe.stride[0];
+        let ydst = &mut dframe.data[dframe.offset[0]..];
+        let cur_yoff = mb_x * 16 + mb_y * 16 * ystride + x * 4 + y * 4 * ystride;
+        let ctx = &mut self.ipred_ctx;
+        ctx.has_top  = has_top || y > 0;
+        ctx.has_left = has_left || x > 0;
+        let has_tr = ctx.has_top && ((x < 3) || ((y == 0) && (mb_x < self.mb_w - 1)));
+        let has_dl = ctx.has_left && (x == 0) && (y < 3);
+        ctx.fill(ydst, cur_yoff, ystride,
+                 if has_tr { 8 } else { 4 },
+                 if has_dl { 8 } else { 4 });
+
+        if !has_top && y == 0 && (has_left || x > 0) && bmode != PredMode::TMPred {
+            ctx.top = [0x7F; 16];
+            ctx.tl = 0x7F;
+        }
+        if !has_left && x == 0 && (has_top || y > 0) && bmode != PredMode::TMPred {
+            ctx.left = [0x81; 16];
+            ctx.tl = 0x81;
+        }
+        if !has_left && !has_top && x == 0 && y == 0 && bmode != PredMode::DCPred {
+            ctx.top = [0x7F; 16];
+            ctx.left = [0x81; 16];
+            ctx.tl = 0x7F;
+        }
+
+        if !has_tr {
+            ctx.top[4..8].copy_from_slice(&tr_save[x * 4..][..4]);
+        } else {
+            tr_save[x * 4..][..4].copy_from_slice(&ctx.top[4..8]);
+        }
+        if (mb_x == self.mb_w - 1) && has_top && (x == 3) {
+            for el in ctx.top[4..8].iter_mut() {
+                *el = tr_edge;
+            }
+        }
+        match bmode {
+            PredMode::DCPred => IPred4x4::ipred_dc(ydst, cur_yoff, ystride, ctx),
+            PredMode::TMPred => IPred4x4::ipred_tm(ydst, cur_yoff, ystride, ctx),
+            PredMode::HPred  => IPred4x4::ipred_he(ydst, cur_yoff, ystride, ctx),
+            PredMode::VPred  => IPred4x4::ipred_ve(ydst, cur_yoff, ystride, ctx),
+            PredMode::LDPred => IPred4x4::ipred_ld(ydst, cur_yoff, ystride, ctx),
+            PredMode::RDPred => IPred4x4::ipred_rd(ydst, cur_yoff, ystride, ctx),
+            PredMode::VRPred => IPred4x4::ipred_vr(ydst, cur_yoff, ystride, ctx),
+            PredMode::VLPred => IPred4x4::ipred_vl(ydst, cur_yoff, ystride, ctx),
+            PredMode::HDPred => IPred4x4::ipred_hd(ydst, cur_yoff, ystride, ctx),
+            PredMode::HUPred => IPred4x4::ipred_hu(ydst, cur_yoff, ystride, ctx),
+            _ => unreachable!(),
+        };
+    }
+    // codes a single 4x4 luma block predicted in the frame, returns distortion and whether it has coefficients
+    fn code_subblock(&self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, x: usize, y: usize, levels: &mut [i16; 16]) -> u32 {
+        let ystride = dframe.stride[0];
+        let yoff = dframe.offset[0] + mb_x * 16 + x * 4 + (mb_y * 16 + y * 4) * ystride;
+        let sstride = self.src_stride[0];
+        let soff = mb_x * 16 + x * 4 + (mb_y * 16 + y * 4) * sstride;
+        let mut blk = [0; 16];
+        get_residue(&mut blk, &self.src[0], soff, sstride, dframe.data, yoff, ystride);
+        fdct4x4(&mut blk);
+        quant_block(&blk, levels, &self.qmat[0], 0);
+        let mut blk = dequant_block(levels, &self.qmat[0], 0);
+        inv_transform(&mut blk, has_nonzero(levels));
+        add_coeffs4x4(dframe.data, yoff, ystride, &blk);
+        sse(dframe.data, yoff, ystride, &self.src[0], soff, sstride, 4, 4)
+    }
+
+    fn decide_chroma(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, is_intra: bool) -> ChromaDecision {
+        let mut best = ChromaDecision { mode: PredMode::DCPred, levels: [[0; 16]; 8], dist: 0, bits: 0, recon: MBRecon::new() };
+        let mut best_cost = u64::MAX;
+        let uv_probs = if is_intra { KF_UV_MODE_TREE_PROBS } else { UV_MODE_TREE_PROBS };
+        for &mode in INTRA16_MODES.iter() {
+            let mut levels = [[0; 16]; 8];
+            self.predict_chroma(dframe, mb_x, mb_y, mode);
+            let dist = self.code_chroma(dframe, mb_x, mb_y, &mut levels);
+            let bits = self.estimate_chroma_tokens(&levels, mb_x) + tree_cost(UV_MODE_TREE, uv_probs, mode);
+            let cost = self.rd_cost(dist, bits);
+            if cost < best_cost {
+                best_cost = cost;
+                best.mode   = mode;
+                best.levels = levels;
+                best.dist   = dist;
+                best.bits   = bits;
+                best.recon.save_chroma(dframe, mb_x, mb_y);
+            }
+        }
+        best
+    }
+    fn finish_decision(&self, cand: &mut MBDecision, dist: u32, mut bits: u32) {
+        cand.info.skip = !cand.levels.iter().any(has_nonzero);
+        bits += bit_cost(cand.info.skip, self.mprobs.prob_skip);
+        cand.cost = self.rd_cost(dist, bits);
+    }
+    fn try_intra16(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, is_intra: bool, chroma: &ChromaDecision) -> MBDecision {
+        let mut best = MBDecision::new();
+        for &mode in INTRA16_MODES.iter() {
+            let mut cand = MBDecision::new();
+            self.predict_intra16(dframe, mb_x, mb_y, mode);
+            let ldist = self.code_luma16(dframe, mb_x, mb_y, &mut cand.levels);
+            cand.levels[16..24].copy_from_slice(&chroma.levels);
+            let mut bits = self.estimate_luma_tokens(&cand.levels, true, mb_x) + chroma.bits;
+            bits += if is_intra {
+                    tree_cost(KF_Y_MODE_TREE, KF_Y_MODE_TREE_PROBS, mode)
+                } else {
+                    bit_cost(false, self.mprobs.prob_intra) + tree_cost(Y_MODE_TREE, Y_MODE_TREE_PROBS, mode)
+                };
+            cand.info = MBInfo { ymode: mode, uvmode: chroma.mode, ..MBInfo::default() };
+            self.finish_decision(&mut cand, ldist + chroma.dist, bits);
+            if cand.cost < best.cost {
+                cand.recon.save_luma(dframe, mb_x, mb_y);
+                cand.recon.u = chroma.recon.u;
+                cand.recon.v = chroma.recon.v;
+                cand.bmodes = [mode; 16];
+                best = cand;
+            }
+        }
+        best
+    }
+    fn try_bpred(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, is_intra: bool, chroma: &ChromaDecision) -> MBDecision {
+        let mut cand = MBDecision::new();
+        let mut tr_save = [0x7Fu8; 16];
+        let tr_edge = if mb_y > 0 {
+                let ystride = dframe.stride[0];
+                dframe.data[dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride - ystride + 15]
+            } else {
+                0x7F
+            };
+        let pc = &self.pcache;
+        let mut left = pc.y_pred_left;
+        let mut top = [0; 4];
+        top.copy_from_slice(&pc.y_pred.data[pc.y_pred.xpos + mb_x * 4 - pc.y_pred.stride..][..4]);
+
+        let mut dist = 0;
+        let mut bits = if is_intra {
+                tree_cost(KF_Y_MODE_TREE, KF_Y_MODE_TREE_PROBS, PredMode::BPred)
+            } else {
+                bit_cost(false, self.mprobs.prob_intra) + tree_cost(Y_MODE_TREE, Y_MODE_TREE_PROBS, PredMode::BPred)
+            };
+        let iidx = mb_x * 4 + mb_y * 4 * self.ymode_stride;
+        for y in 0..4 {
+            for x in 0..4 {
+                let bmode_probs = if is_intra {
+                        let top_mode = if (y > 0) || (mb_y > 0) {
+                                self.ymodes[iidx + x + y * self.ymode_stride - self.ymode_stride]
+                            } else {
+                                PredMode::DCPred
+                            };
+                        let left_mode = if (x > 0) || (mb_x > 0) {
+                                self.ymodes[iidx + x + y * self.ymode_stride - 1]
+                            } else {
+                                PredMode::DCPred
+                            };
+                        &KF_B_MODE_TREE_PROBS[top_mode.to_b_index()][left_mode.to_b_index()]
+                    } else {
+                        B_MODE_TREE_PROBS
+                    };
+                let pctx = left[y] + top[x];
+                let mut best_cost = u64::MAX;
+                let mut best_mode = PredMode::DCPred;
+                let mut best_levels = [0; 16];
+                let mut best_dist = 0;
+                let mut best_bits = 0;
+                let mut best_nz = 0;
+                for &bmode in BPRED_MODES.iter() {
+                    let mut tmp_tr = tr_save;
+                    let mut levels = [0; 16];
+                    self.predict_subblock(dframe, mb_x, mb_y, x, y, bmode, &mut tmp_tr, tr_edge);
+                    let bdist = self.code_subblock(dframe, mb_x, mb_y, x, y, &mut levels);
+                    let (tbits, has_nz) = estimate_block(&levels, 3, pctx, &self.coef_probs);
+                    let bbits = tbits + tree_cost(B_MODE_TREE, bmode_probs, bmode);
+                    let cost = self.rd_cost(bdist, bbits);
+                    if cost < best_cost {
+                        best_cost   = cost;
+                        best_mode   = bmode;
+                        best_levels = levels;
+                        best_dist   = bdist;
+                        best_bits   = bbits;
+                        best_nz     = has_nz;
+                    }
+                }
+                // redo the best prediction so the following blocks use the proper reconstruction
+                self.predict_subblock(dframe, mb_x, mb_y, x, y, best_mode, &mut tr_save, tr_edge);
+                let mut levels = [0; 16];
+                self.code_subblock(dframe, mb_x, mb_y, x, y, &mut levels);
+                debug_assert_eq!(levels, best_levels);
+
+                self.ymodes[iidx + x + y * self.ymode_stride] = best_mode;
+                cand.bmodes[x + y * 4] = best_mode;
+                cand.levels[x + y * 4] = best_levels;
+                left[y] = best_nz;
+                top[x]  = best_nz;
+                dist += best_dist;
+                bits += best_bits;
+            }
+        }
+        cand.levels[16..24].copy_from_slice(&chroma.levels);
+        cand.info = MBInfo { ymode: PredMode::BPred, uvmode: chroma.mode, has_y2: false, ..MBInfo::default() };
+        self.finish_decision(&mut cand, dist + chroma.dist, bits + chroma.bits);
+        cand.recon.save_luma(dframe, mb_x, mb_y);
+        cand.recon.u = chroma.recon.u;
+        cand.recon.v = chroma.recon.v;
+        cand
+    }
+
+    fn mv_bits(&self, mv: MV, pred_mv: MV) -> u32 {
+        estimate_mv_component(mv.y - pred_mv.y, &self.mv_probs[0]) + estimate_mv_component(mv.x - pred_mv.x, &self.mv_probs[1])
+    }
+    fn mv_valid(&self, mv: MV, pred_mv: MV, mb_x: usize, mb_y: usize) -> bool {
+        let max_diff = i32::from(MAX_MV_COMP);
+        self.clip_mv(mv, mb_x, mb_y) == mv &&
+            (i32::from(mv.x) - i32::from(pred_mv.x)).abs() <= max_diff &&
+            (i32::from(mv.y) - i32::from(pred_mv.y)).abs() <= max_diff
+    }
+    fn luma_sad(&mut self, rbuf: &NAVideoBufferRef<u8>, mb_x: usize, mb_y: usize, mv: MV) -> u32 {
+        mc_block16x16(&mut self.pred_buf, 0, 16, mb_x * 16, mb_y * 16, mv.x * 2, mv.y * 2, rbuf.clone(), 0, &mut self.mc_buf);
+        let sstride = self.src_stride[0];
+        sad(&self.pred_buf, 0, 16, &self.src[0], mb_x * 16 + mb_y * 16 * sstride, sstride, 16, 16)
+    }
+    fn me_cost(&mut self, rbuf: &NAVideoBufferRef<u8>, mb_x: usize, mb_y: usize, mv: MV, pred_mv: MV) -> u64 {
+        let dist = self.luma_sad(rbuf, mb_x, mb_y, mv);
+        u64::from(dist) * 8 + self.lambda_sad * u64::from(self.mv_bits(mv, pred_mv))
+    }
+    fn search_mv(&mut self, rbuf: &NAVideoBufferRef<u8>, mb_x: usize, mb_y: usize, pred_mv: MV, cands: &[MV]) -> MV {
+        let mut best_mv = pred_mv;
+        let mut best_cost = self.me_cost(rbuf, mb_x, mb_y, pred_mv, pred_mv);
+        for &cand in cands.iter() {
+            let mv = MV { x: (cand.x + 2) & !3, y: (cand.y + 2) & !3 };
+            if mv != best_mv && self.mv_valid(mv, pred_mv, mb_x, mb_y) {
+                let cost = self.me_cost(rbuf, mb_x, mb_y, mv, pred_mv);
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_mv = mv;
+                }
+            }
+        }
+
+        let center = best_mv;
+        let range = self.me_range * 4;
+        for _ in 0..self.me_range {
+            let start = best_mv;
+            for &(dx, dy) in MV_SEARCH_STEPS.iter() {
+                let mv = MV { x: start.x + dx, y: start.y + dy };
+                if (mv.x - center.x).abs() > range || (mv.y - center.y).abs() > range || !self.mv_valid(mv, pred_mv, mb_x, mb_y) {
+                    continue;
+                }
+                let cost = self.me_cost(rbuf, mb_x, mb_y, mv, pred_mv);
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_mv = mv;
+                }
+            }
+            if best_mv == start {
+                break;
+            }
+        }
+        for &step in [2, 1].iter() {
+            let start = best_mv;
+            for &(dx, dy) in MV_REFINE_STEPS.iter() {
+                let mv = MV { x: start.x + dx * step, y: start.y + dy * step };
+                if !self.mv_valid(mv, pred_mv, mb_x, mb_y) {
+                    continue;
+                }
+                let cost = self.me_cost(rbuf, mb_x, mb_y, mv, pred_mv);
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_mv = mv;
+                }
+            }
+        }
+        best_mv
+    }
+    fn ref_bits(&self, rframe: VP8Ref) -> u32 {
+        let probs = &self.mprobs;
+        bit_cost(true, probs.prob_intra) + match rframe {
+                VP8Ref::Last   => bit_cost(false, probs.prob_last),
+                VP8Ref::Golden => bit_cost(true, probs.prob_last) + bit_cost(false, probs.prob_gold),
+                VP8Ref::AltRef => bit_cost(true, probs.prob_last) + bit_cost(true, probs.prob_gold),
+                VP8Ref::Intra  => unreachable!(),
+            }
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn try_inter(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, rframe: VP8Ref, rbuf: &NAVideoBufferRef<u8>, mb_type: VPMBType, mv: MV, mode_bits: u32) -> MBDecision {
+        let mut cand = MBDecision::new();
+        let ystride = dframe.stride[0];
+        let yoff = dframe.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+        mc_block16x16(dframe.data, yoff, ystride, mb_x * 16, mb_y * 16, mv.x * 2, mv.y * 2, rbuf.clone(), 0, &mut self.mc_buf);
+        for plane in 1..3 {
+            let stride = dframe.stride[plane];
+            let off = dframe.offset[plane] + mb_x * 8 + mb_y * 8 * stride;
+            mc_block8x8(dframe.data, off, stride, mb_x * 8, mb_y * 8, mv.x, mv.y, rbuf.clone(), plane, &mut self.mc_buf);
+        }
+        let ldist = self.code_luma16(dframe, mb_x, mb_y, &mut cand.levels);
+        let cdist = self.code_chroma(dframe, mb_x, mb_y, &mut cand.levels[16..24]);
+        let bits = self.estimate_luma_tokens(&cand.levels, true, mb_x) + self.estimate_chroma_tokens(&cand.levels[16..24], mb_x) + mode_bits;
+        cand.info = MBInfo { mb_type, ymode: PredMode::Inter, uvmode: PredMode::Inter, rframe, mv, ..MBInfo::default() };
+        self.finish_decision(&mut cand, ldist + cdist, bits);
+        cand.recon.save_luma(dframe, mb_x, mb_y);
+        cand.recon.save_chroma(dframe, mb_x, mb_y);
+        cand.bmodes = [PredMode::Inter; 16];
+        cand
+    }
+    fn decide_inter(&mut self, dframe: &mut NASimpleVideoFrame<u8>, mb_x: usize, mb_y: usize, refs: &[(VP8Ref, NAVideoBufferRef<u8>)]) -> MBDecision {
+        const NUM_RD_CANDS: usize = 2;
+
+        let (mvprobs, nearest_mv, near_mv, best_mv) = self.find_mv_pred(mb_x, mb_y);
+        // preselect the best candidates by SAD and estimated mode cost
+        let mut cands: Vec<(u64, usize, VPMBType, MV, u32)> = Vec::with_capacity(refs.len() * 4);
+        for (ref_no, (rframe, rbuf)) in refs.iter().enumerate() {
+            let ref_bits = self.ref_bits(*rframe);
+            let search_mv = self.search_mv(rbuf, mb_x, mb_y, best_mv, &[ZERO_MV, nearest_mv, near_mv]);
+            let modes = [(VPMBType::InterNoMV, ZERO_MV), (VPMBType::InterNearest, nearest_mv),
+                         (VPMBType::InterNear, near_mv), (VPMBType::InterMV, search_mv)];
+            for &(mb_type, mv) in modes.iter() {
+                let mut mode_bits = ref_bits + tree_cost(MV_REF_TREE, &mvprobs, mb_type);
+                if mb_type == VPMBType::InterMV {
+                    if !self.mv_valid(mv, best_mv, mb_x, mb_y) {
+                        continue;
+                    }
+                    mode_bits += self.mv_bits(mv, best_mv);
+                }
+                let dist = self.luma_sad(rbuf, mb_x, mb_y, mv);
+                let cost = u64::from(dist) * 8 + self.lambda_sad * u64::from(mode_bits);
+                cands.push((cost, ref_no, mb_type, mv, mode_bits));
+            }
+        }
+        cands.sort_by_key(|el| el.0);
+
+        let mut best = MBDecision::new();
+        for &(_, ref_no, mb_type, mv, mode_bits) in cands.iter().take(NUM_RD_CANDS) {
+            let (rframe, ref rbuf) = refs[ref_no];
+            let cand = self.try_inter(dframe, mb_x, mb_y, rframe, rbuf, mb_type, mv, mode_bits);
+            if cand.cost < best.cost {
+                best = cand;
+            }
+        }
+        best
+    }
+
+    /// Selects coding modes for all macroblocks and reconstructs the frame (without loop filtering).
+    pub fn analyse_frame(&mut self, dframe: &mut NASimpleVideoFrame<u8>, is_intra: bool, refs: &[(VP8Ref, NAVideoBufferRef<u8>)]) {
+        self.pcache.reset();
+        let mut mb_idx = 0;
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                let mut best = if !is_intra {
+                        self.decide_inter(dframe, mb_x, mb_y, refs)
+                    } else {
+                        MBDecision::new()
+                    };
+                let chroma = self.decide_chroma(dframe, mb_x, mb_y, is_intra);
+                let cand = self.try_intra16(dframe, mb_x, mb_y, is_intra, &chroma);
+                let try_bpred = is_intra || cand.cost < best.cost;
+                if cand.cost < best.cost {
+                    best = cand;
+                }
+                if try_bpred {
+                    let cand = self.try_bpred(dframe, mb_x, mb_y, is_intra, &chroma);
+                    if cand.cost < best.cost {
+                        best = cand;
+                    }
+                }
+                put_mb(dframe, mb_x, mb_y, &best.recon);
+
+                let iidx = mb_x * 4 + mb_y * 4 * self.ymode_stride;
+                for (dst, src) in self.ymodes[iidx..].chunks_mut(self.ymode_stride).zip(best.bmodes.chunks(4)) {
+                    dst[..4].copy_from_slice(src);
+                }
+                let info = best.info;
+                process_mb_tokens(&mut self.pcache, &best.levels, info.has_y2, info.skip, mb_x,
+                        |blk, _, _| if has_nonzero(blk) { 1 } else { 0 });
+                self.mb_info[mb_idx] = info;
+                self.levels[mb_idx] = best.levels;
+                mb_idx += 1;
+            }
+            self.pcache.update_row();
+            self.pcache.reset_left();
+        }
+    }
+}
@@ -0,0 +1,699 @@
+use nihav_core::codecs::*;
+use nihav_core::io::byteio::*;
+use super::vpcommon::*;
+use super::vp78::*;
+use super::vp78data::*;
+use super::vp8data::*;
+use super::vp8dsp::loop_filter_mb;
+
+mod coder;
+use coder::*;
+mod dsp;
+mod mb;
+use mb::*;
+mod ratectl;
+use ratectl::*;
+
+const MAX_DIM: usize = 16383;
+
+#[derive(Default)]
+struct FrameStats {
+    coefs:      Vec<[u8; 25]>,
+    coef_stats: CoeffStats,
+    mv_stats:   [[ProbCounter; 19]; 2],
+    skip:       ProbCounter,
+    intra:      ProbCounter,
+    last:       ProbCounter,
+    gold:       ProbCounter,
+}
+
+impl FrameStats {
+    fn new() -> Self {
+        Self {
+            coefs:      Vec::new(),
+            coef_stats: [[[[ProbCounter::default(); 11]; 3]; 8]; 4],
+            mv_stats:   [[ProbCounter::default(); 19]; 2],
+            ..Default::default()
+        }
+    }
+}
+
+struct VP8Encoder {
+    stream:     Option<NAStreamRef>,
+    pkt:        Option<NAPacket>,
+    key_int:    u8,
+    frmcount:   u8,
+    width:      usize,
+    height:     usize,
+    mb_w:       usize,
+    mb_h:       usize,
+    fenc:       FrameEncoder,
+    ratectl:    RateControl,
+
+    coef_probs: CoeffProbs,
+    mv_probs:   [[u8; 19]; 2],
+    mprobs:     ModeProbs,
+    inner_filt: Vec<bool>,
+
+    last_frame: Option<NAVideoBufferRef<u8>>,
+    gold_frame: Option<NAVideoBufferRef<u8>>,
+    alt_frame:  Option<NAVideoBufferRef<u8>>,
+    lf_buf:     Option<NAVideoBufferRef<u8>>,
+    frame_id:   u32,
+    last_id:    u32,
+    gold_id:    u32,
+    alt_id:     u32,
+    golden_int: u8,
+    since_gold: u8,
+
+    force_q:    Option<usize>,
+    lf_level:   Option<u8>,
+    log2_parts: u8,
+    me_range:   i16,
+}
+
+impl VP8Encoder {
+    fn new() -> Self {
+        Self {
+            stream:     None,
+            pkt:        None,
+            key_int:    30,
+            frmcount:   0,
+            width:      0,
+            height:     0,
+            mb_w:       0,
+            mb_h:       0,
+            fenc:       FrameEncoder::new(),
+            ratectl:    RateControl::new(),
+
+            coef_probs: DEFAULT_DCT_PROBS,
+            mv_probs:   DEFAULT_MV_PROBS,
+            mprobs:     ModeProbs::default(),
+            inner_filt: Vec::new(),
+
+            last_frame: None,
+            gold_frame: None,
+            alt_frame:  None,
+            lf_buf:     None,
+            frame_id:   0,
+            last_id:    0,
+            gold_id:    0,
+            alt_id:     0,
+            golden_int: 10,
+            since_gold: 0,
+
+            force_q:    None,
+            lf_level:   None,
+            log2_parts: 0,
+            me_range:   16,
+        }
+    }
+    fn get_refs(&self) -> Vec<(VP8Ref, NAVideoBufferRef<u8>)> {
+        let mut refs = Vec::with_capacity(3);
+        if let Some(ref frm) = self.last_frame {
+            refs.push((VP8Ref::Last, frm.clone()));
+        }
+        if let Some(ref frm) = self.gold_frame {
+            if self.gold_id != self.last_id {
+                refs.push((VP8Ref::Golden, frm.clone()));
+            }
+        }
+        if let Some(ref frm) = self.alt_frame {
+            if self.alt_id != self.last_id && self.alt_id != self.gold_id {
+                refs.push((VP8Ref::AltRef, frm.clone()));
+            }
+        }
+        refs
+    }
+    fn gather_stats(&mut self, is_intra: bool) -> FrameStats {
+        let mut stats = FrameStats::new();
+        stats.coefs.reserve(self.mb_w * self.mb_h);
+        let fenc = &mut self.fenc;
+        fenc.pcache.reset();
+        let mut mb_idx = 0;
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                let info = fenc.mb_info[mb_idx];
+                let mut ctx = [0; 25];
+                let mut blk_no = 0;
+                let coef_stats = &mut stats.coef_stats;
+                process_mb_tokens(&mut fenc.pcache, &fenc.levels[mb_idx], info.has_y2, info.skip, mb_x,
+                        |blk, ctype, pctx| {
+                            ctx[blk_no] = pctx;
+                            blk_no += 1;
+                            collect_block_stats(blk, ctype, pctx, coef_stats)
+                        });
+                stats.coefs.push(ctx);
+
+                stats.skip.add(info.skip);
+                if !is_intra {
+                    stats.intra.add(info.rframe != VP8Ref::Intra);
+                    if info.rframe != VP8Ref::Intra {
+                        stats.last.add(info.rframe != VP8Ref::Last);
+                        if info.rframe != VP8Ref::Last {
+                            stats.gold.add(info.rframe == VP8Ref::AltRef);
+                        }
+                    }
+                    if info.mb_type == VPMBType::InterMV {
+                        let (_, _, _, best_mv) = fenc.find_mv_pred(mb_x, mb_y);
+                        collect_mv_stats(info.mv.y - best_mv.y, &mut stats.mv_stats[0]);
+                        collect_mv_stats(info.mv.x - best_mv.x, &mut stats.mv_stats[1]);
+                    }
+                }
+                mb_idx += 1;
+            }
+            fenc.pcache.update_row();
+            fenc.pcache.reset_left();
+        }
+        stats
+    }
+    fn filter_frame(&self, buf: &mut NAVideoBufferRef<u8>, level: u8, is_intra: bool) {
+        let mut dframe = NASimpleVideoFrame::from_video_buf(buf).unwrap();
+        let mut mb_idx = 0;
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                loop_filter_mb(&mut dframe, mb_x, mb_y, level, self.inner_filt[mb_idx], false, 0, is_intra);
+                mb_idx += 1;
+            }
+        }
+    }
+    fn pick_lf_level(&mut self, buf: &NAVideoBufferRef<u8>, is_intra: bool) -> u8 {
+        let mut lf_buf = self.lf_buf.take().unwrap();
+        let mut eval_level = |level: u8| -> u64 {
+                lf_buf.get_data_mut().unwrap().copy_from_slice(buf.get_data());
+                if level > 0 {
+                    self.filter_frame(&mut lf_buf, level, is_intra);
+                }
+                self.fenc.frame_sse(&lf_buf)
+            };
+
+        let mut best_level = 16;
+        let mut best_dist = eval_level(best_level);
+        let mut step = 8;
+        while step > 0 {
+            let start = best_level;
+            for &level in [start.saturating_sub(step), (start + step).min(63)].iter() {
+                if level == start {
+                    continue;
+                }
+                let dist = eval_level(level);
+                if dist < best_dist {
+                    best_dist = dist;
+                    best_level = level;
+                }
+            }
+            if best_level == start {
+                step >>= 1;
+            }
+        }
+        self.lf_buf = Some(lf_buf);
+        best_level
+    }
+    fn write_header(&self, bc: &mut BoolEncoder, is_intra: bool, golden: bool, q: usize, lf_level: u8) -> EncoderResult<()> {
+        if is_intra {
+            bc.put_flag(false)?; // colour space
+            bc.put_flag(false)?; // clamping type
+        }
+        bc.put_flag(false)?; // segmentation
+        bc.put_flag(false)?; // simple loop filter
+        bc.put_bits(u32::from(lf_level), 6)?;
+        bc.put_bits(0, 3)?; // sharpness
+        bc.put_flag(false)?; // loop filter deltas
+        bc.put_bits(u32::from(self.log2_parts), 2)?;
+        bc.put_bits(q as u32, 7)?;
+        for _ in 0..5 {
+            bc.put_flag(false)?; // quantiser deltas
+        }
+        if !is_intra {
+            bc.put_flag(golden)?; // refresh golden frame
+            bc.put_flag(false)?; // refresh altref frame
+            if !golden {
+                bc.put_bits(0, 2)?; // copy to golden frame
+            }
+            bc.put_bits(if golden { 2 } else { 0 }, 2)?; // copy to altref frame
+            bc.put_flag(false)?; // golden frame sign bias
+            bc.put_flag(false)?; // altref frame sign bias
+        }
+        bc.put_flag(true)?; // refresh entropy probabilities
+        if !is_intra {
+            bc.put_flag(true)?; // refresh last frame
+        }
+        Ok(())
+    }
+    fn write_mb_modes(&self, bc: &mut BoolEncoder, is_intra: bool) -> EncoderResult<()> {
+        let fenc = &self.fenc;
+        let ymode_stride = fenc.ymode_stride;
+        let mut mb_idx = 0;
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                let info = &fenc.mb_info[mb_idx];
+                bc.put_bool(info.skip, self.mprobs.prob_skip)?;
+                let iidx = mb_x * 4 + mb_y * 4 * ymode_stride;
+                if is_intra {
+                    bc.write_tree(KF_Y_MODE_TREE, KF_Y_MODE_TREE_PROBS, info.ymode)?;
+                    if info.ymode == PredMode::BPred {
+                        for y in 0..4 {
+                            for x in 0..4 {
+                                let cur_idx = iidx + x + y * ymode_stride;
+                                let top_mode = if (y > 0) || (mb_y > 0) {
+                                        fenc.ymodes[cur_idx - ymode_stride]
+                                    } else {
+                                        PredMode::DCPred
+                                    };
+                                let left_mode = if (x > 0) || (mb_x > 0) {
+                                        fenc.ymodes[cur_idx - 1]
+                                    } else {
+                                        PredMode::DCPred
+                                    };
+                                let probs = &KF_B_MODE_TREE_PROBS[top_mode.to_b_index()][left_mode.to_b_index()];
+                                bc.write_tree(B_MODE_TREE, probs, fenc.ymodes[cur_idx])?;
+                            }
+                        }
+                    }
+                    bc.write_tree(UV_MODE_TREE, KF_UV_MODE_TREE_PROBS, info.uvmode)?;
+                } else if info.rframe == VP8Ref::Intra {
+                    bc.put_bool(false, self.mprobs.prob_intra)?;
+                    bc.write_tree(Y_MODE_TREE, Y_MODE_TREE_PROBS, info.ymode)?;
+                    if info.ymode == PredMode::BPred {
+                        for y in 0..4 {
+                            for x in 0..4 {
+                                bc.write_tree(B_MODE_TREE, B_MODE_TREE_PROBS, fenc.ymodes[iidx + x + y * ymode_stride])?;
+                            }
+                        }
+                    }
+                    bc.write_tree(UV_MODE_TREE, UV_MODE_TREE_PROBS, info.uvmode)?;
+                } else {
+                    bc.put_bool(true, self.mprobs.prob_intra)?;
+                    bc.put_bool(info.rframe != VP8Ref::Last, self.mprobs.prob_last)?;
+                    if info.rframe != VP8Ref::Last {
+                        bc.put_bool(info.rframe == VP8Ref::AltRef, self.mprobs.prob_gold)?;
+                    }
+                    let (mvprobs, _, _, best_mv) = fenc.find_mv_pred(mb_x, mb_y);
+                    bc.write_tree(MV_REF_TREE, &mvprobs, info.mb_type)?;
+                    if info.mb_type == VPMBType::InterMV {
+                        encode_mv_component(bc, info.mv.y - best_mv.y, &self.mv_probs[0])?;
+                        encode_mv_component(bc, info.mv.x - best_mv.x, &self.mv_probs[1])?;
+                    }
+                }
+                mb_idx += 1;
+            }
+        }
+        Ok(())
+    }
+    fn write_partition(&self, part: usize, stats: &FrameStats) -> EncoderResult<Vec<u8>> {
+        let mut dbuf = Vec::new();
+        let mut gw = GrowableMemoryWriter::new_write(&mut dbuf);
+        let mut bw = ByteWriter::new(&mut gw);
+        let mut bc = BoolEncoder::new(&mut bw);
+        for mb_y in (part..self.mb_h).step_by(1 << self.log2_parts) {
+            for mb_x in 0..self.mb_w {
+                let mb_idx = mb_x + mb_y * self.mb_w;
+                let info = &self.fenc.mb_info[mb_idx];
+                if info.skip {
+                    continue;
+                }
+                let levels = &self.fenc.levels[mb_idx];
+                let ctx = &stats.coefs[mb_idx];
+                let mut blk_no = 0;
+                if info.has_y2 {
+                    encode_block(&mut bc, &levels[24], 1, ctx[0], &self.coef_probs)?;
+                    blk_no += 1;
+                }
+                let ytype = if info.has_y2 { 0 } else { 3 };
+                for (i, blk) in levels[..24].iter().enumerate() {
+                    let ctype = if i < 16 { ytype } else { 2 };
+                    encode_block(&mut bc, blk, ctype, ctx[blk_no], &self.coef_probs)?;
+                    blk_no += 1;
+                }
+            }
+        }
+        bc.flush()?;
+        Ok(dbuf)
+    }
+    fn encode_frame(&mut self, vbuf: &NAVideoBuffer<u8>, is_intra: bool, golden: bool, q: usize) -> EncoderResult<Vec<u8>> {
+        if is_intra {
+            self.coef_probs = DEFAULT_DCT_PROBS;
+            self.mv_probs   = DEFAULT_MV_PROBS;
+            self.mprobs     = ModeProbs::default();
+        }
+        self.fenc.read_frame(vbuf);
+        self.fenc.set_quant(q);
+        self.fenc.me_range   = self.me_range;
+        self.fenc.coef_probs = self.coef_probs;
+        self.fenc.mv_probs   = self.mv_probs;
+        self.fenc.mprobs     = self.mprobs;
+
+        let refs = if !is_intra { self.get_refs() } else { Vec::new() };
+        let vinfo = NAVideoInfo::new(self.width, self.height, false, YUV420_FORMAT);
+        let mut buf = alloc_video_buffer(vinfo, 4)?.get_vbuf().unwrap();
+        let mut dframe = NASimpleVideoFrame::from_video_buf(&mut buf).unwrap();
+        self.fenc.analyse_frame(&mut dframe, is_intra, &refs);
+
+        let stats = self.gather_stats(is_intra);
+        self.mprobs.prob_skip = stats.skip.to_prob();
+        if !is_intra {
+            self.mprobs.prob_intra = stats.intra.to_prob();
+            self.mprobs.prob_last  = stats.last.to_prob();
+            self.mprobs.prob_gold  = stats.gold.to_prob();
+        }
+
+        for (filt, info) in self.inner_filt.iter_mut().zip(self.fenc.mb_info.iter()) {
+            *filt = !info.skip || info.ymode == PredMode::BPred;
+        }
+        let lf_level = if let Some(level) = self.lf_level {
+                level
+            } else {
+                self.pick_lf_level(&buf, is_intra)
+            };
+
+        let mut hdr = Vec::new();
+        let mut gw = GrowableMemoryWriter::new_write(&mut hdr);
+        let mut bw = ByteWriter::new(&mut gw);
+        let mut bc = BoolEncoder::new(&mut bw);
+        self.write_header(&mut bc, is_intra, golden, q, lf_level)?;
+        encode_coef_prob_updates(&mut bc, &mut self.coef_probs, &stats.coef_stats)?;
+        bc.put_flag(true)?; // macroblock skip flags are present
+        bc.put_byte(self.mprobs.prob_skip)?;
+        if !is_intra {
+            bc.put_byte(self.mprobs.prob_intra)?;
+            bc.put_byte(self.mprobs.prob_last)?;
+            bc.put_byte(self.mprobs.prob_gold)?;
+            bc.put_flag(false)?; // luma mode probabilities update
+            bc.put_flag(false)?; // chroma mode probabilities update
+            encode_mv_prob_updates(&mut bc, &mut self.mv_probs, &stats.mv_stats)?;
+        }
+        self.write_mb_modes(&mut bc, is_intra)?;
+        bc.flush()?;
+
+        let num_parts = 1 << self.log2_parts;
+        let mut parts = Vec::with_capacity(num_parts);
+        for part in 0..num_parts {
+            parts.push(self.write_partition(part, &stats)?);
+        }
+
+        // decoder expects the first partition to be longer than the frame header
+        let hdr_size = if is_intra { 10 } else { 3 };
+        while hdr.len() <= hdr_size {
+            hdr.push(0);
+        }
+        if hdr.len() >= (1 << 19) {
+            return Err(EncoderError::Bug);
+        }
+        let mut dbuf = Vec::with_capacity(hdr_size + hdr.len() + parts.iter().fold(0, |acc, p| acc + p.len() + 3));
+        let mut gw = GrowableMemoryWriter::new_write(&mut dbuf);
+        let mut bw = ByteWriter::new(&mut gw);
+        let frame_tag = (if is_intra { 0 } else { 1 }) | (1 << 4) | ((hdr.len() as u32) << 5);
+        bw.write_u24le(frame_tag)?;
+        if is_intra {
+            bw.write_u24be(0x9D012A)?;
+            bw.write_u16le(self.width as u16)?;
+            bw.write_u16le(self.height as u16)?;
+        }
+        bw.write_buf(&hdr)?;
+        for part in parts[..num_parts - 1].iter() {
+            if part.len() >= (1 << 24) {
+                return Err(EncoderError::Bug);
+            }
+            bw.write_u24le(part.len() as u32)?;
+        }
+        for part in parts.iter() {
+            bw.write_buf(part)?;
+        }
+
+        if lf_level > 0 {
+            self.filter_frame(&mut buf, lf_level, is_intra);
+        }
+
+        self.frame_id += 1;
+        if is_intra {
+            self.gold_frame = Some(buf.clone());
+            self.alt_frame  = Some(buf.clone());
+            self.gold_id    = self.frame_id;
+            self.alt_id     = self.frame_id;
+        } else if golden {
+            self.alt_frame  = self.gold_frame.take();
+            self.alt_id     = self.gold_id;
+            self.gold_frame = Some(buf.clone());
+            self.gold_id    = self.frame_id;
+        }
+        self.last_frame = Some(buf);
+        self.last_id    = self.frame_id;
+
+        Ok(dbuf)
+    }
+}
+
+impl NAEncoder for VP8Encoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                let outinfo = NAVideoInfo::new((vinfo.width + 1) & !1, (vinfo.height + 1) & !1, false, YUV420_FORMAT);
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Video(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                if vinfo.format != YUV420_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ((vinfo.width | vinfo.height) & 1) != 0 || vinfo.width == 0 || vinfo.height == 0 {
+                    return Err(EncoderError::FormatError);
+                }
+                if vinfo.width > MAX_DIM || vinfo.height > MAX_DIM {
+                    return Err(EncoderError::FormatError);
+                }
+
+                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, false, YUV420_FORMAT);
+                let info = NACodecInfo::new("vp8", NACodecTypeInfo::Video(out_info), None);
+                let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+
+                self.width  = vinfo.width;
+                self.height = vinfo.height;
+                self.mb_w   = (vinfo.width  + 15) >> 4;
+                self.mb_h   = (vinfo.height + 15) >> 4;
+                self.fenc.resize(self.mb_w, self.mb_h);
+                self.inner_filt = vec![false; self.mb_w * self.mb_h];
+                self.lf_buf = alloc_video_buffer(out_info, 4)?.get_vbuf();
+                self.last_frame = None;
+                self.gold_frame = None;
+                self.alt_frame  = None;
+                self.frmcount   = 0;
+                self.since_gold = 0;
+
+                self.ratectl.init(self.mb_w, self.mb_h, encinfo.bitrate, encinfo.quality, encinfo.tb_num, encinfo.tb_den);
+
+                self.stream = Some(stream.clone());
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref vbuf) = buf.get_vbuf() {
+            let is_intra = self.frmcount == 0 || self.last_frame.is_none();
+            let golden = !is_intra && self.golden_int > 0 && self.since_gold + 1 >= self.golden_int;
+            let quant = if let Some(q) = self.force_q {
+                    q
+                } else {
+                    self.ratectl.guess_quant(is_intra, golden)
+                };
+
+            let dbuf = self.encode_frame(vbuf, is_intra, golden, quant)?;
+            if self.force_q.is_none() {
+                self.ratectl.update(is_intra, quant, dbuf.len() * 8);
+            }
+            self.since_gold = if is_intra || golden { 0 } else { self.since_gold.saturating_add(1) };
+
+            self.pkt = Some(NAPacket::new(self.stream.clone().unwrap(), frm.ts, is_intra, dbuf));
+            if self.key_int > 0 {
+                self.frmcount += 1;
+            }
+            if self.frmcount == self.key_int {
+                self.frmcount = 0;
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        let mut npkt = None;
+        std::mem::swap(&mut self.pkt, &mut npkt);
+        Ok(npkt)
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.frmcount = 0;
+        Ok(())
+    }
+}
+
+const QUANT_OPTION: &str = "quant";
+const LF_LEVEL_OPTION: &str = "lf_level";
+const PARTITIONS_OPTION: &str = "partitions";
+const MV_RANGE_OPTION: &str = "mv_range";
+const GOLDEN_INT_OPTION: &str = "golden_int";
+
+const ENCODER_OPTS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: KEYFRAME_OPTION, description: KEYFRAME_OPTION_DESC,
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(128)) },
+    NAOptionDefinition {
+        name: QUANT_OPTION, description: "force fixed quantiser for encoding",
+        opt_type: NAOptionDefinitionType::Int(Some(-1), Some(127)) },
+    NAOptionDefinition {
+        name: LF_LEVEL_OPTION, description: "loop filter level (-1 - automatic)",
+        opt_type: NAOptionDefinitionType::Int(Some(-1), Some(63)) },
+    NAOptionDefinition {
+        name: PARTITIONS_OPTION, description: "number of token partitions (1, 2, 4 or 8)",
+        opt_type: NAOptionDefinitionType::Int(Some(1), Some(8)) },
+    NAOptionDefinition {
+        name: MV_RANGE_OPTION, description: "motion search range (in pixels)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(64)) },
+    NAOptionDefinition {
+        name: GOLDEN_INT_OPTION, description: "golden frame refresh interval (0 - never)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(128)) },
+];
+
+impl NAOptionHandler for VP8Encoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { ENCODER_OPTS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in ENCODER_OPTS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        KEYFRAME_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.key_int = intval as u8;
+                            }
+                        },
+                        QUANT_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.force_q = if intval < 0 { None } else { Some(intval as usize) };
+                            }
+                        },
+                        LF_LEVEL_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.lf_level = if intval < 0 { None } else { Some(intval as u8) };
+                            }
+                        },
+                        PARTITIONS_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.log2_parts = match intval {
+                                        1     => 0,
+                                        2..=3 => 1,
+                                        4..=7 => 2,
+                                        _     => 3,
+                                    };
+                            }
+                        },
+                        MV_RANGE_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.me_range = intval as i16;
+                            }
+                        },
+                        GOLDEN_INT_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.golden_int = intval as u8;
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            KEYFRAME_OPTION => Some(NAValue::Int(i64::from(self.key_int))),
+            QUANT_OPTION => if let Some(q) = self.force_q {
+                    Some(NAValue::Int(q as i64))
+                } else {
+                    Some(NAValue::Int(-1))
+                },
+            LF_LEVEL_OPTION => if let Some(level) = self.lf_level {
+                    Some(NAValue::Int(i64::from(level)))
+                } else {
+                    Some(NAValue::Int(-1))
+                },
+            PARTITIONS_OPTION => Some(NAValue::Int(1 << self.log2_parts)),
+            MV_RANGE_OPTION => Some(NAValue::Int(i64::from(self.me_range))),
+            GOLDEN_INT_OPTION => Some(NAValue::Int(i64::from(self.golden_int))),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(VP8Encoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::enc_video::*;
+    use crate::*;
+
+    fn encode_synth(enc_options: &[NAOption], bitrate: u32, nframes: usize, ref_hash: &[u32; 4]) -> SynthTestResult {
+        let mut enc_reg = RegisteredEncoders::new();
+        duck_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        duck_register_all_decoders(&mut dec_reg);
+
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(90, 70, false, YUV420_FORMAT)),
+                quality: 0,
+                bitrate,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "vp8", enc_params, enc_options, nframes, ref_hash);
+        // PSNR should stay above ~30dB
+        for &mse in result.max_mse.iter() {
+            assert!(mse < 64);
+        }
+        result
+    }
+
+    #[test]
+    fn test_vp8_encoder_fixed_quant() {
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(20) },
+                NAOption { name: KEYFRAME_OPTION, value: NAValue::Int(8) },
+                NAOption { name: super::GOLDEN_INT_OPTION, value: NAValue::Int(3) },
+            ];
+        let result = encode_synth(enc_options, 0, 12, &[0xed86e42e, 0x9bc5fa41, 0x49546ea8, 0x88885a55]);
+        assert_eq!(result.keyframes, 2);
+    }
+    #[test]
+    fn test_vp8_encoder_partitions() {
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(30) },
+                NAOption { name: super::PARTITIONS_OPTION, value: NAValue::Int(4) },
+                NAOption { name: super::LF_LEVEL_OPTION, value: NAValue::Int(20) },
+            ];
+        encode_synth(enc_options, 0, 6, &[0x91b01e17, 0x603112c3, 0xb0155ab2, 0xd9c100d2]);
+    }
+    #[test]
+    fn test_vp8_encoder_ratectl() {
+        let low  = encode_synth(&[], 60000, 10, &[0x67b75dcc, 0xa8bcb771, 0x3237d2c0, 0x5e05a5ee]);
+        let high = encode_synth(&[], 300000, 10, &[0x3bd1a57d, 0x23d010e9, 0xf681d899, 0x64de10e9]);
+        assert!(low.size < high.size);
+    }
+}
@@ -0,0 +1,125 @@
+use super::super::vp8data::AC_QUANTS;
+
+const DEFAULT_QUANT: usize = 40;
+// initial guess for the amount of bits per macroblock multiplied by the quantiser step
+const INTRA_MB_COMPLEXITY: f32 = 20000.0;
+const INTER_MB_COMPLEXITY: f32 =  4000.0;
+
+pub struct RateControl {
+    tgt_br:         u32,
+    budget:         isize,
+    cur_time:       u32,
+    ts_num:         u32,
+    ts_den:         u32,
+    quality:        u8,
+    num_mbs:        usize,
+    complexity:     [f32; 2],
+    projected:      usize,
+}
+
+impl RateControl {
+    pub fn new() -> Self {
+        Self {
+            tgt_br:     0,
+            budget:     0,
+            cur_time:   0,
+            ts_num:     0,
+            ts_den:     0,
+            quality:    0,
+            num_mbs:    0,
+            complexity: [INTRA_MB_COMPLEXITY, INTER_MB_COMPLEXITY],
+            projected:  0,
+        }
+    }
+    pub fn init(&mut self, mb_w: usize, mb_h: usize, bitrate: u32, quality: u8, ts_num: u32, ts_den: u32) {
+        self.num_mbs    = mb_w * mb_h;
+        self.quality    = quality.min(100);
+        self.cur_time   = 0;
+        self.complexity = [INTRA_MB_COMPLEXITY, INTER_MB_COMPLEXITY];
+        if bitrate == 0 || ts_num == 0 || ts_den == 0 {
+            self.tgt_br = 0;
+            self.budget = 0;
+        } else {
+            self.tgt_br     = bitrate;
+            self.budget     = bitrate as isize;
+            self.ts_num     = ts_num;
+            self.ts_den     = ts_den;
+        }
+    }
+    fn estimate_frame_size(&self, intra: bool, q: usize) -> usize {
+        let cplx = self.complexity[if intra { 0 } else { 1 }];
+        (cplx * (self.num_mbs as f32) / f32::from(AC_QUANTS[q])) as usize
+    }
+    pub fn guess_quant(&mut self, intra: bool, golden: bool) -> usize {
+        if self.tgt_br == 0 {
+            return if self.quality > 0 {
+                    usize::from(100 - self.quality) * 127 / 100
+                } else {
+                    DEFAULT_QUANT
+                };
+        }
+        let mut fsize = self.get_target_frame_size(intra);
+        if golden && !intra {
+            // golden frames serve as a reference for longer so they deserve better quality
+            fsize += fsize / 2;
+        }
+        self.projected = fsize;
+        for q in 0..128 {
+            if self.estimate_frame_size(intra, q) <= fsize {
+                return q;
+            }
+        }
+        127
+    }
+    pub fn update(&mut self, intra: bool, q: usize, dsize: usize) {
+        if self.num_mbs > 0 {
+            let idx = if intra { 0 } else { 1 };
+            let cur_cplx = (dsize as f32) * f32::from(AC_QUANTS[q]) / (self.num_mbs as f32);
+            self.complexity[idx] = (self.complexity[idx] + cur_cplx) * 0.5;
+        }
+        if self.tgt_br == 0 {
+            return;
+        }
+        self.budget -= dsize as isize;
+        self.cur_time += self.ts_num;
+        while self.cur_time >= self.ts_den {
+            self.cur_time -= self.ts_den;
+            self.budget += self.tgt_br as isize;
+        }
+    }
+    fn get_target_frame_size(&self, intra: bool) -> usize {
+        let avg_fsize = (self.tgt_br as usize) * (self.ts_num as usize) / (self.ts_den as usize);
+        let mut fsize = self.budget / (((self.ts_den - self.cur_time) / self.ts_num).max(1) as isize);
+        if fsize <= 0 {
+            fsize = (avg_fsize / 2) as isize;
+        }
+        if intra {
+            fsize *= 4;
+        }
+        fsize as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ratectl() {
+        let mut rc = RateControl::new();
+        rc.init(20, 15, 0, 75, 1, 25);
+        assert_eq!(rc.guess_quant(true, false), 31);
+
+        rc.init(20, 15, 200000, 0, 1, 25);
+        let q_intra = rc.guess_quant(true, false);
+        rc.update(true, q_intra, 30000);
+        let q_inter = rc.guess_quant(false, false);
+        let q_golden = rc.guess_quant(false, true);
+        assert!(q_golden <= q_inter);
+        // spending too many bits should lead to a coarser quantiser
+        for _ in 0..10 {
+            rc.update(false, q_inter, 40000);
+        }
+        assert!(rc.guess_quant(false, false) > q_inter);
+    }
+}
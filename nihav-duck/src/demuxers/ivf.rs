@@ -4,6 +4,10 @@ struct IVFDemuxer<'a> {
     src:            &'a mut ByteReader<'a>,
     nframes:        u32,
     frameno:        u32,
+    tb_num:         u32,
+    tb_den:         u32,
+    data_start:     u64,
+    is_vp8:         bool,
 }
 
 impl<'a> IVFDemuxer<'a> {
@@ -12,12 +16,16 @@ impl<'a> IVFDemuxer<'a> {
             src,
             nframes:    0,
             frameno:    0,
+            tb_num:     0,
+            tb_den:     0,
+            data_start: 0,
+            is_vp8:     false,
         }
     }
 }
 
 impl<'a> DemuxCore<'a> for IVFDemuxer<'a> {
-    fn open(&mut self, strmgr: &mut StreamManager, _seek_index: &mut SeekIndex) -> DemuxerResult<()> {
+    fn open(&mut self, strmgr: &mut StreamManager, seek_index: &mut SeekIndex) -> DemuxerResult<()> {
         let tag                         = self.src.read_tag()?;
         validate!(&tag == b"DKIF");
         let ver                         = self.src.read_u16le()?;
@@ -36,8 +44,16 @@ impl<'a> DemuxCore<'a> for IVFDemuxer<'a> {
         let tb_num                      = self.src.read_u32le()?;
         self.nframes                    = self.src.read_u32le()?;
         self.frameno = 0;
+        self.tb_num = tb_num;
+        self.tb_den = tb_den;
 
         self.src.seek(SeekFrom::Start(hdr_len as u64))?;
+        self.data_start = hdr_len as u64;
+        self.is_vp8 = codec_name == "vp8";
+        // keyframes can be told apart only for VP8 so seek index is not built for other codecs
+        if self.is_vp8 {
+            seek_index.mode = SeekIndexMode::Automatic;
+        }
 
         let vci = NACodecTypeInfo::Video(NAVideoInfo::new(width, height, false, YUV420_FORMAT));
         let vinfo = NACodecInfo::new(codec_name, vci, None);
@@ -61,7 +77,8 @@ impl<'a> DemuxCore<'a> for IVFDemuxer<'a> {
             if let Some(stream) = strmgr.get_stream(0) {
                 let (tb_num, tb_den) = stream.get_timebase();
                 let ts = NATimeInfo::new(Some(tstamp), None, None, tb_num, tb_den);
-                return self.src.read_packet(stream, ts, false, fsize);
+                let is_keyframe = self.is_vp8 && (self.src.peek_byte()? & 1) == 0;
+                return self.src.read_packet(stream, ts, is_keyframe, fsize);
             } else {
                 return Err(DemuxerError::InvalidData);
             }
@@ -69,10 +86,40 @@ impl<'a> DemuxCore<'a> for IVFDemuxer<'a> {
         Err(DemuxerError::EOF)
     }
 
-    fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
-        Err(DemuxerError::NotPossible)
+    fn seek(&mut self, time: NATimePoint, seek_index: &SeekIndex) -> DemuxerResult<()> {
+        let ret = seek_index.find_pos(time);
+        if ret.is_none() {
+            return Err(DemuxerError::SeekError);
+        }
+        let seek_info = ret.unwrap();
+        // frame headers have to be walked to restore frame counter
+        self.src.seek(SeekFrom::Start(self.data_start))?;
+        let mut frameno = 0;
+        while self.src.tell() < seek_info.pos {
+            validate!(frameno < self.nframes);
+            let fsize                   = self.src.read_u32le()? as usize;
+                                          self.src.read_skip(fsize + 8)?;
+            frameno += 1;
+        }
+        validate!(self.src.tell() == seek_info.pos);
+        self.frameno = frameno;
+        Ok(())
+    }
+    fn get_duration(&self) -> u64 {
+        if self.tb_den != 0 {
+            NATimeInfo::ts_to_time(u64::from(self.nframes), 1000, self.tb_num, self.tb_den)
+        } else {
+            0
+        }
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_packet_pos(&mut self) -> Option<u64> {
+        if self.is_vp8 {
+            Some(self.src.tell())
+        } else {
+            None
+        }
+    }
+    fn is_seekable(&mut self) -> bool { self.src.is_seekable() }
 }
 
 impl<'a> NAOptionHandler for IVFDemuxer<'a> {
@@ -115,4 +162,69 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_ivf_keyframes() {
+        for &(fcc, auto_idx, ref_keyframes) in [(b"VP80", true, [true, false, true]), (b"XXXX", false, [false; 3])].iter() {
+            let mut data = Vec::new();
+            data.extend_from_slice(b"DKIF\0\0\x20\0");
+            data.extend_from_slice(fcc);
+            data.extend_from_slice(&[16, 0, 16, 0]);
+            for &val in [30u32, 1, 3, 0].iter() {
+                data.extend_from_slice(&val.to_le_bytes());
+            }
+            // VP8 keyframes have the lowest bit of the first byte cleared
+            for (i, &first_byte) in [0x10u8, 0x11, 0x10].iter().enumerate() {
+                data.extend_from_slice(&4u32.to_le_bytes());
+                data.extend_from_slice(&(i as u64).to_le_bytes());
+                data.extend_from_slice(&[first_byte, 0, 0, 0]);
+            }
+
+            let mut mr = MemoryReader::new_read(&data);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = IVFDemuxer::new(&mut br);
+            let mut sm = StreamManager::new();
+            let mut si = SeekIndex::new();
+            dmx.open(&mut sm, &mut si).unwrap();
+            assert_eq!(si.mode == SeekIndexMode::Automatic, auto_idx);
+            assert_eq!(dmx.get_packet_pos().is_some(), auto_idx);
+            let mut keyframes = Vec::new();
+            while let Ok(pkt) = dmx.get_frame(&mut sm) {
+                keyframes.push(pkt.is_keyframe());
+            }
+            assert_eq!(keyframes, ref_keyframes);
+        }
+    }
+
+    #[test]
+    fn test_ivf_demux_stream() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DKIF\0\0\x20\0VP80");
+        data.extend_from_slice(&[16, 0, 16, 0]);
+        for &val in [30u32, 1, 3, 0].iter() {
+            data.extend_from_slice(&val.to_le_bytes());
+        }
+        for i in 0..3u8 {
+            data.extend_from_slice(&(u32::from(i) + 1).to_le_bytes());
+            data.extend_from_slice(&u64::from(i).to_le_bytes());
+            data.extend_from_slice(&vec![i * 2; usize::from(i) + 1]);
+        }
+
+        let mut sr = StreamReader::new_read(&data[..]);
+        let mut br = ByteReader::new(&mut sr);
+        let dmx_cr = IVFDemuxerCreator {};
+        let mut dmx = create_demuxer(&dmx_cr, &mut br).unwrap();
+        assert!(!dmx.is_seekable());
+        let mut pkts = Vec::new();
+        loop {
+            match dmx.get_frame() {
+                Ok(pkt) => pkts.push((pkt.get_pts(), pkt.get_buffer().to_vec())),
+                Err(DemuxerError::EOF) => break,
+                Err(err) => panic!("demuxing error {:?}", err),
+            }
+        }
+        assert_eq!(pkts, [(Some(0), vec![0]), (Some(1), vec![2, 2]), (Some(2), vec![4, 4, 4])]);
+        assert_eq!(dmx.seek(NATimePoint::Milliseconds(0)).unwrap_err(), DemuxerError::NotPossible);
+        assert_eq!(dmx.build_seek_index().unwrap_err(), DemuxerError::NotPossible);
+    }
 }
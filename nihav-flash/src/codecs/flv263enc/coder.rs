@@ -0,0 +1,149 @@
+use nihav_core::io::bitwriter::*;
+use nihav_core::io::codebook::CodebookDescReader;
+use nihav_codec_support::codecs::{MV, ZIGZAG};
+use nihav_codec_support::codecs::h263::data::*;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Variant {
+    Spark,
+    H263,
+}
+
+impl Variant {
+    pub fn max_level(self) -> i16 {
+        match self {
+            Variant::Spark => 1023,
+            Variant::H263  => 127,
+        }
+    }
+}
+
+const MAX_VLC_RUN: usize = 41;
+const MAX_VLC_LEVEL: usize = 13;
+const ESCAPE: (u32, u8) = (0x03, 7);
+
+pub struct RLCodes {
+    codes:  [[[(u16, u8); MAX_VLC_LEVEL]; MAX_VLC_RUN]; 2],
+}
+
+impl RLCodes {
+    pub fn new() -> Self {
+        let mut codes = [[[(0, 0); MAX_VLC_LEVEL]; MAX_VLC_RUN]; 2];
+        let mut cr = H263RLCodeReader::new(H263_RL_CODES);
+        for idx in 0..cr.len() {
+            let sym = cr.sym(idx);
+            if sym.is_escape() {
+                continue;
+            }
+            let last = if sym.is_last() { 1 } else { 0 };
+            codes[last][usize::from(sym.get_run())][sym.get_level() as usize] = (cr.code(idx) as u16, cr.bits(idx));
+        }
+        Self { codes }
+    }
+    fn get_code(&self, last: bool, run: usize, level: usize) -> Option<(u16, u8)> {
+        if run >= MAX_VLC_RUN || level >= MAX_VLC_LEVEL {
+            return None;
+        }
+        let code = self.codes[if last { 1 } else { 0 }][run][level];
+        if code.1 != 0 {
+            Some(code)
+        } else {
+            None
+        }
+    }
+    fn write_coef(&self, bw: &mut BitWriter, last: bool, run: usize, level: i16, variant: Variant) {
+        if let Some((code, bits)) = self.get_code(last, run, level.unsigned_abs() as usize) {
+            bw.write(u32::from(code), bits);
+            bw.write_bit(level < 0);
+            return;
+        }
+        bw.write(ESCAPE.0, ESCAPE.1);
+        match variant {
+            Variant::Spark => {
+                let long = level.abs() > 63;
+                bw.write_bit(long);
+                bw.write_bit(last);
+                bw.write(run as u32, 6);
+                bw.write_s(i32::from(level), if long { 11 } else { 7 });
+            },
+            Variant::H263 => {
+                bw.write_bit(last);
+                bw.write(run as u32, 6);
+                bw.write_s(i32::from(level), 8);
+            },
+        }
+    }
+    /// Writes quantised block coefficients (in natural order) starting from the provided scan position.
+    pub fn write_block(&self, bw: &mut BitWriter, blk: &[i16; 64], start: usize, variant: Variant) {
+        let last_pos = if let Some(pos) = ZIGZAG[start..].iter().rposition(|&idx| blk[idx] != 0) {
+                pos + start
+            } else {
+                return;
+            };
+        let mut run = 0;
+        for (pos, &idx) in ZIGZAG.iter().enumerate().take(last_pos + 1).skip(start) {
+            let level = blk[idx];
+            if level == 0 {
+                run += 1;
+            } else {
+                self.write_coef(bw, pos == last_pos, run, level, variant);
+                run = 0;
+            }
+        }
+    }
+}
+
+pub fn write_intra_dc(bw: &mut BitWriter, dc: i16) {
+    // value 128 is coded with a special code
+    bw.write(if dc == 128 { 0xFF } else { dc as u32 }, 8);
+}
+
+pub fn write_intra_mcbpc(bw: &mut BitWriter, cbpc: u8) {
+    let (code, bits) = H263_INTRA_MCBPC[usize::from(cbpc)];
+    bw.write(u32::from(code), bits);
+}
+
+pub fn write_inter_mcbpc(bw: &mut BitWriter, cbpc: u8, intra: bool) {
+    let (code, bits) = H263_INTER_MCBPC[usize::from(cbpc) | if intra { 4 } else { 0 }];
+    bw.write(u32::from(code), bits);
+}
+
+pub fn write_cbpy(bw: &mut BitWriter, cbpy: u8, intra: bool) {
+    let (code, bits) = H263_CBPY[usize::from(if intra { cbpy } else { cbpy ^ 0xF })];
+    bw.write(u32::from(code), bits);
+}
+
+/// Returns motion vector difference wrapped into the range representable by the codes.
+pub fn mv_diff(mv: MV, pred_mv: MV) -> MV {
+    let wrap = |diff: i16| {
+            if diff > 32 {
+                diff - 64
+            } else if diff < -32 {
+                diff + 64
+            } else {
+                diff
+            }
+        };
+    MV::new(wrap(mv.x - pred_mv.x), wrap(mv.y - pred_mv.y))
+}
+
+fn write_mv_component(bw: &mut BitWriter, val: i16) {
+    let (code, bits) = H263_MV[val.unsigned_abs() as usize];
+    bw.write(u32::from(code), bits);
+    if val != 0 {
+        bw.write_bit(val < 0);
+    }
+}
+
+pub fn write_mv(bw: &mut BitWriter, diff: MV) {
+    write_mv_component(bw, diff.x);
+    write_mv_component(bw, diff.y);
+}
+
+pub fn mv_bits(diff: MV) -> u32 {
+    let comp_bits = |val: i16| {
+            let (_, bits) = H263_MV[val.unsigned_abs().min(32) as usize];
+            u32::from(bits) + if val != 0 { 1 } else { 0 }
+        };
+    comp_bits(diff.x) + comp_bits(diff.y)
+}
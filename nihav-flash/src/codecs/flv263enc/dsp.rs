@@ -0,0 +1,215 @@
+use nihav_codec_support::codecs::h263::code::H263_INTERP_FUNCS;
+
+const FIX_0_298631336: i32 =  2446;
+const FIX_0_390180644: i32 =  3196;
+const FIX_0_541196100: i32 =  4433;
+const FIX_0_765366865: i32 =  6270;
+const FIX_0_899976223: i32 =  7373;
+const FIX_1_175875602: i32 =  9633;
+const FIX_1_501321110: i32 = 12299;
+const FIX_1_847759065: i32 = 15137;
+const FIX_1_961570560: i32 = 16069;
+const FIX_2_053119869: i32 = 16819;
+const FIX_2_562915447: i32 = 20995;
+const FIX_3_072711026: i32 = 25172;
+
+const CONST_BITS: u8 = 13;
+const PASS1_BITS: u8 = 2;
+
+fn descale(val: i32, shift: u8) -> i32 {
+    (val + (1 << (shift - 1))) >> shift
+}
+
+fn fdct_1d(src: [i32; 8], dst: &mut [i32; 8], even_shift: u8, odd_shift: u8, even_scale: u8) {
+    let tmp0 = src[0] + src[7];
+    let tmp7 = src[0] - src[7];
+    let tmp1 = src[1] + src[6];
+    let tmp6 = src[1] - src[6];
+    let tmp2 = src[2] + src[5];
+    let tmp5 = src[2] - src[5];
+    let tmp3 = src[3] + src[4];
+    let tmp4 = src[3] - src[4];
+
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    if even_shift > 0 {
+        dst[0] = descale(tmp10 + tmp11, even_shift);
+        dst[4] = descale(tmp10 - tmp11, even_shift);
+    } else {
+        dst[0] = (tmp10 + tmp11) << even_scale;
+        dst[4] = (tmp10 - tmp11) << even_scale;
+    }
+
+    let z1 = (tmp12 + tmp13) * FIX_0_541196100;
+    dst[2] = descale(z1 + tmp13 *   FIX_0_765366865,  odd_shift);
+    dst[6] = descale(z1 + tmp12 * (-FIX_1_847759065), odd_shift);
+
+    let z1 = tmp4 + tmp7;
+    let z2 = tmp5 + tmp6;
+    let z3 = tmp4 + tmp6;
+    let z4 = tmp5 + tmp7;
+    let z5 = (z3 + z4) * FIX_1_175875602;
+
+    let tmp4 = tmp4 * FIX_0_298631336;
+    let tmp5 = tmp5 * FIX_2_053119869;
+    let tmp6 = tmp6 * FIX_3_072711026;
+    let tmp7 = tmp7 * FIX_1_501321110;
+    let z1 = z1 * -FIX_0_899976223;
+    let z2 = z2 * -FIX_2_562915447;
+    let z3 = z3 * -FIX_1_961570560 + z5;
+    let z4 = z4 * -FIX_0_390180644 + z5;
+
+    dst[7] = descale(tmp4 + z1 + z3, odd_shift);
+    dst[5] = descale(tmp5 + z2 + z4, odd_shift);
+    dst[3] = descale(tmp6 + z2 + z3, odd_shift);
+    dst[1] = descale(tmp7 + z1 + z4, odd_shift);
+}
+
+/// Forward 8x8 DCT producing coefficients in the scale expected by H.263 IDCT.
+pub fn fdct(blk: &mut [i16; 64]) {
+    let mut tmp = [0i32; 64];
+    for (drow, srow) in tmp.chunks_exact_mut(8).zip(blk.chunks_exact(8)) {
+        let mut src = [0; 8];
+        for (d, &s) in src.iter_mut().zip(srow.iter()) {
+            *d = i32::from(s);
+        }
+        let mut dst = [0; 8];
+        fdct_1d(src, &mut dst, 0, CONST_BITS - PASS1_BITS, PASS1_BITS);
+        drow.copy_from_slice(&dst);
+    }
+    for i in 0..8 {
+        let mut src = [0; 8];
+        for (j, el) in src.iter_mut().enumerate() {
+            *el = tmp[i + j * 8];
+        }
+        let mut dst = [0; 8];
+        // the last pass also removes the output scaling by eight
+        fdct_1d(src, &mut dst, PASS1_BITS + 3, CONST_BITS + PASS1_BITS + 3, 0);
+        for (j, &el) in dst.iter().enumerate() {
+            blk[i + j * 8] = el as i16;
+        }
+    }
+}
+
+pub fn quant_intra(blk: &mut [i16; 64], q: i16, max_level: i16) -> bool {
+    let dc = (blk[0] + 4) >> 3;
+    blk[0] = dc.clamp(1, 254);
+    let mut coded = false;
+    for coef in blk[1..].iter_mut() {
+        let level = (coef.abs() / (q * 2)).min(max_level);
+        *coef = if *coef < 0 { -level } else { level };
+        coded |= level != 0;
+    }
+    coded
+}
+
+pub fn quant_inter(blk: &mut [i16; 64], q: i16, max_level: i16) -> bool {
+    let mut coded = false;
+    for coef in blk.iter_mut() {
+        let level = ((coef.abs() - q / 2).max(0) / (q * 2)).min(max_level);
+        *coef = if *coef < 0 { -level } else { level };
+        coded |= level != 0;
+    }
+    coded
+}
+
+pub fn dequant(blk: &mut [i16; 64], q: i16, intra: bool) {
+    let q_add = (q - 1) | 1;
+    let start = if intra {
+            blk[0] <<= 3;
+            1
+        } else {
+            0
+        };
+    for coef in blk[start..].iter_mut() {
+        if *coef > 0 {
+            *coef = (*coef * q * 2 + q_add).min(2047);
+        } else if *coef < 0 {
+            *coef = (*coef * q * 2 - q_add).max(-2048);
+        }
+    }
+}
+
+pub fn sad(src1: &[u8], stride1: usize, src2: &[u8], stride2: usize) -> u32 {
+    let mut dist = 0;
+    for (row1, row2) in src1.chunks(stride1).zip(src2.chunks(stride2)).take(16) {
+        for (&a, &b) in row1[..16].iter().zip(row2[..16].iter()) {
+            dist += u32::from((i16::from(a) - i16::from(b)).unsigned_abs());
+        }
+    }
+    dist
+}
+
+pub struct MotionSearch<'a> {
+    pub src:    &'a [u8],
+    pub sstride: usize,
+    pub refpic: &'a [u8],
+    pub rstride: usize,
+    pub w:      usize,
+    pub h:      usize,
+    pub xpos:   usize,
+    pub ypos:   usize,
+}
+
+impl<'a> MotionSearch<'a> {
+    /// Fetches 17x17 reference block with the position clipped to the plane like decoder edge emulation does.
+    fn fetch_block(&self, dst: &mut [u8; 17 * 17], xpos: isize, ypos: isize) {
+        for (y, drow) in dst.chunks_exact_mut(17).enumerate() {
+            let sy = (ypos + (y as isize)).clamp(0, (self.h as isize) - 1) as usize;
+            let srow = &self.refpic[sy * self.rstride..];
+            for (x, el) in drow.iter_mut().enumerate() {
+                let sx = (xpos + (x as isize)).clamp(0, (self.w as isize) - 1) as usize;
+                *el = srow[sx];
+            }
+        }
+    }
+    /// Calculates SAD for the motion vector in half-pel units.
+    pub fn mv_sad(&self, mvx: i16, mvy: i16) -> u32 {
+        let mut blk = [0u8; 17 * 17];
+        let mut pred = [0u8; 16 * 16];
+        let mode = ((mvx & 1) + (mvy & 1) * 2) as usize;
+        self.fetch_block(&mut blk, (self.xpos as isize) + isize::from(mvx >> 1), (self.ypos as isize) + isize::from(mvy >> 1));
+        (H263_INTERP_FUNCS[mode])(&mut pred, 16, &blk, 17, 16, 16);
+        sad(&self.src[self.xpos + self.ypos * self.sstride..], self.sstride, &pred, 16)
+    }
+    pub fn intra_cost(&self) -> u32 {
+        let mut sum = 0;
+        for row in self.src[self.xpos + self.ypos * self.sstride..].chunks(self.sstride).take(16) {
+            sum += row[..16].iter().fold(0u32, |acc, &a| acc + u32::from(a));
+        }
+        let mean = ((sum + 128) >> 8) as i16;
+        let mut dev = 0;
+        for row in self.src[self.xpos + self.ypos * self.sstride..].chunks(self.sstride).take(16) {
+            dev += row[..16].iter().fold(0u32, |acc, &a| acc + u32::from((i16::from(a) - mean).unsigned_abs()));
+        }
+        dev
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nihav_codec_support::codecs::h263::code::h263_idct;
+
+    #[test]
+    fn test_fdct() {
+        let mut blk = [0i16; 64];
+        for (i, el) in blk.iter_mut().enumerate() {
+            *el = ((i * 37 + (i >> 3) * 11) % 97) as i16 - 48;
+        }
+        let orig = blk;
+        fdct(&mut blk);
+        h263_idct(&mut blk);
+        for (&a, &b) in blk.iter().zip(orig.iter()) {
+            assert!((a - b).abs() <= 1);
+        }
+
+        let mut blk = [100i16; 64];
+        fdct(&mut blk);
+        assert_eq!(blk[0], 800);
+        assert!(blk[1..].iter().all(|&c| c == 0));
+    }
+}
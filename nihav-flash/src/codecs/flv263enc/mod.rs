@@ -0,0 +1,675 @@
+use nihav_core::codecs::*;
+use nihav_core::io::bitwriter::*;
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use nihav_codec_support::codecs::blockdsp;
+use nihav_codec_support::codecs::h263::BlockDSP;
+use nihav_codec_support::codecs::h263::code::{H263BlockDSP, h263_idct};
+use nihav_codec_support::codecs::h263::data::H263_SIZES;
+
+mod coder;
+use coder::*;
+mod dsp;
+use dsp::*;
+mod ratectl;
+use ratectl::*;
+
+// picture sizes that have a dedicated code in Sorenson Spark picture header
+const SPARK_SIZES: [(usize, usize); 5] = [(352, 288), (176, 144), (128, 96), (320, 240), (160, 120)];
+
+// TMN-style bias in favour of inter coding
+const INTRA_BIAS: u32 = 500;
+
+struct MVPredictor {
+    mvs:    Vec<MV>,
+    mb_w:   usize,
+}
+
+impl MVPredictor {
+    fn new(mb_w: usize) -> Self {
+        Self {
+            mvs:    vec![ZERO_MV; mb_w * 2],
+            mb_w,
+        }
+    }
+    fn predict(&self, mb_x: usize, mb_y: usize) -> MV {
+        let left = if mb_x > 0 { self.mvs[self.mb_w + mb_x - 1] } else { ZERO_MV };
+        if mb_y == 0 {
+            return left;
+        }
+        let top = self.mvs[mb_x];
+        let top_right = if mb_x + 1 < self.mb_w { self.mvs[mb_x + 1] } else { ZERO_MV };
+        MV::pred(left, top, top_right)
+    }
+    fn set(&mut self, mb_x: usize, mv: MV) {
+        self.mvs[self.mb_w + mb_x] = mv;
+    }
+    fn update_row(&mut self) {
+        let (top, cur) = self.mvs.split_at_mut(self.mb_w);
+        top.copy_from_slice(cur);
+    }
+}
+
+struct H263Encoder {
+    stream:     Option<NAStreamRef>,
+    pkt:        Option<NAPacket>,
+    variant:    Variant,
+    key_int:    u8,
+    frmcount:   u8,
+    tr:         u8,
+    width:      usize,
+    height:     usize,
+    mb_w:       usize,
+    mb_h:       usize,
+    src:        [Vec<u8>; 3],
+    src_stride: [usize; 3],
+    ref_frm:    Option<NAVideoBufferRef<u8>>,
+    rlcodes:    RLCodes,
+    bdsp:       H263BlockDSP,
+    ratectl:    RateControl,
+    force_q:    Option<u8>,
+    me_range:   i16,
+}
+
+impl H263Encoder {
+    fn new(variant: Variant) -> Self {
+        Self {
+            stream:     None,
+            pkt:        None,
+            variant,
+            key_int:    25,
+            frmcount:   0,
+            tr:         0,
+            width:      0,
+            height:     0,
+            mb_w:       0,
+            mb_h:       0,
+            src:        [Vec::new(), Vec::new(), Vec::new()],
+            src_stride: [0; 3],
+            ref_frm:    None,
+            rlcodes:    RLCodes::new(),
+            bdsp:       H263BlockDSP::new(),
+            ratectl:    RateControl::new(),
+            force_q:    None,
+            me_range:   8,
+        }
+    }
+    fn read_frame(&mut self, vbuf: &NAVideoBuffer<u8>) {
+        let src = vbuf.get_data();
+        for (plane, (dst, &dstride)) in self.src.iter_mut().zip(self.src_stride.iter()).enumerate() {
+            let (w, h) = vbuf.get_dimensions(plane);
+            let sstride = vbuf.get_stride(plane);
+            let soff = vbuf.get_offset(plane);
+            let dheight = dst.len() / dstride;
+            for (y, drow) in dst.chunks_exact_mut(dstride).enumerate().take(dheight) {
+                let sy = y.min(h - 1);
+                let srow = &src[soff + sy * sstride..][..w];
+                drow[..w].copy_from_slice(srow);
+                let last = srow[w - 1];
+                for el in drow[w..].iter_mut() {
+                    *el = last;
+                }
+            }
+        }
+    }
+    fn get_mb_source(&self, mb_x: usize, mb_y: usize, blk: &mut [[i16; 64]; 6]) {
+        for (i, dblk) in blk.iter_mut().enumerate() {
+            let (plane, xoff, yoff) = if i < 4 {
+                    (0, mb_x * 16 + (i & 1) * 8, mb_y * 16 + (i >> 1) * 8)
+                } else {
+                    (i - 3, mb_x * 8, mb_y * 8)
+                };
+            let stride = self.src_stride[plane];
+            for (drow, srow) in dblk.chunks_exact_mut(8).zip(self.src[plane][xoff + yoff * stride..].chunks(stride)) {
+                for (dst, &src) in drow.iter_mut().zip(srow.iter()) {
+                    *dst = i16::from(src);
+                }
+            }
+        }
+    }
+    fn mv_valid(&self, mv: MV, pred_mv: MV, mb_x: usize, mb_y: usize) -> bool {
+        if mv.x < -32 || mv.x > 31 || mv.y < -32 || mv.y > 31 {
+            return false;
+        }
+        if self.variant == Variant::H263 {
+            // without unrestricted motion vectors mode the reference block should lie inside the picture
+            if (mv.x - pred_mv.x).abs() > 32 || (mv.y - pred_mv.y).abs() > 32 {
+                return false;
+            }
+            let xpos = (mb_x * 32) as isize + isize::from(mv.x);
+            let ypos = (mb_y * 32) as isize + isize::from(mv.y);
+            if xpos < 0 || ypos < 0 {
+                return false;
+            }
+            if (xpos >> 1) + 16 + (xpos & 1) > (self.width as isize) || (ypos >> 1) + 16 + (ypos & 1) > (self.height as isize) {
+                return false;
+            }
+        }
+        true
+    }
+    fn search_mv(&self, ms: &MotionSearch, mb_x: usize, mb_y: usize, pred_mv: MV, q: u8) -> (MV, u32) {
+        let lambda = u32::from(q);
+        let mv_cost = |mv: MV| -> Option<(u32, u32)> {
+                if self.mv_valid(mv, pred_mv, mb_x, mb_y) {
+                    let dist = ms.mv_sad(mv.x, mv.y);
+                    Some((dist + lambda * mv_bits(mv_diff(mv, pred_mv)), dist))
+                } else {
+                    None
+                }
+            };
+
+        let (mut best_cost, mut best_dist) = mv_cost(ZERO_MV).unwrap();
+        let mut best_mv = ZERO_MV;
+        let pred_fullpel = MV::new(pred_mv.x & !1, pred_mv.y & !1);
+        if pred_fullpel != ZERO_MV {
+            if let Some((cost, dist)) = mv_cost(pred_fullpel) {
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_dist = dist;
+                    best_mv   = pred_fullpel;
+                }
+            }
+        }
+
+        const DIA_PATTERN: [MV; 4] = [MV{ x: -2, y: 0 }, MV{ x: 2, y: 0 }, MV{ x: 0, y: -2 }, MV{ x: 0, y: 2 }];
+        let range = self.me_range * 2;
+        for _ in 0..self.me_range {
+            let mut cur_best = best_mv;
+            for &offset in DIA_PATTERN.iter() {
+                let mv = best_mv + offset;
+                if mv.x.abs() > range || mv.y.abs() > range {
+                    continue;
+                }
+                if let Some((cost, dist)) = mv_cost(mv) {
+                    if cost < best_cost {
+                        best_cost = cost;
+                        best_dist = dist;
+                        cur_best  = mv;
+                    }
+                }
+            }
+            if cur_best == best_mv {
+                break;
+            }
+            best_mv = cur_best;
+        }
+
+        let center = best_mv;
+        for yoff in -1..=1 {
+            for xoff in -1..=1 {
+                if xoff == 0 && yoff == 0 {
+                    continue;
+                }
+                let mv = center + MV::new(xoff, yoff);
+                if let Some((cost, dist)) = mv_cost(mv) {
+                    if cost < best_cost {
+                        best_cost = cost;
+                        best_dist = dist;
+                        best_mv   = mv;
+                    }
+                }
+            }
+        }
+
+        (best_mv, best_dist)
+    }
+    fn write_pic_header(&self, bw: &mut BitWriter, is_intra: bool, q: u8) {
+        match self.variant {
+            Variant::Spark => {
+                bw.write(1, 17); // picture start code
+                bw.write(1, 5);  // version
+                bw.write(u32::from(self.tr), 8);
+                if let Some(idx) = SPARK_SIZES.iter().position(|&dim| dim == (self.width, self.height)) {
+                    bw.write((idx + 2) as u32, 3);
+                } else if self.width < 256 && self.height < 256 {
+                    bw.write(0, 3);
+                    bw.write(self.width as u32, 8);
+                    bw.write(self.height as u32, 8);
+                } else {
+                    bw.write(1, 3);
+                    bw.write(self.width as u32, 16);
+                    bw.write(self.height as u32, 16);
+                }
+                bw.write(if is_intra { 0 } else { 1 }, 2);
+                bw.write0(); // deblocking
+                bw.write(u32::from(q), 5);
+                bw.write0(); // no extra information
+            },
+            Variant::H263 => {
+                let sfmt = H263_SIZES.iter().position(|&dim| dim == (self.width, self.height)).unwrap();
+                bw.write(0x20, 22); // picture start code
+                bw.write(u32::from(self.tr), 8);
+                bw.write1(); // marker
+                bw.write0(); // H.261 distinction bit
+                bw.write0(); // split screen
+                bw.write0(); // document camera
+                bw.write0(); // freeze picture release
+                bw.write(sfmt as u32, 3);
+                bw.write_bit(!is_intra);
+                bw.write0(); // unrestricted motion vectors
+                bw.write0(); // syntax-based arithmetic coding
+                bw.write0(); // advanced prediction
+                bw.write0(); // PB-frames
+                bw.write(u32::from(q), 5);
+                bw.write0(); // continuous presence multipoint
+                bw.write0(); // no extra information
+            },
+        }
+    }
+    fn code_intra_mb(&self, bw: &mut BitWriter, blk: &mut [[i16; 64]; 6], q: u8, is_intra: bool) {
+        let mut cbp = 0;
+        for (i, blk) in blk.iter_mut().enumerate() {
+            fdct(blk);
+            if quant_intra(blk, i16::from(q), self.variant.max_level()) {
+                cbp |= 0x20 >> i;
+            }
+        }
+        if is_intra {
+            write_intra_mcbpc(bw, cbp & 3);
+        } else {
+            bw.write0(); // coded macroblock
+            write_inter_mcbpc(bw, cbp & 3, true);
+        }
+        write_cbpy(bw, cbp >> 2, true);
+        for (i, blk) in blk.iter_mut().enumerate() {
+            write_intra_dc(bw, blk[0]);
+            if (cbp & (0x20 >> i)) != 0 {
+                self.rlcodes.write_block(bw, blk, 1, self.variant);
+            }
+            dequant(blk, i16::from(q), true);
+            h263_idct(blk);
+        }
+    }
+    fn encode_frame(&mut self, is_intra: bool, q: u8) -> EncoderResult<Vec<u8>> {
+        let mut bw = BitWriter::new(Vec::with_capacity(self.mb_w * self.mb_h * 64), BitWriterMode::BE);
+        self.write_pic_header(&mut bw, is_intra, q);
+
+        let vinfo = NAVideoInfo::new(self.width, self.height, false, YUV420_FORMAT);
+        let mut buf = alloc_video_buffer(vinfo, 4)?.get_vbuf().unwrap();
+        let ref_frm = if !is_intra { self.ref_frm.clone() } else { None };
+        let mut mvpred = MVPredictor::new(self.mb_w);
+        let mut blk = [[0i16; 64]; 6];
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                self.get_mb_source(mb_x, mb_y, &mut blk);
+                let ref_frm = if let Some(ref rfrm) = ref_frm {
+                        rfrm
+                    } else {
+                        self.code_intra_mb(&mut bw, &mut blk, q, true);
+                        blockdsp::put_blocks(&mut buf, mb_x, mb_y, &blk);
+                        continue;
+                    };
+
+                let pred_mv = mvpred.predict(mb_x, mb_y);
+                let (rw, rh) = ref_frm.get_dimensions(0);
+                let ms = MotionSearch {
+                        src:        &self.src[0],
+                        sstride:    self.src_stride[0],
+                        refpic:     &ref_frm.get_data()[ref_frm.get_offset(0)..],
+                        rstride:    ref_frm.get_stride(0),
+                        w:          rw,
+                        h:          rh,
+                        xpos:       mb_x * 16,
+                        ypos:       mb_y * 16,
+                    };
+                let (mv, inter_dist) = self.search_mv(&ms, mb_x, mb_y, pred_mv, q);
+                if ms.intra_cost() + INTRA_BIAS < inter_dist {
+                    self.code_intra_mb(&mut bw, &mut blk, q, false);
+                    blockdsp::put_blocks(&mut buf, mb_x, mb_y, &blk);
+                    mvpred.set(mb_x, ZERO_MV);
+                    continue;
+                }
+
+                self.bdsp.copy_blocks(&mut buf, ref_frm.clone(), mb_x * 16, mb_y * 16, mv);
+                let mut cbp = 0;
+                for (i, blk) in blk.iter_mut().enumerate() {
+                    let (plane, xoff, yoff) = if i < 4 {
+                            (0, mb_x * 16 + (i & 1) * 8, mb_y * 16 + (i >> 1) * 8)
+                        } else {
+                            (i - 3, mb_x * 8, mb_y * 8)
+                        };
+                    let stride = buf.get_stride(plane);
+                    let pred = &buf.get_data()[buf.get_offset(plane) + xoff + yoff * stride..];
+                    for (drow, prow) in blk.chunks_exact_mut(8).zip(pred.chunks(stride)) {
+                        for (dst, &p) in drow.iter_mut().zip(prow.iter()) {
+                            *dst -= i16::from(p);
+                        }
+                    }
+                    fdct(blk);
+                    if quant_inter(blk, i16::from(q), self.variant.max_level()) {
+                        cbp |= 0x20 >> i;
+                    }
+                }
+                if cbp == 0 && mv == ZERO_MV {
+                    bw.write1(); // not coded macroblock
+                    mvpred.set(mb_x, ZERO_MV);
+                    continue;
+                }
+                bw.write0(); // coded macroblock
+                write_inter_mcbpc(&mut bw, cbp & 3, false);
+                write_cbpy(&mut bw, cbp >> 2, false);
+                write_mv(&mut bw, mv_diff(mv, pred_mv));
+                for (i, blk) in blk.iter_mut().enumerate() {
+                    if (cbp & (0x20 >> i)) != 0 {
+                        self.rlcodes.write_block(&mut bw, blk, 0, self.variant);
+                    }
+                    dequant(blk, i16::from(q), false);
+                    h263_idct(blk);
+                }
+                blockdsp::add_blocks(&mut buf, mb_x, mb_y, &blk);
+                mvpred.set(mb_x, mv);
+            }
+            mvpred.update_row();
+        }
+        self.ref_frm = Some(buf);
+
+        Ok(bw.end())
+    }
+}
+
+impl NAEncoder for H263Encoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                let (w, h) = match self.variant {
+                        Variant::Spark => (vinfo.width.clamp(1, 65535), vinfo.height.clamp(1, 65535)),
+                        Variant::H263 => {
+                            // pick the smallest standard picture size fitting the requested one
+                            let sizes = &H263_SIZES[1..];
+                            if let Some(&dim) = sizes.iter().find(|&&(w, h)| w >= vinfo.width && h >= vinfo.height) {
+                                dim
+                            } else {
+                                sizes[sizes.len() - 1]
+                            }
+                        },
+                    };
+                let outinfo = NAVideoInfo::new(w, h, false, YUV420_FORMAT);
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Video(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(vinfo) => {
+                if vinfo.format != YUV420_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                match self.variant {
+                    Variant::Spark => {
+                        if vinfo.width == 0 || vinfo.height == 0 || vinfo.width > 65535 || vinfo.height > 65535 {
+                            return Err(EncoderError::FormatError);
+                        }
+                    },
+                    Variant::H263 => {
+                        if !H263_SIZES[1..].contains(&(vinfo.width, vinfo.height)) {
+                            return Err(EncoderError::FormatError);
+                        }
+                    },
+                }
+
+                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, false, YUV420_FORMAT);
+                let cname = match self.variant {
+                        Variant::Spark => "flv263",
+                        Variant::H263  => "h263",
+                    };
+                let info = NACodecInfo::new(cname, NACodecTypeInfo::Video(out_info), None);
+                let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+
+                self.width  = vinfo.width;
+                self.height = vinfo.height;
+                self.mb_w   = (vinfo.width  + 15) >> 4;
+                self.mb_h   = (vinfo.height + 15) >> 4;
+                self.src_stride = [self.mb_w * 16, self.mb_w * 8, self.mb_w * 8];
+                self.src = [vec![0; self.mb_w * self.mb_h * 256], vec![0; self.mb_w * self.mb_h * 64], vec![0; self.mb_w * self.mb_h * 64]];
+                self.ref_frm  = None;
+                self.frmcount = 0;
+                self.tr       = 0;
+
+                self.ratectl.init(self.mb_w, self.mb_h, encinfo.bitrate, encinfo.quality, encinfo.tb_num, encinfo.tb_den);
+
+                self.stream = Some(stream.clone());
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref vbuf) = buf.get_vbuf() {
+            let is_intra = self.frmcount == 0 || self.ref_frm.is_none();
+            let quant = if let Some(q) = self.force_q {
+                    q
+                } else {
+                    self.ratectl.guess_quant(is_intra)
+                };
+
+            self.read_frame(vbuf);
+            let dbuf = self.encode_frame(is_intra, quant)?;
+            if self.force_q.is_none() {
+                self.ratectl.update(is_intra, quant, dbuf.len() * 8);
+            }
+            self.tr = self.tr.wrapping_add(1);
+
+            self.pkt = Some(NAPacket::new(self.stream.clone().unwrap(), frm.ts, is_intra, dbuf));
+            if self.key_int > 0 {
+                self.frmcount += 1;
+            }
+            if self.frmcount == self.key_int {
+                self.frmcount = 0;
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        let mut npkt = None;
+        std::mem::swap(&mut self.pkt, &mut npkt);
+        Ok(npkt)
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.frmcount = 0;
+        Ok(())
+    }
+}
+
+const QUANT_OPTION: &str = "quant";
+const MV_RANGE_OPTION: &str = "mv_range";
+
+const ENCODER_OPTS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: KEYFRAME_OPTION, description: KEYFRAME_OPTION_DESC,
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(128)) },
+    NAOptionDefinition {
+        name: QUANT_OPTION, description: "force fixed quantiser for encoding",
+        opt_type: NAOptionDefinitionType::Int(Some(-1), Some(31)) },
+    NAOptionDefinition {
+        name: MV_RANGE_OPTION, description: "motion search range (in pixels)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(15)) },
+];
+
+impl NAOptionHandler for H263Encoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { ENCODER_OPTS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in ENCODER_OPTS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        KEYFRAME_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.key_int = intval as u8;
+                            }
+                        },
+                        QUANT_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.force_q = if intval < 1 { None } else { Some(intval as u8) };
+                            }
+                        },
+                        MV_RANGE_OPTION => {
+                            if let NAValue::Int(intval) = option.value {
+                                self.me_range = intval as i16;
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            KEYFRAME_OPTION => Some(NAValue::Int(i64::from(self.key_int))),
+            QUANT_OPTION => if let Some(q) = self.force_q {
+                    Some(NAValue::Int(i64::from(q)))
+                } else {
+                    Some(NAValue::Int(-1))
+                },
+            MV_RANGE_OPTION => Some(NAValue::Int(i64::from(self.me_range))),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(H263Encoder::new(Variant::Spark))
+}
+
+pub fn get_encoder_h263() -> Box<dyn NAEncoder + Send> {
+    Box::new(H263Encoder::new(Variant::H263))
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_core::demuxers::*;
+    use nihav_core::muxers::*;
+    use nihav_codec_support::test::enc_video::*;
+    use crate::*;
+
+    fn encode_synth(enc_options: &[NAOption], bitrate: u32, nframes: usize, ref_hash: &[u32; 4]) -> SynthTestResult {
+        let mut enc_reg = RegisteredEncoders::new();
+        flash_register_all_encoders(&mut enc_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        flash_register_all_decoders(&mut dec_reg);
+
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(100, 76, false, YUV420_FORMAT)),
+                quality: 0,
+                bitrate,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let result = test_encoding_synth_md5(&enc_reg, &dec_reg, "flv263", enc_params, enc_options, nframes, ref_hash);
+        assert!(result.max_mse[0] < 40);
+        assert!(result.max_mse[1] < 80 && result.max_mse[2] < 80);
+        result
+    }
+
+    #[test]
+    fn test_flv263_encoder_roundtrip() {
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(4) },
+                NAOption { name: KEYFRAME_OPTION, value: NAValue::Int(10) },
+            ];
+        let result = encode_synth(enc_options, 0, 12, &[0x4d46af92, 0x00591719, 0x144964e9, 0x5346e207]);
+        assert_eq!(result.keyframes, 2);
+    }
+    #[test]
+    fn test_flv263_encoder_ratectl() {
+        let low  = encode_synth(&[], 50000, 10, &[0x546b6349, 0xed327bda, 0x5c327493, 0x115fc6bd]);
+        let high = encode_synth(&[], 400000, 10, &[0x99f65ece, 0x62c4593b, 0x8c8de9e6, 0xb59c7910]);
+        assert!(low.size < high.size);
+    }
+    #[test]
+    fn test_flv263_encoder() {
+        let mut dmx_reg = RegisteredDemuxers::new();
+        flash_register_all_demuxers(&mut dmx_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        flash_register_all_decoders(&mut dec_reg);
+        let mut mux_reg = RegisteredMuxers::new();
+        flash_register_all_muxers(&mut mux_reg);
+        let mut enc_reg = RegisteredEncoders::new();
+        flash_register_all_encoders(&mut enc_reg);
+
+        // the same Sorenson H.263 sample as in the flv263 decoder test
+        let dec_config = DecoderTestParams {
+                demuxer:        "flv",
+                in_name:        "assets/Flash/input.flv",
+                stream_type:    StreamType::Video,
+                limit:          Some(10),
+                dmx_reg, dec_reg,
+            };
+        let enc_config = EncoderTestParams {
+                muxer:          "flv",
+                enc_name:       "flv263",
+                out_name:       "flv263.flv",
+                mux_reg, enc_reg,
+            };
+        let dst_vinfo = NAVideoInfo {
+                width:   0,
+                height:  0,
+                format:  YUV420_FORMAT,
+                flipped: false,
+                bits:    12,
+            };
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(dst_vinfo),
+                quality: 0,
+                bitrate: 0,
+                tb_num:  0,
+                tb_den:  0,
+                flags:   0,
+            };
+        let enc_options = &[
+                NAOption { name: super::QUANT_OPTION, value: NAValue::Int(6) },
+            ];
+        test_encoding_to_file(&dec_config, &enc_config, enc_params, enc_options);
+    }
+
+    #[test]
+    fn test_h263_encoder_header() {
+        let mut enc_reg = RegisteredEncoders::new();
+        flash_register_all_encoders(&mut enc_reg);
+        let mut enc = (enc_reg.find_encoder("h263").unwrap())();
+        let vinfo = NAVideoInfo::new(176, 144, false, YUV420_FORMAT);
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(160, 120, false, YUV420_FORMAT)),
+                quality: 0,
+                bitrate: 0,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        // non-standard picture sizes should be negotiated into standard ones
+        let enc_params = enc.negotiate_format(&enc_params).unwrap();
+        assert_eq!(enc_params.format.get_video_info().unwrap().width, 176);
+        let stream = enc.init(0, enc_params).unwrap();
+        assert_eq!(stream.get_info().get_name(), "h263");
+        for fno in 0..3 {
+            let buf = gen_test_frame(vinfo, fno);
+            let frm = NAFrame::new(NATimeInfo::new(Some(fno as u64), None, None, 1, 25), FrameType::Other, false, stream.get_info(), buf);
+            enc.encode(&frm).unwrap();
+            let pkt = enc.get_packet().unwrap().unwrap();
+            let data = pkt.get_buffer();
+            // picture start code, temporal reference and QCIF source format
+            assert_eq!(&data[..2], &[0x00, 0x00]);
+            assert_eq!(data[2] & 0xFC, 0x80);
+            assert_eq!(((data[2] & 3) << 6) | (data[3] >> 2), fno as u8);
+            assert_eq!((data[4] >> 2) & 7, 2);
+            assert_eq!(pkt.is_keyframe(), fno == 0);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+pub const DEFAULT_QUANT: u8 = 8;
+// initial guess for the amount of bits per macroblock multiplied by the quantiser
+const INTRA_MB_COMPLEXITY: f32 = 3000.0;
+const INTER_MB_COMPLEXITY: f32 =  600.0;
+
+pub struct RateControl {
+    tgt_br:         u32,
+    budget:         isize,
+    cur_time:       u32,
+    ts_num:         u32,
+    ts_den:         u32,
+    quality:        u8,
+    num_mbs:        usize,
+    complexity:     [f32; 2],
+}
+
+impl RateControl {
+    pub fn new() -> Self {
+        Self {
+            tgt_br:     0,
+            budget:     0,
+            cur_time:   0,
+            ts_num:     0,
+            ts_den:     0,
+            quality:    0,
+            num_mbs:    0,
+            complexity: [INTRA_MB_COMPLEXITY, INTER_MB_COMPLEXITY],
+        }
+    }
+    pub fn init(&mut self, mb_w: usize, mb_h: usize, bitrate: u32, quality: u8, ts_num: u32, ts_den: u32) {
+        self.num_mbs    = mb_w * mb_h;
+        self.quality    = quality.min(100);
+        self.cur_time   = 0;
+        self.complexity = [INTRA_MB_COMPLEXITY, INTER_MB_COMPLEXITY];
+        if bitrate == 0 || ts_num == 0 || ts_den == 0 {
+            self.tgt_br = 0;
+            self.budget = 0;
+        } else {
+            self.tgt_br     = bitrate;
+            self.budget     = bitrate as isize;
+            self.ts_num     = ts_num;
+            self.ts_den     = ts_den;
+        }
+    }
+    fn estimate_frame_size(&self, intra: bool, q: u8) -> usize {
+        let cplx = self.complexity[if intra { 0 } else { 1 }];
+        (cplx * (self.num_mbs as f32) / f32::from(q)) as usize
+    }
+    pub fn guess_quant(&self, intra: bool) -> u8 {
+        if self.tgt_br == 0 {
+            return if self.quality > 0 {
+                    1 + (100 - self.quality) * 30 / 100
+                } else {
+                    DEFAULT_QUANT
+                };
+        }
+        let fsize = self.get_target_frame_size(intra);
+        for q in 1..32 {
+            if self.estimate_frame_size(intra, q) <= fsize {
+                return q;
+            }
+        }
+        31
+    }
+    pub fn update(&mut self, intra: bool, q: u8, dsize: usize) {
+        if self.num_mbs > 0 {
+            let idx = if intra { 0 } else { 1 };
+            let cur_cplx = (dsize as f32) * f32::from(q) / (self.num_mbs as f32);
+            self.complexity[idx] = (self.complexity[idx] + cur_cplx) * 0.5;
+        }
+        if self.tgt_br == 0 {
+            return;
+        }
+        self.budget -= dsize as isize;
+        self.cur_time += self.ts_num;
+        while self.cur_time >= self.ts_den {
+            self.cur_time -= self.ts_den;
+            self.budget += self.tgt_br as isize;
+        }
+    }
+    fn get_target_frame_size(&self, intra: bool) -> usize {
+        let avg_fsize = (self.tgt_br as usize) * (self.ts_num as usize) / (self.ts_den as usize);
+        let mut fsize = self.budget / (((self.ts_den - self.cur_time) / self.ts_num).max(1) as isize);
+        if fsize <= 0 {
+            fsize = (avg_fsize / 2) as isize;
+        }
+        if intra {
+            fsize *= 3;
+        }
+        fsize as usize
+    }
+}
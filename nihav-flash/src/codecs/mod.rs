@@ -37,11 +37,18 @@ pub fn flash_register_all_decoders(rd: &mut RegisteredDecoders) {
     }
 }
 
+#[cfg(feature="encoder_flv263")]
+mod flv263enc;
 #[cfg(feature="encoder_flv_adpcm")]
 mod adpcmenc;
 
 #[cfg(feature="encoders")]
 const ENCODERS: &[EncoderInfo] = &[
+#[cfg(feature="encoder_flv263")]
+    EncoderInfo { name: "flv263", get_encoder: flv263enc::get_encoder },
+#[cfg(feature="encoder_flv263")]
+    EncoderInfo { name: "h263", get_encoder: flv263enc::get_encoder_h263 },
+
 #[cfg(feature="encoder_flv_adpcm")]
     EncoderInfo { name: "flv-adpcm", get_encoder: adpcmenc::get_encoder },
 ];
@@ -14,6 +14,7 @@ struct FLVDemuxer<'a> {
     duration:   u64,
     width:      usize,
     height:     usize,
+    metadata:   Metadata,
 }
 
 fn get_vcodec_name(tag: u8) -> DemuxerResult<&'static str> {
@@ -69,7 +70,7 @@ impl<'a> DemuxCore<'a> for FLVDemuxer<'a> {
             self.parse_tag(strmgr)?;
         }
     }
-    fn seek(&mut self, time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
+    fn seek(&mut self, time: NATimePoint, seek_index: &SeekIndex) -> DemuxerResult<()> {
         let dst_ms = match time {
                 NATimePoint::PTS(pts) => pts,
                 NATimePoint::Milliseconds(ms) => ms,
@@ -77,6 +78,10 @@ impl<'a> DemuxCore<'a> for FLVDemuxer<'a> {
             };
         self.apkts.clear();
         self.vpkts.clear();
+        if let Some(seek_info) = seek_index.find_pos(NATimePoint::Milliseconds(dst_ms)) {
+                                          self.src.seek(SeekFrom::Start(seek_info.pos))?;
+            return Ok(());
+        }
         let mut prev = None;
         loop {
             let ppos                    = self.src.read_u32be()?;
@@ -109,6 +114,17 @@ impl<'a> DemuxCore<'a> for FLVDemuxer<'a> {
         }
     }
     fn get_duration(&self) -> u64 { self.duration }
+    fn get_packet_pos(&mut self) -> Option<u64> {
+        if self.vpkts.is_empty() && self.apkts.is_empty() {
+            Some(self.src.tell())
+        } else {
+            None
+        }
+    }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
+    fn is_seekable(&mut self) -> bool { self.src.is_seekable() }
 }
 
 impl<'a> NAOptionHandler for FLVDemuxer<'a> {
@@ -130,6 +146,7 @@ impl<'a> FLVDemuxer<'a> {
             duration:   0,
             width:      0,
             height:     0,
+            metadata:   Metadata::new(),
         }
     }
     fn parse_tag(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<()> {
@@ -419,6 +436,15 @@ impl<'a> FLVDemuxer<'a> {
                                 let len     = self.src.read_u16be()? as usize;
                                 let mut val = vec![0; len];
                                               self.src.read_buf(&mut val)?;
+                                let name = String::from_utf8_lossy(&name);
+                                let key = match name.as_ref() {
+                                        "author"            => METADATA_ARTIST,
+                                        "description"       => METADATA_COMMENT,
+                                        "creationdate"      => METADATA_DATE,
+                                        "metadatacreator"   => METADATA_ENCODER,
+                                        _ => name.as_ref(),
+                                    };
+                                self.metadata.add_text(key, &String::from_utf8_lossy(&val));
                             },
                             3 => {
                                 break;//unimplemented!();
@@ -515,4 +541,49 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+    #[test]
+    fn test_flv_metadata() {
+        fn amf_string(dst: &mut Vec<u8>, name: &str, val: &str) {
+            dst.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            dst.extend_from_slice(name.as_bytes());
+            dst.push(2);
+            dst.extend_from_slice(&(val.len() as u16).to_be_bytes());
+            dst.extend_from_slice(val.as_bytes());
+        }
+        fn flv_tag(dst: &mut Vec<u8>, tag: u8, data: &[u8]) {
+            dst.push(tag);
+            dst.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            dst.extend_from_slice(&[0; 7]); // timestamp and stream ID
+            dst.extend_from_slice(data);
+            dst.extend_from_slice(&((data.len() + 11) as u32).to_be_bytes());
+        }
+
+        let mut script = vec![2, 0, 10];
+        script.extend_from_slice(b"onMetaData");
+        script.push(8);
+        script.extend_from_slice(&3u32.to_be_bytes());
+        amf_string(&mut script, "author", "Test author");
+        amf_string(&mut script, "description", "Test description");
+        amf_string(&mut script, "custom", "Custom value");
+        script.extend_from_slice(&[0, 0, 9]);
+
+        let mut file = b"FLV\x01\x04\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        flv_tag(&mut file, 18, &script);
+        // 11025Hz 16-bit mono PCM
+        flv_tag(&mut file, 8, &[0x36, 0, 0, 0, 0]);
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = FLVDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        let mut meta = ContainerMetadata::new();
+        dmx.fill_metadata(&mut meta);
+        let gmeta = meta.get_global();
+        assert_eq!(gmeta.get_text(METADATA_ARTIST), Some("Test author"));
+        assert_eq!(gmeta.get_text(METADATA_COMMENT), Some("Test description"));
+        assert_eq!(gmeta.get_text("custom"), Some("Custom value"));
+        assert_eq!(gmeta.get_text(METADATA_TITLE), None);
+    }
 }
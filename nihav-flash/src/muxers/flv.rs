@@ -47,6 +47,7 @@ fn find_audio_tag(cname: &'static str, rate: u32, channels: u8) -> MuxerResult<u
 trait FLVPropertyWriter {
     fn write_property_num(&mut self, name: &str, val: f64) -> MuxerResult<()>;
     fn write_property_bool(&mut self, name: &str, val: bool) -> MuxerResult<()>;
+    fn write_property_str(&mut self, name: &str, val: &str) -> MuxerResult<()>;
 }
 
 impl<'a> FLVPropertyWriter for ByteWriter<'a> {
@@ -64,8 +65,24 @@ impl<'a> FLVPropertyWriter for ByteWriter<'a> {
         self.write_byte(val as u8)?;
         Ok(())
     }
+    fn write_property_str(&mut self, name: &str, val: &str) -> MuxerResult<()> {
+        if val.len() > 0xFFFF {
+            return Err(MuxerError::InvalidArgument);
+        }
+        self.write_u16be(name.len() as u16)?;
+        self.write_buf(name.as_bytes())?;
+        self.write_byte(2)?;
+        self.write_u16be(val.len() as u16)?;
+        self.write_buf(val.as_bytes())?;
+        Ok(())
+    }
 }
 
+/// Properties written by the muxer itself.
+const RESERVED_PROPERTIES: &[&str] = &[
+    "width", "height", "videocodecid", "audiosamplerate", "stereo", "audiocodecid", "duration"
+];
+
 macro_rules! write_packet {
     ($self: expr, $pkt_type: expr, $ts: expr, $code: block) => {
         let start = $self.bw.tell();
@@ -94,6 +111,7 @@ struct FLVMuxer<'a> {
     vp6b:   u8,
     time:   u32,
     dpos:   u64,
+    meta:   Metadata,
 }
 
 impl<'a> FLVMuxer<'a> {
@@ -106,6 +124,7 @@ impl<'a> FLVMuxer<'a> {
             vp6b:   0,
             time:   0,
             dpos:   0,
+            meta:   Metadata::new(),
         }
     }
     fn write_metadata(&mut self, strmgr: &StreamManager) -> MuxerResult<()> {
@@ -126,6 +145,15 @@ impl<'a> FLVMuxer<'a> {
                     _ => {},
                 };
             }
+            for (key, value) in self.meta.iter() {
+                if let MetadataValue::Text(ref text) = *value {
+                    let name = if key.as_str() == METADATA_DATE { "creationdate" } else { key.as_str() };
+                    if name.is_empty() || name.len() > 0xFFFF || RESERVED_PROPERTIES.contains(&name) {
+                        continue;
+                    }
+                    self.bw.write_property_str(name, text)?;
+                }
+            }
             self.bw.write_property_num("duration", 0.0)?;
             self.dpos = self.bw.tell() - 8;
             self.bw.write_u16be(0)?;
@@ -266,6 +294,9 @@ impl<'a> MuxCore<'a> for FLVMuxer<'a> {
     fn flush(&mut self) -> MuxerResult<()> {
         Ok(())
     }
+    fn set_metadata(&mut self, metadata: &ContainerMetadata) {
+        self.meta = metadata.get_global().clone();
+    }
     fn end(&mut self) -> MuxerResult<()> {
         self.bw.seek(SeekFrom::Start(self.dpos))?;
         self.bw.write_f64be((self.time as f64) / 1000.0)?;
@@ -8,13 +8,13 @@ struct BMVDemuxer<'a> {
     vpos:       u64,
     apos:       u64,
     pkt_buf:    Vec<NAPacket>,
+    nframes:    u64,
+    key_apos:   Vec<(u64, u64)>,
 }
 
 impl<'a> DemuxCore<'a> for BMVDemuxer<'a> {
     #[allow(unused_variables)]
-    fn open(&mut self, strmgr: &mut StreamManager, _seek_index: &mut SeekIndex) -> DemuxerResult<()> {
-        let src = &mut self.src;
-
+    fn open(&mut self, strmgr: &mut StreamManager, seek_index: &mut SeekIndex) -> DemuxerResult<()> {
         let vhdr = NAVideoInfo::new(640, 429, false, PAL8_FORMAT);
         let vci = NACodecTypeInfo::Video(vhdr);
         let vinfo = NACodecInfo::new("bmv-video", vci, None);
@@ -24,6 +24,7 @@ impl<'a> DemuxCore<'a> for BMVDemuxer<'a> {
         let ainfo = NACodecInfo::new("bmv-audio", NACodecTypeInfo::Audio(ahdr), None);
         self.aud_id = strmgr.add_stream(NAStream::new(StreamType::Audio, 1, ainfo, 1, 22050, 0)).unwrap();
 
+        self.scan_chunks(seek_index)?;
         self.vpos       = 0;
         self.apos       = 0;
         Ok(())
@@ -70,10 +71,23 @@ impl<'a> DemuxCore<'a> for BMVDemuxer<'a> {
         }
     }
 
-    fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
-        Err(DemuxerError::NotPossible)
+    fn seek(&mut self, time: NATimePoint, seek_index: &SeekIndex) -> DemuxerResult<()> {
+        let ret = seek_index.find_pos(time);
+        if ret.is_none() {
+            return Err(DemuxerError::SeekError);
+        }
+        let seek_info = ret.unwrap();
+        if let Ok(idx) = self.key_apos.binary_search_by_key(&seek_info.pos, |&(pos, _)| pos) {
+            self.src.seek(SeekFrom::Start(seek_info.pos))?;
+            self.vpos = seek_info.pts;
+            self.apos = self.key_apos[idx].1;
+            self.pkt_buf.clear();
+            Ok(())
+        } else {
+            Err(DemuxerError::SeekError)
+        }
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { self.nframes * 1000 / 12 }
 }
 
 impl<'a> NAOptionHandler for BMVDemuxer<'a> {
@@ -91,7 +105,54 @@ impl<'a> BMVDemuxer<'a> {
             vpos:       0,
             apos:       0,
             pkt_buf:    Vec::with_capacity(1),
+            nframes:    0,
+            key_apos:   Vec::new(),
+        }
+    }
+    // the format has no header so the number of frames and keyframe positions are obtained by walking chunk headers
+    fn scan_chunks(&mut self, seek_index: &mut SeekIndex) -> DemuxerResult<()> {
+        let start = self.src.tell();
+        let mut vpos = 0;
+        let mut apos = 0;
+        loop {
+            let pos = self.src.tell();
+            let ctype = match self.src.read_byte() {
+                    Ok(val) => val,
+                    Err(_) => break,
+                };
+            if ctype == 0 {
+                continue;
+            }
+            if ctype == 1 {
+                break;
+            }
+            let size = match self.src.read_u24le() {
+                    Ok(val) => val as usize,
+                    Err(_) => break,
+                };
+            if size == 0 {
+                break;
+            }
+            if (ctype & 3) == 3 {
+                seek_index.add_entry(0, SeekEntry { time: vpos * 1000 / 12, pts: vpos, pos });
+                self.key_apos.push((pos, apos));
+            }
+            if (ctype & 0x20) != 0 {
+                if let Ok(nblocks) = self.src.peek_byte() {
+                    apos += u64::from(nblocks) * 32;
+                }
+            }
+            if self.src.read_skip(size).is_err() {
+                break;
+            }
+            vpos += 1;
+        }
+        self.src.seek(SeekFrom::Start(start))?;
+        self.nframes = vpos;
+        if !self.key_apos.is_empty() {
+            seek_index.mode = SeekIndexMode::Present;
         }
+        Ok(())
     }
 }
 
@@ -113,6 +174,7 @@ struct BMV3Demuxer<'a> {
     asize:      usize,
     ablob:      usize,
     pkt_buf:    Vec<NAPacket>,
+    duration:   u64,
 }
 
 impl<'a> DemuxCore<'a> for BMV3Demuxer<'a> {
@@ -148,6 +210,7 @@ impl<'a> DemuxCore<'a> for BMV3Demuxer<'a> {
         validate!(data_size > 0);
         self.asize = audio_size;
         self.ablob = audio_blob_size;
+        self.duration = if fps > 0 { (nframes as u64) * 256 * 1000 / u64::from(fps) } else { 0 };
 
         let vhdr = NAVideoInfo::new(width, height, false, RGB565_FORMAT);
         let vci = NACodecTypeInfo::Video(vhdr);
@@ -222,7 +285,7 @@ impl<'a> DemuxCore<'a> for BMV3Demuxer<'a> {
     fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
         Err(DemuxerError::NotPossible)
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { self.duration }
 }
 
 impl<'a> NAOptionHandler for BMV3Demuxer<'a> {
@@ -242,6 +305,7 @@ impl<'a> BMV3Demuxer<'a> {
             asize:      0,
             ablob:      0,
             pkt_buf:    Vec::with_capacity(1),
+            duration:   0,
         }
     }
 }
@@ -298,4 +362,27 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+    #[test]
+    fn test_bmv3_duration() {
+        for &(fps, duration) in [(12u16 * 256, 2500u64), (0, 0)].iter() {
+            let mut hdr = Vec::new();
+            hdr.extend_from_slice(b"BMVi");
+            hdr.extend_from_slice(&24u32.to_le_bytes());
+            hdr.extend_from_slice(&0u32.to_le_bytes());
+            hdr.extend_from_slice(&30u32.to_le_bytes());
+            hdr.extend_from_slice(&[0; 4]);
+            hdr.extend_from_slice(&fps.to_le_bytes());
+            hdr.extend_from_slice(&[82, 0, 41, 0, 0, 0]); // audio size, blob size and IDs
+            hdr.extend_from_slice(&[0x80, 2, 0xB0, 1]); // 640x432
+            hdr.extend_from_slice(b"DATA");
+            hdr.extend_from_slice(&1u32.to_le_bytes());
+            let mut mr = MemoryReader::new_read(&hdr);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = BMV3Demuxer::new(&mut br);
+            let mut sm = StreamManager::new();
+            let mut si = SeekIndex::new();
+            dmx.open(&mut sm, &mut si).unwrap();
+            assert_eq!(dmx.get_duration(), duration);
+        }
+    }
 }
@@ -11,6 +11,7 @@ struct FutureVisionVideoDemuxer<'a> {
     a_id:       Option<usize>,
     v_id:       Option<usize>,
     vframe:     bool,
+    fps:        u32,
 }
 
 impl<'a> DemuxCore<'a> for FutureVisionVideoDemuxer<'a> {
@@ -26,6 +27,7 @@ impl<'a> DemuxCore<'a> for FutureVisionVideoDemuxer<'a> {
         let _flags                      = src.read_u32le()?;
         let nframes                     = src.read_u32le()? as usize;
         let fps                         = src.read_u32le()?;
+        self.fps = fps;
         let arate                       = src.read_u32le()?;
         let abits                       = src.read_u32le()?;
 
@@ -81,7 +83,13 @@ impl<'a> DemuxCore<'a> for FutureVisionVideoDemuxer<'a> {
     fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
         Err(DemuxerError::NotPossible)
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        if self.fps > 0 {
+            (self.vsize.len() as u64) * 1000 / u64::from(self.fps)
+        } else {
+            0
+        }
+    }
 }
 impl<'a> NAOptionHandler for FutureVisionVideoDemuxer<'a> {
     fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
@@ -99,6 +107,7 @@ impl<'a> FutureVisionVideoDemuxer<'a> {
             a_id:       None,
             v_id:       None,
             vframe:     false,
+            fps:        0,
         }
     }
 }
@@ -227,4 +236,25 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_fst_duration() {
+        for &(fps, duration) in [(5u32, 800u64), (0, 0)].iter() {
+            let mut hdr = Vec::new();
+            hdr.extend_from_slice(b"2TSF");
+            for &val in [320u32, 200, 0, 4, fps, 0, 0].iter() {
+                hdr.extend_from_slice(&val.to_le_bytes());
+            }
+            for _ in 0..4 {
+                hdr.extend_from_slice(&[0; 6]);
+            }
+            let mut mr = MemoryReader::new_read(&hdr);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = FutureVisionVideoDemuxer::new(&mut br);
+            let mut sm = StreamManager::new();
+            let mut si = SeekIndex::new();
+            dmx.open(&mut sm, &mut si).unwrap();
+            assert_eq!(dmx.get_duration(), duration);
+        }
+    }
 }
@@ -11,6 +11,7 @@ enum GDVState {
 struct GremlinVideoDemuxer<'a> {
     src:        &'a mut ByteReader<'a>,
     frames:     u16,
+    fps:        u16,
     cur_frame:  u16,
     asize:      usize,
     apacked:    bool,
@@ -97,6 +98,7 @@ impl<'a> DemuxCore<'a> for GremlinVideoDemuxer<'a> {
             self.apacked = (aflags & 8) != 0;
         }
         self.frames = frames;
+        self.fps = fps;
         self.state = GDVState::NewFrame;
         Ok(())
     }
@@ -113,7 +115,13 @@ impl<'a> DemuxCore<'a> for GremlinVideoDemuxer<'a> {
     fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
         Err(DemuxerError::NotPossible)
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        if self.fps > 0 {
+            u64::from(self.frames) * 1000 / u64::from(self.fps)
+        } else {
+            0
+        }
+    }
 }
 impl<'a> NAOptionHandler for GremlinVideoDemuxer<'a> {
     fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
@@ -130,6 +138,7 @@ impl<'a> GremlinVideoDemuxer<'a> {
         GremlinVideoDemuxer {
             cur_frame: 0,
             frames: 0,
+            fps: 0,
             asize: 0,
             apacked: false,
             state: GDVState::NewFrame,
@@ -197,4 +206,23 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_gdv_duration() {
+        for &(fps, duration) in [(15u16, 2000u64), (0, 0)].iter() {
+            let mut hdr = Vec::new();
+            hdr.extend_from_slice(&0x29111994u32.to_le_bytes());
+            hdr.extend_from_slice(&[0, 0, 30, 0]); // ID and number of frames
+            hdr.extend_from_slice(&fps.to_le_bytes());
+            hdr.extend_from_slice(&[0; 10]); // no audio, no video
+            hdr.extend_from_slice(&[0x40, 1, 0xC8, 0]); // 320x200
+            let mut mr = MemoryReader::new_read(&hdr);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = GremlinVideoDemuxer::new(&mut br);
+            let mut sm = StreamManager::new();
+            let mut si = SeekIndex::new();
+            dmx.open(&mut sm, &mut si).unwrap();
+            assert_eq!(dmx.get_duration(), duration);
+        }
+    }
 }
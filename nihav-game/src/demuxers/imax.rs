@@ -11,6 +11,7 @@ struct IMAXDemuxer<'a> {
     v_id:       usize,
     pal:        Arc<[u8; 1024]>,
     pal_change: bool,
+    duration:   u64,
 }
 
 impl<'a> DemuxCore<'a> for IMAXDemuxer<'a> {
@@ -27,6 +28,7 @@ impl<'a> DemuxCore<'a> for IMAXDemuxer<'a> {
         let _zero                       = src.read_u16le()?;
         let _max_vframe_size            = src.read_u32le()?;
         let _buffering_size             = src.read_u32le()?;
+        self.duration = if fps > 0 { nframes * 1000 / u64::from(fps) } else { 0 };
 
         let vhdr = NAVideoInfo::new(320, 160, false, PAL8_FORMAT);
         let vci = NACodecTypeInfo::Video(vhdr);
@@ -87,7 +89,7 @@ impl<'a> DemuxCore<'a> for IMAXDemuxer<'a> {
     fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
         Err(DemuxerError::NotPossible)
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { self.duration }
 }
 impl<'a> NAOptionHandler for IMAXDemuxer<'a> {
     fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
@@ -104,6 +106,7 @@ impl<'a> IMAXDemuxer<'a> {
             v_id:       0,
             pal:        Arc::new([0; 1024]),
             pal_change: false,
+            duration:   0,
         }
     }
 }
@@ -141,4 +144,23 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+
+    #[test]
+    fn test_imax_duration() {
+        for &(fps, duration) in [(10u16, 3000u64), (0, 0)].iter() {
+            let mut hdr = Vec::new();
+            hdr.extend_from_slice(b"IMAX");
+            hdr.extend_from_slice(&30u32.to_le_bytes());
+            hdr.extend_from_slice(&fps.to_le_bytes());
+            hdr.extend_from_slice(&[0x02, 0x01, 0, 0]);
+            hdr.extend_from_slice(&[0; 8]);
+            let mut mr = MemoryReader::new_read(&hdr);
+            let mut br = ByteReader::new(&mut mr);
+            let mut dmx = IMAXDemuxer::new(&mut br);
+            let mut sm = StreamManager::new();
+            let mut si = SeekIndex::new();
+            dmx.open(&mut sm, &mut si).unwrap();
+            assert_eq!(dmx.get_duration(), duration);
+        }
+    }
 }
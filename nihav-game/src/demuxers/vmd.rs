@@ -28,11 +28,14 @@ struct VMDDemuxer<'a> {
     is_indeo:   bool,
     is_lhaud:   bool,
     frames:     Vec<FrameRec>,
+    nframes:    usize,
+    width:      usize,
+    height:     usize,
 }
 
 impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
     #[allow(unused_variables)]
-    fn open(&mut self, strmgr: &mut StreamManager, _seek_index: &mut SeekIndex) -> DemuxerResult<()> {
+    fn open(&mut self, strmgr: &mut StreamManager, seek_index: &mut SeekIndex) -> DemuxerResult<()> {
         let src = &mut self.src;
 
         let mut header: [u8; OLD_HEADER_SIZE] = [0; OLD_HEADER_SIZE];
@@ -47,6 +50,8 @@ impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
         let mut width  = read_u16le(&header[12..])? as usize;
         let mut height = read_u16le(&header[14..])? as usize;
         self.is_indeo = &header[24..27] == b"iv3";
+        self.width  = width;
+        self.height = height;
         if self.is_indeo && width > 320 {
             width  >>= 1;
             height >>= 1;
@@ -55,6 +60,7 @@ impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
         let nframes = read_u16le(&header[6..])? as usize;
         let fpb     = read_u16le(&header[18..])? as usize;
         validate!(nframes > 0 && fpb > 0);
+        self.nframes = nframes;
 
         let mut edata: Vec<u8> = Vec::with_capacity(OLD_HEADER_SIZE);
         edata.extend_from_slice(&header);
@@ -146,6 +152,7 @@ impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
         }
 
         self.fno = 0;
+        seek_index.mode = SeekIndexMode::Automatic;
         Ok(())
     }
 
@@ -159,6 +166,11 @@ impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
         }
 
         let is_video = cur_frame.chtype == CHTYPE_VIDEO;
+        // a frame updating the whole picture area is the best guess for a keyframe
+        let is_keyframe = is_video && !self.is_indeo &&
+                read_u16le(&cur_frame.hdr[0..])? == 0 && read_u16le(&cur_frame.hdr[2..])? == 0 &&
+                (read_u16le(&cur_frame.hdr[4..])? as usize) + 1 == self.width &&
+                (read_u16le(&cur_frame.hdr[6..])? as usize) + 1 == self.height;
         let mut buf: Vec<u8> = Vec::with_capacity(FRAME_HDR_SIZE + (cur_frame.size as usize));
         if !((is_video && self.is_indeo) || (!is_video && self.is_lhaud)) {
             buf.extend_from_slice(&cur_frame.hdr);
@@ -175,16 +187,24 @@ impl<'a> DemuxCore<'a> for VMDDemuxer<'a> {
         let str = strmgr.get_stream(str_id).unwrap();
         let (tb_num, tb_den) = str.get_timebase();
         let ts = NATimeInfo::new(Some(u64::from(cur_frame.ts)), None, None, tb_num, tb_den);
-        let pkt = NAPacket::new(str, ts, false, buf);
+        let pkt = NAPacket::new(str, ts, is_keyframe, buf);
 
         Ok(pkt)
     }
 
-    fn seek(&mut self, _time: NATimePoint, _seek_index: &SeekIndex) -> DemuxerResult<()> {
-        Err(DemuxerError::NotPossible)
+    fn seek(&mut self, time: NATimePoint, seek_index: &SeekIndex) -> DemuxerResult<()> {
+        let ret = seek_index.find_pos(time);
+        if ret.is_none() {
+            return Err(DemuxerError::SeekError);
+        }
+        let seek_info = ret.unwrap();
+        validate!(seek_info.pos < (self.frames.len() as u64));
+        self.fno = seek_info.pos as usize;
+        Ok(())
     }
 
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { (self.nframes as u64) * 1000 / 12 }
+    fn get_packet_pos(&mut self) -> Option<u64> { Some(self.fno as u64) }
 }
 
 impl<'a> NAOptionHandler for VMDDemuxer<'a> {
@@ -203,6 +223,9 @@ impl<'a> VMDDemuxer<'a> {
             is_indeo:   false,
             is_lhaud:   false,
             frames:     Vec::new(),
+            nframes:    0,
+            width:      0,
+            height:     0,
         }
     }
 }
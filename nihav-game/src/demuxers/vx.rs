@@ -15,6 +15,7 @@ struct VXDemuxer<'a> {
     num_aud:    usize,
     ano:        u64,
     num_afrm:   u64,
+    fps:        u32,
 }
 
 impl<'a> DemuxCore<'a> for VXDemuxer<'a> {
@@ -32,6 +33,7 @@ impl<'a> DemuxCore<'a> for VXDemuxer<'a> {
         let _unk                        = src.read_u32le()? as usize;
         let fps                         = src.read_u32le()?;
         validate!(fps > 0 && fps < 256);
+        self.fps = fps;
         let srate                       = src.read_u32le()?;
         let num_audio_tracks            = src.read_u32le()? as usize;
         let _max_frame_size             = src.read_u32le()? as usize;
@@ -100,7 +102,13 @@ impl<'a> DemuxCore<'a> for VXDemuxer<'a> {
         Err(DemuxerError::NotPossible)
     }
 
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        if self.fps > 0 {
+            self.num_vfrm * 1000 / u64::from(self.fps)
+        } else {
+            0
+        }
+    }
 }
 
 impl<'a> NAOptionHandler for VXDemuxer<'a> {
@@ -121,6 +129,7 @@ impl<'a> VXDemuxer<'a> {
             ano:        0,
             num_vfrm:   0,
             num_afrm:   0,
+            fps:        0,
             src:        io,
         }
     }
@@ -0,0 +1,77 @@
+use nihav_core::codecs::*;
+use std::str::FromStr;
+use crate::*;
+
+// generates a tone mixed with pseudo-random noise, with a silent gap in the middle
+pub fn gen_signal(len: usize, channels: usize) -> Vec<Vec<i16>> {
+    let mut seed = 42u32;
+    let mut left = Vec::with_capacity(len);
+    let mut right = Vec::with_capacity(len);
+    for i in 0..len {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        let silent = (5000..7000).contains(&i);
+        let noise = if silent { 0 } else { ((seed >> 20) as i32 - 2048) / 16 };
+        let t = i as f32;
+        let tone = if silent { 0.0 } else { (t * 0.031).sin() * 12000.0 };
+        left.push((tone + (t * 0.17).sin() * 1500.0) as i16 + noise as i16);
+        right.push((tone * 0.7) as i16 - (noise / 2) as i16);
+    }
+    let mut signal = vec![left, right];
+    signal.truncate(channels);
+    signal
+}
+
+// encodes the whole signal as one frame and returns the output stream and all encoded packets
+pub fn encode_signal(enc_name: &str, options: &[NAOption], srate: u32, block_len: usize, signal: &[Vec<i16>]) -> (NAStreamRef, Vec<NAPacket>) {
+    let len = signal[0].len();
+    let channels = signal.len() as u8;
+
+    let mut enc_reg = RegisteredEncoders::new();
+    llaudio_register_all_encoders(&mut enc_reg);
+    let mut encoder = (enc_reg.find_encoder(enc_name).unwrap())();
+    encoder.set_options(options);
+    let ainfo = NAAudioInfo::new(srate, channels, SND_S16P_FORMAT, block_len);
+    let enc_params = EncodeParameters {
+            format:  NACodecTypeInfo::Audio(ainfo),
+            quality: 0,
+            bitrate: 0,
+            tb_num:  1,
+            tb_den:  srate,
+            flags:   0,
+        };
+    let stream = encoder.init(0, enc_params).unwrap();
+
+    let chmap = NAChannelMap::from_str(if channels == 1 { "C" } else { "L,R" }).unwrap();
+    let abuf = alloc_audio_buffer(ainfo, len, chmap).unwrap();
+    if let NABufferType::AudioI16(ref buf) = abuf {
+        let mut buf = buf.clone();
+        let stride = buf.get_stride();
+        let data = buf.get_data_mut().unwrap();
+        for (dst, src) in data.chunks_mut(stride).zip(signal.iter()) {
+            dst[..len].copy_from_slice(src);
+        }
+    }
+    let frm = NAFrame::new(NATimeInfo::new(Some(0), None, None, 1, srate), FrameType::I, true, stream.get_info(), abuf);
+    encoder.encode(&frm).unwrap();
+    encoder.flush().unwrap();
+
+    let mut pkts = Vec::new();
+    while let Ok(Some(pkt)) = encoder.get_packet() {
+        pkts.push(pkt);
+    }
+    (stream, pkts)
+}
+
+// checks that the decoded frame matches the signal at the provided position and returns the frame length
+pub fn check_frame(frm: &NAFrameRef, signal: &[Vec<i16>], pos: usize) -> usize {
+    if let NABufferType::AudioI16(ref buf) = frm.get_buffer() {
+        let len = buf.get_length();
+        let stride = buf.get_stride();
+        for (dec, src) in buf.get_data().chunks(stride).zip(signal.iter()) {
+            assert_eq!(&dec[..len], &src[pos..][..len]);
+        }
+        len
+    } else {
+        panic!("wrong output format");
+    }
+}
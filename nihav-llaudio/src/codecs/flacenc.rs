@@ -2,6 +2,7 @@ use nihav_core::codecs::*;
 use nihav_core::io::bitwriter::*;
 use nihav_core::io::intcode::*;
 use nihav_codec_support::dsp::lpc::*;
+use nihav_codec_support::md5::MD5;
 
 fn to_unsigned(val: i32) -> u32 {
     if val >= 0 {
@@ -87,6 +88,11 @@ fn apply_stereo(samp1: &mut [i32], samp2: &mut [i32], smode: StereoMode) {
 
 fn apply_fixed_filter(dst: &mut [u32], src: &[i32], order: i8) {
     match order {
+        0 => {
+            for (dst, &cur) in dst.iter_mut().zip(src.iter()) {
+                *dst = to_unsigned(cur);
+            }
+        },
         -1 => {
             let mut last = src[0];
             for (dst, &cur) in dst[1..].iter_mut().zip(src[1..].iter()) {
@@ -140,37 +146,269 @@ fn apply_lpc(dst: &mut [u32], src: &[i32], filter: &[i32], shift: u8) {
     }
 }
 
-fn encode_residual(bw: &mut BitWriter, src: &[u32]) {
-    let sum = src.iter().sum::<u32>() / (src.len() as u32);
+/// Converts LPC filter into integer coefficients with the requested precision.
+/// Returns coefficient size in bits and filter shift.
+fn quantise_lpc(ffilter: &[f64], ifilter: &mut [i32], prec: u32) -> Option<(u8, u8)> {
+    let scale = f64::from(1 << prec);
+
+    let maxval = (ffilter.iter().fold(0.0, |acc: f64, &el| acc.max(el.abs())) * scale) as i32;
+    let mut mask = 0;
+    for (dst, &src) in ifilter.iter_mut().zip(ffilter.iter()) {
+        *dst = (src * scale) as i32;
+        mask |= *dst;
+    }
+    if mask == 0 {
+        return Some((1, 0));
+    }
+    let mut zbits = mask.trailing_zeros();
+    let mut filter_bits = 33 - maxval.leading_zeros() - zbits;
+    if filter_bits > 15 {
+        let sub = filter_bits - 15;
+        zbits += sub;
+        filter_bits = 15;
+    }
+    if zbits > prec {
+        return None;
+    }
+    if zbits > 0 {
+        for el in ifilter.iter_mut() {
+            *el >>= zbits;
+        }
+    }
+    Some((filter_bits as u8, (prec - zbits) as u8))
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum LPCWindow {
+    Rectangle,
+    Hann,
+    Welch,
+    Tukey,
+}
+
+impl std::fmt::Display for LPCWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            LPCWindow::Rectangle => write!(f, "rectangle"),
+            LPCWindow::Hann      => write!(f, "hann"),
+            LPCWindow::Welch     => write!(f, "welch"),
+            LPCWindow::Tukey     => write!(f, "tukey"),
+        }
+    }
+}
+
+fn apply_window(dst: &mut Vec<f64>, src: &[i32], window: LPCWindow) {
+    dst.clear();
+    let len = src.len();
+    if len < 3 {
+        dst.extend(src.iter().map(|&el| f64::from(el)));
+        return;
+    }
+    let n = (len - 1) as f64;
+    match window {
+        LPCWindow::Rectangle => {
+            dst.extend(src.iter().map(|&el| f64::from(el)));
+        },
+        LPCWindow::Hann => {
+            for (i, &el) in src.iter().enumerate() {
+                let w = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * (i as f64) / n).cos();
+                dst.push(f64::from(el) * w);
+            }
+        },
+        LPCWindow::Welch => {
+            let half = n / 2.0;
+            for (i, &el) in src.iter().enumerate() {
+                let x = ((i as f64) - half) / half;
+                dst.push(f64::from(el) * (1.0 - x * x));
+            }
+        },
+        LPCWindow::Tukey => {
+            // Tukey window with half of the block being tapered
+            let taper = n / 4.0;
+            for (i, &el) in src.iter().enumerate() {
+                let pos = (i as f64).min(n - (i as f64));
+                let w = if pos < taper {
+                        0.5 - 0.5 * (std::f64::consts::PI * pos / taper).cos()
+                    } else {
+                        1.0
+                    };
+                dst.push(f64::from(el) * w);
+            }
+        },
+    };
+}
+
+fn estimate_rice_param(src: &[u32]) -> u8 {
+    if src.is_empty() {
+        return 0;
+    }
+    let sum = src.iter().fold(0u64, |acc, &el| acc + u64::from(el)) / (src.len() as u64);
+    (63 - sum.max(1).leading_zeros()) as u8
+}
+
+fn rice_bits(src: &[u32], k: u8) -> usize {
+    src.iter().fold(0, |acc, &el| acc + ((el >> k) as usize)) + src.len() * (usize::from(k) + 1)
+}
+
+#[derive(Clone,Default)]
+struct RiceParams {
+    part_order: u8,
+    long:       bool,
+    params:     Vec<u8>,
+}
+
+/// Finds the best residual partitioning and Rice parameters, returns the number of bits required to code the residual.
+fn find_rice_params(res: &[u32], nsamples: usize, order: usize, max_part_order: u8, search: bool, rice: &mut RiceParams) -> usize {
+    let mut best_bits = usize::MAX;
+    for part_order in 0..=max_part_order {
+        let nparts = 1 << part_order;
+        let psize = nsamples >> part_order;
+        if (nsamples & (nparts - 1)) != 0 || psize <= order {
+            break;
+        }
+        let mut params = Vec::with_capacity(nparts);
+        let mut bits = 0;
+        let mut start = order;
+        for part in 0..nparts {
+            let len = if part == 0 { psize - order } else { psize };
+            let src = &res[start..][..len];
+            start += len;
+
+            let mut k = estimate_rice_param(src).min(30);
+            let mut kbits = rice_bits(src, k);
+            if search {
+                if k > 0 {
+                    let nbits = rice_bits(src, k - 1);
+                    if nbits < kbits {
+                        k -= 1;
+                        kbits = nbits;
+                    }
+                }
+                if k < 30 {
+                    let nbits = rice_bits(src, k + 1);
+                    if nbits < kbits {
+                        k += 1;
+                        kbits = nbits;
+                    }
+                }
+            }
+            params.push(k);
+            bits += kbits;
+        }
+        let long = params.iter().any(|&k| k >= 15);
+        bits += 2 + 4 + nparts * if long { 5 } else { 4 };
+        if bits < best_bits {
+            best_bits = bits;
+            rice.part_order = part_order;
+            rice.long = long;
+            rice.params = params;
+        }
+    }
+    best_bits
+}
 
-    let k = (31 - sum.max(1).leading_zeros()) as u8;
-    if k < 16 {
+fn encode_residual(bw: &mut BitWriter, src: &[u32], order: usize, rice: &RiceParams) {
+    if !rice.long {
         bw.write(0, 2);
-        bw.write(0, 4); // 1 partition
-        bw.write(u32::from(k), 4);
     } else {
         bw.write(1, 2);
-        bw.write(0, 4); // 1 partition
-        bw.write(u32::from(k), 5);
     }
-    for &samp in src.iter() {
-        bw.write_code(UintCodeType::Rice(k), samp);
+    bw.write(u32::from(rice.part_order), 4);
+    let psize = src.len() >> rice.part_order;
+    let mut start = order;
+    for (part, &k) in rice.params.iter().enumerate() {
+        let len = if part == 0 { psize - order } else { psize };
+        bw.write(u32::from(k), if !rice.long { 4 } else { 5 });
+        for &samp in src[start..][..len].iter() {
+            bw.write_code(UintCodeType::Rice(k), samp);
+        }
+        start += len;
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Predictor {
+    Constant,
+    Verbatim,
+    Fixed(usize),
+    Lpc(usize),
+}
+
+#[derive(Clone)]
+struct SubframeInfo {
+    pred:           Predictor,
+    filter:         [i32; 32],
+    filter_bits:    u8,
+    shift:          u8,
+    rice:           RiceParams,
+    bits:           usize,
+}
+
+impl SubframeInfo {
+    fn new(pred: Predictor, bits: usize) -> Self {
+        Self {
+            pred, bits,
+            filter:         [0; 32],
+            filter_bits:    0,
+            shift:          0,
+            rice:           RiceParams::default(),
+        }
     }
 }
 
+struct ChannelData {
+    samples:    Vec<i32>,
+    samp_bits:  u8,
+    info:       SubframeInfo,
+}
+
+#[derive(Clone,Copy)]
+struct CompressionParams {
+    max_lpc_order:  usize,
+    max_part_order: u8,
+    prec_search:    bool,
+    exhaustive:     bool,
+    stereo_search:  bool,
+    rice_search:    bool,
+    var_block:      bool,
+    window:         LPCWindow,
+}
+
+const COMPRESSION_LEVELS: [CompressionParams; 9] = [
+    CompressionParams { max_lpc_order:  0, max_part_order: 0, prec_search: false, exhaustive: false, stereo_search: false, rice_search: false, var_block: false, window: LPCWindow::Rectangle },
+    CompressionParams { max_lpc_order:  0, max_part_order: 3, prec_search: false, exhaustive: false, stereo_search: false, rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order:  0, max_part_order: 3, prec_search: false, exhaustive: false, stereo_search: true,  rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order:  6, max_part_order: 4, prec_search: false, exhaustive: false, stereo_search: false, rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order:  8, max_part_order: 4, prec_search: false, exhaustive: false, stereo_search: true,  rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order:  8, max_part_order: 5, prec_search: false, exhaustive: false, stereo_search: true,  rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order:  8, max_part_order: 6, prec_search: true,  exhaustive: false, stereo_search: true,  rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order: 12, max_part_order: 6, prec_search: true,  exhaustive: true,  stereo_search: true,  rice_search: true,  var_block: false, window: LPCWindow::Tukey },
+    CompressionParams { max_lpc_order: 12, max_part_order: 6, prec_search: true,  exhaustive: true,  stereo_search: true,  rice_search: true,  var_block: true,  window: LPCWindow::Tukey },
+];
+
+const DEFAULT_COMPRESSION: u8 = 5;
+/// Approximate frame header and footer size in bits.
+const FRAME_OVERHEAD_BITS: usize = 64;
+const MIN_VAR_BLOCK_LEN: usize = 256;
+
 #[derive(Default)]
 struct FLACEncoder {
     stream:     Option<NAStreamRef>,
     channels:   usize,
     srate:      u32,
     block_len:  usize,
-    order:      i8,
+    level:      u8,
+    order:      Option<i8>,
+    window:     Option<LPCWindow>,
+    part_order: Option<u8>,
+    var_block:  Option<bool>,
     samples:    Vec<Vec<i32>>,
     flush:      bool,
     cur_pos:    usize,
     tmp:        Vec<u32>,
+    wsamples:   Vec<f64>,
     ffilter:    [f64; 32],
-    ifilter:    [i32; 32],
+    md5:        MD5,
 }
 
 #[allow(clippy::match_overlapping_arm)]
@@ -249,13 +487,35 @@ fn write_utf8(bw: &mut BitWriter, val: usize) {
 const DEFAULT_BLOCK_LEN: usize = 4096;
 
 impl FLACEncoder {
-    fn new() -> Self { Self::default() }
+    fn new() -> Self {
+        Self {
+            level:  DEFAULT_COMPRESSION,
+            ..Default::default()
+        }
+    }
+    fn get_params(&self) -> CompressionParams {
+        let mut cparams = COMPRESSION_LEVELS[usize::from(self.level)];
+        if let Some(window) = self.window {
+            cparams.window = window;
+        }
+        if let Some(part_order) = self.part_order {
+            cparams.max_part_order = part_order;
+        }
+        if let Some(var_block) = self.var_block {
+            cparams.var_block = var_block;
+        }
+        cparams
+    }
     fn encode_packet(&mut self) -> EncoderResult<NAPacket> {
-        if self.samples[0].is_empty() || (!self.flush && self.samples[0].len() < self.block_len) {
+        if self.samples[0].is_empty() || (!self.flush && self.samples[0].len() <= self.block_len) {
             return Err(EncoderError::TryAgain);
         }
 
-        let nsamples = self.samples[0].len().min(self.block_len);
+        let cparams = self.get_params();
+        let mut nsamples = self.samples[0].len().min(self.block_len);
+        if cparams.var_block && nsamples == self.block_len {
+            nsamples = self.find_block_len(nsamples, &cparams);
+        }
 
         let mut bw = BitWriter::new(Vec::with_capacity(1024), BitWriterMode::BE);
         bw.write(0x3FFE, 14);
@@ -264,14 +524,7 @@ impl FLACEncoder {
         let scode = nsamples_code(nsamples);
         bw.write(u32::from(scode), 4);
         bw.write(0x0, 4); // sample rate - read the stream info
-        let smode = if self.channels == 2 {
-                let (l, r) = self.samples.split_at_mut(1);
-                let smode = find_stereo_mode(&l[0][..nsamples], &r[0][..nsamples]);
-                apply_stereo(&mut l[0][..nsamples], &mut r[0][..nsamples], smode);
-                smode
-            } else {
-                StereoMode::Normal
-            };
+        let (smode, chdata) = self.analyse_frame(0, nsamples, &cparams);
         let chan_idx = match smode {
                 StereoMode::Normal    => (self.channels as u32) - 1,
                 StereoMode::LeftSide  => 8,
@@ -286,14 +539,8 @@ impl FLACEncoder {
         // optional bits per sample
         bw.write(0x00, 8); // header CRC placeholder
         let hdr_crc_pos = bw.tell() >> 3;
-        for chan in 0..self.channels {
-            let samp_bits = match (smode, chan) {
-                    (StereoMode::LeftSide,  1) |
-                    (StereoMode::SideRight, 0) |
-                    (StereoMode::MidSide,   1) => 16 + 1,
-                    _ => 16,
-                };
-            self.encode_channel(&mut bw, chan, nsamples, samp_bits);
+        for chan in chdata.iter() {
+            self.encode_channel(&mut bw, chan);
         }
 
         let mut dbuf = bw.end();
@@ -306,90 +553,227 @@ impl FLACEncoder {
         }
         self.cur_pos += nsamples;
 
-        Ok(NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf))
-    }
-    fn encode_channel(&mut self, bw: &mut BitWriter, chan: usize, nsamples: usize, samp_bits: u8) {
-        let samp = &self.samples[chan][..nsamples];
+        let mut pkt = NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf);
+        if self.flush && self.samples[0].is_empty() {
+            let mut md5 = [0; 16];
+            self.md5.finish();
+            self.md5.get_hash_bytes(&mut md5);
+            pkt.add_side_data(NASideData::StreamMD5(md5));
+            self.md5 = MD5::new();
+        }
 
-        let s0 = samp[0];
-        let mut same = true;
-        for &s in samp[1..].iter() {
-            if s != s0 {
-                same = false;
-                break;
+        Ok(pkt)
+    }
+    /// Selects the length of the next block by checking whether splitting the block into smaller ones would require fewer bits.
+    fn find_block_len(&mut self, len: usize, cparams: &CompressionParams) -> usize {
+        let (first_len, _) = self.best_split(0, len, 2, cparams);
+        first_len
+    }
+    fn best_split(&mut self, start: usize, len: usize, depth: u8, cparams: &CompressionParams) -> (usize, usize) {
+        let (_, chdata) = self.analyse_frame(start, len, cparams);
+        let whole = chdata.iter().fold(FRAME_OVERHEAD_BITS, |acc, ch| acc + ch.info.bits);
+        let half = len / 2;
+        if depth == 0 || half < MIN_VAR_BLOCK_LEN {
+            return (len, whole);
+        }
+        let (first_len, bits1) = self.best_split(start, half, depth - 1, cparams);
+        let (_, bits2) = self.best_split(start + half, len - half, depth - 1, cparams);
+        if bits1 + bits2 < whole {
+            (first_len, bits1 + bits2)
+        } else {
+            (len, whole)
+        }
+    }
+    fn analyse_frame(&mut self, start: usize, nsamples: usize, cparams: &CompressionParams) -> (StereoMode, Vec<ChannelData>) {
+        let mut chdata = Vec::with_capacity(self.channels);
+        if self.channels != 2 {
+            for chan in 0..self.channels {
+                let samples = self.samples[chan][start..][..nsamples].to_vec();
+                let info = self.analyse_channel(&samples, 16, cparams);
+                chdata.push(ChannelData { samples, samp_bits: 16, info });
             }
+            return (StereoMode::Normal, chdata);
         }
 
-        bw.write0();
-        if !same {
-            match self.order {
-                0 => {
-                    bw.write(1, 6);
-                    bw.write0(); // no wasted bits
-                    for &el in samp.iter() {
-                        bw.write_s(el, samp_bits);
-                    }
-                },
-                -1 | -2 | -3 | -4 => {
-                    let order = -self.order as usize;
+        let mut l = self.samples[0][start..][..nsamples].to_vec();
+        let mut r = self.samples[1][start..][..nsamples].to_vec();
+        if !cparams.stereo_search {
+            let smode = find_stereo_mode(&l, &r);
+            apply_stereo(&mut l, &mut r, smode);
+            let (lbits, rbits) = match smode {
+                    StereoMode::LeftSide  => (16, 17),
+                    StereoMode::SideRight => (17, 16),
+                    StereoMode::MidSide   => (16, 17),
+                    StereoMode::Normal    => (16, 16),
+                };
+            let linfo = self.analyse_channel(&l, lbits, cparams);
+            let rinfo = self.analyse_channel(&r, rbits, cparams);
+            chdata.push(ChannelData { samples: l, samp_bits: lbits, info: linfo });
+            chdata.push(ChannelData { samples: r, samp_bits: rbits, info: rinfo });
+            return (smode, chdata);
+        }
 
-                    apply_fixed_filter(&mut self.tmp, samp, self.order);
+        let mut s = Vec::with_capacity(nsamples);
+        let mut m = Vec::with_capacity(nsamples);
+        for (&a, &b) in l.iter().zip(r.iter()) {
+            let side = a - b;
+            s.push(side);
+            m.push(b + (side >> 1));
+        }
+        let linfo = self.analyse_channel(&l, 16, cparams);
+        let rinfo = self.analyse_channel(&r, 16, cparams);
+        let sinfo = self.analyse_channel(&s, 17, cparams);
+        let minfo = self.analyse_channel(&m, 16, cparams);
 
-                    bw.write(8 | (order as u32), 6);
-                    bw.write0(); // no wasted bits
-                    for &el in samp[..order].iter() {
-                        bw.write_s(el, samp_bits);
-                    }
-                    encode_residual(bw, &self.tmp[order..nsamples]);
-                },
-                _ => {
-                    let order = self.order as usize;
-                    calc_lpc_filter(samp, &mut self.ffilter[..order]);
-
-                    let mut filter_prec = 12;
-                    let scale = f64::from(1 << filter_prec);
-
-                    let maxval = (self.ffilter[..order].iter().fold(0.0, |acc: f64, &el| acc.max(el.abs())) * scale) as i32;
-                    let mut mask = 0;
-                    for (dst, &src) in self.ifilter[..order].iter_mut().zip(&self.ffilter) {
-                        *dst = (src * scale) as i32;
-                        mask |= *dst;
-                    }
-                    let mut zbits = mask.trailing_zeros();
-                    let mut filter_bits = 33 - maxval.leading_zeros() - zbits;
-                    if filter_bits > 15 {
-                        let sub = filter_bits - 15;
-                        zbits += sub;
-                        filter_bits = 15;
-                    }
-                    filter_prec -= zbits;
-                    if zbits > 0 {
-                        for el in self.ifilter[..order].iter_mut() {
-                            *el >>= zbits;
-                        }
-                    }
+        let costs = [linfo.bits + rinfo.bits, linfo.bits + sinfo.bits, sinfo.bits + rinfo.bits, minfo.bits + sinfo.bits];
+        let mut best_idx = 0;
+        for (i, &cost) in costs.iter().enumerate().skip(1) {
+            if cost < costs[best_idx] {
+                best_idx = i;
+            }
+        }
+        let (smode, first, second) = match best_idx {
+                0 => (StereoMode::Normal,    ChannelData { samples: l, samp_bits: 16, info: linfo }, ChannelData { samples: r, samp_bits: 16, info: rinfo }),
+                1 => (StereoMode::LeftSide,  ChannelData { samples: l, samp_bits: 16, info: linfo }, ChannelData { samples: s, samp_bits: 17, info: sinfo }),
+                2 => (StereoMode::SideRight, ChannelData { samples: s, samp_bits: 17, info: sinfo }, ChannelData { samples: r, samp_bits: 16, info: rinfo }),
+                _ => (StereoMode::MidSide,   ChannelData { samples: m, samp_bits: 16, info: minfo }, ChannelData { samples: s, samp_bits: 17, info: sinfo }),
+            };
+        chdata.push(first);
+        chdata.push(second);
+        (smode, chdata)
+    }
+    fn analyse_channel(&mut self, samp: &[i32], samp_bits: u8, cparams: &CompressionParams) -> SubframeInfo {
+        let nsamples = samp.len();
+        let sbits = usize::from(samp_bits);
 
-                    apply_lpc(&mut self.tmp, samp, &self.ifilter[..order], filter_prec as u8);
+        let s0 = samp[0];
+        if samp[1..].iter().all(|&s| s == s0) {
+            return SubframeInfo::new(Predictor::Constant, 8 + sbits);
+        }
 
-                    bw.write(0x20 | ((order - 1) as u32), 6);
-                    bw.write0(); // no wasted bits
-                    for &el in samp[..order].iter() {
-                        bw.write_s(el, samp_bits);
+        let mut best = SubframeInfo::new(Predictor::Verbatim, 8 + nsamples * sbits);
+        match self.order {
+            Some(0) => {},
+            // forced predictor is used even if it is worse than verbatim coding
+            Some(order) if order < 0 => {
+                let order = -order as usize;
+                if nsamples > order {
+                    best.bits = usize::MAX;
+                    self.try_fixed(samp, sbits, order, cparams, &mut best);
+                }
+            },
+            Some(order) => {
+                let order = order as usize;
+                if nsamples > order {
+                    let verbatim_bits = best.bits;
+                    best.bits = usize::MAX;
+                    self.try_lpc(samp, sbits, order, cparams, &mut best);
+                    if best.pred == Predictor::Verbatim {
+                        best.bits = verbatim_bits;
                     }
-                    bw.write(filter_bits - 1, 4);
-                    bw.write(filter_prec, 5);
-                    for &coef in self.ifilter[..order].iter().rev() {
-                        bw.write_s(coef, filter_bits as u8);
+                }
+            },
+            None => {
+                for order in 0..=4 {
+                    if nsamples > order {
+                        self.try_fixed(samp, sbits, order, cparams, &mut best);
                     }
-                    encode_residual(bw, &self.tmp[order..nsamples]);
-                },
-            };
+                }
+                let max_order = cparams.max_lpc_order.min(nsamples - 1);
+                if max_order > 0 {
+                    let min_order = if cparams.exhaustive { 1 } else { max_order };
+                    for order in min_order..=max_order {
+                        self.try_lpc(samp, sbits, order, cparams, &mut best);
+                    }
+                }
+            },
+        };
+        best
+    }
+    fn try_fixed(&mut self, samp: &[i32], sbits: usize, order: usize, cparams: &CompressionParams, best: &mut SubframeInfo) {
+        let nsamples = samp.len();
+        apply_fixed_filter(&mut self.tmp, samp, -(order as i8));
+        let mut rice = RiceParams::default();
+        let bits = 8 + order * sbits + find_rice_params(&self.tmp[..nsamples], nsamples, order, cparams.max_part_order, cparams.rice_search, &mut rice);
+        if bits < best.bits {
+            *best = SubframeInfo::new(Predictor::Fixed(order), bits);
+            best.rice = rice;
+        }
+    }
+    fn try_lpc(&mut self, samp: &[i32], sbits: usize, order: usize, cparams: &CompressionParams, best: &mut SubframeInfo) {
+        let nsamples = samp.len();
+        if cparams.window == LPCWindow::Rectangle {
+            calc_lpc_filter(samp, &mut self.ffilter[..order]);
         } else {
-            bw.write(0, 6);
-            bw.write0(); // no wasted bits
-            bw.write(u32::from(s0 as u16), samp_bits);
+            apply_window(&mut self.wsamples, samp, cparams.window);
+            calc_lpc_filter(&self.wsamples, &mut self.ffilter[..order]);
+        }
+
+        let precisions: &[u32] = if cparams.prec_search { &[12, 13, 14, 15] } else { &[12] };
+        let mut filter = [0; 32];
+        for &prec in precisions.iter() {
+            if let Some((filter_bits, shift)) = quantise_lpc(&self.ffilter[..order], &mut filter[..order], prec) {
+                apply_lpc(&mut self.tmp, samp, &filter[..order], shift);
+                let mut rice = RiceParams::default();
+                let bits = 8 + order * sbits + 4 + 5 + order * usize::from(filter_bits)
+                         + find_rice_params(&self.tmp[..nsamples], nsamples, order, cparams.max_part_order, cparams.rice_search, &mut rice);
+                if bits < best.bits {
+                    *best = SubframeInfo::new(Predictor::Lpc(order), bits);
+                    best.filter = filter;
+                    best.filter_bits = filter_bits;
+                    best.shift = shift;
+                    best.rice = rice;
+                }
+            }
         }
     }
+    fn encode_channel(&mut self, bw: &mut BitWriter, chan: &ChannelData) {
+        let samp = &chan.samples;
+        let samp_bits = chan.samp_bits;
+        let info = &chan.info;
+        let nsamples = samp.len();
+
+        bw.write0();
+        match info.pred {
+            Predictor::Constant => {
+                bw.write(0, 6);
+                bw.write0(); // no wasted bits
+                bw.write_s(samp[0], samp_bits);
+            },
+            Predictor::Verbatim => {
+                bw.write(1, 6);
+                bw.write0(); // no wasted bits
+                for &el in samp.iter() {
+                    bw.write_s(el, samp_bits);
+                }
+            },
+            Predictor::Fixed(order) => {
+                apply_fixed_filter(&mut self.tmp, samp, -(order as i8));
+
+                bw.write(8 | (order as u32), 6);
+                bw.write0(); // no wasted bits
+                for &el in samp[..order].iter() {
+                    bw.write_s(el, samp_bits);
+                }
+                encode_residual(bw, &self.tmp[..nsamples], order, &info.rice);
+            },
+            Predictor::Lpc(order) => {
+                apply_lpc(&mut self.tmp, samp, &info.filter[..order], info.shift);
+
+                bw.write(0x20 | ((order - 1) as u32), 6);
+                bw.write0(); // no wasted bits
+                for &el in samp[..order].iter() {
+                    bw.write_s(el, samp_bits);
+                }
+                bw.write(u32::from(info.filter_bits) - 1, 4);
+                bw.write(u32::from(info.shift), 5);
+                for &coef in info.filter[..order].iter().rev() {
+                    bw.write_s(coef, info.filter_bits);
+                }
+                encode_residual(bw, &self.tmp[..nsamples], order, &info.rice);
+            },
+        };
+    }
 }
 
 impl NAEncoder for FLACEncoder {
@@ -484,6 +868,15 @@ impl NAEncoder for FLACEncoder {
                     }
                 }
             }
+
+            let start = self.samples[0].len() - len;
+            let mut bytes = Vec::with_capacity(len * self.channels * 2);
+            for i in start..start + len {
+                for chan in self.samples.iter() {
+                    bytes.extend_from_slice(&(chan[i] as i16).to_le_bytes());
+                }
+            }
+            self.md5.update_hash(&bytes);
             Ok(())
         } else {
             Err(EncoderError::InvalidParameters)
@@ -504,11 +897,23 @@ impl NAEncoder for FLACEncoder {
 
 const ENCODER_OPTS: &[NAOptionDefinition] = &[
     NAOptionDefinition {
-        name: "order", description: "LPC order",
+        name: "compression", description: "compression level (0-8)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(8)) },
+    NAOptionDefinition {
+        name: "order", description: "force LPC order (negative values select fixed predictors)",
         opt_type: NAOptionDefinitionType::Int(Some(-4), Some(32)) },
     NAOptionDefinition {
         name: "block_size", description: "block size",
         opt_type: NAOptionDefinitionType::Int(Some(2), Some(65535)) },
+    NAOptionDefinition {
+        name: "window", description: "window function for LPC analysis",
+        opt_type: NAOptionDefinitionType::String(Some(&["rectangle", "hann", "welch", "tukey"])) },
+    NAOptionDefinition {
+        name: "max_partition_order", description: "maximum residual partition order",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(15)) },
+    NAOptionDefinition {
+        name: "variable_block", description: "use variable block size",
+        opt_type: NAOptionDefinitionType::Bool },
 ];
 
 impl NAOptionHandler for FLACEncoder {
@@ -518,9 +923,14 @@ impl NAOptionHandler for FLACEncoder {
             for opt_def in ENCODER_OPTS.iter() {
                 if opt_def.check(option).is_ok() {
                     match option.name {
+                        "compression" => {
+                            if let NAValue::Int(val) = option.value {
+                                self.level = val as u8;
+                            }
+                        },
                         "order" => {
                             if let NAValue::Int(val) = option.value {
-                                self.order = val as i8;
+                                self.order = Some(val as i8);
                             }
                         },
                         "block_size" => {
@@ -529,6 +939,27 @@ impl NAOptionHandler for FLACEncoder {
                                 self.tmp.resize(self.block_len, 0);
                             }
                         },
+                        "window" => {
+                            if let NAValue::String(ref str) = option.value {
+                                match str.as_str() {
+                                    "rectangle" => self.window = Some(LPCWindow::Rectangle),
+                                    "hann"      => self.window = Some(LPCWindow::Hann),
+                                    "welch"     => self.window = Some(LPCWindow::Welch),
+                                    "tukey"     => self.window = Some(LPCWindow::Tukey),
+                                    _ => {},
+                                };
+                            }
+                        },
+                        "max_partition_order" => {
+                            if let NAValue::Int(val) = option.value {
+                                self.part_order = Some(val as u8);
+                            }
+                        },
+                        "variable_block" => {
+                            if let NAValue::Bool(val) = option.value {
+                                self.var_block = Some(val);
+                            }
+                        },
                         _ => {},
                     };
                 }
@@ -536,9 +967,14 @@ impl NAOptionHandler for FLACEncoder {
         }
     }
     fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        let cparams = self.get_params();
         match name {
-            "order" => Some(NAValue::Int(i64::from(self.order))),
+            "compression" => Some(NAValue::Int(i64::from(self.level))),
+            "order" => self.order.map(|order| NAValue::Int(i64::from(order))),
             "block_size" => Some(NAValue::Int(self.block_len as i64)),
+            "window" => Some(NAValue::String(cparams.window.to_string())),
+            "max_partition_order" => Some(NAValue::Int(i64::from(cparams.max_part_order))),
+            "variable_block" => Some(NAValue::Bool(cparams.var_block)),
             _ => None,
         }
     }
@@ -553,12 +989,15 @@ mod test {
     use nihav_core::codecs::*;
     use nihav_core::demuxers::*;
     use nihav_core::muxers::*;
+    use nihav_codec_support::md5::MD5;
     use nihav_codec_support::test::enc_video::*;
     use crate::*;
+    use super::super::enctest::*;
 
     #[test]
     fn test_flac_encoder_verbatim() {
         let enc_options = &[
+                NAOption{name: "compression", value: NAValue::Int(0)},
                 NAOption{name: "order", value: NAValue::Int(0)},
             ];
         test_flac_encoder("uncompr.flac", enc_options,
@@ -567,6 +1006,7 @@ mod test {
     #[test]
     fn test_flac_encoder_fixed1() {
         let enc_options = &[
+                NAOption{name: "compression", value: NAValue::Int(0)},
                 NAOption{name: "order", value: NAValue::Int(-1)},
             ];
         test_flac_encoder("fixed1.flac", enc_options,
@@ -575,6 +1015,7 @@ mod test {
     #[test]
     fn test_flac_encoder_fixed4() {
         let enc_options = &[
+                NAOption{name: "compression", value: NAValue::Int(0)},
                 NAOption{name: "order", value: NAValue::Int(-4)},
             ];
         test_flac_encoder("fixed1.flac", enc_options,
@@ -583,12 +1024,68 @@ mod test {
     #[test]
     fn test_flac_encoder_lpc10() {
         let enc_options = &[
+                NAOption{name: "compression", value: NAValue::Int(0)},
                 NAOption{name: "order", value: NAValue::Int(10)},
             ];
         test_flac_encoder("lpc10.flac", enc_options,
                           &[0xb6736f2e, 0xb61dda7a, 0xeb4037db, 0x8ee4afb9]);
     }
 
+    const SYNTH_LEN: usize = 4096 * 3 + 1000;
+
+    /// Encodes the synthetic signal, decodes it back and returns the encoded packets.
+    fn encode_synth(enc_options: &[NAOption], signal: &[Vec<i16>]) -> Vec<NAPacket> {
+        let mut dec_reg = RegisteredDecoders::new();
+        llaudio_register_all_decoders(&mut dec_reg);
+
+        let (_, pkts) = encode_signal("flac", enc_options, 44100, 4096, signal);
+        assert!(!pkts.is_empty());
+        assert!(matches!(pkts.last().unwrap().side_data.last(), Some(NASideData::StreamMD5(_))));
+
+        let ainfo = NAAudioInfo::new(44100, 2, SND_S16P_FORMAT, 4096);
+        let mut edata = vec![0; 34];
+        edata[2..4].copy_from_slice(&4096u16.to_be_bytes());
+        edata[10..14].copy_from_slice(&((44100u32 << 12) | (1 << 9) | (15 << 4)).to_be_bytes());
+        let info = NACodecInfo::new("flac", NACodecTypeInfo::Audio(ainfo), Some(edata)).into_ref();
+        let mut decoder = (dec_reg.find_decoder("flac").unwrap())();
+        let mut dsupp = NADecoderSupport::new();
+        decoder.init(&mut dsupp, info.clone()).unwrap();
+        let dstream = NAStream::new(StreamType::Audio, 0, (*info).clone(), 1, 44100, 0).into_ref();
+
+        let mut pos = 0;
+        for pkt in pkts.iter() {
+            let dpkt = NAPacket::new(dstream.clone(), pkt.ts, true, pkt.get_buffer().to_vec());
+            let frm = decoder.decode(&mut dsupp, &dpkt).unwrap();
+            pos += check_frame(&frm, signal, pos);
+        }
+        assert_eq!(pos, SYNTH_LEN);
+        pkts
+    }
+    #[test]
+    fn test_flac_encoder_roundtrip() {
+        let signal = gen_signal(SYNTH_LEN, 2);
+        let mut last_size = usize::MAX;
+        for &level in [0, 5, 8].iter() {
+            let pkts = encode_synth(&[NAOption{name: "compression", value: NAValue::Int(level)}], &signal);
+            let size = pkts.iter().fold(0, |acc, pkt| acc + pkt.get_buffer().len());
+            assert!(size <= last_size);
+            last_size = size;
+        }
+    }
+    #[test]
+    fn test_flac_encoder_default() {
+        let signal = gen_signal(SYNTH_LEN, 2);
+        let pkts = encode_synth(&[], &signal);
+        let mut md5 = MD5::new();
+        for pkt in pkts.iter() {
+            md5.update_hash(&pkt.get_buffer());
+        }
+        md5.finish();
+        let mut hash = [0; 4];
+        md5.get_hash(&mut hash);
+        assert_eq!(hash, [0x6e2850de, 0xfc439e49, 0x9eb42edb, 0x2c0316cf]);
+    }
+
     fn test_flac_encoder(name: &'static str, enc_options: &[NAOption], hash: &[u32; 4]) {
         let mut dmx_reg = RegisteredDemuxers::new();
         llaudio_register_all_demuxers(&mut dmx_reg);
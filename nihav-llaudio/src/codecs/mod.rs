@@ -50,12 +50,25 @@ pub fn llaudio_register_all_packetisers(rp: &mut RegisteredPacketisers) {
     }
 }
 
+#[cfg(all(test, feature="decoders", feature="encoders"))]
+pub mod enctest;
+
 #[cfg(feature="encoder_flac")]
 pub mod flacenc;
 
+#[cfg(feature="encoder_tta")]
+pub mod ttaenc;
+
+#[cfg(feature="encoder_wavpack")]
+pub mod wavpackenc;
+
 const LL_AUDIO_ENCODERS: &[EncoderInfo] = &[
-#[cfg(feature="decoder_flac")]
+#[cfg(feature="encoder_flac")]
     EncoderInfo { name: "flac", get_encoder: flacenc::get_encoder },
+#[cfg(feature="encoder_tta")]
+    EncoderInfo { name: "tta", get_encoder: ttaenc::get_encoder },
+#[cfg(feature="encoder_wavpack")]
+    EncoderInfo { name: "wavpack", get_encoder: wavpackenc::get_encoder },
 ];
 
 /// Registers all available encoders provided by this crate.
@@ -208,12 +208,16 @@ fn check_crc(buf: &[u8]) -> bool {
     if buf.len() <= 4 {
         return false;
     }
-    let mut crc = 0xFFFFFFFF;
     let ref_crc = read_u32le(&buf[buf.len() - 4..]).unwrap_or(0);
-    for el in buf.iter().take(buf.len() - 4) {
+    calc_crc(&buf[..buf.len() - 4]) == ref_crc
+}
+
+pub(crate) fn calc_crc(buf: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF;
+    for el in buf.iter() {
         crc = CRC32_TAB[(crc as u8 ^ *el) as usize] ^ (crc >> 8);
     }
-    crc == !ref_crc
+    !crc
 }
 
 impl NADecoder for TTADecoder {
@@ -346,7 +350,7 @@ mod test {
     }
 }
 
-const CRC32_TAB: [u32; 256] = [
+pub(crate) const CRC32_TAB: [u32; 256] = [
 	0x00000000, 0x77073096, 0xee0e612c, 0x990951ba,
     0x076dc419, 0x706af48f, 0xe963a535, 0x9e6495a3,
     0x0edb8832, 0x79dcb8a4, 0xe0d5e91e, 0x97d2d988,
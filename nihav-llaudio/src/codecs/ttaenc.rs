@@ -0,0 +1,353 @@
+use nihav_core::codecs::*;
+use nihav_core::io::bitwriter::*;
+use nihav_core::io::intcode::*;
+use super::tta::calc_crc;
+
+#[derive(Default)]
+struct Filter {
+    predictor:  i32,
+    error:      i32,
+    round:      i32,
+    shift:      u8,
+    qm:         [i32; 8],
+    dx:         [i32; 8],
+    dl:         [i32; 8],
+}
+
+impl Filter {
+    fn reset(&mut self, bpp: u8) {
+        const SHIFTS: [u8; 3] = [10, 9, 10];
+        self.shift = SHIFTS[(bpp - 1) as usize];
+        self.round = (1 << self.shift) >> 1;
+        self.error = 0;
+        self.qm = [0; 8];
+        self.dx = [0; 8];
+        self.dl = [0; 8];
+        self.predictor = 0;
+    }
+    fn hybrid_filt(&mut self, val: i32) -> i32 {
+        if self.error < 0 {
+            for (qm, dx) in self.qm.iter_mut().zip(self.dx.iter()) {
+                *qm -= *dx;
+            }
+        } else if self.error > 0 {
+            for (qm, dx) in self.qm.iter_mut().zip(self.dx.iter()) {
+                *qm += *dx;
+            }
+        }
+
+        let mut sum = self.round;
+        for (dl, qm) in self.dl.iter().zip(self.qm.iter()) {
+            sum = sum.wrapping_add(*dl * *qm);
+        }
+        let delta = val - (sum >> self.shift);
+        self.error = delta;
+
+        for i in 0..4 {
+            self.dx[i] = self.dx[i + 1];
+            self.dl[i] = self.dl[i + 1];
+        }
+        self.dx[4] =  (self.dl[4] >> 30) | 1;
+        self.dx[5] = ((self.dl[5] >> 30) | 2) & !1;
+        self.dx[6] = ((self.dl[6] >> 30) | 2) & !1;
+        self.dx[7] = ((self.dl[7] >> 30) | 4) & !3;
+        self.dl[4] = -self.dl[5];
+        self.dl[5] = -self.dl[6];
+        self.dl[6] = val - self.dl[7];
+        self.dl[7] = val;
+        self.dl[5] += self.dl[6];
+        self.dl[4] += self.dl[5];
+
+        delta
+    }
+    fn static_pred(&mut self, bpp: u8, val: i32) -> i32 {
+        let pred = match bpp {
+                0     => ((i64::from(self.predictor) * 15) >> 4) as i32,
+                1 | 2 => ((i64::from(self.predictor) * 31) >> 5) as i32,
+                _     => self.predictor,
+            };
+        self.predictor = val;
+        val - pred
+    }
+}
+
+struct RiceCoder {
+    k:      u8,
+    sum:    u32,
+}
+
+impl RiceCoder {
+    fn new() -> Self {
+        let k = 10;
+        Self {
+            k, sum: RiceCoder::limit(k)
+        }
+    }
+    fn reset(&mut self) {
+        self.k = 10;
+        self.sum = RiceCoder::limit(self.k);
+    }
+    fn limit(k: u8) -> u32 { 1 << (k + 4).min(31) }
+    fn update(&mut self, val: u32) {
+        self.sum -= self.sum >> 4;
+        self.sum += val;
+        if self.k > 0 && self.sum < Self::limit(self.k) {
+            self.k -= 1;
+        } else if self.sum > Self::limit(self.k + 1) {
+            self.k += 1;
+        }
+    }
+}
+
+struct ChannelEncoder {
+    filt:       Filter,
+    rice0:      RiceCoder,
+    rice1:      RiceCoder,
+}
+
+impl ChannelEncoder {
+    fn new() -> Self {
+        Self {
+            filt:       Filter::default(),
+            rice0:      RiceCoder::new(),
+            rice1:      RiceCoder::new(),
+        }
+    }
+    fn encode_sample(&mut self, bw: &mut BitWriter, bpp: u8, sample: i32) {
+        let hval = self.filt.static_pred(bpp, sample);
+        let delta = self.filt.hybrid_filt(hval);
+        let val = if delta > 0 { (delta * 2 - 1) as u32 } else { (-delta * 2) as u32 };
+
+        let k0 = self.rice0.k;
+        if val < (1 << k0) {
+            bw.write_code(UintCodeType::UnaryOnes, 0);
+            bw.write(val, k0);
+        } else {
+            let val1 = val - (1 << k0);
+            let k1 = self.rice1.k;
+            bw.write_code(UintCodeType::UnaryOnes, (val1 >> k1) + 1);
+            bw.write(val1 & ((1 << k1) - 1), k1);
+            self.rice1.update(val1);
+        }
+        self.rice0.update(val);
+    }
+}
+
+struct TTAEncoder {
+    stream:     Option<NAStreamRef>,
+    samples:    Vec<Vec<i32>>,
+    srate:      u32,
+    framelen:   usize,
+    flush:      bool,
+    ch_enc:     Vec<ChannelEncoder>,
+    cur_pos:    u64,
+}
+
+impl TTAEncoder {
+    fn new() -> Self {
+        Self {
+            stream:     None,
+            samples:    Vec::new(),
+            srate:      0,
+            framelen:   0,
+            flush:      false,
+            ch_enc:     Vec::new(),
+            cur_pos:    0,
+        }
+    }
+    fn encode_packet(&mut self) -> EncoderResult<NAPacket> {
+        if self.samples.is_empty() || self.samples[0].is_empty() || (!self.flush && self.samples[0].len() < self.framelen) {
+            return Err(EncoderError::TryAgain);
+        }
+
+        let nsamples = self.samples[0].len().min(self.framelen);
+        let bpp = 2;
+        for chenc in self.ch_enc.iter_mut() {
+            chenc.rice0.reset();
+            chenc.rice1.reset();
+            chenc.filt.reset(bpp);
+        }
+
+        let channels = self.ch_enc.len();
+        let mut bw = BitWriter::new(Vec::with_capacity(nsamples * channels * 2), BitWriterMode::LE);
+        let mut cur = [0; 2];
+        for i in 0..nsamples {
+            for (dst, src) in cur.iter_mut().zip(self.samples.iter()) {
+                *dst = src[i];
+            }
+            if channels > 1 {
+                cur[0] = cur[1] - cur[0];
+                cur[1] -= cur[0] / 2;
+            }
+            for (chenc, &samp) in self.ch_enc.iter_mut().zip(cur.iter()) {
+                chenc.encode_sample(&mut bw, bpp, samp);
+            }
+        }
+        let mut dbuf = bw.end();
+        let crc = calc_crc(&dbuf);
+        dbuf.extend_from_slice(&crc.to_le_bytes());
+
+        for samp in self.samples.iter_mut() {
+            samp.drain(..nsamples);
+        }
+        let ts = NATimeInfo::new(Some(self.cur_pos), None, Some(nsamples as u64), 1, self.srate);
+        self.cur_pos += nsamples as u64;
+
+        Ok(NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf))
+    }
+}
+
+impl NAEncoder for TTAEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Audio(NAAudioInfo::new(44100, 2, SND_S16P_FORMAT, 0)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                let mut outinfo = ainfo;
+                outinfo.channels = outinfo.channels.clamp(1, 2);
+                outinfo.sample_rate = outinfo.sample_rate.clamp(257, 1048575);
+                if outinfo.format != SND_S16P_FORMAT && outinfo.format != SND_S16_FORMAT {
+                    outinfo.format = SND_S16P_FORMAT;
+                }
+                outinfo.block_len = (outinfo.sample_rate * 256 / 245) as usize;
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Audio(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                if ainfo.format != SND_S16P_FORMAT && ainfo.format != SND_S16_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.channels == 0 || ainfo.channels > 2 {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.sample_rate <= 256 || ainfo.sample_rate >= 1048576 {
+                    return Err(EncoderError::FormatError);
+                }
+                self.srate = ainfo.sample_rate;
+                self.framelen = (self.srate * 256 / 245) as usize;
+
+                let out_ainfo = NAAudioInfo::new(ainfo.sample_rate, ainfo.channels, SND_S16P_FORMAT, self.framelen);
+                let info = NACodecInfo::new("tta", NACodecTypeInfo::Audio(out_ainfo), None);
+                let mut stream = NAStream::new(StreamType::Audio, stream_id, info, 1, ainfo.sample_rate, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+                self.stream = Some(stream.clone());
+
+                self.samples.clear();
+                self.ch_enc.clear();
+                for _ in 0..ainfo.channels {
+                    self.samples.push(Vec::with_capacity(self.framelen));
+                    self.ch_enc.push(ChannelEncoder::new());
+                }
+                self.cur_pos = 0;
+                self.flush = false;
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref abuf) = buf.get_abuf_i16() {
+            let src = abuf.get_data();
+            let len = abuf.get_length();
+            if abuf.get_step() == 1 {
+                let astride = abuf.get_stride();
+                for (dst, src) in self.samples.iter_mut().zip(src.chunks(astride)) {
+                    dst.extend(src[..len].iter().map(|&samp| i32::from(samp)));
+                }
+            } else {
+                let channels = self.samples.len();
+                for src in src.chunks_exact(channels).take(len) {
+                    for (dst, &samp) in self.samples.iter_mut().zip(src.iter()) {
+                        dst.push(i32::from(samp));
+                    }
+                }
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        if let Ok(pkt) = self.encode_packet() {
+            Ok(Some(pkt))
+        } else {
+            Ok(None)
+        }
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.flush = true;
+        Ok(())
+    }
+}
+
+impl NAOptionHandler for TTAEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+    fn set_options(&mut self, _options: &[NAOption]) { }
+    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(TTAEncoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use crate::*;
+    use super::calc_crc;
+    use super::super::enctest::*;
+
+    #[test]
+    fn test_tta_encoder_roundtrip() {
+        const SRATE: u32 = 22050;
+        const FRAMELEN: usize = (SRATE * 256 / 245) as usize;
+        const LEN: usize = FRAMELEN * 2 + 1234;
+
+        let mut dec_reg = RegisteredDecoders::new();
+        llaudio_register_all_decoders(&mut dec_reg);
+
+        for channels in 1..=2 {
+            let signal = gen_signal(LEN, channels);
+            let (_, pkts) = encode_signal("tta", &[], SRATE, FRAMELEN, &signal);
+            assert_eq!(pkts.len(), 3);
+
+            let ainfo = NAAudioInfo::new(SRATE, channels as u8, SND_S16P_FORMAT, FRAMELEN);
+            let mut edata = Vec::with_capacity(22);
+            edata.extend_from_slice(b"TTA1");
+            edata.extend_from_slice(&1u16.to_le_bytes());
+            edata.extend_from_slice(&(channels as u16).to_le_bytes());
+            edata.extend_from_slice(&16u16.to_le_bytes());
+            edata.extend_from_slice(&SRATE.to_le_bytes());
+            edata.extend_from_slice(&(LEN as u32).to_le_bytes());
+            let crc = calc_crc(&edata);
+            edata.extend_from_slice(&crc.to_le_bytes());
+            let info = NACodecInfo::new("tta", NACodecTypeInfo::Audio(ainfo), Some(edata)).into_ref();
+            let mut decoder = (dec_reg.find_decoder("tta").unwrap())();
+            let mut dsupp = NADecoderSupport::new();
+            decoder.init(&mut dsupp, info.clone()).unwrap();
+            let dstream = NAStream::new(StreamType::Audio, 0, (*info).clone(), 1, SRATE, 0).into_ref();
+
+            let mut pos = 0;
+            for pkt in pkts.iter() {
+                let dpkt = NAPacket::new(dstream.clone(), pkt.ts, true, pkt.get_buffer().to_vec());
+                let frm = decoder.decode(&mut dsupp, &dpkt).unwrap();
+                pos += check_frame(&frm, &signal, pos);
+            }
+            assert_eq!(pos, LEN);
+        }
+    }
+}
@@ -0,0 +1,854 @@
+use nihav_core::codecs::*;
+use nihav_core::io::bitwriter::*;
+use nihav_core::io::intcode::*;
+
+const SAMPLE_RATES: [u32; 15] = [
+     6000,  8000,  9600, 11025, 12000, 16000,  22050, 24000,
+    32000, 44100, 48000, 64000, 88200, 96000, 192000
+];
+const WV_FLAG_MONO: u32         = 1 <<  2;
+const WV_FLAG_JSTEREO: u32      = 1 <<  4;
+const WV_FLAG_CH_DECORR: u32    = 1 <<  5;
+const WV_FLAG_START_BLOCK: u32  = 1 << 11;
+const WV_FLAG_END_BLOCK: u32    = 1 << 12;
+const WV_MAG_SHIFT: u8          = 18;
+const WV_SRATE_SHIFT: u8        = 23;
+
+const WV_VERSION: u16 = 0x407;
+const WV_HEADER_SIZE: usize = 32;
+
+const DEFAULT_BLOCK_LEN: usize = 22050;
+const DEFAULT_COMPRESSION: u8 = 1;
+const DECORR_DELTA: i32 = 2;
+
+/// Decorrelation terms for each compression level, in the order they are applied by the encoder.
+const TERMS_FAST: &[i32] = &[ 17, 17 ];
+const TERMS_NORMAL: &[i32] = &[ 18, 18, 2, 3, -2 ];
+const TERMS_HIGH: &[i32] = &[ 18, 18, 2, 3, -2, 18, 2, 4, 7, 5, 3, 6, 8, -1, 18, 2 ];
+
+fn wv_log2lin(val: i32) -> i32 {
+    let sign = val < 0;
+    let aval = val.abs();
+    let mant = 0x100 | i32::from(WV_EXP_TABLE[(aval & 0xFF) as usize]);
+    let exp = aval >> 8;
+    let aval = if exp >= 9 {
+            mant << (exp - 9)
+        } else {
+            mant >> (9 - exp)
+        };
+    if !sign {
+        aval
+    } else {
+        -aval
+    }
+}
+
+fn wv_lin2log(val: u32) -> u32 {
+    if val == 0 {
+        0
+    } else if val == 1 {
+        0x100
+    } else {
+        let val = val + (val >> 9);
+        let bits = 32 - val.leading_zeros();
+        if bits < 9 {
+            (bits << 8) + u32::from(WV_LOG_TABLE[((val << (9 - bits)) & 0xFF) as usize])
+        } else {
+            (bits << 8) + u32::from(WV_LOG_TABLE[((val >> (bits - 9)) & 0xFF) as usize])
+        }
+    }
+}
+
+fn wv_lin2log_s(val: i32) -> i32 {
+    let lval = wv_lin2log(val.unsigned_abs()) as i32;
+    if val < 0 { -lval } else { lval }
+}
+
+/// Converts value to the logarithmic form used in the stream and returns both stored and reconstructed values.
+fn quantise_log(val: i32) -> (i16, i32) {
+    let lval = wv_lin2log_s(val) as i16;
+    (lval, wv_log2lin(i32::from(lval)))
+}
+
+fn store_weight(weight: i32) -> i8 {
+    let mut w = weight.clamp(-1024, 1024);
+    if w > 0 {
+        w -= (w + 64) >> 7;
+    }
+    ((w + 4) >> 3) as i8
+}
+
+fn restore_weight(val: i8) -> i32 {
+    let mut w = i32::from(val) << 3;
+    if w > 0 {
+        w += (w + 64) >> 7;
+    }
+    w
+}
+
+fn apply_weight(weight: i32, val: i32) -> i32 {
+    ((i64::from(weight) * i64::from(val) + 512) >> 10) as i32
+}
+
+fn update_weight(weight: &mut i32, delta: i32, val: i32, res: i32) {
+    if (val != 0) && (res != 0) {
+        *weight -= ((((res ^ val) >> 30) & 2) - 1) * delta;
+    }
+}
+
+fn update_weight_clip(weight: &mut i32, delta: i32, val: i32, res: i32) {
+    if (val != 0) && (res != 0) {
+        if (val ^ res) < 0 {
+            *weight = (*weight - delta).max(-1024);
+        } else {
+            *weight = (*weight + delta).min(1024);
+        }
+    }
+}
+
+fn predict(term: i32, samples: &mut [i32; 8]) -> i32 {
+    let pred = if (term & 1) != 0 {
+            2 * samples[0] - samples[1]
+        } else {
+            (3 * samples[0] - samples[1]) >> 1
+        };
+    samples[1] = samples[0];
+    pred
+}
+
+#[derive(Clone,Copy,Default)]
+struct Decorrelator {
+    term:       i32,
+    delta:      i32,
+    weight_a:   i32,
+    weight_b:   i32,
+    samples_a:  [i32; 8],
+    samples_b:  [i32; 8],
+}
+
+impl Decorrelator {
+    fn new(term: i32) -> Self {
+        Self {
+            term,
+            delta:  DECORR_DELTA,
+            ..Default::default()
+        }
+    }
+    fn encode_mono(&mut self, src: &mut [i32]) {
+        let term = self.term;
+        for (i, el) in src.iter_mut().enumerate() {
+            let samp = *el;
+            let pos = i & 7;
+            let (a, npos) = if term > 8 {
+                    (predict(term, &mut self.samples_a), 0)
+                } else {
+                    (self.samples_a[pos], (pos + term as usize) & 7)
+                };
+            let res = samp - apply_weight(self.weight_a, a);
+            update_weight(&mut self.weight_a, self.delta, a, res);
+            self.samples_a[npos] = samp;
+            *el = res;
+        }
+    }
+    fn encode_stereo(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let term = self.term;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let (ls, rs) = (*l, *r);
+            match term {
+                1..=8 => {
+                    let pos = i & 7;
+                    let npos = (pos + term as usize) & 7;
+                    let a = self.samples_a[pos];
+                    let b = self.samples_b[pos];
+                    *l = ls - apply_weight(self.weight_a, a);
+                    *r = rs - apply_weight(self.weight_b, b);
+                    update_weight(&mut self.weight_a, self.delta, a, *l);
+                    update_weight(&mut self.weight_b, self.delta, b, *r);
+                    self.samples_a[npos] = ls;
+                    self.samples_b[npos] = rs;
+                },
+                9.. => {
+                    let a = predict(term, &mut self.samples_a);
+                    let b = predict(term, &mut self.samples_b);
+                    *l = ls - apply_weight(self.weight_a, a);
+                    *r = rs - apply_weight(self.weight_b, b);
+                    update_weight(&mut self.weight_a, self.delta, a, *l);
+                    update_weight(&mut self.weight_b, self.delta, b, *r);
+                    self.samples_a[0] = ls;
+                    self.samples_b[0] = rs;
+                },
+                -1 => {
+                    let a = self.samples_a[0];
+                    *l = ls - apply_weight(self.weight_a, a);
+                    *r = rs - apply_weight(self.weight_b, ls);
+                    update_weight_clip(&mut self.weight_a, self.delta, a, *l);
+                    update_weight_clip(&mut self.weight_b, self.delta, ls, *r);
+                    self.samples_a[0] = rs;
+                },
+                _ => {
+                    let b = self.samples_b[0];
+                    *r = rs - apply_weight(self.weight_b, b);
+                    update_weight_clip(&mut self.weight_b, self.delta, b, *r);
+                    let a = if term == -3 {
+                            let prev = self.samples_a[0];
+                            self.samples_a[0] = rs;
+                            prev
+                        } else {
+                            rs
+                        };
+                    *l = ls - apply_weight(self.weight_a, a);
+                    update_weight_clip(&mut self.weight_a, self.delta, a, *l);
+                    self.samples_b[0] = ls;
+                },
+            };
+        }
+    }
+    /// Moves history so that it starts from the beginning of the circular buffer as the decoder expects it for a new block.
+    fn finish_block(&mut self, len: usize) {
+        if self.term > 0 && self.term <= 8 {
+            let (old_a, old_b) = (self.samples_a, self.samples_b);
+            for (k, (a, b)) in self.samples_a.iter_mut().zip(self.samples_b.iter_mut()).enumerate() {
+                *a = old_a[(len + k) & 7];
+                *b = old_b[(len + k) & 7];
+            }
+        }
+    }
+    fn write_weights(&mut self, dst: &mut Vec<u8>, stereo: bool) {
+        let w = store_weight(self.weight_a);
+        self.weight_a = restore_weight(w);
+        dst.push(w as u8);
+        if stereo {
+            let w = store_weight(self.weight_b);
+            self.weight_b = restore_weight(w);
+            dst.push(w as u8);
+        }
+    }
+    fn write_samples(&mut self, dst: &mut Vec<u8>, stereo: bool) {
+        let write_hist = |dst: &mut Vec<u8>, val: &mut i32| {
+                let (lval, rval) = quantise_log(*val);
+                *val = rval;
+                dst.extend_from_slice(&lval.to_le_bytes());
+            };
+        match self.term {
+            9.. => {
+                write_hist(dst, &mut self.samples_a[0]);
+                write_hist(dst, &mut self.samples_a[1]);
+                if stereo {
+                    write_hist(dst, &mut self.samples_b[0]);
+                    write_hist(dst, &mut self.samples_b[1]);
+                }
+            },
+            1..=8 => {
+                let len = self.term as usize;
+                for (a, b) in self.samples_a[..len].iter_mut().zip(self.samples_b[..len].iter_mut()) {
+                    write_hist(dst, a);
+                    if stereo {
+                        write_hist(dst, b);
+                    }
+                }
+                for el in self.samples_a[len..].iter_mut().chain(self.samples_b[len..].iter_mut()) {
+                    *el = 0;
+                }
+            },
+            _ => {
+                write_hist(dst, &mut self.samples_a[0]);
+                write_hist(dst, &mut self.samples_b[0]);
+            },
+        };
+    }
+}
+
+fn write_biased_code(bw: &mut BitWriter, val: u32) {
+    if val < 2 {
+        bw.write_code(UintCodeType::UnaryOnes, val);
+    } else {
+        let bits = 31 - val.leading_zeros();
+        bw.write_code(UintCodeType::UnaryOnes, bits + 1);
+        bw.write(val & ((1 << bits) - 1), bits as u8);
+    }
+}
+
+fn write_tail(bw: &mut BitWriter, val: u32, bits: u32) {
+    if bits < 1 {
+        return;
+    }
+    let p = 31 - bits.leading_zeros();
+    let esc = (1 << (p + 1)) - (bits + 1);
+    if val < esc {
+        bw.write(val, p as u8);
+    } else {
+        let val = val + esc;
+        bw.write(val >> 1, p as u8);
+        bw.write(val & 1, 1);
+    }
+}
+
+/// Sample magnitude code that can be written only after the following sample is known.
+#[derive(Clone,Copy)]
+struct PendingCode {
+    ones:   u32,
+    tail:   u32,
+    add:    u32,
+    sign:   bool,
+}
+
+#[derive(Default)]
+struct EntropyEncoder {
+    median:     [[u32; 3]; 2],
+    num_zeroes: u32,
+    pending:    Option<PendingCode>,
+}
+
+impl EntropyEncoder {
+    fn get_median(&self, channel: usize, idx: usize) -> u32 {
+        (self.median[channel][idx] >> 4) + 1
+    }
+    fn inc_median(&mut self, channel: usize, idx: usize) {
+        self.median[channel][idx] += ((self.median[channel][idx] + (128 >> idx)) / (128 >> idx)) * 5;
+    }
+    fn dec_median(&mut self, channel: usize, idx: usize) {
+        self.median[channel][idx] -= ((self.median[channel][idx] + (128 >> idx) - 2) / (128 >> idx)) * 2;
+    }
+    fn write_medians(&mut self, dst: &mut Vec<u8>, channels: usize) {
+        for median in self.median[..channels].iter_mut() {
+            for el in median.iter_mut() {
+                let (lval, rval) = quantise_log(*el as i32);
+                *el = rval as u32;
+                dst.extend_from_slice(&lval.to_le_bytes());
+            }
+        }
+    }
+    fn flush_code(bw: &mut BitWriter, code: PendingCode, next_one: bool) {
+        let val = code.ones * 2 + (next_one as u32);
+        if val < 16 {
+            bw.write_code(UintCodeType::UnaryOnes, val);
+        } else {
+            bw.write_code(UintCodeType::UnaryOnes, 16);
+            write_biased_code(bw, val - 16);
+        }
+        write_tail(bw, code.tail, code.add);
+        bw.write_bit(code.sign);
+    }
+    fn encode_sample(&mut self, bw: &mut BitWriter, res: &[i32], channel: usize) {
+        if self.pending.is_none() && (self.median[0][0] < 2) && (self.median[1][0] < 2) {
+            if self.num_zeroes > 0 {
+                self.num_zeroes -= 1;
+                if self.num_zeroes != 0 {
+                    return;
+                }
+            } else {
+                let nzeroes = res.iter().take_while(|&&el| el == 0).count() as u32;
+                write_biased_code(bw, nzeroes);
+                if nzeroes != 0 {
+                    self.num_zeroes = nzeroes;
+                    self.median = [[0; 3]; 2];
+                    return;
+                }
+            }
+        }
+
+        let val = res[0];
+        let sign = val < 0;
+        let mag = (if sign { !val } else { val }) as u32;
+        let m0 = self.get_median(channel, 0);
+        let m1 = self.get_median(channel, 1);
+        let m2 = self.get_median(channel, 2);
+        let (ones, tail, add) = if mag < m0 {
+                self.dec_median(channel, 0);
+                (0, mag, m0 - 1)
+            } else if mag - m0 < m1 {
+                self.inc_median(channel, 0);
+                self.dec_median(channel, 1);
+                (1, mag - m0, m1 - 1)
+            } else if mag - m0 - m1 < m2 {
+                self.inc_median(channel, 0);
+                self.inc_median(channel, 1);
+                self.dec_median(channel, 2);
+                (2, mag - m0 - m1, m2 - 1)
+            } else {
+                let mag = mag - m0 - m1;
+                self.inc_median(channel, 0);
+                self.inc_median(channel, 1);
+                self.inc_median(channel, 2);
+                (2 + mag / m2, mag % m2, m2 - 1)
+            };
+
+        let cur_code = PendingCode { ones, tail, add, sign };
+        if let Some(code) = self.pending.take() {
+            if ones == 0 {
+                // the previous code signals that this sample has zero ones count so it is coded without it
+                Self::flush_code(bw, code, false);
+                write_tail(bw, tail, add);
+                bw.write_bit(sign);
+            } else {
+                Self::flush_code(bw, code, true);
+                self.pending = Some(PendingCode { ones: ones - 1, ..cur_code });
+            }
+        } else {
+            self.pending = Some(cur_code);
+        }
+    }
+    fn finish(&mut self, bw: &mut BitWriter) {
+        if let Some(code) = self.pending.take() {
+            Self::flush_code(bw, code, false);
+        }
+        self.num_zeroes = 0;
+    }
+}
+
+fn write_subblock(dst: &mut Vec<u8>, id: u8, data: &[u8]) {
+    let odd = data.len() & 1;
+    let words = (data.len() >> 1) + odd;
+    let id = if odd != 0 { id | 0x40 } else { id };
+    if words < 256 {
+        dst.push(id);
+        dst.push(words as u8);
+    } else {
+        dst.push(id | 0x80);
+        dst.extend_from_slice(&(words as u32).to_le_bytes()[..3]);
+    }
+    dst.extend_from_slice(data);
+    if odd != 0 {
+        dst.push(0);
+    }
+}
+
+fn write_header(dst: &mut [u8], block_size: usize, block_index: u64, block_samples: u32, flags: u32, crc: u32) {
+    dst[..4].copy_from_slice(b"wvpk");
+    dst[4..8].copy_from_slice(&((block_size - 8) as u32).to_le_bytes());
+    dst[8..10].copy_from_slice(&WV_VERSION.to_le_bytes());
+    dst[10] = (block_index >> 32) as u8;
+    dst[11] = 0;
+    dst[12..16].copy_from_slice(&u32::MAX.to_le_bytes()); // total samples are unknown
+    dst[16..20].copy_from_slice(&(block_index as u32).to_le_bytes());
+    dst[20..24].copy_from_slice(&block_samples.to_le_bytes());
+    dst[24..28].copy_from_slice(&flags.to_le_bytes());
+    dst[28..32].copy_from_slice(&crc.to_le_bytes());
+}
+
+struct WavPackEncoder {
+    stream:     Option<NAStreamRef>,
+    samples:    Vec<Vec<i32>>,
+    channels:   usize,
+    srate:      u32,
+    srate_idx:  u32,
+    block_len:  usize,
+    level:      u8,
+    joint:      bool,
+    flush:      bool,
+    cur_pos:    u64,
+    decorr:     Vec<Decorrelator>,
+    ent:        EntropyEncoder,
+}
+
+impl WavPackEncoder {
+    fn new() -> Self {
+        Self {
+            stream:     None,
+            samples:    Vec::new(),
+            channels:   0,
+            srate:      0,
+            srate_idx:  0,
+            block_len:  DEFAULT_BLOCK_LEN,
+            level:      DEFAULT_COMPRESSION,
+            joint:      true,
+            flush:      false,
+            cur_pos:    0,
+            decorr:     Vec::new(),
+            ent:        EntropyEncoder::default(),
+        }
+    }
+    fn init_decorr(&mut self) {
+        let terms = match self.level {
+                0 => TERMS_FAST,
+                1 => TERMS_NORMAL,
+                _ => TERMS_HIGH,
+            };
+        self.decorr.clear();
+        for &term in terms.iter() {
+            // cross-channel terms make no sense for mono
+            if term > 0 || self.channels > 1 {
+                self.decorr.push(Decorrelator::new(term));
+            }
+        }
+        self.ent = EntropyEncoder::default();
+    }
+    fn encode_packet(&mut self) -> EncoderResult<NAPacket> {
+        if self.samples.is_empty() || self.samples[0].is_empty() || (!self.flush && self.samples[0].len() < self.block_len) {
+            return Err(EncoderError::TryAgain);
+        }
+
+        let nsamples = self.samples[0].len().min(self.block_len);
+        let stereo = self.channels > 1;
+
+        let mut crc = 0xFFFFFFFFu32;
+        let mut maxval = 0;
+        if stereo {
+            for (&l, &r) in self.samples[0][..nsamples].iter().zip(self.samples[1][..nsamples].iter()) {
+                crc = crc.wrapping_mul(3).wrapping_add(l as u32).wrapping_mul(3).wrapping_add(r as u32);
+                maxval |= l.unsigned_abs() | r.unsigned_abs();
+            }
+        } else {
+            for &s in self.samples[0][..nsamples].iter() {
+                crc = crc.wrapping_mul(3).wrapping_add(s as u32);
+                maxval |= s.unsigned_abs();
+            }
+        }
+
+        let mut flags = 1 | WV_FLAG_START_BLOCK | WV_FLAG_END_BLOCK | (self.srate_idx << WV_SRATE_SHIFT);
+        let mut mag = 32 - maxval.leading_zeros();
+        if !stereo {
+            flags |= WV_FLAG_MONO;
+        } else {
+            if self.joint {
+                flags |= WV_FLAG_JSTEREO;
+                mag += 1;
+            }
+            if self.decorr.iter().any(|dcr| dcr.term < 0) {
+                flags |= WV_FLAG_CH_DECORR;
+            }
+        }
+        flags |= mag.min(31) << WV_MAG_SHIFT;
+
+        let mut body = Vec::with_capacity(nsamples * self.channels * 2);
+        let mut sbuf = Vec::with_capacity(32);
+        for dcr in self.decorr.iter() {
+            sbuf.push((((dcr.term + 5) & 0x1F) | (dcr.delta << 5)) as u8);
+        }
+        write_subblock(&mut body, 0x02, &sbuf);
+        sbuf.clear();
+        for dcr in self.decorr.iter_mut() {
+            dcr.write_weights(&mut sbuf, stereo);
+        }
+        write_subblock(&mut body, 0x03, &sbuf);
+        sbuf.clear();
+        for dcr in self.decorr.iter_mut() {
+            dcr.write_samples(&mut sbuf, stereo);
+        }
+        write_subblock(&mut body, 0x04, &sbuf);
+        sbuf.clear();
+        self.ent.write_medians(&mut sbuf, self.channels);
+        write_subblock(&mut body, 0x05, &sbuf);
+
+        let mut bw = BitWriter::new(Vec::with_capacity(nsamples * self.channels * 2), BitWriterMode::LE);
+        if stereo {
+            let mut l = self.samples[0][..nsamples].to_vec();
+            let mut r = self.samples[1][..nsamples].to_vec();
+            if self.joint {
+                for (l, r) in l.iter_mut().zip(r.iter_mut()) {
+                    *l -= *r;
+                    *r += *l >> 1;
+                }
+            }
+            for dcr in self.decorr.iter_mut() {
+                dcr.encode_stereo(&mut l, &mut r);
+                dcr.finish_block(nsamples);
+            }
+            let mut res = Vec::with_capacity(nsamples * 2);
+            for (&l, &r) in l.iter().zip(r.iter()) {
+                res.push(l);
+                res.push(r);
+            }
+            for i in 0..res.len() {
+                self.ent.encode_sample(&mut bw, &res[i..], i & 1);
+            }
+        } else {
+            let mut res = self.samples[0][..nsamples].to_vec();
+            for dcr in self.decorr.iter_mut() {
+                dcr.encode_mono(&mut res);
+                dcr.finish_block(nsamples);
+            }
+            for i in 0..res.len() {
+                self.ent.encode_sample(&mut bw, &res[i..], 0);
+            }
+        }
+        self.ent.finish(&mut bw);
+        write_subblock(&mut body, 0x0A, &bw.end());
+
+        let mut dbuf = vec![0; WV_HEADER_SIZE];
+        write_header(&mut dbuf, WV_HEADER_SIZE + body.len(), self.cur_pos, nsamples as u32, flags, crc);
+        dbuf.extend_from_slice(&body);
+
+        for samp in self.samples.iter_mut() {
+            samp.drain(..nsamples);
+        }
+        let ts = NATimeInfo::new(Some(self.cur_pos), None, Some(nsamples as u64), 1, self.srate);
+        self.cur_pos += nsamples as u64;
+
+        Ok(NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf))
+    }
+}
+
+impl NAEncoder for WavPackEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Audio(NAAudioInfo::new(44100, 2, SND_S16P_FORMAT, DEFAULT_BLOCK_LEN)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                let mut outinfo = ainfo;
+                outinfo.channels = outinfo.channels.clamp(1, 2);
+                if !SAMPLE_RATES.contains(&outinfo.sample_rate) {
+                    outinfo.sample_rate = 44100;
+                }
+                if outinfo.format != SND_S16P_FORMAT && outinfo.format != SND_S16_FORMAT {
+                    outinfo.format = SND_S16P_FORMAT;
+                }
+                outinfo.block_len = self.block_len;
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Audio(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                if ainfo.format != SND_S16P_FORMAT && ainfo.format != SND_S16_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.channels == 0 || ainfo.channels > 2 {
+                    return Err(EncoderError::FormatError);
+                }
+                if let Some(idx) = SAMPLE_RATES.iter().position(|&srate| srate == ainfo.sample_rate) {
+                    self.srate_idx = idx as u32;
+                } else {
+                    return Err(EncoderError::FormatError);
+                }
+                self.channels = usize::from(ainfo.channels);
+                self.srate = ainfo.sample_rate;
+
+                // the decoder reads stream parameters from a block header without any audio data
+                let mut edata = vec![0; WV_HEADER_SIZE];
+                let mut flags = 1 | WV_FLAG_START_BLOCK | WV_FLAG_END_BLOCK | (self.srate_idx << WV_SRATE_SHIFT);
+                if self.channels == 1 {
+                    flags |= WV_FLAG_MONO;
+                }
+                write_header(&mut edata, WV_HEADER_SIZE, 0, 0, flags, 0xFFFFFFFF);
+
+                let out_ainfo = NAAudioInfo::new(ainfo.sample_rate, ainfo.channels, SND_S16P_FORMAT, self.block_len);
+                let info = NACodecInfo::new("wavpack", NACodecTypeInfo::Audio(out_ainfo), Some(edata));
+                let mut stream = NAStream::new(StreamType::Audio, stream_id, info, 1, ainfo.sample_rate, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+                self.stream = Some(stream.clone());
+
+                self.samples.clear();
+                for _ in 0..self.channels {
+                    self.samples.push(Vec::with_capacity(self.block_len));
+                }
+                self.init_decorr();
+                self.cur_pos = 0;
+                self.flush = false;
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref abuf) = buf.get_abuf_i16() {
+            let src = abuf.get_data();
+            let len = abuf.get_length();
+            if abuf.get_step() == 1 {
+                let astride = abuf.get_stride();
+                for (dst, src) in self.samples.iter_mut().zip(src.chunks(astride)) {
+                    dst.extend(src[..len].iter().map(|&samp| i32::from(samp)));
+                }
+            } else {
+                let channels = self.samples.len();
+                for src in src.chunks_exact(channels).take(len) {
+                    for (dst, &samp) in self.samples.iter_mut().zip(src.iter()) {
+                        dst.push(i32::from(samp));
+                    }
+                }
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        if let Ok(pkt) = self.encode_packet() {
+            Ok(Some(pkt))
+        } else {
+            Ok(None)
+        }
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.flush = true;
+        Ok(())
+    }
+}
+
+const ENCODER_OPTS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: "compression", description: "compression level (0 - fast, 1 - normal, 2 - high)",
+        opt_type: NAOptionDefinitionType::Int(Some(0), Some(2)) },
+    NAOptionDefinition {
+        name: "block_size", description: "block size",
+        opt_type: NAOptionDefinitionType::Int(Some(256), Some(131072)) },
+    NAOptionDefinition {
+        name: "joint_stereo", description: "code stereo as mid and side channels",
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
+impl NAOptionHandler for WavPackEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { ENCODER_OPTS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in ENCODER_OPTS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        "compression" => {
+                            if let NAValue::Int(val) = option.value {
+                                self.level = val as u8;
+                                if self.stream.is_some() {
+                                    self.init_decorr();
+                                }
+                            }
+                        },
+                        "block_size" => {
+                            if let NAValue::Int(val) = option.value {
+                                self.block_len = val as usize;
+                            }
+                        },
+                        "joint_stereo" => {
+                            if let NAValue::Bool(val) = option.value {
+                                self.joint = val;
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            "compression" => Some(NAValue::Int(i64::from(self.level))),
+            "block_size" => Some(NAValue::Int(self.block_len as i64)),
+            "joint_stereo" => Some(NAValue::Bool(self.joint)),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(WavPackEncoder::new())
+}
+
+const WV_EXP_TABLE: [u8; 256] = [
+    0x00, 0x01, 0x01, 0x02, 0x03, 0x03, 0x04, 0x05,
+    0x06, 0x06, 0x07, 0x08, 0x08, 0x09, 0x0a, 0x0b,
+    0x0b, 0x0c, 0x0d, 0x0e, 0x0e, 0x0f, 0x10, 0x10,
+    0x11, 0x12, 0x13, 0x13, 0x14, 0x15, 0x16, 0x16,
+    0x17, 0x18, 0x19, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+    0x1d, 0x1e, 0x1f, 0x20, 0x20, 0x21, 0x22, 0x23,
+    0x24, 0x24, 0x25, 0x26, 0x27, 0x28, 0x28, 0x29,
+    0x2a, 0x2b, 0x2c, 0x2c, 0x2d, 0x2e, 0x2f, 0x30,
+    0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x35, 0x36,
+    0x37, 0x38, 0x39, 0x3a, 0x3a, 0x3b, 0x3c, 0x3d,
+    0x3e, 0x3f, 0x40, 0x41, 0x41, 0x42, 0x43, 0x44,
+    0x45, 0x46, 0x47, 0x48, 0x48, 0x49, 0x4a, 0x4b,
+    0x4c, 0x4d, 0x4e, 0x4f, 0x50, 0x51, 0x51, 0x52,
+    0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+    0x5b, 0x5c, 0x5d, 0x5e, 0x5e, 0x5f, 0x60, 0x61,
+    0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71,
+    0x72, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79,
+    0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80, 0x81,
+    0x82, 0x83, 0x84, 0x85, 0x87, 0x88, 0x89, 0x8a,
+    0x8b, 0x8c, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x92,
+    0x93, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+    0x9c, 0x9d, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4,
+    0xa5, 0xa6, 0xa8, 0xa9, 0xaa, 0xab, 0xac, 0xad,
+    0xaf, 0xb0, 0xb1, 0xb2, 0xb3, 0xb4, 0xb6, 0xb7,
+    0xb8, 0xb9, 0xba, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0,
+    0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc8, 0xc9, 0xca,
+    0xcb, 0xcd, 0xce, 0xcf, 0xd0, 0xd2, 0xd3, 0xd4,
+    0xd6, 0xd7, 0xd8, 0xd9, 0xdb, 0xdc, 0xdd, 0xde,
+    0xe0, 0xe1, 0xe2, 0xe4, 0xe5, 0xe6, 0xe8, 0xe9,
+    0xea, 0xec, 0xed, 0xee, 0xf0, 0xf1, 0xf2, 0xf4,
+    0xf5, 0xf6, 0xf8, 0xf9, 0xfa, 0xfc, 0xfd, 0xff
+];
+const WV_LOG_TABLE: [u8; 256] = [
+    0x00, 0x01, 0x03, 0x04, 0x06, 0x07, 0x09, 0x0a,
+    0x0b, 0x0d, 0x0e, 0x10, 0x11, 0x12, 0x14, 0x15,
+    0x16, 0x18, 0x19, 0x1a, 0x1c, 0x1d, 0x1e, 0x20,
+    0x21, 0x22, 0x24, 0x25, 0x26, 0x28, 0x29, 0x2a,
+    0x2c, 0x2d, 0x2e, 0x2f, 0x31, 0x32, 0x33, 0x34,
+    0x36, 0x37, 0x38, 0x39, 0x3b, 0x3c, 0x3d, 0x3e,
+    0x3f, 0x41, 0x42, 0x43, 0x44, 0x45, 0x47, 0x48,
+    0x49, 0x4a, 0x4b, 0x4d, 0x4e, 0x4f, 0x50, 0x51,
+    0x52, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+    0x5c, 0x5d, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63,
+    0x64, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x6b, 0x6c,
+    0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x74, 0x75,
+    0x76, 0x77, 0x78, 0x79, 0x7a, 0x7b, 0x7c, 0x7d,
+    0x7e, 0x7f, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85,
+    0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+    0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95,
+    0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9b, 0x9c,
+    0x9d, 0x9e, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4,
+    0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xa9, 0xaa, 0xab,
+    0xac, 0xad, 0xae, 0xaf, 0xb0, 0xb1, 0xb2, 0xb2,
+    0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xb9,
+    0xba, 0xbb, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0, 0xc0,
+    0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc6, 0xc7,
+    0xc8, 0xc9, 0xca, 0xcb, 0xcb, 0xcc, 0xcd, 0xce,
+    0xcf, 0xd0, 0xd0, 0xd1, 0xd2, 0xd3, 0xd4, 0xd4,
+    0xd5, 0xd6, 0xd7, 0xd8, 0xd8, 0xd9, 0xda, 0xdb,
+    0xdc, 0xdc, 0xdd, 0xde, 0xdf, 0xe0, 0xe0, 0xe1,
+    0xe2, 0xe3, 0xe4, 0xe4, 0xe5, 0xe6, 0xe7, 0xe7,
+    0xe8, 0xe9, 0xea, 0xea, 0xeb, 0xec, 0xed, 0xee,
+    0xee, 0xef, 0xf0, 0xf1, 0xf1, 0xf2, 0xf3, 0xf4,
+    0xf4, 0xf5, 0xf6, 0xf7, 0xf7, 0xf8, 0xf9, 0xf9,
+    0xfa, 0xfb, 0xfc, 0xfc, 0xfd, 0xfe, 0xff, 0xff
+];
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use crate::*;
+    use super::super::enctest::*;
+
+    #[test]
+    fn test_wavpack_encoder_roundtrip() {
+        const SRATE: u32 = 22050;
+        const BLOCK_LEN: usize = 4096;
+        const LEN: usize = BLOCK_LEN * 3 + 1000;
+
+        let mut dec_reg = RegisteredDecoders::new();
+        llaudio_register_all_decoders(&mut dec_reg);
+
+        for &(channels, level, joint) in [(1, 1, false), (2, 0, true), (2, 1, false), (2, 1, true), (2, 2, true)].iter() {
+            let signal = gen_signal(LEN, channels);
+            let options = [NAOption{name: "compression", value: NAValue::Int(level)},
+                           NAOption{name: "block_size", value: NAValue::Int(BLOCK_LEN as i64)},
+                           NAOption{name: "joint_stereo", value: NAValue::Bool(joint)}];
+            let (stream, pkts) = encode_signal("wavpack", &options, SRATE, BLOCK_LEN, &signal);
+            assert_eq!(pkts.len(), 4);
+
+            let mut decoder = (dec_reg.find_decoder("wavpack").unwrap())();
+            let mut dsupp = NADecoderSupport::new();
+            decoder.init(&mut dsupp, stream.get_info()).unwrap();
+
+            let mut size = 0;
+            let mut pos = 0;
+            for pkt in pkts.iter() {
+                size += pkt.get_buffer().len();
+                let frm = decoder.decode(&mut dsupp, pkt).unwrap();
+                pos += check_frame(&frm, &signal, pos);
+            }
+            assert_eq!(pos, LEN);
+            assert!(size < LEN * channels * 2 * 3 / 5);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use nihav_core::frame::*;
 use nihav_core::demuxers::*;
+use super::apetag::read_apev2_tag;
 
 #[derive(Clone,Copy)]
 struct Frame {
@@ -16,6 +17,7 @@ struct APEDemuxer<'a> {
     last_blocks:    u32,
     truncated:      bool,
     duration:       u64,
+    metadata:       Metadata,
 }
 
 impl<'a> APEDemuxer<'a> {
@@ -28,6 +30,7 @@ impl<'a> APEDemuxer<'a> {
             last_blocks:    0,
             truncated:      false,
             duration:       0,
+            metadata:       Metadata::new(),
         }
     }
 }
@@ -185,6 +188,9 @@ impl<'a> DemuxCore<'a> for APEDemuxer<'a> {
 
         self.cur_frame = 0;
 
+        // tags are optional so a broken one should not prevent playback
+        let _ = read_apev2_tag(src, &mut self.metadata);
+
         Ok(())
     }
     fn get_frame(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<NAPacket> {
@@ -227,6 +233,9 @@ impl<'a> DemuxCore<'a> for APEDemuxer<'a> {
         Ok(())
     }
     fn get_duration(&self) -> u64 { self.duration }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
 }
 
 impl<'a> NAOptionHandler for APEDemuxer<'a> {
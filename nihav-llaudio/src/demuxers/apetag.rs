@@ -0,0 +1,142 @@
+use nihav_core::demuxers::*;
+
+const APE_TAG_FOOTER_SIZE: usize = 32;
+const ID3V1_SIZE: i64 = 128;
+const MAX_TAG_SIZE: usize = 1 << 24;
+
+fn apev2_key_to_name(key: &str) -> &str {
+    match key.to_ascii_lowercase().as_str() {
+        "title"     => METADATA_TITLE,
+        "artist"    => METADATA_ARTIST,
+        "album"     => METADATA_ALBUM,
+        "comment"   => METADATA_COMMENT,
+        "copyright" => METADATA_COPYRIGHT,
+        "year"      => METADATA_DATE,
+        "genre"     => METADATA_GENRE,
+        "track"     => METADATA_TRACK,
+        "language"  => METADATA_LANGUAGE,
+        _ => key,
+    }
+}
+
+fn parse_apev2_items(src: &[u8], nitems: usize, meta: &mut Metadata) -> DemuxerResult<()> {
+    let mut pos = 0;
+    for _ in 0..nitems {
+        validate!(pos + 8 <= src.len());
+        let size = read_u32le(&src[pos..])? as usize;
+        let flags = read_u32le(&src[pos + 4..])?;
+        pos += 8;
+        let key_len = src[pos..].iter().position(|&c| c == 0);
+        validate!(key_len.is_some());
+        let key_len = key_len.unwrap_or(0);
+        let key = String::from_utf8_lossy(&src[pos..][..key_len]).to_string();
+        pos += key_len + 1;
+        validate!(size <= src.len() - pos);
+        let value = &src[pos..][..size];
+        pos += size;
+
+        let name = apev2_key_to_name(&key);
+        match (flags >> 1) & 3 {
+            0 => {
+                // multiple values are separated with zero bytes
+                for part in value.split(|&c| c == 0) {
+                    meta.add_text(name, &String::from_utf8_lossy(part));
+                }
+            },
+            1 if key.to_ascii_lowercase().starts_with("cover art") => {
+                let desc_len = value.iter().position(|&c| c == 0).unwrap_or(0);
+                let desc = String::from_utf8_lossy(&value[..desc_len]).to_string();
+                let data = value[(desc_len + 1).min(value.len())..].to_vec();
+                let mime = if data.starts_with(&[0xFF, 0xD8]) {
+                        "image/jpeg"
+                    } else if data.starts_with(b"\x89PNG") {
+                        "image/png"
+                    } else {
+                        ""
+                    };
+                let pic = MetadataPicture { mime: mime.to_string(), desc, data };
+                meta.add(METADATA_COVER_ART, MetadataValue::Picture(pic));
+            },
+            1 => meta.add(name, MetadataValue::Binary(value.to_vec())),
+            _ => {},
+        };
+    }
+    Ok(())
+}
+
+/// Reads APEv2 tag stored at the end of the file (optionally followed by ID3v1 tag).
+///
+/// The current position is restored afterwards.
+pub fn read_apev2_tag(src: &mut ByteReader, meta: &mut Metadata) -> DemuxerResult<()> {
+    let orig_pos = src.tell();
+    let ret = find_and_parse_apev2(src, meta);
+    src.seek(SeekFrom::Start(orig_pos))?;
+    ret
+}
+
+fn find_and_parse_apev2(src: &mut ByteReader, meta: &mut Metadata) -> DemuxerResult<()> {
+    let file_size = src.size();
+    if file_size < APE_TAG_FOOTER_SIZE as i64 {
+        return Ok(());
+    }
+    let mut footer = [0; APE_TAG_FOOTER_SIZE];
+    let mut found = false;
+    for &trail in [0, ID3V1_SIZE].iter() {
+        let footer_pos = file_size - trail - (APE_TAG_FOOTER_SIZE as i64);
+        if footer_pos < 0 {
+            break;
+        }
+        src.seek(SeekFrom::Start(footer_pos as u64))?;
+        src.read_buf(&mut footer)?;
+        if &footer[..8] == b"APETAGEX" {
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        return Ok(());
+    }
+    let version = read_u32le(&footer[8..])?;
+    let size    = read_u32le(&footer[12..])? as usize;
+    let nitems  = read_u32le(&footer[16..])? as usize;
+    validate!(version == 1000 || version == 2000);
+    validate!((APE_TAG_FOOTER_SIZE..=MAX_TAG_SIZE).contains(&size));
+    let items_size = size - APE_TAG_FOOTER_SIZE;
+    src.seek(SeekFrom::Current(-(size as i64)))?;
+    let mut buf = vec![0; items_size];
+    src.read_buf(&mut buf)?;
+    parse_apev2_items(&buf, nitems, meta)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apev2_tag() {
+        let mut items = Vec::new();
+        for (key, val) in [("Title", &b"Song"[..]), ("Artist", &b"One\0Two"[..])].iter() {
+            items.extend_from_slice(&(val.len() as u32).to_le_bytes());
+            items.extend_from_slice(&[0; 4]);
+            items.extend_from_slice(key.as_bytes());
+            items.push(0);
+            items.extend_from_slice(val);
+        }
+        let mut file = vec![0x55; 100];
+        file.extend_from_slice(&items);
+        file.extend_from_slice(b"APETAGEX");
+        file.extend_from_slice(&2000u32.to_le_bytes());
+        file.extend_from_slice(&((items.len() + APE_TAG_FOOTER_SIZE) as u32).to_le_bytes());
+        file.extend_from_slice(&2u32.to_le_bytes());
+        file.extend_from_slice(&[0; 12]);
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        br.read_skip(10).unwrap();
+        let mut meta = Metadata::new();
+        read_apev2_tag(&mut br, &mut meta).unwrap();
+        assert_eq!(br.tell(), 10);
+        assert_eq!(meta.get_text(METADATA_TITLE), Some("Song"));
+        assert_eq!(meta.get_all(METADATA_ARTIST).count(), 2);
+    }
+}
@@ -21,6 +21,7 @@ struct FLACDemuxer<'a> {
     srate:          u32,
     known_frames:   Vec<FrameSeekInfo>,
     build_index:    bool,
+    metadata:       Metadata,
 }
 
 impl<'a> FLACDemuxer<'a> {
@@ -37,6 +38,7 @@ impl<'a> FLACDemuxer<'a> {
             srate:          0,
             known_frames:   Vec::new(),
             build_index:    false,
+            metadata:       Metadata::new(),
         }
     }
     fn read_frame(&mut self) -> DemuxerResult<(Vec<u8>, u64, u64)> {
@@ -127,7 +129,7 @@ fn read_utf8(src: &[u8]) -> DemuxerResult<u32> {
     }
     let len = (!src[0]).leading_zeros() as usize;
     validate!(len != 1 && len <= 5 && src.len() >= len);
-    let mut val = u32::from(src[0] & 0x1F);
+    let mut val = u32::from(src[0] & (0x7F >> len));
     for byte in src.iter().take(len).skip(1) {
         validate!((*byte & 0xC0) == 0x80);
         val = (val << 6) | u32::from(*byte & 0x3F);
@@ -172,10 +174,26 @@ impl<'a> DemuxCore<'a> for FLACDemuxer<'a> {
                         let sample      = self.src.read_u64be()?;
                         let offset      = self.src.read_u64be()?;
                         let _nsamps     = self.src.read_u16be()?;
+                        if sample == u64::MAX { // placeholder point
+                            continue;
+                        }
                         let time = sample * 1000 / u64::from(srate.max(1000));
                         seek_index.add_entry(0, SeekEntry { time, pts: sample, pos: offset });
                     }
                 },
+                0x04 => {
+                    let mut buf = vec![0; len];
+                                          self.src.read_buf(&mut buf)?;
+                    // tags are optional so a damaged comment block should not prevent playback
+                    let _ = parse_vorbis_comment(&buf, &mut self.metadata);
+                },
+                0x06 => {
+                    let mut buf = vec![0; len];
+                                          self.src.read_buf(&mut buf)?;
+                    if let Some((_ptype, pic)) = parse_flac_picture(&buf) {
+                        self.metadata.add(METADATA_COVER_ART, MetadataValue::Picture(pic));
+                    }
+                },
                 _ =>                      self.src.read_skip(len)?,
             };
 
@@ -193,6 +211,13 @@ impl<'a> DemuxCore<'a> for FLACDemuxer<'a> {
             self.build_index = false;
         }
         self.data_start = self.src.tell();
+        if self.blk_samples != 0 {
+            // equal block sizes do not mean that frames use fixed blocking strategy
+            let frame_hdr = self.src.peek_u16be()?;
+            if (frame_hdr & 1) != 0 {
+                self.blk_samples = 0;
+            }
+        }
         validate!(srate != 0);
         self.srate = srate;
 
@@ -262,6 +287,9 @@ impl<'a> DemuxCore<'a> for FLACDemuxer<'a> {
         }
     }
     fn get_duration(&self) -> u64 { self.tot_samples * 1000 / u64::from(self.srate) }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
 }
 
 impl<'a> NAOptionHandler for FLACDemuxer<'a> {
@@ -303,6 +331,30 @@ mod test {
             println!("Got {}", pkt);
         }
     }
+    #[test]
+    fn test_flac_demux_broken_comment() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"fLaC");
+        data.push(0x00);
+        data.extend_from_slice(&[0, 0, 34]);
+        let mut streaminfo = [0u8; 34];
+        streaminfo[0..2].copy_from_slice(&16u16.to_be_bytes());
+        streaminfo[2..4].copy_from_slice(&4096u16.to_be_bytes());
+        streaminfo[10..14].copy_from_slice(&((44100u32 << 12) | (1 << 9) | (15 << 4)).to_be_bytes());
+        data.extend_from_slice(&streaminfo);
+        // vendor string length points past the end of the block
+        data.push(0x84);
+        data.extend_from_slice(&[0, 0, 8]);
+        data.extend_from_slice(&[0xFF, 0xFF, 0, 0, b'a', b'b', b'c', b'd']);
+
+        let mut mr = MemoryReader::new_read(&data);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = FLACDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        assert_eq!(sm.get_num_streams(), 1);
+    }
 }
 
 const CRC16_TABLE: [u16; 256] = [
@@ -12,6 +12,7 @@ struct FLACDemuxer<'a> {
     max_size:       usize,
     srate:          u32,
     build_index:    bool,
+    metadata:       Metadata,
 }
 
 impl<'a> FLACDemuxer<'a> {
@@ -27,6 +28,7 @@ impl<'a> FLACDemuxer<'a> {
             max_size:       0,
             srate:          0,
             build_index:    false,
+            metadata:       Metadata::new(),
         }
     }
 }
@@ -72,6 +74,19 @@ impl<'a> RawDemuxCore<'a> for FLACDemuxer<'a> {
                         seek_index.add_entry(0, SeekEntry { time, pts: sample, pos: offset });
                     }
                 },
+                0x04 => {
+                    let mut buf = vec![0; len];
+                                          self.src.read_buf(&mut buf)?;
+                    // tags are optional so a damaged comment block should not prevent playback
+                    let _ = parse_vorbis_comment(&buf, &mut self.metadata);
+                },
+                0x06 => {
+                    let mut buf = vec![0; len];
+                                          self.src.read_buf(&mut buf)?;
+                    if let Some((_ptype, pic)) = parse_flac_picture(&buf) {
+                        self.metadata.add(METADATA_COVER_ART, MetadataValue::Picture(pic));
+                    }
+                },
                 _ =>                      self.src.read_skip(len)?,
             };
 
@@ -118,6 +133,10 @@ impl<'a> RawDemuxCore<'a> for FLACDemuxer<'a> {
         }
     }
     fn get_duration(&self) -> u64 { self.tot_samples * 1000 / u64::from(self.srate) }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
+    fn is_seekable(&mut self) -> bool { self.src.is_seekable() }
 }
 
 impl<'a> NAOptionHandler for FLACDemuxer<'a> {
@@ -7,6 +7,8 @@ macro_rules! validate {
 
 #[cfg(feature="demuxer_ape")]
 mod ape;
+#[cfg(any(feature="demuxer_ape", feature="demuxer_wavpack"))]
+mod apetag;
 #[cfg(feature="demuxer_flac")]
 mod flac;
 #[cfg(feature="demuxer_flac")]
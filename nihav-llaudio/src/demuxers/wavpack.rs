@@ -1,5 +1,6 @@
 use nihav_core::frame::*;
 use nihav_core::demuxers::*;
+use super::apetag::read_apev2_tag;
 
 const SAMPLE_RATES: [u32; 15] = [
      6000,  8000,  9600, 11025, 12000, 16000,  22050, 24000,
@@ -99,6 +100,7 @@ struct WavPackDemuxer<'a> {
     first_blocks:   Option<(WVHeader, Vec<u8>)>,
     srate:          u32,
     known_frames:   Vec<FrameSeekInfo>,
+    metadata:       Metadata,
 }
 
 impl<'a> WavPackDemuxer<'a> {
@@ -110,6 +112,7 @@ impl<'a> WavPackDemuxer<'a> {
             first_blocks:   None,
             srate:          0,
             known_frames:   Vec::new(),
+            metadata:       Metadata::new(),
         }
     }
     fn read_blocks(&mut self) -> DemuxerResult<(WVHeader, Vec<u8>)> {
@@ -181,6 +184,8 @@ impl<'a> DemuxCore<'a> for WavPackDemuxer<'a> {
         self.known_frames.push(FrameSeekInfo { off: 0, samplepos: hdr.block_index });
 
         self.first_blocks = Some((hdr, buf));
+        // tags are optional so a broken one should not prevent playback
+        let _ = read_apev2_tag(self.src, &mut self.metadata);
         Ok(())
     }
     fn get_frame(&mut self, strmgr: &mut StreamManager) -> DemuxerResult<NAPacket> {
@@ -253,7 +258,16 @@ impl<'a> DemuxCore<'a> for WavPackDemuxer<'a> {
             Err(DemuxerError::NotPossible)
         }
     }
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 {
+        if self.srate != 0 {
+            self.nsamples * 1000 / u64::from(self.srate)
+        } else {
+            0
+        }
+    }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata.get_global_mut() = self.metadata.clone();
+    }
 }
 
 impl<'a> NAOptionHandler for WavPackDemuxer<'a> {
@@ -7,19 +7,49 @@ struct FLACMuxer<'a> {
     duration:       u64,
     maxblk:         u16,
     minblk:         u16,
+    last_blk:       Option<u16>,
     bits:           u8,
+    metadata:       Metadata,
+    md5:            Option<[u8; 16]>,
+    srate:          u32,
+    data_start:     u64,
+    seek_points:    usize,
+    frames:         Vec<(u64, u64, u16)>,
 }
 
+/// Seek table interval in seconds.
+const SEEK_INTERVAL: u64 = 10;
+/// Number of seek points reserved when stream duration is not known.
+const DEFAULT_SEEK_POINTS: usize = 100;
+const SEEK_POINT_SIZE: usize = 18;
+const SEEKTABLE_OFFSET: u64 = 42;
+
 impl<'a> FLACMuxer<'a> {
     fn new(bw: &'a mut ByteWriter<'a>) -> Self {
         Self {
             bw,
-            maxpkt: std::usize::MAX, minpkt: 0,
-            maxblk: std::u16::MAX, minblk: 0,
+            maxpkt: 0, minpkt: usize::MAX,
+            maxblk: 0, minblk: u16::MAX,
+            last_blk: None,
             duration: 0,
             bits: 0,
+            metadata: Metadata::new(),
+            md5: None,
+            srate: 0,
+            data_start: 0,
+            seek_points: 0,
+            frames: Vec::new(),
         }
     }
+    fn write_metadata_block(&mut self, id: u8, data: &[u8], last: bool) -> MuxerResult<()> {
+        if data.len() >= (1 << 24) {
+            return Err(MuxerError::InvalidArgument);
+        }
+        self.bw.write_byte(if last { id | 0x80 } else { id })?;
+        self.bw.write_u24be(data.len() as u32)?;
+        self.bw.write_buf(data)?;
+        Ok(())
+    }
 }
 
 impl<'a> MuxCore<'a> for FLACMuxer<'a> {
@@ -29,8 +59,26 @@ impl<'a> MuxCore<'a> for FLACMuxer<'a> {
         }
         let stream = strmgr.get_stream(0).unwrap();
         if let NACodecTypeInfo::Audio(ref ainfo) = stream.get_info().get_properties() {
+            let mut blocks = Vec::new();
+            if !self.metadata.is_empty() {
+                blocks.push((4, create_vorbis_comment(&self.metadata, "NihAV")));
+            }
+            for pic in self.metadata.get_all(METADATA_COVER_ART) {
+                if let MetadataValue::Picture(ref pic) = *pic {
+                    blocks.push((6, create_flac_picture(3, pic)));
+                }
+            }
+
+            let duration = stream.duration * u64::from(stream.tb_num) / u64::from(stream.tb_den.max(1));
+            self.seek_points = if duration > 0 {
+                    (duration / SEEK_INTERVAL + 1) as usize
+                } else {
+                    DEFAULT_SEEK_POINTS
+                };
+            self.srate = ainfo.sample_rate;
+
             self.bw.write_buf(b"fLaC")?;
-            self.bw.write_byte(0x80)?; // last metadata block - streaminfo
+            self.bw.write_byte(0x00)?; // streaminfo
             self.bw.write_u24be(34)?; // streaminfo size
             self.bw.write_u16be(2)?; // minimum block size
             self.bw.write_u16be(ainfo.block_len as u16)?;
@@ -44,6 +92,20 @@ impl<'a> MuxCore<'a> for FLACMuxer<'a> {
             self.bw.write_u32be(0)?;//total samples low 32 bits
             self.bw.write_u64be(0)?;self.bw.write_u64be(0)?; //MD5
 
+            // seek table is filled at the end, for now it contains only placeholders
+            let mut seektable = Vec::with_capacity(self.seek_points * SEEK_POINT_SIZE);
+            for _ in 0..self.seek_points {
+                seektable.extend_from_slice(&[0xFF; 8]);
+                seektable.extend_from_slice(&[0; 10]);
+            }
+            self.write_metadata_block(3, &seektable, blocks.is_empty())?;
+
+            let nblocks = blocks.len();
+            for (i, (id, data)) in blocks.into_iter().enumerate() {
+                self.write_metadata_block(id, &data, i + 1 == nblocks)?;
+            }
+            self.data_start = self.bw.tell();
+
             Ok(())
         } else {
             Err(MuxerError::InvalidArgument)
@@ -56,18 +118,38 @@ impl<'a> MuxCore<'a> for FLACMuxer<'a> {
 
         self.maxpkt = self.maxpkt.max(pktlen);
         self.minpkt = self.minpkt.min(pktlen);
+        // the last block may be shorter so it does not count for the minimum block size
+        if let Some(blk) = self.last_blk {
+            self.minblk = self.minblk.min(blk);
+        }
+        self.last_blk = Some(samples as u16);
         self.maxblk = self.maxblk.max(samples as u16);
-        self.minblk = self.minblk.min(samples as u16);
+        let offset = self.bw.tell() - self.data_start;
+        self.frames.push((self.duration, offset, samples as u16));
         self.duration += samples;
 
+        for side_data in pkt.side_data.iter() {
+            if let NASideData::StreamMD5(md5) = side_data {
+                self.md5 = Some(*md5);
+            }
+        }
+
         self.bw.write_buf(&pkt.get_buffer())?;
         Ok(())
     }
     fn flush(&mut self) -> MuxerResult<()> {
         Ok(())
     }
+    fn set_metadata(&mut self, metadata: &ContainerMetadata) {
+        self.metadata = metadata.get_global().clone();
+    }
     fn end(&mut self) -> MuxerResult<()> {
-//todo: write MD5 somehow?
+        if self.minblk == u16::MAX {
+            self.minblk = self.last_blk.unwrap_or(0);
+        }
+        if self.minpkt == usize::MAX {
+            self.minpkt = 0;
+        }
         self.bw.seek(SeekFrom::Start(8))?;
         self.bw.write_u16be(self.minblk)?;
         self.bw.write_u16be(self.maxblk)?;
@@ -76,6 +158,31 @@ impl<'a> MuxCore<'a> for FLACMuxer<'a> {
         self.bw.seek(SeekFrom::Current(3))?;
         self.bw.write_byte((self.bits << 4) | (((self.duration >> 32) as u8) & 0xF))?;
         self.bw.write_u32be(self.duration as u32)?;
+        if let Some(ref md5) = self.md5 {
+            self.bw.write_buf(md5)?;
+        }
+
+        // pick the frames starting before regularly spaced points in the stream
+        let min_interval = SEEK_INTERVAL * u64::from(self.srate.max(1));
+        let interval = min_interval.max(self.duration / (self.seek_points as u64) + 1);
+        self.bw.seek(SeekFrom::Start(SEEKTABLE_OFFSET + 4))?;
+        let mut target = 0;
+        let mut npoints = 0;
+        for (i, &(pos, offset, nsamples)) in self.frames.iter().enumerate() {
+            if npoints >= self.seek_points {
+                break;
+            }
+            let next_pos = self.frames.get(i + 1).map_or(u64::MAX, |frm| frm.0);
+            if next_pos > target {
+                self.bw.write_u64be(pos)?;
+                self.bw.write_u64be(offset)?;
+                self.bw.write_u16be(nsamples)?;
+                npoints += 1;
+                while target < next_pos {
+                    target += interval;
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -101,8 +208,10 @@ mod test {
     use nihav_core::codecs::*;
     use nihav_core::demuxers::*;
     use nihav_core::muxers::*;
+    use nihav_codec_support::md5::MD5;
     use nihav_codec_support::test::enc_video::*;
     use crate::*;
+    use crate::codecs::enctest::*;
 
     #[test]
     fn test_flac_muxer() {
@@ -127,5 +236,58 @@ mod test {
         test_remuxing_md5(&dec_config, "flac", &mux_reg,
                           [0x77afb7c0, 0x84d2bd87, 0x6e028092, 0x7db7c72e]);
     }
-}
+    #[test]
+    fn test_flac_muxer_seektable() {
+        const SRATE: u32 = 8000;
+        const LEN: usize = (SRATE as usize) * 25;
+
+        let signal = gen_signal(LEN, 1);
+        let mut bytes = Vec::with_capacity(LEN * 2);
+        for &samp in signal[0].iter() {
+            bytes.extend_from_slice(&samp.to_le_bytes());
+        }
+        let mut md5 = MD5::new();
+        md5.update_hash(&bytes);
+        md5.finish();
+        let mut ref_md5 = [0; 16];
+        md5.get_hash_bytes(&mut ref_md5);
+
+        let options = [NAOption{name: "compression", value: NAValue::Int(0)}];
+        let (stream, pkts) = encode_signal("flac", &options, SRATE, 4096, &signal);
+
+        let mut mux_reg = RegisteredMuxers::new();
+        llaudio_register_all_muxers(&mut mux_reg);
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut out_sm = StreamManager::new();
+            let mut out_stream = (*stream).clone();
+            out_stream.duration = LEN as u64;
+            out_sm.add_stream(out_stream);
+            let mut mux = create_muxer(mux_reg.find_muxer("flac").unwrap(), out_sm, &mut bw).unwrap();
+            for pkt in pkts {
+                mux.mux_frame(pkt).unwrap();
+            }
+            mux.end().unwrap();
+        }
+
+        assert_eq!(&dst[26..42], &ref_md5);
+        assert_eq!(dst[42], 0x83);
+        assert_eq!(read_u24be(&dst[43..]).unwrap(), 3 * 18);
+        let mut points = Vec::new();
+        for point in dst[46..][..3 * 18].chunks(18) {
+            points.push(read_u64be(point).unwrap());
+        }
+        assert_eq!(points, [0, 4096 * 19, 4096 * 39]);
 
+        let mut dmx_reg = RegisteredDemuxers::new();
+        llaudio_register_all_demuxers(&mut dmx_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("flac").unwrap(), &mut br).unwrap();
+        dmx.seek(NATimePoint::Milliseconds(20000)).unwrap();
+        let pkt = dmx.get_frame().unwrap();
+        assert_eq!(pkt.get_pts(), Some(4096 * 39));
+    }
+}
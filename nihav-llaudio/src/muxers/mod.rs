@@ -2,10 +2,18 @@ use nihav_core::muxers::*;
 
 #[cfg(feature="muxer_flac")]
 mod flac;
+#[cfg(feature="muxer_tta")]
+mod tta;
+#[cfg(feature="muxer_wavpack")]
+mod wavpack;
 
 const MUXERS: &[&dyn MuxerCreator] = &[
 #[cfg(feature="muxer_flac")]
     &flac::FLACMuxerCreator {},
+#[cfg(feature="muxer_tta")]
+    &tta::TTAMuxerCreator {},
+#[cfg(feature="muxer_wavpack")]
+    &wavpack::WavPackMuxerCreator {},
 ];
 
 pub fn llaudio_register_all_muxers(rm: &mut RegisteredMuxers) {
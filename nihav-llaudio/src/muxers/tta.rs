@@ -0,0 +1,210 @@
+use nihav_core::muxers::*;
+use crate::codecs::tta::calc_crc;
+
+struct TTAMuxer<'a> {
+    bw:             &'a mut ByteWriter<'a>,
+    channels:       u16,
+    bits:           u16,
+    srate:          u32,
+    framelen:       u64,
+    nsamples:       u64,
+    reserved:       Option<usize>,
+    sizes:          Vec<u32>,
+    frames:         Vec<Vec<u8>>,
+}
+
+const HEADER_SIZE: u64 = 22;
+
+impl<'a> TTAMuxer<'a> {
+    fn new(bw: &'a mut ByteWriter<'a>) -> Self {
+        Self {
+            bw,
+            channels:   0,
+            bits:       0,
+            srate:      0,
+            framelen:   0,
+            nsamples:   0,
+            reserved:   None,
+            sizes:      Vec::new(),
+            frames:     Vec::new(),
+        }
+    }
+    fn write_header(&mut self) -> MuxerResult<()> {
+        let mut hdr = Vec::with_capacity(HEADER_SIZE as usize);
+        hdr.extend_from_slice(b"TTA1");
+        hdr.extend_from_slice(&1u16.to_le_bytes());
+        hdr.extend_from_slice(&self.channels.to_le_bytes());
+        hdr.extend_from_slice(&self.bits.to_le_bytes());
+        hdr.extend_from_slice(&self.srate.to_le_bytes());
+        hdr.extend_from_slice(&(self.nsamples as u32).to_le_bytes());
+        let crc = calc_crc(&hdr);
+        hdr.extend_from_slice(&crc.to_le_bytes());
+        self.bw.write_buf(&hdr)?;
+        Ok(())
+    }
+    fn write_seek_table(&mut self) -> MuxerResult<()> {
+        let mut table = Vec::with_capacity(self.sizes.len() * 4 + 4);
+        for &size in self.sizes.iter() {
+            table.extend_from_slice(&size.to_le_bytes());
+        }
+        let crc = calc_crc(&table);
+        table.extend_from_slice(&crc.to_le_bytes());
+        self.bw.write_buf(&table)?;
+        Ok(())
+    }
+}
+
+impl<'a> MuxCore<'a> for TTAMuxer<'a> {
+    fn create(&mut self, strmgr: &StreamManager) -> MuxerResult<()> {
+        if strmgr.get_num_streams() != 1 {
+            return Err(MuxerError::InvalidArgument);
+        }
+        let stream = strmgr.get_stream(0).unwrap();
+        if stream.get_info().get_name() != "tta" {
+            return Err(MuxerError::UnsupportedFormat);
+        }
+        if let NACodecTypeInfo::Audio(ref ainfo) = stream.get_info().get_properties() {
+            if ainfo.sample_rate <= 256 || ainfo.sample_rate >= 1048576 || ainfo.channels == 0 {
+                return Err(MuxerError::UnsupportedFormat);
+            }
+            self.channels = u16::from(ainfo.channels);
+            self.bits = u16::from(ainfo.format.bits);
+            self.srate = ainfo.sample_rate;
+            self.framelen = u64::from(self.srate * 256 / 245);
+
+            // the seek table precedes the frames so it can be reserved only when the stream length is known beforehand
+            let nsamples = stream.duration * u64::from(stream.tb_num) * u64::from(self.srate) / u64::from(stream.tb_den.max(1));
+            if nsamples > 0 && nsamples <= u64::from(u32::MAX) {
+                let nframes = ((nsamples - 1) / self.framelen + 1) as usize;
+                self.nsamples = nsamples;
+                self.reserved = Some(nframes);
+                self.sizes = vec![0; nframes];
+                self.write_header()?;
+                self.write_seek_table()?;
+                self.sizes.clear();
+            }
+
+            Ok(())
+        } else {
+            Err(MuxerError::InvalidArgument)
+        }
+    }
+    fn mux_frame(&mut self, _strmgr: &StreamManager, pkt: NAPacket) -> MuxerResult<()> {
+        let src = pkt.get_buffer();
+        if src.len() <= 4 {
+            return Err(MuxerError::InvalidData);
+        }
+        self.sizes.push(src.len() as u32);
+        if self.reserved.is_some() {
+            self.bw.write_buf(&src)?;
+        } else {
+            self.nsamples += pkt.ts.duration.unwrap_or(self.framelen);
+            self.frames.push(src.to_vec());
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> MuxerResult<()> {
+        Ok(())
+    }
+    fn end(&mut self) -> MuxerResult<()> {
+        if let Some(nframes) = self.reserved {
+            if self.sizes.len() != nframes {
+                return Err(MuxerError::InvalidData);
+            }
+            self.bw.seek(SeekFrom::Start(HEADER_SIZE))?;
+            self.write_seek_table()?;
+            self.bw.seek(SeekFrom::End(0))?;
+        } else {
+            if self.frames.is_empty() || self.nsamples > u64::from(u32::MAX) {
+                return Err(MuxerError::InvalidData);
+            }
+            self.write_header()?;
+            self.write_seek_table()?;
+            for frame in self.frames.iter() {
+                self.bw.write_buf(frame)?;
+            }
+            self.frames.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<'a> NAOptionHandler for TTAMuxer<'a> {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+    fn set_options(&mut self, _options: &[NAOption]) { }
+    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+}
+
+pub struct TTAMuxerCreator {}
+
+impl MuxerCreator for TTAMuxerCreator {
+    fn new_muxer<'a>(&self, bw: &'a mut ByteWriter<'a>) -> Box<dyn MuxCore<'a> + 'a> {
+        Box::new(TTAMuxer::new(bw))
+    }
+    fn get_name(&self) -> &'static str { "tta" }
+    fn get_capabilities(&self) -> MuxerCapabilities { MuxerCapabilities::SingleAudio("tta") }
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_core::demuxers::*;
+    use nihav_core::muxers::*;
+    use crate::*;
+    use crate::codecs::enctest::*;
+    use super::calc_crc;
+
+    fn mux_tta(duration: u64) -> (Vec<u8>, Vec<Vec<i16>>) {
+        const SRATE: u32 = 8000;
+        const LEN: usize = (SRATE as usize) * 3 + 1000;
+
+        let signal = gen_signal(LEN, 1);
+        let (stream, pkts) = encode_signal("tta", &[], SRATE, 1, &signal);
+
+        let mut mux_reg = RegisteredMuxers::new();
+        llaudio_register_all_muxers(&mut mux_reg);
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut out_sm = StreamManager::new();
+            let mut out_stream = (*stream).clone();
+            out_stream.duration = duration;
+            out_sm.add_stream(out_stream);
+            let mut mux = create_muxer(mux_reg.find_muxer("tta").unwrap(), out_sm, &mut bw).unwrap();
+            for pkt in pkts {
+                mux.mux_frame(pkt).unwrap();
+            }
+            mux.end().unwrap();
+        }
+        (dst, signal)
+    }
+
+    #[test]
+    fn test_tta_muxer() {
+        let (dst, signal) = mux_tta(0);
+        let len = signal[0].len();
+        let (dst2, _) = mux_tta(len as u64);
+        assert_eq!(dst, dst2);
+        assert_eq!(calc_crc(&dst[..18]), read_u32le(&dst[18..]).unwrap());
+        assert_eq!(read_u32le(&dst[14..]).unwrap() as usize, len);
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        llaudio_register_all_demuxers(&mut dmx_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        llaudio_register_all_decoders(&mut dec_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("tta").unwrap(), &mut br).unwrap();
+        let stream = dmx.get_stream(0).unwrap();
+        let mut decoder = (dec_reg.find_decoder("tta").unwrap())();
+        let mut dsupp = NADecoderSupport::new();
+        decoder.init(&mut dsupp, stream.get_info()).unwrap();
+        let mut pos = 0;
+        while let Ok(pkt) = dmx.get_frame() {
+            let frm = decoder.decode(&mut dsupp, &pkt).unwrap();
+            pos += check_frame(&frm, &signal, pos);
+        }
+        assert_eq!(pos, len);
+    }
+}
@@ -0,0 +1,134 @@
+use nihav_core::muxers::*;
+
+struct WavPackMuxer<'a> {
+    bw:             &'a mut ByteWriter<'a>,
+    nsamples:       u64,
+    first_pos:      Option<u64>,
+}
+
+const WV_HEADER_SIZE: usize = 32;
+
+impl<'a> WavPackMuxer<'a> {
+    fn new(bw: &'a mut ByteWriter<'a>) -> Self {
+        Self {
+            bw,
+            nsamples:   0,
+            first_pos:  None,
+        }
+    }
+}
+
+impl<'a> MuxCore<'a> for WavPackMuxer<'a> {
+    fn create(&mut self, strmgr: &StreamManager) -> MuxerResult<()> {
+        if strmgr.get_num_streams() != 1 {
+            return Err(MuxerError::InvalidArgument);
+        }
+        let stream = strmgr.get_stream(0).unwrap();
+        if stream.get_info().get_name() != "wavpack" {
+            return Err(MuxerError::UnsupportedFormat);
+        }
+        Ok(())
+    }
+    fn mux_frame(&mut self, _strmgr: &StreamManager, pkt: NAPacket) -> MuxerResult<()> {
+        let src = pkt.get_buffer();
+        if src.len() < WV_HEADER_SIZE || &src[..4] != b"wvpk" {
+            return Err(MuxerError::InvalidData);
+        }
+        let block_samples = read_u32le(&src[20..]).unwrap_or(0);
+        // blocks without audio data (e.g. stream parameters only) are not written
+        if block_samples == 0 {
+            return Ok(());
+        }
+        if self.first_pos.is_none() {
+            self.first_pos = Some(self.bw.tell());
+        }
+        self.nsamples += u64::from(block_samples);
+        self.bw.write_buf(&src)?;
+        Ok(())
+    }
+    fn flush(&mut self) -> MuxerResult<()> {
+        Ok(())
+    }
+    fn end(&mut self) -> MuxerResult<()> {
+        // total number of samples is stored only in the first block since blocks are produced before the stream length is known
+        if let Some(pos) = self.first_pos {
+            if self.nsamples >= (1 << 40) {
+                return Err(MuxerError::InvalidData);
+            }
+            self.bw.seek(SeekFrom::Start(pos + 11))?;
+            self.bw.write_byte((self.nsamples >> 32) as u8)?;
+            self.bw.write_u32le(self.nsamples as u32)?;
+            self.bw.seek(SeekFrom::End(0))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> NAOptionHandler for WavPackMuxer<'a> {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+    fn set_options(&mut self, _options: &[NAOption]) { }
+    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+}
+
+pub struct WavPackMuxerCreator {}
+
+impl MuxerCreator for WavPackMuxerCreator {
+    fn new_muxer<'a>(&self, bw: &'a mut ByteWriter<'a>) -> Box<dyn MuxCore<'a> + 'a> {
+        Box::new(WavPackMuxer::new(bw))
+    }
+    fn get_name(&self) -> &'static str { "wavpack" }
+    fn get_capabilities(&self) -> MuxerCapabilities { MuxerCapabilities::SingleAudio("wavpack") }
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_core::demuxers::*;
+    use nihav_core::muxers::*;
+    use crate::*;
+    use crate::codecs::enctest::*;
+
+    #[test]
+    fn test_wavpack_muxer() {
+        const SRATE: u32 = 8000;
+        const LEN: usize = (SRATE as usize) * 3 + 1000;
+
+        let signal = gen_signal(LEN, 2);
+        let options = [NAOption{name: "block_size", value: NAValue::Int(i64::from(SRATE))}];
+        let (stream, pkts) = encode_signal("wavpack", &options, SRATE, 1, &signal);
+
+        let mut mux_reg = RegisteredMuxers::new();
+        llaudio_register_all_muxers(&mut mux_reg);
+        let mut dst = Vec::new();
+        {
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut out_sm = StreamManager::new();
+            out_sm.add_stream((*stream).clone());
+            let mut mux = create_muxer(mux_reg.find_muxer("wavpack").unwrap(), out_sm, &mut bw).unwrap();
+            for pkt in pkts {
+                mux.mux_frame(pkt).unwrap();
+            }
+            mux.end().unwrap();
+        }
+        assert_eq!(read_u32le(&dst[12..]).unwrap() as usize, LEN);
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        llaudio_register_all_demuxers(&mut dmx_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        llaudio_register_all_decoders(&mut dec_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("wavpack").unwrap(), &mut br).unwrap();
+        let stream = dmx.get_stream(0).unwrap();
+        let mut decoder = (dec_reg.find_decoder("wavpack").unwrap())();
+        let mut dsupp = NADecoderSupport::new();
+        decoder.init(&mut dsupp, stream.get_info()).unwrap();
+        let mut pos = 0;
+        while let Ok(pkt) = dmx.get_frame() {
+            let frm = decoder.decode(&mut dsupp, &pkt).unwrap();
+            pos += check_frame(&frm, &signal, pos);
+        }
+        assert_eq!(pos, LEN);
+    }
+}
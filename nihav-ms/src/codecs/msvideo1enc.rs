@@ -1,5 +1,6 @@
 use nihav_core::codecs::*;
 use nihav_core::io::byteio::*;
+use nihav_core::scale::*;
 use nihav_codec_support::vq::*;
 
 #[derive(Default,Clone,Copy,PartialEq)]
@@ -14,6 +15,13 @@ impl Pixel16 {
     }
     fn invalid() -> Self { Self(0x8000) }
     fn is_invalid(self) -> bool { self == Self::invalid() }
+    fn from_rgb(clr: &[u8; 3]) -> Self {
+        Self::pack(u16::from(clr[0] >> 3), u16::from(clr[1] >> 3), u16::from(clr[2] >> 3))
+    }
+    fn to_rgb(self) -> [u8; 3] {
+        let (r, g, b) = self.unpack();
+        [((r << 3) | (r >> 2)) as u8, ((g << 3) | (g >> 2)) as u8, ((b << 3) | (b >> 2)) as u8]
+    }
 }
 impl VQElement for Pixel16 {
     fn dist(&self, rval: Self) -> u32 {
@@ -290,14 +298,236 @@ impl BlockState {
     }
 }
 
+fn clr_dist(a: &[u8; 3], b: &[u8; 3]) -> u32 {
+    let rd = i32::from(a[0]) - i32::from(b[0]);
+    let gd = i32::from(a[1]) - i32::from(b[1]);
+    let bd = i32::from(a[2]) - i32::from(b[2]);
+    (rd * rd + gd * gd + bd * bd) as u32
+}
+
+fn find_nearest(pal: &[[u8; 3]; 256], clr: &[u8; 3]) -> u8 {
+    let mut best_idx = 0;
+    let mut best_dist = u32::MAX;
+    for (idx, pclr) in pal.iter().enumerate() {
+        let dist = clr_dist(pclr, clr);
+        if dist < best_dist {
+            best_dist = dist;
+            best_idx = idx;
+            if dist == 0 {
+                break;
+            }
+        }
+    }
+    best_idx as u8
+}
+
+#[derive(Default)]
+struct BlockState8 {
+    fill_dist:  u32,
+    fill_val:   u8,
+    clr2_dist:  u32,
+    clr2_flags: u16,
+    clr2:       [u8; 2],
+    clr8_dist:  u32,
+    clr8_flags: u16,
+    clr8:       [u8; 8],
+}
+
+impl BlockState8 {
+    fn calc_clrs(pix: &[u8], pal: &[[u8; 3]; 256]) -> [u8; 2] {
+        let     clr0 = pix[0];
+        let mut clr1 = None;
+        let mut exact = true;
+        for &idx in pix[1..].iter() {
+            if idx != clr0 && Some(idx) != clr1 {
+                if clr1.is_none() {
+                    clr1 = Some(idx);
+                } else {
+                    exact = false;
+                    break;
+                }
+            }
+        }
+        if exact {
+            return [clr0, clr1.unwrap_or(clr0)];
+        }
+
+        let mut src = [Pixel16::min_cw(); 16];
+        for (dst, &idx) in src.iter_mut().zip(pix.iter()) {
+            *dst = Pixel16::from_rgb(&pal[usize::from(idx)]);
+        }
+        let mut clrs = [Pixel16::min_cw(); 2];
+        if quantise_median_cut::<Pixel16, Pixel16Sum>(&src[..pix.len()], &mut clrs) < 2 {
+            clrs[1] = clrs[0];
+        }
+        [find_nearest(pal, &clrs[0].to_rgb()), find_nearest(pal, &clrs[1].to_rgb())]
+    }
+    fn calc_stats(&mut self, buf: &[u8; 16], pal: &[[u8; 3]; 256]) {
+        let mut cand = Vec::with_capacity(17);
+        let mut avg = [0u32; 3];
+        for &idx in buf.iter() {
+            if !cand.contains(&idx) {
+                cand.push(idx);
+            }
+            for (sum, &comp) in avg.iter_mut().zip(pal[usize::from(idx)].iter()) {
+                *sum += u32::from(comp);
+            }
+        }
+        let avg_clr = [((avg[0] + 8) / 16) as u8, ((avg[1] + 8) / 16) as u8, ((avg[2] + 8) / 16) as u8];
+        let avg_idx = find_nearest(pal, &avg_clr);
+        if !cand.contains(&avg_idx) {
+            cand.push(avg_idx);
+        }
+        self.fill_dist = u32::MAX;
+        for &cidx in cand.iter() {
+            let cclr = &pal[usize::from(cidx)];
+            let dist = buf.iter().fold(0, |acc, &idx| acc + clr_dist(&pal[usize::from(idx)], cclr));
+            if dist < self.fill_dist {
+                self.fill_dist = dist;
+                self.fill_val  = cidx;
+            }
+        }
+        if self.fill_dist == 0 {
+            self.clr2_dist = u32::MAX;
+            self.clr8_dist = u32::MAX;
+            return;
+        }
+
+        self.clr2 = Self::calc_clrs(buf, pal);
+        self.clr2_flags = 0;
+        self.clr2_dist = 0;
+        for (i, &idx) in buf.iter().enumerate() {
+            let clr = &pal[usize::from(idx)];
+            let dist0 = clr_dist(clr, &pal[usize::from(self.clr2[0])]);
+            let dist1 = clr_dist(clr, &pal[usize::from(self.clr2[1])]);
+            if dist0 < dist1 {
+                self.clr2_flags |= 1 << i;
+                self.clr2_dist += dist0;
+            } else {
+                self.clr2_dist += dist1;
+            }
+        }
+        // opcodes with the top bit set are fills and skips
+        if (self.clr2_flags & 0x8000) != 0 {
+            self.clr2_flags = !self.clr2_flags;
+            self.clr2.swap(0, 1);
+        }
+        if self.clr2_dist == 0 {
+            self.clr8_dist = u32::MAX;
+            return;
+        }
+
+        self.clr8_flags = 0;
+        self.clr8_dist = 0;
+        for quad in 0..4 {
+            let off = (quad & 1) * 2 + (quad & 2) * 4;
+            let pos = [off, off + 1, off + 4, off + 5];
+            let src = [buf[pos[0]], buf[pos[1]], buf[pos[2]], buf[pos[3]]];
+            let clrs = Self::calc_clrs(&src, pal);
+            let base = (quad & 1) * 2 + (quad & 2) * 2;
+            self.clr8[base]     = clrs[0];
+            self.clr8[base + 1] = clrs[1];
+            for (&bit, &idx) in pos.iter().zip(src.iter()) {
+                let clr = &pal[usize::from(idx)];
+                let dist0 = clr_dist(clr, &pal[usize::from(clrs[0])]);
+                let dist1 = clr_dist(clr, &pal[usize::from(clrs[1])]);
+                if dist0 < dist1 {
+                    self.clr8_flags |= 1 << bit;
+                    self.clr8_dist += dist0;
+                } else {
+                    self.clr8_dist += dist1;
+                }
+            }
+        }
+        // eight-colour blocks are signalled by opcodes 0x8800 and above,
+        // so flip quadrant colours until the flags fall into that range
+        if (self.clr8_flags & 0x8000) == 0 {
+            self.clr8_flags ^= 0xCC00;
+            self.clr8.swap(6, 7);
+        }
+        if (self.clr8_flags & 0x7800) == 0 {
+            self.clr8_flags ^= 0x3300;
+            self.clr8.swap(4, 5);
+        }
+    }
+    fn put_fill(&self, dst: &mut [u8], dstride: usize) {
+        for line in dst.chunks_mut(dstride).take(4) {
+            for pix in line[..4].iter_mut() {
+                *pix = self.fill_val;
+            }
+        }
+    }
+    fn put_clr2(&self, dst: &mut [u8], dstride: usize) {
+        for j in 0..4 {
+            for i in 0..4 {
+                if (self.clr2_flags & (1 << (i + j * 4))) != 0 {
+                    dst[i + j * dstride] = self.clr2[0];
+                } else {
+                    dst[i + j * dstride] = self.clr2[1];
+                }
+            }
+        }
+    }
+    fn put_clr8(&self, dst: &mut [u8], dstride: usize) {
+        for j in 0..4 {
+            for i in 0..4 {
+                let base = (i >> 1) * 2 + (j >> 1) * 4;
+                if (self.clr8_flags & (1 << (i + j * 4))) != 0 {
+                    dst[i + j * dstride] = self.clr8[base];
+                } else {
+                    dst[i + j * dstride] = self.clr8[base + 1];
+                }
+            }
+        }
+    }
+    fn write_fill(&self, bw: &mut ByteWriter) -> EncoderResult<()> {
+        bw.write_u16le(u16::from(self.fill_val) | 0x8000)?;
+        Ok(())
+    }
+    fn write_clr2(&self, bw: &mut ByteWriter) -> EncoderResult<()> {
+        bw.write_u16le(self.clr2_flags)?;
+        bw.write_buf(&self.clr2)?;
+        Ok(())
+    }
+    fn write_clr8(&self, bw: &mut ByteWriter) -> EncoderResult<()> {
+        bw.write_u16le(self.clr8_flags)?;
+        bw.write_buf(&self.clr8)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+enum CodingMode {
+    Auto,
+    RGB555,
+    Palette,
+}
+
+impl std::fmt::Display for CodingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CodingMode::Auto    => write!(f, "auto"),
+            CodingMode::RGB555  => write!(f, "rgb555"),
+            CodingMode::Palette => write!(f, "palette"),
+        }
+    }
+}
+
 struct MSVideo1Encoder {
     stream:     Option<NAStreamRef>,
     pkt:        Option<NAPacket>,
     pool:       NAVideoBufferPool<u16>,
     lastfrm:    Option<NAVideoBufferRef<u16>>,
+    pool8:      NAVideoBufferPool<u8>,
+    lastfrm8:   Option<NAVideoBufferRef<u8>>,
+    qbuf:       Option<NABufferType>,
     quality:    u8,
     frmcount:   u8,
     key_int:    u8,
+    mode:       CodingMode,
+    paletted:   bool,
+    pal:        [[u8; 3]; 256],
+    pal_changed: bool,
 }
 
 impl MSVideo1Encoder {
@@ -307,11 +537,21 @@ impl MSVideo1Encoder {
             pkt:        None,
             pool:       NAVideoBufferPool::new(2),
             lastfrm:    None,
+            pool8:      NAVideoBufferPool::new(2),
+            lastfrm8:   None,
+            qbuf:       None,
             quality:    0,
             frmcount:   0,
             key_int:    25,
+            mode:       CodingMode::Auto,
+            paletted:   false,
+            pal:        [[0; 3]; 256],
+            pal_changed: false,
         }
     }
+    fn can_quantise(fmt: &NAPixelFormaton) -> bool {
+        fmt.model.is_rgb() && !fmt.is_unpacked() && fmt.elem_size >= 3 && fmt.get_max_depth() == 8
+    }
     fn get_block(src: &[u16], sstride: usize, buf: &mut [Pixel16; 16]) {
         for (line, dst) in src.chunks(sstride).zip(buf.chunks_mut(4)) {
             for i in 0..4 {
@@ -431,6 +671,200 @@ impl MSVideo1Encoder {
         bw.write_u16le(0)?;
         Ok(true)
     }
+    fn get_block8(src: &[u8], sstride: usize, buf: &mut [u8; 16]) {
+        for (line, dst) in src.chunks(sstride).zip(buf.chunks_mut(4)) {
+            dst.copy_from_slice(&line[..4]);
+        }
+    }
+    fn encode_inter8(bw: &mut ByteWriter, cur_frm: &mut NAVideoBuffer<u8>, in_frm: &NAVideoBuffer<u8>, prev_frm: &NAVideoBuffer<u8>, pal: &[[u8; 3]; 256]) -> EncoderResult<bool> {
+        let mut is_intra = true;
+        let src = in_frm.get_data();
+        let sstride = in_frm.get_stride(0);
+        let soff = in_frm.get_offset(0);
+        let (w, h) = in_frm.get_dimensions(0);
+        let rsrc = prev_frm.get_data();
+        let rstride = prev_frm.get_stride(0);
+        let roff = prev_frm.get_offset(0);
+        let dstride = cur_frm.get_stride(0);
+        let doff = cur_frm.get_offset(0);
+        let dst = cur_frm.get_data_mut().unwrap();
+        // skipped blocks are taken from the reference frame
+        dst.copy_from_slice(rsrc);
+        let mut skip_run = 0;
+        for ((sstrip, rstrip), dstrip) in src[soff..].chunks(sstride * 4).take(h / 4).zip(rsrc[roff..].chunks(rstride * 4)).zip(dst[doff..].chunks_mut(dstride * 4)) {
+            for x in (0..w).step_by(4) {
+                let mut buf = [0; 16];
+                let mut refbuf = [0; 16];
+                Self::get_block8(&sstrip[x..], sstride, &mut buf);
+                Self::get_block8(&rstrip[x..], rstride, &mut refbuf);
+
+                let mut skip_dist = 0;
+                for (&idx, &ridx) in buf.iter().zip(refbuf.iter()) {
+                    skip_dist += clr_dist(&pal[usize::from(idx)], &pal[usize::from(ridx)]);
+                }
+                if skip_dist == 0 {
+                    skip_run += 1;
+                    is_intra = false;
+                    if skip_run == 1023 {
+                        Self::write_skips(bw, skip_run)?;
+                        skip_run = 0;
+                    }
+                    continue;
+                }
+
+                let mut bstate = BlockState8::default();
+                bstate.calc_stats(&buf, pal);
+
+                let dst = &mut dstrip[x..];
+                if skip_dist <= bstate.fill_dist {
+                    skip_run += 1;
+                    is_intra = false;
+                    if skip_run == 1023 {
+                        Self::write_skips(bw, skip_run)?;
+                        skip_run = 0;
+                    }
+                } else if bstate.fill_dist <= bstate.clr2_dist {
+                    bstate.put_fill(dst, dstride);
+                    if skip_run != 0 {
+                        Self::write_skips(bw, skip_run)?;
+                        skip_run = 0;
+                    }
+                    bstate.write_fill(bw)?;
+                } else if bstate.clr8_dist < bstate.clr2_dist {
+                    bstate.put_clr8(dst, dstride);
+                    if skip_run != 0 {
+                        Self::write_skips(bw, skip_run)?;
+                        skip_run = 0;
+                    }
+                    bstate.write_clr8(bw)?;
+                } else {
+                    bstate.put_clr2(dst, dstride);
+                    if skip_run != 0 {
+                        Self::write_skips(bw, skip_run)?;
+                        skip_run = 0;
+                    }
+                    bstate.write_clr2(bw)?;
+                }
+            }
+        }
+        if skip_run != 0 {
+            Self::write_skips(bw, skip_run)?;
+        }
+        if is_intra {
+            bw.write_u16le(0)?;
+        }
+        Ok(is_intra)
+    }
+    fn encode_intra8(bw: &mut ByteWriter, cur_frm: &mut NAVideoBuffer<u8>, in_frm: &NAVideoBuffer<u8>, pal: &[[u8; 3]; 256]) -> EncoderResult<bool> {
+        let src = in_frm.get_data();
+        let sstride = in_frm.get_stride(0);
+        let soff = in_frm.get_offset(0);
+        let (w, h) = in_frm.get_dimensions(0);
+        let dstride = cur_frm.get_stride(0);
+        let doff = cur_frm.get_offset(0);
+        let dst = cur_frm.get_data_mut().unwrap();
+        for (sstrip, dstrip) in src[soff..].chunks(sstride * 4).take(h / 4).zip(dst[doff..].chunks_mut(dstride * 4)) {
+            for x in (0..w).step_by(4) {
+                let mut buf = [0; 16];
+                Self::get_block8(&sstrip[x..], sstride, &mut buf);
+                let mut bstate = BlockState8::default();
+                bstate.calc_stats(&buf, pal);
+
+                let dst = &mut dstrip[x..];
+                if bstate.fill_dist <= bstate.clr2_dist {
+                    bstate.put_fill(dst, dstride);
+                    bstate.write_fill(bw)?;
+                } else if bstate.clr8_dist < bstate.clr2_dist {
+                    bstate.put_clr8(dst, dstride);
+                    bstate.write_clr8(bw)?;
+                } else {
+                    bstate.put_clr2(dst, dstride);
+                    bstate.write_clr2(bw)?;
+                }
+            }
+        }
+        bw.write_u16le(0)?;
+        Ok(true)
+    }
+    fn quantise_frame(&mut self, buf: &NABufferType) -> EncoderResult<NAVideoBufferRef<u8>> {
+        let qbuf = if let Some(ref mut qbuf) = self.qbuf {
+                qbuf
+            } else {
+                return Err(EncoderError::FormatError);
+            };
+        if self.frmcount == 0 {
+            if palettise_frame(buf, qbuf, QuantisationMode::MedianCut, PaletteSearchMode::Local).is_err() {
+                return Err(EncoderError::FormatError);
+            }
+        } else {
+            // keep the palette until the next keyframe so inter frames can be coded
+            let sbuf = buf.get_vbuf().unwrap();
+            let ifmt = sbuf.get_info().get_format();
+            if !Self::can_quantise(&ifmt) {
+                return Err(EncoderError::FormatError);
+            }
+            let esize = ifmt.elem_size as usize;
+            let coffs = [ifmt.comp_info[0].unwrap().comp_offs as usize, ifmt.comp_info[1].unwrap().comp_offs as usize, ifmt.comp_info[2].unwrap().comp_offs as usize];
+            let sstride = sbuf.get_stride(0);
+            let soff = sbuf.get_offset(0);
+            let (w, h) = sbuf.get_dimensions(0);
+            let src = sbuf.get_data();
+
+            let mut dbuf = qbuf.get_vbuf().unwrap();
+            let dstride = dbuf.get_stride(0);
+            let doff = dbuf.get_offset(0);
+            let dst = dbuf.get_data_mut().unwrap();
+            for (sline, dline) in src[soff..].chunks(sstride).zip(dst[doff..].chunks_mut(dstride)).take(h) {
+                for (pix, didx) in sline.chunks_exact(esize).zip(dline.iter_mut()).take(w) {
+                    *didx = find_nearest(&self.pal, &[pix[coffs[0]], pix[coffs[1]], pix[coffs[2]]]);
+                }
+            }
+        }
+        Ok(qbuf.get_vbuf().unwrap())
+    }
+    fn encode_pal(&mut self, vbuf: &NAVideoBuffer<u8>, ts: NATimeInfo) -> EncoderResult<()> {
+        let paloff = vbuf.get_offset(1);
+        let data = vbuf.get_data();
+        let mut npal = [[0; 3]; 256];
+        for (dst, src) in npal.iter_mut().zip(data[paloff..].chunks_exact(3)) {
+            dst.copy_from_slice(src);
+        }
+        if npal != self.pal {
+            self.pal = npal;
+            self.pal_changed = true;
+        }
+        // the previous frame is not a valid reference with a different palette
+        if self.pal_changed {
+            self.frmcount = 0;
+        }
+
+        let mut cur_frm = self.pool8.get_free().unwrap();
+        let mut dbuf = Vec::with_capacity(4);
+        let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
+        let mut bw   = ByteWriter::new(&mut gw);
+        if self.frmcount == 0 {
+            self.lastfrm8 = None;
+        }
+        let is_intra = if let Some(ref prev_buf) = self.lastfrm8 {
+                Self::encode_inter8(&mut bw, &mut cur_frm, vbuf, prev_buf, &self.pal)?
+            } else {
+                Self::encode_intra8(&mut bw, &mut cur_frm, vbuf, &self.pal)?
+            };
+        self.lastfrm8 = Some(cur_frm);
+        let mut pkt = NAPacket::new(self.stream.clone().unwrap(), ts, is_intra, dbuf);
+        let mut pal = [0; 1024];
+        for (dst, src) in pal.chunks_exact_mut(4).zip(self.pal.iter()) {
+            dst[..3].copy_from_slice(src);
+        }
+        pkt.add_side_data(NASideData::Palette(self.pal_changed, Arc::new(pal)));
+        self.pal_changed = false;
+        self.pkt = Some(pkt);
+        self.frmcount += 1;
+        if self.frmcount == self.key_int {
+            self.frmcount = 0;
+        }
+        Ok(())
+    }
 }
 
 const RGB555_FORMAT: NAPixelFormaton = NAPixelFormaton {
@@ -452,7 +886,12 @@ impl NAEncoder for MSVideo1Encoder {
             },
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                let outinfo = NAVideoInfo::new((vinfo.width + 3) & !3, (vinfo.height + 3) & !3, true, RGB555_FORMAT);
+                let pix_fmt = match self.mode {
+                        CodingMode::Auto if vinfo.format.is_paletted() => PAL8_FORMAT,
+                        CodingMode::Auto | CodingMode::RGB555 => RGB555_FORMAT,
+                        CodingMode::Palette => PAL8_FORMAT,
+                    };
+                let outinfo = NAVideoInfo::new((vinfo.width + 3) & !3, (vinfo.height + 3) & !3, true, pix_fmt);
                 let mut ofmt = *encinfo;
                 ofmt.format = NACodecTypeInfo::Video(outinfo);
                 Ok(ofmt)
@@ -464,19 +903,42 @@ impl NAEncoder for MSVideo1Encoder {
             NACodecTypeInfo::None => Err(EncoderError::FormatError),
             NACodecTypeInfo::Audio(_) => Err(EncoderError::FormatError),
             NACodecTypeInfo::Video(vinfo) => {
-                if vinfo.format != RGB555_FORMAT {
+                self.paletted = match self.mode {
+                        CodingMode::Auto    => vinfo.format.is_paletted(),
+                        CodingMode::RGB555  => false,
+                        CodingMode::Palette => true,
+                    };
+                if !self.paletted && vinfo.format != RGB555_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if self.paletted && !vinfo.format.is_paletted() && !Self::can_quantise(&vinfo.format) {
                     return Err(EncoderError::FormatError);
                 }
                 if ((vinfo.width | vinfo.height) & 3) != 0 {
                     return Err(EncoderError::FormatError);
                 }
 
-                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, true, RGB555_FORMAT);
+                let out_fmt = if self.paletted { PAL8_FORMAT } else { RGB555_FORMAT };
+                let out_info = NAVideoInfo::new(vinfo.width, vinfo.height, true, out_fmt);
                 let info = NACodecInfo::new("msvideo1", NACodecTypeInfo::Video(out_info), None);
                 let mut stream = NAStream::new(StreamType::Video, stream_id, info, encinfo.tb_num, encinfo.tb_den, 0);
                 stream.set_num(stream_id as usize);
                 let stream = stream.into_ref();
-                if self.pool.prealloc_video(out_info, 2).is_err() {
+                if self.paletted {
+                    if self.pool8.prealloc_video(out_info, 2).is_err() {
+                        return Err(EncoderError::AllocError);
+                    }
+                    // RGB input is quantised to a palette by the encoder itself
+                    self.qbuf = None;
+                    if !vinfo.format.is_paletted() {
+                        let qinfo = NAVideoInfo::new(vinfo.width, vinfo.height, vinfo.flipped, PAL8_FORMAT);
+                        if let Ok(qbuf) = alloc_video_buffer(qinfo, 0) {
+                            self.qbuf = Some(qbuf);
+                        } else {
+                            return Err(EncoderError::AllocError);
+                        }
+                    }
+                } else if self.pool.prealloc_video(out_info, 2).is_err() {
                     return Err(EncoderError::AllocError);
                 }
 
@@ -489,7 +951,14 @@ impl NAEncoder for MSVideo1Encoder {
     }
     fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
         let buf = frm.get_buffer();
-        if let Some(ref vbuf) = buf.get_vbuf16() {
+        if self.paletted {
+            let vbuf = match buf.get_vbuf() {
+                    Some(vbuf) if vbuf.get_info().get_format().is_paletted() => vbuf,
+                    Some(_) => self.quantise_frame(&buf)?,
+                    None => return Err(EncoderError::InvalidParameters),
+                };
+            self.encode_pal(&vbuf, frm.ts)
+        } else if let Some(ref vbuf) = buf.get_vbuf16() {
             let mut cur_frm = self.pool.get_free().unwrap();
             let mut dbuf = Vec::with_capacity(4);
             let mut gw   = GrowableMemoryWriter::new_write(&mut dbuf);
@@ -528,6 +997,9 @@ const ENCODER_OPTS: &[NAOptionDefinition] = &[
     NAOptionDefinition {
         name: KEYFRAME_OPTION, description: KEYFRAME_OPTION_DESC,
         opt_type: NAOptionDefinitionType::Int(Some(0), Some(128)) },
+    NAOptionDefinition {
+        name: "mode", description: "Coding mode",
+        opt_type: NAOptionDefinitionType::String(Some(&["auto", "rgb555", "palette"])) },
 ];
 
 impl NAOptionHandler for MSVideo1Encoder {
@@ -542,6 +1014,16 @@ impl NAOptionHandler for MSVideo1Encoder {
                                 self.key_int = intval as u8;
                             }
                         },
+                        "mode" => {
+                            if let NAValue::String(ref str) = option.value {
+                                match str.as_str() {
+                                    "auto"      => self.mode = CodingMode::Auto,
+                                    "rgb555"    => self.mode = CodingMode::RGB555,
+                                    "palette"   => self.mode = CodingMode::Palette,
+                                    _ => {},
+                                };
+                            }
+                        },
                         _ => {},
                     };
                 }
@@ -551,6 +1033,7 @@ impl NAOptionHandler for MSVideo1Encoder {
     fn query_option_value(&self, name: &str) -> Option<NAValue> {
         match name {
             KEYFRAME_OPTION => Some(NAValue::Int(i64::from(self.key_int))),
+            "mode" => Some(NAValue::String(self.mode.to_string())),
             _ => None,
         }
     }
@@ -614,4 +1097,136 @@ mod test {
         test_encoding_md5(&dec_config, &enc_config, enc_params, &[],
                           &[0x6921e67e, 0x4f2ada95, 0x009ffc62, 0xd4bfab6a]);
     }
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 48;
+
+    fn get_rgb(buf: &NABufferType, x: usize, y: usize) -> [u8; 3] {
+        let vbuf = buf.get_vbuf().unwrap();
+        let fmt = vbuf.get_info().get_format();
+        let data = vbuf.get_data();
+        let pos = vbuf.get_offset(0) + y * vbuf.get_stride(0);
+        if fmt.is_paletted() {
+            let idx = usize::from(data[pos + x]);
+            let pal = &data[vbuf.get_offset(1) + idx * 3..][..3];
+            [pal[0], pal[1], pal[2]]
+        } else {
+            let pix = &data[pos + x * 3..][..3];
+            [pix[0], pix[1], pix[2]]
+        }
+    }
+
+    fn gen_frame(fmt: NAPixelFormaton, frameno: usize) -> NABufferType {
+        let vinfo = NAVideoInfo::new(WIDTH, HEIGHT, true, fmt);
+        let buf = alloc_video_buffer(vinfo, 2).unwrap();
+        let mut vbuf = buf.get_vbuf().unwrap();
+        let stride = vbuf.get_stride(0);
+        let off = vbuf.get_offset(0);
+        let paloff = vbuf.get_offset(1);
+        let data = vbuf.get_data_mut().unwrap();
+        for (y, line) in data[off..].chunks_mut(stride).take(HEIGHT).enumerate() {
+            for x in 0..WIDTH {
+                let in_square = (x + frameno * 4) % WIDTH < WIDTH / 4 && (HEIGHT / 3..HEIGHT * 2 / 3).contains(&y);
+                let idx = if in_square { 200 } else { ((x / 8 + y / 8) * 9 + x + (y & 3) * 5) as u8 };
+                let clr = [idx, 255 - idx, idx / 2];
+                if fmt.is_paletted() {
+                    line[x] = idx;
+                } else {
+                    line[x * 3..][..3].copy_from_slice(&clr);
+                }
+            }
+        }
+        if fmt.is_paletted() {
+            for (i, clr) in data[paloff..].chunks_exact_mut(3).take(256).enumerate() {
+                clr[0] = i as u8;
+                clr[1] = (255 - i) as u8;
+                clr[2] = (i / 2) as u8;
+            }
+        }
+        buf
+    }
+
+    // encodes synthetic frames into AVI, decodes them back and returns the total size and the worst frame error
+    fn roundtrip_pal(fmt: NAPixelFormaton, mode: &str, nframes: usize) -> (usize, u64) {
+        let mut enc_reg = RegisteredEncoders::new();
+        ms_register_all_encoders(&mut enc_reg);
+        let mut encoder = (enc_reg.find_encoder("msvideo1").unwrap())();
+        encoder.set_options(&[NAOption{ name: "mode", value: NAValue::String(mode.to_string()) },
+                              NAOption{ name: KEYFRAME_OPTION, value: NAValue::Int(10) }]);
+        let enc_params = EncodeParameters {
+                format:  NACodecTypeInfo::Video(NAVideoInfo::new(WIDTH, HEIGHT, true, fmt)),
+                quality: 0,
+                bitrate: 0,
+                tb_num:  1,
+                tb_den:  25,
+                flags:   0,
+            };
+        let stream = encoder.init(0, enc_params).unwrap();
+
+        let mut frames = Vec::with_capacity(nframes);
+        let mut dst = Vec::new();
+        {
+            let mut strmgr = StreamManager::new();
+            strmgr.add_stream_ref(stream.clone());
+            let mut gw = GrowableMemoryWriter::new_write(&mut dst);
+            let mut bw = ByteWriter::new(&mut gw);
+            let mut mux_reg = RegisteredMuxers::new();
+            generic_register_all_muxers(&mut mux_reg);
+            let mut mux = create_muxer(mux_reg.find_muxer("avi").unwrap(), strmgr, &mut bw).unwrap();
+            for frameno in 0..nframes {
+                let buf = gen_frame(fmt, frameno);
+                let frm = NAFrame::new(NATimeInfo::new(Some(frameno as u64), None, None, 1, 25), FrameType::Other, false, stream.get_info(), buf.clone());
+                encoder.encode(&frm).unwrap();
+                let pkt = encoder.get_packet().unwrap().unwrap();
+                assert_eq!(pkt.is_keyframe(), (frameno % 10) == 0);
+                mux.mux_frame(pkt).unwrap();
+                frames.push(buf);
+            }
+            mux.end().unwrap();
+        }
+
+        let mut dmx_reg = RegisteredDemuxers::new();
+        generic_register_all_demuxers(&mut dmx_reg);
+        let mut dec_reg = RegisteredDecoders::new();
+        ms_register_all_decoders(&mut dec_reg);
+        let mut mr = MemoryReader::new_read(&dst);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = create_demuxer(dmx_reg.find_demuxer("avi").unwrap(), &mut br).unwrap();
+        let dstream = dmx.get_stream(0).unwrap();
+        assert!(dstream.get_info().get_properties().get_video_info().unwrap().get_format().is_paletted());
+        let mut decoder = (dec_reg.find_decoder("msvideo1").unwrap())();
+        let mut dsupp = NADecoderSupport::new();
+        decoder.init(&mut dsupp, dstream.get_info()).unwrap();
+
+        let mut total_size = 0;
+        let mut max_mse = 0;
+        for src in frames.iter() {
+            let pkt = dmx.get_frame().unwrap();
+            total_size += pkt.get_buffer().len();
+            let dfrm = decoder.decode(&mut dsupp, &pkt).unwrap();
+            let dbuf = dfrm.get_buffer();
+            let mut sum = 0;
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let sclr = get_rgb(src, x, y);
+                    let dclr = get_rgb(&dbuf, x, y);
+                    for (&a, &b) in sclr.iter().zip(dclr.iter()) {
+                        sum += (i64::from(a) - i64::from(b)).pow(2) as u64;
+                    }
+                }
+            }
+            max_mse = max_mse.max(sum / ((WIDTH * HEIGHT) as u64));
+        }
+        assert!(dmx.get_frame().is_err());
+        (total_size, max_mse)
+    }
+
+    #[test]
+    fn test_ms_video1_encoder_pal8() {
+        let (pal_size, pal_mse) = roundtrip_pal(PAL8_FORMAT, "auto", 12);
+        assert!(pal_mse < 32);
+        assert!(pal_size < WIDTH * HEIGHT * 12 / 4);
+        let (_, rgb_mse) = roundtrip_pal(RGB24_FORMAT, "palette", 12);
+        assert!(rgb_mse < 64);
+    }
 }
@@ -0,0 +1,90 @@
+use nihav_core::codecs::*;
+use std::str::FromStr;
+use crate::*;
+
+const SRATE: u32 = 22050;
+
+fn gen_signal(len: usize, ch: usize) -> Vec<i16> {
+    (0..len).map(|i| {
+            let t = i as f32 / (SRATE as f32);
+            let freq = if ch == 0 { 440.0 } else { 1250.0 };
+            let env = if i < len / 2 { 12000.0 } else { 3000.0 };
+            (env * (t * freq * 2.0 * std::f32::consts::PI).sin()) as i16
+        }).collect()
+}
+
+// encodes a synthetic signal, decodes it back and returns the total size and signal-to-noise ratio
+pub fn roundtrip(enc_name: &str, dec_name: &str, channels: u8, options: &[NAOption]) -> (usize, f64) {
+    const LEN: usize = 4000;
+
+    let mut enc_reg = RegisteredEncoders::new();
+    qt_register_all_encoders(&mut enc_reg);
+    let mut dec_reg = RegisteredDecoders::new();
+    qt_register_all_decoders(&mut dec_reg);
+
+    let mut encoder = (enc_reg.find_encoder(enc_name).unwrap())();
+    encoder.set_options(options);
+    let ainfo = NAAudioInfo::new(SRATE, channels, SND_S16P_FORMAT, 0);
+    let enc_params = encoder.negotiate_format(&EncodeParameters {
+            format:  NACodecTypeInfo::Audio(ainfo),
+            quality: 0,
+            bitrate: 0,
+            tb_num:  0,
+            tb_den:  0,
+            flags:   0,
+        }).unwrap();
+    let stream = encoder.init(0, enc_params).unwrap();
+    let mut decoder = (dec_reg.find_decoder(dec_name).unwrap())();
+    let mut dsupp = NADecoderSupport::new();
+    decoder.init(&mut dsupp, stream.get_info()).unwrap();
+
+    let src: Vec<Vec<i16>> = (0..usize::from(channels)).map(|ch| gen_signal(LEN, ch)).collect();
+    let chmap = NAChannelMap::from_str(if channels == 1 { "C" } else { "L,R" }).unwrap();
+    let abuf = alloc_audio_buffer(ainfo, LEN, chmap).unwrap();
+    if let NABufferType::AudioI16(ref buf) = abuf {
+        let mut buf = buf.clone();
+        let stride = buf.get_stride();
+        let data = buf.get_data_mut().unwrap();
+        for (dst, src) in data.chunks_mut(stride).zip(src.iter()) {
+            dst[..LEN].copy_from_slice(src);
+        }
+    }
+    let frm = NAFrame::new(NATimeInfo::new(Some(0), None, None, 1, SRATE), FrameType::I, true, stream.get_info(), abuf);
+    encoder.encode(&frm).unwrap();
+    encoder.flush().unwrap();
+
+    let mut total_size = 0;
+    let mut dec: Vec<Vec<i32>> = vec![Vec::new(); usize::from(channels)];
+    while let Ok(Some(pkt)) = encoder.get_packet() {
+        total_size += pkt.get_buffer().len();
+        let frm = decoder.decode(&mut dsupp, &pkt).unwrap();
+        match frm.get_buffer() {
+            NABufferType::AudioI16(ref buf) => {
+                let stride = buf.get_stride();
+                let len = buf.get_length();
+                for (dst, src) in dec.iter_mut().zip(buf.get_data().chunks(stride)) {
+                    dst.extend(src[..len].iter().map(|&samp| i32::from(samp)));
+                }
+            },
+            NABufferType::AudioU8(ref buf) => {
+                let stride = buf.get_stride();
+                let len = buf.get_length();
+                for (dst, src) in dec.iter_mut().zip(buf.get_data().chunks(stride)) {
+                    dst.extend(src[..len].iter().map(|&samp| (i32::from(samp) - 128) << 8));
+                }
+            },
+            _ => panic!("unexpected output format"),
+        };
+    }
+
+    let mut sig = 0.0f64;
+    let mut noise = 0.0f64;
+    for (src, dec) in src.iter().zip(dec.iter()) {
+        assert!(dec.len() >= LEN);
+        for (&s, &d) in src.iter().zip(dec.iter()) {
+            sig += f64::from(s) * f64::from(s);
+            noise += f64::from(i32::from(s) - d).powi(2);
+        }
+    }
+    (total_size, 10.0 * (sig / noise.max(1.0)).log10())
+}
@@ -0,0 +1,287 @@
+use nihav_core::codecs::*;
+use nihav_core::io::byteio::*;
+use nihav_codec_support::codecs::imaadpcm::*;
+
+const PACKET_LEN: usize = 34;
+const PACKET_SAMPLES: usize = 64;
+
+#[derive(Clone,Copy)]
+struct TrellisNode {
+    state:  IMAState,
+    nib:    u8,
+    error:  i64,
+}
+
+impl Default for TrellisNode {
+    fn default() -> Self {
+        TrellisNode {
+            state:  IMAState::new(),
+            nib:    0,
+            error:  0,
+        }
+    }
+}
+
+struct IMAADPCMEncoder {
+    stream:     Option<NAStreamRef>,
+    samples:    Vec<Vec<i16>>,
+    state:      [IMAState; 2],
+    srate:      u32,
+    flush:      bool,
+    trellis:    bool,
+    nodes:      Vec<[TrellisNode; 16]>,
+    nibs:       [u8; PACKET_SAMPLES],
+    cur_pos:    u64,
+}
+
+impl IMAADPCMEncoder {
+    fn new() -> Self {
+        Self {
+            stream:     None,
+            samples:    Vec::new(),
+            state:      [IMAState::new(); 2],
+            srate:      0,
+            flush:      false,
+            trellis:    false,
+            nodes:      Vec::with_capacity(PACKET_SAMPLES + 1),
+            nibs:       [0; PACKET_SAMPLES],
+            cur_pos:    0,
+        }
+    }
+    fn encode_packet(&mut self) -> EncoderResult<NAPacket> {
+        if self.samples.is_empty() || self.samples[0].is_empty() || (!self.flush && self.samples[0].len() < PACKET_SAMPLES) {
+            return Err(EncoderError::TryAgain);
+        }
+
+        let nsamples = self.samples[0].len().min(PACKET_SAMPLES);
+        let channels = self.samples.len();
+        let mut dbuf = vec![0u8; PACKET_LEN * channels];
+        let mut mw = MemoryWriter::new_write(dbuf.as_mut_slice());
+        let mut bw = ByteWriter::new(&mut mw);
+        for ch in 0..channels {
+            if self.samples[ch].len() < PACKET_SAMPLES {
+                self.samples[ch].resize(PACKET_SAMPLES, 0);
+            }
+
+            // only the top nine bits of the predictor are transmitted
+            let pred = ((self.state[ch].predictor + 0x40) & !0x7F).min(0x7F80) as i16;
+            self.state[ch].reset(pred, self.state[ch].step as u8);
+            let step = if !self.trellis {
+                    let state = &mut self.state[ch];
+                    let step = state.step as u8;
+                    for (nib, &samp) in self.nibs.iter_mut().zip(self.samples[ch].iter()) {
+                        *nib = state.compress_sample(samp);
+                        state.expand_sample(*nib);
+                    }
+                    step
+                } else {
+                    let step = self.search_trellis(ch);
+                    // the state at the end of the block is needed for the next one
+                    self.state[ch].reset(pred, step);
+                    for &nib in self.nibs.iter() {
+                        self.state[ch].expand_sample(nib);
+                    }
+                    step
+                };
+
+            bw.write_u16be((pred as u16) | u16::from(step))?;
+            for pair in self.nibs.chunks_exact(2) {
+                bw.write_byte(pair[0] | (pair[1] << 4))?;
+            }
+        }
+
+        for samp in self.samples.iter_mut() {
+            samp.drain(..PACKET_SAMPLES);
+        }
+        let ts = NATimeInfo::new(Some(self.cur_pos), None, Some(nsamples as u64), 1, self.srate);
+        self.cur_pos += nsamples as u64;
+
+        Ok(NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf))
+    }
+    fn search_trellis(&mut self, ch: usize) -> u8 {
+        let start = self.state[ch];
+        let mut state = [TrellisNode::default(); 16];
+        for (i, node) in state.iter_mut().enumerate() {
+            let step = (((start.step + i) as isize) - 8).max(0).min(IMA_MAX_STEP as isize) as usize;
+            node.state.predictor = start.predictor;
+            node.state.step = step;
+            node.error = 0;
+            node.nib = step as u8;
+        }
+        self.nodes.clear();
+        self.nodes.push(state);
+        for &sample in self.samples[ch][..PACKET_SAMPLES].iter() {
+            for (nnib, cstate) in state.iter_mut().enumerate() {
+                for (nib, pnode) in self.nodes[self.nodes.len() - 1].iter().enumerate() {
+                    let mut ima = pnode.state;
+                    let nsamp = ima.expand_sample(nnib as u8);
+                    let diff = i64::from(i32::from(sample) - i32::from(nsamp));
+                    let error = pnode.error + diff * diff;
+                    if (nib == 0) || error < cstate.error {
+                        cstate.state = ima;
+                        cstate.nib = nib as u8;
+                        cstate.error = error;
+                    }
+                }
+            }
+            self.nodes.push(state);
+        }
+
+        let mut idx = 0;
+        let mut best_err = self.nodes[self.nodes.len() - 1][0].error;
+        for (i, node) in self.nodes[self.nodes.len() - 1].iter().enumerate() {
+            if node.error < best_err {
+                best_err = node.error;
+                idx = i;
+            }
+        }
+        for dst in self.nibs.iter_mut().rev() {
+            let nodes = self.nodes.pop().unwrap();
+            *dst = idx as u8;
+            idx = nodes[idx].nib as usize;
+        }
+        self.nodes[0][idx].nib
+    }
+}
+
+impl NAEncoder for IMAADPCMEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Audio(NAAudioInfo::new(0, 1, SND_S16P_FORMAT, PACKET_SAMPLES)),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                let mut outinfo = ainfo;
+                outinfo.channels = outinfo.channels.clamp(1, 2);
+                if outinfo.format != SND_S16P_FORMAT && outinfo.format != SND_S16_FORMAT {
+                    outinfo.format = SND_S16P_FORMAT;
+                }
+                outinfo.block_len = PACKET_SAMPLES;
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Audio(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                if ainfo.format != SND_S16P_FORMAT && ainfo.format != SND_S16_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.channels == 0 || ainfo.channels > 2 {
+                    return Err(EncoderError::FormatError);
+                }
+
+                let out_ainfo = NAAudioInfo::new(ainfo.sample_rate, ainfo.channels, NASoniton::new(4, 0), PACKET_SAMPLES);
+                let info = NACodecInfo::new("ima-adpcm-qt", NACodecTypeInfo::Audio(out_ainfo), None);
+                let mut stream = NAStream::new(StreamType::Audio, stream_id, info, 1, ainfo.sample_rate, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+                self.stream = Some(stream.clone());
+
+                self.samples.clear();
+                for _ in 0..ainfo.channels {
+                    self.samples.push(Vec::with_capacity(PACKET_SAMPLES));
+                }
+                self.state = [IMAState::new(); 2];
+                self.srate = ainfo.sample_rate;
+                self.cur_pos = 0;
+                self.flush = false;
+
+                Ok(stream)
+            },
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref abuf) = buf.get_abuf_i16() {
+            let src = abuf.get_data();
+            let len = abuf.get_length();
+            if abuf.get_step() == 1 {
+                let astride = abuf.get_stride();
+                for (dst, src) in self.samples.iter_mut().zip(src.chunks(astride)) {
+                    dst.extend_from_slice(&src[..len]);
+                }
+            } else {
+                let channels = self.samples.len();
+                for src in src.chunks_exact(channels).take(len) {
+                    for (dst, &samp) in self.samples.iter_mut().zip(src.iter()) {
+                        dst.push(samp);
+                    }
+                }
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        if let Ok(pkt) = self.encode_packet() {
+            Ok(Some(pkt))
+        } else {
+            Ok(None)
+        }
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.flush = true;
+        Ok(())
+    }
+}
+
+const ENCODER_OPTS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: "trellis", description: "Use trellis search",
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
+impl NAOptionHandler for IMAADPCMEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { ENCODER_OPTS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in ENCODER_OPTS.iter() {
+                if opt_def.check(option).is_ok() {
+                    match option.name {
+                        "trellis" => {
+                            if let NAValue::Bool(val) = option.value {
+                                self.trellis = val;
+                            }
+                        },
+                        _ => {},
+                    };
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            "trellis" => Some(NAValue::Bool(self.trellis)),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_encoder() -> Box<dyn NAEncoder + Send> {
+    Box::new(IMAADPCMEncoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use crate::codecs::enctest::roundtrip;
+
+    #[test]
+    fn test_ima_adpcm_qt_encoder() {
+        let (size, snr) = roundtrip("ima-adpcm-qt", "ima-adpcm-qt", 2, &[]);
+        assert_eq!(size, 4032 / 64 * 34 * 2);
+        assert!(snr > 20.0);
+        let (_, tr_snr) = roundtrip("ima-adpcm-qt", "ima-adpcm-qt", 2, &[NAOption{ name: "trellis", value: NAValue::Bool(true) }]);
+        assert!(tr_snr > snr);
+    }
+}
@@ -1,6 +1,8 @@
 use nihav_core::codecs::*;
+#[cfg(feature="decoder_mace")]
 use std::str::FromStr;
 
+#[cfg(feature="decoder_mace")]
 const SND_U8P_FORMAT: NASoniton = NASoniton { bits: 8, be: false, packed: false, planar: true, float: false, signed: false };
 
 #[derive(Clone,Copy,Default)]
@@ -18,6 +20,7 @@ fn clip(val: i32) -> i16 {
     else { val as i16 }
 }
 
+#[cfg(feature="decoder_mace")]
 fn to_sample(val: i32) -> u8 {
     ((val >> 8) + 128).max(0).min(255) as u8
 }
@@ -46,13 +49,13 @@ impl ChannelPredictor {
 
         pred
     }
-    fn pred_mace3(&mut self, idx: usize, middle: bool) -> u8 {
+    fn update_mace3(&mut self, idx: usize, middle: bool) -> i16 {
         let pred = self.get_quant(idx, middle);
         let cur = clip(i32::from(pred) + i32::from(self.level));
         self.level = cur - (cur >> 3);
-        to_sample(i32::from(cur))
+        cur
     }
-    fn pred_mace6(&mut self, idx: usize, middle: bool) -> (u8, u8) {
+    fn update_mace6(&mut self, idx: usize, middle: bool) -> (i32, i32) {
         let pred = self.get_quant(idx, middle);
         if (self.prev ^ pred) >= 0 {
             self.scale = self.scale.saturating_add(506);
@@ -71,10 +74,20 @@ impl ChannelPredictor {
         self.pprev = self.prev;
         self.prev  = cur;
 
+        (s0, s1)
+    }
+    #[cfg(feature="decoder_mace")]
+    fn pred_mace3(&mut self, idx: usize, middle: bool) -> u8 {
+        to_sample(i32::from(self.update_mace3(idx, middle)))
+    }
+    #[cfg(feature="decoder_mace")]
+    fn pred_mace6(&mut self, idx: usize, middle: bool) -> (u8, u8) {
+        let (s0, s1) = self.update_mace6(idx, middle);
         (to_sample(s0), to_sample(s1))
     }
 }
 
+#[cfg(feature="decoder_mace")]
 struct MaceDecoder {
     ainfo:      NAAudioInfo,
     chmap:      NAChannelMap,
@@ -82,6 +95,7 @@ struct MaceDecoder {
     is_mace6:   bool,
 }
 
+#[cfg(feature="decoder_mace")]
 impl MaceDecoder {
     fn new(is_mace6: bool) -> Self {
         Self {
@@ -93,6 +107,7 @@ impl MaceDecoder {
     }
 }
 
+#[cfg(feature="decoder_mace")]
 impl NADecoder for MaceDecoder {
     fn init(&mut self, _supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
         if let NACodecTypeInfo::Audio(ainfo) = info.get_properties() {
@@ -162,20 +177,236 @@ impl NADecoder for MaceDecoder {
     }
 }
 
+#[cfg(feature="decoder_mace")]
 impl NAOptionHandler for MaceDecoder {
     fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
     fn set_options(&mut self, _options: &[NAOption]) { }
     fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
 }
 
+#[cfg(feature="decoder_mace")]
 pub fn get_decoder_3() -> Box<dyn NADecoder + Send> {
     Box::new(MaceDecoder::new(false))
 }
 
+#[cfg(feature="decoder_mace")]
 pub fn get_decoder_6() -> Box<dyn NADecoder + Send> {
     Box::new(MaceDecoder::new(true))
 }
 
+// number of bytes coded per channel in one packet
+#[cfg(feature="encoder_mace")]
+const BLOCK_BYTES: usize = 64;
+
+#[cfg(feature="encoder_mace")]
+fn sqr_err(pred: i32, tgt: i16) -> i64 {
+    let diff = i64::from(pred - i32::from(tgt));
+    diff * diff
+}
+
+#[cfg(feature="encoder_mace")]
+impl ChannelPredictor {
+    fn search_mace3(&mut self, middle: bool, tgt: i16) -> u8 {
+        let ncodes = if middle { 4 } else { 8 };
+        let mut best_idx = 0;
+        let mut best_err = i64::MAX;
+        for idx in 0..ncodes {
+            let mut pred = *self;
+            let err = sqr_err(i32::from(pred.update_mace3(idx, middle)), tgt);
+            if err < best_err {
+                best_err = err;
+                best_idx = idx;
+            }
+        }
+        self.update_mace3(best_idx, middle);
+        best_idx as u8
+    }
+    fn search_mace6(&mut self, middle: bool, tgt0: i16, tgt1: i16) -> u8 {
+        let ncodes = if middle { 4 } else { 8 };
+        let mut best_idx = 0;
+        let mut best_err = i64::MAX;
+        for idx in 0..ncodes {
+            let mut pred = *self;
+            let (s0, s1) = pred.update_mace6(idx, middle);
+            let err = sqr_err(s0, tgt0) + sqr_err(s1, tgt1);
+            if err < best_err {
+                best_err = err;
+                best_idx = idx;
+            }
+        }
+        self.update_mace6(best_idx, middle);
+        best_idx as u8
+    }
+}
+
+#[cfg(feature="encoder_mace")]
+struct MaceEncoder {
+    stream:     Option<NAStreamRef>,
+    samples:    Vec<Vec<i16>>,
+    ch_pred:    [ChannelPredictor; 2],
+    is_mace6:   bool,
+    srate:      u32,
+    flush:      bool,
+    cur_pos:    u64,
+}
+
+#[cfg(feature="encoder_mace")]
+impl MaceEncoder {
+    fn new(is_mace6: bool) -> Self {
+        Self {
+            stream:     None,
+            samples:    Vec::new(),
+            ch_pred:    [ChannelPredictor::default(); 2],
+            is_mace6,
+            srate:      0,
+            flush:      false,
+            cur_pos:    0,
+        }
+    }
+    fn samples_per_byte(&self) -> usize { if self.is_mace6 { 6 } else { 3 } }
+    fn encode_packet(&mut self) -> EncoderResult<NAPacket> {
+        let spb = self.samples_per_byte();
+        let block_len = BLOCK_BYTES * spb;
+        if self.samples.is_empty() || self.samples[0].is_empty() || (!self.flush && self.samples[0].len() < block_len) {
+            return Err(EncoderError::TryAgain);
+        }
+
+        let channels = self.samples.len();
+        let nsamples = self.samples[0].len().min(block_len);
+        let nbytes = self.samples[0][..nsamples].chunks(spb).len();
+        let mut dbuf = vec![0u8; nbytes * channels];
+        for (ch, samples) in self.samples.iter_mut().enumerate() {
+            samples.resize((nbytes * spb).max(samples.len()), 0);
+            let pred = &mut self.ch_pred[ch];
+            for (dst, src) in dbuf.iter_mut().skip(ch).step_by(channels).zip(samples.chunks_exact(spb)) {
+                *dst = if !self.is_mace6 {
+                        let idx0 = pred.search_mace3(false, src[0]);
+                        let idx1 = pred.search_mace3(true,  src[1]);
+                        let idx2 = pred.search_mace3(false, src[2]);
+                        idx0 | (idx1 << 3) | (idx2 << 5)
+                    } else {
+                        let idx0 = pred.search_mace6(false, src[0], src[1]);
+                        let idx1 = pred.search_mace6(true,  src[2], src[3]);
+                        let idx2 = pred.search_mace6(false, src[4], src[5]);
+                        (idx0 << 5) | (idx1 << 3) | idx2
+                    };
+            }
+            samples.drain(..nbytes * spb);
+        }
+
+        let ts = NATimeInfo::new(Some(self.cur_pos), None, Some(nsamples as u64), 1, self.srate);
+        self.cur_pos += nsamples as u64;
+        Ok(NAPacket::new(self.stream.clone().unwrap(), ts, true, dbuf))
+    }
+}
+
+#[cfg(feature="encoder_mace")]
+impl NAEncoder for MaceEncoder {
+    fn negotiate_format(&self, encinfo: &EncodeParameters) -> EncoderResult<EncodeParameters> {
+        match encinfo.format {
+            NACodecTypeInfo::None => {
+                Ok(EncodeParameters {
+                    format: NACodecTypeInfo::Audio(NAAudioInfo::new(0, 1, SND_S16P_FORMAT, BLOCK_BYTES * self.samples_per_byte())),
+                    ..Default::default()
+                })
+            },
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                let mut outinfo = ainfo;
+                outinfo.channels = outinfo.channels.clamp(1, 2);
+                if outinfo.format != SND_S16P_FORMAT && outinfo.format != SND_S16_FORMAT {
+                    outinfo.format = SND_S16P_FORMAT;
+                }
+                outinfo.block_len = BLOCK_BYTES * self.samples_per_byte();
+                let mut ofmt = *encinfo;
+                ofmt.format = NACodecTypeInfo::Audio(outinfo);
+                Ok(ofmt)
+            }
+        }
+    }
+    fn init(&mut self, stream_id: u32, encinfo: EncodeParameters) -> EncoderResult<NAStreamRef> {
+        match encinfo.format {
+            NACodecTypeInfo::None => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Video(_) => Err(EncoderError::FormatError),
+            NACodecTypeInfo::Audio(ainfo) => {
+                if ainfo.format != SND_S16P_FORMAT && ainfo.format != SND_S16_FORMAT {
+                    return Err(EncoderError::FormatError);
+                }
+                if ainfo.channels != 1 && ainfo.channels != 2 {
+                    return Err(EncoderError::FormatError);
+                }
+
+                let name = if self.is_mace6 { "mace-6" } else { "mace-3" };
+                let out_ainfo = NAAudioInfo::new(ainfo.sample_rate, ainfo.channels, NASoniton::new(8, 0), BLOCK_BYTES * self.samples_per_byte());
+                let info = NACodecInfo::new(name, NACodecTypeInfo::Audio(out_ainfo), None);
+                let mut stream = NAStream::new(StreamType::Audio, stream_id, info, 1, ainfo.sample_rate, 0);
+                stream.set_num(stream_id as usize);
+                let stream = stream.into_ref();
+
+                self.stream = Some(stream.clone());
+                self.samples = vec![Vec::new(); usize::from(ainfo.channels)];
+                self.ch_pred = [ChannelPredictor::default(); 2];
+                self.srate = ainfo.sample_rate;
+                self.flush = false;
+                self.cur_pos = 0;
+
+                Ok(stream)
+            }
+        }
+    }
+    fn encode(&mut self, frm: &NAFrame) -> EncoderResult<()> {
+        let buf = frm.get_buffer();
+        if let Some(ref abuf) = buf.get_abuf_i16() {
+            let src = abuf.get_data();
+            let len = abuf.get_length();
+            let step = abuf.get_step();
+            let stride = abuf.get_stride();
+            if step == 1 {
+                for (ch, dst) in self.samples.iter_mut().enumerate() {
+                    dst.extend_from_slice(&src[ch * stride..][..len]);
+                }
+            } else {
+                for frame in src.chunks_exact(step).take(len) {
+                    for (dst, &samp) in self.samples.iter_mut().zip(frame.iter()) {
+                        dst.push(samp);
+                    }
+                }
+            }
+            Ok(())
+        } else {
+            Err(EncoderError::InvalidParameters)
+        }
+    }
+    fn get_packet(&mut self) -> EncoderResult<Option<NAPacket>> {
+        if let Ok(pkt) = self.encode_packet() {
+            Ok(Some(pkt))
+        } else {
+            Ok(None)
+        }
+    }
+    fn flush(&mut self) -> EncoderResult<()> {
+        self.flush = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature="encoder_mace")]
+impl NAOptionHandler for MaceEncoder {
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
+    fn set_options(&mut self, _options: &[NAOption]) { }
+    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+}
+
+#[cfg(feature="encoder_mace")]
+pub fn get_encoder_3() -> Box<dyn NAEncoder + Send> {
+    Box::new(MaceEncoder::new(false))
+}
+
+#[cfg(feature="encoder_mace")]
+pub fn get_encoder_6() -> Box<dyn NAEncoder + Send> {
+    Box::new(MaceEncoder::new(true))
+}
+
 #[cfg(test)]
 mod test {
     use nihav_core::codecs::RegisteredDecoders;
@@ -203,6 +434,18 @@ mod test {
         test_decoding("mov", "mace-6", "assets/QT/surge-1-8-MAC6.mov", None, &dmx_reg, &dec_reg,
                       ExpectedTestResult::MD5([0xc32857e2, 0xc1ea1ce8, 0x2d77dacf, 0xef504f1f]));
     }
+    #[cfg(feature="encoder_mace")]
+    #[test]
+    fn test_mace_encoder() {
+        use crate::codecs::enctest::roundtrip;
+
+        let (size3, snr3) = roundtrip("mace-3", "mace-3", 2, &[]);
+        let (size6, snr6) = roundtrip("mace-6", "mace-6", 2, &[]);
+        assert_eq!(size3, 4002 / 3 * 2);
+        assert_eq!(size6, 4002 / 6 * 2);
+        assert!(snr3 > 18.0);
+        assert!(snr6 > 10.0);
+    }
 }
 
 const STEP_TAB0: [i16; 8] = [ -13, 8, 76, 222, 222, 76, 8, -13 ];
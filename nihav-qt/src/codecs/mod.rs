@@ -40,7 +40,10 @@ mod alac;
 #[cfg(feature="decoder_ima_adpcm_qt")]
 mod imaadpcm;
 
-#[cfg(feature="decoder_mace")]
+#[cfg(feature="encoder_ima_adpcm_qt")]
+mod imaadpcmenc;
+
+#[cfg(any(feature="decoder_mace", feature="encoder_mace"))]
 mod mace;
 
 #[cfg(any(feature="decoder_qdm",feature="decoder_qdm2"))]
@@ -96,3 +99,22 @@ pub fn qt_register_all_decoders(rd: &mut RegisteredDecoders) {
         rd.add_decoder(*decoder);
     }
 }
+
+#[cfg(all(test, feature="decoders", feature="encoders"))]
+mod enctest;
+
+const QT_ENCODERS: &[EncoderInfo] = &[
+#[cfg(feature="encoder_ima_adpcm_qt")]
+    EncoderInfo { name: "ima-adpcm-qt", get_encoder: imaadpcmenc::get_encoder },
+#[cfg(feature="encoder_mace")]
+    EncoderInfo { name: "mace-3", get_encoder: mace::get_encoder_3 },
+#[cfg(feature="encoder_mace")]
+    EncoderInfo { name: "mace-6", get_encoder: mace::get_encoder_6 },
+];
+
+/// Registers all available encoders provided by this crate.
+pub fn qt_register_all_encoders(re: &mut RegisteredEncoders) {
+    for encoder in QT_ENCODERS.iter() {
+        re.add_encoder(*encoder);
+    }
+}
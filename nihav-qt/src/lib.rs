@@ -6,3 +6,4 @@ extern crate nihav_codec_support;
 #[allow(clippy::single_match)]
 mod codecs;
 pub use crate::codecs::qt_register_all_decoders;
+pub use crate::codecs::qt_register_all_encoders;
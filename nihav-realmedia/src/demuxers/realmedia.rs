@@ -364,6 +364,35 @@ struct RealMediaDemuxer<'a> {
 
     queued_pkts:    Vec<NAPacket>,
     slice_buf:      Vec<u8>,
+
+    metadata:       ContainerMetadata,
+    duration:       u32,
+
+    resilient:      bool,
+    skipped:        Vec<SkippedRange>,
+}
+
+const RM_PKT_HDR_CHECK_SIZE: usize = 12;
+
+fn is_valid_packet_header(str_data: &CommonStreamData, hdr: &[u8], pos: u64, data_end: u64) -> bool {
+    let ver     = read_u16be(hdr).unwrap_or(2);
+    let len     = read_u16be(&hdr[2..]).unwrap_or(0) as usize;
+    let str_no  = read_u16be(&hdr[4..]).unwrap_or(0) as u32;
+    let hdr_size = if ver == 0 { 12 } else { 13 };
+    if ver > 1 || len < hdr_size || pos + (len as u64) > data_end {
+        return false;
+    }
+    let stream_id = if !str_data.mlti_mapper.is_mlti_stream(str_no) {
+            str_no
+        } else {
+            let pkt_grp = if ver == 0 { u16::from(hdr[10]) } else { 0 };
+            if let Some(id) = str_data.mlti_mapper.find_substream(str_no, pkt_grp) {
+                id
+            } else {
+                return false;
+            }
+        };
+    str_data.find_stream(stream_id).is_some()
 }
 
 fn find_codec_name(registry: &[(&[u8;4], &'static str)], fcc: u32) -> &'static str {
@@ -683,6 +712,11 @@ impl<'a> DemuxCore<'a> for RealMediaDemuxer<'a> {
                 return Err(DemuxerError::EOF);
             }
 
+            if self.resilient && !self.check_packet_header()? {
+                self.resync_packet()?;
+                continue;
+            }
+
             let pkt_start = self.src.tell();
             let ver             = self.src.read_u16be()?;
             validate!(ver <= 1);
@@ -706,6 +740,7 @@ impl<'a> DemuxCore<'a> for RealMediaDemuxer<'a> {
 //println!("packet @{:X} size {} for {} ts {} grp {} flags {:X}", pkt_start, len, str_no, ts, pkt_grp, flags);
             self.cur_packet += 1;
 
+            validate!(len >= (hdr_size as usize));
             let payload_size = len - (hdr_size as usize);
 
             let stream_id = self.str_data.get_stream_id(str_no as u32, pkt_grp);
@@ -732,11 +767,17 @@ impl<'a> DemuxCore<'a> for RealMediaDemuxer<'a> {
             let keyframe = (flags & KEYFRAME_FLAG) != 0;
 
             let ret = RMDemuxCommon::parse_packet_payload(&mut self.src, &mut self.str_data.streams[str_id], stream, &mut self.slice_buf, &mut self.queued_pkts, keyframe, ts, payload_size);
-            if let Err(DemuxerError::TryAgain) = ret {
-                continue;
-            } else {
-                return ret;
-            }
+            match ret {
+                Err(DemuxerError::TryAgain) => continue,
+                Err(DemuxerError::EOF) | Err(DemuxerError::IOError) | Ok(_) => return ret,
+                Err(_) if self.resilient => {
+                    // drop the damaged packet and continue with the next one
+                    let pkt_end = pkt_start + (len as u64);
+                    self.src.seek(SeekFrom::Start(pkt_end))?;
+                    self.skipped.push(SkippedRange { start: pkt_start, end: pkt_end });
+                },
+                Err(_) => return ret,
+            };
         }
     }
 
@@ -764,13 +805,38 @@ impl<'a> DemuxCore<'a> for RealMediaDemuxer<'a> {
         Err(DemuxerError::SeekError)
     }
 
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { u64::from(self.duration) }
+    fn fill_metadata(&mut self, metadata: &mut ContainerMetadata) {
+        *metadata = self.metadata.clone();
+    }
+    fn get_skipped_ranges(&self) -> &[SkippedRange] { &self.skipped }
 }
 
+const DEMUXER_OPTIONS: &[NAOptionDefinition] = &[
+    NAOptionDefinition {
+        name: RESILIENT_OPTION, description: RESILIENT_OPTION_DESC,
+        opt_type: NAOptionDefinitionType::Bool },
+];
+
 impl<'a> NAOptionHandler for RealMediaDemuxer<'a> {
-    fn get_supported_options(&self) -> &[NAOptionDefinition] { &[] }
-    fn set_options(&mut self, _options: &[NAOption]) { }
-    fn query_option_value(&self, _name: &str) -> Option<NAValue> { None }
+    fn get_supported_options(&self) -> &[NAOptionDefinition] { DEMUXER_OPTIONS }
+    fn set_options(&mut self, options: &[NAOption]) {
+        for option in options.iter() {
+            for opt_def in DEMUXER_OPTIONS.iter() {
+                if opt_def.check(option).is_ok() {
+                    if let (RESILIENT_OPTION, NAValue::Bool(val)) = (option.name, &option.value) {
+                        self.resilient = *val;
+                    }
+                }
+            }
+        }
+    }
+    fn query_option_value(&self, name: &str) -> Option<NAValue> {
+        match name {
+            RESILIENT_OPTION => Some(NAValue::Bool(self.resilient)),
+            _ => None,
+        }
+    }
 }
 
 fn read_chunk(src: &mut ByteReader) -> DemuxerResult<(u32, u32, u16)> {
@@ -996,8 +1062,36 @@ impl<'a> RealMediaDemuxer<'a> {
             cur_data_chunk: 0,
             queued_pkts:    Vec::new(),
             slice_buf:      Vec::new(),
+            metadata:       ContainerMetadata::new(),
+            duration:       0,
+            resilient:      false,
+            skipped:        Vec::new(),
         }
     }
+    fn check_packet_header(&mut self) -> DemuxerResult<bool> {
+        let pos = self.src.tell();
+        let mut hdr = [0; RM_PKT_HDR_CHECK_SIZE];
+        if self.src.peek_buf(&mut hdr)? < hdr.len() {
+            return Ok(false);
+        }
+        Ok(is_valid_packet_header(&self.str_data, &hdr, pos, self.data_end))
+    }
+    fn resync_packet(&mut self) -> DemuxerResult<()> {
+        let start = self.src.tell();
+        let data_end = self.data_end;
+        let str_data = &self.str_data;
+        let ret = resync(self.src, RM_PKT_HDR_CHECK_SIZE, data_end.saturating_sub(start), |pos, hdr| is_valid_packet_header(str_data, hdr, pos, data_end));
+        match ret {
+            Ok(range) => self.skipped.push(range),
+            Err(DemuxerError::EOF) => {
+                // nothing usable in the rest of the data chunk (which may lie beyond the end of truncated input)
+                self.skipped.push(SkippedRange { start, end: data_end });
+                self.cur_packet = self.num_packets;
+            },
+            Err(err) => return Err(err),
+        };
+        Ok(())
+    }
 #[allow(unused_variables)]
     fn read_header(&mut self, strmgr: &mut StreamManager, seek_idx: &mut SeekIndex) -> DemuxerResult<()> {
         let (id, size, ver) = read_chunk(self.src)?;
@@ -1025,6 +1119,7 @@ impl<'a> RealMediaDemuxer<'a> {
         if ver == 2 {
                           self.src.read_skip(4)?;
         }
+        self.duration = duration;
         let idx_off     = self.src.read_u32be()?;
         let data_off    = self.src.read_u32be()?;
         let num_streams = self.src.read_u16be()? as usize;
@@ -1095,16 +1190,12 @@ impl<'a> RealMediaDemuxer<'a> {
         }
         Ok(false)
     }
-#[allow(unused_variables)]
     fn parse_content_desc(&mut self) -> DemuxerResult<()> {
-        let title_len       = self.src.read_u16be()? as usize;
-        self.src.read_skip(title_len)?;
-        let author_len      = self.src.read_u16be()? as usize;
-        self.src.read_skip(author_len)?;
-        let copywrong_len   = self.src.read_u16be()? as usize;
-        self.src.read_skip(copywrong_len)?;
-        let comment_len     = self.src.read_u16be()? as usize;
-        self.src.read_skip(comment_len)?;
+        for &key in [METADATA_TITLE, METADATA_ARTIST, METADATA_COPYRIGHT, METADATA_COMMENT].iter() {
+            let len         = self.src.read_u16be()? as usize;
+            let text        = read_meta_string(self.src, len)?;
+            self.metadata.get_global_mut().add_text(key, &text);
+        }
         Ok(())
     }
 #[allow(unused_variables)]
@@ -1141,6 +1232,7 @@ impl<'a> RealMediaDemuxer<'a> {
         if !is_mlti {
             self.str_data.str_ids.push(stream_no);
         }
+        self.metadata.get_stream_mut(stream_no).add_text(METADATA_TITLE, &sname);
 
         Ok(())
     }
@@ -1178,6 +1270,21 @@ impl<'a> RealMediaDemuxer<'a> {
     }
 }
 
+fn read_meta_string(src: &mut ByteReader, size: usize) -> DemuxerResult<String> {
+    if size == 0 {
+        return Ok(String::new());
+    }
+    let mut vec: Vec<u8> = vec![0; size];
+    src.read_buf(&mut vec)?;
+    while let Some(0) = vec.last() {
+        vec.pop();
+    }
+    match String::from_utf8(vec) {
+        Ok(res) => Ok(res),
+        Err(err) => Ok(err.into_bytes().iter().map(|&c| char::from(c)).collect()), // ISO 8859-1
+    }
+}
+
 fn read_string_size(src: &mut ByteReader, size: usize) -> DemuxerResult<String> {
     let mut vec: Vec<u8> = Vec::new();
     for _ in 0..size {
@@ -1199,6 +1306,7 @@ struct RealAudioDemuxer<'a> {
     data_end:       u64,
     blk_size:       usize,
     queued_pkts:    Vec<NAPacket>,
+    duration:       u64,
 }
 
 impl<'a> DemuxCore<'a> for RealAudioDemuxer<'a> {
@@ -1249,6 +1357,14 @@ println!(" got ainfo {:?}", ainfo);
         self.data_end   = if ainfo.total_bytes > 0 { self.src.tell() + (ainfo.total_bytes as u64) } else { 0 };
         self.blk_size = blk_size;
         self.stream = Some(astr);
+        self.duration = if ainfo.total_bytes > 0 {
+                ainfo.get_duration(1000)
+            } else if ainfo.bytes_per_minute > 0 {
+                let data_size = (self.src.size().max(0) as u64).saturating_sub(self.data_start);
+                data_size * 60 * 1000 / u64::from(ainfo.bytes_per_minute)
+            } else {
+                0
+            };
 
         Ok(())
     }
@@ -1280,7 +1396,7 @@ println!(" got ainfo {:?}", ainfo);
         Err(NotImplemented)
     }
 
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { self.duration }
 }
 
 impl<'a> NAOptionHandler for RealAudioDemuxer<'a> {
@@ -1298,6 +1414,7 @@ impl<'a> RealAudioDemuxer<'a> {
             blk_size:       0,
             stream:         None,
             queued_pkts:    Vec::new(),
+            duration:       0,
         }
     }
 }
@@ -1551,6 +1668,7 @@ struct RealIVRDemuxer<'a> {
     queued_pkts:    Vec<NAPacket>,
     slice_buf:      Vec<u8>,
     str_data:       CommonStreamData,
+    duration:       u64,
 }
 
 impl<'a> DemuxCore<'a> for RealIVRDemuxer<'a> {
@@ -1606,6 +1724,8 @@ println!("R1M kind");
         } else {
             return Err(DemuxerError::InvalidData);
         }
+        // IVR has no global duration so it is taken from the stream properties
+        self.duration = strmgr.iter().map(|stream| stream.get_duration_ms()).max().unwrap_or(0);
 
         Ok(())
     }
@@ -1631,7 +1751,7 @@ println!("R1M kind");
         Err(NotImplemented)
     }
 
-    fn get_duration(&self) -> u64 { 0 }
+    fn get_duration(&self) -> u64 { self.duration }
 }
 
 impl<'a> NAOptionHandler for RealIVRDemuxer<'a> {
@@ -1649,6 +1769,7 @@ impl<'a> RealIVRDemuxer<'a> {
             queued_pkts:    Vec::new(),
             slice_buf:      Vec::new(),
             str_data:       CommonStreamData::new(),
+            duration:       0,
         }
     }
 }
@@ -1707,6 +1828,127 @@ mod test {
     use super::*;
     use std::fs::File;
 
+    fn rm_chunk(tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut ret = tag.to_vec();
+        ret.extend_from_slice(&((payload.len() + 10) as u32).to_be_bytes());
+        ret.extend_from_slice(&[0, 0]); // version
+        ret.extend_from_slice(payload);
+        ret
+    }
+
+    fn rm_string(dst: &mut Vec<u8>, text: &str) {
+        dst.extend_from_slice(&(text.len() as u16).to_be_bytes());
+        dst.extend_from_slice(text.as_bytes());
+    }
+
+    // creates a file with content description and a single RealAudio Lossless stream with the provided packet payloads
+    fn create_rm_file(cont: &[&str; 4], sname: &str, payloads: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = rm_chunk(b".RMF", &[0, 0, 0, 0, 0, 0, 0, 3]);
+
+        let mut prop = vec![0; 36];
+        prop[16..20].copy_from_slice(&(payloads.len() as u32).to_be_bytes());
+        prop.extend_from_slice(&[0, 1, 0, 0]); // streams and flags
+        file.extend_from_slice(&rm_chunk(b"PROP", &prop));
+
+        let mut cdesc = Vec::new();
+        for text in cont.iter() {
+            rm_string(&mut cdesc, text);
+        }
+        file.extend_from_slice(&rm_chunk(b"CONT", &cdesc));
+
+        let mut mdpr = vec![0; 30];
+        mdpr.push(sname.len() as u8);
+        mdpr.extend_from_slice(sname.as_bytes());
+        mdpr.push(0); // MIME type
+        let mut lsd = b"LSD:".to_vec();
+        lsd.extend_from_slice(&[0, 0, 0, 3, 0, 1, 0, 16, 0, 0, 0x1F, 0x40]);
+        mdpr.extend_from_slice(&(lsd.len() as u32).to_be_bytes());
+        mdpr.extend_from_slice(&lsd);
+        file.extend_from_slice(&rm_chunk(b"MDPR", &mdpr));
+
+        let mut data = (payloads.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]); // next data header
+        for (i, payload) in payloads.iter().enumerate() {
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&((payload.len() + 12) as u16).to_be_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&((i * 100) as u32).to_be_bytes());
+            data.extend_from_slice(&[0, KEYFRAME_FLAG]);
+            data.extend_from_slice(payload);
+        }
+        file.extend_from_slice(&rm_chunk(b"DATA", &data));
+        file
+    }
+
+    #[test]
+    fn test_rm_metadata() {
+        let file = create_rm_file(&["Test title", "Test author", "Test copyright", ""], "Audio track", &[vec![0; 16]]);
+
+        let mut mr = MemoryReader::new_read(&file);
+        let mut br = ByteReader::new(&mut mr);
+        let mut dmx = RealMediaDemuxer::new(&mut br);
+        let mut sm = StreamManager::new();
+        let mut si = SeekIndex::new();
+        dmx.open(&mut sm, &mut si).unwrap();
+        let mut meta = ContainerMetadata::new();
+        dmx.fill_metadata(&mut meta);
+        let gmeta = meta.get_global();
+        assert_eq!(gmeta.get_text(METADATA_TITLE), Some("Test title"));
+        assert_eq!(gmeta.get_text(METADATA_ARTIST), Some("Test author"));
+        assert_eq!(gmeta.get_text(METADATA_COPYRIGHT), Some("Test copyright"));
+        assert_eq!(meta.get_stream(0).and_then(|smeta| smeta.get_text(METADATA_TITLE)), Some("Audio track"));
+        let pkt = dmx.get_frame(&mut sm).unwrap();
+        assert_eq!(pkt.get_buffer().len(), 16);
+    }
+
+    #[test]
+    fn test_rm_demux_resilient() {
+        const NUM_PKTS: usize = 5;
+        const BAD_PKT: usize = 2;
+        const PKT_SIZE: usize = 12 + 16;
+        let payloads: Vec<Vec<u8>> = (0..NUM_PKTS).map(|i| vec![i as u8; 16]).collect();
+        let mut file = create_rm_file(&["", "", "", ""], "", &payloads);
+        let data_pos = file.windows(4).position(|tag| tag == b"DATA").unwrap();
+        let pkt_pos = |i: usize| data_pos + 18 + i * PKT_SIZE;
+
+        // damage the header of one packet
+        let bad_pos = pkt_pos(BAD_PKT);
+        file[bad_pos..][..8].copy_from_slice(&[0xFF; 8]);
+
+        let read_all = |data: &[u8]| -> (Vec<u8>, Vec<SkippedRange>) {
+                let mut mr = MemoryReader::new_read(data);
+                let mut br = ByteReader::new(&mut mr);
+                let mut dmx = RealMediaDemuxer::new(&mut br);
+                dmx.set_options(&[NAOption{ name: RESILIENT_OPTION, value: NAValue::Bool(true) }]);
+                let mut sm = StreamManager::new();
+                let mut si = SeekIndex::new();
+                dmx.open(&mut sm, &mut si).unwrap();
+                let mut pkts = Vec::new();
+                loop {
+                    match dmx.get_frame(&mut sm) {
+                        Ok(pkt) => {
+                            assert_eq!(pkt.get_buffer().len(), 16);
+                            pkts.push(pkt.get_buffer()[0]);
+                        },
+                        Err(DemuxerError::EOF) => break,
+                        Err(err) => panic!("demuxing error {:?}", err),
+                    }
+                }
+                (pkts, dmx.get_skipped_ranges().to_vec())
+            };
+
+        let (pkts, skipped) = read_all(&file);
+        assert_eq!(pkts, [0, 1, 3, 4]);
+        assert_eq!(skipped, [SkippedRange { start: bad_pos as u64, end: (bad_pos + PKT_SIZE) as u64 }]);
+
+        // cut the input inside the header of the last packet, the rest of the data chunk is reported as skipped
+        let trunc_len = pkt_pos(NUM_PKTS - 1) + 6;
+        let (pkts, skipped) = read_all(&file[..trunc_len]);
+        assert_eq!(pkts, [0, 1, 3]);
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[1], SkippedRange { start: pkt_pos(NUM_PKTS - 1) as u64, end: file.len() as u64 });
+    }
+
     #[test]
     fn test_rm_demux() {
         let mut file =
@@ -281,6 +281,7 @@ static CODEC_REGISTER: &[CodecDescription] = &[
     desc!(audio-hyb; "wavpack",      "WavPack"),
 
     desc!(video-im; "jpeg",          "JPEG"),
+    desc!(video;    "h263",          "ITU H.263"),
     desc!(video;    "h264",          "ITU H.264", CODEC_CAP_COMPLEX_REORDER | CODEC_CAP_HYBRID),
 ];
 
@@ -293,6 +294,7 @@ static AVI_VIDEO_CODEC_REGISTER: &[(&[u8;4], &str)] = &[
     (b"WHAM", "msvideo1"),
 
     (b"MJPG", "jpeg"),
+    (b"H263", "h263"),
 
     (b"IF09", "indeo1"),
     (b"RT21", "indeo2"),
@@ -327,6 +329,7 @@ static AVI_VIDEO_CODEC_REGISTER: &[(&[u8;4], &str)] = &[
     (b"VP62", "vp6"),
     (b"VP6A", "vp6a"),
     (b"VP70", "vp7"),
+    (b"VP80", "vp8"),
 ];
 
 static WAV_CODEC_REGISTER: &[(u16, &str)] = &[
@@ -334,9 +337,13 @@ static WAV_CODEC_REGISTER: &[(u16, &str)] = &[
     (0x0001, "pcm"),
     (0x0002, "ms-adpcm"),
     (0x0003, "pcm"),
+    (0x0006, "alaw"),
+    (0x0007, "ulaw"),
     (0x0011, "ima-adpcm-ms"),
+    (0x0055, "mp3"),
     (0x0061, "adpcm-dk4"),
     (0x0062, "adpcm-dk3"),
+    (0x00FF, "aac"),
     (0x0401, "imc"),
     (0x0402, "iac"),
     (0x0500, "on2avc-500"),
@@ -368,6 +375,7 @@ static MOV_VIDEO_CODEC_REGISTER: &[(&[u8;4], &str)] = &[
     (b"VP30", "vp3"),
     (b"VP31", "vp3"),
 
+    (b"h263", "h263"),
     (b"avc1", "h264"),
 ];
 